@@ -0,0 +1,47 @@
+//! Benchmarks of the eSSP encryption, and decryption path.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use ssp_server::essp::{self, Packet};
+
+// Plaintext sizes: a poll, a poll response with events, and a full packet.
+const SIZES: [usize; 3] = [1, 16, 100];
+
+fn key() -> ssp::AesKey {
+    ssp::AesKey::from(&ssp::FixedKey::new())
+}
+
+fn bench_encrypt(c: &mut Criterion) {
+    let key = key();
+    let mut group = c.benchmark_group("essp_encrypt");
+
+    for size in SIZES {
+        let packet = Packet::new(42, &vec![0xa5; size]);
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &packet, |b, packet| {
+            b.iter(|| essp::encrypt(&key, black_box(packet)))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_decrypt(c: &mut Criterion) {
+    let key = key();
+    let mut group = c.benchmark_group("essp_decrypt");
+
+    for size in SIZES {
+        let encrypted = essp::encrypt(&key, &Packet::new(42, &vec![0xa5; size])).unwrap();
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(size),
+            &encrypted,
+            |b, encrypted| b.iter(|| essp::decrypt(&key, black_box(encrypted))),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_encrypt, bench_decrypt);
+criterion_main!(benches);
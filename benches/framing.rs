@@ -0,0 +1,83 @@
+//! Benchmarks of the frame parsing path: byte stuffing, frame decoding, and response parsing.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use ssp_server::framing::{self, FrameDecoder};
+
+const STX: u8 = 0x7f;
+const OK: u8 = 0xf0;
+const READ: u8 = 0xef;
+const CREDIT: u8 = 0xee;
+const STACKED: u8 = 0xeb;
+
+fn frame(seq: u8, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![STX, seq, data.len() as u8];
+    frame.extend_from_slice(data);
+
+    let crc = ssp::crc::crc16(&frame[1..]);
+    frame.extend_from_slice(crc.to_le_bytes().as_ref());
+
+    frame
+}
+
+// Poll response reporting a note credit.
+fn poll_response() -> Vec<u8> {
+    frame(0x80, &[OK, READ, 1, CREDIT, 1, STACKED])
+}
+
+// Response with every other byte an STX, stuffing to almost twice its length.
+fn stx_heavy_response() -> Vec<u8> {
+    let data: Vec<u8> = (0..64u8)
+        .map(|i| if i % 2 == 0 { STX } else { i })
+        .collect();
+
+    frame(0x80, &data)
+}
+
+fn bench_stuffing(c: &mut Criterion) {
+    let frame = stx_heavy_response();
+    let stuffed = framing::stuff(&frame).unwrap();
+
+    c.bench_function("stuff", |b| b.iter(|| framing::stuff(black_box(&frame))));
+
+    let mut out = Vec::with_capacity(stuffed.len());
+    c.bench_function("stuff_into", |b| {
+        b.iter(|| framing::stuff_into(black_box(&frame), &mut out))
+    });
+
+    c.bench_function("unstuff", |b| {
+        b.iter(|| framing::unstuff(black_box(&stuffed)))
+    });
+}
+
+fn bench_decoder(c: &mut Criterion) {
+    let stuffed = framing::stuff(&stx_heavy_response()).unwrap();
+    let mut decoder = FrameDecoder::new();
+
+    c.bench_function("frame_decoder", |b| {
+        b.iter(|| {
+            decoder.reset();
+            for &byte in black_box(&stuffed).iter() {
+                if decoder.push(byte).unwrap() {
+                    break;
+                }
+            }
+            decoder.frame().len()
+        })
+    });
+}
+
+fn bench_parse_response(c: &mut Criterion) {
+    let poll = poll_response();
+
+    c.bench_function("check_frame", |b| {
+        b.iter(|| framing::check_frame(black_box(&poll)))
+    });
+
+    c.bench_function("parse_poll_response", |b| {
+        b.iter(|| framing::parse_response(black_box(&poll), ssp::MessageType::Poll))
+    });
+}
+
+criterion_group!(benches, bench_stuffing, bench_decoder, bench_parse_response);
+criterion_main!(benches);
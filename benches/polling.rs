@@ -0,0 +1,39 @@
+//! Benchmarks of the poll round trip over the in-memory emulator transport.
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use parking_lot::Mutex;
+
+use ssp_server::emulator::{Emulator, EmulatorTransport};
+use ssp_server::DeviceHandle;
+
+fn connect() -> DeviceHandle {
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+    let handle = DeviceHandle::from_transport(EmulatorTransport::new(emulator)).unwrap();
+
+    handle.sync().unwrap();
+    handle.enable_device(ssp::ProtocolVersion::Six).unwrap();
+    // drain the reset reported after power on
+    handle.poll().unwrap();
+
+    handle
+}
+
+fn bench_poll(c: &mut Criterion) {
+    let handle = connect();
+
+    c.bench_function("poll_round_trip", |b| b.iter(|| handle.poll().unwrap()));
+}
+
+fn bench_encrypted_poll(c: &mut Criterion) {
+    let mut handle = connect();
+    handle.negotiate_keys().unwrap();
+
+    c.bench_function("encrypted_poll_round_trip", |b| {
+        b.iter(|| handle.poll().unwrap())
+    });
+}
+
+criterion_group!(benches, bench_poll, bench_encrypted_poll);
+criterion_main!(benches);
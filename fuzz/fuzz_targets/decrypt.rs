@@ -0,0 +1,21 @@
+//! Decrypts arbitrary eSSP packets, under a key taken from the input.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ssp_server::essp;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 16 {
+        return;
+    }
+
+    let (key, packet) = data.split_at(16);
+    let key = ssp::AesKey::clone_from_slice(key);
+
+    if let Ok(decrypted) = essp::decrypt(&key, packet) {
+        // a valid packet survives an encryption round trip
+        let encrypted = essp::encrypt(&key, &decrypted).expect("decrypted data fits a packet");
+        assert_eq!(essp::decrypt(&key, &encrypted).ok(), Some(decrypted));
+    }
+});
@@ -0,0 +1,29 @@
+//! Pushes arbitrary bytes through the incremental frame decoder, and the unstuffing codec.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ssp_server::framing::{self, FrameDecoder};
+
+fuzz_target!(|data: &[u8]| {
+    let mut decoder = FrameDecoder::new();
+
+    for &byte in data {
+        match decoder.push(byte) {
+            Ok(true) => {
+                let frame = decoder.frame().to_vec();
+                assert_eq!(Some(frame.len()), decoder.expected_len());
+
+                // every decoded frame survives a stuffing round trip
+                let stuffed = framing::stuff(&frame).expect("decoded frame starts with STX");
+                assert_eq!(framing::unstuff(&stuffed).ok(), Some(frame));
+
+                decoder.reset();
+            }
+            Ok(false) => assert!(decoder.min_remaining() > 0),
+            Err(_) => decoder.reset(),
+        }
+    }
+
+    let _ = framing::unstuff(data);
+});
@@ -0,0 +1,35 @@
+//! Parses arbitrary unstuffed frames as responses to every command type.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ssp::MessageType;
+use ssp_server::framing;
+
+const COMMANDS: [MessageType; 12] = [
+    MessageType::Poll,
+    MessageType::SetupRequest,
+    MessageType::UnitData,
+    MessageType::ChannelValueData,
+    MessageType::SerialNumber,
+    MessageType::DatasetVersion,
+    MessageType::LastRejectCode,
+    MessageType::RequestKeyExchange,
+    MessageType::Synchronisation,
+    MessageType::Enable,
+    MessageType::SetInhibits,
+    MessageType::Encrypted,
+];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, frame)) = data.split_first() else {
+        return;
+    };
+
+    let command = COMMANDS[usize::from(selector) % COMMANDS.len()];
+
+    if let Ok(res) = framing::parse_response(frame, command) {
+        let _ = res.as_response().response_status();
+        let _ = format!("{res}");
+    }
+});
@@ -0,0 +1,67 @@
+//! Feeds arbitrary bytes from the wire through the resynchronizing frame reader.
+
+#![no_main]
+
+use std::io::{self, Read, Write};
+use std::time;
+
+use libfuzzer_sys::fuzz_target;
+use ssp_server::framing::{self, FrameDecoder, FrameTimeouts};
+use ssp_server::transport::Transport;
+
+// Transport reading from a fixed buffer, in chunks of varying size.
+struct WireTransport<'a> {
+    wire: &'a [u8],
+    chunk: usize,
+}
+
+impl Read for WireTransport<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.wire.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        let n = buf.len().min(self.wire.len()).min(self.chunk);
+        buf[..n].copy_from_slice(&self.wire[..n]);
+        self.wire = &self.wire[n..];
+
+        Ok(n)
+    }
+}
+
+impl Write for WireTransport<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for WireTransport<'_> {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk, wire)) = data.split_first() else {
+        return;
+    };
+
+    let mut transport = WireTransport {
+        wire,
+        chunk: usize::from(chunk).max(1),
+    };
+    let mut decoder = FrameDecoder::new();
+    let timeouts = FrameTimeouts {
+        response: time::Duration::ZERO,
+        inter_byte: time::Duration::ZERO,
+    };
+
+    // keep reading frames until the wire runs dry, every frame read must be valid
+    while framing::read_frame_timeout(&mut transport, &mut decoder, timeouts).is_ok() {
+        assert!(framing::check_frame(decoder.frame()).is_ok());
+    }
+});
@@ -0,0 +1,488 @@
+//! Append-only audit log of privileged device commands.
+//!
+//! Payouts, empties, channel inhibit changes, and key operations are recorded with the acting
+//! client, time, parameters, and result. The audit log is separate from debug logging, so it is
+//! kept regardless of the log level, and never rotated by the logger.
+//!
+//! Frontends identify the acting client with [with_actor]. Commands sent outside a frontend are
+//! recorded with the [AUDIT_LOCAL_ACTOR].
+//!
+//! Every accepted note is also recorded, by the [AUDIT_DEVICE_ACTOR], with the details reported by
+//! the device (see [NoteRecord]), so disputed credits can be traced back to the device, and to a
+//! single note where the device reports the note serial number.
+//!
+//! The audit log is kept in a file by default, or in any [Storage] backend with
+//! [from_storage](AuditLog::from_storage).
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::lease::ClientId;
+use crate::storage::{FileStorage, Storage, AUDIT_COLLECTION};
+
+/// Environment variable with the path of the audit log file.
+pub const AUDIT_ENV_PATH: &str = "SSP_AUDIT_LOG";
+/// Actor recorded for commands sent outside a frontend, e.g. by the CLI.
+pub const AUDIT_LOCAL_ACTOR: &str = "local";
+/// Actor recorded for events reported by the device, e.g. accepted notes.
+pub const AUDIT_DEVICE_ACTOR: &str = "device";
+
+thread_local! {
+    static ACTOR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with `actor` recorded as the acting client for audited commands.
+///
+/// Nested calls override the outer actor for the duration of `f`.
+pub fn with_actor<T, F: FnOnce() -> T>(actor: &str, f: F) -> T {
+    let prev = ACTOR.with(|cur| cur.replace(Some(actor.into())));
+    let res = f();
+    ACTOR.with(|cur| *cur.borrow_mut() = prev);
+
+    res
+}
+
+/// Gets the actor recorded for a connection-based frontend client.
+pub fn client_actor(client: ClientId) -> String {
+    format!("client:{}", client.as_u64())
+}
+
+/// Gets the acting client set with [with_actor], if any.
+pub fn actor() -> Option<String> {
+    ACTOR.with(|cur| cur.borrow().clone())
+}
+
+/// Privileged operation recorded in the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AuditOp {
+    /// Notes dispensed from the payout module.
+    Payout,
+    /// Payout module emptied to the cashbox.
+    Empty,
+    /// Payout module emptied to the cashbox, with a count of the emptied notes.
+    SmartEmpty,
+    /// Channel inhibits changed, i.e. which notes are accepted.
+    SetInhibits,
+    /// eSSP session key negotiated.
+    KeyNegotiation,
+    /// Fixed part of the eSSP key replaced.
+    SetFixedKey,
+    /// Note accepted, and moved into storage.
+    NoteCredit,
+}
+
+impl AuditOp {
+    /// Gets the [AuditOp] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Payout => "payout",
+            Self::Empty => "empty",
+            Self::SmartEmpty => "smart_empty",
+            Self::SetInhibits => "set_inhibits",
+            Self::KeyNegotiation => "key_negotiation",
+            Self::SetFixedKey => "set_fixed_key",
+            Self::NoteCredit => "note_credit",
+        }
+    }
+
+    /// Parses an [AuditOp] from its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "payout" => Some(Self::Payout),
+            "empty" => Some(Self::Empty),
+            "smart_empty" => Some(Self::SmartEmpty),
+            "set_inhibits" => Some(Self::SetInhibits),
+            "key_negotiation" => Some(Self::KeyNegotiation),
+            "set_fixed_key" => Some(Self::SetFixedKey),
+            "note_credit" => Some(Self::NoteCredit),
+            _ => None,
+        }
+    }
+}
+
+/// Single entry in the audit log.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuditRecord {
+    /// Sequence number, increasing by one for every record.
+    pub seq: u64,
+    /// Time of the command, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Acting client.
+    pub actor: String,
+    /// Recorded operation.
+    pub op: AuditOp,
+    /// Command parameters, never including key material.
+    pub params: String,
+    /// Error returned by the command, `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    /// Gets whether the command succeeded.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.seq,
+            self.timestamp_ms,
+            escape(self.actor.as_str()),
+            self.op.as_str(),
+            if self.is_ok() { "ok" } else { "error" },
+            escape(self.params.as_str()),
+            escape(self.error.as_deref().unwrap_or_default()),
+        )
+    }
+
+    fn from_line(line: &str) -> Result<Self> {
+        let invalid = || ssp::Error::Io(format!("invalid audit record: {line}"));
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 7 {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            seq: fields[0].parse().map_err(|_| invalid())?,
+            timestamp_ms: fields[1].parse().map_err(|_| invalid())?,
+            actor: unescape(fields[2]),
+            op: AuditOp::from_name(fields[3]).ok_or_else(invalid)?,
+            params: unescape(fields[5]),
+            error: match fields[4] {
+                "ok" => None,
+                "error" => Some(unescape(fields[6])),
+                _ => return Err(invalid()),
+            },
+        })
+    }
+}
+
+/// Details of an accepted note, recorded as a [NoteCredit](AuditOp::NoteCredit) audit record.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NoteRecord {
+    /// Channel the note was accepted on.
+    pub channel: u8,
+    /// Value of the note.
+    pub value: u32,
+    /// Protocol version negotiated with the device.
+    pub protocol_version: u8,
+    /// Serial number of the device, zero if unknown.
+    pub device_serial: u32,
+    /// Serial number of the note, if reported by the device.
+    ///
+    /// The `ssp` library does not read note serial numbers, so notes accepted by a
+    /// [DeviceHandle](crate::DeviceHandle) are recorded without one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub note_serial: Option<String>,
+}
+
+impl NoteRecord {
+    /// Creates a new [NoteRecord], without a note serial number.
+    pub const fn new(channel: u8, value: u32, protocol_version: u8, device_serial: u32) -> Self {
+        Self {
+            channel,
+            value,
+            protocol_version,
+            device_serial,
+            note_serial: None,
+        }
+    }
+
+    /// Builder function that sets the serial number of the note.
+    ///
+    /// Whitespace is removed, since it separates the audit record parameters.
+    pub fn with_note_serial(mut self, note_serial: &str) -> Self {
+        self.note_serial = Some(note_serial.split_whitespace().collect());
+        self
+    }
+
+    /// Gets the audit record parameters for the note.
+    pub fn to_params(&self) -> String {
+        let mut params = format!(
+            "channel={} value={} protocol={} device_serial={}",
+            self.channel, self.value, self.protocol_version, self.device_serial
+        );
+
+        if let Some(note_serial) = self.note_serial.as_deref() {
+            params.push_str(&format!(" note_serial={note_serial}"));
+        }
+
+        params
+    }
+
+    /// Parses a [NoteRecord] from audit record parameters.
+    pub fn from_params(params: &str) -> Option<Self> {
+        let mut note = Self::default();
+
+        for field in params.split_whitespace() {
+            match field.split_once('=')? {
+                ("channel", val) => note.channel = val.parse().ok()?,
+                ("value", val) => note.value = val.parse().ok()?,
+                ("protocol", val) => note.protocol_version = val.parse().ok()?,
+                // records written before the note serial number was added
+                ("device_serial" | "serial", val) => note.device_serial = val.parse().ok()?,
+                ("note_serial", val) => note.note_serial = Some(val.into()),
+                _ => (),
+            }
+        }
+
+        Some(note)
+    }
+
+    /// Gets the [NoteRecord] of a [NoteCredit](AuditOp::NoteCredit) audit `record`.
+    pub fn from_record(record: &AuditRecord) -> Option<Self> {
+        if record.op == AuditOp::NoteCredit {
+            Self::from_params(record.params.as_str())
+        } else {
+            None
+        }
+    }
+}
+
+/// Filter for [AuditLog::query].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuditQuery {
+    /// Only records of this operation.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub op: Option<AuditOp>,
+    /// Only records of this actor.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub actor: Option<String>,
+    /// Only records at, or after this time (milliseconds since the Unix epoch).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub since_ms: Option<u64>,
+    /// Only records before this time (milliseconds since the Unix epoch).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub until_ms: Option<u64>,
+    /// Only the most recent matching records, up to this number.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    /// Creates a new [AuditQuery] matching all records.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder function that only matches records of `op`.
+    pub fn with_op(mut self, op: AuditOp) -> Self {
+        self.op = Some(op);
+        self
+    }
+
+    /// Builder function that only matches records of `actor`.
+    pub fn with_actor(mut self, actor: &str) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    /// Builder function that only matches records at, or after `since_ms`.
+    pub fn with_since_ms(mut self, since_ms: u64) -> Self {
+        self.since_ms = Some(since_ms);
+        self
+    }
+
+    /// Builder function that only matches records before `until_ms`.
+    pub fn with_until_ms(mut self, until_ms: u64) -> Self {
+        self.until_ms = Some(until_ms);
+        self
+    }
+
+    /// Builder function that limits the result to the `limit` most recent records.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Gets whether `record` matches the filter, ignoring the limit.
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        self.op.map(|op| op == record.op).unwrap_or(true)
+            && self
+                .actor
+                .as_ref()
+                .map(|actor| actor == &record.actor)
+                .unwrap_or(true)
+            && self
+                .since_ms
+                .map(|since| record.timestamp_ms >= since)
+                .unwrap_or(true)
+            && self
+                .until_ms
+                .map(|until| record.timestamp_ms < until)
+                .unwrap_or(true)
+    }
+}
+
+/// Append-only audit log, with one record per line.
+///
+/// Records are durably stored before the command result is returned. Cloned logs share the same
+/// storage.
+#[derive(Clone)]
+pub struct AuditLog {
+    path: String,
+    storage: Arc<dyn Storage>,
+    next_seq: Arc<Mutex<u64>>,
+}
+
+impl AuditLog {
+    /// Opens the audit log at `path`, creating it if it does not exist.
+    ///
+    /// New records are appended after the existing records.
+    pub fn open(path: &str) -> Result<Self> {
+        Self::from_storage(Arc::new(FileStorage::for_file(AUDIT_COLLECTION, path)))
+    }
+
+    /// Opens the audit log kept in the [AUDIT_COLLECTION] of the `storage`.
+    ///
+    /// New records are appended after the existing records.
+    pub fn from_storage(storage: Arc<dyn Storage>) -> Result<Self> {
+        let log = Self {
+            path: storage.location(AUDIT_COLLECTION),
+            storage,
+            next_seq: Arc::new(Mutex::new(0)),
+        };
+
+        let next_seq = log.read_records()?.last().map(|r| r.seq + 1).unwrap_or(0);
+        *log.next_seq.lock() = next_seq;
+
+        Ok(log)
+    }
+
+    /// Opens the audit log at the path set in the [AUDIT_ENV_PATH] environment variable.
+    ///
+    /// Returns `Ok(None)` if the variable is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(AUDIT_ENV_PATH) {
+            Ok(path) => Self::open(path.as_str()).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Gets the path of the audit log file, or the location in the [Storage] backend.
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    /// Appends a record of `op` with the result of the command.
+    ///
+    /// The actor is taken from [with_actor], or [AUDIT_LOCAL_ACTOR] if unset.
+    pub fn record<T>(&self, op: AuditOp, params: &str, result: &Result<T>) -> Result<AuditRecord> {
+        let timestamp_ms = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        // hold the sequence number, so records are stored in order
+        let mut next_seq = self.next_seq.lock();
+
+        let record = AuditRecord {
+            seq: *next_seq,
+            timestamp_ms,
+            actor: actor().unwrap_or(AUDIT_LOCAL_ACTOR.into()),
+            op,
+            params: params.into(),
+            error: result.as_ref().err().map(|err| format!("{err}")),
+        };
+
+        self.storage
+            .append(AUDIT_COLLECTION, record.to_line().as_str())?;
+        *next_seq += 1;
+
+        Ok(record)
+    }
+
+    /// Appends a [NoteCredit](AuditOp::NoteCredit) record for an accepted `note`, recorded by the
+    /// [AUDIT_DEVICE_ACTOR].
+    pub fn record_note(&self, note: &NoteRecord) -> Result<AuditRecord> {
+        with_actor(AUDIT_DEVICE_ACTOR, || {
+            self.record(AuditOp::NoteCredit, note.to_params().as_str(), &Ok(()))
+        })
+    }
+
+    /// Gets the accepted notes matching `query`, oldest first.
+    ///
+    /// The operation in the `query` is ignored.
+    pub fn notes(&self, query: &AuditQuery) -> Result<Vec<(AuditRecord, NoteRecord)>> {
+        let query = query.clone().with_op(AuditOp::NoteCredit);
+
+        Ok(self
+            .query(&query)?
+            .into_iter()
+            .filter_map(|record| NoteRecord::from_record(&record).map(|note| (record, note)))
+            .collect())
+    }
+
+    /// Gets the records matching `query`, oldest first.
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+        let mut records: Vec<AuditRecord> = self
+            .read_records()?
+            .into_iter()
+            .filter(|record| query.matches(record))
+            .collect();
+
+        if let Some(limit) = query.limit {
+            let skip = records.len().saturating_sub(limit);
+            records.drain(..skip);
+        }
+
+        Ok(records)
+    }
+
+    fn read_records(&self) -> Result<Vec<AuditRecord>> {
+        self.storage
+            .load(AUDIT_COLLECTION)?
+            .iter()
+            .map(|line| AuditRecord::from_line(line))
+            .collect()
+    }
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AuditLog")
+            .field("path", &self.path)
+            .field("storage", &self.storage.name())
+            .finish_non_exhaustive()
+    }
+}
+
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
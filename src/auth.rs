@@ -0,0 +1,228 @@
+//! Token authentication, and role-based authorization for the network frontends.
+//!
+//! Each client presents a bearer token, mapped to a [Role]. Commands require a minimum role:
+//!
+//! - [Observer](Role::Observer): status queries, and device events, e.g. for a kiosk UI
+//! - [Operator](Role::Operator): note handling, i.e. enabling, disabling, stacking, and rejecting
+//! - [Maintainer](Role::Maintainer): payouts, empties, resets, and key management
+//!
+//! Without configured tokens, authentication is disabled, and all clients are treated as
+//! maintainers. Configure tokens with [with_token](Authenticator::with_token), or from the
+//! [AUTH_ENV_TOKENS] and [AUTH_ENV_TOKENS_FILE] environment variables.
+
+use std::fmt;
+use std::sync::Arc;
+
+use ssp::Result;
+
+use crate::error::{self, Error};
+
+/// Environment variable with a comma-separated list of `role:token` entries.
+pub const AUTH_ENV_TOKENS: &str = "SSP_AUTH_TOKENS";
+/// Environment variable with the path of a file of `role:token` entries, one per line.
+pub const AUTH_ENV_TOKENS_FILE: &str = "SSP_AUTH_TOKENS_FILE";
+/// Prefix of the error message returned for missing, or unknown tokens.
+pub const AUTH_UNAUTHENTICATED: &str = "authentication required";
+/// Prefix of the error message returned when a token's role does not allow a command.
+pub const AUTH_DENIED: &str = "permission denied";
+
+/// Client role, ordered from least to most privileged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Role {
+    /// Read-only access to the device status and events.
+    Observer,
+    /// Note handling commands.
+    Operator,
+    /// All commands, including payouts, empties, and key management.
+    Maintainer,
+}
+
+impl Role {
+    /// Gets the [Role] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Observer => "observer",
+            Self::Operator => "operator",
+            Self::Maintainer => "maintainer",
+        }
+    }
+
+    /// Parses a [Role] from its name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "observer" => Some(Self::Observer),
+            "operator" => Some(Self::Operator),
+            "maintainer" => Some(Self::Maintainer),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Gets the minimum [Role] required to send a command with the given [Method](ssp::Method).
+pub fn required_role(method: ssp::Method) -> Role {
+    match method {
+        ssp::Method::Status => Role::Observer,
+        ssp::Method::Accept
+        | ssp::Method::Stop
+        | ssp::Method::Enable
+        | ssp::Method::Disable
+        | ssp::Method::Reject
+        | ssp::Method::Stack
+        | ssp::Method::StackerFull => Role::Operator,
+        _ => Role::Maintainer,
+    }
+}
+
+/// Maps client tokens to [Role]s.
+///
+/// Cloned authenticators share the same tokens.
+#[derive(Clone, Default)]
+pub struct Authenticator {
+    tokens: Arc<Vec<(String, Role)>>,
+}
+
+impl Authenticator {
+    /// Creates a new [Authenticator] without tokens, i.e. with authentication disabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new [Authenticator] from a list of `role:token` entries, separated by commas or
+    /// newlines.
+    ///
+    /// Empty entries, and lines starting with `#` are ignored.
+    pub fn parse(entries: &str) -> Result<Self> {
+        entries
+            .split([',', '\n'])
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty() && !entry.starts_with('#'))
+            .try_fold(Self::new(), |auth, entry| {
+                let (role, token) = entry.split_once(':').ok_or(ssp::Error::Io(
+                    "expected auth token entry as role:token".into(),
+                ))?;
+
+                let role = Role::from_name(role)
+                    .ok_or(ssp::Error::Io(format!("unknown auth role: {role}")))?;
+
+                Ok(auth.with_token(token.trim(), role))
+            })
+    }
+
+    /// Creates a new [Authenticator] from the [AUTH_ENV_TOKENS_FILE] and [AUTH_ENV_TOKENS]
+    /// environment variables.
+    ///
+    /// Authentication is disabled if neither is set.
+    pub fn from_env() -> Result<Self> {
+        let mut entries = String::new();
+
+        if let Ok(path) = std::env::var(AUTH_ENV_TOKENS_FILE) {
+            entries += std::fs::read_to_string(path)?.as_str();
+            entries.push('\n');
+        }
+
+        if let Ok(tokens) = std::env::var(AUTH_ENV_TOKENS) {
+            entries += tokens.as_str();
+        }
+
+        Self::parse(entries.as_str())
+    }
+
+    /// Builder function that adds a `token` granting `role`.
+    ///
+    /// Empty tokens are ignored.
+    pub fn with_token(mut self, token: &str, role: Role) -> Self {
+        if !token.is_empty() {
+            Arc::make_mut(&mut self.tokens).push((token.into(), role));
+        }
+        self
+    }
+
+    /// Gets whether authentication is enabled, i.e. any tokens are configured.
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Gets the [Role] granted by `token`.
+    ///
+    /// With authentication disabled, all clients are [Maintainer](Role::Maintainer)s.
+    ///
+    /// Returns an [Unauthenticated](Error::Unauthenticated) error if the token is missing, or
+    /// unknown.
+    pub fn authenticate(&self, token: Option<&str>) -> error::Result<Role> {
+        if !self.is_enabled() {
+            return Ok(Role::Maintainer);
+        }
+
+        let token = token.ok_or(Error::unauthenticated(ssp::Error::Io(format!(
+            "{AUTH_UNAUTHENTICATED}, missing token"
+        ))))?;
+
+        // compare against every token, so the timing does not reveal which token matched
+        self.tokens
+            .iter()
+            .fold(None, |found, (known, role)| {
+                if constant_time_eq(known.as_bytes(), token.as_bytes()) {
+                    Some(*role)
+                } else {
+                    found
+                }
+            })
+            .ok_or(Error::unauthenticated(ssp::Error::Io(format!(
+                "{AUTH_UNAUTHENTICATED}, invalid token"
+            ))))
+    }
+
+    /// Checks whether `token` grants at least `role`.
+    ///
+    /// Returns the granted [Role], an [Unauthenticated](Error::Unauthenticated) error if the token
+    /// is missing, or unknown, or a [PermissionDenied](Error::PermissionDenied) error if its role
+    /// is insufficient.
+    pub fn authorize_role(&self, token: Option<&str>, role: Role) -> error::Result<Role> {
+        let granted = self.authenticate(token)?;
+
+        if granted >= role {
+            Ok(granted)
+        } else {
+            Err(Error::permission_denied(ssp::Error::Io(format!(
+                "{AUTH_DENIED}, {granted} role can not send {role} commands"
+            ))))
+        }
+    }
+
+    /// Checks whether `token` allows sending a command with the given [Method](ssp::Method).
+    pub fn authorize(&self, token: Option<&str>, method: ssp::Method) -> error::Result<Role> {
+        self.authorize_role(token, required_role(method))
+    }
+}
+
+impl fmt::Debug for Authenticator {
+    // never print the tokens
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Authenticator")
+            .field("tokens", &self.tokens.len())
+            .finish()
+    }
+}
+
+/// Parses the token from an `Authorization: Bearer <token>` header value.
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+
+    if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() {
+        Some(token.trim())
+    } else {
+        None
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
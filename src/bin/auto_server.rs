@@ -23,7 +23,7 @@ fn main() -> ssp::Result<()> {
         ssp::ProtocolVersion::Eight,
     )?;
 
-    let handle = server.handle()?;
+    let mut handle = server.handle()?;
 
     let rgb = [
         // Red
@@ -51,6 +51,7 @@ fn main() -> ssp::Result<()> {
     }
 
     handle.disable()?;
+    handle.shutdown()?;
 
     Ok(())
 }
@@ -0,0 +1,334 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use ssp::ResponseOps;
+
+extern crate ssp_server;
+
+use ssp_server::{
+    capture, cash_levels, export, journal, key_store, registry, DeviceHandle, PollMode,
+};
+
+const USAGE: &str =
+    "Usage: ssp-cli [--port <PATH>] [--fixed-key <HEX>] [--capture <FILE>] [--watch]
+               [COMMAND [ARGS]]
+
+Options:
+    -p, --port <PATH>       serial device, or tcp://<host>:<port> bridge (default: /dev/ttyUSB0)
+    -k, --fixed-key <HEX>   site-specific eSSP fixed key, 16 hex digits (default: $SSP_FIXED_KEY,
+                            or the ITL default key)
+    -c, --capture <FILE>    record all traffic with the device to a capture file
+                            (default: $SSP_CAPTURE)
+    -w, --watch             print device events until interrupted
+    -h, --help              print this message
+
+Commands:
+    sync                        synchronize with the device
+    enable                      initialize and enable the device
+    disable                     disable the device
+    poll                        send a single poll, and print the response
+    status                      print the device status
+    payout <AMOUNT> <CURRENCY>  dispense a note, e.g. `payout 20 EUR`
+    empty                       empty all notes into the cashbox
+    levels                      print the estimated note levels ($SSP_CASH_LEVELS)
+    schema [KIND]               print the JSON Schema of frontend messages, KIND is one of:
+                                all (default), commands, responses, events
+    capture <FILE>              pretty-print the frames of a capture file
+    export [SINCE [UNTIL]]      print the journaled transactions ($SSP_JOURNAL) as CSV, SINCE and
+                                UNTIL are YYYY-MM-DD dates, or milliseconds since the Unix epoch
+    summary [SINCE [UNTIL]]     print the journaled transactions per denomination as CSV
+    devices                     print the registered devices ($SSP_DEVICE_REGISTRY)";
+
+/// Default serial device path.
+const SERIAL_PATH: &str = "/dev/ttyUSB0";
+
+struct Args {
+    port: String,
+    fixed_key: Option<String>,
+    capture: Option<String>,
+    watch: bool,
+    command: Vec<String>,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = std::env::args().skip(1);
+
+        let mut port = SERIAL_PATH.to_string();
+        let mut fixed_key = None;
+        let mut capture = None;
+        let mut watch = false;
+        let mut command = Vec::new();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-p" | "--port" => port = args.next().ok_or("missing value for --port")?,
+                "-k" | "--fixed-key" => {
+                    fixed_key = Some(args.next().ok_or("missing value for --fixed-key")?)
+                }
+                "-c" | "--capture" => {
+                    capture = Some(args.next().ok_or("missing value for --capture")?)
+                }
+                "-w" | "--watch" => watch = true,
+                "-h" | "--help" => return Err(String::new()),
+                _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+                _ => command.push(arg),
+            }
+        }
+
+        if command.is_empty() && !watch {
+            Err("missing command".into())
+        } else {
+            Ok(Self {
+                port,
+                fixed_key,
+                capture,
+                watch,
+                command,
+            })
+        }
+    }
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn"))
+        .format_timestamp(Some(env_logger::fmt::TimestampPrecision::Millis))
+        .init();
+
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("error: {err}\n");
+            }
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(args) {
+        eprintln!("error: {err}");
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Args) -> ssp::Result<()> {
+    // schemas, captures, exports, and registered devices are printed offline, without opening
+    // the device
+    match args
+        .command
+        .split_first()
+        .map(|(command, params)| (command.as_str(), params))
+    {
+        Some(("schema", params)) => return print_schema(params),
+        Some(("capture", params)) => return print_capture(params),
+        Some(("export", params)) => return print_export(params, false),
+        Some(("summary", params)) => return print_export(params, true),
+        Some(("devices", _)) => return print_devices(),
+        _ => (),
+    }
+
+    let mut handle = DeviceHandle::new(args.port.as_str())?
+        .with_env_fixed_key()?
+        .with_env_audit_log()?
+        .with_env_journal()?
+        .with_env_cash_levels()?
+        .with_env_device_registry()?
+        .with_env_payout_intents()?;
+
+    handle = match args.capture.as_deref() {
+        Some(path) => handle.with_capture(capture::CaptureFile::create(path)?)?,
+        None => handle.with_env_capture()?,
+    };
+
+    if let Some(fixed_key) = args.fixed_key.as_deref() {
+        handle = handle.with_fixed_key(key_store::parse_fixed_key(fixed_key)?);
+    }
+
+    if let Some((command, params)) = args.command.split_first() {
+        run_command(&mut handle, command.as_str(), params)?;
+    }
+
+    if args.watch {
+        watch(&handle)?;
+    }
+
+    Ok(())
+}
+
+fn run_command(handle: &mut DeviceHandle, command: &str, params: &[String]) -> ssp::Result<()> {
+    match command {
+        "sync" => println!("{}", handle.sync()?.response_status()),
+        "enable" => {
+            handle.enable_device(ssp::ProtocolVersion::Eight)?;
+            println!("{}", handle.enable()?.response_status());
+        }
+        "disable" => println!("{}", handle.disable()?.response_status()),
+        "poll" => println!("{}", handle.poll()?),
+        "status" => println!("{}", handle.device_status()?),
+        "payout" => {
+            let list = payout_list(params)?;
+            handle.dispense(&list)?;
+            println!("{list}");
+        }
+        "empty" => {
+            // emptying requires an eSSP session
+            handle.negotiate_keys()?;
+            println!("{}", handle.empty()?.response_status());
+        }
+        "levels" => {
+            // the device levels are not available, print the persisted estimate instead
+            let model = handle.cash_levels().ok_or(ssp::Error::Io(format!(
+                "note levels are not supported by the ssp protocol library, set {} for estimated levels",
+                cash_levels::CASH_LEVELS_ENV_PATH
+            )))?;
+            let estimate = model.estimate();
+
+            for (location, levels) in [
+                ("cashbox", &estimate.cashbox),
+                ("recycler", &estimate.recycler),
+            ] {
+                for level in levels.iter() {
+                    println!("{location}\t{}\t{}", level.value, level.count);
+                }
+            }
+            println!("total\t{}", estimate.total());
+
+            for alert in model.active_alerts() {
+                println!("alert\t{alert}");
+            }
+        }
+        _ => return Err(ssp::Error::Io(format!("unknown command: {command}"))),
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "jsonrpc")]
+fn print_schema(params: &[String]) -> ssp::Result<()> {
+    let kind = params.first().map(|p| p.as_str()).unwrap_or("all");
+
+    let schema = ssp_server::schema::schema_by_name(kind)
+        .ok_or(ssp::Error::Io(format!("unknown schema kind: {kind}")))?;
+
+    let json = serde_json::to_string_pretty(&schema)
+        .map_err(|err| ssp::Error::Io(format!("schema encoding error: {err}")))?;
+
+    println!("{json}");
+
+    Ok(())
+}
+
+#[cfg(not(feature = "jsonrpc"))]
+fn print_schema(_params: &[String]) -> ssp::Result<()> {
+    Err(ssp::Error::Io(
+        "schema export requires the `jsonrpc` feature".into(),
+    ))
+}
+
+fn print_capture(params: &[String]) -> ssp::Result<()> {
+    let path = params
+        .first()
+        .ok_or(ssp::Error::Io("usage: capture <FILE>".into()))?;
+
+    print!("{}", capture::pretty_print(&capture::read_capture(path)?));
+
+    Ok(())
+}
+
+fn print_export(params: &[String], summary: bool) -> ssp::Result<()> {
+    let journal = journal::TransactionJournal::from_env()?.ok_or(ssp::Error::Io(format!(
+        "set {} to the transaction journal",
+        journal::JOURNAL_ENV_PATH
+    )))?;
+
+    let date = |date: &String| {
+        export::parse_date(date).ok_or(ssp::Error::Io(format!("invalid date: {date}")))
+    };
+
+    let mut csv = export::CsvExport::new();
+    if let Some(since) = params.first() {
+        csv = csv.with_since_ms(date(since)?);
+    }
+    if let Some(until) = params.get(1) {
+        csv = csv.with_until_ms(date(until)?);
+    }
+
+    let entries = journal.entries()?;
+    let stdout = std::io::stdout().lock();
+
+    if summary {
+        csv.write_summary(&entries, stdout)?;
+    } else {
+        csv.write_transactions(&entries, stdout)?;
+    }
+
+    Ok(())
+}
+
+fn print_devices() -> ssp::Result<()> {
+    let registry = registry::DeviceRegistry::from_env()?.ok_or(ssp::Error::Io(format!(
+        "set {} to the device registry",
+        registry::DEVICE_REGISTRY_ENV_PATH
+    )))?;
+
+    for device in registry.devices() {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            device.serial_number,
+            device.unit_type,
+            device.firmware,
+            device.dataset,
+            device.port,
+            device.last_seen_ms
+        );
+    }
+
+    Ok(())
+}
+
+fn payout_list(params: &[String]) -> ssp::Result<ssp::PayoutDenominationList> {
+    let (amount, currency) = match params {
+        [amount, currency] => (amount, currency),
+        _ => return Err(ssp::Error::Io("usage: payout <AMOUNT> <CURRENCY>".into())),
+    };
+
+    // amounts are given in whole units, the device expects the lowest currency unit
+    let value = amount
+        .parse::<u32>()
+        .ok()
+        .and_then(|a| a.checked_mul(100))
+        .ok_or(ssp::Error::Io(format!("invalid payout amount: {amount}")))?;
+
+    let mut list = ssp::PayoutDenominationList::new();
+    list.as_inner_mut()
+        .push(ssp::PayoutDenomination::create(
+            1,
+            value,
+            ssp::CountryCode::from(currency.to_uppercase().as_str()),
+        ))
+        .map_err(|_| ssp::Error::InvalidLength((1, ssp::MAX_PAYOUTS)))?;
+
+    Ok(list)
+}
+
+fn watch(handle: &DeviceHandle) -> ssp::Result<()> {
+    let stop = Arc::new(AtomicBool::new(false));
+
+    // Set signal handlers
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&stop))?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))?;
+
+    let push_queue =
+        handle.start_background_polling_with_queue(Arc::clone(&stop), PollMode::Auto)?;
+
+    while !stop.load(Ordering::Relaxed) {
+        if let Ok(event) = push_queue.pop_event() {
+            println!("{event}");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use parking_lot::Mutex;
+
+extern crate ssp_server;
+
+use ssp_server::{grpc, DeviceHandle, PollMode};
+
+fn main() -> ssp::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug"))
+        .format_timestamp(Some(env_logger::fmt::TimestampPrecision::Millis))
+        .init();
+
+    let stop = Arc::new(AtomicBool::new(false));
+
+    // Set signal handlers
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&stop))?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))?;
+
+    let handle = DeviceHandle::new("/dev/ttyUSB0")?
+        .with_env_fixed_key()?
+        .with_env_auth()?
+        .with_env_audit_log()?
+        .with_env_journal()?
+        .with_env_cash_levels()?
+        .with_env_event_log()?
+        .with_env_reject_history()?
+        .with_env_device_registry()?
+        .with_env_payout_intents()?
+        .with_env_capture()?
+        .with_env_secure_shutdown();
+
+    // enable the device to fully configure
+    handle.enable_device(ssp::ProtocolVersion::Eight)?;
+    // disable again to allow clients to decide when to begin accepting notes
+    handle.disable()?;
+
+    let push_queue =
+        handle.start_background_polling_with_queue(Arc::clone(&stop), PollMode::Interactive)?;
+
+    let handle = Arc::new(Mutex::new(handle));
+    let shutdown_handle = Arc::clone(&handle);
+    let addr = grpc::get_grpc_addr()?;
+
+    #[cfg(feature = "tls")]
+    match ssp_server::tls::TlsConfig::from_env()? {
+        Some(tls) => grpc::serve_tls(handle, push_queue, addr, &tls, Arc::clone(&stop))?,
+        None => grpc::serve(handle, push_queue, addr, Arc::clone(&stop))?,
+    }
+    #[cfg(not(feature = "tls"))]
+    grpc::serve(handle, push_queue, addr, Arc::clone(&stop))?;
+
+    // stop the background polling routine
+    stop.store(true, Ordering::SeqCst);
+
+    shutdown_handle.lock().shutdown()?;
+
+    Ok(())
+}
@@ -28,12 +28,20 @@ fn main() -> ssp::Result<()> {
         false,
     )?;
 
+    // let systemd know the device is initialized, no-op when not running as a service
+    ssp_server::systemd::notify_ready()?;
+    ssp_server::systemd::start_watchdog(&*server.handle()?, Arc::clone(&stop));
+
     while !stop.load(Ordering::Relaxed) {
         server.accept(Arc::clone(&stop))?;
     }
 
+    ssp_server::systemd::notify_stopping()?;
+
     // stop the background polling routine
     stop.store(true, Ordering::SeqCst);
 
+    server.handle()?.shutdown()?;
+
     Ok(())
 }
@@ -0,0 +1,348 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::{fmt, fs, thread, time};
+
+use ssp::MessageOps;
+
+extern crate ssp_server;
+
+use ssp_server::DeviceHandle;
+
+const USAGE: &str = "Usage: ssp-soak [--port <PATH>] [--emulator] [--encrypt] [--duration <SECS>]
+                [--command-interval <SECS>] [--note-interval <SECS>]
+                [--report-interval <SECS>] [--max-error-rate <RATE>]
+
+Polls a device continuously, sends periodic commands, and optionally inserts notes into an
+emulated device, printing error rates, and memory use, until the duration elapses, or the
+process is interrupted.
+
+Options:
+    -p, --port <PATH>               serial device, or tcp://<host>:<port> bridge
+                                    (default: /dev/ttyUSB0)
+    -E, --emulator                  soak an in-memory emulated device instead of a port, requires
+                                    the `emulator` feature
+    -e, --encrypt                   negotiate an eSSP session key before polling
+    -d, --duration <SECS>           stop after the duration (default: until interrupted)
+    -c, --command-interval <SECS>   interval between periodic commands (default: 10)
+    -n, --note-interval <SECS>      interval between notes inserted into the emulated device,
+                                    0 disables note traffic (default: 5)
+    -r, --report-interval <SECS>    interval between progress reports (default: 60)
+    -m, --max-error-rate <RATE>     fail if more than the share of exchanges failed
+                                    (default: 0.01)
+    -h, --help                      print this message";
+
+/// Default serial device path.
+const SERIAL_PATH: &str = "/dev/ttyUSB0";
+/// Interval between polls (milliseconds).
+const POLL_INTERVAL_MS: u64 = 200;
+/// Credit event status byte.
+const NOTE_CREDIT: u8 = 0xee;
+
+struct Args {
+    port: String,
+    emulator: bool,
+    encrypt: bool,
+    duration: Option<time::Duration>,
+    command_interval: time::Duration,
+    note_interval: Option<time::Duration>,
+    report_interval: time::Duration,
+    max_error_rate: f64,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = std::env::args().skip(1);
+
+        let mut parsed = Self {
+            port: SERIAL_PATH.to_string(),
+            emulator: false,
+            encrypt: false,
+            duration: None,
+            command_interval: time::Duration::from_secs(10),
+            note_interval: Some(time::Duration::from_secs(5)),
+            report_interval: time::Duration::from_secs(60),
+            max_error_rate: 0.01,
+        };
+
+        let mut secs = |name: &str, value: Option<String>| -> Result<time::Duration, String> {
+            let value = value.ok_or(format!("missing value for {name}"))?;
+            value
+                .parse::<u64>()
+                .map(time::Duration::from_secs)
+                .map_err(|_| format!("invalid value for {name}: {value}"))
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-p" | "--port" => parsed.port = args.next().ok_or("missing value for --port")?,
+                "-E" | "--emulator" => parsed.emulator = true,
+                "-e" | "--encrypt" => parsed.encrypt = true,
+                "-d" | "--duration" => parsed.duration = Some(secs("--duration", args.next())?),
+                "-c" | "--command-interval" => {
+                    parsed.command_interval = secs("--command-interval", args.next())?
+                }
+                "-n" | "--note-interval" => {
+                    let interval = secs("--note-interval", args.next())?;
+                    parsed.note_interval = (!interval.is_zero()).then_some(interval);
+                }
+                "-r" | "--report-interval" => {
+                    parsed.report_interval = secs("--report-interval", args.next())?
+                }
+                "-m" | "--max-error-rate" => {
+                    let value = args.next().ok_or("missing value for --max-error-rate")?;
+                    parsed.max_error_rate = value
+                        .parse()
+                        .map_err(|_| format!("invalid value for --max-error-rate: {value}"))?;
+                }
+                "-h" | "--help" => return Err(String::new()),
+                _ => return Err(format!("unknown option: {arg}")),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+// Counters of a soak run.
+#[derive(Debug, Default)]
+struct Stats {
+    polls: u64,
+    poll_errors: u64,
+    commands: u64,
+    command_errors: u64,
+    notes_inserted: u64,
+    credits: u64,
+    max_poll_gap: time::Duration,
+    start_rss_kb: Option<u64>,
+    rss_kb: Option<u64>,
+    max_rss_kb: Option<u64>,
+}
+
+impl Stats {
+    fn exchanges(&self) -> u64 {
+        self.polls + self.commands
+    }
+
+    fn errors(&self) -> u64 {
+        self.poll_errors + self.command_errors
+    }
+
+    fn error_rate(&self) -> f64 {
+        match self.exchanges() {
+            0 => 0.0,
+            n => self.errors() as f64 / n as f64,
+        }
+    }
+
+    fn sample_memory(&mut self) {
+        self.rss_kb = rss_kb();
+        self.start_rss_kb = self.start_rss_kb.or(self.rss_kb);
+        self.max_rss_kb = self.max_rss_kb.max(self.rss_kb);
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kb = |kb: Option<u64>| kb.map_or("-".into(), |kb| format!("{kb}kB"));
+
+        write!(
+            f,
+            "polls={} poll_errors={} commands={} command_errors={} error_rate={:.4} \
+             notes={} credits={} max_poll_gap={:?} rss={} start_rss={} max_rss={}",
+            self.polls,
+            self.poll_errors,
+            self.commands,
+            self.command_errors,
+            self.error_rate(),
+            self.notes_inserted,
+            self.credits,
+            self.max_poll_gap,
+            kb(self.rss_kb),
+            kb(self.start_rss_kb),
+            kb(self.max_rss_kb),
+        )
+    }
+}
+
+// Gets the resident memory of the process, on Linux.
+fn rss_kb() -> Option<u64> {
+    let statm = fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+
+    Some(pages * 4)
+}
+
+// Source of note traffic, inserting notes into an emulated device.
+type NoteSource = Box<dyn FnMut(u8) -> ssp::Result<()>>;
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn"))
+        .format_timestamp(Some(env_logger::fmt::TimestampPrecision::Millis))
+        .init();
+
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("error: {err}\n");
+            }
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    match run(args) {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(feature = "emulator")]
+fn emulated_device() -> ssp::Result<(DeviceHandle, NoteSource)> {
+    use parking_lot::Mutex;
+    use ssp_server::emulator::{Emulator, EmulatorTransport};
+
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+    let handle = DeviceHandle::from_transport(EmulatorTransport::new(Arc::clone(&emulator)))?;
+
+    Ok((
+        handle,
+        Box::new(move |channel| emulator.lock().credit(channel)),
+    ))
+}
+
+#[cfg(not(feature = "emulator"))]
+fn emulated_device() -> ssp::Result<(DeviceHandle, NoteSource)> {
+    Err(ssp::Error::Io(
+        "soaking an emulated device requires the `emulator` feature".into(),
+    ))
+}
+
+// Runs the soak, and returns whether the error rate stayed within the limit.
+fn run(args: Args) -> ssp::Result<bool> {
+    let stop = Arc::new(AtomicBool::new(false));
+
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&stop))?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))?;
+
+    let (mut handle, mut notes) = if args.emulator {
+        let (handle, notes) = emulated_device()?;
+        (handle, args.note_interval.map(|_| notes))
+    } else {
+        (
+            DeviceHandle::new(args.port.as_str())?.with_env_fixed_key()?,
+            None,
+        )
+    };
+
+    handle.sync()?;
+    if args.encrypt {
+        handle.negotiate_keys()?;
+    }
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+
+    let mut stats = Stats::default();
+    stats.sample_memory();
+
+    let start = time::Instant::now();
+    let mut last_poll = time::Instant::now();
+    let mut last_command = time::Instant::now();
+    let mut last_note = time::Instant::now();
+    let mut last_report = time::Instant::now();
+    let mut command = 0usize;
+    let mut channel = 0u8;
+
+    println!(
+        "soak started: {}",
+        if args.emulator {
+            "emulator"
+        } else {
+            args.port.as_str()
+        }
+    );
+
+    while !stop.load(Ordering::Relaxed) && args.duration.is_none_or(|d| start.elapsed() < d) {
+        thread::sleep(
+            time::Duration::from_millis(POLL_INTERVAL_MS).saturating_sub(last_poll.elapsed()),
+        );
+
+        stats.max_poll_gap = stats.max_poll_gap.max(last_poll.elapsed());
+        last_poll = time::Instant::now();
+
+        stats.polls += 1;
+        match handle.poll() {
+            // channel numbers stay below the credit status byte, so counting the byte counts credits
+            Ok(res) => {
+                stats.credits += res.data()[1..]
+                    .iter()
+                    .filter(|&&b| b == NOTE_CREDIT)
+                    .count() as u64
+            }
+            Err(err) => {
+                stats.poll_errors += 1;
+                log::warn!("Poll failed: {err}");
+            }
+        }
+
+        if last_command.elapsed() >= args.command_interval {
+            last_command = time::Instant::now();
+            stats.commands += 1;
+
+            if let Err(err) = periodic_command(&handle, command) {
+                stats.command_errors += 1;
+                log::warn!("Periodic command failed: {err}");
+            }
+            command = command.wrapping_add(1);
+        }
+
+        if let (Some(notes), Some(interval)) = (notes.as_mut(), args.note_interval) {
+            if last_note.elapsed() >= interval {
+                last_note = time::Instant::now();
+                channel = channel % 7 + 1;
+
+                match notes(channel) {
+                    Ok(()) => stats.notes_inserted += 1,
+                    Err(err) => log::warn!("Failed to insert a note: {err}"),
+                }
+            }
+        }
+
+        if last_report.elapsed() >= args.report_interval {
+            last_report = time::Instant::now();
+            stats.sample_memory();
+            println!("[{:>8}s] {stats}", start.elapsed().as_secs());
+        }
+    }
+
+    stats.sample_memory();
+    println!("soak finished after {:?}: {stats}", start.elapsed());
+
+    let passed = stats.error_rate() <= args.max_error_rate;
+    if !passed {
+        eprintln!(
+            "error rate {:.4} over the {:.4} limit",
+            stats.error_rate(),
+            args.max_error_rate
+        );
+    }
+
+    Ok(passed)
+}
+
+// Sends one of the commands a host sends between polls, in turn.
+fn periodic_command(handle: &DeviceHandle, n: usize) -> ssp::Result<()> {
+    match n % 6 {
+        0 => handle.serial_number().map(drop),
+        1 => handle.dataset_version().map(drop),
+        2 => handle.channel_value_data().map(drop),
+        3 => handle.unit_data().map(drop),
+        4 => handle.last_reject_code().map(drop),
+        _ => handle.setup_request().map(drop),
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::{atomic::AtomicBool, Arc};
+
+extern crate ssp_server;
+
+use ssp_server::bridge::{self, TcpBridge};
+
+fn main() -> ssp::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug"))
+        .format_timestamp(Some(env_logger::fmt::TimestampPrecision::Millis))
+        .init();
+
+    let stop = Arc::new(AtomicBool::new(false));
+
+    // Set signal handlers
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&stop))?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))?;
+
+    let bridge = TcpBridge::new("/dev/ttyUSB0", bridge::get_bridge_addr()?)?;
+
+    #[cfg(feature = "tls")]
+    let bridge = match ssp_server::tls::TlsConfig::from_env()? {
+        Some(tls) => bridge.with_tls(&tls)?,
+        None => bridge,
+    };
+
+    let mut bridge = bridge;
+
+    bridge.serve(&stop)
+}
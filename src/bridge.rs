@@ -0,0 +1,177 @@
+//! Serial-over-TCP bridge for driving a device attached to another host.
+//!
+//! The bridge forwards the raw SSP byte stream between a local serial port and a single TCP
+//! client. The remote side connects using a [TcpTransport](crate::transport::TcpTransport),
+//! e.g. through [DeviceHandle::new_tcp](crate::DeviceHandle::new_tcp).
+//!
+//! Without TLS, the bridge does not authenticate clients, so only expose it on trusted networks.
+//! With the `tls` feature, [with_tls](TcpBridge::with_tls) encrypts the connection, and
+//! optionally authenticates clients by certificate.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::{thread, time};
+
+use serialport::{SerialPort, TTYPort};
+use ssp::Result;
+
+#[cfg(feature = "tls")]
+use crate::tls::{self, TlsConfig};
+use crate::transport;
+
+/// Default listening address for the serial bridge.
+pub const BRIDGE_ADDR: &str = "127.0.0.1:7000";
+/// Environment variable for overriding the serial bridge listening address.
+pub const BRIDGE_ENV_ADDR: &str = "SSP_BRIDGE_ADDR";
+/// Interval for polling the serial port and TCP client for new data (milliseconds).
+pub const BRIDGE_POLL_MS: u64 = 5;
+
+/// Gets the serial bridge listening address from the environment, or the default address.
+pub fn get_bridge_addr() -> Result<SocketAddr> {
+    std::env::var(BRIDGE_ENV_ADDR)
+        .unwrap_or(BRIDGE_ADDR.into())
+        .parse::<SocketAddr>()
+        .map_err(|err| ssp::Error::Io(format!("invalid bridge address: {err}")))
+}
+
+/// Exposes a local serial port to a TCP client.
+pub struct TcpBridge {
+    serial_port: TTYPort,
+    listener: TcpListener,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+impl TcpBridge {
+    /// Creates a new [TcpBridge].
+    ///
+    /// # Parameters
+    ///
+    /// - `serial_path`: file path to the serial device, e.g. `/dev/ttyUSB0`
+    /// - `addr`: socket address to listen on
+    pub fn new(serial_path: &str, addr: SocketAddr) -> Result<Self> {
+        let mut serial_port = transport::open_serial_port(serial_path)?;
+        // short timeout to interleave reads from the serial port and TCP client
+        serial_port.set_timeout(time::Duration::from_millis(BRIDGE_POLL_MS))?;
+
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        log::info!("Bridging {serial_path} on {addr}");
+
+        Ok(Self {
+            serial_port,
+            listener,
+            #[cfg(feature = "tls")]
+            tls: None,
+        })
+    }
+
+    /// Builder function that serves clients over TLS.
+    ///
+    /// Clients connect with a [TlsTransport](crate::tls::TlsTransport), e.g. through
+    /// [DeviceHandle::new_tls](crate::DeviceHandle::new_tls).
+    #[cfg(feature = "tls")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "tls")))]
+    pub fn with_tls(mut self, config: &TlsConfig) -> Result<Self> {
+        self.tls = Some(Arc::new(config.server_config()?));
+        Ok(self)
+    }
+
+    /// Gets the local address the bridge is listening on.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accepts clients, and forwards bytes between the client and serial port until `stop` is set.
+    ///
+    /// Only one client is served at a time, further clients wait until the current client
+    /// disconnects.
+    pub fn serve(&mut self, stop: &AtomicBool) -> Result<()> {
+        while !stop.load(Ordering::Relaxed) {
+            match self.listener.accept() {
+                Ok((stream, peer)) => {
+                    log::info!("Accepted bridge client: {peer}");
+
+                    if let Err(err) = self.accept_client(stream, stop) {
+                        log::warn!("Bridge client {peer} disconnected with error: {err}");
+                    } else {
+                        log::info!("Bridge client {peer} disconnected");
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(time::Duration::from_millis(250));
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn accept_client(&mut self, stream: TcpStream, stop: &AtomicBool) -> Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(time::Duration::from_millis(BRIDGE_POLL_MS)))?;
+
+        #[cfg(feature = "tls")]
+        let res = match self.tls.as_ref() {
+            Some(config) => {
+                let conn =
+                    rustls::ServerConnection::new(Arc::clone(config)).map_err(tls::tls_error)?;
+                let mut tls_stream = rustls::StreamOwned::new(conn, stream.try_clone()?);
+
+                let res = self.forward(&mut tls_stream, stop);
+                tls_stream.conn.send_close_notify();
+                let _ = tls_stream.flush();
+
+                res
+            }
+            None => self.forward(&mut stream.try_clone()?, stop),
+        };
+        #[cfg(not(feature = "tls"))]
+        let res = self.forward(&mut stream.try_clone()?, stop);
+
+        let _ = stream.shutdown(Shutdown::Both);
+
+        res
+    }
+
+    fn forward<S: Read + Write>(&mut self, stream: &mut S, stop: &AtomicBool) -> Result<()> {
+        // discard anything left over from a previous client
+        SerialPort::clear(&self.serial_port, serialport::ClearBuffer::All)?;
+
+        let mut buf = [0u8; ssp::len::MAX_MESSAGE];
+
+        while !stop.load(Ordering::Relaxed) {
+            match stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    log::trace!("Bridge TX: {:x?}", &buf[..n]);
+                    self.serial_port.write_all(&buf[..n])?;
+                }
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) => {}
+                Err(err) => return Err(err.into()),
+            }
+
+            match self.serial_port.read(&mut buf) {
+                Ok(n) if n > 0 => {
+                    log::trace!("Bridge RX: {:x?}", &buf[..n]);
+                    stream.write_all(&buf[..n])?;
+                }
+                Ok(_) => (),
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => (),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+//! Bounded fan-out of events to subscribers.
+//!
+//! Every [Subscriber] has its own bounded queue, so a slow client never holds back the others.
+//! When a queue is full, the [OverflowPolicy] decides whether the oldest, or the newest event is
+//! dropped, or the sender blocks until the subscriber catches up.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Weak};
+use std::time;
+
+use parking_lot::{Condvar, Mutex};
+
+use ssp::Result;
+
+/// Default number of events queued per subscriber.
+pub const DEFAULT_BROADCAST_CAPACITY: usize = 1024;
+/// Environment variable setting the [OverflowPolicy] of the event broadcast.
+pub const OVERFLOW_POLICY_ENV: &str = "SSP_EVENT_OVERFLOW";
+
+/// What to do with an event sent to a subscriber with a full queue.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum OverflowPolicy {
+    /// Drops the oldest queued event to make room for the new one.
+    #[default]
+    DropOldest,
+    /// Drops the new event, keeping the queued events.
+    DropNewest,
+    /// Blocks the sender until the subscriber makes room, or unsubscribes.
+    Block,
+}
+
+impl OverflowPolicy {
+    /// Gets the [OverflowPolicy] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::DropOldest => "drop-oldest",
+            Self::DropNewest => "drop-newest",
+            Self::Block => "block",
+        }
+    }
+
+    /// Parses an [OverflowPolicy] from its name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().replace('_', "-").as_str() {
+            "drop-oldest" => Some(Self::DropOldest),
+            "drop-newest" => Some(Self::DropNewest),
+            "block" => Some(Self::Block),
+            _ => None,
+        }
+    }
+
+    /// Gets the [OverflowPolicy] from the [OVERFLOW_POLICY_ENV] environment variable.
+    ///
+    /// Returns the default policy if the variable is unset.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(OVERFLOW_POLICY_ENV) {
+            Ok(name) => Self::from_name(name.as_str()).ok_or(ssp::Error::Enum(format!(
+                "unknown event overflow policy: {name}"
+            ))),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug)]
+struct Queue<T> {
+    events: VecDeque<T>,
+    dropped: u64,
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    queue: Mutex<Queue<T>>,
+    // signaled when an event is queued
+    ready: Condvar,
+    // signaled when an event is received, or the subscriber is dropped
+    space: Condvar,
+}
+
+/// Sends events to every [Subscriber], each with its own bounded queue.
+#[derive(Debug)]
+pub struct Broadcast<T: Clone> {
+    capacity: usize,
+    policy: OverflowPolicy,
+    slots: Mutex<Vec<Weak<Slot<T>>>>,
+}
+
+impl<T: Clone> Broadcast<T> {
+    /// Creates a new [Broadcast] queueing up to `capacity` events per subscriber.
+    ///
+    /// A zero `capacity` is raised to one.
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            policy,
+            slots: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Gets the number of events queued per subscriber.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Gets the [OverflowPolicy] applied to full subscriber queues.
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// Adds a [Subscriber] receiving every event sent after this call.
+    pub fn subscribe(&self) -> Subscriber<T> {
+        let slot = Arc::new(Slot {
+            queue: Mutex::new(Queue {
+                events: VecDeque::with_capacity(self.capacity),
+                dropped: 0,
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            space: Condvar::new(),
+        });
+
+        self.slots.lock().push(Arc::downgrade(&slot));
+
+        Subscriber { slot }
+    }
+
+    /// Gets the number of live subscribers.
+    pub fn subscribers(&self) -> usize {
+        let mut slots = self.slots.lock();
+        slots.retain(|slot| slot.strong_count() > 0);
+        slots.len()
+    }
+
+    /// Sends an event to every subscriber.
+    ///
+    /// Returns the number of subscribers the event was queued for. With the
+    /// [Block](OverflowPolicy::Block) policy, waits for full subscribers to make room.
+    pub fn send(&self, event: T) -> usize {
+        let slots: Vec<Arc<Slot<T>>> = {
+            let mut slots = self.slots.lock();
+            slots.retain(|slot| slot.strong_count() > 0);
+            slots.iter().filter_map(Weak::upgrade).collect()
+        };
+
+        slots
+            .iter()
+            .filter(|slot| self.push(slot, event.clone()))
+            .count()
+    }
+
+    // Queues an event for one subscriber, applying the overflow policy.
+    fn push(&self, slot: &Slot<T>, event: T) -> bool {
+        let mut queue = slot.queue.lock();
+
+        if queue.events.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    queue.events.pop_front();
+                    queue.dropped = queue.dropped.saturating_add(1);
+                }
+                OverflowPolicy::DropNewest => {
+                    queue.dropped = queue.dropped.saturating_add(1);
+                    return false;
+                }
+                OverflowPolicy::Block => {
+                    while queue.events.len() >= self.capacity && !queue.closed {
+                        slot.space.wait(&mut queue);
+                    }
+                }
+            }
+        }
+
+        if queue.closed {
+            return false;
+        }
+
+        queue.events.push_back(event);
+        slot.ready.notify_one();
+
+        true
+    }
+}
+
+impl<T: Clone> Default for Broadcast<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_BROADCAST_CAPACITY, OverflowPolicy::default())
+    }
+}
+
+/// Receives events sent by a [Broadcast].
+///
+/// Dropping the subscriber unsubscribes it, and releases any sender blocked on its queue.
+#[derive(Debug)]
+pub struct Subscriber<T> {
+    slot: Arc<Slot<T>>,
+}
+
+impl<T> Subscriber<T> {
+    /// Receives the oldest queued event, if any.
+    pub fn try_recv(&self) -> Option<T> {
+        let event = self.slot.queue.lock().events.pop_front();
+        if event.is_some() {
+            self.slot.space.notify_all();
+        }
+        event
+    }
+
+    /// Receives the oldest queued event, waiting up to `timeout` for one to be sent.
+    pub fn recv_timeout(&self, timeout: time::Duration) -> Option<T> {
+        let mut queue = self.slot.queue.lock();
+
+        if queue.events.is_empty() {
+            self.slot.ready.wait_for(&mut queue, timeout);
+        }
+
+        let event = queue.events.pop_front();
+        if event.is_some() {
+            self.slot.space.notify_all();
+        }
+        event
+    }
+
+    /// Gets the number of queued events.
+    pub fn len(&self) -> usize {
+        self.slot.queue.lock().events.len()
+    }
+
+    /// Gets whether no events are queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the number of events dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.slot.queue.lock().dropped
+    }
+}
+
+impl<T> Drop for Subscriber<T> {
+    fn drop(&mut self) {
+        self.slot.queue.lock().closed = true;
+        self.slot.space.notify_all();
+    }
+}
@@ -0,0 +1,629 @@
+//! Wire-level capture of the bytes exchanged with a device.
+//!
+//! A [CaptureTransport] wraps any [Transport], and records every transmitted (TX) and received (RX)
+//! chunk with a timestamp to a [CaptureFile]. Captures are plain text, one chunk per line, so they
+//! can be attached to support tickets, e.g. when working with ITL support on protocol issues:
+//!
+//! ```text
+//! # ssp-capture v1 1700000000000
+//! 0       tx      7f8001116582
+//! 2345    rx      7f8001f02380
+//! ```
+//!
+//! [read_capture] loads a capture, and [frames] reassembles the chunks into SSP frames for
+//! pretty-printing, e.g. with `ssp-cli capture <FILE>`.
+//!
+//! A [ReplayTransport] plays a capture back through the [Transport] abstraction, so a session
+//! observed in the field can be reproduced in a test without the device:
+//!
+//! ```no_run
+//! # fn main() -> ssp::Result<()> {
+//! use ssp_server::{capture::ReplayTransport, DeviceHandle};
+//!
+//! let handle = DeviceHandle::from_transport(ReplayTransport::open("field.capture")?)?;
+//! handle.sync()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::framing::FrameDecoder;
+use crate::redact::FrameRedactor;
+use crate::transport::Transport;
+
+/// Environment variable with the path of the capture file.
+pub const CAPTURE_ENV_PATH: &str = "SSP_CAPTURE";
+
+/// First line of every capture file, followed by the start time in milliseconds since the Unix
+/// epoch.
+pub const CAPTURE_HEADER: &str = "# ssp-capture v1";
+
+/// Direction of a captured chunk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CaptureDirection {
+    /// Bytes written to the device.
+    Tx,
+    /// Bytes read from the device.
+    Rx,
+    /// Transport buffers discarded, carries no bytes.
+    Clear,
+}
+
+impl CaptureDirection {
+    /// Gets the [CaptureDirection] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tx => "tx",
+            Self::Rx => "rx",
+            Self::Clear => "clear",
+        }
+    }
+
+    /// Parses a [CaptureDirection] from its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tx" => Some(Self::Tx),
+            "rx" => Some(Self::Rx),
+            "clear" => Some(Self::Clear),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CaptureDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str().to_uppercase())
+    }
+}
+
+/// Single chunk in a capture.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CaptureRecord {
+    /// Time since the start of the capture (microseconds).
+    pub elapsed_us: u64,
+    /// Direction of the chunk.
+    pub direction: CaptureDirection,
+    /// Bytes of the chunk, as sent on the wire.
+    pub data: Vec<u8>,
+}
+
+impl CaptureRecord {
+    /// Creates a new [CaptureRecord].
+    pub fn new(elapsed_us: u64, direction: CaptureDirection, data: &[u8]) -> Self {
+        Self {
+            elapsed_us,
+            direction,
+            data: data.into(),
+        }
+    }
+
+    fn to_line(&self) -> String {
+        let hex: String = self.data.iter().map(|b| format!("{b:02x}")).collect();
+
+        format!("{}\t{}\t{hex}\n", self.elapsed_us, self.direction.as_str())
+    }
+
+    fn from_line(line: &str) -> Result<Self> {
+        let invalid = || ssp::Error::Io(format!("invalid capture record: {line}"));
+
+        // fields are separated by tabs, the hex field is empty for records without bytes
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let hex = match fields.as_slice() {
+            [_, _] => "",
+            [_, _, hex] if hex.len() % 2 == 0 => hex,
+            _ => return Err(invalid()),
+        };
+
+        let data = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<std::result::Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+
+        Ok(Self {
+            elapsed_us: fields[0].parse().map_err(|_| invalid())?,
+            direction: CaptureDirection::from_name(fields[1]).ok_or_else(invalid)?,
+            data,
+        })
+    }
+}
+
+/// Capture file, recording chunks as they are exchanged.
+///
+/// Every record is flushed when it is written, so a capture survives a crash of the process.
+/// Cloned captures share the same file, and start time.
+#[derive(Clone, Debug)]
+pub struct CaptureFile {
+    path: PathBuf,
+    file: Arc<Mutex<fs::File>>,
+    start: time::Instant,
+}
+
+impl CaptureFile {
+    /// Creates a new capture at `path`, replacing any existing file.
+    pub fn create(path: &str) -> Result<Self> {
+        let path = PathBuf::from(path);
+
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = fs::File::create(&path)?;
+
+        let start_ms = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        writeln!(file, "{CAPTURE_HEADER} {start_ms}")?;
+
+        log::info!("Capturing wire traffic to {}", path.display());
+
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+            start: time::Instant::now(),
+        })
+    }
+
+    /// Creates a new capture at the path set in the [CAPTURE_ENV_PATH] environment variable.
+    ///
+    /// Returns `Ok(None)` if the variable is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(CAPTURE_ENV_PATH) {
+            Ok(path) => Self::create(path.as_str()).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Gets the path of the capture file.
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap_or_default()
+    }
+
+    /// Appends a record of `data` sent in `direction`.
+    pub fn record(&self, direction: CaptureDirection, data: &[u8]) -> Result<CaptureRecord> {
+        let mut file = self.file.lock();
+
+        let record = CaptureRecord::new(self.start.elapsed().as_micros() as u64, direction, data);
+
+        file.write_all(record.to_line().as_bytes())?;
+        file.flush()?;
+
+        Ok(record)
+    }
+}
+
+/// Reads all records of the capture at `path`, oldest first.
+pub fn read_capture(path: &str) -> Result<Vec<CaptureRecord>> {
+    BufReader::new(fs::File::open(path)?)
+        .lines()
+        .filter(|line| !matches!(line, Ok(l) if l.is_empty() || l.starts_with('#')))
+        .map(|line| CaptureRecord::from_line(line?.as_str()))
+        .collect()
+}
+
+/// [Transport] recording all traffic of the wrapped transport to a [CaptureFile].
+///
+/// Frames of sensitive commands are recorded [redacted](crate::redact). Failing to write the
+/// capture is logged, and never fails the wrapped transport.
+pub struct CaptureTransport<T: Transport> {
+    inner: T,
+    capture: CaptureFile,
+    tx_redactor: FrameRedactor,
+    rx_redactor: FrameRedactor,
+}
+
+impl<T: Transport> CaptureTransport<T> {
+    /// Creates a new [CaptureTransport] recording the traffic of `inner` to `capture`.
+    pub fn new(inner: T, capture: CaptureFile) -> Self {
+        Self {
+            inner,
+            capture,
+            tx_redactor: FrameRedactor::new(),
+            rx_redactor: FrameRedactor::new(),
+        }
+    }
+
+    /// Gets a reference to the [CaptureFile].
+    pub fn capture(&self) -> &CaptureFile {
+        &self.capture
+    }
+
+    /// Gets a reference to the wrapped transport.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Stops capturing, and returns the wrapped transport.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record(&self, direction: CaptureDirection, data: &[u8]) {
+        if data.is_empty() && direction != CaptureDirection::Clear {
+            return;
+        }
+
+        if let Err(err) = self.capture.record(direction, data) {
+            log::warn!("Failed to capture {direction} data: {err}");
+        }
+    }
+}
+
+impl<T: Transport> Read for CaptureTransport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+
+        if n > 0 {
+            let data = self.rx_redactor.push(&buf[..n]);
+            self.record(CaptureDirection::Rx, &data);
+        }
+
+        Ok(n)
+    }
+}
+
+impl<T: Transport> Write for CaptureTransport<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+
+        if n > 0 {
+            // a new command ends any partial response
+            let partial = self.rx_redactor.flush();
+            self.record(CaptureDirection::Rx, &partial);
+
+            let data = self.tx_redactor.push(&buf[..n]);
+            self.record(CaptureDirection::Tx, &data);
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Transport> Transport for CaptureTransport<T> {
+    fn clear(&mut self) -> Result<()> {
+        let partial = self.rx_redactor.flush();
+        self.record(CaptureDirection::Rx, &partial);
+
+        self.record(CaptureDirection::Clear, &[]);
+        self.inner.clear()
+    }
+
+    fn set_read_timeout(&mut self, timeout: time::Duration) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        self.inner.discard_input()
+    }
+}
+
+/// SSP frame reassembled from a capture.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CapturedFrame {
+    /// Time the last byte of the frame was captured (microseconds).
+    pub elapsed_us: u64,
+    /// Direction of the frame.
+    pub direction: CaptureDirection,
+    /// Unstuffed frame, or the raw bytes if the frame is invalid.
+    pub frame: Vec<u8>,
+    /// Error decoding the frame, `None` for a valid frame.
+    pub error: Option<String>,
+}
+
+impl CapturedFrame {
+    /// Gets the sequence ID byte, if the frame is long enough.
+    pub fn sequence_id(&self) -> Option<u8> {
+        self.frame.get(ssp::message_index::SEQ_ID).copied()
+    }
+
+    /// Gets the data field of the frame.
+    pub fn data(&self) -> &[u8] {
+        let start = ssp::message_index::DATA.min(self.frame.len());
+        let end = self.frame.len().saturating_sub(2).max(start);
+
+        self.frame[start..end].as_ref()
+    }
+
+    /// Gets whether the frame carries an encrypted (eSSP) message.
+    pub fn is_encrypted(&self) -> bool {
+        self.data().first() == Some(&ssp::STEXN)
+    }
+}
+
+impl fmt::Display for CapturedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = self.elapsed_us as f64 / 1_000.0;
+
+        write!(f, "{ms:>12.3}ms {} ", self.direction)?;
+
+        if let Some(err) = self.error.as_ref() {
+            return write!(f, "invalid frame ({err}): {:02x?}", self.frame);
+        }
+
+        let seq = self.sequence_id().unwrap_or_default();
+        let data = self.data();
+
+        write!(f, "seq=0x{seq:02x} len={:<3} ", data.len())?;
+
+        match (self.direction, data.first()) {
+            (_, Some(&ssp::STEXN)) => write!(f, "{:<24}", "encrypted")?,
+            (CaptureDirection::Tx, Some(&cmd)) => {
+                write!(f, "{:<24}", format!("{}", ssp::MessageType::from(cmd)))?
+            }
+            (CaptureDirection::Rx, Some(&status)) => write!(
+                f,
+                "{:<24}",
+                format!("{}", ssp::ResponseStatus::from(status))
+            )?,
+            _ => write!(f, "{:<24}", "")?,
+        }
+
+        write!(f, " {:02x?}", self.frame)
+    }
+}
+
+/// Reassembles the captured chunks into SSP frames, in the order they completed.
+///
+/// TX and RX bytes are decoded independently. Bytes not forming a valid frame are returned as an
+/// invalid [CapturedFrame], and decoding continues with the next byte.
+pub fn frames(records: &[CaptureRecord]) -> Vec<CapturedFrame> {
+    let mut tx = FrameDecoder::new();
+    let mut rx = FrameDecoder::new();
+    let mut tx_raw = Vec::new();
+    let mut rx_raw = Vec::new();
+    let mut frames = Vec::new();
+
+    for record in records.iter() {
+        let (decoder, raw) = match record.direction {
+            CaptureDirection::Tx => (&mut tx, &mut tx_raw),
+            CaptureDirection::Rx => (&mut rx, &mut rx_raw),
+            CaptureDirection::Clear => {
+                rx.reset();
+                rx_raw.clear();
+                continue;
+            }
+        };
+
+        for &byte in record.data.iter() {
+            raw.push(byte);
+
+            match decoder.push(byte) {
+                Ok(true) => {
+                    frames.push(CapturedFrame {
+                        elapsed_us: record.elapsed_us,
+                        direction: record.direction,
+                        frame: decoder.frame().into(),
+                        error: None,
+                    });
+                    decoder.reset();
+                    raw.clear();
+                }
+                Ok(false) => (),
+                Err(err) => {
+                    frames.push(CapturedFrame {
+                        elapsed_us: record.elapsed_us,
+                        direction: record.direction,
+                        frame: std::mem::take(raw),
+                        error: Some(format!("{err}")),
+                    });
+                    decoder.reset();
+                }
+            }
+        }
+    }
+
+    frames
+}
+
+/// Pretty-prints the `records` of a capture, one frame per line.
+pub fn pretty_print(records: &[CaptureRecord]) -> String {
+    frames(records)
+        .iter()
+        .map(|frame| format!("{frame}\n"))
+        .collect()
+}
+
+/// [Transport] replaying a capture in place of the device.
+///
+/// Reads return the captured RX bytes, in the captured chunks, once all TX bytes captured before
+/// them were written. Reading ahead of the host times out, like a device waiting for a command.
+/// Past the end of the capture, writes are accepted, and reads time out, like a silent device.
+///
+/// In strict mode (the default), written bytes must match the captured TX bytes. Once the host
+/// diverges from the captured session, every read fails with the divergence, so the command
+/// being replayed fails. Otherwise, writes only consume the same number of captured bytes, and
+/// mismatched bytes are counted.
+///
+/// Encrypted sessions only replay if the host derives the same keys, e.g. with a
+/// [SeededEntropy](crate::entropy::SeededEntropy) source, and in non-strict mode, since the host
+/// fills the packing of encrypted commands with random bytes.
+pub struct ReplayTransport {
+    records: Vec<CaptureRecord>,
+    index: usize,
+    offset: usize,
+    strict: bool,
+    mismatches: usize,
+    diverged: Option<String>,
+}
+
+impl ReplayTransport {
+    /// Creates a new [ReplayTransport] replaying the `records` of a capture.
+    pub fn new(records: Vec<CaptureRecord>) -> Self {
+        let mut replay = Self {
+            records,
+            index: 0,
+            offset: 0,
+            strict: true,
+            mismatches: 0,
+            diverged: None,
+        };
+        replay.skip_empty();
+        replay
+    }
+
+    /// Creates a new [ReplayTransport] replaying the capture at `path`.
+    pub fn open(path: &str) -> Result<Self> {
+        read_capture(path).map(Self::new)
+    }
+
+    /// Builder function that sets whether written bytes must match the captured TX bytes.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Gets whether written bytes must match the captured TX bytes.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Gets the index of the next record to replay.
+    pub fn position(&self) -> usize {
+        self.index
+    }
+
+    /// Gets whether all records were replayed.
+    pub fn is_finished(&self) -> bool {
+        self.index >= self.records.len()
+    }
+
+    /// Gets the number of written bytes not matching the captured TX bytes.
+    pub fn mismatches(&self) -> usize {
+        self.mismatches
+    }
+
+    /// Gets where the host diverged from the captured session in strict mode, if it did.
+    pub fn diverged(&self) -> Option<&str> {
+        self.diverged.as_deref()
+    }
+
+    fn current(&self) -> Option<&CaptureRecord> {
+        self.records.get(self.index)
+    }
+
+    // Skips records without replayable bytes, i.e. clears, and exhausted chunks.
+    fn skip_empty(&mut self) {
+        while let Some(record) = self.current() {
+            if record.direction != CaptureDirection::Clear && self.offset < record.data.len() {
+                break;
+            }
+
+            self.index += 1;
+            self.offset = 0;
+        }
+    }
+
+    fn advance(&mut self, len: usize) {
+        self.offset += len;
+        self.skip_empty();
+    }
+
+    fn diverge(&mut self, reason: String) {
+        log::warn!("Replay diverged: {reason}");
+
+        if self.strict && self.diverged.is_none() {
+            self.diverged = Some(reason);
+        }
+    }
+}
+
+impl Read for ReplayTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(reason) = self.diverged.as_ref() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, reason.clone()));
+        }
+
+        let record = match self.current() {
+            Some(record) if record.direction == CaptureDirection::Rx => record,
+            _ => return Err(io::ErrorKind::TimedOut.into()),
+        };
+
+        let chunk = &record.data[self.offset..];
+        let len = chunk.len().min(buf.len());
+        buf[..len].copy_from_slice(&chunk[..len]);
+
+        self.advance(len);
+
+        Ok(len)
+    }
+}
+
+impl Write for ReplayTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+
+        while written < buf.len() {
+            let index = self.index;
+
+            let expected = match self.current() {
+                Some(record) if record.direction == CaptureDirection::Tx => {
+                    &record.data[self.offset..]
+                }
+                Some(record) => {
+                    let reason = format!(
+                        "record {index}: {} bytes written, the capture expects {}",
+                        buf.len() - written,
+                        record.direction
+                    );
+                    self.mismatches += buf.len() - written;
+                    self.diverge(reason);
+                    break;
+                }
+                // the capture ended, the device went silent
+                None => break,
+            };
+
+            let len = expected.len().min(buf.len() - written);
+            let actual = &buf[written..written + len];
+
+            let diff = expected[..len]
+                .iter()
+                .zip(actual.iter())
+                .filter(|(e, a)| e != a)
+                .count();
+
+            if diff > 0 {
+                let reason = format!(
+                    "record {index}: expected {:02x?}, written {actual:02x?}",
+                    &expected[..len]
+                );
+                self.mismatches += diff;
+                self.diverge(reason);
+            }
+
+            written += len;
+            self.advance(len);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn clear(&mut self) -> Result<()> {
+        // captured RX bytes were all read by the host, so there is nothing to discard
+        Ok(())
+    }
+}
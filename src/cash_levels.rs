@@ -0,0 +1,709 @@
+//! Persisted estimates of the cashbox, and recycler contents.
+//!
+//! The estimate is a model of the notes held by the device, updated from:
+//!
+//! - credited notes, stored in the recycler for recycled denominations, and in the cashbox
+//!   otherwise
+//! - notes cleared into the cashbox after a reset
+//! - completed payouts, taken from the recycler
+//! - empties, moving all recycler notes into the cashbox
+//! - note counts reported by the device, replacing the recycler estimate
+//!
+//! The model is saved after every update, and loaded on startup, so the estimate survives
+//! restarts. It is an estimate: notes moved while the host was not running are not accounted for,
+//! until the levels are reported again. Emptying the cashbox on collection is recorded with
+//! [collect_cashbox](CashLevels::collect_cashbox).
+//!
+//! The `ssp` library does not implement the `GetAllLevels` command yet, so recycler levels are
+//! not polled from the device. Counts obtained elsewhere are applied with
+//! [apply_levels](CashLevels::apply_levels).
+//!
+//! # Watermark alerts
+//!
+//! Low-float thresholds per recycled note value, and a cashbox-nearly-full threshold are checked
+//! after every update. A [CashAlert] is raised when a level crosses its threshold, and raised
+//! again only after the level recovered. Raised alerts are appended to an alert history next to
+//! the levels file, with the `alerts` extension, queued for [take_alerts](CashLevels::take_alerts),
+//! and published to [event sinks](crate::sink), so collections, and refills can be scheduled
+//! before the device runs out of notes, or space.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+/// Environment variable with the path of the cash levels file.
+pub const CASH_LEVELS_ENV_PATH: &str = "SSP_CASH_LEVELS";
+
+/// Environment variable with the comma-separated note values stored in the recycler, e.g.
+/// `500,1000`.
+pub const CASH_RECYCLED_ENV: &str = "SSP_CASH_RECYCLED";
+
+/// Environment variable with the comma-separated low-float thresholds, as `value:count` pairs,
+/// e.g. `500:20,1000:10`.
+pub const CASH_LOW_FLOAT_ENV: &str = "SSP_CASH_LOW_FLOAT";
+
+/// Environment variable with the number of cashbox notes raising a cashbox-nearly-full alert.
+pub const CASH_CASHBOX_FULL_ENV: &str = "SSP_CASH_CASHBOX_FULL";
+
+/// First line of every cash levels file, followed by the time of the last update in milliseconds
+/// since the Unix epoch.
+pub const CASH_LEVELS_HEADER: &str = "# ssp-cash-levels v1";
+
+/// First line of every cash alert history file.
+pub const CASH_ALERTS_HEADER: &str = "# ssp-cash-alerts v1";
+
+// Maximum number of raised alerts queued for [CashLevels::take_alerts].
+const MAX_PENDING_ALERTS: usize = 64;
+
+/// Location of notes held by the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CashLocation {
+    /// Notes stacked into the cashbox, only removed on collection.
+    Cashbox,
+    /// Notes stored in the recycler (payout module), available for payouts.
+    Recycler,
+}
+
+impl CashLocation {
+    /// Gets the [CashLocation] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Cashbox => "cashbox",
+            Self::Recycler => "recycler",
+        }
+    }
+
+    /// Parses a [CashLocation] from its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "cashbox" => Some(Self::Cashbox),
+            "recycler" => Some(Self::Recycler),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CashLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Number of notes of a single value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DenominationLevel {
+    /// Note value, in the lowest currency unit.
+    pub value: u32,
+    /// Number of notes.
+    pub count: u32,
+}
+
+impl DenominationLevel {
+    /// Creates a new [DenominationLevel].
+    pub const fn new(value: u32, count: u32) -> Self {
+        Self { value, count }
+    }
+
+    /// Gets the total amount of the notes.
+    pub const fn amount(&self) -> u64 {
+        self.value as u64 * self.count as u64
+    }
+}
+
+/// Estimated contents of the device.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CashEstimate {
+    /// Estimated cashbox contents, by increasing note value.
+    pub cashbox: Vec<DenominationLevel>,
+    /// Estimated recycler contents, by increasing note value.
+    pub recycler: Vec<DenominationLevel>,
+    /// Time of the last update, in milliseconds since the Unix epoch.
+    pub updated_ms: u64,
+}
+
+impl CashEstimate {
+    /// Gets the estimated amount in the cashbox.
+    pub fn cashbox_total(&self) -> u64 {
+        self.cashbox.iter().map(|l| l.amount()).sum()
+    }
+
+    /// Gets the estimated number of notes in the cashbox.
+    pub fn cashbox_notes(&self) -> u64 {
+        self.cashbox.iter().map(|l| u64::from(l.count)).sum()
+    }
+
+    /// Gets the estimated amount in the recycler.
+    pub fn recycler_total(&self) -> u64 {
+        self.recycler.iter().map(|l| l.amount()).sum()
+    }
+
+    /// Gets the estimated number of notes in the recycler.
+    pub fn recycler_notes(&self) -> u64 {
+        self.recycler.iter().map(|l| u64::from(l.count)).sum()
+    }
+
+    /// Gets the estimated amount held by the device.
+    pub fn total(&self) -> u64 {
+        self.cashbox_total() + self.recycler_total()
+    }
+}
+
+/// Kind of a [CashAlert].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CashAlertKind {
+    /// Recycler level of a note value fell below its low-float threshold.
+    FloatLow,
+    /// Number of notes in the cashbox reached the cashbox-nearly-full threshold.
+    CashboxNearFull,
+}
+
+impl CashAlertKind {
+    /// Gets the [CashAlertKind] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::FloatLow => "float_low",
+            Self::CashboxNearFull => "cashbox_near_full",
+        }
+    }
+
+    /// Parses a [CashAlertKind] from its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "float_low" => Some(Self::FloatLow),
+            "cashbox_near_full" => Some(Self::CashboxNearFull),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CashAlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Level crossing a threshold, see [watermark alerts](crate::cash_levels#watermark-alerts).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CashAlert {
+    /// Kind of the alert.
+    pub kind: CashAlertKind,
+    /// Note value of a [FloatLow](CashAlertKind::FloatLow) alert, zero for the cashbox.
+    pub value: u32,
+    /// Number of notes when the alert was raised.
+    pub count: u64,
+    /// Threshold crossed by the level.
+    pub threshold: u64,
+    /// Time the alert was raised, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+}
+
+impl CashAlert {
+    fn to_line(self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            self.timestamp_ms, self.kind, self.value, self.count, self.threshold
+        )
+    }
+
+    fn from_line(line: &str) -> Result<Self> {
+        let invalid = || ssp::Error::Io(format!("invalid cash alert: {line}"));
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [timestamp_ms, kind, value, count, threshold] = fields.as_slice() else {
+            return Err(invalid());
+        };
+
+        Ok(Self {
+            kind: CashAlertKind::from_name(kind).ok_or_else(invalid)?,
+            value: value.parse().map_err(|_| invalid())?,
+            count: count.parse().map_err(|_| invalid())?,
+            threshold: threshold.parse().map_err(|_| invalid())?,
+            timestamp_ms: timestamp_ms.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl fmt::Display for CashAlert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            CashAlertKind::FloatLow => write!(
+                f,
+                "{}: {} notes of {}, threshold: {}",
+                self.kind, self.count, self.value, self.threshold
+            ),
+            CashAlertKind::CashboxNearFull => write!(
+                f,
+                "{}: {} notes, threshold: {}",
+                self.kind, self.count, self.threshold
+            ),
+        }
+    }
+}
+
+struct CashState {
+    cashbox: BTreeMap<u32, u32>,
+    recycler: BTreeMap<u32, u32>,
+    recycled: BTreeSet<u32>,
+    updated_ms: u64,
+    low_float: BTreeMap<u32, u32>,
+    cashbox_full: Option<u64>,
+    active: BTreeMap<(CashAlertKind, u32), CashAlert>,
+    pending: VecDeque<CashAlert>,
+}
+
+impl CashState {
+    fn levels(&mut self, location: CashLocation) -> &mut BTreeMap<u32, u32> {
+        match location {
+            CashLocation::Cashbox => &mut self.cashbox,
+            CashLocation::Recycler => &mut self.recycler,
+        }
+    }
+
+    fn to_contents(&self) -> String {
+        let mut contents = format!("{CASH_LEVELS_HEADER} {}\n", self.updated_ms);
+
+        for (location, levels) in [
+            (CashLocation::Cashbox, &self.cashbox),
+            (CashLocation::Recycler, &self.recycler),
+        ] {
+            for (value, count) in levels.iter().filter(|(_, &count)| count != 0) {
+                contents.push_str(format!("{location}\t{value}\t{count}\n").as_str());
+            }
+        }
+
+        contents
+    }
+
+    // Updates the active alerts, and returns the newly raised alerts.
+    fn check_watermarks(&mut self, timestamp_ms: u64) -> Vec<CashAlert> {
+        let mut levels: Vec<(CashAlertKind, u32, u64, Option<u64>)> = self
+            .low_float
+            .iter()
+            .map(|(&value, &threshold)| {
+                let count = self.recycler.get(&value).copied().unwrap_or_default();
+                (
+                    CashAlertKind::FloatLow,
+                    value,
+                    count.into(),
+                    (count < threshold).then_some(threshold.into()),
+                )
+            })
+            .collect();
+
+        if let Some(threshold) = self.cashbox_full {
+            let notes: u64 = self.cashbox.values().map(|&c| u64::from(c)).sum();
+            levels.push((
+                CashAlertKind::CashboxNearFull,
+                0,
+                notes,
+                (notes >= threshold).then_some(threshold),
+            ));
+        }
+
+        let mut raised = Vec::new();
+
+        // drop alerts for removed thresholds
+        let keys: BTreeSet<(CashAlertKind, u32)> = levels
+            .iter()
+            .map(|&(kind, value, _, _)| (kind, value))
+            .collect();
+        self.active.retain(|key, _| keys.contains(key));
+
+        for (kind, value, count, crossed) in levels {
+            match crossed {
+                Some(threshold) if !self.active.contains_key(&(kind, value)) => {
+                    let alert = CashAlert {
+                        kind,
+                        value,
+                        count,
+                        threshold,
+                        timestamp_ms,
+                    };
+
+                    self.active.insert((kind, value), alert);
+                    raised.push(alert);
+                }
+                Some(_) => (),
+                None => {
+                    self.active.remove(&(kind, value));
+                }
+            }
+        }
+
+        raised
+    }
+}
+
+/// Persisted model of the cashbox, and recycler contents.
+///
+/// Cloned models share the same state, and file.
+#[derive(Clone)]
+pub struct CashLevels {
+    path: PathBuf,
+    state: Arc<Mutex<CashState>>,
+}
+
+impl CashLevels {
+    /// Opens the cash levels at `path`, starting with an empty device if the file does not exist.
+    pub fn open(path: &str) -> Result<Self> {
+        let path = PathBuf::from(path);
+
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut state = CashState {
+            cashbox: BTreeMap::new(),
+            recycler: BTreeMap::new(),
+            recycled: BTreeSet::new(),
+            updated_ms: 0,
+            low_float: BTreeMap::new(),
+            cashbox_full: None,
+            active: BTreeMap::new(),
+            pending: VecDeque::new(),
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                for line in contents.lines().filter(|l| !l.is_empty()) {
+                    if let Some(updated) = line.strip_prefix(CASH_LEVELS_HEADER) {
+                        state.updated_ms = updated.trim().parse().unwrap_or_default();
+                        continue;
+                    }
+
+                    let invalid = || ssp::Error::Io(format!("invalid cash level: {line}"));
+
+                    let fields: Vec<&str> = line.split('\t').collect();
+                    let [location, value, count] = fields.as_slice() else {
+                        return Err(invalid());
+                    };
+
+                    let location = CashLocation::from_name(location).ok_or_else(invalid)?;
+                    let value = value.parse::<u32>().map_err(|_| invalid())?;
+                    let count = count.parse::<u32>().map_err(|_| invalid())?;
+
+                    state.levels(location).insert(value, count);
+                }
+
+                log::debug!("Loaded cash levels from {}", path.display());
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("No cash levels at {}, starting empty", path.display());
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(Self {
+            path,
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    /// Opens the cash levels at the path set in the [CASH_LEVELS_ENV_PATH] environment variable.
+    ///
+    /// Recycled denominations are set from the [CASH_RECYCLED_ENV] environment variable.
+    ///
+    /// Returns `Ok(None)` if the path is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(path) = std::env::var(CASH_LEVELS_ENV_PATH) else {
+            return Ok(None);
+        };
+
+        let mut levels = Self::open(path.as_str())?;
+
+        if let Ok(recycled) = std::env::var(CASH_RECYCLED_ENV) {
+            for value in recycled.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+                let value = value.parse::<u32>().map_err(|_| {
+                    ssp::Error::Io(format!("invalid {CASH_RECYCLED_ENV} note value: {value}"))
+                })?;
+
+                levels = levels.with_recycled(value);
+            }
+        }
+
+        if let Ok(low_float) = std::env::var(CASH_LOW_FLOAT_ENV) {
+            for pair in low_float
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+            {
+                let invalid =
+                    || ssp::Error::Io(format!("invalid {CASH_LOW_FLOAT_ENV} threshold: {pair}"));
+
+                let (value, count) = pair.split_once(':').ok_or_else(invalid)?;
+                let value = value.trim().parse::<u32>().map_err(|_| invalid())?;
+                let count = count.trim().parse::<u32>().map_err(|_| invalid())?;
+
+                levels = levels.with_low_float(value, count);
+            }
+        }
+
+        if let Ok(full) = std::env::var(CASH_CASHBOX_FULL_ENV) {
+            let full = full.trim().parse::<u64>().map_err(|_| {
+                ssp::Error::Io(format!("invalid {CASH_CASHBOX_FULL_ENV} threshold: {full}"))
+            })?;
+
+            levels = levels.with_cashbox_full(full);
+        }
+
+        Ok(Some(levels))
+    }
+
+    /// Gets the path of the cash levels file.
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap_or_default()
+    }
+
+    /// Builder function that routes credited notes of `value` to the recycler.
+    ///
+    /// Routing is configured on the device, and is not persisted.
+    pub fn with_recycled(self, value: u32) -> Self {
+        self.state.lock().recycled.insert(value);
+        self
+    }
+
+    /// Gets the note values routed to the recycler.
+    pub fn recycled(&self) -> Vec<u32> {
+        self.state.lock().recycled.iter().copied().collect()
+    }
+
+    /// Builder function that raises a [FloatLow](CashAlertKind::FloatLow) alert when fewer than
+    /// `count` notes of `value` are left in the recycler.
+    ///
+    /// Thresholds are not persisted. Levels already below the threshold raise an alert with the
+    /// next [check_watermarks](Self::check_watermarks), or update.
+    pub fn with_low_float(self, value: u32, count: u32) -> Self {
+        self.state.lock().low_float.insert(value, count);
+        self
+    }
+
+    /// Builder function that raises a [CashboxNearFull](CashAlertKind::CashboxNearFull) alert
+    /// when the cashbox holds `count` notes, or more.
+    ///
+    /// Thresholds are not persisted. Levels already above the threshold raise an alert with the
+    /// next [check_watermarks](Self::check_watermarks), or update.
+    pub fn with_cashbox_full(self, count: u64) -> Self {
+        self.state.lock().cashbox_full = Some(count);
+        self
+    }
+
+    /// Gets the low-float thresholds, by increasing note value.
+    pub fn low_float(&self) -> Vec<DenominationLevel> {
+        self.state
+            .lock()
+            .low_float
+            .iter()
+            .map(|(&value, &count)| DenominationLevel::new(value, count))
+            .collect()
+    }
+
+    /// Gets the cashbox-nearly-full threshold, if set.
+    pub fn cashbox_full(&self) -> Option<u64> {
+        self.state.lock().cashbox_full
+    }
+
+    /// Gets the path of the alert history file.
+    pub fn alerts_path(&self) -> PathBuf {
+        self.path.with_extension("alerts")
+    }
+
+    /// Checks the levels against the thresholds, and returns the newly raised alerts.
+    ///
+    /// Levels are checked after every update, use this to check the loaded levels on startup.
+    pub fn check_watermarks(&self) -> Result<Vec<CashAlert>> {
+        let mut state = self.state.lock();
+        self.raise_alerts(&mut state)
+    }
+
+    /// Gets the alerts for levels still past their thresholds.
+    pub fn active_alerts(&self) -> Vec<CashAlert> {
+        self.state.lock().active.values().copied().collect()
+    }
+
+    /// Takes the raised alerts not taken yet, oldest first.
+    ///
+    /// At most the last 64 alerts are kept.
+    pub fn take_alerts(&self) -> Vec<CashAlert> {
+        self.state.lock().pending.drain(..).collect()
+    }
+
+    /// Reads the history of raised alerts, oldest first.
+    pub fn alerts(&self) -> Result<Vec<CashAlert>> {
+        match fs::read_to_string(self.alerts_path()) {
+            Ok(contents) => contents
+                .lines()
+                .filter(|l| !(l.is_empty() || l.starts_with('#')))
+                .map(CashAlert::from_line)
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Gets the estimated contents of the device.
+    pub fn estimate(&self) -> CashEstimate {
+        let state = self.state.lock();
+
+        let levels = |levels: &BTreeMap<u32, u32>| {
+            levels
+                .iter()
+                .filter(|(_, &count)| count != 0)
+                .map(|(&value, &count)| DenominationLevel::new(value, count))
+                .collect()
+        };
+
+        CashEstimate {
+            cashbox: levels(&state.cashbox),
+            recycler: levels(&state.recycler),
+            updated_ms: state.updated_ms,
+        }
+    }
+
+    /// Records a credited note of `value`, and returns where it was stored.
+    pub fn record_credit(&self, value: u32) -> Result<CashLocation> {
+        self.update(|state| {
+            let location = if state.recycled.contains(&value) {
+                CashLocation::Recycler
+            } else {
+                CashLocation::Cashbox
+            };
+
+            add(state.levels(location), value, 1);
+
+            location
+        })
+    }
+
+    /// Records a note of `value` cleared into the cashbox, e.g. after a reset.
+    pub fn record_cleared_into_cashbox(&self, value: u32) -> Result<()> {
+        self.update(|state| add(&mut state.cashbox, value, 1))
+    }
+
+    /// Records a payout of `count` notes of `value` from the recycler.
+    pub fn record_payout(&self, value: u32, count: u32) -> Result<()> {
+        self.update(|state| {
+            let level = state.recycler.entry(value).or_default();
+
+            if *level < count {
+                log::warn!(
+                    "Paid out {count} notes of {value}, estimated recycler level: {level}, resetting to zero"
+                );
+            }
+
+            *level = level.saturating_sub(count);
+        })
+    }
+
+    /// Records an empty, moving all recycler notes into the cashbox.
+    pub fn record_empty(&self) -> Result<()> {
+        self.update(|state| {
+            for (value, count) in std::mem::take(&mut state.recycler) {
+                add(&mut state.cashbox, value, count);
+            }
+        })
+    }
+
+    /// Records the collection of the cashbox contents, and returns the collected estimate.
+    pub fn collect_cashbox(&self) -> Result<Vec<DenominationLevel>> {
+        self.update(|state| {
+            std::mem::take(&mut state.cashbox)
+                .into_iter()
+                .filter(|&(_, count)| count != 0)
+                .map(|(value, count)| DenominationLevel::new(value, count))
+                .collect()
+        })
+    }
+
+    /// Replaces the recycler estimate with the `levels` reported by the device.
+    ///
+    /// Values missing from `levels` are assumed empty.
+    pub fn apply_levels(&self, levels: &[DenominationLevel]) -> Result<()> {
+        self.update(|state| {
+            state.recycler = levels.iter().map(|l| (l.value, l.count)).collect();
+        })
+    }
+
+    // Applies `f` to the state, and saves the result before returning.
+    //
+    // The file is replaced atomically, so a crash leaves either the old, or the new levels.
+    fn update<R>(&self, f: impl FnOnce(&mut CashState) -> R) -> Result<R> {
+        let mut state = self.state.lock();
+
+        let res = f(&mut state);
+
+        state.updated_ms = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        let tmp_path = self.path.with_extension("tmp");
+        {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(state.to_contents().as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+
+        self.raise_alerts(&mut state)?;
+
+        Ok(res)
+    }
+
+    // Checks the watermarks, and records the newly raised alerts.
+    fn raise_alerts(&self, state: &mut CashState) -> Result<Vec<CashAlert>> {
+        let now_ms = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        let raised = state.check_watermarks(now_ms);
+
+        if raised.is_empty() {
+            return Ok(raised);
+        }
+
+        let path = self.alerts_path();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+
+        if file.metadata()?.len() == 0 {
+            file.write_all(format!("{CASH_ALERTS_HEADER}\n").as_bytes())?;
+        }
+
+        for alert in raised.iter() {
+            log::warn!("Cash level alert: {alert}");
+
+            file.write_all(alert.to_line().as_bytes())?;
+
+            state.pending.push_back(*alert);
+            if state.pending.len() > MAX_PENDING_ALERTS {
+                state.pending.pop_front();
+            }
+        }
+
+        file.sync_all()?;
+
+        Ok(raised)
+    }
+}
+
+fn add(levels: &mut BTreeMap<u32, u32>, value: u32, count: u32) {
+    let level = levels.entry(value).or_default();
+    *level = level.saturating_add(count);
+}
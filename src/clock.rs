@@ -0,0 +1,110 @@
+//! Clocks for the timers of a [DeviceHandle](crate::DeviceHandle).
+//!
+//! The background poll schedule, the [Hold](ssp::MessageType::Hold) keep-alive sent on every poll
+//! tick while a note sits in escrow, and the session key age checked by the
+//! [KeyRotationPolicy](crate::key_rotation::KeyRotationPolicy) read the time from a [Clock]. By
+//! default, time comes from the system ([SystemClock]).
+//!
+//! Attach a [MockClock] with [with_clock](crate::DeviceHandle::with_clock) to test the timers
+//! deterministically: simulated time only moves when the test [advances](MockClock::advance) it,
+//! or when a timer sleeps on the clock, instead of with `thread::sleep`.
+
+use std::fmt;
+use std::sync::Arc;
+use std::{thread, time};
+
+use parking_lot::Mutex;
+
+/// Maximum real time waited for a deadline on a [MockClock] before checking the clock again
+/// (milliseconds).
+pub const MOCK_WAIT_SLICE_MS: u64 = 1;
+
+/// Source of the current time, and of sleeps, for timers.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Gets the current time.
+    fn now(&self) -> time::Instant;
+
+    /// Sleeps for the `duration`.
+    fn sleep(&self, duration: time::Duration);
+
+    /// Converts a `deadline` on this clock to a deadline on the system clock, used to wait for
+    /// the deadline while waking up for other work.
+    fn system_deadline(&self, deadline: time::Instant) -> time::Instant {
+        time::Instant::now() + deadline.saturating_duration_since(self.now())
+    }
+}
+
+/// [Clock] using the system time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl SystemClock {
+    /// Creates a new [SystemClock].
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> time::Instant {
+        time::Instant::now()
+    }
+
+    fn sleep(&self, duration: time::Duration) {
+        thread::sleep(duration);
+    }
+
+    fn system_deadline(&self, deadline: time::Instant) -> time::Instant {
+        deadline
+    }
+}
+
+/// Simulated [Clock] for deterministic tests.
+///
+/// Time starts at the creation of the clock, and only moves with [advance](Self::advance), or
+/// [sleep](Clock::sleep), which returns right away. Clones share the same time, so a test keeps
+/// a clone to drive the timers of a handle.
+#[derive(Clone, Debug)]
+pub struct MockClock(Arc<Mutex<time::Instant>>);
+
+impl MockClock {
+    /// Creates a new [MockClock], starting at the current system time.
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(time::Instant::now())))
+    }
+
+    /// Moves the simulated time forward by the `duration`.
+    pub fn advance(&self, duration: time::Duration) {
+        *self.0.lock() += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> time::Instant {
+        *self.0.lock()
+    }
+
+    fn sleep(&self, duration: time::Duration) {
+        self.advance(duration);
+        // let other threads observe the new time
+        thread::yield_now();
+    }
+
+    fn system_deadline(&self, deadline: time::Instant) -> time::Instant {
+        // simulated time does not pass while waiting, check the clock again shortly
+        let remaining = deadline.saturating_duration_since(self.now());
+
+        time::Instant::now() + remaining.min(time::Duration::from_millis(MOCK_WAIT_SLICE_MS))
+    }
+}
+
+/// Gets the default [Clock], using the system time.
+pub fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock::new())
+}
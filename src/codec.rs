@@ -0,0 +1,175 @@
+//! Wire formats for the network frontends.
+//!
+//! JSON is always available. The compact binary formats are enabled with the `cbor` and
+//! `msgpack` features, for bandwidth-constrained links.
+//!
+//! JSON messages are newline-delimited on stream sockets. Binary messages are framed with a
+//! 4-byte big-endian length prefix.
+
+use std::fmt;
+use std::io::{Read, Write};
+
+use serde::{de::DeserializeOwned, Serialize};
+use ssp::Result;
+
+/// Maximum length of a binary frame (bytes).
+pub const MAX_FRAME_LEN: usize = 64 * 1024;
+
+/// Encoding used for messages exchanged with a client.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WireFormat {
+    /// JSON encoding, the default for all frontends.
+    #[default]
+    Json,
+    /// CBOR encoding (RFC 8949).
+    #[cfg(feature = "cbor")]
+    Cbor,
+    /// MessagePack encoding.
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+impl WireFormat {
+    /// Gets the short name of the format, as used in the socket handshake.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            #[cfg(feature = "cbor")]
+            Self::Cbor => "cbor",
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => "msgpack",
+        }
+    }
+
+    /// Gets the format from its short name, e.g. `cbor`.
+    ///
+    /// Returns `None` for unknown names, or formats disabled at compile time.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            #[cfg(feature = "cbor")]
+            "cbor" => Some(Self::Cbor),
+            #[cfg(feature = "msgpack")]
+            "msgpack" | "messagepack" => Some(Self::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// Gets the MIME type of the format.
+    pub const fn mime(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            #[cfg(feature = "cbor")]
+            Self::Cbor => "application/cbor",
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => "application/msgpack",
+        }
+    }
+
+    /// Gets the format from a MIME type, ignoring any parameters.
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        match mime.split(';').next().unwrap_or("").trim() {
+            "application/json" => Some(Self::Json),
+            #[cfg(feature = "cbor")]
+            "application/cbor" => Some(Self::Cbor),
+            #[cfg(feature = "msgpack")]
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Self::MessagePack)
+            }
+            _ => None,
+        }
+    }
+
+    /// Negotiates a format from an HTTP `Accept` header, preferring the first supported entry.
+    ///
+    /// Falls back to JSON when no entry is supported.
+    pub fn from_accept(accept: &str) -> Self {
+        accept
+            .split(',')
+            .find_map(Self::from_mime)
+            .unwrap_or_default()
+    }
+
+    /// Gets whether the format is a binary format.
+    pub const fn is_binary(&self) -> bool {
+        !matches!(self, Self::Json)
+    }
+
+    /// Encodes a value into the format.
+    pub fn encode<T: Serialize>(&self, val: &T) -> Result<Vec<u8>> {
+        match self {
+            Self::Json => serde_json::to_vec(val).map_err(encode_error),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(val, &mut buf).map_err(encode_error)?;
+                Ok(buf)
+            }
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => rmp_serde::to_vec_named(val).map_err(encode_error),
+        }
+    }
+
+    /// Decodes a value from the format.
+    pub fn decode<T: DeserializeOwned>(&self, buf: &[u8]) -> Result<T> {
+        match self {
+            Self::Json => serde_json::from_slice(buf).map_err(decode_error),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => ciborium::from_reader(buf).map_err(decode_error),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => rmp_serde::from_slice(buf).map_err(decode_error),
+        }
+    }
+
+    /// Encodes a value, and writes it as a single frame.
+    pub fn write_frame<W: Write, T: Serialize>(&self, writer: &mut W, val: &T) -> Result<()> {
+        let buf = self.encode(val)?;
+
+        if self.is_binary() {
+            if buf.len() > MAX_FRAME_LEN {
+                return Err(ssp::Error::InvalidLength((buf.len(), MAX_FRAME_LEN)));
+            }
+            writer.write_all((buf.len() as u32).to_be_bytes().as_ref())?;
+            writer.write_all(buf.as_ref())?;
+        } else {
+            writer.write_all(buf.as_ref())?;
+            writer.write_all(b"\n")?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Reads a single length-prefixed binary frame, and decodes the value.
+    ///
+    /// JSON messages are newline-delimited, and read by the line-based socket handlers instead.
+    pub fn read_frame<R: Read, T: DeserializeOwned>(&self, reader: &mut R) -> Result<T> {
+        let mut len_buf = [0u8; 4];
+        reader.read_exact(len_buf.as_mut())?;
+
+        let len = u32::from_be_bytes(len_buf) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(ssp::Error::InvalidLength((len, MAX_FRAME_LEN)));
+        }
+
+        let mut buf = vec![0u8; len];
+        reader.read_exact(buf.as_mut())?;
+
+        self.decode(buf.as_ref())
+    }
+}
+
+impl fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+fn encode_error<E: fmt::Display>(err: E) -> ssp::Error {
+    ssp::Error::Io(format!("message encoding error: {err}"))
+}
+
+fn decode_error<E: fmt::Display>(err: E) -> ssp::Error {
+    ssp::Error::Io(format!("message decoding error: {err}"))
+}
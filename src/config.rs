@@ -0,0 +1,826 @@
+//! Server configuration loaded from a TOML file.
+//!
+//! An [SspServerConfig] collects the settings otherwise spread over builder functions, and
+//! environment variables: the serial port, response timeouts, the polling interval, the escrow
+//! policy, the default channel inhibits, the encryption settings, and the enabled frontends.
+//! Every setting is optional, missing settings keep their defaults:
+//!
+//! ```toml
+//! port = "/dev/ttyUSB0"
+//! baud_rate = 9600
+//!
+//! [timeouts]
+//! poll_ms = 500
+//! payout_ms = 10000
+//!
+//! [polling]
+//! interval_ms = 650
+//!
+//! [escrow]
+//! policy = "hold"
+//!
+//! [inhibits]
+//! enabled_channels = [1, 2, 3, 4]
+//!
+//! [encryption]
+//! require = true
+//! fixed_key = "0123456701234567"
+//! rotate_after_secs = 3600
+//!
+//! [frontends]
+//! jsonrpc = true
+//! http = "127.0.0.1:8080"
+//! ```
+//!
+//! Environment variables override the file, for containerized deployments configured without
+//! a mounted file. [from_env](SspServerConfig::from_env) loads the file at [CONFIG_ENV_PATH],
+//! if set, and applies the overrides:
+//!
+//! | Variable | Setting |
+//! |----------|---------|
+//! | `SSP_SERIAL_PATH` | `port` |
+//! | `SSP_BAUD_RATE` | `baud_rate` |
+//! | `SSP_<CLASS>_TIMEOUT_MS`, e.g. `SSP_PAYOUT_TIMEOUT_MS` | `timeouts.<class>_ms` |
+//! | `SSP_POLL_MS` | `polling.interval_ms` |
+//! | `SSP_JITTER_BUDGET_MS` | `polling.jitter_budget_ms` |
+//! | `SSP_ESCROW_POLICY` | `escrow.policy` |
+//! | `SSP_ENABLED_CHANNELS`, comma-separated, or `all` | `inhibits.enabled_channels` |
+//! | `SSP_REQUIRE_ENCRYPTION` | `encryption.require` |
+//! | `SSP_FIXED_KEY` | `encryption.fixed_key` |
+//! | `SSP_SECURE_SHUTDOWN` | `encryption.secure_shutdown` |
+//! | `SSP_ROTATE_AFTER_COMMANDS` | `encryption.rotate_after_commands` |
+//! | `SSP_ROTATE_AFTER_SECS` | `encryption.rotate_after_secs` |
+//! | `SSP_JSONRPC` | `frontends.jsonrpc` |
+//! | `SSP_STDIO` | `frontends.stdio` |
+//! | `SSP_HTTP_ADDR` | `frontends.http` |
+//! | `SSP_GRPC_ADDR` | `frontends.grpc` |
+//!
+//! Flags accept `1`, `true`, `yes`, and `on`, or `0`, `false`, `no`, and `off`.
+//!
+//! Open a configured [DeviceHandle](crate::DeviceHandle) with
+//! [from_config](crate::DeviceHandle::from_config):
+//!
+//! ```no_run
+//! # fn main() -> ssp::Result<()> {
+//! use ssp_server::config::SspServerConfig;
+//!
+//! let config = SspServerConfig::from_env()?;
+//! let _handle = ssp_server::DeviceHandle::from_config(&config)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A [ConfigWatcher] reloads the file when it changes, or on `SIGHUP`, and applies the runtime
+//! settings to the running handle, see [reload_config](crate::DeviceHandle::reload_config):
+//!
+//! ```no_run
+//! # fn main() -> ssp::Result<()> {
+//! use std::sync::{atomic::AtomicBool, Arc};
+//!
+//! use parking_lot::Mutex;
+//! use ssp_server::config::{ConfigWatcher, SspServerConfig};
+//!
+//! let path = "/etc/ssp/ssp-server.toml";
+//! let config = SspServerConfig::load(path)?.with_env()?;
+//! let handle = Arc::new(Mutex::new(ssp_server::DeviceHandle::from_config(&config)?));
+//! let stop = Arc::new(AtomicBool::new(false));
+//!
+//! let watcher = ConfigWatcher::new(path, config);
+//! watcher.register_sighup()?;
+//! watcher.spawn(handle, stop)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{fmt, fs, str::FromStr, thread, time};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use ssp::Result;
+
+use crate::device_handle::{BAUD_RATE, MAX_POLLING_MS, SECURE_SHUTDOWN_ENV};
+use crate::key_rotation::KeyRotationPolicy;
+use crate::key_store::{self, FIXED_KEY_ENV};
+use crate::poll_timer::DEFAULT_JITTER_BUDGET_MS;
+use crate::retry::CommandClass;
+use crate::{DeviceHandle, PollMode};
+
+/// Default serial device path.
+pub const DEFAULT_PORT: &str = "/dev/ttyUSB0";
+/// Highest channel number with a default inhibit.
+pub const MAX_INHIBIT_CHANNEL: u8 = 16;
+
+/// Interval between checks of a watched configuration file (milliseconds).
+pub const WATCH_INTERVAL_MS: u64 = 1_000;
+
+/// Environment variable with the path of the configuration file.
+pub const CONFIG_ENV_PATH: &str = "SSP_CONFIG";
+/// Environment variable overriding the serial device path.
+pub const SERIAL_PATH_ENV: &str = "SSP_SERIAL_PATH";
+/// Environment variable overriding the baud rate.
+pub const BAUD_RATE_ENV: &str = "SSP_BAUD_RATE";
+/// Environment variable overriding the polling interval (milliseconds).
+pub const POLL_MS_ENV: &str = "SSP_POLL_MS";
+/// Environment variable overriding the polling jitter budget (milliseconds).
+pub const JITTER_BUDGET_MS_ENV: &str = "SSP_JITTER_BUDGET_MS";
+/// Environment variable overriding the escrow policy.
+pub const ESCROW_POLICY_ENV: &str = "SSP_ESCROW_POLICY";
+/// Environment variable overriding the enabled channels.
+pub const ENABLED_CHANNELS_ENV: &str = "SSP_ENABLED_CHANNELS";
+/// Environment variable overriding whether encryption is required.
+pub const REQUIRE_ENCRYPTION_ENV: &str = "SSP_REQUIRE_ENCRYPTION";
+/// Environment variable overriding the number of commands before a session key rotation.
+pub const ROTATE_AFTER_COMMANDS_ENV: &str = "SSP_ROTATE_AFTER_COMMANDS";
+/// Environment variable overriding the session key age before a rotation (seconds).
+pub const ROTATE_AFTER_SECS_ENV: &str = "SSP_ROTATE_AFTER_SECS";
+/// Environment variable overriding whether the JSON-RPC socket frontend is enabled.
+pub const JSONRPC_ENV: &str = "SSP_JSONRPC";
+/// Environment variable overriding whether the JSON-RPC stdio frontend is enabled.
+pub const STDIO_ENV: &str = "SSP_STDIO";
+/// Environment variable overriding the HTTP frontend listen address.
+pub const HTTP_ADDR_ENV: &str = "SSP_HTTP_ADDR";
+/// Environment variable overriding the gRPC frontend listen address.
+pub const GRPC_ADDR_ENV: &str = "SSP_GRPC_ADDR";
+
+/// Configuration of an SSP server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SspServerConfig {
+    /// Serial device path, or `tcp://<host>:<port>` bridge address.
+    pub port: String,
+    /// Serial connection baud rate (bps).
+    pub baud_rate: u32,
+    /// Response timeouts per command class.
+    pub timeouts: TimeoutsConfig,
+    /// Background polling schedule.
+    pub polling: PollingConfig,
+    /// Handling of notes held in escrow.
+    pub escrow: EscrowConfig,
+    /// Channels enabled when the device is enabled.
+    pub inhibits: InhibitsConfig,
+    /// Encryption settings.
+    pub encryption: EncryptionConfig,
+    /// Enabled frontends.
+    pub frontends: FrontendsConfig,
+}
+
+impl SspServerConfig {
+    /// Creates a new [SspServerConfig] with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads, and validates an [SspServerConfig] from the TOML file at `path`.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| ssp::Error::Io(format!("failed to read configuration {path}: {err}")))?;
+
+        Self::from_toml(contents.as_str())
+            .map_err(|err| ssp::Error::Io(format!("invalid configuration {path}: {err}")))
+    }
+
+    /// Parses, and validates an [SspServerConfig] from TOML `contents`.
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let config: Self =
+            toml::from_str(contents).map_err(|err| ssp::Error::Io(err.to_string()))?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Loads the configuration file at the [CONFIG_ENV_PATH] environment variable, or the
+    /// defaults if unset, and applies the environment overrides, see
+    /// [with_env](Self::with_env).
+    pub fn from_env() -> Result<Self> {
+        let config = match std::env::var(CONFIG_ENV_PATH) {
+            Ok(path) => Self::load(path.as_str())?,
+            Err(_) => Self::new(),
+        };
+
+        config.with_env()
+    }
+
+    /// Builder function that applies the overrides set in the process environment.
+    pub fn with_env(self) -> Result<Self> {
+        self.with_env_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Builder function that applies the overrides returned by `var` for each variable name,
+    /// and validates the result.
+    ///
+    /// Unset variables, where `var` returns `None`, keep the current setting.
+    pub fn with_env_vars<F>(mut self, var: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(port) = var(SERIAL_PATH_ENV) {
+            self.port = port.trim().into();
+        }
+        if let Some(baud_rate) = var(BAUD_RATE_ENV) {
+            self.baud_rate = parse_env(BAUD_RATE_ENV, baud_rate.as_str())?;
+        }
+
+        for class in CommandClass::ALL {
+            let name = timeout_env(class);
+            if let Some(timeout) = var(name.as_str()) {
+                *self.timeouts.response_timeout_ms_mut(class) =
+                    Some(parse_env(name.as_str(), timeout.as_str())?);
+            }
+        }
+
+        if let Some(interval) = var(POLL_MS_ENV) {
+            self.polling.interval_ms = Some(parse_env(POLL_MS_ENV, interval.as_str())?);
+        }
+        if let Some(budget) = var(JITTER_BUDGET_MS_ENV) {
+            self.polling.jitter_budget_ms = parse_env(JITTER_BUDGET_MS_ENV, budget.as_str())?;
+        }
+
+        if let Some(policy) = var(ESCROW_POLICY_ENV) {
+            self.escrow.policy = parse_env(ESCROW_POLICY_ENV, policy.as_str())?;
+        }
+
+        if let Some(channels) = var(ENABLED_CHANNELS_ENV) {
+            self.inhibits.enabled_channels = match channels.trim() {
+                "all" => None,
+                channels => Some(
+                    channels
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|c| !c.is_empty())
+                        .map(|c| parse_env(ENABLED_CHANNELS_ENV, c))
+                        .collect::<Result<_>>()?,
+                ),
+            };
+        }
+
+        let encryption = &mut self.encryption;
+
+        if let Some(require) = var(REQUIRE_ENCRYPTION_ENV) {
+            encryption.require = parse_flag(REQUIRE_ENCRYPTION_ENV, require.as_str())?;
+        }
+        if let Some(fixed_key) = var(FIXED_KEY_ENV) {
+            encryption.fixed_key = Some(fixed_key.trim().into());
+        }
+        if let Some(secure) = var(SECURE_SHUTDOWN_ENV) {
+            encryption.secure_shutdown = parse_flag(SECURE_SHUTDOWN_ENV, secure.as_str())?;
+        }
+        if let Some(max) = var(ROTATE_AFTER_COMMANDS_ENV) {
+            encryption.rotate_after_commands =
+                Some(parse_env(ROTATE_AFTER_COMMANDS_ENV, max.as_str())?);
+        }
+        if let Some(secs) = var(ROTATE_AFTER_SECS_ENV) {
+            encryption.rotate_after_secs = Some(parse_env(ROTATE_AFTER_SECS_ENV, secs.as_str())?);
+        }
+
+        let frontends = &mut self.frontends;
+
+        if let Some(jsonrpc) = var(JSONRPC_ENV) {
+            frontends.jsonrpc = parse_flag(JSONRPC_ENV, jsonrpc.as_str())?;
+        }
+        if let Some(stdio) = var(STDIO_ENV) {
+            frontends.stdio = parse_flag(STDIO_ENV, stdio.as_str())?;
+        }
+        if let Some(addr) = var(HTTP_ADDR_ENV) {
+            frontends.http = Some(addr.trim().into());
+        }
+        if let Some(addr) = var(GRPC_ADDR_ENV) {
+            frontends.grpc = Some(addr.trim().into());
+        }
+
+        self.validate()?;
+
+        Ok(self)
+    }
+
+    /// Gets the sections changed in the `new` configuration.
+    pub fn changes(&self, new: &Self) -> Vec<ConfigChange> {
+        let (old_key, new_key) = (&self.encryption, &new.encryption);
+
+        [
+            (self.port != new.port, ConfigChange::Port),
+            (self.baud_rate != new.baud_rate, ConfigChange::BaudRate),
+            (self.timeouts != new.timeouts, ConfigChange::Timeouts),
+            (self.polling != new.polling, ConfigChange::Polling),
+            (self.escrow != new.escrow, ConfigChange::Escrow),
+            (self.inhibits != new.inhibits, ConfigChange::Inhibits),
+            (
+                old_key.require != new_key.require
+                    || old_key.fixed_key != new_key.fixed_key
+                    || old_key.secure_shutdown != new_key.secure_shutdown,
+                ConfigChange::Encryption,
+            ),
+            (
+                old_key.rotate_after_commands != new_key.rotate_after_commands
+                    || old_key.rotate_after_secs != new_key.rotate_after_secs,
+                ConfigChange::KeyRotation,
+            ),
+            (self.frontends != new.frontends, ConfigChange::Frontends),
+        ]
+        .into_iter()
+        .filter_map(|(changed, change)| changed.then_some(change))
+        .collect()
+    }
+
+    /// Serializes the [SspServerConfig] to TOML.
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|err| ssp::Error::Io(err.to_string()))
+    }
+
+    /// Returns `Err(_)` describing the first invalid setting, if any.
+    pub fn validate(&self) -> Result<()> {
+        if self.port.is_empty() {
+            return Err(ssp::Error::Io("port must not be empty".into()));
+        }
+
+        if self.baud_rate == 0 {
+            return Err(ssp::Error::Io("baud_rate must not be zero".into()));
+        }
+
+        for class in CommandClass::ALL {
+            if self.timeouts.response_timeout(class) == Some(time::Duration::ZERO) {
+                return Err(ssp::Error::Io(format!(
+                    "{class} response timeout must not be zero"
+                )));
+            }
+        }
+
+        if let Some(interval_ms) = self.polling.interval_ms {
+            if interval_ms == 0 || interval_ms > MAX_POLLING_MS {
+                return Err(ssp::Error::Io(format!(
+                    "polling interval_ms must be between 1 and {MAX_POLLING_MS}"
+                )));
+            }
+        }
+
+        self.inhibits.bitfields()?;
+        self.encryption.fixed_key()?;
+
+        Ok(())
+    }
+}
+
+impl Default for SspServerConfig {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT.into(),
+            baud_rate: BAUD_RATE,
+            timeouts: TimeoutsConfig::default(),
+            polling: PollingConfig::default(),
+            escrow: EscrowConfig::default(),
+            inhibits: InhibitsConfig::default(),
+            encryption: EncryptionConfig::default(),
+            frontends: FrontendsConfig::default(),
+        }
+    }
+}
+
+/// Section of an [SspServerConfig] changed by a reload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigChange {
+    /// The serial device path.
+    Port,
+    /// The baud rate.
+    BaudRate,
+    /// The response timeouts.
+    Timeouts,
+    /// The poll interval, or jitter budget.
+    Polling,
+    /// The escrow policy.
+    Escrow,
+    /// The enabled channels.
+    Inhibits,
+    /// The encryption requirement, fixed key, or secure shutdown.
+    Encryption,
+    /// The session key rotation thresholds.
+    KeyRotation,
+    /// The enabled frontends.
+    Frontends,
+}
+
+impl ConfigChange {
+    /// Gets the [ConfigChange] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Port => "port",
+            Self::BaudRate => "baud_rate",
+            Self::Timeouts => "timeouts",
+            Self::Polling => "polling",
+            Self::Escrow => "escrow",
+            Self::Inhibits => "inhibits",
+            Self::Encryption => "encryption",
+            Self::KeyRotation => "key_rotation",
+            Self::Frontends => "frontends",
+        }
+    }
+
+    /// Gets whether the change only applies after a restart.
+    pub const fn requires_restart(&self) -> bool {
+        matches!(
+            self,
+            Self::Port | Self::BaudRate | Self::Encryption | Self::Frontends
+        )
+    }
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Response timeouts per [CommandClass] (milliseconds) of the configured handle, unset classes
+/// keep the process-wide [timeouts](crate::timeouts).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutsConfig {
+    /// Response timeout for poll commands.
+    pub poll_ms: Option<u64>,
+    /// Response timeout for query commands.
+    pub query_ms: Option<u64>,
+    /// Response timeout for control commands.
+    pub control_ms: Option<u64>,
+    /// Response timeout for payout commands.
+    pub payout_ms: Option<u64>,
+    /// Response timeout for encryption commands.
+    pub encryption_ms: Option<u64>,
+    /// Response timeout for firmware commands.
+    pub firmware_ms: Option<u64>,
+}
+
+impl TimeoutsConfig {
+    /// Gets the configured response timeout of a command `class`, if set.
+    pub fn response_timeout(&self, class: CommandClass) -> Option<time::Duration> {
+        let ms = match class {
+            CommandClass::Poll => self.poll_ms,
+            CommandClass::Query => self.query_ms,
+            CommandClass::Control => self.control_ms,
+            CommandClass::Payout => self.payout_ms,
+            CommandClass::Encryption => self.encryption_ms,
+            CommandClass::Firmware => self.firmware_ms,
+        };
+
+        ms.map(time::Duration::from_millis)
+    }
+
+    fn response_timeout_ms_mut(&mut self, class: CommandClass) -> &mut Option<u64> {
+        match class {
+            CommandClass::Poll => &mut self.poll_ms,
+            CommandClass::Query => &mut self.query_ms,
+            CommandClass::Control => &mut self.control_ms,
+            CommandClass::Payout => &mut self.payout_ms,
+            CommandClass::Encryption => &mut self.encryption_ms,
+            CommandClass::Firmware => &mut self.firmware_ms,
+        }
+    }
+}
+
+/// Background polling schedule.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PollingConfig {
+    /// Interval between polls (milliseconds), at most [MAX_POLLING_MS], the default of the
+    /// polling routine if unset.
+    pub interval_ms: Option<u64>,
+    /// Maximum delay of a poll after its scheduled tick (milliseconds).
+    pub jitter_budget_ms: u64,
+}
+
+impl PollingConfig {
+    /// Gets the interval between polls, if set.
+    pub fn interval(&self) -> Option<time::Duration> {
+        self.interval_ms.map(time::Duration::from_millis)
+    }
+
+    /// Gets the maximum delay of a poll after its scheduled tick.
+    pub const fn jitter_budget(&self) -> time::Duration {
+        time::Duration::from_millis(self.jitter_budget_ms)
+    }
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: None,
+            jitter_budget_ms: DEFAULT_JITTER_BUDGET_MS,
+        }
+    }
+}
+
+/// Handling of a note read into escrow.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EscrowPolicy {
+    /// Notes are stacked, and credited right away.
+    #[default]
+    Auto,
+    /// Notes are held in escrow until a frontend stacks, or rejects them.
+    Hold,
+}
+
+impl EscrowPolicy {
+    /// Gets the [EscrowPolicy] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Hold => "hold",
+        }
+    }
+
+    /// Gets the [PollMode] of the background polling routine implementing the policy.
+    pub const fn poll_mode(&self) -> PollMode {
+        match self {
+            Self::Auto => PollMode::Auto,
+            Self::Hold => PollMode::Interactive,
+        }
+    }
+}
+
+impl FromStr for EscrowPolicy {
+    type Err = ssp::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "hold" => Ok(Self::Hold),
+            _ => Err(ssp::Error::Io(format!("unknown escrow policy: {s}"))),
+        }
+    }
+}
+
+impl fmt::Display for EscrowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Handling of notes held in escrow.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EscrowConfig {
+    /// Escrow policy of the background polling routine.
+    pub policy: EscrowPolicy,
+}
+
+/// Channels enabled when the device is enabled.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InhibitsConfig {
+    /// Enabled channels, counting from one, all channels if unset.
+    pub enabled_channels: Option<Vec<u8>>,
+}
+
+impl InhibitsConfig {
+    /// Gets the inhibit bitfields of the enabled channels, a set bit enables the channel.
+    ///
+    /// Returns `Err(_)` if a channel is outside of `1..=`[MAX_INHIBIT_CHANNEL].
+    pub fn bitfields(&self) -> Result<[u8; 2]> {
+        let Some(channels) = self.enabled_channels.as_ref() else {
+            return Ok([0xff, 0xff]);
+        };
+
+        let mut bitfields = [0u8; 2];
+
+        for &channel in channels {
+            if !(1..=MAX_INHIBIT_CHANNEL).contains(&channel) {
+                return Err(ssp::Error::Io(format!(
+                    "enabled channel {channel} is outside of 1..={MAX_INHIBIT_CHANNEL}"
+                )));
+            }
+
+            let idx = usize::from(channel - 1);
+            bitfields[idx / 8] |= 1 << (idx % 8);
+        }
+
+        Ok(bitfields)
+    }
+}
+
+/// Encryption settings.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// Whether value-relevant commands are refused without an encryption key.
+    pub require: bool,
+    /// Site-specific eSSP fixed key, 16 hex digits, the ITL default key if unset.
+    pub fixed_key: Option<String>,
+    /// Whether the secure shutdown path is enabled.
+    pub secure_shutdown: bool,
+    /// Number of encrypted commands after which the session key is renegotiated.
+    pub rotate_after_commands: Option<u32>,
+    /// Age after which the session key is renegotiated (seconds).
+    pub rotate_after_secs: Option<u64>,
+}
+
+impl EncryptionConfig {
+    /// Gets the parsed [FixedKey](ssp::FixedKey), if set.
+    pub fn fixed_key(&self) -> Result<Option<ssp::FixedKey>> {
+        self.fixed_key
+            .as_deref()
+            .map(key_store::parse_fixed_key)
+            .transpose()
+    }
+
+    /// Gets the [KeyRotationPolicy] of the session key.
+    pub fn key_rotation(&self) -> KeyRotationPolicy {
+        let policy = KeyRotationPolicy::new();
+
+        let policy = match self.rotate_after_commands {
+            Some(max) => policy.with_max_commands(max),
+            None => policy,
+        };
+
+        match self.rotate_after_secs {
+            Some(secs) => policy.with_max_age(time::Duration::from_secs(secs)),
+            None => policy,
+        }
+    }
+}
+
+/// Enabled frontends.
+///
+/// The frontends are started by the server binaries, which only serve the frontends compiled
+/// in with their features.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrontendsConfig {
+    /// Whether the JSON-RPC frontend on a Unix socket is enabled.
+    pub jsonrpc: bool,
+    /// Whether the JSON-RPC frontend on stdin, and stdout is enabled.
+    pub stdio: bool,
+    /// Listen address of the HTTP frontend, disabled if unset.
+    pub http: Option<String>,
+    /// Listen address of the gRPC frontend, disabled if unset.
+    pub grpc: Option<String>,
+}
+
+impl FrontendsConfig {
+    /// Gets the names of the enabled frontends.
+    pub fn enabled(&self) -> Vec<&'static str> {
+        [
+            (self.jsonrpc, "jsonrpc"),
+            (self.stdio, "stdio"),
+            (self.http.is_some(), "http"),
+            (self.grpc.is_some(), "grpc"),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, name)| enabled.then_some(name))
+        .collect()
+    }
+}
+
+impl Default for FrontendsConfig {
+    fn default() -> Self {
+        Self {
+            jsonrpc: true,
+            stdio: false,
+            http: None,
+            grpc: None,
+        }
+    }
+}
+
+/// Watches a configuration file, and reloads the runtime settings into a [DeviceHandle].
+///
+/// The file is reloaded when its modification time changes, or on request, e.g. on `SIGHUP`
+/// with [register_sighup](Self::register_sighup), or from a frontend with
+/// [request_reload](Self::request_reload). Environment overrides are applied on top of the
+/// reloaded file. An invalid file is logged, and the current settings are kept.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    config: SspServerConfig,
+    modified: Option<time::SystemTime>,
+    reload: Arc<AtomicBool>,
+}
+
+impl ConfigWatcher {
+    /// Creates a new [ConfigWatcher] for the file at `path`, with the `config` currently applied.
+    pub fn new<P: AsRef<Path>>(path: P, config: SspServerConfig) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified(&path);
+
+        Self {
+            path,
+            config,
+            modified,
+            reload: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Gets the path of the watched file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Gets the currently applied [SspServerConfig].
+    pub fn config(&self) -> &SspServerConfig {
+        &self.config
+    }
+
+    /// Gets the flag requesting a reload, set it to reload the file on the next check.
+    pub fn reload_flag(&self) -> &Arc<AtomicBool> {
+        &self.reload
+    }
+
+    /// Requests a reload on the next check.
+    pub fn request_reload(&self) {
+        self.reload.store(true, Ordering::SeqCst);
+    }
+
+    /// Requests a reload when the process receives `SIGHUP`.
+    pub fn register_sighup(&self) -> Result<()> {
+        signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&self.reload))?;
+        Ok(())
+    }
+
+    /// Gets whether a reload was requested, or the file changed since the last reload.
+    pub fn due(&self) -> bool {
+        self.reload.load(Ordering::Relaxed) || modified(&self.path) != self.modified
+    }
+
+    /// Reloads the file, and applies the changed runtime settings to the `handle`.
+    ///
+    /// Returns the changed sections. Changes requiring a restart are logged, and remembered,
+    /// but not applied.
+    pub fn reload(&mut self, handle: &mut DeviceHandle) -> Result<Vec<ConfigChange>> {
+        self.reload.store(false, Ordering::SeqCst);
+        self.modified = modified(&self.path);
+
+        let path = self.path.to_string_lossy();
+        let config = SspServerConfig::load(path.as_ref())?.with_env()?;
+
+        let changes = self.config.changes(&config);
+        if changes.is_empty() {
+            log::debug!("Configuration {path} unchanged");
+            return Ok(changes);
+        }
+
+        handle.reload_config(&config)?;
+
+        for change in changes.iter().filter(|c| c.requires_restart()) {
+            log::warn!("Configuration {path}: {change} changes apply after a restart");
+        }
+        log::info!(
+            "Reloaded configuration {path}, changed: {}",
+            changes
+                .iter()
+                .map(ConfigChange::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        self.config = config;
+
+        Ok(changes)
+    }
+
+    /// Spawns a thread checking the file every [WATCH_INTERVAL_MS], and reloading it into the
+    /// `handle` when due, until `stop` is set.
+    pub fn spawn(
+        mut self,
+        handle: Arc<Mutex<DeviceHandle>>,
+        stop: Arc<AtomicBool>,
+    ) -> Result<thread::JoinHandle<()>> {
+        thread::Builder::new()
+            .name("config-watcher".into())
+            .spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(time::Duration::from_millis(WATCH_INTERVAL_MS));
+
+                    if self.due() {
+                        if let Err(err) = self.reload(&mut handle.lock()) {
+                            log::error!("Failed to reload configuration: {err}");
+                        }
+                    }
+                }
+            })
+            .map_err(|err| ssp::Error::Io(format!("failed to spawn the config watcher: {err}")))
+    }
+}
+
+// Gets the modification time of the file at `path`.
+fn modified(path: &Path) -> Option<time::SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Gets the name of the environment variable overriding the response timeout of a `class`.
+pub fn timeout_env(class: CommandClass) -> String {
+    format!("SSP_{}_TIMEOUT_MS", class.as_str().to_uppercase())
+}
+
+fn parse_env<T: FromStr>(name: &str, value: &str) -> Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| ssp::Error::Io(format!("invalid {name} value: {value}")))
+}
+
+fn parse_flag(name: &str, value: &str) -> Result<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(ssp::Error::Io(format!("invalid {name} flag: {value}"))),
+    }
+}
@@ -0,0 +1,535 @@
+//! Protocol timing conformance checks.
+//!
+//! A [TimingTransport] wraps any [Transport], and measures the timing of the exchanges passing
+//! through it: the gaps between polls, how long the device takes to start answering, the gaps
+//! between the bytes of a response, and when, and how often, commands are retransmitted. Every
+//! measurement outside the [TimingLimits] is recorded as a [Violation] in the shared
+//! [TimingReport], so scheduler changes can be checked against the limits of the ITL
+//! specification, instead of by eye:
+//!
+//! ```no_run
+//! # fn main() -> ssp::Result<()> {
+//! use ssp_server::conformance::{TimingLimits, TimingTransport};
+//! use ssp_server::{transport, DeviceHandle};
+//!
+//! let port = transport::open_serial_port("/dev/ttyUSB0")?;
+//! let transport = TimingTransport::new(port, TimingLimits::new());
+//! let report = transport.report().clone();
+//!
+//! let handle = DeviceHandle::from_transport(transport)?;
+//! handle.poll()?;
+//!
+//! for violation in report.violations() {
+//!     log::warn!("Timing violation: {violation}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The measurements are taken by a [TimingMonitor], which takes the time of every write, and
+//! read as a parameter, so the checks also run on recorded, or simulated timings.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::device_handle::INTER_BYTE_TIMEOUT_MS;
+use crate::framing::{self, FrameDecoder, MAX_RETRANSMISSIONS};
+use crate::timeouts::POLL_TIMEOUT_MS;
+use crate::transport::Transport;
+
+/// Default shortest gap between two polls, giving the device time to process the last one
+/// (milliseconds).
+pub const DEFAULT_MIN_POLL_GAP_MS: u64 = 200;
+/// Default longest gap between two polls, recommended by ITL for timely event reporting
+/// (milliseconds).
+pub const DEFAULT_MAX_POLL_GAP_MS: u64 = 1_000;
+/// Default time the device has to start answering a command (milliseconds).
+pub const DEFAULT_RESPONSE_WINDOW_MS: u64 = 1_000;
+/// Default shortest wait before retransmitting a command that got no response (milliseconds).
+pub const DEFAULT_MIN_RETRANSMIT_GAP_MS: u64 = POLL_TIMEOUT_MS;
+
+/// Timing limits checked by a [TimingMonitor].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimingLimits {
+    min_poll_gap: time::Duration,
+    max_poll_gap: time::Duration,
+    response_window: time::Duration,
+    inter_byte: time::Duration,
+    min_retransmit_gap: time::Duration,
+    max_retransmissions: u32,
+}
+
+impl TimingLimits {
+    /// Creates new [TimingLimits] with the default limits.
+    pub const fn new() -> Self {
+        Self {
+            min_poll_gap: time::Duration::from_millis(DEFAULT_MIN_POLL_GAP_MS),
+            max_poll_gap: time::Duration::from_millis(DEFAULT_MAX_POLL_GAP_MS),
+            response_window: time::Duration::from_millis(DEFAULT_RESPONSE_WINDOW_MS),
+            inter_byte: time::Duration::from_millis(INTER_BYTE_TIMEOUT_MS),
+            min_retransmit_gap: time::Duration::from_millis(DEFAULT_MIN_RETRANSMIT_GAP_MS),
+            max_retransmissions: MAX_RETRANSMISSIONS,
+        }
+    }
+
+    /// Builder function that sets the allowed range of gaps between two polls.
+    pub fn with_poll_gap(mut self, min: time::Duration, max: time::Duration) -> Self {
+        self.min_poll_gap = min;
+        self.max_poll_gap = max.max(min);
+        self
+    }
+
+    /// Builder function that sets the time the device has to start answering a command.
+    pub fn with_response_window(mut self, window: time::Duration) -> Self {
+        self.response_window = window;
+        self
+    }
+
+    /// Builder function that sets the longest gap between two bytes of a response.
+    pub fn with_inter_byte(mut self, inter_byte: time::Duration) -> Self {
+        self.inter_byte = inter_byte;
+        self
+    }
+
+    /// Builder function that sets the shortest wait before retransmitting a command that got no
+    /// response, and how many times a command may be retransmitted.
+    pub fn with_retransmissions(mut self, min_gap: time::Duration, max: u32) -> Self {
+        self.min_retransmit_gap = min_gap;
+        self.max_retransmissions = max;
+        self
+    }
+
+    /// Gets the shortest gap between two polls.
+    pub const fn min_poll_gap(&self) -> time::Duration {
+        self.min_poll_gap
+    }
+
+    /// Gets the longest gap between two polls.
+    pub const fn max_poll_gap(&self) -> time::Duration {
+        self.max_poll_gap
+    }
+
+    /// Gets the time the device has to start answering a command.
+    pub const fn response_window(&self) -> time::Duration {
+        self.response_window
+    }
+
+    /// Gets the longest gap between two bytes of a response.
+    pub const fn inter_byte(&self) -> time::Duration {
+        self.inter_byte
+    }
+
+    /// Gets the shortest wait before retransmitting a command that got no response.
+    pub const fn min_retransmit_gap(&self) -> time::Duration {
+        self.min_retransmit_gap
+    }
+
+    /// Gets how many times a command may be retransmitted.
+    pub const fn max_retransmissions(&self) -> u32 {
+        self.max_retransmissions
+    }
+}
+
+impl Default for TimingLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Timing measurement outside the [TimingLimits].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Violation {
+    /// A poll followed the previous poll too closely.
+    PollGapTooShort(time::Duration),
+    /// A poll followed the previous poll too late.
+    PollGapTooLong(time::Duration),
+    /// The device started answering the command after the response window.
+    SlowResponse {
+        command: ssp::MessageType,
+        elapsed: time::Duration,
+    },
+    /// The bytes of a response to the command were too far apart.
+    InterByteGap {
+        command: ssp::MessageType,
+        gap: time::Duration,
+    },
+    /// The command was retransmitted without a response, before the host waited long enough.
+    EarlyRetransmission {
+        command: ssp::MessageType,
+        gap: time::Duration,
+    },
+    /// The command was retransmitted more times than allowed.
+    ExcessRetransmissions {
+        command: ssp::MessageType,
+        count: u32,
+    },
+}
+
+impl Violation {
+    /// Gets the [Violation] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::PollGapTooShort(_) => "poll_gap_too_short",
+            Self::PollGapTooLong(_) => "poll_gap_too_long",
+            Self::SlowResponse { .. } => "slow_response",
+            Self::InterByteGap { .. } => "inter_byte_gap",
+            Self::EarlyRetransmission { .. } => "early_retransmission",
+            Self::ExcessRetransmissions { .. } => "excess_retransmissions",
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.as_str();
+
+        match self {
+            Self::PollGapTooShort(gap) | Self::PollGapTooLong(gap) => {
+                write!(f, "{name}({}ms)", gap.as_millis())
+            }
+            Self::SlowResponse { command, elapsed } => write!(
+                f,
+                "{name}({}, {}ms)",
+                <&str>::from(*command),
+                elapsed.as_millis()
+            ),
+            Self::InterByteGap { command, gap } | Self::EarlyRetransmission { command, gap } => {
+                write!(
+                    f,
+                    "{name}({}, {}ms)",
+                    <&str>::from(*command),
+                    gap.as_millis()
+                )
+            }
+            Self::ExcessRetransmissions { command, count } => {
+                write!(f, "{name}({}, {count})", <&str>::from(*command))
+            }
+        }
+    }
+}
+
+/// Measurements of a [TimingMonitor].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TimingSummary {
+    /// Number of commands sent, without retransmissions.
+    pub commands: u64,
+    /// Number of polls sent, without retransmissions.
+    pub polls: u64,
+    /// Number of complete responses received.
+    pub responses: u64,
+    /// Number of retransmitted commands.
+    pub retransmissions: u64,
+    /// Shortest gap between two polls.
+    pub min_poll_gap: Option<time::Duration>,
+    /// Longest gap between two polls.
+    pub max_poll_gap: Option<time::Duration>,
+    /// Longest time the device took to start answering.
+    pub max_response_time: Option<time::Duration>,
+    /// Timing measurements outside the limits, in order.
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for TimingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |d: Option<time::Duration>| d.map(|d| d.as_millis()).unwrap_or_default();
+
+        write!(
+            f,
+            "commands: {}, polls: {}, responses: {}, retransmissions: {}, poll gap: {}-{}ms, max response time: {}ms, violations: {}",
+            self.commands,
+            self.polls,
+            self.responses,
+            self.retransmissions,
+            ms(self.min_poll_gap),
+            ms(self.max_poll_gap),
+            ms(self.max_response_time),
+            self.violations.len(),
+        )
+    }
+}
+
+/// Shared report of the measurements of a [TimingMonitor].
+///
+/// Stays readable after a [TimingTransport] is moved into a [DeviceHandle](crate::DeviceHandle).
+#[derive(Clone, Debug, Default)]
+pub struct TimingReport(Arc<Mutex<TimingSummary>>);
+
+impl TimingReport {
+    /// Creates a new, empty [TimingReport].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets a copy of the measurements.
+    pub fn summary(&self) -> TimingSummary {
+        self.0.lock().clone()
+    }
+
+    /// Gets a copy of the violations, in order.
+    pub fn violations(&self) -> Vec<Violation> {
+        self.0.lock().violations.clone()
+    }
+
+    /// Gets whether every measurement was within the limits.
+    pub fn is_conformant(&self) -> bool {
+        self.0.lock().violations.is_empty()
+    }
+
+    /// Clears the measurements, e.g. after a warm-up phase.
+    pub fn clear(&self) {
+        *self.0.lock() = TimingSummary::default();
+    }
+}
+
+// Command waiting for its response.
+#[derive(Debug)]
+struct Exchange {
+    command: ssp::MessageType,
+    stuffed: Vec<u8>,
+    sent: time::Instant,
+    last_byte: Option<time::Instant>,
+    retransmissions: u32,
+}
+
+/// Checks the timing of the exchanges with a device against [TimingLimits].
+///
+/// Feed it every frame written by the host with [on_write](Self::on_write), and every chunk of
+/// bytes read with [on_read](Self::on_read), with the time they passed the wire.
+#[derive(Debug)]
+pub struct TimingMonitor {
+    limits: TimingLimits,
+    report: TimingReport,
+    decoder: FrameDecoder,
+    last_poll: Option<time::Instant>,
+    exchange: Option<Exchange>,
+}
+
+impl TimingMonitor {
+    /// Creates a new [TimingMonitor] checking the `limits`.
+    pub fn new(limits: TimingLimits) -> Self {
+        Self {
+            limits,
+            report: TimingReport::new(),
+            decoder: FrameDecoder::new(),
+            last_poll: None,
+            exchange: None,
+        }
+    }
+
+    /// Gets the [TimingLimits].
+    pub const fn limits(&self) -> &TimingLimits {
+        &self.limits
+    }
+
+    /// Gets the [TimingReport] of the measurements.
+    pub fn report(&self) -> &TimingReport {
+        &self.report
+    }
+
+    /// Records the stuffed frame written by the host at time `at`.
+    ///
+    /// Writing the same frame again is a retransmission. Bytes that do not decode as a frame are
+    /// ignored.
+    pub fn on_write(&mut self, at: time::Instant, stuffed: &[u8]) {
+        let Ok(frame) = framing::unstuff(stuffed) else {
+            return;
+        };
+        let Some(&command) = frame.get(ssp::message::index::DATA) else {
+            return;
+        };
+
+        self.decoder.reset();
+
+        let mut summary = self.report.0.lock();
+
+        if let Some(exchange) = self
+            .exchange
+            .as_mut()
+            .filter(|e| e.stuffed.as_slice() == stuffed)
+        {
+            let gap = at.saturating_duration_since(exchange.sent);
+            let command = exchange.command;
+
+            // a corrupted response may be retransmitted right away, a missing one only after the
+            // host waited for it
+            if exchange.last_byte.is_none() && gap < self.limits.min_retransmit_gap {
+                summary
+                    .violations
+                    .push(Violation::EarlyRetransmission { command, gap });
+            }
+
+            exchange.retransmissions += 1;
+            if exchange.retransmissions > self.limits.max_retransmissions {
+                summary.violations.push(Violation::ExcessRetransmissions {
+                    command,
+                    count: exchange.retransmissions,
+                });
+            }
+
+            exchange.sent = at;
+            exchange.last_byte = None;
+            summary.retransmissions += 1;
+
+            return;
+        }
+
+        let command = ssp::MessageType::from(command);
+
+        summary.commands += 1;
+
+        if command == ssp::MessageType::Poll {
+            if let Some(last) = self.last_poll {
+                let gap = at.saturating_duration_since(last);
+
+                summary.min_poll_gap = Some(summary.min_poll_gap.map_or(gap, |g| g.min(gap)));
+                summary.max_poll_gap = Some(summary.max_poll_gap.map_or(gap, |g| g.max(gap)));
+
+                if gap < self.limits.min_poll_gap {
+                    summary.violations.push(Violation::PollGapTooShort(gap));
+                } else if gap > self.limits.max_poll_gap {
+                    summary.violations.push(Violation::PollGapTooLong(gap));
+                }
+            }
+
+            self.last_poll = Some(at);
+            summary.polls += 1;
+        }
+
+        self.exchange = Some(Exchange {
+            command,
+            stuffed: stuffed.into(),
+            sent: at,
+            last_byte: None,
+            retransmissions: 0,
+        });
+    }
+
+    /// Records the `bytes` read by the host at time `at`.
+    ///
+    /// Bytes read without a command waiting for its response are ignored.
+    pub fn on_read(&mut self, at: time::Instant, bytes: &[u8]) {
+        let Some(exchange) = self.exchange.as_mut() else {
+            return;
+        };
+        if bytes.is_empty() {
+            return;
+        }
+
+        let mut summary = self.report.0.lock();
+        let command = exchange.command;
+
+        match exchange.last_byte {
+            None => {
+                let elapsed = at.saturating_duration_since(exchange.sent);
+
+                summary.max_response_time = Some(
+                    summary
+                        .max_response_time
+                        .map_or(elapsed, |t| t.max(elapsed)),
+                );
+
+                if elapsed > self.limits.response_window {
+                    summary
+                        .violations
+                        .push(Violation::SlowResponse { command, elapsed });
+                }
+            }
+            Some(last) => {
+                let gap = at.saturating_duration_since(last);
+                if gap > self.limits.inter_byte {
+                    summary
+                        .violations
+                        .push(Violation::InterByteGap { command, gap });
+                }
+            }
+        }
+
+        exchange.last_byte = Some(at);
+
+        for &byte in bytes {
+            match self.decoder.push(byte) {
+                Ok(true) => {
+                    summary.responses += 1;
+                    self.decoder.reset();
+                }
+                Ok(false) => (),
+                Err(_) => self.decoder.reset(),
+            }
+        }
+    }
+}
+
+/// Transport decorator checking the timing of the exchanges with a device.
+///
+/// Every written frame, and read chunk is passed to a [TimingMonitor], timed when the inner
+/// transport returns.
+pub struct TimingTransport<T: Transport> {
+    inner: T,
+    monitor: TimingMonitor,
+}
+
+impl<T: Transport> TimingTransport<T> {
+    /// Creates a new [TimingTransport] checking the exchanges over `inner` against the `limits`.
+    pub fn new(inner: T, limits: TimingLimits) -> Self {
+        Self {
+            inner,
+            monitor: TimingMonitor::new(limits),
+        }
+    }
+
+    /// Gets the [TimingReport] of the measurements.
+    pub fn report(&self) -> &TimingReport {
+        self.monitor.report()
+    }
+
+    /// Gets a reference to the wrapped transport.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Stops measuring, and returns the wrapped transport.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Transport> Read for TimingTransport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.monitor.on_read(time::Instant::now(), &buf[..n]);
+
+        Ok(n)
+    }
+}
+
+impl<T: Transport> Write for TimingTransport<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.monitor.on_write(time::Instant::now(), &buf[..n]);
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Transport> Transport for TimingTransport<T> {
+    fn clear(&mut self) -> Result<()> {
+        self.inner.clear()
+    }
+
+    fn set_read_timeout(&mut self, timeout: time::Duration) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        self.inner.discard_input()
+    }
+}
@@ -1,15 +1,17 @@
 #![allow(dead_code)]
 
+use std::borrow::Borrow;
+use std::cell::RefCell;
 use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
 #[cfg(feature = "jsonrpc")]
 use std::os::unix::net::UnixStream;
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::{thread, time};
 
 use crossbeam::channel;
 use parking_lot::{Mutex, MutexGuard};
-use serialport::TTYPort;
 
 #[cfg(feature = "jsonrpc")]
 use smol_jsonrpc::{Error as RpcError, Request, Response};
@@ -18,14 +20,65 @@ use smol_jsonrpc::{Error as RpcError, Request, Response};
 use ssp::jsonrpc::{jsonrpc_id, set_jsonrpc_id};
 use ssp::{CommandOps, MessageOps, ResponseOps, Result};
 
+use crate::audit::{AuditLog, AuditOp};
+use crate::auth::Authenticator;
+use crate::capture::{CaptureDirection, CaptureFile, CaptureTransport};
+use crate::cash_levels::{CashLevels, DenominationLevel};
+use crate::clock::{self, Clock};
+#[cfg(feature = "jsonrpc")]
+use crate::codec::WireFormat;
+use crate::encryption::{EncryptionMode, EncryptionPolicy};
+use crate::entropy::{EntropySource, SystemEntropy};
+use crate::essp;
+use crate::event_log::EventLog;
+use crate::framing::FrameBuffers;
+use crate::journal::{TransactionJournal, TransactionKind};
+use crate::key_negotiation::{
+    self, KeyNegotiationAttempt, KeyNegotiationDiagnostic, KeyNegotiationStep,
+};
+use crate::key_rotation::KeyRotationPolicy;
+use crate::key_store::{self, KeyStore};
+use crate::latency;
+use crate::lease::LeaseManager;
+#[cfg(feature = "jsonrpc")]
+use crate::lease::{self, ClientId, LeaseReply};
+use crate::payout_intent::{self, IntentState, PayoutIntentLog};
+use crate::poll_timer::PollSchedule;
+use crate::reconcile::{DeviceCounters, ReconciliationReport};
+use crate::redact::{self, RedactedExchange};
+use crate::registry::DeviceRegistry;
+use crate::reject_code::RejectReason;
+use crate::reject_history::RejectHistory;
+use crate::retry::{self, CommandClass, RetryPolicy};
+use crate::snapshot::{BezelSnapshot, DeviceSnapshot};
+use crate::stats::{Exchange, LinkStats};
+use crate::telemetry::CommandSpan;
+use crate::transport::{self, NullTransport, TcpTransport, Transport};
 use crate::{continue_on_err, encryption_key};
+use crate::{frame_log, framing};
 
 mod inner;
+mod link;
+mod submit;
+mod workers;
+
+use inner::EventStores;
+use link::LinkState;
+use submit::PortTurn;
+pub use submit::{CommandHandle, CommandPriority};
+use workers::Workers;
+pub use workers::{WorkerKind, WorkerStatus};
 
 /// Timeout for waiting for lock on a mutex (milliseconds).
 pub const LOCK_TIMEOUT_MS: u64 = 5_000;
 /// Timeout for waiting for serial communication (milliseconds).
 pub const SERIAL_TIMEOUT_MS: u64 = 10_000;
+/// Maximum gap between two bytes of a response (milliseconds).
+pub const INTER_BYTE_TIMEOUT_MS: u64 = 100;
+/// Time the bus must stay silent before resynchronizing after a failed exchange (milliseconds).
+pub const BUS_QUIET_MS: u64 = INTER_BYTE_TIMEOUT_MS;
+/// Maximum time to wait for the bus to go quiet after a failed exchange (milliseconds).
+pub const BUS_RECOVERY_TIMEOUT_MS: u64 = 1_000;
 /// Minimum polling interval between messages (milliseconds).
 pub const MIN_POLLING_MS: u64 = 500;
 /// Medium polling interval between messages (milliseconds).
@@ -37,141 +90,53 @@ pub const MAX_POLLING_MS: u64 = 1_000;
 pub const QUEUE_TIMEOUT_MS: u64 = 50;
 /// Default serial connection BAUD rate (bps).
 pub const BAUD_RATE: u32 = 9_600;
+/// Maximum number of attempts for negotiating an encryption key.
+pub const KEY_NEGOTIATION_ATTEMPTS: u32 = 3;
+/// Maximum number of eSSP packets the device count may run ahead of the host count, and still be
+/// resynchronized without negotiating a new key.
+pub const SEQUENCE_RESYNC_WINDOW: u32 = 8;
+/// Environment variable enabling the secure shutdown path, set to `1` or `true`.
+pub const SECURE_SHUTDOWN_ENV: &str = "SSP_SECURE_SHUTDOWN";
 
-pub(crate) static SEQ_FLAG: AtomicBool = AtomicBool::new(false);
-static POLLING_INIT: AtomicBool = AtomicBool::new(false);
-
-static ESCROWED: AtomicBool = AtomicBool::new(false);
-static ESCROWED_AMOUNT: AtomicU32 = AtomicU32::new(0);
-
-static ENABLED: AtomicBool = AtomicBool::new(false);
-
-static CASHBOX_ATTACHED: AtomicBool = AtomicBool::new(true);
-
-// Time when a device reset was initiated.
-static RESET_TIME: AtomicU64 = AtomicU64::new(0);
 // Timeout for waiting for the device to reset (seconds).
 const RESET_TIMEOUT_SECS: u64 = 60;
 
-static PROTOCOL_VERSION: AtomicU8 = AtomicU8::new(6);
-
-static INTERACTIVE: AtomicBool = AtomicBool::new(false);
-
-static DISPENSING: AtomicBool = AtomicBool::new(false);
-
-static UNSAFE_JAM: AtomicBool = AtomicBool::new(false);
-
-pub(crate) fn sequence_flag() -> ssp::SequenceFlag {
-    SEQ_FLAG.load(Ordering::Relaxed).into()
-}
-
-pub(crate) fn set_sequence_flag(flag: ssp::SequenceFlag) {
-    SEQ_FLAG.store(flag.into(), Ordering::SeqCst);
-}
-
-// Whether the polling routine has started.
-fn polling_inited() -> bool {
-    POLLING_INIT.load(Ordering::Relaxed)
-}
-
-// Sets the flag indicating whether the polling routine started.
-fn set_polling_inited(inited: bool) {
-    POLLING_INIT.store(inited, Ordering::SeqCst);
-}
-
-pub(crate) fn escrowed() -> bool {
-    ESCROWED.load(Ordering::Relaxed)
-}
-
-pub(crate) fn set_escrowed(escrowed: bool) -> bool {
-    let last = ESCROWED.load(Ordering::Relaxed);
-    ESCROWED.store(escrowed, Ordering::SeqCst);
-    last
-}
-
-pub(crate) fn enabled() -> bool {
-    ENABLED.load(Ordering::Relaxed)
-}
-
-pub(crate) fn set_enabled(enabled: bool) {
-    ENABLED.store(enabled, Ordering::SeqCst);
-}
-
-pub(crate) fn escrowed_amount() -> ssp::ChannelValue {
-    ESCROWED_AMOUNT.load(Ordering::Relaxed).into()
-}
-
-pub(crate) fn set_escrowed_amount(amount: ssp::ChannelValue) -> ssp::ChannelValue {
-    let last = ssp::ChannelValue::from(ESCROWED_AMOUNT.load(Ordering::Relaxed));
-    ESCROWED_AMOUNT.store(amount.into(), Ordering::SeqCst);
-    last
-}
-
-/// Gets whether the csahboxed is attached to the device.
-pub fn cashbox_attached() -> bool {
-    CASHBOX_ATTACHED.load(Ordering::Relaxed)
-}
-
-/// Sets whether the csahboxed is attached to the device.
-pub fn set_cashbox_attached(attached: bool) -> bool {
-    let last = cashbox_attached();
-    CASHBOX_ATTACHED.store(attached, Ordering::SeqCst);
-    last
-}
+// Serializes the use of the process-global eSSP sequence count of the `ssp` library.
+static SEQUENCE_COUNT_LOCK: Mutex<()> = Mutex::new(());
 
-pub(crate) fn resetting() -> bool {
-    reset_time() != 0
+thread_local! {
+    // Frame buffers reused by every message sent from the thread, e.g. the polling routine.
+    static FRAME_BUFFERS: RefCell<FrameBuffers> = RefCell::new(FrameBuffers::new());
 }
 
-pub(crate) fn reset_time() -> u64 {
-    RESET_TIME.load(Ordering::Relaxed)
+// Runs `f` with the frame buffers of the current thread.
+fn with_frame_buffers<T>(f: impl FnOnce(&mut FrameBuffers) -> T) -> T {
+    FRAME_BUFFERS.with(|buffers| match buffers.try_borrow_mut() {
+        Ok(mut buffers) => f(&mut buffers),
+        // already in use further up the stack, use temporary buffers
+        Err(_) => f(&mut FrameBuffers::new()),
+    })
 }
 
-pub(crate) fn set_reset_time(time: u64) -> u64 {
-    let last = reset_time();
-    RESET_TIME.store(time, Ordering::SeqCst);
-    last
+// Response to a command skipped as a no-op.
+fn coalesced<T: ResponseOps>(mut res: T) -> T {
+    res.set_response_status(ssp::ResponseStatus::Ok);
+    res
 }
 
-pub(crate) fn protocol_version() -> ssp::ProtocolVersion {
-    PROTOCOL_VERSION.load(Ordering::Relaxed).into()
-}
-
-pub(crate) fn set_protocol_version(protocol: ssp::ProtocolVersion) -> ssp::ProtocolVersion {
-    let last = protocol_version();
-    PROTOCOL_VERSION.store(protocol.into(), Ordering::SeqCst);
-    last
-}
-
-pub(crate) fn interactive() -> bool {
-    INTERACTIVE.load(Ordering::Relaxed)
-}
-
-pub(crate) fn set_interactive(val: bool) -> bool {
-    let last = interactive();
-    INTERACTIVE.store(val, Ordering::SeqCst);
-    last
-}
-
-pub(crate) fn dispensing() -> bool {
-    DISPENSING.load(Ordering::Relaxed)
-}
-
-pub(crate) fn set_dispensing(val: bool) {
-    DISPENSING.store(val, Ordering::SeqCst);
-}
-
-pub(crate) fn unsafe_jam() -> bool {
-    UNSAFE_JAM.load(Ordering::Relaxed)
-}
-
-pub(crate) fn set_unsafe_jam(val: bool) {
-    UNSAFE_JAM.store(val, Ordering::SeqCst)
+/// Gets whether an error was returned for a plaintext response to an encrypted command.
+///
+/// Downgrades are [Encryption](ssp::Error::Encryption) errors carrying the status of the
+/// plaintext response. The only other [Encryption](ssp::Error::Encryption) error is
+/// [KeyNotSet](ssp::ResponseStatus::KeyNotSet), for a lost session.
+pub fn is_encryption_downgrade(err: &ssp::Error) -> bool {
+    matches!(err, ssp::Error::Encryption(status) if *status != ssp::ResponseStatus::KeyNotSet)
 }
 
 /// Polling interactivity mode.
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PollMode {
     /// Automically handle polling events.
     Auto,
@@ -227,44 +192,296 @@ impl PushEventReceiver {
     }
 }
 
+/// Lock on the [Transport] of a [DeviceHandle], returned by
+/// [serial_port](DeviceHandle::serial_port).
+///
+/// While a background polling routine runs, the routine waits between polls until the guard is
+/// dropped.
+pub struct SerialPortGuard<'a> {
+    // released before the turn ends
+    port: MutexGuard<'a, Box<dyn Transport>>,
+    _turn: PortTurn,
+}
+
+impl Deref for SerialPortGuard<'_> {
+    type Target = Box<dyn Transport>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.port
+    }
+}
+
+impl DerefMut for SerialPortGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.port
+    }
+}
+
 /// Handle for communicating with a SSP-enabled device over serial.
 ///
 /// ```no_run
 /// let _handle = ssp_server::DeviceHandle::new("/dev/ttyUSB0").unwrap();
 /// ```
 pub struct DeviceHandle {
-    serial_port: Arc<Mutex<TTYPort>>,
+    serial_port: Arc<Mutex<Box<dyn Transport>>>,
+    link: Arc<LinkState>,
+    port_path: String,
     generator: ssp::GeneratorKey,
     modulus: ssp::ModulusKey,
     random: ssp::RandomKey,
     fixed_key: ssp::FixedKey,
+    entropy: Box<dyn EntropySource>,
     key: Arc<Mutex<Option<ssp::AesKey>>>,
+    leases: LeaseManager,
+    auth: Authenticator,
+    audit: Option<AuditLog>,
+    journal: Option<TransactionJournal>,
+    cash_levels: Option<CashLevels>,
+    event_log: Option<EventLog>,
+    rejects: Option<RejectHistory>,
+    registry: Option<DeviceRegistry>,
+    payout_intents: Option<PayoutIntentLog>,
+    #[cfg(feature = "sqlite")]
+    event_store: Option<crate::sqlite::SqliteEventStore>,
+    #[cfg(feature = "json-log")]
+    json_log: Option<crate::json_log::JsonLogSink>,
+    key_rotation: KeyRotationPolicy,
+    key_negotiation: Option<KeyNegotiationDiagnostic>,
+    session_start: Option<time::Instant>,
+    key_store: Option<Box<dyn KeyStore>>,
+    secure_shutdown: bool,
+    poll_schedule: PollSchedule,
+    default_inhibits: [u8; 2],
+    clock: Arc<dyn Clock>,
+    events: Arc<Mutex<Option<channel::Sender<ssp::Event>>>>,
+    commands: Arc<submit::CommandQueue>,
+    workers: Workers,
 }
 
 impl DeviceHandle {
     /// Creates a new [DeviceHandle] with a serial connection over the supplied serial device.
+    ///
+    /// Paths starting with `tcp://` connect to a [TcpBridge](crate::bridge::TcpBridge) instead,
+    /// e.g. `tcp://192.168.1.10:7000`.
     pub fn new(serial_path: &str) -> Result<Self> {
-        // For details on the following setup, see sections 5.4 & 7 in the SSP implementation guide
-        let serial_port = Arc::new(Mutex::new(
-            serialport::new(serial_path, BAUD_RATE)
-                // disable flow control serial lines
-                .flow_control(serialport::FlowControl::None)
-                // eight-bit data size
-                .data_bits(serialport::DataBits::Eight)
-                // no control bit parity
-                .parity(serialport::Parity::None)
-                // two bit stop
-                .stop_bits(serialport::StopBits::Two)
-                // serial device times out after 10 seconds, so do we
-                .timeout(time::Duration::from_millis(SERIAL_TIMEOUT_MS))
-                // get back a TTY port for POSIX systems, Windows is not supported
-                .open_native()?,
-        ));
-
-        let mut prime_gen = ssp::primes::Generator::from_entropy();
-
-        let mut generator = ssp::GeneratorKey::from_generator(&mut prime_gen);
-        let mut modulus = ssp::ModulusKey::from_generator(&mut prime_gen);
+        let mut handle = match serial_path.strip_prefix(transport::TCP_SCHEME) {
+            Some(addr) => Self::new_tcp(addr)?,
+            None => Self::from_transport(transport::open_serial_port(serial_path)?)?,
+        };
+
+        handle.port_path = serial_path.into();
+
+        Ok(handle)
+    }
+
+    /// Creates a new [DeviceHandle] with a serial connection over the supplied serial device, and
+    /// completes the initial [sync](Self::sync), retrying with the backoff of the `policy`.
+    ///
+    /// At boot, the USB serial adapter is often not enumerated yet when the service starts.
+    /// Opening the port, and the sync are retried until they succeed, or the `policy` runs out of
+    /// attempts, returning the last error.
+    ///
+    /// ```no_run
+    /// # fn main() -> ssp::Result<()> {
+    /// use std::time::Duration;
+    ///
+    /// use ssp_server::retry::RetryPolicy;
+    ///
+    /// let policy = RetryPolicy::new()
+    ///     .with_max_attempts(30)
+    ///     .with_backoff(Duration::from_millis(500), Duration::from_secs(10));
+    ///
+    /// let _handle = ssp_server::DeviceHandle::connect_with_retry("/dev/ttyUSB0", policy)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn connect_with_retry(serial_path: &str, policy: RetryPolicy) -> Result<Self> {
+        Self::retry_connect(serial_path, policy, || Self::new(serial_path))
+    }
+
+    /// Creates a new [DeviceHandle] over the [Transport] returned by `open`, and completes the
+    /// initial [sync](Self::sync), retrying with the backoff of the `policy`.
+    ///
+    /// See [connect_with_retry](Self::connect_with_retry).
+    pub fn connect_transport_with_retry<T, F>(mut open: F, policy: RetryPolicy) -> Result<Self>
+    where
+        T: Transport + 'static,
+        F: FnMut() -> Result<T>,
+    {
+        Self::retry_connect("transport", policy, || Self::from_transport(open()?))
+    }
+
+    fn retry_connect<F>(name: &str, policy: RetryPolicy, mut connect: F) -> Result<Self>
+    where
+        F: FnMut() -> Result<Self>,
+    {
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            let err = match connect().and_then(|handle| handle.sync().map(|_| handle)) {
+                Ok(handle) => {
+                    if attempts > 1 {
+                        log::info!("Connected to {name} after {attempts} attempts");
+                    }
+                    return Ok(handle);
+                }
+                Err(err) => err,
+            };
+
+            if !policy.should_retry(attempts) {
+                log::error!("Failed to connect to {name} after {attempts} attempts: {err}");
+                return Err(err);
+            }
+
+            let backoff = policy.backoff(attempts);
+            log::warn!(
+                "Failed to connect to {name} (attempt {attempts} of {}): {err}, retrying in {backoff:?}",
+                policy.max_attempts()
+            );
+
+            thread::sleep(backoff);
+        }
+    }
+
+    /// Creates a new [DeviceHandle] connected to the port of the `config`, and configured with
+    /// its settings, see [with_config](Self::with_config).
+    ///
+    /// ```no_run
+    /// # fn main() -> ssp::Result<()> {
+    /// let config = ssp_server::config::SspServerConfig::load("/etc/ssp/ssp-server.toml")?;
+    /// let _handle = ssp_server::DeviceHandle::from_config(&config)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "config")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "config")))]
+    pub fn from_config(config: &crate::config::SspServerConfig) -> Result<Self> {
+        config.validate()?;
+
+        let port = config.port.as_str();
+
+        let mut handle = match port.strip_prefix(transport::TCP_SCHEME) {
+            Some(addr) => Self::new_tcp(addr)?,
+            None => Self::from_transport(transport::open_serial_port_with_baud_rate(
+                port,
+                config.baud_rate,
+            )?)?,
+        };
+
+        handle.port_path = port.into();
+
+        handle.with_config(config)
+    }
+
+    /// Builder function that applies the settings of the `config`, except for the port.
+    ///
+    /// Response timeouts only apply to this handle, unset timeouts use the process-wide
+    /// [timeouts](crate::timeouts). The escrow policy applies once the caller starts the
+    /// background polling routine, see
+    /// [EscrowPolicy::poll_mode](crate::config::EscrowPolicy::poll_mode), the frontends are
+    /// started by the server binaries.
+    #[cfg(feature = "config")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "config")))]
+    pub fn with_config(self, config: &crate::config::SspServerConfig) -> Result<Self> {
+        config.validate()?;
+
+        for class in CommandClass::ALL {
+            if let Some(timeout) = config.timeouts.response_timeout(class) {
+                self.link.set_response_timeout(class, Some(timeout));
+            }
+        }
+
+        let encryption = &config.encryption;
+
+        let mut handle = self
+            .with_poll_jitter_budget(config.polling.jitter_budget())
+            .with_default_inhibits(config.inhibits.bitfields()?)
+            .with_require_encryption(encryption.require)
+            .with_secure_shutdown(encryption.secure_shutdown)
+            .with_key_rotation(encryption.key_rotation());
+
+        if let Some(interval) = config.polling.interval() {
+            handle = handle.with_poll_interval(interval);
+        }
+
+        Ok(match encryption.fixed_key()? {
+            Some(fixed_key) => handle.with_fixed_key(fixed_key),
+            None => handle,
+        })
+    }
+
+    /// Applies the runtime settings of the `config` to a running handle, without dropping the
+    /// serial session.
+    ///
+    /// Reloads the response timeouts, the poll interval, and jitter budget, the escrow policy, the
+    /// default inhibits, and the key rotation thresholds of this handle, other handles in the
+    /// process keep their settings. Unset timeouts return to the process-wide
+    /// [timeouts](crate::timeouts), and an unset poll interval to its default. Changed inhibits
+    /// are sent to the device right away, if its inhibits were already set. The escrow policy
+    /// switches the polling routine between interactive, and automatic mode.
+    ///
+    /// The port, baud rate, encryption requirement, fixed key, and frontends only apply on
+    /// restart, see [ConfigChange::requires_restart](crate::config::ConfigChange::requires_restart).
+    #[cfg(feature = "config")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "config")))]
+    pub fn reload_config(&mut self, config: &crate::config::SspServerConfig) -> Result<()> {
+        config.validate()?;
+
+        for class in CommandClass::ALL {
+            self.link
+                .set_response_timeout(class, config.timeouts.response_timeout(class));
+        }
+
+        self.poll_schedule.set_interval(config.polling.interval());
+        self.poll_schedule
+            .set_jitter_budget(config.polling.jitter_budget());
+
+        let interactive = config.escrow.policy.poll_mode() == PollMode::Interactive;
+        if self.link.set_interactive(interactive) != interactive {
+            log::info!("Escrow policy changed to {}", config.escrow.policy);
+        }
+
+        self.key_rotation = config.encryption.key_rotation();
+
+        self.set_default_inhibits(config.inhibits.bitfields()?)
+    }
+
+    /// Creates a new [DeviceHandle] connected to a [TcpBridge](crate::bridge::TcpBridge)
+    /// listening on `addr`.
+    ///
+    /// ```no_run
+    /// let _handle = ssp_server::DeviceHandle::new_tcp("192.168.1.10:7000").unwrap();
+    /// ```
+    pub fn new_tcp(addr: &str) -> Result<Self> {
+        let mut handle = Self::from_transport(TcpTransport::connect(addr)?)?;
+        handle.port_path = format!("{}{addr}", transport::TCP_SCHEME);
+
+        Ok(handle)
+    }
+
+    /// Creates a new [DeviceHandle] connected to a [TcpBridge](crate::bridge::TcpBridge) serving
+    /// TLS on `addr`.
+    ///
+    /// ```no_run
+    /// let config = ssp_server::tls::TlsClientConfig::new("/etc/ssp/ca.pem");
+    /// let _handle = ssp_server::DeviceHandle::new_tls("bridge.local:7000", &config).unwrap();
+    /// ```
+    #[cfg(feature = "tls")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "tls")))]
+    pub fn new_tls(addr: &str, config: &crate::tls::TlsClientConfig) -> Result<Self> {
+        Self::from_transport(crate::tls::TlsTransport::connect(addr, config)?)
+    }
+
+    /// Creates a new [DeviceHandle] communicating over the supplied [Transport].
+    pub fn from_transport<T: Transport + 'static>(transport: T) -> Result<Self> {
+        let serial_port: Arc<Mutex<Box<dyn Transport>>> = Arc::new(Mutex::new(Box::new(transport)));
+
+        let mut entropy = SystemEntropy::new();
+
+        let mut generator = entropy.generator_key();
+        let mut modulus = entropy.modulus_key();
 
         // Modulus key must be smaller than the Generator key
         let mod_inner = modulus.as_inner();
@@ -275,20 +492,827 @@ impl DeviceHandle {
             generator = mod_inner.into();
         }
 
-        let random = ssp::RandomKey::from_entropy();
+        let random = entropy.random_key();
         let fixed_key = ssp::FixedKey::from_inner(ssp::DEFAULT_FIXED_KEY_U64);
         let key = Arc::new(Mutex::new(None));
 
         Ok(Self {
             serial_port,
+            link: Arc::new(LinkState::new()),
+            port_path: String::new(),
             generator,
             modulus,
             random,
             fixed_key,
+            entropy: Box::new(entropy),
             key,
+            leases: LeaseManager::new(),
+            auth: Authenticator::new(),
+            audit: None,
+            journal: None,
+            cash_levels: None,
+            event_log: None,
+            rejects: None,
+            registry: None,
+            payout_intents: None,
+            #[cfg(feature = "sqlite")]
+            event_store: None,
+            #[cfg(feature = "json-log")]
+            json_log: None,
+            key_rotation: KeyRotationPolicy::new(),
+            key_negotiation: None,
+            session_start: None,
+            key_store: None,
+            secure_shutdown: false,
+            poll_schedule: PollSchedule::new(),
+            default_inhibits: [0xff, 0xff],
+            clock: clock::system_clock(),
+            events: Arc::new(Mutex::new(None)),
+            commands: Arc::default(),
+            workers: Workers::default(),
         })
     }
 
+    /// Gets the serial device path, or bridge address the handle is connected to.
+    ///
+    /// Empty for handles created with [from_transport](Self::from_transport).
+    pub fn port_path(&self) -> &str {
+        self.port_path.as_str()
+    }
+
+    /// Gets a reference to the [LeaseManager] arbitrating between frontend clients.
+    ///
+    /// All frontends sharing the [DeviceHandle] share the same lease.
+    pub fn leases(&self) -> &LeaseManager {
+        &self.leases
+    }
+
+    /// Gets a reference to the [Authenticator] checking the credentials of network clients.
+    pub fn auth(&self) -> &Authenticator {
+        &self.auth
+    }
+
+    /// Builder function that sets the [Authenticator] checking the credentials of network
+    /// clients.
+    ///
+    /// All network frontends sharing the [DeviceHandle] use the same tokens.
+    pub fn with_auth(mut self, auth: Authenticator) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Builder function that sets the [Authenticator] from the environment, see
+    /// [Authenticator::from_env].
+    pub fn with_env_auth(self) -> Result<Self> {
+        Ok(self.with_auth(Authenticator::from_env()?))
+    }
+
+    /// Gets the [AuditLog] recording privileged commands, if set.
+    pub fn audit_log(&self) -> Option<&AuditLog> {
+        self.audit.as_ref()
+    }
+
+    /// Builder function that sets the [AuditLog] recording privileged commands.
+    ///
+    /// Payouts, empties, channel inhibit changes, and key operations are recorded, see
+    /// [audit](crate::audit).
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Builder function that opens the [AuditLog] set in the
+    /// [AUDIT_ENV_PATH](crate::audit::AUDIT_ENV_PATH) environment variable, if set.
+    pub fn with_env_audit_log(self) -> Result<Self> {
+        match AuditLog::from_env()? {
+            Some(audit) => Ok(self.with_audit_log(audit)),
+            None => Ok(self),
+        }
+    }
+
+    // Records the result of a privileged command in the audit log, if set.
+    //
+    // The command already ran, so failing to write the record does not change its result.
+    fn audit<T>(&self, op: AuditOp, params: &str, res: Result<T>) -> Result<T> {
+        if let Some(audit) = self.audit.as_ref() {
+            if let Err(err) = audit.record(op, params, &res) {
+                log::error!("Failed to write audit record for {}: {err}", op.as_str());
+            }
+        }
+
+        res
+    }
+
+    /// Builder function that records all traffic with the device to the `capture`.
+    ///
+    /// See [capture](crate::capture) for the capture format.
+    pub fn with_capture(self, capture: CaptureFile) -> Result<Self> {
+        {
+            let mut serial_port = self.serial_port()?;
+            let inner = std::mem::replace(&mut *serial_port, Box::new(NullTransport));
+            *serial_port = Box::new(CaptureTransport::new(inner, capture));
+        }
+
+        Ok(self)
+    }
+
+    /// Builder function that records all traffic with the device to the capture file set in the
+    /// [CAPTURE_ENV_PATH](crate::capture::CAPTURE_ENV_PATH) environment variable, if set.
+    pub fn with_env_capture(self) -> Result<Self> {
+        match CaptureFile::from_env()? {
+            Some(capture) => self.with_capture(capture),
+            None => Ok(self),
+        }
+    }
+
+    /// Gets the [TransactionJournal] recording credits and payouts, if set.
+    pub fn journal(&self) -> Option<&TransactionJournal> {
+        self.journal.as_ref()
+    }
+
+    /// Builder function that sets the [TransactionJournal] recording credits and payouts.
+    ///
+    /// Credits are recorded by the background polling routine, see [journal](crate::journal).
+    pub fn with_journal(mut self, journal: TransactionJournal) -> Self {
+        log::info!(
+            "Transaction journal {}: {:?}",
+            journal.path(),
+            journal.totals()
+        );
+
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Builder function that opens the [TransactionJournal] set in the
+    /// [JOURNAL_ENV_PATH](crate::journal::JOURNAL_ENV_PATH) environment variable, if set.
+    pub fn with_env_journal(self) -> Result<Self> {
+        match TransactionJournal::from_env()? {
+            Some(journal) => Ok(self.with_journal(journal)),
+            None => Ok(self),
+        }
+    }
+
+    /// Gets the [CashLevels] estimating the cashbox, and recycler contents, if set.
+    pub fn cash_levels(&self) -> Option<&CashLevels> {
+        self.cash_levels.as_ref()
+    }
+
+    /// Builder function that sets the [CashLevels] estimating the cashbox, and recycler contents.
+    ///
+    /// Credits are recorded by the background polling routine, payouts and empties by the
+    /// commands, see [cash_levels](crate::cash_levels).
+    pub fn with_cash_levels(mut self, cash_levels: CashLevels) -> Self {
+        log::info!(
+            "Cash levels {}: {:?}",
+            cash_levels.path(),
+            cash_levels.estimate()
+        );
+
+        // raise alerts for levels loaded past their thresholds
+        if let Err(err) = cash_levels.check_watermarks() {
+            log::error!("Failed to record cash level alerts: {err}");
+        }
+
+        self.cash_levels = Some(cash_levels);
+        self
+    }
+
+    /// Builder function that opens the [CashLevels] set in the
+    /// [CASH_LEVELS_ENV_PATH](crate::cash_levels::CASH_LEVELS_ENV_PATH) environment variable, if
+    /// set.
+    pub fn with_env_cash_levels(self) -> Result<Self> {
+        match CashLevels::from_env()? {
+            Some(cash_levels) => Ok(self.with_cash_levels(cash_levels)),
+            None => Ok(self),
+        }
+    }
+
+    /// Compares the transaction journal with the device `counters`, and the recycler levels of
+    /// the [CashLevels], if set, see [reconcile](crate::reconcile).
+    ///
+    /// Returns an error if no transaction journal is set.
+    pub fn reconcile(&self, counters: Option<&DeviceCounters>) -> Result<ReconciliationReport> {
+        let journal = self.journal.as_ref().ok_or(ssp::Error::Io(
+            "transaction journal is not configured".into(),
+        ))?;
+
+        let mut report = crate::reconcile::reconcile(journal, counters, self.cash_levels.as_ref())?;
+        report.serial_number = self.device_serial_number();
+
+        if !report.is_balanced() {
+            log::warn!("Reconciliation found discrepancies: {report}");
+        }
+
+        Ok(report)
+    }
+
+    /// Gets the [EventLog] assigning sequence numbers to device events, if set.
+    pub fn event_log(&self) -> Option<&EventLog> {
+        self.event_log.as_ref()
+    }
+
+    /// Builder function that sets the [EventLog] assigning sequence numbers to device events.
+    ///
+    /// Events are logged as they are pushed to the queue returned from
+    /// [start_background_polling_with_queue](Self::start_background_polling_with_queue), see
+    /// [event_log](crate::event_log).
+    pub fn with_event_log(mut self, event_log: EventLog) -> Self {
+        log::info!("Event log keeping {} events", event_log.capacity());
+
+        self.event_log = Some(event_log);
+        self
+    }
+
+    /// Builder function that creates an [EventLog] with the capacity set in the
+    /// [EVENT_LOG_ENV_CAPACITY](crate::event_log::EVENT_LOG_ENV_CAPACITY) environment variable,
+    /// if set.
+    pub fn with_env_event_log(self) -> Result<Self> {
+        match EventLog::from_env()? {
+            Some(event_log) => Ok(self.with_event_log(event_log)),
+            None => Ok(self),
+        }
+    }
+
+    /// Gets the [RejectHistory] recording rejected notes, and reject codes, if set.
+    pub fn reject_history(&self) -> Option<&RejectHistory> {
+        self.rejects.as_ref()
+    }
+
+    /// Builder function that sets the [RejectHistory] recording rejected notes, and reject codes.
+    ///
+    /// Reject events are recorded by the background polling routine, reject codes by
+    /// [last_reject_code](Self::last_reject_code), see [reject_history](crate::reject_history).
+    pub fn with_reject_history(mut self, rejects: RejectHistory) -> Self {
+        log::info!("Reject history {}", rejects.path());
+
+        self.rejects = Some(rejects);
+        self
+    }
+
+    /// Builder function that opens the [RejectHistory] set in the
+    /// [REJECT_HISTORY_ENV_PATH](crate::reject_history::REJECT_HISTORY_ENV_PATH) environment
+    /// variable, if set.
+    pub fn with_env_reject_history(self) -> Result<Self> {
+        match RejectHistory::from_env()? {
+            Some(rejects) => Ok(self.with_reject_history(rejects)),
+            None => Ok(self),
+        }
+    }
+
+    /// Gets the [PayoutIntentLog] recording payouts before they are sent to the device, if set.
+    pub fn payout_intents(&self) -> Option<&PayoutIntentLog> {
+        self.payout_intents.as_ref()
+    }
+
+    /// Builder function that sets the [PayoutIntentLog] recording payouts before they are sent
+    /// to the device.
+    ///
+    /// Unresolved intents left by a previous run are logged as warnings, see
+    /// [payout_intent](crate::payout_intent).
+    pub fn with_payout_intents(mut self, payout_intents: PayoutIntentLog) -> Self {
+        log::info!("Payout intent log {}", payout_intents.path());
+
+        for intent in payout_intents.unresolved() {
+            log::warn!("Unresolved {intent}, check the device before paying out again");
+        }
+
+        self.payout_intents = Some(payout_intents);
+        self
+    }
+
+    /// Builder function that opens the [PayoutIntentLog] set in the
+    /// [PAYOUT_INTENT_ENV_PATH](crate::payout_intent::PAYOUT_INTENT_ENV_PATH) environment
+    /// variable, if set.
+    pub fn with_env_payout_intents(self) -> Result<Self> {
+        match PayoutIntentLog::from_env()? {
+            Some(payout_intents) => Ok(self.with_payout_intents(payout_intents)),
+            None => Ok(self),
+        }
+    }
+
+    /// Gets the [DeviceRegistry] recording the devices the handle has talked to, if set.
+    pub fn device_registry(&self) -> Option<&DeviceRegistry> {
+        self.registry.as_ref()
+    }
+
+    /// Builder function that sets the [DeviceRegistry] recording the devices the handle has
+    /// talked to.
+    ///
+    /// Devices are registered by [enable_device](Self::enable_device), and marked as seen by the
+    /// background polling routine, see [registry](crate::registry).
+    pub fn with_device_registry(mut self, registry: DeviceRegistry) -> Self {
+        log::info!("Device registry {}", registry.path());
+
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Builder function that opens the [DeviceRegistry] set in the
+    /// [DEVICE_REGISTRY_ENV_PATH](crate::registry::DEVICE_REGISTRY_ENV_PATH) environment
+    /// variable, if set.
+    pub fn with_env_device_registry(self) -> Result<Self> {
+        match DeviceRegistry::from_env()? {
+            Some(registry) => Ok(self.with_device_registry(registry)),
+            None => Ok(self),
+        }
+    }
+
+    // Records the identity of the device in the registry, if set.
+    fn register_device(
+        &self,
+        serial_port: &mut dyn Transport,
+        key: Option<&ssp::AesKey>,
+        setup: &ssp::SetupRequestResponse,
+    ) {
+        let Some(registry) = self.registry.as_ref() else {
+            return;
+        };
+
+        let dataset = match self
+            .dataset_version_inner(serial_port, key)
+            .and_then(|res| res.dataset_version().map(String::from))
+        {
+            Ok(dataset) => dataset,
+            Err(err) => {
+                log::warn!("Failed to get the dataset version for the device registry: {err}");
+                String::new()
+            }
+        };
+
+        if let Err(err) = registry.register(
+            self.device_serial_number(),
+            setup.unit_type().to_string().as_str(),
+            setup.firmware_version().to_string().as_str(),
+            dataset.as_str(),
+            self.port_path(),
+        ) {
+            log::error!("Failed to register device: {err}");
+        }
+    }
+
+    /// Gets the [SqliteEventStore](crate::sqlite::SqliteEventStore) recording command outcomes,
+    /// if set.
+    #[cfg(feature = "sqlite")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "sqlite")))]
+    pub fn event_store(&self) -> Option<&crate::sqlite::SqliteEventStore> {
+        self.event_store.as_ref()
+    }
+
+    /// Builder function that sets the [SqliteEventStore](crate::sqlite::SqliteEventStore)
+    /// recording the outcome of commands handled by [on_message](Self::on_message), and
+    /// [on_request](Self::on_request).
+    ///
+    /// Device events are recorded by adding the store as an [EventSink](crate::sink::EventSink).
+    #[cfg(feature = "sqlite")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "sqlite")))]
+    pub fn with_event_store(mut self, store: crate::sqlite::SqliteEventStore) -> Self {
+        self.event_store = Some(store);
+        self
+    }
+
+    /// Builder function that opens the [SqliteEventStore](crate::sqlite::SqliteEventStore) set
+    /// in the [SQLITE_ENV_PATH](crate::sqlite::SQLITE_ENV_PATH) environment variable, if set.
+    #[cfg(feature = "sqlite")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "sqlite")))]
+    pub fn with_env_event_store(self) -> Result<Self> {
+        match crate::sqlite::SqliteEventStore::from_env()? {
+            Some(store) => Ok(self.with_event_store(store)),
+            None => Ok(self),
+        }
+    }
+
+    /// Gets the [JsonLogSink](crate::json_log::JsonLogSink) writing command outcomes, if set.
+    #[cfg(feature = "json-log")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "json-log")))]
+    pub fn json_log(&self) -> Option<&crate::json_log::JsonLogSink> {
+        self.json_log.as_ref()
+    }
+
+    /// Builder function that sets the [JsonLogSink](crate::json_log::JsonLogSink) writing the
+    /// outcome of commands handled by [on_message](Self::on_message), and
+    /// [on_request](Self::on_request).
+    ///
+    /// Device events are written by adding the sink as an [EventSink](crate::sink::EventSink).
+    #[cfg(feature = "json-log")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "json-log")))]
+    pub fn with_json_log(mut self, json_log: crate::json_log::JsonLogSink) -> Self {
+        match self.device_serial_number() {
+            0 => (),
+            serial_number => json_log.set_serial_number(serial_number),
+        }
+        self.json_log = Some(json_log);
+        self
+    }
+
+    /// Builder function that opens the [JsonLogSink](crate::json_log::JsonLogSink) set in the
+    /// [JSON_LOG_ENV_PATH](crate::json_log::JSON_LOG_ENV_PATH) environment variable, if set.
+    #[cfg(feature = "json-log")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "json-log")))]
+    pub fn with_env_json_log(self) -> Result<Self> {
+        match crate::json_log::JsonLogSink::from_env()? {
+            Some(json_log) => Ok(self.with_json_log(json_log)),
+            None => Ok(self),
+        }
+    }
+
+    // Records the outcome of a command in the event store, and JSON log, if set.
+    #[cfg(all(feature = "jsonrpc", any(feature = "sqlite", feature = "json-log")))]
+    fn record_command<T>(&self, method: ssp::Method, res: &Result<T>) {
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = self.event_store.as_ref() {
+            if let Err(err) = store.record_command(method, res) {
+                log::error!("Failed to store outcome of {method} command: {err}");
+            }
+        }
+
+        #[cfg(feature = "json-log")]
+        if let Some(json_log) = self.json_log.as_ref() {
+            if let Err(err) = json_log.record_command(method, res) {
+                log::error!("Failed to log outcome of {method} command: {err}");
+            }
+        }
+    }
+
+    // Records a completed payout in the transaction journal, and cash levels, if set.
+    //
+    // The notes were already dispensed, so failing to write the entry does not change the result.
+    fn journal_payout(&self, list: &ssp::PayoutDenominationList, res: &Result<()>) {
+        if res.is_err() {
+            return;
+        }
+
+        for denom in list.iter().filter(|d| d.number() != 0) {
+            if let Some(journal) = self.journal.as_ref() {
+                if let Err(err) = journal.record(
+                    TransactionKind::Payout,
+                    self.device_serial_number(),
+                    denom.value(),
+                    denom.number().into(),
+                    format!("{}", denom.currency()).as_str(),
+                ) {
+                    log::error!("Failed to journal payout of {denom}: {err}");
+                }
+            }
+
+            if let Some(cash_levels) = self.cash_levels.as_ref() {
+                if let Err(err) = cash_levels.record_payout(denom.value(), denom.number().into()) {
+                    log::error!("Failed to update cash levels for payout of {denom}: {err}");
+                }
+            }
+        }
+    }
+
+    // Writes a pending intent before sending a payout `command`, if a [PayoutIntentLog] is set.
+    //
+    // Returns the intent ID, `None` if the command does not pay out. The payout must not be sent
+    // if the intent cannot be written.
+    pub(crate) fn begin_payout_intent(
+        payout_intents: Option<&PayoutIntentLog>,
+        link: &LinkState,
+        command: &dyn CommandOps,
+    ) -> Result<Option<u64>> {
+        let (Some(payout_intents), Some(list)) =
+            (payout_intents, payout_intent::payout_list(command))
+        else {
+            return Ok(None);
+        };
+
+        payout_intents
+            .begin(link.serial_number(), &list)
+            .map(|intent| Some(intent.id))
+            .map_err(|err| ssp::Error::Io(format!("failed to write payout intent: {err}")))
+    }
+
+    // Moves the payout `intent`, if any, after the device answered the payout command with `res`.
+    //
+    // Accepted payouts are issued, and refused payouts failed. If the device may, or may not have
+    // received the command, the intent stays pending.
+    //
+    // The payout already went ahead, or failed, so failing to write the state only leaves the
+    // intent unresolved.
+    pub(crate) fn mark_payout_intent(
+        payout_intents: Option<&PayoutIntentLog>,
+        intent: Option<u64>,
+        res: &Result<ssp::MessageVariant>,
+    ) {
+        let state = match res {
+            Ok(res) if res.as_response().response_status().is_ok() => IntentState::Issued,
+            Ok(_) | Err(ssp::Error::Status(_)) => IntentState::Failed,
+            Err(_) => return,
+        };
+
+        if let (Some(payout_intents), Some(id)) = (payout_intents, intent) {
+            if let Err(err) = payout_intents.mark(id, state) {
+                log::error!("Failed to mark payout intent {id} as {state}: {err}");
+            }
+        }
+    }
+
+    // Gets the estimated recycler contents before an empty, if known.
+    fn estimated_recycler(&self) -> Vec<DenominationLevel> {
+        self.cash_levels
+            .as_ref()
+            .map(|levels| levels.estimate().recycler)
+            .unwrap_or_default()
+    }
+
+    // Records a completed empty of the `recycler` notes in the transaction journal, and cash
+    // levels, if set.
+    fn record_empty<T>(&self, recycler: Vec<DenominationLevel>, res: &Result<T>) {
+        if res.is_err() {
+            return;
+        }
+
+        if let Some(journal) = self.journal.as_ref() {
+            let levels = if recycler.is_empty() {
+                // the emptied notes are unknown, record the empty itself
+                vec![DenominationLevel::new(0, 0)]
+            } else {
+                recycler
+            };
+
+            for level in levels.iter() {
+                if let Err(err) = journal.record(
+                    TransactionKind::Empty,
+                    self.device_serial_number(),
+                    level.value,
+                    level.count,
+                    "",
+                ) {
+                    log::error!("Failed to journal empty of {level:?}: {err}");
+                }
+            }
+        }
+
+        if let Some(cash_levels) = self.cash_levels.as_ref() {
+            if let Err(err) = cash_levels.record_empty() {
+                log::error!("Failed to update cash levels for empty: {err}");
+            }
+        }
+    }
+
+    /// Gets the [KeyRotationPolicy] for the eSSP session key.
+    pub const fn key_rotation(&self) -> &KeyRotationPolicy {
+        &self.key_rotation
+    }
+
+    /// Builder function that sets the [KeyRotationPolicy] for the eSSP session key.
+    pub fn with_key_rotation(mut self, policy: KeyRotationPolicy) -> Self {
+        self.key_rotation = policy;
+        self
+    }
+
+    /// Gets the [FixedKey](ssp::FixedKey) used for key negotiation.
+    pub const fn fixed_key(&self) -> &ssp::FixedKey {
+        &self.fixed_key
+    }
+
+    /// Builder function that sets the [FixedKey](ssp::FixedKey) used for key negotiation.
+    ///
+    /// Devices provisioned with a site-specific fixed key only negotiate with the same key. By
+    /// default, the ITL default key is used.
+    pub fn with_fixed_key(mut self, fixed_key: ssp::FixedKey) -> Self {
+        self.fixed_key = fixed_key;
+        self
+    }
+
+    /// Builder function that sets the [FixedKey](ssp::FixedKey) from the
+    /// [FIXED_KEY_ENV](key_store::FIXED_KEY_ENV) environment variable, if set.
+    pub fn with_env_fixed_key(self) -> Result<Self> {
+        match key_store::fixed_key_from_env()? {
+            Some(fixed_key) => Ok(self.with_fixed_key(fixed_key)),
+            None => Ok(self),
+        }
+    }
+
+    /// Gets whether value-relevant commands are refused without an encryption key.
+    pub fn requires_encryption(&self) -> bool {
+        self.link.requires_encryption()
+    }
+
+    /// Builder function that sets whether value-relevant commands are refused without an
+    /// encryption key.
+    ///
+    /// When set, [value-relevant](crate::encryption::is_value_relevant) commands fail with a
+    /// [KeyNotSet](ssp::ResponseStatus::KeyNotSet) encryption error, instead of being sent in
+    /// plaintext, including commands sent with [submit](Self::submit).
+    pub fn with_require_encryption(self, require: bool) -> Self {
+        self.link.set_require_encryption(require);
+        self
+    }
+
+    /// Gets whether the secure shutdown path is enabled.
+    pub const fn secure_shutdown(&self) -> bool {
+        self.secure_shutdown
+    }
+
+    /// Builder function that sets whether the secure shutdown path is enabled.
+    ///
+    /// When set, [shutdown](Self::shutdown) resets the device's encryption, and wipes the
+    /// in-memory keys, and [reset](Self::reset) wipes the session key. A decommissioned host then
+    /// leaves neither the device, nor its memory holding a live session key.
+    pub fn with_secure_shutdown(mut self, secure: bool) -> Self {
+        self.secure_shutdown = secure;
+        self
+    }
+
+    /// Gets the maximum delay of a background poll after its scheduled tick.
+    pub fn poll_jitter_budget(&self) -> time::Duration {
+        self.poll_schedule.jitter_budget()
+    }
+
+    /// Builder function that sets the maximum delay of a background poll after its scheduled
+    /// tick, see [PollTimer](crate::poll_timer::PollTimer).
+    pub fn with_poll_jitter_budget(self, jitter_budget: time::Duration) -> Self {
+        self.poll_schedule.set_jitter_budget(jitter_budget);
+        self
+    }
+
+    /// Gets the response timeout of a command `class` for this handle.
+    ///
+    /// Unless set from a configuration, the process-wide [timeouts](crate::timeouts) apply.
+    pub fn response_timeout(&self, class: CommandClass) -> time::Duration {
+        self.link.response_timeout(class)
+    }
+
+    /// Gets the escrow [PollMode] of the background polling routine.
+    pub fn poll_mode(&self) -> PollMode {
+        if self.link.interactive() {
+            PollMode::Interactive
+        } else {
+            PollMode::Auto
+        }
+    }
+
+    /// Gets the interval between background polls, if set.
+    ///
+    /// Unless set, the background polling routine uses its default interval.
+    pub fn poll_interval(&self) -> Option<time::Duration> {
+        self.poll_schedule.interval()
+    }
+
+    /// Builder function that sets the interval between background polls.
+    ///
+    /// A zero `interval` is ignored.
+    pub fn with_poll_interval(self, interval: time::Duration) -> Self {
+        self.poll_schedule.set_interval(Some(interval));
+        self
+    }
+
+    /// Gets the [PollSchedule] shared with the background polling routine.
+    ///
+    /// Changes to the schedule apply to a running routine from its next poll on.
+    pub fn poll_schedule(&self) -> &PollSchedule {
+        &self.poll_schedule
+    }
+
+    /// Gets the inhibit bitfields set by [enable_device](Self::enable_device), a set bit enables
+    /// the channel.
+    pub const fn default_inhibits(&self) -> [u8; 2] {
+        self.default_inhibits
+    }
+
+    /// Builder function that sets the inhibit bitfields set by
+    /// [enable_device](Self::enable_device), for channels one to sixteen.
+    ///
+    /// By default, all channels are enabled.
+    pub fn with_default_inhibits(mut self, inhibits: [u8; 2]) -> Self {
+        self.default_inhibits = inhibits;
+        self
+    }
+
+    /// Sets the inhibit bitfields set by [enable_device](Self::enable_device), and sends them to
+    /// the device if its inhibits were already set.
+    pub fn set_default_inhibits(&mut self, inhibits: [u8; 2]) -> Result<()> {
+        self.default_inhibits = inhibits;
+
+        if self
+            .link
+            .known_inhibits()
+            .is_some_and(|known| known != inhibits)
+        {
+            let [low, high] = inhibits;
+            self.set_inhibits(ssp::EnableBitfieldList::from([
+                ssp::EnableBitfield::from(low),
+                ssp::EnableBitfield::from(high),
+            ]))?;
+        }
+
+        Ok(())
+    }
+
+    // Gets the state of the link to the device, shared with its background threads.
+    pub(crate) fn link(&self) -> Arc<LinkState> {
+        Arc::clone(&self.link)
+    }
+
+    /// Gets the type, and sequence ID of the last command sent to the device, if any.
+    pub fn last_command(&self) -> Option<(ssp::MessageType, u8)> {
+        self.link.last_command()
+    }
+
+    /// Converts an `err` returned by a handle function into a structured [Error](crate::Error),
+    /// for the failed `command`.
+    ///
+    /// The sequence ID is only set if the last command sent on the link is the `command`, since
+    /// the background polling routine, and other callers send commands on the same link.
+    pub fn error(&self, command: ssp::MessageType, err: ssp::Error) -> crate::Error {
+        let err = crate::Error::from_ssp(err).with_command(command);
+
+        match self.last_command() {
+            Some((last, sequence_id)) if last == command => err.with_sequence_id(sequence_id),
+            _ => err,
+        }
+    }
+
+    /// Gets the [Clock] measuring the background poll schedule, and the session key age.
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Builder function that sets the [Clock] measuring the background poll schedule, the
+    /// [Hold](ssp::MessageType::Hold) keep-alive, and the session key age.
+    ///
+    /// Set a [MockClock](crate::clock::MockClock) to test the timers deterministically.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Gets the [EncryptionPolicy] used to send commands to the device.
+    pub fn encryption_policy(&self) -> EncryptionPolicy {
+        self.link.encryption_policy()
+    }
+
+    /// Sets the [EncryptionPolicy] used to send commands to the device.
+    pub fn set_encryption_policy(&self, policy: EncryptionPolicy) {
+        self.link.set_encryption_policy(policy);
+    }
+
+    /// Builder function that sets the [EncryptionPolicy] used to send commands to the device.
+    pub fn with_encryption_policy(self, policy: EncryptionPolicy) -> Self {
+        self.set_encryption_policy(policy);
+        self
+    }
+
+    /// Builder function that enables the secure shutdown path if the [SECURE_SHUTDOWN_ENV]
+    /// environment variable is set to `1`, or `true`.
+    pub fn with_env_secure_shutdown(self) -> Self {
+        let secure = std::env::var(SECURE_SHUTDOWN_ENV)
+            .map(|val| matches!(val.trim().to_lowercase().as_str(), "1" | "true"))
+            .unwrap_or(false);
+
+        self.with_secure_shutdown(secure)
+    }
+
+    /// Builder function that sets the [KeyStore] persisting the [FixedKey](ssp::FixedKey).
+    ///
+    /// Loads the stored key, if any, to use for key negotiation. A stored key replaces the key set
+    /// with [with_fixed_key](Self::with_fixed_key), since it is newer. New keys set with
+    /// [set_encryption_key](Self::set_encryption_key) are stored before they are sent to the
+    /// device.
+    pub fn with_key_store<S: KeyStore + 'static>(mut self, store: S) -> Result<Self> {
+        if let Some(fixed_key) = store.load()? {
+            log::info!("Loaded fixed encryption key from {} store", store.name());
+            self.fixed_key = fixed_key;
+        }
+
+        self.key_store = Some(Box::new(store));
+
+        Ok(self)
+    }
+
+    /// Builder function that sets the [EntropySource] used to generate encryption keys.
+    ///
+    /// Regenerates the generator, modulus, and random keys from the new source. New
+    /// [FixedKey](ssp::FixedKey)s sent by [set_encryption_key](Self::set_encryption_key) also come
+    /// from the source.
+    ///
+    /// ```no_run
+    /// use ssp_server::entropy::SeededEntropy;
+    ///
+    /// let _handle = ssp_server::DeviceHandle::new("/dev/ttyUSB0")
+    ///     .unwrap()
+    ///     .with_entropy_source(SeededEntropy::from_data(b"test fixture"));
+    /// ```
+    pub fn with_entropy_source<E: EntropySource + 'static>(mut self, entropy: E) -> Self {
+        log::debug!("Using {} entropy source for key generation", entropy.name());
+
+        self.entropy = Box::new(entropy);
+
+        self.new_generator_key();
+        self.new_modulus_key();
+        self.new_random_key();
+
+        self
+    }
+
+    /// Gets the name of the [EntropySource] used to generate encryption keys.
+    pub fn entropy_source(&self) -> &str {
+        self.entropy.name()
+    }
+
     /// Starts background polling routine to regularly send [PollCommand] messages to the device.
     ///
     /// **Args**
@@ -311,28 +1335,42 @@ impl DeviceHandle {
     /// # }
     /// ```
     pub fn start_background_polling(&self, stop_polling: Arc<AtomicBool>) -> Result<()> {
-        if polling_inited() {
+        if self.link.polling_inited() {
             Err(ssp::Error::PollingReinit)
         } else {
             // Set the global flag to disallow multiple background polling threads.
-            set_polling_inited(true);
+            self.link.set_polling_inited(true);
 
             let serial_port = Arc::clone(&self.serial_port);
+            let link = Arc::clone(&self.link);
             let end_polling = Arc::clone(&stop_polling);
             let key = Arc::clone(&self.key);
-
-            thread::spawn(move || -> Result<()> {
-                let mut now = time::Instant::now();
+            let registry = self.registry.clone();
+            let commands = Arc::clone(&self.commands);
+            let schedule = self.poll_schedule.clone();
+            let default_interval = time::Duration::from_millis(MED_POLLING_MS);
+            let clock = Arc::clone(&self.clock);
+
+            let spawned = self.workers.spawn(WorkerKind::Poll, &self.events, move || {
+                // submitted commands are sent between polls
+                let _scheduled = commands.schedule();
+                let mut timer = schedule
+                    .timer(default_interval)
+                    .with_clock(Arc::clone(&clock));
 
                 while !end_polling.load(Ordering::Relaxed) {
-                    if now.elapsed().as_millis() > MED_POLLING_MS as u128 {
-                        now = time::Instant::now();
-
-                        if resetting() {
+                    if timer.due() {
+                        // escrow decisions are sent before the poll
+                        commands.run_preempting(&serial_port, &link, &key);
+                        timer.tick();
+                        // apply schedule changes, e.g. from a configuration reload
+                        schedule.update(&mut timer, default_interval);
+
+                        if link.resetting() || link.key_negotiating() {
                             continue;
                         }
 
-                        if unsafe_jam() {
+                        if link.unsafe_jam() {
                             log::debug!("Unsafe jam detected, resetting device...");
                             let mut locked_port = continue_on_err!(
                                 Self::lock_serial_port(&serial_port),
@@ -340,12 +1378,18 @@ impl DeviceHandle {
                             );
                             let mut message = ssp::ResetCommand::new();
                             continue_on_err!(
-                                Self::poll_message_variant(&mut locked_port, &mut message),
+                                Self::poll_message_variant(
+                                    locked_port.as_mut(),
+                                    &link,
+                                    &mut message,
+                                    CommandClass::Control
+                                ),
                                 "Failed to reset device"
                             );
                             // Wait for device to reset
-                            thread::sleep(time::Duration::from_secs(15));
-                            set_unsafe_jam(false);
+                            clock.sleep(time::Duration::from_secs(15));
+                            link.set_unsafe_jam(false);
+                            timer.restart();
                             continue;
                         }
 
@@ -353,8 +1397,9 @@ impl DeviceHandle {
                             Self::lock_serial_port(&serial_port),
                             "Failed to lock serial port in background polling routine"
                         );
+                        // copy the key, so the lock is not held during the blocking read
                         let key = continue_on_err!(
-                            Self::lock_encryption_key(&key),
+                            Self::copy_encryption_key(&key),
                             "Failed to lock encryption key in background polling routine"
                         );
 
@@ -362,12 +1407,22 @@ impl DeviceHandle {
 
                         let res = if let Some(key) = key.as_ref() {
                             continue_on_err!(
-                                Self::poll_encrypted_message(&mut locked_port, &mut message, key),
+                                Self::poll_encrypted_message(
+                                    locked_port.as_mut(),
+                                    &link,
+                                    &mut message,
+                                    key
+                                ),
                                 "Failed poll command in background polling routine"
                             )
                         } else {
                             continue_on_err!(
-                                Self::poll_message_variant(&mut locked_port, &mut message),
+                                Self::poll_message_variant(
+                                    locked_port.as_mut(),
+                                    &link,
+                                    &mut message,
+                                    CommandClass::Poll
+                                ),
                                 "Failed poll command in background polling routine"
                             )
                         };
@@ -379,28 +1434,38 @@ impl DeviceHandle {
                                 res.into_poll_response(),
                                 "Failed to convert poll response in background polling routine"
                             );
-                            let last_statuses = poll_res.last_response_statuses();
+                            link.set_last_poll_now();
+
+                            if let Some(registry) = registry.as_ref() {
+                                if let Err(err) = registry.touch(link.serial_number()) {
+                                    log::warn!("Failed to update the device registry: {err}");
+                                }
+                            }
 
-                            log::debug!("Successful poll command, last statuses: {last_statuses}");
+                            log::debug!(
+                                "Successful poll command, last statuses: {}",
+                                poll_res.last_response_statuses()
+                            );
                         } else if status == ssp::ResponseStatus::UnsafeJam {
                             log::error!("Unsafe Jam detected! Please remove the jam from the device. Attempting an automatic device reset...");
-                            set_unsafe_jam(true);
+                            link.set_unsafe_jam(true);
                         } else {
                             log::warn!("Failed poll command, response status: {status}");
                         }
                     }
 
-                    thread::sleep(time::Duration::from_millis(MED_POLLING_MS / 3));
+                    commands.run_until(timer.next_system_tick(), &serial_port, &link, &key);
                 }
 
                 // Now that polling finished, reset the flag to allow another background routine to
                 // start.
-                set_polling_inited(false);
+                link.set_polling_inited(false);
 
                 Ok(())
             });
 
-            set_polling_inited(false);
+            self.link.set_polling_inited(false);
+            spawned?;
 
             Ok(())
         }
@@ -447,35 +1512,66 @@ impl DeviceHandle {
         stop_polling: Arc<AtomicBool>,
         poll_mode: PollMode,
     ) -> Result<PushEventReceiver> {
-        if polling_inited() {
+        if self.link.polling_inited() {
             Err(ssp::Error::PollingReinit)
         } else {
             // Set the global flag to disallow multiple background polling threads.
-            set_polling_inited(true);
+            self.link.set_polling_inited(true);
 
             if poll_mode == PollMode::Interactive {
-                set_interactive(true);
+                self.link.set_interactive(true);
             }
 
             let serial_port = Arc::clone(&self.serial_port);
+            let link = Arc::clone(&self.link);
             let end_polling = Arc::clone(&stop_polling);
-            let key = Arc::clone(&self.key);
+            let shared_key = Arc::clone(&self.key);
+            let stores = EventStores {
+                journal: self.journal.clone(),
+                cash_levels: self.cash_levels.clone(),
+                audit: self.audit.clone(),
+                rejects: self.rejects.clone(),
+                payout_intents: self.payout_intents.clone(),
+            };
+            let registry = self.registry.clone();
+            let commands = Arc::clone(&self.commands);
 
             let (tx, rx) = channel::unbounded();
 
-            thread::spawn(move || -> Result<()> {
-                let mut now = time::Instant::now();
+            // allows the handle to push events outside of the polling routine
+            *self.events.lock() = Some(tx.clone());
+            let events = Arc::clone(&self.events);
+
+            let schedule = self.poll_schedule.clone();
+            let default_interval = time::Duration::from_millis(MIN_POLLING_MS);
+            let clock = Arc::clone(&self.clock);
+
+            let spawned = self.workers.spawn(WorkerKind::Poll, &self.events, move || {
+                // submitted commands are sent between polls
+                let _scheduled = commands.schedule();
+                let mut timer = schedule
+                    .timer(default_interval)
+                    .with_clock(Arc::clone(&clock));
 
                 while !end_polling.load(Ordering::Relaxed) {
-                    if now.elapsed().as_millis() >= MIN_POLLING_MS as u128 {
-                        now = time::Instant::now();
+                    if timer.due() {
+                        // escrow decisions are sent before the poll
+                        commands.run_preempting(&serial_port, &link, &shared_key);
+                        timer.tick();
+                        // apply schedule changes, e.g. from a configuration reload
+                        schedule.update(&mut timer, default_interval);
+
+                        if link.resetting() {
+                            clock.sleep(time::Duration::from_secs(1));
+                            timer.restart();
+                            continue;
+                        }
 
-                        if resetting() {
-                            thread::sleep(time::Duration::from_secs(1));
+                        if link.key_negotiating() {
                             continue;
                         }
 
-                        if unsafe_jam() {
+                        if link.unsafe_jam() {
                             log::debug!("Unsafe jam detected, resetting device...");
                             let mut locked_port = continue_on_err!(
                                 Self::lock_serial_port(&serial_port),
@@ -483,12 +1579,18 @@ impl DeviceHandle {
                             );
                             let mut message = ssp::ResetCommand::new();
                             continue_on_err!(
-                                Self::poll_message_variant(&mut locked_port, &mut message),
+                                Self::poll_message_variant(
+                                    locked_port.as_mut(),
+                                    &link,
+                                    &mut message,
+                                    CommandClass::Control
+                                ),
                                 "Failed to reset device"
                             );
                             // Wait for device to reset
-                            thread::sleep(time::Duration::from_secs(15));
-                            set_unsafe_jam(false);
+                            clock.sleep(time::Duration::from_secs(15));
+                            link.set_unsafe_jam(false);
+                            timer.restart();
                             continue;
                         }
 
@@ -497,12 +1599,13 @@ impl DeviceHandle {
                             "Failed to lock serial port in background polling routine"
                         );
 
+                        // copy the key, so the lock is not held during the blocking read
                         let key = continue_on_err!(
-                            Self::lock_encryption_key(&key),
+                            Self::copy_encryption_key(&shared_key),
                             "Failed to lock encryption key in background polling routine"
                         );
 
-                        if escrowed() {
+                        if link.escrowed() {
                             // Do not automatically poll when device has a bill in escrow,
                             // and the user is in interactive mode.
                             //
@@ -516,18 +1619,39 @@ impl DeviceHandle {
                             let mut message = ssp::HoldCommand::new();
 
                             continue_on_err!(
-                                Self::poll_message(&mut locked_port, &mut message, key.as_ref()),
+                                Self::poll_message(
+                                    locked_port.as_mut(),
+                                    &link,
+                                    &mut message,
+                                    key.as_ref()
+                                ),
                                 "Failed hold command"
                             );
 
-                            thread::sleep(time::Duration::from_millis(MIN_POLLING_MS));
+                            // the device is still responding while holding the note
+                            link.set_last_poll_now();
+
+                            // send the stack, or reject command while the note is held
+                            drop(locked_port);
+                            commands.run_until(
+                                timer.next_system_tick(),
+                                &serial_port,
+                                &link,
+                                &shared_key,
+                            );
 
                             continue;
                         }
 
-                        if dispensing() {
+                        if link.dispensing() {
                             // Do not automatically poll when device is dispensing notes
-                            thread::sleep(time::Duration::from_millis(MIN_POLLING_MS));
+                            drop(locked_port);
+                            commands.run_until(
+                                timer.next_system_tick(),
+                                &serial_port,
+                                &link,
+                                &shared_key,
+                            );
 
                             continue;
                         }
@@ -535,7 +1659,12 @@ impl DeviceHandle {
                         let mut message = ssp::PollCommand::new();
 
                         let res = continue_on_err!(
-                            Self::poll_message(&mut locked_port, &mut message, key.as_ref()),
+                            Self::poll_message(
+                                locked_port.as_mut(),
+                                &link,
+                                &mut message,
+                                key.as_ref()
+                            ),
                             "Failed poll command"
                         );
 
@@ -546,52 +1675,134 @@ impl DeviceHandle {
                                 "Failed to convert poll response in background polling routine"
                             );
 
-                            Self::parse_events(&poll_res, &tx)?;
+                            link.set_last_poll_now();
+
+                            if let Some(registry) = registry.as_ref() {
+                                if let Err(err) = registry.touch(link.serial_number()) {
+                                    log::warn!("Failed to update the device registry: {err}");
+                                }
+                            }
+
+                            Self::parse_events(&link, &poll_res, &tx, &stores)?;
                         } else if status.to_u8() == 0 {
                             log::info!("Device returned a null response: {}", res.as_response());
                             log::trace!("Response data: {:x?}", res.as_response().buf());
                         } else if status == ssp::ResponseStatus::UnsafeJam {
                             log::error!("Unsafe Jam detected! Please remove the jam from the device. Attempting an automatic device reset...");
-                            set_unsafe_jam(true);
+                            link.set_unsafe_jam(true);
                         } else {
                             log::warn!("Failed poll command, response status: {status}");
                         }
                     }
 
-                    thread::sleep(time::Duration::from_millis(MIN_POLLING_MS));
+                    // send submitted commands until the next poll
+                    commands.run_until(
+                        timer.next_system_tick(),
+                        &serial_port,
+                        &link,
+                        &shared_key,
+                    );
                 }
 
                 // Now that polling finished, reset the flag to allow another background routine to
                 // start.
-                set_polling_inited(false);
+                link.set_polling_inited(false);
+
+                // close the push event queue, unless a new polling routine replaced it
+                let mut events = events.lock();
+                if events
+                    .as_ref()
+                    .map(|events| events.same_channel(&tx))
+                    .unwrap_or(false)
+                {
+                    *events = None;
+                }
+
+                Ok(())
+            });
+
+            self.link.set_polling_inited(false);
+            spawned?;
+
+            match self.event_log.clone() {
+                Some(event_log) => Ok(PushEventReceiver::new(self.sequence_events(rx, event_log)?)),
+                None => Ok(PushEventReceiver::new(rx)),
+            }
+        }
+    }
+
+    // Logs the events from `rx` to the `event_log`, and forwards them to the returned queue.
+    //
+    // Stops when all senders are dropped, or the returned queue is dropped.
+    fn sequence_events(
+        &self,
+        rx: channel::Receiver<ssp::Event>,
+        event_log: EventLog,
+    ) -> Result<channel::Receiver<ssp::Event>> {
+        let (tx, sequenced) = channel::unbounded();
+
+        self.workers
+            .spawn(WorkerKind::EventDispatch, &self.events, move || {
+                for event in rx.iter() {
+                    let seq = event_log.record(event.clone());
+                    log::trace!("Logged event #{seq}: {event}");
+
+                    if tx.send(event).is_err() {
+                        break;
+                    }
+                }
+
+                Ok(())
+            })?;
+
+        Ok(sequenced)
+    }
 
-                Ok(())
-            });
+    /// Gets rolling statistics of the recent exchanges with the device, including polls: counts of
+    /// timeouts, CRC errors, and retransmissions, and round-trip percentiles.
+    ///
+    /// See [stats](crate::stats) for the covered window.
+    pub fn stats(&self) -> LinkStats {
+        self.link.stats().stats()
+    }
 
-            set_polling_inited(false);
+    /// Gets the status of the worker threads started by the handle.
+    ///
+    /// Workers are listed until they are joined, or replaced by a restarted worker, so a worker
+    /// stopped by a panic shows up with [panicked](WorkerStatus::panicked) set.
+    pub fn workers(&self) -> Vec<WorkerStatus> {
+        self.workers.statuses()
+    }
 
-            Ok(PushEventReceiver::new(rx))
-        }
+    /// Waits up to `timeout` for the polling, and event dispatch workers to stop, e.g. after
+    /// setting the `stop_polling` flag, and joins them.
+    ///
+    /// The I/O worker runs until the handle is dropped.
+    ///
+    /// Returns `Err(_)` if a worker is still running after the `timeout`.
+    pub fn join_workers(&self, timeout: time::Duration) -> Result<()> {
+        self.workers.join(timeout)
     }
 
     fn poll_resetting(
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
+        link: &LinkState,
         key: Option<&ssp::AesKey>,
         tx: Option<&channel::Sender<ssp::Event>>,
     ) -> Result<()> {
         use std::ops::Sub;
 
-        let reset_time = time::Instant::now().sub(time::Duration::from_secs(reset_time()));
+        let reset_time = time::Instant::now().sub(time::Duration::from_secs(link.reset_time()));
         let mut message = ssp::PollCommand::new();
 
         while (1..RESET_TIMEOUT_SECS).contains(&reset_time.elapsed().as_secs()) {
             let elapsed = reset_time.elapsed().as_secs();
             let res = continue_on_err!(
-                Self::poll_message(serial_port, &mut message, key),
+                Self::poll_message(serial_port, link, &mut message, key),
                 format!("Device is still resetting, elapsed time: {elapsed}")
             );
             if res.as_response().response_status().is_ok() {
-                set_reset_time(0);
+                link.set_reset_time(0);
 
                 if let Some(tx) = tx {
                     continue_on_err!(
@@ -609,6 +1820,55 @@ impl DeviceHandle {
 
     #[cfg(feature = "jsonrpc")]
     pub fn on_message(&mut self, stream: &mut UnixStream) -> Result<ssp::Method> {
+        self.on_message_with_format(stream, &mut WireFormat::Json)
+    }
+
+    /// Message handler for clients that negotiated a [WireFormat].
+    ///
+    /// Clients select a format by sending a `ready <format>` handshake line, e.g. `ready cbor`.
+    /// After the handshake, messages are exchanged in the negotiated format for the rest of the
+    /// connection. The `format` is updated in place when a handshake is received.
+    #[cfg(feature = "jsonrpc")]
+    pub fn on_message_with_format(
+        &mut self,
+        stream: &mut UnixStream,
+        format: &mut WireFormat,
+    ) -> Result<ssp::Method> {
+        self.on_client_message(stream, format, None)
+    }
+
+    /// Message handler for a client identified by `client`, for lease arbitration.
+    ///
+    /// Handles the [lease](crate::lease) methods, and rejects state-changing commands while
+    /// another client holds the lease. Anonymous clients (`None`) can not claim a lease.
+    ///
+    /// Otherwise, the same as [on_message_with_format](Self::on_message_with_format).
+    #[cfg(feature = "jsonrpc")]
+    pub fn on_client_message(
+        &mut self,
+        stream: &mut UnixStream,
+        format: &mut WireFormat,
+        client: Option<ClientId>,
+    ) -> Result<ssp::Method> {
+        match client {
+            Some(id) => crate::audit::with_actor(crate::audit::client_actor(id).as_str(), || {
+                self.on_client_message_inner(stream, format, client)
+            }),
+            None => self.on_client_message_inner(stream, format, client),
+        }
+    }
+
+    #[cfg(feature = "jsonrpc")]
+    fn on_client_message_inner(
+        &mut self,
+        stream: &mut UnixStream,
+        format: &mut WireFormat,
+        client: Option<ClientId>,
+    ) -> Result<ssp::Method> {
+        if format.is_binary() {
+            return self.on_binary_message(stream, *format, client);
+        }
+
         stream.set_nonblocking(true)?;
 
         let mut message_buf = vec![0u8; 1024];
@@ -632,6 +1892,17 @@ impl DeviceHandle {
         }
 
         let message_string = std::str::from_utf8(message_buf[..idx].as_ref()).unwrap_or("");
+
+        if let Some(negotiated) = message_string
+            .trim()
+            .strip_prefix("ready ")
+            .and_then(WireFormat::from_name)
+        {
+            log::debug!("Client negotiated wire format: {negotiated}");
+            *format = negotiated;
+            return Ok(ssp::Method::Enable);
+        }
+
         if message_string.is_empty() || message_string.contains("ready") {
             return Ok(ssp::Method::Enable);
         }
@@ -649,6 +1920,13 @@ impl DeviceHandle {
                 };
 
                 log::debug!("Message: {message:?}");
+
+                if let Some(res) = self.on_lease_request(client, &message) {
+                    WireFormat::Json.write_frame(stream, &res)?;
+                    // neutral method, so the server keeps the connection state
+                    return Ok(ssp::Method::Status);
+                }
+
                 let event = ssp::Event::from(&message);
                 let method = event.method();
                 log::debug!("Message method: {method}");
@@ -656,19 +1934,24 @@ impl DeviceHandle {
                 let jsonrpc_id = message.id().unwrap_or(jsonrpc_id());
                 set_jsonrpc_id(jsonrpc_id);
 
-                match method {
-                    ssp::Method::Accept => self.on_enable(stream, &event)?,
-                    ssp::Method::Stop => self.on_disable(stream, &event)?,
-                    ssp::Method::Enable => self.on_enable_payout(stream, &event)?,
-                    ssp::Method::Disable => self.on_disable_payout(stream, &event)?,
-                    ssp::Method::Reject => self.on_reject(stream, &event)?,
-                    ssp::Method::Stack => self.on_stack(stream, &event)?,
-                    ssp::Method::StackerFull => self.on_stacker_full(stream, &event)?,
-                    ssp::Method::Status => self.on_status(stream, &event)?,
-                    ssp::Method::Reset => self.on_reset(stream, &event)?,
-                    ssp::Method::Dispense => self.on_dispense(stream, &event)?,
-                    _ => return Err(ssp::Error::JsonRpc("unsupported method".into())),
-                }
+                // handlers only write a response on success, so retries never duplicate replies
+                let res = self.with_rekey(|handle| match method {
+                    ssp::Method::Accept => handle.on_enable(stream, &event),
+                    ssp::Method::Stop => handle.on_disable(stream, &event),
+                    ssp::Method::Enable => handle.on_enable_payout(stream, &event),
+                    ssp::Method::Disable => handle.on_disable_payout(stream, &event),
+                    ssp::Method::Reject => handle.on_reject(stream, &event),
+                    ssp::Method::Stack => handle.on_stack(stream, &event),
+                    ssp::Method::StackerFull => handle.on_stacker_full(stream, &event),
+                    ssp::Method::Status => handle.on_status(stream, &event),
+                    ssp::Method::Reset => handle.on_reset(stream, &event),
+                    ssp::Method::Dispense => handle.on_dispense(stream, &event),
+                    _ => Err(ssp::Error::JsonRpc("unsupported method".into())),
+                });
+
+                #[cfg(any(feature = "sqlite", feature = "json-log"))]
+                self.record_command(method, &res);
+                res?;
 
                 return Ok(method);
             }
@@ -677,6 +1960,179 @@ impl DeviceHandle {
         Ok(ssp::Method::Disable)
     }
 
+    #[cfg(feature = "jsonrpc")]
+    fn on_binary_message(
+        &mut self,
+        stream: &mut UnixStream,
+        format: WireFormat,
+        client: Option<ClientId>,
+    ) -> Result<ssp::Method> {
+        stream.set_nonblocking(true)?;
+
+        // check for the start of a frame without blocking
+        let mut first = [0u8; 1];
+        match stream.read(first.as_mut()) {
+            // Client hung up the socket, so let the caller know to shutdown the stream
+            Ok(0) => return Ok(ssp::Method::Shutdown),
+            Ok(_) => (),
+            Err(_) => return Ok(ssp::Method::Enable),
+        }
+
+        // read the rest of the frame using the socket timeouts
+        stream.set_nonblocking(false)?;
+
+        let req: Request = format.read_frame(&mut first.as_ref().chain(&mut *stream))?;
+        log::debug!("Message: {req:?}");
+
+        if let Some(res) = self.on_lease_request(client, &req) {
+            format.write_frame(stream, &res)?;
+            return Ok(ssp::Method::Status);
+        }
+
+        let method = ssp::Event::from(&req).method();
+
+        let res = self.on_request(&req);
+        format.write_frame(stream, &res)?;
+
+        Ok(method)
+    }
+
+    /// Handles a JSON-RPC [Request], and returns the [Response] to send to the client.
+    ///
+    /// Supports the same methods as [on_message](Self::on_message), errors are returned as
+    /// JSON-RPC error responses.
+    ///
+    /// Exposed to help with creating a custom message handler.
+    #[cfg(feature = "jsonrpc")]
+    pub fn on_request(&mut self, req: &Request) -> Response {
+        let event = ssp::Event::from(req);
+
+        let res = self.with_rekey(|handle| handle.dispatch_request(&event));
+        #[cfg(any(feature = "sqlite", feature = "json-log"))]
+        self.record_command(event.method(), &res);
+
+        let res = match res {
+            Ok(res) => res,
+            Err(err) => {
+                log::warn!("Error handling request: {err}");
+                Response::new().with_error(RpcError::from(&err))
+            }
+        };
+
+        match req.id() {
+            Some(id) => res.with_id(id),
+            None => res,
+        }
+    }
+
+    /// Handles a JSON-RPC [Request] from a client identified by `client`, including the
+    /// [lease](crate::lease) methods.
+    ///
+    /// State-changing commands are rejected while another client holds the lease.
+    #[cfg(feature = "jsonrpc")]
+    pub fn on_client_request(&mut self, client: Option<ClientId>, req: &Request) -> Response {
+        match (self.on_lease_request(client, req), client) {
+            (Some(res), _) => res,
+            (None, Some(id)) => {
+                crate::audit::with_actor(crate::audit::client_actor(id).as_str(), || {
+                    self.on_request(req)
+                })
+            }
+            (None, None) => self.on_request(req),
+        }
+    }
+
+    // Handles lease methods, and lease denials.
+    //
+    // Returns `None` if the request should be dispatched to the device.
+    #[cfg(feature = "jsonrpc")]
+    fn on_lease_request(&self, client: Option<ClientId>, req: &Request) -> Option<Response> {
+        let res = match req.method().unwrap_or("") {
+            lease::LEASE_CLAIM_METHOD => client
+                .ok_or(ssp::Error::JsonRpc(
+                    "anonymous clients can not claim a lease".into(),
+                ))
+                .and_then(|client| {
+                    let ttl = req
+                        .params::<serde_json::Value>()
+                        .ok()
+                        .and_then(|p| p.get("ttl_ms").and_then(|t| t.as_u64()))
+                        .map(time::Duration::from_millis);
+
+                    self.leases.claim(client, ttl).map_err(ssp::Error::from)
+                })
+                .map(|lease| Response::new().with_result(LeaseReply::from(&lease))),
+            lease::LEASE_RELEASE_METHOD => client
+                .ok_or(ssp::Error::JsonRpc(
+                    "anonymous clients can not hold a lease".into(),
+                ))
+                .and_then(|client| self.leases.release(client).map_err(ssp::Error::from))
+                .map(|_| Response::new()),
+            _ => match self
+                .leases
+                .authorize_method(client, ssp::Event::from(req).method())
+            {
+                Ok(()) => return None,
+                Err(err) => Err(err.into()),
+            },
+        };
+
+        let res = res.unwrap_or_else(|err| {
+            log::warn!("Lease request denied: {err}");
+            Response::new().with_error(RpcError::from(&err))
+        });
+
+        Some(match req.id() {
+            Some(id) => res.with_id(id),
+            None => res,
+        })
+    }
+
+    #[cfg(feature = "jsonrpc")]
+    fn dispatch_request(&self, event: &ssp::Event) -> Result<Response> {
+        let method = event.method();
+        log::debug!("Message method: {method}");
+
+        let res_event = match method {
+            ssp::Method::Accept => {
+                let enable_event = ssp::EnableEvent::try_from(event)?;
+                self.enable()?;
+                ssp::Event::from(enable_event)
+            }
+            ssp::Method::Stop => {
+                self.disable()?;
+                ssp::Event::from(ssp::DisableEvent::new())
+            }
+            ssp::Method::Enable => {
+                let enable_event = ssp::EnableEvent::try_from(event)?;
+                self.enable_payout()?;
+                ssp::Event::from(enable_event)
+            }
+            ssp::Method::Disable => {
+                self.disable_payout()?;
+                ssp::Event::from(ssp::DisableEvent::new())
+            }
+            ssp::Method::Reject => {
+                self.reject()?;
+                ssp::Event::from(ssp::RejectEvent::new())
+            }
+            ssp::Method::Stack => ssp::Event::from(ssp::StackEvent::from(self.stack()?)),
+            ssp::Method::Status => ssp::Event::from(ssp::StatusEvent::new(self.device_status()?)),
+            ssp::Method::Reset => {
+                self.full_reset()?;
+                ssp::Event::from(ssp::ResetEvent::new())
+            }
+            ssp::Method::Dispense => {
+                let dispense_event = event.payload().as_dispense_event()?;
+                self.dispense(dispense_event.as_inner())?;
+                return Ok(Response::new());
+            }
+            _ => return Err(ssp::Error::JsonRpc("unsupported method".into())),
+        };
+
+        Ok(Response::from(res_event))
+    }
+
     /// Message handler for [Disable](ssp::Event::DisableEvent) events.
     ///
     /// Exposed to help with creating a custom message handler.
@@ -802,31 +2258,7 @@ impl DeviceHandle {
     /// Exposed to help with creating a custom message handler.
     #[cfg(feature = "jsonrpc")]
     pub fn on_status(&self, stream: &mut UnixStream, _event: &ssp::Event) -> Result<()> {
-        let (data, dataset_version) = {
-            let mut serial_port = self.serial_port()?;
-            let key = self.encryption_key()?;
-
-            log::trace!(
-                "full status: {}",
-                self.setup_request_inner(&mut serial_port, key.as_ref())?
-            );
-
-            (
-                self.unit_data_inner(&mut serial_port, key.as_ref())?,
-                Self::dataset_version_inner(&mut serial_port, key.as_ref())?,
-            )
-        };
-
-        let cashbox_attached = cashbox_attached();
-        let status = ssp::DeviceStatus::from(data)
-            .with_dataset_version(dataset_version.dataset_version()?)
-            .with_cashbox_attached(cashbox_attached);
-
-        let event = if cashbox_attached {
-            ssp::StatusEvent::new(status)
-        } else {
-            ssp::StatusEvent::new(status.with_response_status(ssp::ResponseStatus::CashboxRemoved))
-        };
+        let event = ssp::StatusEvent::new(self.device_status()?);
 
         let mut res = Response::from(ssp::Event::from(event));
         res.set_id(jsonrpc_id());
@@ -862,8 +2294,6 @@ impl DeviceHandle {
 
     /// Performs the full reset protocol to restart a device.
     pub fn full_reset(&self) -> Result<()> {
-        use serialport::SerialPort;
-
         self.reset()?;
 
         let now = time::Instant::now();
@@ -873,28 +2303,30 @@ impl DeviceHandle {
 
         let mut serial_port = self.serial_port()?;
         // Clear the serial port to simulate closing and opening the port
-        serial_port.clear(serialport::ClearBuffer::All)?;
+        serial_port.clear()?;
 
         while now.elapsed().as_secs() < RESET_TIMEOUT_SECS {
-            if let Ok(res) = self.sync_inner(&mut serial_port, None) {
+            if let Ok(res) = self.sync_inner(serial_port.as_mut(), None) {
                 if res.response_status().is_ok() {
-                    set_reset_time(0);
+                    self.link.set_reset_time(0);
 
-                    if let Err(err) =
-                        self.enable_device_inner(&mut serial_port, protocol_version(), None)
-                    {
+                    if let Err(err) = self.enable_device_inner(
+                        serial_port.as_mut(),
+                        self.protocol_version(),
+                        None,
+                    ) {
                         log::error!("Error enabling device after reset: {err}");
                     }
 
-                    if interactive() {
+                    if self.link.interactive() {
                         // if the server is running in interactive mode, disable until the client
                         // re-enables the device.
-                        if let Err(err) = self.disable_inner(&mut serial_port, None) {
+                        if let Err(err) = self.disable_inner(serial_port.as_mut(), None) {
                             log::error!("Error disabling device after reset: {err}");
                         }
                     }
 
-                    let poll_res = self.poll_inner(&mut serial_port, None)?;
+                    let poll_res = self.poll_inner(serial_port.as_mut(), None)?;
                     if poll_res.response_status().is_ok() {
                         log::debug!("Successfully reset device");
 
@@ -912,9 +2344,156 @@ impl DeviceHandle {
         Err(ssp::Error::JsonRpc("failed to reset device".into()))
     }
 
+    /// Gets the time elapsed since the last successful background poll.
+    ///
+    /// Returns `None` if background polling has not completed a poll yet.
+    pub fn last_poll_elapsed(&self) -> Option<time::Duration> {
+        self.link.last_poll_elapsed()
+    }
+
+    /// Gets whether the cashbox is attached to the device.
+    pub fn cashbox_attached(&self) -> bool {
+        self.link.cashbox_attached()
+    }
+
+    /// Sets whether the cashbox is attached to the device.
+    ///
+    /// Returns the previous value.
+    pub fn set_cashbox_attached(&self, attached: bool) -> bool {
+        self.link.set_cashbox_attached(attached)
+    }
+
+    /// Gets the host protocol version last set on the device.
+    pub fn protocol_version(&self) -> ssp::ProtocolVersion {
+        self.link.protocol_version()
+    }
+
+    /// Gets the unit type reported by the device, `None` until the first setup request.
+    pub fn unit_type(&self) -> Option<ssp::UnitType> {
+        self.link.unit_type()
+    }
+
+    /// Gets the serial number last reported by the device, zero if not yet known.
+    pub fn device_serial_number(&self) -> u32 {
+        self.link.serial_number()
+    }
+
+    /// Gets the number of times the eSSP sequence count was resynchronized with the device.
+    pub fn sequence_resyncs(&self) -> u64 {
+        self.link.sequence_resyncs()
+    }
+
+    /// Gets the number of decrypted responses rejected as replays, i.e. carrying a sequence count
+    /// behind the host count.
+    pub fn replayed_responses(&self) -> u64 {
+        self.link.replayed_responses()
+    }
+
+    /// Gets the number of encrypted commands the device answered with a plaintext response.
+    pub fn encryption_downgrades(&self) -> u64 {
+        self.link.encryption_downgrades()
+    }
+
+    /// Gets whether the eSSP session is out of sync, and needs a new encryption key.
+    pub fn session_desynced(&self) -> bool {
+        self.link.session_desynced()
+    }
+
+    /// Gets the number of times a new encryption key was negotiated after losing the eSSP
+    /// session.
+    pub fn session_rekeys(&self) -> u64 {
+        self.link.session_rekeys()
+    }
+
     /// Gets whether the device is currently dispensing notes.
     pub fn dispensing(&self) -> bool {
-        dispensing()
+        self.link.dispensing()
+    }
+
+    /// Gets the current [DeviceStatus](ssp::DeviceStatus) by querying the device.
+    ///
+    /// If the cashbox is detached, the response status is set to
+    /// [CashboxRemoved](ssp::ResponseStatus::CashboxRemoved).
+    pub fn device_status(&self) -> Result<ssp::DeviceStatus> {
+        let (data, dataset_version) = {
+            let mut serial_port = self.serial_port()?;
+            let key = self.encryption_key_copy()?;
+
+            log::trace!(
+                "full status: {}",
+                self.setup_request_inner(serial_port.as_mut(), key.as_ref())?
+            );
+
+            (
+                self.unit_data_inner(serial_port.as_mut(), key.as_ref())?,
+                self.dataset_version_inner(serial_port.as_mut(), key.as_ref())?,
+            )
+        };
+
+        let cashbox_attached = self.cashbox_attached();
+        let status = ssp::DeviceStatus::from(data)
+            .with_dataset_version(dataset_version.dataset_version()?)
+            .with_cashbox_attached(cashbox_attached);
+
+        if cashbox_attached {
+            Ok(status)
+        } else {
+            Ok(status.with_response_status(ssp::ResponseStatus::CashboxRemoved))
+        }
+    }
+
+    /// Dispenses notes from the device using a [PayoutDenominationList](ssp::PayoutDenominationList).
+    ///
+    /// The device and payout module are enabled for the duration of the payout, and disabled
+    /// again afterwards.
+    ///
+    /// If a [PayoutIntentLog] is set, the payout intent is synced to disk before the payout
+    /// command is sent, and the payout fails if the intent cannot be written.
+    ///
+    /// If disabling the device fails after a successful payout, the payout is still recorded, and
+    /// the disable error is returned.
+    pub fn dispense(&self, list: &ssp::PayoutDenominationList) -> Result<()> {
+        let mut disabled = Ok(());
+        let res = self.dispense_inner(list, &mut disabled);
+        self.audit(AuditOp::Payout, format!("{list}").as_str(), res)?;
+
+        // the notes were paid out, and recorded, but the device was left enabled
+        disabled
+    }
+
+    fn dispense_inner(
+        &self,
+        list: &ssp::PayoutDenominationList,
+        disabled: &mut Result<()>,
+    ) -> Result<()> {
+        let mut serial_port = self.serial_port()?;
+        let key = self.encryption_key_copy()?;
+        let key = key.as_ref();
+
+        self.enable_inner(serial_port.as_mut(), key)?;
+        self.enable_payout_inner(serial_port.as_mut(), key)?;
+
+        let _dispensing = self.link.dispensing_guard();
+
+        let mut payout = ssp::PayoutByDenominationCommand::new()
+            .with_payout_denominations(list)
+            .with_payout_option(ssp::PayoutOption::PayoutAmount);
+
+        let res = self.payout_by_denomination_inner(serial_port.as_mut(), &mut payout, key);
+
+        // record the payout before the cleanup commands, which can fail after the notes went out
+        self.journal_payout(list, &res);
+
+        *disabled = self
+            .disable_payout_inner(serial_port.as_mut(), key)
+            .and_then(|_| self.disable_inner(serial_port.as_mut(), key))
+            .map(|_| ());
+
+        if let Err(err) = disabled.as_ref() {
+            log::error!("Failed to disable the device after the payout of {list}: {err}");
+        }
+
+        res
     }
 
     /// Message handle for dispense request using a
@@ -936,21 +2515,7 @@ impl DeviceHandle {
         let payout_denom = inner_event.as_inner();
         log::trace!("PayoutByDenomination request: {payout_denom}");
 
-        let mut serial_port = self.serial_port()?;
-        let key_guard = self.encryption_key()?;
-        let key = key_guard.as_ref();
-
-        self.enable_inner(&mut serial_port, key)?;
-        self.enable_payout_inner(&mut serial_port, key)?;
-
-        set_dispensing(true);
-
-        let mut payout =
-            ssp::PayoutByDenominationCommand::new().with_payout_denominations(payout_denom);
-
-        let res = if let Err(err) =
-            self.payout_by_denomination_inner(&mut serial_port, &mut payout, key)
-        {
+        let res = if let Err(err) = self.dispense(payout_denom) {
             Response::new()
                 .with_id(jsonrpc_id())
                 .with_error(RpcError::new().with_message(format!("{err}").as_str()))
@@ -958,11 +2523,6 @@ impl DeviceHandle {
             Response::new().with_id(jsonrpc_id())
         };
 
-        self.disable_payout_inner(&mut serial_port, key)?;
-        self.disable_inner(&mut serial_port, key)?;
-
-        set_dispensing(false);
-
         let res_str = serde_json::to_string(&res)? + "\n";
 
         stream.write_all(res_str.as_bytes())?;
@@ -970,42 +2530,76 @@ impl DeviceHandle {
         Ok(())
     }
 
-    /// Acquires a lock on the serial port used for communication with the acceptor device.
-    pub fn serial_port(&self) -> Result<MutexGuard<'_, TTYPort>> {
-        Self::lock_serial_port(&self.serial_port)
+    /// Acquires a lock on the [Transport] used for communication with the acceptor device.
+    ///
+    /// While a background polling routine runs, waits for the routine to hand over the transport
+    /// between polls, so the caller, and the routine never race for the lock.
+    pub fn serial_port(&self) -> Result<SerialPortGuard<'_>> {
+        self.serial_port_turn(false)
+    }
+
+    // Acquires the transport, with a turn preempting the queued commands, and the next poll.
+    fn preempting_serial_port(&self) -> Result<SerialPortGuard<'_>> {
+        self.serial_port_turn(true)
+    }
+
+    fn serial_port_turn(&self, preempt: bool) -> Result<SerialPortGuard<'_>> {
+        let turn = self
+            .commands
+            .take_turn(time::Duration::from_millis(SERIAL_TIMEOUT_MS), preempt)?;
+
+        Ok(SerialPortGuard {
+            port: Self::lock_serial_port(&self.serial_port)?,
+            _turn: turn,
+        })
     }
 
     pub(crate) fn lock_serial_port(
-        serial_port: &Arc<Mutex<TTYPort>>,
-    ) -> Result<MutexGuard<'_, TTYPort>> {
+        serial_port: &Arc<Mutex<Box<dyn Transport>>>,
+    ) -> Result<MutexGuard<'_, Box<dyn Transport>>> {
         serial_port
             .try_lock_for(time::Duration::from_millis(SERIAL_TIMEOUT_MS))
-            .ok_or(ssp::Error::SerialPort(
-                "timed out locking serial port".into(),
-            ))
+            .ok_or_else(|| ssp::Error::Timeout("locking serial port".into()))
     }
 
     /// Acquires a lock on the AES encryption key.
+    ///
+    /// Do not hold the lock during serial I/O, use
+    /// [encryption_key_copy](Self::encryption_key_copy) instead.
     pub fn encryption_key(&self) -> Result<MutexGuard<'_, Option<ssp::AesKey>>> {
         Self::lock_encryption_key(&self.key)
     }
 
+    /// Gets a copy of the AES encryption key, releasing the lock before returning.
+    ///
+    /// Lock the serial port before copying the key: the key only changes while the serial port
+    /// is locked, so the copy stays current until the serial port is released.
+    pub fn encryption_key_copy(&self) -> Result<Option<ssp::AesKey>> {
+        Self::copy_encryption_key(&self.key)
+    }
+
     pub(crate) fn lock_encryption_key(
         key: &Arc<Mutex<Option<ssp::AesKey>>>,
     ) -> Result<MutexGuard<'_, Option<ssp::AesKey>>> {
         key.try_lock_for(time::Duration::from_millis(LOCK_TIMEOUT_MS))
-            .ok_or(ssp::Error::Io("timed out locking encryption key".into()))
+            .ok_or_else(|| ssp::Error::Timeout("locking encryption key".into()))
+    }
+
+    pub(crate) fn copy_encryption_key(
+        key: &Arc<Mutex<Option<ssp::AesKey>>>,
+    ) -> Result<Option<ssp::AesKey>> {
+        Ok(*Self::lock_encryption_key(key)?)
     }
 
-    /// Creates a new [GeneratorKey](ssp::GeneratorKey) from system entropy.
+    /// Creates a new [GeneratorKey](ssp::GeneratorKey) from the [EntropySource].
     pub fn new_generator_key(&mut self) {
-        self.generator = ssp::GeneratorKey::from_entropy();
+        self.generator = self.entropy.generator_key();
         self.reset_key();
     }
 
-    /// Creates a new [ModulusKey](ssp::ModulusKey) from system entropy.
+    /// Creates a new [ModulusKey](ssp::ModulusKey) from the [EntropySource].
     pub fn new_modulus_key(&mut self) {
-        let mut modulus = ssp::ModulusKey::from_entropy();
+        let mut modulus = self.entropy.modulus_key();
 
         // Modulus key must be smaller than the Generator key
         let gen_inner = self.generator.as_inner();
@@ -1021,9 +2615,9 @@ impl DeviceHandle {
         self.reset_key();
     }
 
-    /// Creates a new [RandomKey](ssp::RandomKey) from system entropy.
+    /// Creates a new [RandomKey](ssp::RandomKey) from the [EntropySource].
     pub fn new_random_key(&mut self) {
-        self.random = ssp::RandomKey::from_entropy();
+        self.random = self.entropy.random_key();
         self.reset_key();
     }
 
@@ -1090,9 +2684,227 @@ impl DeviceHandle {
         self.new_modulus_key();
         self.new_random_key();
 
-        ssp::reset_sequence_count();
+        self.negotiate_keys()
+    }
+
+    /// Negotiates the encryption key for a new eSSP session, retrying on failures.
+    ///
+    /// Runs [set_generator](Self::set_generator), [set_modulus](Self::set_modulus), and
+    /// [request_key_exchange](Self::request_key_exchange) in order. When a step fails, the key
+    /// used by the step is regenerated, and the sequence starts over, up to
+    /// [KEY_NEGOTIATION_ATTEMPTS] times, with exponential backoff between attempts (see
+    /// [backoff](key_negotiation::backoff)). Failed attempts are recorded in the
+    /// [key_negotiation_diagnostic](Self::key_negotiation_diagnostic).
+    ///
+    /// Returns once the AES encryption key is established.
+    pub fn negotiate_keys(&mut self) -> Result<()> {
+        // pause background polling, any in-flight poll finishes before the first negotiation
+        // message acquires the serial port
+        self.link.set_key_negotiating(true);
+        let res = self.negotiate_keys_retry();
+        self.link.set_key_negotiating(false);
+
+        self.audit(AuditOp::KeyNegotiation, "", res)
+    }
+
+    /// Gets the diagnostic of the last key negotiation, if any.
+    ///
+    /// Records every failed attempt, with the key sizes, the failed step, and the device
+    /// response, to help debug units with flaky eSSP firmware.
+    pub fn key_negotiation_diagnostic(&self) -> Option<&KeyNegotiationDiagnostic> {
+        self.key_negotiation.as_ref()
+    }
+
+    fn negotiate_keys_retry(&mut self) -> Result<()> {
+        let mut diagnostic = KeyNegotiationDiagnostic::new();
+        let mut attempt = 0;
+
+        let res = loop {
+            attempt += 1;
+
+            let mut record = KeyNegotiationAttempt::new(attempt, &self.generator, &self.modulus);
+
+            let err = match self.negotiate_keys_attempt(&mut record) {
+                Ok(()) => break Ok(()),
+                Err(err) => err,
+            };
+
+            record.error = format!("{err}");
+
+            if attempt >= KEY_NEGOTIATION_ATTEMPTS {
+                diagnostic.attempts.push(record);
+                log::error!("Key negotiation failed after {attempt} attempts");
+                break Err(err);
+            }
+
+            let backoff = key_negotiation::backoff(attempt);
+            record.backoff_ms = backoff.as_millis() as u64;
+
+            log::warn!(
+                "Key negotiation {record}, retrying in {}ms",
+                record.backoff_ms
+            );
+            diagnostic.attempts.push(record);
+
+            thread::sleep(backoff);
+        };
+
+        diagnostic.succeeded = res.is_ok();
+        if diagnostic.has_failures() {
+            log::warn!("{diagnostic}");
+        }
+        self.key_negotiation = Some(diagnostic);
+
+        res
+    }
+
+    /// Negotiates a new encryption key if the eSSP session is out of sync with the device.
+    ///
+    /// Device sequence counts up to [SEQUENCE_RESYNC_WINDOW] packets ahead are resynchronized
+    /// in-place. Counts behind the host are rejected as replays (see
+    /// [replayed_responses](Self::replayed_responses)). Replays, larger mismatches, and corrupt
+    /// responses mark the session as out of sync, and fail the command with a
+    /// [KeyNotSet](ssp::ResponseStatus::KeyNotSet) error.
+    ///
+    /// Returns `Ok(true)` if a new key was negotiated.
+    pub fn resync_session(&mut self) -> Result<bool> {
+        if self.session_desynced() {
+            log::info!("eSSP session out of sync, negotiating a new key");
+            self.rekey(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet))?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Gets whether the eSSP session key is due for rotation under the [KeyRotationPolicy].
+    ///
+    /// Always `false` without an established session key.
+    pub fn key_rotation_due(&self) -> bool {
+        match self.session_start {
+            Some(start) => self.key_rotation.is_due(
+                self.link.sequence_count().as_inner(),
+                self.clock.now().saturating_duration_since(start),
+            ),
+            None => false,
+        }
+    }
+
+    /// Negotiates a new eSSP session key if the current key is due for rotation.
+    ///
+    /// Returns `Ok(true)` if a new key was negotiated.
+    pub fn rotate_key_if_due(&mut self) -> Result<bool> {
+        if self.key_rotation_due() {
+            log::info!("eSSP session key due for rotation, negotiating a new key");
+            self.renegotiate_key()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Runs a command, and retries it once with a new encryption key if the eSSP session was
+    /// lost.
+    ///
+    /// The session is lost when responses fail to decrypt, or the device replies with
+    /// [KeyNotSet](ssp::ResponseStatus::KeyNotSet) mid-session, e.g. after a power cycle. Commands
+    /// without an established session are not retried.
+    ///
+    /// After negotiating the new key, a [Fail](ssp::Method::Fail) event with the original error
+    /// is sent to the push event queue, so operators know re-keying happened.
+    ///
+    /// Commands answered in plaintext (see [is_encryption_downgrade]) are never retried, only a
+    /// [Fail](ssp::Method::Fail) event is sent. The next [resync_session](Self::resync_session)
+    /// negotiates a new key.
+    ///
+    /// Commands may return [ssp::Error]s, or structured [Error](crate::Error)s.
+    pub fn with_rekey<T, E, F>(&mut self, mut cmd: F) -> std::result::Result<T, E>
+    where
+        E: Borrow<ssp::Error> + From<ssp::Error>,
+        F: FnMut(&Self) -> std::result::Result<T, E>,
+    {
+        match cmd(self) {
+            Err(err) if is_encryption_downgrade(err.borrow()) => {
+                self.send_fail_event(err.borrow().clone());
+                Err(err)
+            }
+            Err(err)
+                if matches!(
+                    err.borrow(),
+                    ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet)
+                ) && self.session_start.is_some() =>
+            {
+                let err = err.borrow().clone();
+                log::warn!("Lost the eSSP session: {err}, negotiating a new key and retrying");
+                self.rekey(err)?;
+                cmd(self)
+            }
+            res => res,
+        }
+    }
+
+    // Negotiates a new key after losing the eSSP session, and notifies about the `cause`.
+    fn rekey(&mut self, cause: ssp::Error) -> Result<()> {
+        self.renegotiate_key()?;
+
+        self.link.add_session_rekey();
+
+        self.send_fail_event(cause);
+
+        Ok(())
+    }
+
+    // Sends a [Fail](ssp::Method::Fail) event with the `cause` to the push event queue.
+    fn send_fail_event(&self, cause: ssp::Error) {
+        if let Some(tx) = self.events.lock().as_ref() {
+            let event = ssp::Event::new(ssp::Method::Fail, ssp::EventPayload::Error(cause));
+            if let Err(err) = tx.send(event) {
+                log::warn!("Failed to send fail event: {err}");
+            }
+        }
+    }
+
+    // Runs one key negotiation, recording the device response of the last step in `record`.
+    fn negotiate_keys_attempt(&mut self, record: &mut KeyNegotiationAttempt) -> Result<()> {
+        // the negotiation messages are sent in clear-text, and start a new packet count
+        self.session_start = None;
+        self.reset_key();
+        self.link.reset_sequence_count();
+
+        let res = self.sync();
+        record.record_response(KeyNegotiationStep::Sync, &res);
+        Self::status_res(&res?)?;
+
+        let res = self.set_generator();
+        record.record_response(KeyNegotiationStep::SetGenerator, &res);
+        if let Err(err) = res.and_then(|res| Self::status_res(&res)) {
+            self.new_generator_key();
+            return Err(err);
+        }
+
+        let res = self.set_modulus();
+        record.record_response(KeyNegotiationStep::SetModulus, &res);
+        if let Err(err) = res.and_then(|res| Self::status_res(&res)) {
+            self.new_modulus_key();
+            return Err(err);
+        }
+
+        let res = self.request_key_exchange();
+        record.record_response(KeyNegotiationStep::RequestKeyExchange, &res);
+        if let Err(err) = res.and_then(|res| Self::status_res(&res)) {
+            self.new_random_key();
+            return Err(err);
+        }
+
+        record.step = KeyNegotiationStep::SetKey;
 
-        self.negotiate_key()
+        if self.encryption_key()?.is_some() {
+            self.link.set_session_desynced(false);
+            self.session_start = Some(self.clock.now());
+            Ok(())
+        } else {
+            Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet))
+        }
     }
 
     /// Sends a command to stack a bill in escrow.
@@ -1100,12 +2912,17 @@ impl DeviceHandle {
         let mut serial_port = self.serial_port()?;
 
         let mut message = ssp::PollCommand::new();
-        let res = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let res = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         let status = res.as_response().response_status();
         if status.is_ok() {
-            set_escrowed(false);
-            Ok(set_escrowed_amount(ssp::ChannelValue::default()))
+            self.link.set_escrowed(false);
+            Ok(self.link.set_escrowed_amount(ssp::ChannelValue::default()))
         } else {
             Err(ssp::Error::InvalidStatus((status, ssp::ResponseStatus::Ok)))
         }
@@ -1117,26 +2934,63 @@ impl DeviceHandle {
     ///
     /// The caller should wait a reasonable amount of time for the device
     /// to come back online before sending additional messages.
+    ///
+    /// Skipped if the inhibits are already known to be set, see
+    /// [force_set_inhibits](Self::force_set_inhibits).
     pub fn set_inhibits(
         &self,
         enable_list: ssp::EnableBitfieldList,
     ) -> Result<ssp::SetInhibitsResponse> {
-        let mut serial_port = self.serial_port()?;
-        self.set_inhibits_inner(&mut serial_port, enable_list, encryption_key!(self))
+        let inhibits: Vec<u8> = enable_list.iter().map(|&b| u8::from(b)).collect();
+
+        if self.link.known_inhibits().as_ref() == Some(&inhibits) {
+            log::debug!("Inhibits already set, skipping the SetInhibits command");
+            return Ok(coalesced(ssp::SetInhibitsResponse::new()));
+        }
+
+        self.force_set_inhibits(enable_list)
+    }
+
+    /// Send a [SetInhibitsCommand](ssp::SetInhibitsCommand) message to the device, even if the
+    /// inhibits are already known to be set.
+    pub fn force_set_inhibits(
+        &self,
+        enable_list: ssp::EnableBitfieldList,
+    ) -> Result<ssp::SetInhibitsResponse> {
+        let params = format!("{enable_list:?}");
+
+        let res = self.serial_port().and_then(|mut serial_port| {
+            self.set_inhibits_inner(
+                serial_port.as_mut(),
+                enable_list,
+                encryption_key!(self).as_ref(),
+            )
+        });
+
+        self.audit(AuditOp::SetInhibits, params.as_str(), res)
     }
 
     fn set_inhibits_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         enable_list: ssp::EnableBitfieldList,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::SetInhibitsResponse> {
+        let inhibits: Vec<u8> = enable_list.iter().map(|&b| u8::from(b)).collect();
+
         let mut message = ssp::SetInhibitsCommand::new();
         message.set_inhibits(enable_list)?;
 
-        let res = Self::poll_message(serial_port, &mut message, key)?;
+        let res = Self::poll_message(serial_port, &self.link, &mut message, key)?
+            .into_set_inhibits_response();
+
+        if res.is_ok() {
+            self.link.set_known_inhibits(inhibits.clone());
+            self.link
+                .update_device_config(|config| config.inhibits = Some(inhibits));
+        }
 
-        res.into_set_inhibits_response()
+        res
     }
 
     /// Send a [ResetCommand](ssp::ResetCommand) message to the device.
@@ -1150,11 +3004,20 @@ impl DeviceHandle {
 
         let mut message = ssp::ResetCommand::new();
 
-        Self::set_message_sequence_flag(&mut message);
+        Self::set_message_sequence_flag(&self.link, &mut message);
+
+        serial_port.write_all(framing::stuff(message.as_bytes())?.as_ref())?;
 
-        serial_port.write_all(message.as_bytes())?;
+        // the device comes back disabled, with the dataset possibly updated
+        self.link.clear_setup_cache();
+        self.link.clear_known_state();
 
-        set_reset_time(
+        // the device drops the eSSP session on reset, so the session key is no longer needed
+        if self.secure_shutdown {
+            Self::wipe_session_key(&self.key)?;
+        }
+
+        self.link.set_reset_time(
             time::SystemTime::now()
                 .duration_since(time::UNIX_EPOCH)?
                 .as_secs(),
@@ -1167,19 +3030,19 @@ impl DeviceHandle {
     pub fn poll(&self) -> Result<ssp::PollResponse> {
         let mut serial_port = self.serial_port()?;
 
-        self.poll_inner(&mut serial_port, encryption_key!(self))
+        self.poll_inner(serial_port.as_mut(), encryption_key!(self).as_ref())
     }
 
     fn poll_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::PollResponse> {
         let mut message = ssp::PollCommand::new();
 
-        Self::set_message_sequence_flag(&mut message);
+        Self::set_message_sequence_flag(&self.link, &mut message);
 
-        let response = Self::poll_message(serial_port, &mut message, key)?;
+        let response = Self::poll_message(serial_port, &self.link, &mut message, key)?;
 
         response.into_poll_response()
     }
@@ -1190,9 +3053,14 @@ impl DeviceHandle {
 
         let mut message = ssp::PollWithAckCommand::new();
 
-        Self::set_message_sequence_flag(&mut message);
+        Self::set_message_sequence_flag(&self.link, &mut message);
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         response.into_poll_with_ack_response()
     }
@@ -1203,30 +3071,43 @@ impl DeviceHandle {
 
         let mut message = ssp::EventAckCommand::new();
 
-        Self::set_message_sequence_flag(&mut message);
+        Self::set_message_sequence_flag(&self.link, &mut message);
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         response.into_event_ack_response()
     }
 
     /// Send a [RejectCommand](ssp::RejectCommand) message to the device.
+    ///
+    /// While a background polling routine runs, the command is sent before the queued commands,
+    /// and the next scheduled poll.
     pub fn reject(&self) -> Result<ssp::RejectResponse> {
-        let mut serial_port = self.serial_port()?;
+        let mut serial_port = self.preempting_serial_port()?;
 
         let mut message = ssp::RejectCommand::new();
 
-        Self::set_message_sequence_flag(&mut message);
+        Self::set_message_sequence_flag(&self.link, &mut message);
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         let res = response.into_reject_response()?;
 
         let status = res.response_status();
 
         if status.is_ok() {
-            set_escrowed(false);
-            set_escrowed_amount(ssp::ChannelValue::default());
+            self.link.set_escrowed(false);
+            self.link.set_escrowed_amount(ssp::ChannelValue::default());
 
             Ok(res)
         } else {
@@ -1238,21 +3119,21 @@ impl DeviceHandle {
     pub fn sync(&self) -> Result<ssp::SyncResponse> {
         let mut serial_port = self.serial_port()?;
 
-        self.sync_inner(&mut serial_port, encryption_key!(self))
+        self.sync_inner(serial_port.as_mut(), encryption_key!(self).as_ref())
     }
 
     fn sync_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::SyncResponse> {
         let mut message = ssp::SyncCommand::new();
 
-        set_sequence_flag(ssp::SequenceFlag::from(1));
+        self.link.set_sequence_flag(ssp::SequenceFlag::from(1));
 
-        let response = Self::poll_message(serial_port, &mut message, key)?;
+        let response = Self::poll_message(serial_port, &self.link, &mut message, key)?;
 
-        set_sequence_flag(ssp::SequenceFlag::from(0));
+        self.link.set_sequence_flag(ssp::SequenceFlag::from(0));
 
         response.into_sync_response()
     }
@@ -1264,23 +3145,30 @@ impl DeviceHandle {
     ) -> Result<ssp::EnableResponse> {
         let mut serial_port = self.serial_port()?;
 
-        self.enable_device_inner(&mut serial_port, protocol_version, encryption_key!(self))
+        self.enable_device_inner(
+            serial_port.as_mut(),
+            protocol_version,
+            encryption_key!(self).as_ref(),
+        )
     }
 
     fn enable_device_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         protocol_version: ssp::ProtocolVersion,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::EnableResponse> {
         self.host_protocol_version_inner(serial_port, protocol_version, key)?;
-        set_protocol_version(protocol_version);
 
         let status = self.setup_request_inner(serial_port, key)?;
         log::trace!("Status: {status}");
 
         let serial = self.serial_number_inner(serial_port, key)?;
-        log::trace!("Serial number: {serial}");
+        if !redact::redaction_policy().serials {
+            log::trace!("Serial number: {serial}");
+        }
+
+        self.register_device(serial_port, key, &status);
 
         let res = self.enable_inner(serial_port, key)?;
 
@@ -1290,9 +3178,10 @@ impl DeviceHandle {
             self.enable_payout_inner(serial_port, key)?;
         }
 
+        let [low, high] = self.default_inhibits;
         let enable_list = ssp::EnableBitfieldList::from([
-            ssp::EnableBitfield::from(0xff),
-            ssp::EnableBitfield::from(0xff),
+            ssp::EnableBitfield::from(low),
+            ssp::EnableBitfield::from(high),
         ]);
 
         self.set_inhibits_inner(serial_port, enable_list, key)?;
@@ -1302,21 +3191,36 @@ impl DeviceHandle {
     }
 
     /// Send a [EnableCommand](ssp::EnableCommand) message to the device.
+    ///
+    /// Skipped if the device is already known to be enabled, see
+    /// [force_enable](Self::force_enable).
     pub fn enable(&self) -> Result<ssp::EnableResponse> {
+        if self.link.known_enabled() == Some(true) {
+            log::debug!("Device already enabled, skipping the Enable command");
+            return Ok(coalesced(ssp::EnableResponse::new()));
+        }
+
+        self.force_enable()
+    }
+
+    /// Send a [EnableCommand](ssp::EnableCommand) message to the device, even if already known
+    /// to be enabled.
+    pub fn force_enable(&self) -> Result<ssp::EnableResponse> {
         let mut serial_port = self.serial_port()?;
-        self.enable_inner(&mut serial_port, encryption_key!(self))
+        self.enable_inner(serial_port.as_mut(), encryption_key!(self).as_ref())
     }
 
     fn enable_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::EnableResponse> {
         let mut message = ssp::EnableCommand::new();
 
-        let response = Self::poll_message(serial_port, &mut message, key)?;
+        let response = Self::poll_message(serial_port, &self.link, &mut message, key)?;
 
-        set_enabled(true);
+        self.link.set_enabled(true);
+        self.link.set_known_enabled(true);
 
         response.into_enable_response()
     }
@@ -1324,40 +3228,55 @@ impl DeviceHandle {
     /// Send a [EnablePayoutCommand](ssp::EnablePayoutCommand) message to the device.
     pub fn enable_payout(&self) -> Result<ssp::EnablePayoutResponse> {
         let mut serial_port = self.serial_port()?;
-        self.enable_payout_inner(&mut serial_port, encryption_key!(self))
+        self.enable_payout_inner(serial_port.as_mut(), encryption_key!(self).as_ref())
     }
 
     fn enable_payout_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::EnablePayoutResponse> {
         let mut message =
             ssp::EnablePayoutCommand::new().with_option(ssp::EnablePayoutOption::from(0b11));
 
-        let response = Self::poll_message(serial_port, &mut message, key)?;
+        let response = Self::poll_message(serial_port, &self.link, &mut message, key)?;
 
-        set_enabled(true);
+        self.link.set_enabled(true);
 
         response.into_enable_payout_response()
     }
 
     /// Send a [DisableCommand](ssp::DisableCommand) message to the device.
+    ///
+    /// Skipped if the device is already known to be disabled, see
+    /// [force_disable](Self::force_disable).
     pub fn disable(&self) -> Result<ssp::DisableResponse> {
+        if self.link.known_enabled() == Some(false) {
+            log::debug!("Device already disabled, skipping the Disable command");
+            return Ok(coalesced(ssp::DisableResponse::new()));
+        }
+
+        self.force_disable()
+    }
+
+    /// Send a [DisableCommand](ssp::DisableCommand) message to the device, even if already known
+    /// to be disabled.
+    pub fn force_disable(&self) -> Result<ssp::DisableResponse> {
         let mut serial_port = self.serial_port()?;
-        self.disable_inner(&mut serial_port, encryption_key!(self))
+        self.disable_inner(serial_port.as_mut(), encryption_key!(self).as_ref())
     }
 
     fn disable_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::DisableResponse> {
         let mut message = ssp::DisableCommand::new();
 
-        let response = Self::poll_message(serial_port, &mut message, key)?;
+        let response = Self::poll_message(serial_port, &self.link, &mut message, key)?;
 
-        set_enabled(false);
+        self.link.set_enabled(false);
+        self.link.set_known_enabled(false);
 
         response.into_disable_response()
     }
@@ -1365,19 +3284,19 @@ impl DeviceHandle {
     /// Send a [DisablePayoutCommand](ssp::DisablePayoutCommand) message to the device.
     pub fn disable_payout(&self) -> Result<ssp::DisablePayoutResponse> {
         let mut serial_port = self.serial_port()?;
-        self.disable_payout_inner(&mut serial_port, encryption_key!(self))
+        self.disable_payout_inner(serial_port.as_mut(), encryption_key!(self).as_ref())
     }
 
     fn disable_payout_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::DisablePayoutResponse> {
         let mut message = ssp::DisablePayoutCommand::new();
 
-        let response = Self::poll_message(serial_port, &mut message, key)?;
+        let response = Self::poll_message(serial_port, &self.link, &mut message, key)?;
 
-        set_enabled(false);
+        self.link.set_enabled(false);
 
         response.into_disable_payout_response()
     }
@@ -1388,7 +3307,12 @@ impl DeviceHandle {
 
         let mut message = ssp::DisplayOffCommand::new();
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         response.into_display_off_response()
     }
@@ -1399,39 +3323,60 @@ impl DeviceHandle {
 
         let mut message = ssp::DisplayOnCommand::new();
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         response.into_display_on_response()
     }
 
     /// Send an [EmptyCommand](ssp::EmptyCommand) message to the device.
     pub fn empty(&self) -> Result<ssp::EmptyResponse> {
+        let recycler = self.estimated_recycler();
+        let res = self.empty_inner();
+        self.record_empty(recycler, &res);
+        self.audit(AuditOp::Empty, "", res)
+    }
+
+    fn empty_inner(&self) -> Result<ssp::EmptyResponse> {
         let mut serial_port = self.serial_port()?;
 
         let mut message = ssp::EmptyCommand::new();
 
-        if let Some(key) = (*self.encryption_key()?).as_ref() {
-            let res = Self::poll_encrypted_message(&mut serial_port, &mut message, key)?;
+        let res = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
-            res.into_empty_response()
-        } else {
-            Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet))
-        }
+        res.into_empty_response()
     }
 
     /// Send an [SmartEmptyCommand](ssp::SmartEmptyCommand) message to the device.
     pub fn smart_empty(&self) -> Result<ssp::SmartEmptyResponse> {
+        let recycler = self.estimated_recycler();
+        let res = self.smart_empty_inner();
+        self.record_empty(recycler, &res);
+        self.audit(AuditOp::SmartEmpty, "", res)
+    }
+
+    fn smart_empty_inner(&self) -> Result<ssp::SmartEmptyResponse> {
         let mut serial_port = self.serial_port()?;
 
         let mut message = ssp::SmartEmptyCommand::new();
 
-        if let Some(key) = self.encryption_key()?.as_ref() {
-            let res = Self::poll_encrypted_message(&mut serial_port, &mut message, key)?;
+        let res = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
-            res.into_smart_empty_response()
-        } else {
-            Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet))
-        }
+        res.into_smart_empty_response()
     }
 
     /// Send an [HostProtocolVersionCommand](ssp::HostProtocolVersionCommand) message to the device.
@@ -1441,39 +3386,58 @@ impl DeviceHandle {
     ) -> Result<ssp::HostProtocolVersionResponse> {
         let mut serial_port = self.serial_port()?;
 
-        self.host_protocol_version_inner(&mut serial_port, protocol_version, encryption_key!(self))
+        self.host_protocol_version_inner(
+            serial_port.as_mut(),
+            protocol_version,
+            encryption_key!(self).as_ref(),
+        )
     }
 
     fn host_protocol_version_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         protocol_version: ssp::ProtocolVersion,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::HostProtocolVersionResponse> {
         let mut message = ssp::HostProtocolVersionCommand::new();
         message.set_version(protocol_version);
 
-        let response = Self::poll_message(serial_port, &mut message, key)?;
+        let response = Self::poll_message(serial_port, &self.link, &mut message, key)?
+            .into_host_protocol_version_response()?;
 
-        response.into_host_protocol_version_response()
+        self.link.set_protocol_version(protocol_version);
+        self.link
+            .update_device_config(|config| config.protocol_version = Some(protocol_version.into()));
+
+        Ok(response)
     }
 
     /// Send a [SerialNumberCommand](ssp::SerialNumberCommand) message to the device.
     pub fn serial_number(&self) -> Result<ssp::SerialNumberResponse> {
         let mut serial_port = self.serial_port()?;
-        self.serial_number_inner(&mut serial_port, encryption_key!(self))
+        self.serial_number_inner(serial_port.as_mut(), encryption_key!(self).as_ref())
     }
 
     fn serial_number_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::SerialNumberResponse> {
         let mut message = ssp::SerialNumberCommand::new();
 
-        let res = Self::poll_message(serial_port, &mut message, key)?;
+        let res = Self::poll_message(serial_port, &self.link, &mut message, key)?
+            .into_serial_number_response()?;
+
+        if res.response_status().is_ok() {
+            self.link.set_serial_number(res.serial_number());
+
+            #[cfg(feature = "json-log")]
+            if let Some(json_log) = self.json_log.as_ref() {
+                json_log.set_serial_number(res.serial_number().as_inner());
+            }
+        }
 
-        res.into_serial_number_response()
+        Ok(res)
     }
 
     /// Send a [SetGeneratorCommand](ssp::SetGeneratorCommand) message to the device.
@@ -1487,7 +3451,7 @@ impl DeviceHandle {
         let mut message = ssp::SetGeneratorCommand::new();
         message.set_generator(self.generator_key());
 
-        let response = Self::poll_message(&mut serial_port, &mut message, None)?;
+        let response = Self::poll_message(serial_port.as_mut(), &self.link, &mut message, None)?;
 
         response.into_set_generator_response()
     }
@@ -1503,7 +3467,7 @@ impl DeviceHandle {
         let mut message = ssp::SetModulusCommand::new();
         message.set_modulus(self.modulus_key());
 
-        let response = Self::poll_message(&mut serial_port, &mut message, None)?;
+        let response = Self::poll_message(serial_port.as_mut(), &self.link, &mut message, None)?;
 
         response.into_set_modulus_response()
     }
@@ -1526,7 +3490,8 @@ impl DeviceHandle {
             );
             message.set_intermediate_key(&inter_key);
 
-            let response = Self::poll_message(&mut serial_port, &mut message, None)?;
+            let response =
+                Self::poll_message(serial_port.as_mut(), &self.link, &mut message, None)?;
 
             response.into_request_key_exchange_response()?
         };
@@ -1544,39 +3509,144 @@ impl DeviceHandle {
     /// If the response is an `Err(_)`, or the response status is not
     /// [RsponseStatus::Ok](ssp::ResponseStatus::Ok), the caller should call
     /// [new_modulus_key](Self::new_modulus_key), and try again.
+    ///
+    /// With a [KeyStore], the new key is stored before sending the command, and the previous key
+    /// is restored if the command fails.
     pub fn set_encryption_key(&mut self) -> Result<ssp::SetEncryptionKeyResponse> {
+        let res = self.set_encryption_key_inner();
+
+        // never record the key itself
+        let params = match self.key_store.as_ref() {
+            Some(store) => format!("key_store={}", store.name()),
+            None => String::new(),
+        };
+
+        self.audit(AuditOp::SetFixedKey, params.as_str(), res)
+    }
+
+    fn set_encryption_key_inner(&mut self) -> Result<ssp::SetEncryptionKeyResponse> {
         let mut message = ssp::SetEncryptionKeyCommand::new();
 
-        let fixed_key = ssp::FixedKey::from_entropy();
+        let fixed_key = self.entropy.fixed_key();
         message.set_fixed_key(&fixed_key);
 
-        let res = if let Some(key) = encryption_key!(self) {
+        if let Some(store) = self.key_store.as_mut() {
+            store.store(&fixed_key)?;
+        }
+
+        let res = {
             let mut serial_port = self.serial_port()?;
-            Self::poll_encrypted_message(&mut serial_port, &mut message, key)
-        } else {
-            Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet))
+            Self::poll_message(
+                serial_port.as_mut(),
+                &self.link,
+                &mut message,
+                encryption_key!(self).as_ref(),
+            )
         };
 
         match res {
-            Ok(m) => {
+            Ok(m) if m.as_response().response_status().is_ok() => {
                 self.fixed_key = fixed_key;
                 m.into_set_encryption_key_response()
             }
-            Err(err) => Err(err),
+            res => {
+                if let Some(store) = self.key_store.as_mut() {
+                    if let Err(err) = store.store(&self.fixed_key) {
+                        log::error!("Failed to restore the previous fixed key: {err}");
+                    }
+                }
+
+                res.and_then(|m| m.into_set_encryption_key_response())
+            }
+        }
+    }
+
+    /// Shuts down the eSSP session with the device.
+    ///
+    /// With the [secure shutdown](Self::with_secure_shutdown) path enabled, an
+    /// [EncryptionResetCommand](ssp::EncryptionResetCommand) is sent to the device, restoring its
+    /// default fixed key, and all in-memory keys are wiped. The keys are wiped even if the device
+    /// does not answer, and the error is returned afterwards.
+    ///
+    /// Without the secure shutdown path, this is a no-op.
+    pub fn shutdown(&mut self) -> Result<()> {
+        if !self.secure_shutdown {
+            return Ok(());
+        }
+
+        log::info!("Secure shutdown, resetting the device encryption");
+
+        let res = self
+            .encryption_reset()
+            .and_then(|res| Self::status_res(&res));
+
+        match res.as_ref() {
+            Ok(()) => {
+                // the device is back on the default fixed key
+                self.fixed_key = ssp::FixedKey::new();
+                if let Some(store) = self.key_store.as_mut() {
+                    if let Err(err) = store.store(&self.fixed_key) {
+                        log::error!("Failed to store the default fixed key: {err}");
+                    }
+                }
+            }
+            Err(err) => log::error!("Failed to reset the device encryption: {err}"),
+        }
+
+        let res = self.audit(AuditOp::SetFixedKey, "encryption_reset", res);
+
+        self.wipe_keys()?;
+
+        res
+    }
+
+    /// Wipes the in-memory session key, and replaces the negotiation keys.
+    ///
+    /// The session key is overwritten before it is dropped. A new key must be negotiated before
+    /// sending encrypted commands.
+    pub fn wipe_keys(&mut self) -> Result<()> {
+        Self::wipe_session_key(&self.key)?;
+
+        self.new_generator_key();
+        self.new_modulus_key();
+        self.new_random_key();
+
+        self.session_start = None;
+        self.link.reset_sequence_count();
+
+        log::debug!("Wiped in-memory encryption keys");
+
+        Ok(())
+    }
+
+    fn wipe_session_key(key: &Arc<Mutex<Option<ssp::AesKey>>>) -> Result<()> {
+        let mut key = Self::lock_encryption_key(key)?;
+
+        if let Some(key) = key.as_mut() {
+            key.as_mut_slice().fill(0);
+            // keep the compiler from dropping the overwrite of a dead value
+            std::hint::black_box(key);
         }
+
+        key.take();
+
+        Ok(())
     }
 
     /// Send a [EncryptionResetCommand](ssp::EncryptionResetCommand) message to the device.
+    ///
+    /// A refused reset returns a [Status](ssp::Error::Status) error, keeping
+    /// [Encryption](ssp::Error::Encryption) errors for lost sessions, and downgrades.
     pub fn encryption_reset(&mut self) -> Result<ssp::EncryptionResetResponse> {
         let mut serial_port = self.serial_port()?;
 
         let mut message = ssp::EncryptionResetCommand::new();
 
-        let response = Self::poll_message(&mut serial_port, &mut message, None)?;
+        let response = Self::poll_message(serial_port.as_mut(), &self.link, &mut message, None)?;
 
         if response.as_response().response_status() == ssp::ResponseStatus::CommandCannotBeProcessed
         {
-            Err(ssp::Error::Encryption(
+            Err(ssp::Error::Status(
                 ssp::ResponseStatus::CommandCannotBeProcessed,
             ))
         } else {
@@ -1585,21 +3655,30 @@ impl DeviceHandle {
     }
 
     /// Send a [SetupRequestCommand](ssp::SetupRequestCommand) message to the device.
+    ///
+    /// The response is cached until the device resets, or its dataset changes, see
+    /// [clear_setup_cache](Self::clear_setup_cache).
     pub fn setup_request(&self) -> Result<ssp::SetupRequestResponse> {
         let mut serial_port = self.serial_port()?;
-        self.setup_request_inner(&mut serial_port, encryption_key!(self))
+        self.setup_request_inner(serial_port.as_mut(), encryption_key!(self).as_ref())
     }
 
     fn setup_request_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::SetupRequestResponse> {
-        let mut message = ssp::SetupRequestCommand::new();
+        let protocol = self.protocol_version();
 
-        let response = Self::poll_message(serial_port, &mut message, key)?;
+        let res = match self.link.cached_setup_request(protocol) {
+            Some(res) => res,
+            None => {
+                let mut message = ssp::SetupRequestCommand::new();
 
-        let res = response.into_setup_request_response()?;
+                Self::poll_message(serial_port, &self.link, &mut message, key)?
+                    .into_setup_request_response()?
+            }
+        };
 
         // configure global channel values
         let chan_vals = match res.protocol_version()? as u8 {
@@ -1609,6 +3688,10 @@ impl DeviceHandle {
 
         ssp::configure_channels(chan_vals.as_ref())?;
 
+        self.link.set_unit_type(res.unit_type());
+
+        self.link.cache_setup_request(protocol, res);
+
         Ok(res)
     }
 
@@ -1616,17 +3699,17 @@ impl DeviceHandle {
     pub fn unit_data(&self) -> Result<ssp::UnitDataResponse> {
         let mut serial_port = self.serial_port()?;
 
-        self.unit_data_inner(&mut serial_port, encryption_key!(self))
+        self.unit_data_inner(serial_port.as_mut(), encryption_key!(self).as_ref())
     }
 
     fn unit_data_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::UnitDataResponse> {
         let mut message = ssp::UnitDataCommand::new();
 
-        let response = Self::poll_message(serial_port, &mut message, key)?;
+        let response = Self::poll_message(serial_port, &self.link, &mut message, key)?;
 
         response.into_unit_data_response()
     }
@@ -1634,61 +3717,116 @@ impl DeviceHandle {
     pub fn dataset_version(&self) -> Result<ssp::DatasetVersionResponse> {
         let mut serial_port = self.serial_port()?;
 
-        Self::dataset_version_inner(&mut serial_port, encryption_key!(self))
+        self.dataset_version_inner(serial_port.as_mut(), encryption_key!(self).as_ref())
     }
 
     pub fn dataset_version_inner(
-        serial_port: &mut TTYPort,
+        &self,
+        serial_port: &mut dyn Transport,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::DatasetVersionResponse> {
         let mut message = ssp::DatasetVersionCommand::new();
 
-        let response = Self::poll_message(serial_port, &mut message, key)?;
+        let response = Self::poll_message(serial_port, &self.link, &mut message, key)?;
+
+        let res = response.into_dataset_version_response()?;
+
+        if let Ok(version) = res.dataset_version() {
+            if self.link.set_dataset_version(version) {
+                log::info!("Dataset changed to {version}, cleared the cached setup data");
+            }
+        }
 
-        response.into_dataset_version_response()
+        Ok(res)
     }
 
     /// Send a [ChannelValueDataCommand](ssp::ChannelValueDataCommand) message to the device.
+    ///
+    /// The response is cached until the device resets, or its dataset changes, see
+    /// [clear_setup_cache](Self::clear_setup_cache).
     pub fn channel_value_data(&self) -> Result<ssp::ChannelValueDataResponse> {
         let mut serial_port = self.serial_port()?;
 
-        self.channel_value_data_inner(&mut serial_port, encryption_key!(self))
+        self.channel_value_data_inner(serial_port.as_mut(), encryption_key!(self).as_ref())
     }
 
     fn channel_value_data_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::ChannelValueDataResponse> {
-        let mut message = ssp::ChannelValueDataCommand::new();
+        let res = match self.link.cached_channel_value_data() {
+            Some(res) => res,
+            None => {
+                let mut message = ssp::ChannelValueDataCommand::new();
 
-        let response = Self::poll_message(serial_port, &mut message, key)?;
-
-        let res = response.into_channel_value_data_response()?;
+                Self::poll_message(serial_port, &self.link, &mut message, key)?
+                    .into_channel_value_data_response()?
+            }
+        };
 
         ssp::configure_channels(res.channel_values()?.as_ref())?;
 
+        self.link.cache_channel_value_data(res);
+
         Ok(res)
     }
 
+    /// Clears the cached [setup_request](Self::setup_request), and
+    /// [channel_value_data](Self::channel_value_data) responses, so the next calls query the
+    /// device.
+    ///
+    /// The cache is cleared automatically when the device resets, or reports a new dataset
+    /// version.
+    pub fn clear_setup_cache(&self) {
+        self.link.clear_setup_cache();
+    }
+
     /// Send a [LastRejectCodeCommand](ssp::LastRejectCodeCommand) message to the device.
     pub fn last_reject_code(&self) -> Result<ssp::LastRejectCodeResponse> {
         let mut serial_port = self.serial_port()?;
 
         let mut message = ssp::LastRejectCodeCommand::new();
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?
+        .into_last_reject_code_response()?;
+
+        if let Some(rejects) = self.rejects.as_ref() {
+            if let Err(err) =
+                rejects.record_code(self.device_serial_number(), response.reject_code())
+            {
+                log::error!("Failed to record reject code: {err}");
+            }
+        }
+
+        Ok(response)
+    }
 
-        response.into_last_reject_code_response()
+    /// Queries the [RejectReason] of the last note, see [last_reject_code](Self::last_reject_code).
+    pub fn reject_reason(&self) -> Result<RejectReason> {
+        Ok(RejectReason::from(&self.last_reject_code()?))
     }
 
     /// Send a [HoldCommand](ssp::HoldCommand) message to the device.
+    ///
+    /// While a background polling routine runs, the command is sent before the queued commands,
+    /// and the next scheduled poll.
     pub fn hold(&self) -> Result<ssp::HoldResponse> {
-        let mut serial_port = self.serial_port()?;
+        let mut serial_port = self.preempting_serial_port()?;
 
         let mut message = ssp::HoldCommand::new();
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         response.into_hold_response()
     }
@@ -1701,7 +3839,12 @@ impl DeviceHandle {
 
         let mut message = ssp::GetBarcodeReaderConfigurationCommand::new();
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         response.into_get_barcode_reader_configuration_response()
     }
@@ -1712,7 +3855,12 @@ impl DeviceHandle {
 
         let mut message = ssp::GetBarcodeReaderConfigurationCommand::new();
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         Ok(response
             .as_get_barcode_reader_configuration_response()?
@@ -1730,7 +3878,12 @@ impl DeviceHandle {
         let mut message = ssp::SetBarcodeReaderConfigurationCommand::new();
         message.set_configuration(config);
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         response.into_set_barcode_reader_configuration_response()
     }
@@ -1741,7 +3894,12 @@ impl DeviceHandle {
 
         let mut message = ssp::GetBarcodeInhibitCommand::new();
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         response.into_get_barcode_inhibit_response()
     }
@@ -1756,7 +3914,12 @@ impl DeviceHandle {
         let mut message = ssp::SetBarcodeInhibitCommand::new();
         message.set_inhibit(inhibit);
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         response.into_set_barcode_inhibit_response()
     }
@@ -1767,7 +3930,12 @@ impl DeviceHandle {
 
         let mut message = ssp::GetBarcodeDataCommand::new();
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(
+            serial_port.as_mut(),
+            &self.link,
+            &mut message,
+            encryption_key!(self).as_ref(),
+        )?;
 
         response.into_get_barcode_data_response()
     }
@@ -1780,13 +3948,77 @@ impl DeviceHandle {
     ) -> Result<ssp::ConfigureBezelResponse> {
         let mut serial_port = self.serial_port()?;
 
+        self.configure_bezel_inner(
+            serial_port.as_mut(),
+            rgb,
+            storage,
+            encryption_key!(self).as_ref(),
+        )
+    }
+
+    fn configure_bezel_inner(
+        &self,
+        serial_port: &mut dyn Transport,
+        rgb: ssp::RGB,
+        storage: ssp::BezelConfigStorage,
+        key: Option<&ssp::AesKey>,
+    ) -> Result<ssp::ConfigureBezelResponse> {
         let mut message = ssp::ConfigureBezelCommand::new();
         message.set_rgb(rgb);
         message.set_config_storage(storage);
 
-        let response = Self::poll_message(&mut serial_port, &mut message, encryption_key!(self))?;
+        let response = Self::poll_message(serial_port, &self.link, &mut message, key)?
+            .into_configure_bezel_response()?;
+
+        self.link
+            .update_device_config(|config| config.bezel = Some(BezelSnapshot::new(rgb, storage)));
+
+        Ok(response)
+    }
+
+    /// Gets a [DeviceSnapshot] of the configuration applied to the device.
+    ///
+    /// See [snapshot](crate::snapshot) for the tracked configuration.
+    pub fn snapshot(&self) -> DeviceSnapshot {
+        self.link.device_config()
+    }
+
+    /// Reapplies the configuration of a [DeviceSnapshot], e.g. after a device reset.
+    ///
+    /// The configuration is applied in order: protocol version, channel inhibits, and bezel color.
+    /// Bezel colors stored in EEPROM survive a reset, and are skipped. Stops at the first failed
+    /// command.
+    pub fn restore(&self, snapshot: &DeviceSnapshot) -> Result<()> {
+        let mut serial_port = self.serial_port()?;
+
+        self.restore_inner(
+            serial_port.as_mut(),
+            snapshot,
+            encryption_key!(self).as_ref(),
+        )
+    }
+
+    fn restore_inner(
+        &self,
+        serial_port: &mut dyn Transport,
+        snapshot: &DeviceSnapshot,
+        key: Option<&ssp::AesKey>,
+    ) -> Result<()> {
+        if let Some(protocol_version) = snapshot.protocol() {
+            self.host_protocol_version_inner(serial_port, protocol_version, key)?;
+        }
+
+        if let Some(enable_list) = snapshot.inhibit_list() {
+            self.set_inhibits_inner(serial_port, enable_list, key)?;
+        }
+
+        if let Some(bezel) = snapshot.bezel.filter(|b| !b.eeprom) {
+            self.configure_bezel_inner(serial_port, bezel.rgb(), bezel.storage(), key)?;
+        }
+
+        log::info!("Restored device configuration: {snapshot:?}");
 
-        response.into_configure_bezel_response()
+        Ok(())
     }
 
     /// Dispenses notes from the device by sending a [PayoutByDenominationCommand] message.
@@ -1802,29 +4034,46 @@ impl DeviceHandle {
     /// - `Ok(())`
     /// - Err([`Error`](ssp::Error)) if an error occured
     pub fn payout_by_denomination(&self, list: &ssp::PayoutDenominationList) -> Result<()> {
-        let mut serial_port = self.serial_port()?;
         let mut message = ssp::PayoutByDenominationCommand::new()
             .with_payout_denominations(list)
             .with_payout_option(ssp::PayoutOption::PayoutAmount);
 
-        self.payout_by_denomination_inner(&mut serial_port, &mut message, encryption_key!(self))
+        let res = self.serial_port().and_then(|mut serial_port| {
+            self.payout_by_denomination_inner(
+                serial_port.as_mut(),
+                &mut message,
+                encryption_key!(self).as_ref(),
+            )
+        });
+
+        self.journal_payout(list, &res);
+        self.audit(AuditOp::Payout, format!("{list}").as_str(), res)
     }
 
     pub(crate) fn payout_by_denomination_inner(
         &self,
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
         message: &mut ssp::PayoutByDenominationCommand,
         key: Option<&ssp::AesKey>,
     ) -> Result<()> {
         let mut test_cmd = message.with_payout_option(ssp::PayoutOption::TestPayoutAmount);
-        let test_res = Self::poll_message(serial_port, &mut test_cmd, key)?;
+        let test_res = Self::poll_message(serial_port, &self.link, &mut test_cmd, key)?;
 
         log::trace!("Test payout response: {}", test_res.as_response());
         thread::sleep(time::Duration::from_millis(50));
 
         match test_res.as_response().response_status() {
             ssp::ResponseStatus::Ok => {
-                let response = Self::poll_message(serial_port, message, key)?;
+                let payout_intents = self.payout_intents.as_ref();
+                let intent = Self::begin_payout_intent(payout_intents, &self.link, message)?;
+
+                let res = Self::poll_message(serial_port, &self.link, message, key);
+
+                // mark the intent while holding the port, so polling can not observe the payout
+                // first
+                Self::mark_payout_intent(payout_intents, intent, &res);
+
+                let response = res?;
 
                 log::trace!("Payout response: {}", response.as_response());
                 match response.as_response().response_status() {
@@ -1836,19 +4085,37 @@ impl DeviceHandle {
         }
     }
 
-    fn set_message_sequence_flag(message: &mut dyn CommandOps) {
+    fn set_message_sequence_flag(link: &LinkState, message: &mut dyn CommandOps) {
         let mut sequence_id = message.sequence_id();
-        sequence_id.set_flag(sequence_flag());
+        sequence_id.set_flag(link.sequence_flag());
         message.set_sequence_id(sequence_id);
+
+        Self::record_last_command(link, message.message_type(), message);
+    }
+
+    // Records the `command` type, and the sequence ID of its `frame`, for error context.
+    fn record_last_command(link: &LinkState, command: ssp::MessageType, frame: &dyn CommandOps) {
+        if let Some(&sequence_id) = frame.as_bytes().get(ssp::message::index::SEQ_ID) {
+            link.set_last_command(command, sequence_id);
+        }
     }
 
     fn poll_message_variant(
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
+        link: &LinkState,
         message: &mut dyn CommandOps,
+        class: CommandClass,
     ) -> Result<ssp::MessageVariant> {
-        use ssp::message::index;
+        if link.bus_dirty() {
+            Self::recover_bus(serial_port, link)?;
 
-        Self::set_message_sequence_flag(message);
+            // the recovery clears the flag, a Sync is always sent with the flag set
+            if message.message_type() == ssp::MessageType::Synchronisation {
+                link.set_sequence_flag(ssp::SequenceFlag::from(1));
+            }
+        }
+
+        Self::set_message_sequence_flag(link, message);
 
         log::trace!(
             "Message type: {}, SEQID: {}",
@@ -1856,113 +4123,190 @@ impl DeviceHandle {
             message.sequence_id()
         );
 
-        log::trace!("Polled message: {:x?}", message.as_bytes());
+        log::trace!("Polled message: {:x?}", redact::frame(message.as_bytes()));
 
-        let mut attempt = 0;
-        while let Err(_err) = serial_port.write_all(message.as_bytes()) {
-            attempt += 1;
-            log::warn!("Failed to send message, attempt #{attempt}");
+        with_frame_buffers(|buffers| {
+            let timeouts = framing::FrameTimeouts {
+                response: link.response_timeout(class),
+                inter_byte: time::Duration::from_millis(INTER_BYTE_TIMEOUT_MS),
+            };
 
-            thread::sleep(time::Duration::from_millis(MIN_POLLING_MS));
+            // retransmit with the same sequence flag, the device answers a repeated command with
+            // its last response, instead of executing the command again
+            let mut retransmissions = 0;
+            let mut exchange = Exchange::new(class == CommandClass::Poll);
+            let res = loop {
+                let sent = time::Instant::now();
 
-            message.toggle_sequence_id();
-        }
+                if let Err(err) = Self::write_message(serial_port, buffers, message, class) {
+                    link.stats().record(exchange);
+                    return Err(err);
+                }
 
-        // Set the global sequence flag to the opposite value for the next message
-        set_sequence_flag(!message.sequence_id().flag());
+                let res = buffers.read_frame_timeout(serial_port, timeouts);
+                frame_log::log_frame(CaptureDirection::Rx, buffers.frame(), res.as_ref().err());
+                exchange.record_read(&res, sent.elapsed());
 
-        let mut buf = [0u8; ssp::len::MAX_MESSAGE];
+                match res {
+                    Err(err)
+                        if framing::is_retransmittable(&err)
+                            && retransmissions < framing::MAX_RETRANSMISSIONS =>
+                    {
+                        retransmissions += 1;
+                        exchange.record_retry();
+                        log::warn!(
+                            "Error reading response, retransmission #{retransmissions}: {err}"
+                        );
+                    }
+                    res => break res,
+                }
+            };
+
+            // Set the global sequence flag to the opposite value for the next message, also
+            // after giving up on the response
+            link.set_sequence_flag(!message.sequence_id().flag());
+            link.stats().record(exchange);
+
+            res.map_err(|err| {
+                log::warn!("Error reading response: {err}");
+
+                // the rest of the response may still arrive, and the device may, or may not have
+                // executed the command
+                if framing::is_retransmittable(&err) {
+                    link.set_bus_dirty(true);
+                }
 
-        serial_port
-            .read_exact(buf[..index::SEQ_ID].as_mut())
-            .map_err(|err| {
-                log::warn!("Error reading initial response bytes: {err}");
                 err
             })?;
 
-        let stx = buf[index::STX];
-        if stx != ssp::STX {
-            return Err(ssp::Error::InvalidSTX(stx));
-        }
+            log::trace!("Polled response: {:x?}", redact::frame(buffers.frame()));
+
+            framing::parse_response(buffers.frame(), message.message_type())
+        })
+    }
 
-        serial_port.read_exact(buf[index::SEQ_ID..=index::LEN].as_mut())?;
+    // Recovers the bus after a failed exchange: waits for the device to stop sending, then
+    // resynchronizes the sequence flag with a throwaway Sync, so a desynced flag does not make
+    // the device drop, or repeat the next command.
+    //
+    // The bus stays dirty if the Sync fails.
+    fn recover_bus(serial_port: &mut dyn Transport, link: &LinkState) -> Result<()> {
+        link.set_bus_dirty(false);
 
-        let buf_len = buf[index::LEN] as usize;
-        let total = buf_len + ssp::len::METADATA;
-        let mut remaining = index::DATA + buf_len + 2; // data + CRC-16 bytes
+        log::warn!("Recovering the bus after a failed exchange");
 
-        serial_port.read_exact(buf[index::DATA..remaining].as_mut())?;
+        transport::discard_until_quiet(
+            serial_port,
+            time::Duration::from_millis(BUS_QUIET_MS),
+            time::Duration::from_millis(BUS_RECOVERY_TIMEOUT_MS),
+        )?;
+
+        link.set_sequence_flag(ssp::SequenceFlag::from(1));
+
+        Self::poll_message_variant(
+            serial_port,
+            link,
+            &mut ssp::SyncCommand::new(),
+            CommandClass::Control,
+        )
+        .map_err(|err| {
+            log::error!("Failed to resynchronize the bus: {err}");
+            link.set_bus_dirty(true);
+            err
+        })?;
+
+        link.set_sequence_flag(ssp::SequenceFlag::from(0));
+
+        Ok(())
+    }
+
+    // Discards stale input, then writes the `message`, retrying failed writes according to the
+    // retry policy of its `class`.
+    //
+    // Every attempt uses the same sequence flag.
+    fn write_message(
+        serial_port: &mut dyn Transport,
+        buffers: &mut FrameBuffers,
+        message: &mut dyn CommandOps,
+        class: CommandClass,
+    ) -> Result<()> {
+        // a late response to an earlier command would be read as the response to this one
+        if let Err(err) = transport::discard_stale_input(serial_port) {
+            log::warn!("Failed to discard stale input: {err}");
+        }
+
+        let policy = retry::retry_policy(class);
 
-        log::trace!("Polled response: {:x?}", &buf[..remaining]);
+        let mut attempt = 0;
+        loop {
+            let err = match serial_port.write_all(buffers.stuff(message.as_bytes())?) {
+                Ok(()) => break,
+                Err(err) => err,
+            };
+
+            attempt += 1;
 
-        // check for byte stuffing
-        let mut extra = buf[index::DATA..remaining]
-            .iter()
-            .filter(|&c| c == &ssp::STX)
-            .count()
-            / 2;
+            if !policy.should_retry(attempt) {
+                log::error!("Failed to send message after {attempt} attempts: {err}");
 
-        // read extra bytes off the buffer to account for byte stuffing
-        while extra != 0 {
-            log::trace!("Extra bytes: {extra}");
-            if remaining >= ssp::len::MAX_MESSAGE || remaining + extra >= ssp::len::MAX_MESSAGE {
-                return Err(ssp::Error::InvalidLength((
-                    remaining + extra,
-                    ssp::len::MAX_MESSAGE,
+                return Err(ssp::Error::SerialPort(format!(
+                    "failed to send {class} command after {attempt} attempts: {err}"
                 )));
             }
 
-            serial_port.read_exact(buf[remaining..remaining + extra].as_mut())?;
-            extra = buf[remaining..remaining + extra]
-                .iter()
-                .filter(|&c| c == &ssp::STX)
-                .count()
-                / 2;
-            remaining = remaining.saturating_add(extra);
-        }
+            let backoff = policy.backoff(attempt);
+            log::warn!(
+                "Failed to send message, attempt #{attempt}, retrying in {backoff:?}: {err}"
+            );
 
-        // remove any byte stuffing
-        if buf[index::DATA..remaining].contains(&ssp::STX) {
-            log::trace!("Polled response (with stuffing): {:x?}", &buf[..remaining]);
-            ssp::unstuff(buf[index::DATA..remaining].as_mut(), total - index::DATA)?;
+            thread::sleep(backoff);
         }
 
-        ssp::MessageVariant::from_buf(buf[..total].as_ref(), message.message_type())
+        frame_log::log_frame(CaptureDirection::Tx, message.as_bytes(), None);
+
+        Ok(())
     }
 
     fn poll_encrypted_message(
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
+        link: &LinkState,
         message: &mut dyn CommandOps,
         key: &ssp::AesKey,
     ) -> Result<ssp::MessageVariant> {
         let mut enc_cmd = ssp::EncryptedCommand::new();
         enc_cmd.set_message_data(message)?;
 
-        let mut wrapped = enc_cmd.encrypt(key);
-        Self::set_message_sequence_flag(&mut wrapped);
+        let mut wrapped = {
+            // the `ssp` library encrypts with a process-global count, swap in the count of the
+            // link while no other handle can use it
+            let _count_lock = SEQUENCE_COUNT_LOCK.lock();
+
+            ssp::set_sequence_count(link.sequence_count().as_inner());
+            let wrapped = enc_cmd.encrypt(key);
+            link.set_sequence_count(ssp::sequence_count());
+
+            wrapped
+        };
+        Self::set_message_sequence_flag(link, &mut wrapped);
+        // report errors for the wrapped command, instead of the eSSP packet
+        Self::record_last_command(link, message.message_type(), &wrapped);
 
         log::trace!("Encrypted message: {wrapped}");
         log::trace!("Encrypted data: {:x?}", wrapped.data());
 
-        // recalculate the checksum to include a possible change for the sequence flag
-        let is_stuffed = wrapped.is_stuffed();
-
-        // first, remove any byte stuffing
-        if is_stuffed {
+        // remove any byte stuffing, the whole frame is stuffed when it is written
+        if wrapped.is_stuffed() {
             wrapped.unstuff_encrypted_data()?;
         }
 
-        // calculate the new checksum
+        // recalculate the checksum to include a possible change for the sequence flag
         wrapped.calculate_checksum();
 
-        // re-add any byte stuffing
-        if is_stuffed {
-            wrapped.stuff_encrypted_data()?;
-        }
-
-        let response = Self::poll_message_variant(serial_port, &mut wrapped)?;
+        let class = CommandClass::from_message_type(message.command());
+        let response = Self::poll_message_variant(serial_port, link, &mut wrapped, class)?;
 
-        if response.as_response().response_status() == ssp::ResponseStatus::KeyNotSet {
+        let status = response.as_response().response_status();
+        if status == ssp::ResponseStatus::KeyNotSet {
             return Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet));
         }
         log::trace!("Raw response: {:x?}", response.as_response().buf());
@@ -1970,29 +4314,145 @@ impl DeviceHandle {
         let wrapped_res = response.into_wrapped_encrypted_message()?;
         log::trace!("Encrypted response: {:x?}", wrapped_res.buf());
 
+        // a plaintext response to an encrypted command means the device lost the session without
+        // reporting it, or someone is answering in its place, never trust its contents
+        //
+        // `is_encrypted` compares against the STX byte, encrypted packets start with STEX (0x7e)
+        if wrapped_res.data().first() != Some(&ssp::STEXN) {
+            let command = message.command();
+            log::error!(
+                "Device answered encrypted {command} command in plaintext, status: {status}"
+            );
+
+            link.add_encryption_downgrade();
+            link.set_session_desynced(true);
+
+            return Err(ssp::Error::Encryption(status));
+        }
+
         // received an encrypted response, decrypt and process
-        let dec_res = ssp::EncryptedResponse::decrypt(key, wrapped_res);
-        log::trace!("Decrypted response: {dec_res}");
-        log::trace!("Decrypted data: {:x?}", dec_res.message_data());
+        let expected = link.sequence_count();
+        let packet = essp::decrypt(key, wrapped_res.data());
+        if let Ok(packet) = packet.as_ref() {
+            log::trace!(
+                "Decrypted response, count: {}, data: {:x?}",
+                packet.count(),
+                redact::data(packet.data())
+            );
+        }
+
+        let packet = Self::check_sequence_count(link, packet, expected)?;
 
         let mut res = ssp::MessageVariant::new(message.command());
-        res.as_response_mut().set_data(dec_res.message_data())?;
+        res.as_response_mut().set_data(packet.data())?;
         res.as_response_mut().calculate_checksum();
 
         Ok(res)
     }
 
+    // Checks the decrypted response count against the `expected` host count.
+    //
+    // A device count slightly ahead of the host means packets were dropped, e.g. a response lost
+    // on the wire. The response still decrypted correctly, so the host count is resynchronized.
+    //
+    // A device count behind the host means the response was already seen in this session, e.g.
+    // captured and replayed on the wire. Replays are never adopted: the host count is kept, and
+    // the session is re-keyed, so captured responses no longer decrypt.
+    //
+    // Any other mismatch, or a corrupt response, means the session key is no longer usable.
+    fn check_sequence_count(
+        link: &LinkState,
+        packet: Result<essp::Packet>,
+        expected: ssp::SequenceCount,
+    ) -> Result<essp::Packet> {
+        let packet = match packet {
+            Ok(packet) => packet,
+            Err(err) => {
+                log::error!("Invalid decrypted response, error: {err}");
+                link.set_sequence_count(expected);
+                link.set_session_desynced(true);
+
+                return Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet));
+            }
+        };
+
+        let count = ssp::SequenceCount::from_inner(packet.count());
+
+        if count == expected {
+            return Ok(packet);
+        }
+
+        let drift = count.as_inner().wrapping_sub(expected.as_inner());
+        let lag = expected.as_inner().wrapping_sub(count.as_inner());
+
+        if lag < drift {
+            log::error!("Replayed eSSP response rejected, have: {count}, expected: {expected}");
+            link.set_sequence_count(expected);
+            link.add_replayed_response();
+            link.set_session_desynced(true);
+
+            Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet))
+        } else if drift <= SEQUENCE_RESYNC_WINDOW {
+            log::warn!("Resynchronized eSSP sequence count, have: {count}, expected: {expected}");
+            link.set_sequence_count(count);
+            link.add_sequence_resync();
+
+            Ok(packet)
+        } else {
+            log::error!("eSSP sequence count out of sync, have: {count}, expected: {expected}");
+            link.set_sequence_count(expected);
+            link.set_session_desynced(true);
+
+            Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet))
+        }
+    }
+
+    // Sends the `message` according to its [EncryptionMode] in the [EncryptionPolicy].
+    //
+    // Required commands are refused without a `key`, preferred commands are encrypted if a `key`
+    // is set, and plain commands are never encrypted.
     fn poll_message(
-        serial_port: &mut TTYPort,
+        serial_port: &mut dyn Transport,
+        link: &LinkState,
         message: &mut dyn CommandOps,
         key: Option<&ssp::AesKey>,
     ) -> Result<ssp::MessageVariant> {
-        if let Some(key) = key {
-            log::trace!("Polling encrypted message: {:x?}", message.buf());
-            Self::poll_encrypted_message(serial_port, message, key)
+        let command = message.command();
+        let mode = link.encryption_mode(command);
+
+        let key = match (mode, key) {
+            (EncryptionMode::Plain, _) => None,
+            (EncryptionMode::Required, None) => {
+                log::error!(
+                    "Refusing to send {command} command in plaintext, encryption is {mode}"
+                );
+                return Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet));
+            }
+            (_, key) => key,
+        };
+
+        let start = time::Instant::now();
+        let span = CommandSpan::enter(command, key.is_some());
+        let _redacted = RedactedExchange::enter(command);
+
+        let res = if let Some(key) = key {
+            log::trace!(
+                "Polling encrypted message: {:x?}",
+                redact::frame(message.buf())
+            );
+            Self::poll_encrypted_message(serial_port, link, message, key)
         } else {
-            log::trace!("Polling clear-text message: {:x?}", message.buf());
-            Self::poll_message_variant(serial_port, message)
-        }
+            log::trace!(
+                "Polling clear-text message: {:x?}",
+                redact::frame(message.buf())
+            );
+            let class = CommandClass::from_message_type(command);
+            Self::poll_message_variant(serial_port, link, message, class)
+        };
+
+        span.finish(message.sequence_id(), &res);
+        latency::record(command, start.elapsed(), res.is_ok());
+
+        res
     }
 }
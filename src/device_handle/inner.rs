@@ -3,34 +3,55 @@
 use crossbeam::channel;
 use ssp::MessageOps;
 
-use crate::continue_on_err;
-
-use super::{
-    cashbox_attached, set_cashbox_attached, set_escrowed, set_escrowed_amount, set_unsafe_jam,
-    DeviceHandle,
-};
+use crate::audit::{AuditLog, NoteRecord};
+use crate::cash_levels::CashLevels;
+use crate::journal::{TransactionJournal, TransactionKind};
+use crate::payout_intent::PayoutIntentLog;
+use crate::reject_history::RejectHistory;
+use crate::{break_on_err, continue_on_err};
+
+use super::{DeviceHandle, LinkState};
+
+/// Optional stores updated with the events parsed from poll responses.
+#[derive(Clone, Default)]
+pub(crate) struct EventStores {
+    pub journal: Option<TransactionJournal>,
+    pub cash_levels: Option<CashLevels>,
+    pub audit: Option<AuditLog>,
+    pub rejects: Option<RejectHistory>,
+    pub payout_intents: Option<PayoutIntentLog>,
+}
 
 impl DeviceHandle {
     pub(crate) fn parse_events(
+        link: &LinkState,
         poll_res: &ssp::PollResponse,
         tx: &channel::Sender<ssp::Event>,
+        stores: &EventStores,
     ) -> ssp::Result<()> {
+        let journal = stores.journal.as_ref();
+        let cash_levels = stores.cash_levels.as_ref();
+        let audit = stores.audit.as_ref();
+        let rejects = stores.rejects.as_ref();
+        let payout_intents = stores.payout_intents.as_ref();
+
         let data = poll_res.data();
         let data_len = data.len();
         let mut idx = 1;
 
         // Usually, only one event is returned during normal polling.
         //
-        // Just in case multiple events are returned, keep parsing to the end of the data array.
+        // Just in case multiple events are returned, keep parsing to the end of the data array. An
+        // event that fails to parse is truncated, and the events after it cannot be located.
         while idx < data_len {
             let status = ssp::ResponseStatus::from(data[idx]);
 
-            if !(cashbox_attached()
+            if !(link.cashbox_attached()
                 || status == ssp::ResponseStatus::Disabled
                 || status == ssp::ResponseStatus::StackerFull
                 || status == ssp::ResponseStatus::CashboxRemoved)
             {
-                set_cashbox_attached(true);
+                link.set_cashbox_attached(true);
 
                 log::debug!("Cashbox is available: {status}");
 
@@ -42,18 +63,21 @@ impl DeviceHandle {
 
             match status {
                 ssp::ResponseStatus::DeviceReset => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::ResetEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse Reset event"
                     );
                     idx += ssp::ResetEvent::len();
+                    // the dataset may have been updated while resetting
+                    link.clear_setup_cache();
+                    link.clear_known_state();
                     continue_on_err!(
                         tx.send(ssp::Event::from(event)),
                         "Failed to send Reset event"
                     );
                 }
                 ssp::ResponseStatus::Read => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::ReadEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse Read event"
                     );
@@ -63,8 +87,8 @@ impl DeviceHandle {
                     let value = event.value();
                     if value.as_inner() != 0 {
                         // Change the global escrow state
-                        set_escrowed(true);
-                        set_escrowed_amount(value);
+                        link.set_escrowed(true);
+                        link.set_escrowed_amount(value);
 
                         continue_on_err!(
                             tx.send(ssp::Event::from(event)),
@@ -73,15 +97,50 @@ impl DeviceHandle {
                     }
                 }
                 ssp::ResponseStatus::NoteCredit => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::NoteCreditEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse NoteCredit event"
                     );
+                    let channel = data.get(idx + 1).copied().unwrap_or_default();
                     idx += ssp::NoteCreditEvent::len();
 
                     // Bill moved from escrow to storage, modify global escrow state.
-                    set_escrowed(false);
-                    set_escrowed_amount(event.value());
+                    link.set_escrowed(false);
+                    link.set_escrowed_amount(event.value());
+
+                    if let Some(audit) = audit {
+                        let note = NoteRecord::new(
+                            channel,
+                            event.value().as_inner(),
+                            link.protocol_version() as u8,
+                            link.serial_number(),
+                        );
+
+                        if let Err(err) = audit.record_note(&note) {
+                            log::error!("Failed to write audit record for note credit: {err}");
+                        }
+                    }
+
+                    if let Some(journal) = journal {
+                        if let Err(err) = journal.record(
+                            TransactionKind::Credit,
+                            link.serial_number(),
+                            event.value().as_inner(),
+                            1,
+                            "",
+                        ) {
+                            log::error!("Failed to journal credit of {}: {err}", event.value());
+                        }
+                    }
+
+                    if let Some(cash_levels) = cash_levels {
+                        if let Err(err) = cash_levels.record_credit(event.value().as_inner()) {
+                            log::error!(
+                                "Failed to update cash levels for credit of {}: {err}",
+                                event.value()
+                            );
+                        }
+                    }
 
                     continue_on_err!(
                         tx.send(ssp::Event::from(event)),
@@ -89,17 +148,17 @@ impl DeviceHandle {
                     );
                 }
                 ssp::ResponseStatus::CashboxRemoved => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::CashboxRemovedEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse CashboxRemoved event"
                     );
 
                     idx += ssp::CashboxRemovedEvent::len();
 
-                    if cashbox_attached() {
+                    if link.cashbox_attached() {
                         log::debug!("Cashbox is removed");
 
-                        set_cashbox_attached(false);
+                        link.set_cashbox_attached(false);
 
                         continue_on_err!(
                             tx.send(ssp::Event::from(event)),
@@ -108,17 +167,17 @@ impl DeviceHandle {
                     }
                 }
                 ssp::ResponseStatus::CashboxReplaced => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::CashboxReplacedEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse CashboxReplaced event"
                     );
 
                     idx += ssp::CashboxReplacedEvent::len();
 
-                    if !cashbox_attached() {
+                    if !link.cashbox_attached() {
                         log::debug!("Cashbox replaced");
 
-                        set_cashbox_attached(true);
+                        link.set_cashbox_attached(true);
 
                         continue_on_err!(
                             tx.send(ssp::Event::from(event)),
@@ -127,15 +186,16 @@ impl DeviceHandle {
                     }
                 }
                 ssp::ResponseStatus::Disabled => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::DisabledEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse Disabled event"
                     );
                     log::trace!("Device is disabled: {event}");
                     idx += ssp::DisabledEvent::len();
+                    link.set_known_enabled(false);
                 }
                 ssp::ResponseStatus::FraudAttempt => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::FraudAttemptEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse FraudAttempt event"
                     );
@@ -146,7 +206,7 @@ impl DeviceHandle {
                     );
                 }
                 ssp::ResponseStatus::NoteClearedFromFront => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::NoteClearedFromFrontEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse NoteClearedFromFront event"
                     );
@@ -157,18 +217,26 @@ impl DeviceHandle {
                     );
                 }
                 ssp::ResponseStatus::NoteClearedIntoCashbox => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::NoteClearedIntoCashboxEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse NoteClearedIntoCashbox event"
                     );
                     idx += ssp::NoteClearedIntoCashboxEvent::len();
+
+                    if let Some(cash_levels) = cash_levels {
+                        let value = event.value().as_inner();
+                        if let Err(err) = cash_levels.record_cleared_into_cashbox(value) {
+                            log::error!("Failed to update cash levels for cleared note: {err}");
+                        }
+                    }
+
                     continue_on_err!(
                         tx.send(ssp::Event::from(event)),
                         "Failed to send NoteClearedIntoCashbox event"
                     );
                 }
                 ssp::ResponseStatus::Rejected => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::RejectedEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse Rejected event"
                     );
@@ -176,10 +244,16 @@ impl DeviceHandle {
 
                     log::trace!("Received Rejected event: {event}");
 
-                    set_escrowed(false);
+                    if let Some(rejects) = rejects {
+                        if let Err(err) = rejects.record_event(link.serial_number()) {
+                            log::error!("Failed to record Rejected event: {err}");
+                        }
+                    }
+
+                    link.set_escrowed(false);
                 }
                 ssp::ResponseStatus::Rejecting => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::RejectingEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse Rejecting event"
                     );
@@ -187,10 +261,10 @@ impl DeviceHandle {
 
                     log::trace!("Received Rejecting event: {event}");
 
-                    set_escrowed(false);
+                    link.set_escrowed(false);
                 }
                 ssp::ResponseStatus::Stacked => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::StackedEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse Stacked event"
                     );
@@ -198,23 +272,23 @@ impl DeviceHandle {
 
                     log::trace!("Received Stacked event: {event}");
 
-                    set_escrowed(false);
+                    link.set_escrowed(false);
                 }
                 ssp::ResponseStatus::StackerFull => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::StackerFullEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse StackerFull event"
                     );
                     idx += ssp::StackerFullEvent::len();
 
-                    if cashbox_attached() {
+                    if link.cashbox_attached() {
                         // Some firmware/protocol versions seem to send this message for the
                         // cashbox being removed, and the stacker being full. TBD.
                         log::debug!(
                             "Cashbox is unavailable. It was either removed, or the stacker is full"
                         );
 
-                        set_cashbox_attached(false);
+                        link.set_cashbox_attached(false);
 
                         continue_on_err!(
                             tx.send(ssp::Event::from(event)),
@@ -223,7 +297,7 @@ impl DeviceHandle {
                     }
                 }
                 ssp::ResponseStatus::Stacking => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::StackingEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse Stacking event"
                     );
@@ -231,10 +305,10 @@ impl DeviceHandle {
 
                     log::trace!("Received Stacking event: {event}");
 
-                    set_escrowed(false);
+                    link.set_escrowed(false);
                 }
                 ssp::ResponseStatus::UnsafeJam => {
-                    let event = continue_on_err!(
+                    let event = break_on_err!(
                         ssp::UnsafeJamEvent::try_from(data[idx..].as_ref()),
                         "Failed to parse UnsafeJam event"
                     );
@@ -243,14 +317,50 @@ impl DeviceHandle {
                     log::warn!(
                         "Unsafe Jam occurred, please clear the jam from the device, and reset."
                     );
-                    set_escrowed(false);
-                    set_unsafe_jam(true);
+                    link.set_escrowed(false);
+                    link.set_unsafe_jam(true);
 
                     continue_on_err!(
                         tx.send(ssp::Event::from(event)),
                         "Failed to send UnsafeJam event"
                     );
                 }
+                ssp::ResponseStatus::Reserved(DISPENSING) => {
+                    log::trace!("Dispensing notes");
+                    idx += dispense_event_len(link.protocol_version(), data, idx);
+                }
+                ssp::ResponseStatus::Reserved(DISPENSED) => {
+                    log::debug!("Notes dispensed");
+                    idx += dispense_event_len(link.protocol_version(), data, idx);
+
+                    if let Some(payout_intents) = payout_intents {
+                        match payout_intents.complete_dispensed() {
+                            Ok(Some(intent)) => log::debug!("Completed {intent}"),
+                            Ok(None) => log::warn!("Dispensed event without an issued payout"),
+                            Err(err) => log::error!("Failed to complete payout intent: {err}"),
+                        }
+                    }
+                }
+                ssp::ResponseStatus::Reserved(PAYOUT_JAMMED) => {
+                    log::warn!("Payout jammed, please clear the jam from the payout");
+                    idx += dispense_event_len(link.protocol_version(), data, idx);
+
+                    mark_incomplete(payout_intents);
+                }
+                ssp::ResponseStatus::Reserved(INCOMPLETE_PAYOUT) => {
+                    log::warn!("Payout incomplete, not all notes were dispensed");
+                    idx += incomplete_payout_event_len(link.protocol_version(), data, idx);
+
+                    mark_incomplete(payout_intents);
+                }
+                ssp::ResponseStatus::Reserved(EMPTYING | EMPTIED) => {
+                    log::trace!("Emptying the payout: 0x{:02x}", data[idx]);
+                    idx += 1;
+                }
+                ssp::ResponseStatus::Reserved(SMART_EMPTYING | SMART_EMPTIED) => {
+                    log::trace!("Smart emptying the payout: 0x{:02x}", data[idx]);
+                    idx += dispense_event_len(link.protocol_version(), data, idx);
+                }
                 ssp::ResponseStatus::ChannelDisable => {
                     log::trace!("All channels disabled");
                     idx += 1;
@@ -265,3 +375,59 @@ impl DeviceHandle {
         Ok(())
     }
 }
+
+// Marks the issued payout intent as incomplete, after a `Jammed`, or `Incomplete payout` event.
+//
+// Jams are reported on every poll until cleared, and followed by an `Incomplete payout` event, so
+// only the first event finds an issued intent.
+fn mark_incomplete(payout_intents: Option<&PayoutIntentLog>) {
+    if let Some(payout_intents) = payout_intents {
+        match payout_intents.mark_incomplete() {
+            Ok(Some(intent)) => log::warn!("Incomplete {intent}"),
+            Ok(None) => (),
+            Err(err) => log::error!("Failed to mark payout intent as incomplete: {err}"),
+        }
+    }
+}
+
+// `Dispensing` status, not supported by the `ssp` library.
+const DISPENSING: u8 = 0xda;
+// `Dispensed` status, not supported by the `ssp` library.
+const DISPENSED: u8 = 0xd2;
+
+// `Jammed` payout status, not supported by the `ssp` library.
+const PAYOUT_JAMMED: u8 = 0xd5;
+// `Incomplete payout` status, not supported by the `ssp` library.
+const INCOMPLETE_PAYOUT: u8 = 0xdc;
+// `Emptying` status, not supported by the `ssp` library.
+const EMPTYING: u8 = 0xc2;
+// `Emptied` status, not supported by the `ssp` library.
+const EMPTIED: u8 = 0xc3;
+// `Smart emptying` status, not supported by the `ssp` library.
+const SMART_EMPTYING: u8 = 0xb3;
+// `Smart emptied` status, not supported by the `ssp` library.
+const SMART_EMPTIED: u8 = 0xb4;
+
+// Gets the length of a `Dispensing`, `Dispensed`, `Jammed`, `Smart emptying`, or
+// `Smart emptied` event at `idx`, sent by a device using the `protocol` version.
+//
+// Since protocol version 6, the event carries the number of countries, followed by a value, and
+// country code for each, otherwise a single value.
+fn dispense_event_len(protocol: ssp::ProtocolVersion, data: &[u8], idx: usize) -> usize {
+    if protocol as u8 >= 6 {
+        2 + usize::from(data.get(idx + 1).copied().unwrap_or_default()) * 7
+    } else {
+        5
+    }
+}
+
+// Gets the length of an `Incomplete payout` event at `idx`.
+//
+// Like a `Dispensing` event, with the requested value following the dispensed value.
+fn incomplete_payout_event_len(protocol: ssp::ProtocolVersion, data: &[u8], idx: usize) -> usize {
+    if protocol as u8 >= 6 {
+        2 + usize::from(data.get(idx + 1).copied().unwrap_or_default()) * 11
+    } else {
+        9
+    }
+}
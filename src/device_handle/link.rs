@@ -0,0 +1,512 @@
+//! State of the link to a single device.
+
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::time;
+
+use parking_lot::{Mutex, RwLock};
+
+use crate::encryption::{self, EncryptionMode, EncryptionPolicy};
+use crate::retry::CommandClass;
+use crate::snapshot::DeviceSnapshot;
+use crate::stats::StatsRecorder;
+use crate::timeouts;
+
+// Protocol version assumed until the host sets one.
+const DEFAULT_PROTOCOL_VERSION: u8 = 6;
+// Unit type until the first setup request.
+const UNIT_TYPE_UNKNOWN: u16 = u16::MAX;
+
+/// Setup data cached from the device, until it resets, or its dataset changes.
+#[derive(Debug, Default)]
+struct SetupCache {
+    // the response depends on the host protocol version it was requested with
+    setup_request: Option<(ssp::ProtocolVersion, ssp::SetupRequestResponse)>,
+    channel_value_data: Option<ssp::ChannelValueDataResponse>,
+    dataset_version: Option<String>,
+}
+
+/// Last-known state of the device, set by the host, used to skip commands that would be no-ops.
+#[derive(Debug, Default)]
+struct KnownState {
+    enabled: Option<bool>,
+    inhibits: Option<Vec<u8>>,
+}
+
+/// State of the link to a single device, shared by its polling routine, I/O worker, and the
+/// [DeviceHandle](super::DeviceHandle).
+///
+/// Every handle has its own link state, so a device resetting, negotiating a key, jammed, or
+/// holding a note on one port does not pause polling, or commands on another. The device
+/// identity, protocol version, encryption policy, eSSP sequence count, and session counters are
+/// also per link, so handles in the same process never see each other's device. The escrow poll
+/// mode, and response timeouts applied from a configuration are per link too.
+#[derive(Debug)]
+pub(crate) struct LinkState {
+    seq_flag: AtomicBool,
+    polling: AtomicBool,
+    // time a device reset was initiated (seconds)
+    reset_time: AtomicU64,
+    key_negotiating: AtomicBool,
+    unsafe_jam: AtomicBool,
+    escrowed: AtomicBool,
+    escrowed_amount: AtomicU32,
+    dispensing: AtomicBool,
+    // a response read gave up mid-exchange, so the bus needs to be recovered
+    bus_dirty: AtomicBool,
+    stats: StatsRecorder,
+    // type, and sequence ID of the last command sent
+    last_command: Mutex<Option<(ssp::MessageType, u8)>>,
+    setup: Mutex<SetupCache>,
+    known: Mutex<KnownState>,
+    enabled: AtomicBool,
+    cashbox_attached: AtomicBool,
+    protocol_version: AtomicU8,
+    // unit type reported by the device, [UNIT_TYPE_UNKNOWN] until the first setup request
+    unit_type: AtomicU16,
+    // serial number reported by the device, zero until the first serial number request
+    serial_number: AtomicU32,
+    // time of the last successful poll (milliseconds since the UNIX epoch)
+    last_poll_ms: AtomicU64,
+    encryption_policy: RwLock<EncryptionPolicy>,
+    // whether value-relevant commands are refused without an encryption key
+    require_encryption: AtomicBool,
+    // configuration applied by the host, reapplied by [DeviceHandle::restore]
+    device_config: Mutex<DeviceSnapshot>,
+    // whether the host handles escrowed notes, instead of the polling routine
+    interactive: AtomicBool,
+    // response timeouts set for the link, the process-wide timeouts for unset classes
+    response_timeouts: Mutex<[Option<time::Duration>; CommandClass::ALL.len()]>,
+    // eSSP sequence count of the next encrypted command
+    sequence_count: AtomicU32,
+    // number of times the eSSP sequence count was resynchronized with the device
+    sequence_resyncs: AtomicU64,
+    // number of decrypted responses rejected for carrying an already used sequence count
+    replayed_responses: AtomicU64,
+    // number of encrypted commands answered with a plaintext response
+    encryption_downgrades: AtomicU64,
+    // whether the eSSP session is out of sync, and needs a new key
+    session_desync: AtomicBool,
+    // number of times a new key was negotiated after losing the eSSP session
+    session_rekeys: AtomicU64,
+}
+
+impl Default for LinkState {
+    fn default() -> Self {
+        Self {
+            seq_flag: AtomicBool::new(false),
+            polling: AtomicBool::new(false),
+            reset_time: AtomicU64::new(0),
+            key_negotiating: AtomicBool::new(false),
+            unsafe_jam: AtomicBool::new(false),
+            escrowed: AtomicBool::new(false),
+            escrowed_amount: AtomicU32::new(0),
+            dispensing: AtomicBool::new(false),
+            bus_dirty: AtomicBool::new(false),
+            stats: StatsRecorder::default(),
+            last_command: Mutex::new(None),
+            setup: Mutex::new(SetupCache::default()),
+            known: Mutex::new(KnownState::default()),
+            enabled: AtomicBool::new(false),
+            cashbox_attached: AtomicBool::new(true),
+            protocol_version: AtomicU8::new(DEFAULT_PROTOCOL_VERSION),
+            unit_type: AtomicU16::new(UNIT_TYPE_UNKNOWN),
+            serial_number: AtomicU32::new(0),
+            last_poll_ms: AtomicU64::new(0),
+            encryption_policy: RwLock::new(EncryptionPolicy::new()),
+            require_encryption: AtomicBool::new(false),
+            device_config: Mutex::new(DeviceSnapshot::new()),
+            interactive: AtomicBool::new(false),
+            response_timeouts: Mutex::new([None; CommandClass::ALL.len()]),
+            sequence_count: AtomicU32::new(0),
+            sequence_resyncs: AtomicU64::new(0),
+            replayed_responses: AtomicU64::new(0),
+            encryption_downgrades: AtomicU64::new(0),
+            session_desync: AtomicBool::new(false),
+            session_rekeys: AtomicU64::new(0),
+        }
+    }
+}
+
+impl LinkState {
+    /// Creates a new [LinkState].
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn sequence_flag(&self) -> ssp::SequenceFlag {
+        self.seq_flag.load(Ordering::Relaxed).into()
+    }
+
+    pub(crate) fn set_sequence_flag(&self, flag: ssp::SequenceFlag) {
+        self.seq_flag.store(flag.into(), Ordering::SeqCst);
+    }
+
+    pub(crate) fn last_command(&self) -> Option<(ssp::MessageType, u8)> {
+        *self.last_command.lock()
+    }
+
+    pub(crate) fn set_last_command(&self, command: ssp::MessageType, sequence_id: u8) {
+        *self.last_command.lock() = Some((command, sequence_id));
+    }
+
+    // Whether the polling routine has started.
+    pub(crate) fn polling_inited(&self) -> bool {
+        self.polling.load(Ordering::Relaxed)
+    }
+
+    // Sets the flag indicating whether the polling routine started.
+    pub(crate) fn set_polling_inited(&self, inited: bool) {
+        self.polling.store(inited, Ordering::SeqCst);
+    }
+
+    pub(crate) fn resetting(&self) -> bool {
+        self.reset_time() != 0
+    }
+
+    pub(crate) fn reset_time(&self) -> u64 {
+        self.reset_time.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_reset_time(&self, time: u64) -> u64 {
+        self.reset_time.swap(time, Ordering::SeqCst)
+    }
+
+    pub(crate) fn key_negotiating(&self) -> bool {
+        self.key_negotiating.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_key_negotiating(&self, val: bool) {
+        self.key_negotiating.store(val, Ordering::SeqCst)
+    }
+
+    pub(crate) fn unsafe_jam(&self) -> bool {
+        self.unsafe_jam.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_unsafe_jam(&self, val: bool) {
+        self.unsafe_jam.store(val, Ordering::SeqCst)
+    }
+
+    pub(crate) fn escrowed(&self) -> bool {
+        self.escrowed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_escrowed(&self, escrowed: bool) -> bool {
+        self.escrowed.swap(escrowed, Ordering::SeqCst)
+    }
+
+    pub(crate) fn escrowed_amount(&self) -> ssp::ChannelValue {
+        self.escrowed_amount.load(Ordering::Relaxed).into()
+    }
+
+    pub(crate) fn set_escrowed_amount(&self, amount: ssp::ChannelValue) -> ssp::ChannelValue {
+        self.escrowed_amount
+            .swap(amount.into(), Ordering::SeqCst)
+            .into()
+    }
+
+    pub(crate) fn dispensing(&self) -> bool {
+        self.dispensing.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_dispensing(&self, val: bool) {
+        self.dispensing.store(val, Ordering::SeqCst)
+    }
+
+    // Sets the dispensing flag, until the returned guard is dropped, on every return path.
+    pub(crate) fn dispensing_guard(&self) -> DispensingGuard<'_> {
+        self.set_dispensing(true);
+        DispensingGuard(self)
+    }
+
+    pub(crate) fn bus_dirty(&self) -> bool {
+        self.bus_dirty.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_bus_dirty(&self, val: bool) -> bool {
+        self.bus_dirty.swap(val, Ordering::SeqCst)
+    }
+
+    // Recent exchanges with the device.
+    pub(crate) fn stats(&self) -> &StatsRecorder {
+        &self.stats
+    }
+
+    // Cached setup request response, if requested with the `protocol_version`.
+    pub(crate) fn cached_setup_request(
+        &self,
+        protocol_version: ssp::ProtocolVersion,
+    ) -> Option<ssp::SetupRequestResponse> {
+        self.setup
+            .lock()
+            .setup_request
+            .filter(|(version, _)| *version == protocol_version)
+            .map(|(_, res)| res)
+    }
+
+    pub(crate) fn cache_setup_request(
+        &self,
+        protocol_version: ssp::ProtocolVersion,
+        res: ssp::SetupRequestResponse,
+    ) {
+        self.setup.lock().setup_request = Some((protocol_version, res));
+    }
+
+    pub(crate) fn cached_channel_value_data(&self) -> Option<ssp::ChannelValueDataResponse> {
+        self.setup.lock().channel_value_data
+    }
+
+    pub(crate) fn cache_channel_value_data(&self, res: ssp::ChannelValueDataResponse) {
+        self.setup.lock().channel_value_data = Some(res);
+    }
+
+    // Records the dataset version reported by the device, clearing the cached setup data if it
+    // changed.
+    //
+    // Returns whether the dataset changed.
+    pub(crate) fn set_dataset_version(&self, version: &str) -> bool {
+        let mut setup = self.setup.lock();
+
+        let changed = setup
+            .dataset_version
+            .as_ref()
+            .map(|last| last != version)
+            .unwrap_or(false);
+
+        if changed {
+            *setup = SetupCache::default();
+        }
+        setup.dataset_version = Some(version.into());
+
+        changed
+    }
+
+    // Clears the cached setup data, e.g. after the device resets.
+    pub(crate) fn clear_setup_cache(&self) {
+        *self.setup.lock() = SetupCache::default();
+    }
+
+    // Whether the device is known to be enabled, `None` if unknown.
+    pub(crate) fn known_enabled(&self) -> Option<bool> {
+        self.known.lock().enabled
+    }
+
+    pub(crate) fn set_known_enabled(&self, enabled: bool) {
+        self.known.lock().enabled = Some(enabled);
+    }
+
+    // Channel inhibits known to be set on the device, `None` if unknown.
+    pub(crate) fn known_inhibits(&self) -> Option<Vec<u8>> {
+        self.known.lock().inhibits.clone()
+    }
+
+    pub(crate) fn set_known_inhibits(&self, inhibits: Vec<u8>) {
+        self.known.lock().inhibits = Some(inhibits);
+    }
+
+    // Forgets the known device state, e.g. after the device resets.
+    pub(crate) fn clear_known_state(&self) {
+        *self.known.lock() = KnownState::default();
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    pub(crate) fn cashbox_attached(&self) -> bool {
+        self.cashbox_attached.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_cashbox_attached(&self, attached: bool) -> bool {
+        self.cashbox_attached.swap(attached, Ordering::SeqCst)
+    }
+
+    pub(crate) fn protocol_version(&self) -> ssp::ProtocolVersion {
+        self.protocol_version.load(Ordering::Relaxed).into()
+    }
+
+    pub(crate) fn set_protocol_version(
+        &self,
+        protocol: ssp::ProtocolVersion,
+    ) -> ssp::ProtocolVersion {
+        self.protocol_version
+            .swap(protocol.into(), Ordering::SeqCst)
+            .into()
+    }
+
+    pub(crate) fn unit_type(&self) -> Option<ssp::UnitType> {
+        match self.unit_type.load(Ordering::Relaxed) {
+            UNIT_TYPE_UNKNOWN => None,
+            unit_type => Some(ssp::UnitType::from_inner(unit_type as u8)),
+        }
+    }
+
+    pub(crate) fn set_unit_type(&self, unit_type: ssp::UnitType) {
+        self.unit_type
+            .store(unit_type.as_inner().into(), Ordering::SeqCst);
+    }
+
+    pub(crate) fn serial_number(&self) -> u32 {
+        self.serial_number.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_serial_number(&self, serial_number: ssp::SerialNumber) {
+        self.serial_number
+            .store(serial_number.as_inner(), Ordering::SeqCst);
+    }
+
+    // Time elapsed since the last successful poll, `None` before the first one.
+    pub(crate) fn last_poll_elapsed(&self) -> Option<time::Duration> {
+        match self.last_poll_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => time::SystemTime::now()
+                .duration_since(time::UNIX_EPOCH + time::Duration::from_millis(ms))
+                .ok()
+                .or(Some(time::Duration::ZERO)),
+        }
+    }
+
+    pub(crate) fn set_last_poll_now(&self) {
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        self.last_poll_ms.store(now, Ordering::SeqCst);
+    }
+
+    pub(crate) fn encryption_policy(&self) -> EncryptionPolicy {
+        self.encryption_policy.read().clone()
+    }
+
+    pub(crate) fn set_encryption_policy(&self, policy: EncryptionPolicy) {
+        *self.encryption_policy.write() = policy;
+    }
+
+    pub(crate) fn requires_encryption(&self) -> bool {
+        self.require_encryption.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_require_encryption(&self, require: bool) {
+        self.require_encryption.store(require, Ordering::SeqCst);
+    }
+
+    // Encryption mode of the `command`, for the current protocol version, and unit type.
+    //
+    // While encryption is required, value-relevant commands are always encrypted.
+    pub(crate) fn encryption_mode(&self, command: ssp::MessageType) -> EncryptionMode {
+        if self.requires_encryption() && encryption::is_value_relevant(command) {
+            return EncryptionMode::Required;
+        }
+
+        self.encryption_policy
+            .read()
+            .mode(command, self.protocol_version(), self.unit_type())
+    }
+
+    pub(crate) fn device_config(&self) -> DeviceSnapshot {
+        self.device_config.lock().clone()
+    }
+
+    // Updates the configuration applied by the host.
+    pub(crate) fn update_device_config(&self, f: impl FnOnce(&mut DeviceSnapshot)) {
+        f(&mut self.device_config.lock());
+    }
+
+    pub(crate) fn interactive(&self) -> bool {
+        self.interactive.load(Ordering::Relaxed)
+    }
+
+    // Sets the interactive mode, and returns the previous mode.
+    pub(crate) fn set_interactive(&self, val: bool) -> bool {
+        self.interactive.swap(val, Ordering::SeqCst)
+    }
+
+    // Gets the response timeout of a command `class`, falling back to the process-wide timeout.
+    pub(crate) fn response_timeout(&self, class: CommandClass) -> time::Duration {
+        self.response_timeouts.lock()[class as usize]
+            .unwrap_or_else(|| timeouts::response_timeout(class))
+    }
+
+    // Sets the response timeout of a command `class`, `None` to use the process-wide timeout.
+    //
+    // A zero `timeout` is ignored, since it would fail every read.
+    pub(crate) fn set_response_timeout(
+        &self,
+        class: CommandClass,
+        timeout: Option<time::Duration>,
+    ) {
+        if timeout.is_some_and(|timeout| timeout.is_zero()) {
+            log::warn!("Ignoring zero response timeout for {class} commands");
+            return;
+        }
+
+        self.response_timeouts.lock()[class as usize] = timeout;
+    }
+
+    pub(crate) fn sequence_count(&self) -> ssp::SequenceCount {
+        ssp::SequenceCount::from_inner(self.sequence_count.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_sequence_count(&self, count: ssp::SequenceCount) {
+        self.sequence_count
+            .store(count.as_inner(), Ordering::SeqCst);
+    }
+
+    pub(crate) fn reset_sequence_count(&self) {
+        self.sequence_count.store(0, Ordering::SeqCst);
+    }
+
+    pub(crate) fn sequence_resyncs(&self) -> u64 {
+        self.sequence_resyncs.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_sequence_resync(&self) {
+        self.sequence_resyncs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn replayed_responses(&self) -> u64 {
+        self.replayed_responses.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_replayed_response(&self) {
+        self.replayed_responses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn encryption_downgrades(&self) -> u64 {
+        self.encryption_downgrades.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_encryption_downgrade(&self) {
+        self.encryption_downgrades.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn session_desynced(&self) -> bool {
+        self.session_desync.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_session_desynced(&self, val: bool) {
+        self.session_desync.store(val, Ordering::SeqCst)
+    }
+
+    pub(crate) fn session_rekeys(&self) -> u64 {
+        self.session_rekeys.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_session_rekey(&self) {
+        self.session_rekeys.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Clears the dispensing flag of a [LinkState] when dropped.
+pub(crate) struct DispensingGuard<'a>(&'a LinkState);
+
+impl Drop for DispensingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.set_dispensing(false);
+    }
+}
@@ -0,0 +1,586 @@
+//! Non-blocking command submission to the I/O worker of a [DeviceHandle].
+//!
+//! Submitted commands wait in a priority queue. While a background polling routine runs, it takes
+//! the queued commands between polls, so a burst of commands never delays a poll. Otherwise, the
+//! I/O worker sends the commands as they arrive.
+//!
+//! Blocking [DeviceHandle] methods queue a turn on the transport instead of racing the polling
+//! routine for the serial port lock: the polling routine hands over the transport between polls,
+//! in queue order with the submitted commands, and resumes polling after the turn ends.
+//!
+//! Escrow decisions are time-sensitive, so [Hold](ssp::HoldCommand), and
+//! [Reject](ssp::RejectCommand) commands, and the [hold](DeviceHandle::hold), and
+//! [reject](DeviceHandle::reject) turns preempt the queue, and are sent before the next scheduled
+//! poll. Other commands keep their priority, and submission order.
+
+use std::cmp;
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll, Waker};
+use std::{fmt, time};
+
+use parking_lot::{Condvar, Mutex};
+use ssp::{CommandOps, Result};
+
+use super::{DeviceHandle, LinkState, WorkerKind, MIN_POLLING_MS};
+use crate::payout_intent::PayoutIntentLog;
+use crate::transport::Transport;
+
+#[derive(Default)]
+struct CommandSlot {
+    result: Option<Result<ssp::MessageVariant>>,
+    done: bool,
+    waker: Option<Waker>,
+}
+
+type SharedSlot = Arc<(Mutex<CommandSlot>, Condvar)>;
+
+/// Command queued for the I/O worker.
+pub(crate) struct CommandJob {
+    command: Box<dyn CommandOps + Send>,
+    slot: SharedSlot,
+    payout_intents: Option<PayoutIntentLog>,
+}
+
+impl CommandJob {
+    // Sends the command, and completes it with the response.
+    //
+    // Payout commands are recorded in the payout intent log, like the blocking payout methods.
+    fn run(mut self, serial_port: &mut dyn Transport, link: &LinkState, key: Option<&ssp::AesKey>) {
+        let payout_intents = self.payout_intents.as_ref();
+
+        let intent =
+            match DeviceHandle::begin_payout_intent(payout_intents, link, self.command.as_ref()) {
+                Ok(intent) => intent,
+                Err(err) => return self.complete(Err(err)),
+            };
+
+        let res = DeviceHandle::poll_message(serial_port, link, self.command.as_mut(), key);
+        DeviceHandle::mark_payout_intent(payout_intents, intent, &res);
+
+        self.complete(res);
+    }
+
+    // Locks the serial port, and sends the command.
+    fn lock_and_run(
+        self,
+        serial_port: &Arc<Mutex<Box<dyn Transport>>>,
+        link: &LinkState,
+        key: &Arc<Mutex<Option<ssp::AesKey>>>,
+    ) {
+        let locked = DeviceHandle::lock_serial_port(serial_port).and_then(|serial_port| {
+            DeviceHandle::copy_encryption_key(key).map(|key| (serial_port, key))
+        });
+
+        match locked {
+            Ok((mut serial_port, key)) => self.run(serial_port.as_mut(), link, key.as_ref()),
+            Err(err) => self.complete(Err(err)),
+        }
+    }
+
+    fn complete(&self, res: Result<ssp::MessageVariant>) {
+        let (slot, done) = &*self.slot;
+        let mut slot = slot.lock();
+
+        if slot.done {
+            return;
+        }
+
+        slot.result = Some(res);
+        slot.done = true;
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+
+        done.notify_all();
+    }
+}
+
+impl Drop for CommandJob {
+    // Completes the command if the worker dropped it without a response, so waiting callers do
+    // not block forever.
+    fn drop(&mut self) {
+        self.complete(Err(ssp::Error::Io(
+            "command dropped by the I/O worker".into(),
+        )));
+    }
+}
+
+/// Handle of a command submitted with [submit](DeviceHandle::submit).
+///
+/// Wait for the response with [wait](Self::wait), or [wait_timeout](Self::wait_timeout), check
+/// for it without blocking with [try_result](Self::try_result), or `.await` the handle in an
+/// async context.
+pub struct CommandHandle {
+    slot: SharedSlot,
+}
+
+impl CommandHandle {
+    /// Gets whether the command completed.
+    pub fn is_done(&self) -> bool {
+        self.slot.0.lock().done
+    }
+
+    /// Takes the response, if the command completed, without blocking.
+    ///
+    /// Returns `None` while the command is pending, and after the response was taken.
+    pub fn try_result(&mut self) -> Option<Result<ssp::MessageVariant>> {
+        self.slot.0.lock().result.take()
+    }
+
+    /// Blocks until the command completes, and returns the response.
+    pub fn wait(self) -> Result<ssp::MessageVariant> {
+        let (slot, done) = &*self.slot;
+        let mut slot = slot.lock();
+
+        while !slot.done {
+            done.wait(&mut slot);
+        }
+
+        slot.result
+            .take()
+            .unwrap_or(Err(ssp::Error::Io("command response already taken".into())))
+    }
+
+    /// Blocks until the command completes, or the `timeout` expires.
+    ///
+    /// Returns `None` if the command is still pending after the `timeout`, the command stays
+    /// queued.
+    pub fn wait_timeout(&mut self, timeout: time::Duration) -> Option<Result<ssp::MessageVariant>> {
+        let (slot, done) = &*self.slot;
+        let mut slot = slot.lock();
+
+        if !slot.done {
+            let _ = done.wait_while_for(&mut slot, |s| !s.done, timeout);
+        }
+
+        slot.result.take()
+    }
+}
+
+impl Future for CommandHandle {
+    type Output = Result<ssp::MessageVariant>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.0.lock();
+
+        if slot.done {
+            Poll::Ready(
+                slot.result
+                    .take()
+                    .unwrap_or(Err(ssp::Error::Io("command response already taken".into()))),
+            )
+        } else {
+            slot.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Priority of a command submitted with [submit_with_priority](DeviceHandle::submit_with_priority).
+///
+/// Queued commands are sent highest priority first, and in submission order within a priority.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CommandPriority {
+    /// Background commands, e.g. periodic status queries.
+    Low,
+    /// Regular application commands.
+    #[default]
+    Normal,
+    /// Time-critical commands, e.g. stacking, or rejecting a note in escrow.
+    High,
+}
+
+impl CommandPriority {
+    /// Gets the name of the priority.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::High => "high",
+        }
+    }
+}
+
+impl fmt::Display for CommandPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// Work taken from the queue between polls.
+enum Work {
+    // submitted command
+    Command(CommandJob),
+    // turn on the transport for a blocking method, identified by the queue sequence
+    Turn,
+}
+
+struct QueuedJob {
+    preempt: bool,
+    priority: CommandPriority,
+    seq: u64,
+    work: Work,
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedJob {
+    // preempting first, then highest priority, then oldest first
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.preempt
+            .cmp(&other.preempt)
+            .then_with(|| self.priority.cmp(&other.priority))
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    jobs: BinaryHeap<QueuedJob>,
+    next_seq: u64,
+    scheduled: bool,
+    // turn on the transport handed over by the polling routine
+    active_turn: Option<u64>,
+}
+
+/// Priority queue of submitted commands, shared by the I/O worker, and the polling routines.
+#[derive(Default)]
+pub(crate) struct CommandQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+}
+
+impl CommandQueue {
+    fn push(&self, job: CommandJob, priority: CommandPriority) {
+        let preempt = preempts(job.command.command());
+
+        let mut state = self.state.lock();
+        Self::push_work(&mut state, Work::Command(job), priority, preempt);
+
+        self.ready.notify_all();
+    }
+
+    fn push_work(
+        state: &mut QueueState,
+        work: Work,
+        priority: CommandPriority,
+        preempt: bool,
+    ) -> u64 {
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.jobs.push(QueuedJob {
+            preempt,
+            priority,
+            seq,
+            work,
+        });
+
+        seq
+    }
+
+    /// Gets the number of queued commands.
+    pub(crate) fn len(&self) -> usize {
+        self.state.lock().jobs.len()
+    }
+
+    /// Hands the queue to a polling routine, until the returned guard is dropped.
+    ///
+    /// The I/O worker leaves the queued commands to the polling routine in the meantime.
+    pub(crate) fn schedule(self: &Arc<Self>) -> ScheduleGuard {
+        self.set_scheduled(true);
+        ScheduleGuard(Arc::clone(self))
+    }
+
+    fn set_scheduled(&self, scheduled: bool) {
+        let mut state = self.state.lock();
+        state.scheduled = scheduled;
+
+        // without a polling routine, waiting blocking methods lock the transport directly
+        if !scheduled {
+            state
+                .jobs
+                .retain(|queued| matches!(queued.work, Work::Command(_)));
+        }
+
+        self.ready.notify_all();
+    }
+
+    /// Waits up to `timeout` for a turn on the transport, while a polling routine runs.
+    ///
+    /// The polling routine hands over the transport between polls, and waits until the returned
+    /// turn is dropped. A `preempt` turn is handed over before the queued commands, and the next
+    /// scheduled poll. Without a polling routine, returns right away.
+    pub(crate) fn take_turn(
+        self: &Arc<Self>,
+        timeout: time::Duration,
+        preempt: bool,
+    ) -> Result<PortTurn> {
+        let mut state = self.state.lock();
+
+        if !state.scheduled {
+            return Ok(PortTurn::default());
+        }
+
+        let ticket = Self::push_work(&mut state, Work::Turn, CommandPriority::Normal, preempt);
+        self.ready.notify_all();
+
+        let deadline = time::Instant::now() + timeout;
+
+        loop {
+            if state.active_turn == Some(ticket) {
+                return Ok(PortTurn {
+                    queue: Some(Arc::clone(self)),
+                    ticket,
+                });
+            }
+
+            if !state.scheduled {
+                return Ok(PortTurn::default());
+            }
+
+            if time::Instant::now() >= deadline {
+                state.jobs.retain(|queued| queued.seq != ticket);
+
+                return Err(ssp::Error::SerialPort(
+                    "timed out waiting for a turn between polls".into(),
+                ));
+            }
+
+            self.ready.wait_until(&mut state, deadline);
+        }
+    }
+
+    fn end_turn(&self, ticket: u64) {
+        let mut state = self.state.lock();
+
+        if state.active_turn == Some(ticket) {
+            state.active_turn = None;
+        }
+
+        self.ready.notify_all();
+    }
+
+    // Waits until the active turn ends.
+    fn wait_turn(&self) {
+        let mut state = self.state.lock();
+
+        while state.active_turn.is_some() {
+            self.ready.wait(&mut state);
+        }
+    }
+
+    /// Sends queued commands until the `deadline`, i.e. the next poll, waiting for new commands
+    /// in the meantime.
+    ///
+    /// A command is only started before the `deadline`, but can finish after it.
+    pub(crate) fn run_until(
+        &self,
+        deadline: time::Instant,
+        serial_port: &Arc<Mutex<Box<dyn Transport>>>,
+        link: &LinkState,
+        key: &Arc<Mutex<Option<ssp::AesKey>>>,
+    ) {
+        while let Some(work) = self.next_until(deadline) {
+            match work {
+                Work::Command(job) => job.lock_and_run(serial_port, link, key),
+                Work::Turn => self.wait_turn(),
+            }
+        }
+    }
+
+    /// Sends the queued preempting commands, and hands over the preempting turns, before a
+    /// scheduled poll.
+    pub(crate) fn run_preempting(
+        &self,
+        serial_port: &Arc<Mutex<Box<dyn Transport>>>,
+        link: &LinkState,
+        key: &Arc<Mutex<Option<ssp::AesKey>>>,
+    ) {
+        while let Some(work) = self.next_preempting() {
+            match work {
+                Work::Command(job) => job.lock_and_run(serial_port, link, key),
+                Work::Turn => self.wait_turn(),
+            }
+        }
+    }
+
+    fn next_until(&self, deadline: time::Instant) -> Option<Work> {
+        let mut state = self.state.lock();
+
+        while time::Instant::now() < deadline {
+            if let Some(queued) = state.jobs.pop() {
+                return Some(self.hand_over(&mut state, queued));
+            }
+
+            self.ready.wait_until(&mut state, deadline);
+        }
+
+        None
+    }
+
+    fn next_preempting(&self) -> Option<Work> {
+        let mut state = self.state.lock();
+
+        // preempting jobs are ordered first
+        if state
+            .jobs
+            .peek()
+            .map(|queued| queued.preempt)
+            .unwrap_or(false)
+        {
+            let queued = state.jobs.pop()?;
+            Some(self.hand_over(&mut state, queued))
+        } else {
+            None
+        }
+    }
+
+    fn hand_over(&self, state: &mut QueueState, queued: QueuedJob) -> Work {
+        // hand over the transport, before the waiting method can give up on the turn
+        if let Work::Turn = queued.work {
+            state.active_turn = Some(queued.seq);
+            self.ready.notify_all();
+        }
+
+        queued.work
+    }
+
+    // Takes the next command for the I/O worker, while no polling routine runs.
+    fn next_unscheduled(&self, timeout: time::Duration) -> Option<CommandJob> {
+        let mut state = self.state.lock();
+
+        if state.scheduled || state.jobs.is_empty() {
+            self.ready.wait_for(&mut state, timeout);
+        }
+
+        if state.scheduled {
+            return None;
+        }
+
+        // turns are only queued while a polling routine runs
+        match state.jobs.pop().map(|queued| queued.work) {
+            Some(Work::Command(job)) => Some(job),
+            _ => None,
+        }
+    }
+}
+
+// Gets whether a command preempts the queue, and the next scheduled poll.
+fn preempts(command: ssp::MessageType) -> bool {
+    matches!(command, ssp::MessageType::Hold | ssp::MessageType::Reject)
+}
+
+/// Turn on the transport handed over by the polling routine, ending when dropped.
+#[derive(Default)]
+pub(crate) struct PortTurn {
+    queue: Option<Arc<CommandQueue>>,
+    ticket: u64,
+}
+
+impl Drop for PortTurn {
+    fn drop(&mut self) {
+        if let Some(queue) = self.queue.take() {
+            queue.end_turn(self.ticket);
+        }
+    }
+}
+
+/// Returns the [CommandQueue] to the I/O worker when dropped.
+pub(crate) struct ScheduleGuard(Arc<CommandQueue>);
+
+impl Drop for ScheduleGuard {
+    fn drop(&mut self) {
+        self.0.set_scheduled(false);
+    }
+}
+
+impl DeviceHandle {
+    /// Submits a `command` to the I/O worker, and returns without waiting for the response.
+    ///
+    /// Equivalent to [submit_with_priority](Self::submit_with_priority) with the
+    /// [Normal](CommandPriority::Normal) priority.
+    pub fn submit<C: CommandOps + Send + 'static>(&self, command: C) -> CommandHandle {
+        self.submit_with_priority(command, CommandPriority::Normal)
+    }
+
+    /// Submits a `command` with a `priority`, and returns without waiting for the response.
+    ///
+    /// While a background polling routine runs, queued commands are sent between polls, so the
+    /// polling interval holds during a burst of commands. Otherwise, the I/O worker sends them,
+    /// interleaved with the blocking [DeviceHandle] methods. The I/O worker is started on the
+    /// first call.
+    ///
+    /// Use the returned [CommandHandle] to wait for, or poll the response, so UI threads do not
+    /// block on a serial timeout.
+    pub fn submit_with_priority<C: CommandOps + Send + 'static>(
+        &self,
+        command: C,
+        priority: CommandPriority,
+    ) -> CommandHandle {
+        let slot = SharedSlot::default();
+        let job = CommandJob {
+            command: Box::new(command),
+            slot: Arc::clone(&slot),
+            payout_intents: self.payout_intents.clone(),
+        };
+
+        self.start_worker();
+        self.commands.push(job, priority);
+
+        CommandHandle { slot }
+    }
+
+    /// Gets the number of submitted commands waiting to be sent.
+    pub fn queued_commands(&self) -> usize {
+        self.commands.len()
+    }
+
+    // Starts the I/O worker, running until the handle is dropped, unless it is running.
+    fn start_worker(&self) {
+        let commands = Arc::downgrade(&self.commands);
+        let serial_port = Arc::clone(&self.serial_port);
+        let link = Arc::clone(&self.link);
+        let key = Arc::clone(&self.key);
+
+        let res = self
+            .workers
+            .spawn_if_stopped(WorkerKind::Io, &self.events, move || {
+                let idle = time::Duration::from_millis(MIN_POLLING_MS);
+
+                while let Some(queue) = Weak::upgrade(&commands) {
+                    let job = queue.next_unscheduled(idle);
+                    // do not keep the queue alive while sending
+                    drop(queue);
+
+                    if let Some(job) = job {
+                        job.lock_and_run(&serial_port, &link, &key);
+                    }
+                }
+
+                Ok(())
+            });
+
+        // the command stays queued, and is sent by the next started worker
+        if let Err(err) = res {
+            log::error!("Failed to start the I/O worker: {err}");
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Worker threads owned by a device handle.
+
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use crossbeam::channel;
+use parking_lot::Mutex;
+
+use ssp::Result;
+
+// Interval between checks for finished workers while joining (milliseconds).
+const JOIN_POLL_MS: u64 = 10;
+
+/// Kind of worker thread run by a [DeviceHandle](super::DeviceHandle).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum WorkerKind {
+    /// Background polling routine.
+    Poll,
+    /// I/O worker sending submitted commands.
+    Io,
+    /// Forwards polled events to the push event queue, logging them to the event log.
+    EventDispatch,
+}
+
+impl WorkerKind {
+    /// Gets the [WorkerKind] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Poll => "poll",
+            Self::Io => "io",
+            Self::EventDispatch => "event-dispatch",
+        }
+    }
+}
+
+impl fmt::Display for WorkerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Status of a worker thread.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WorkerStatus {
+    /// Kind of worker.
+    pub kind: WorkerKind,
+    /// Whether the worker is still running.
+    pub running: bool,
+    /// Whether the worker stopped because it panicked.
+    pub panicked: bool,
+}
+
+#[derive(Debug)]
+struct Worker {
+    kind: WorkerKind,
+    thread: thread::JoinHandle<()>,
+    panicked: Arc<AtomicBool>,
+}
+
+impl Worker {
+    fn status(&self) -> WorkerStatus {
+        WorkerStatus {
+            kind: self.kind,
+            running: !self.thread.is_finished(),
+            panicked: self.panicked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Joinable worker threads of a handle.
+///
+/// Errors returned by, and panics of a worker are logged, and sent as a
+/// [Fail](ssp::Method::Fail) event to the push event queue, instead of silently stopping it.
+#[derive(Debug, Default)]
+pub(crate) struct Workers {
+    workers: Mutex<Vec<Worker>>,
+}
+
+impl Workers {
+    /// Starts a worker of the given `kind`, running `work`.
+    pub(crate) fn spawn<F>(
+        &self,
+        kind: WorkerKind,
+        events: &Arc<Mutex<Option<channel::Sender<ssp::Event>>>>,
+        work: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> Result<()> + Send + 'static,
+    {
+        Self::start(&mut self.workers.lock(), kind, events, work)
+    }
+
+    /// Starts a worker of the given `kind`, unless one is already running.
+    ///
+    /// Returns whether a worker was started.
+    pub(crate) fn spawn_if_stopped<F>(
+        &self,
+        kind: WorkerKind,
+        events: &Arc<Mutex<Option<channel::Sender<ssp::Event>>>>,
+        work: F,
+    ) -> Result<bool>
+    where
+        F: FnOnce() -> Result<()> + Send + 'static,
+    {
+        let mut workers = self.workers.lock();
+
+        if workers
+            .iter()
+            .any(|w| w.kind == kind && !w.thread.is_finished())
+        {
+            return Ok(false);
+        }
+
+        if workers.iter().any(|w| w.kind == kind) {
+            log::warn!("{kind} worker stopped, restarting");
+        }
+
+        Self::start(&mut workers, kind, events, work).map(|_| true)
+    }
+
+    /// Gets the status of every worker.
+    pub(crate) fn statuses(&self) -> Vec<WorkerStatus> {
+        self.workers.lock().iter().map(Worker::status).collect()
+    }
+
+    /// Waits up to `timeout` for the workers, except the I/O worker, to finish, and joins them.
+    pub(crate) fn join(&self, timeout: time::Duration) -> Result<()> {
+        let start = time::Instant::now();
+
+        loop {
+            let running = {
+                let mut workers = self.workers.lock();
+                Self::join_finished(&mut workers, |w| w.kind != WorkerKind::Io);
+                workers.iter().any(|w| w.kind != WorkerKind::Io)
+            };
+
+            if !running {
+                return Ok(());
+            }
+
+            if start.elapsed() >= timeout {
+                return Err(ssp::Error::Timeout("waiting for workers to stop".into()));
+            }
+
+            thread::sleep(time::Duration::from_millis(JOIN_POLL_MS));
+        }
+    }
+
+    fn start<F>(
+        workers: &mut Vec<Worker>,
+        kind: WorkerKind,
+        events: &Arc<Mutex<Option<channel::Sender<ssp::Event>>>>,
+        work: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> Result<()> + Send + 'static,
+    {
+        // restarted workers replace the finished ones
+        Self::join_finished(workers, |w| w.kind == kind);
+
+        let events = Arc::clone(events);
+        let panicked = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&panicked);
+
+        let thread = thread::Builder::new()
+            .name(format!("ssp-{kind}"))
+            .spawn(move || {
+                let err = match panic::catch_unwind(AssertUnwindSafe(work)) {
+                    Ok(Ok(())) => {
+                        log::debug!("{kind} worker stopped");
+                        return;
+                    }
+                    Ok(Err(err)) => err,
+                    Err(payload) => {
+                        flag.store(true, Ordering::SeqCst);
+                        ssp::Error::Event(format!(
+                            "{kind} worker panicked: {}",
+                            panic_message(payload.as_ref())
+                        ))
+                    }
+                };
+
+                log::error!("{kind} worker failed: {err}");
+
+                if let Some(tx) = events.lock().as_ref() {
+                    let event = ssp::Event::new(ssp::Method::Fail, ssp::EventPayload::Error(err));
+                    if let Err(err) = tx.send(event) {
+                        log::warn!("Failed to send fail event: {err}");
+                    }
+                }
+            })?;
+
+        workers.push(Worker {
+            kind,
+            thread,
+            panicked,
+        });
+
+        Ok(())
+    }
+
+    // Joins, and removes the finished workers matching `filter`.
+    fn join_finished(workers: &mut Vec<Worker>, filter: impl Fn(&Worker) -> bool) {
+        let mut idx = 0;
+        while idx < workers.len() {
+            if filter(&workers[idx]) && workers[idx].thread.is_finished() {
+                // panics are caught by the worker, so joining never fails
+                let _ = workers.swap_remove(idx).thread.join();
+            } else {
+                idx += 1;
+            }
+        }
+    }
+}
+
+// Gets the message of a panic payload.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
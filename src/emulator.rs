@@ -0,0 +1,1200 @@
+//! Device-side emulator of the SSP/eSSP protocol.
+//!
+//! An [Emulator] answers host commands the way a banknote validator does: it tracks the
+//! sequence flag, resends its last response to a retransmitted command, reports queued events on
+//! [Poll](ssp::MessageType::Poll), and negotiates eSSP keys, decrypting encrypted commands, and
+//! encrypting their responses. Tests insert notes with [Emulator::insert_note], and jam them
+//! with [Emulator::jam], to drive the read, escrow, stack, and credit event sequences.
+//!
+//! With a payout unit type, e.g. [UNIT_SMART_PAYOUT], the emulator also behaves like a payout
+//! device: credited notes are stored in the payout while it is enabled, and
+//! [PayoutByDenomination](ssp::MessageType::PayoutByDenomination) dispenses the stored notes one
+//! per poll. Tests set the note levels with [Emulator::with_levels], and interrupt a payout with
+//! [Emulator::jam_payout], or [Emulator::power_cycle], to drive the jammed, and incomplete payout
+//! event sequences.
+//!
+//! The emulator is driven with raw bytes from the wire, so it can sit behind any transport:
+//!
+//! - in memory, with an [EmulatorTransport] passed to
+//!   [DeviceHandle::from_transport](crate::DeviceHandle::from_transport)
+//! - over a serial line, or PTY, with [serve], e.g. in a [PtyLoopback] harness
+//! - in memory, behind a link that randomly drops, and comes back, with a [ChaosTransport]
+//! - in memory, sharing a multi-drop [EmulatorBus] with devices at other addresses, with a
+//!   [BusTransport]
+//!
+//! This lets the whole crate be exercised in CI, and by downstream users, without hardware.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use ssp::{len, Result, STEXN, STX};
+
+use crate::encryption::{UNIT_NV11, UNIT_SMART_HOPPER, UNIT_SMART_PAYOUT};
+use crate::entropy::{EntropySource, SeededEntropy};
+use crate::essp;
+use crate::framing::{self, FrameDecoder};
+use crate::transport::Transport;
+
+pub mod bus;
+pub mod chaos;
+pub mod pty;
+
+pub use bus::{BusTransport, EmulatorBus};
+pub use chaos::{ChaosControl, ChaosSchedule, ChaosTransport};
+pub use pty::PtyLoopback;
+
+/// Default channel values of an [Emulator], in the currency's base unit.
+pub const DEFAULT_CHANNELS: [u32; 7] = [5, 10, 20, 50, 100, 200, 500];
+/// Default dataset version of an [Emulator].
+pub const DEFAULT_DATASET_VERSION: &str = "EUR01610";
+/// Default serial number of an [Emulator].
+pub const DEFAULT_SERIAL_NUMBER: u32 = 0x0102_0304;
+/// Highest protocol version supported by an [Emulator].
+pub const MAX_PROTOCOL_VERSION: u8 = 8;
+/// Last reject code after a note is accepted.
+pub const REJECT_NOTE_ACCEPTED: u8 = 0x00;
+/// Last reject code after a note is rejected on an inhibited channel.
+pub const REJECT_CHANNEL_INHIBITED: u8 = 0x06;
+/// Last reject code after the host rejects a note in escrow.
+pub const REJECT_HOST_REJECTED: u8 = 0x08;
+/// Number of notes the payout of an [Emulator] stores, further notes go to the cashbox.
+pub const PAYOUT_CAPACITY: u32 = 80;
+/// Factor from a channel value to the payout value, in the lowest currency unit.
+pub const PAYOUT_VALUE_MULTIPLIER: u32 = 100;
+/// [PayoutByDenomination](ssp::MessageType::PayoutByDenomination) option testing the payout.
+pub const PAYOUT_OPTION_TEST: u8 = 0x19;
+/// [PayoutByDenomination](ssp::MessageType::PayoutByDenomination) option dispensing the notes.
+pub const PAYOUT_OPTION_PAYOUT: u8 = 0x58;
+/// Payout error: the stored value is lower than the requested value.
+pub const PAYOUT_ERROR_NOT_ENOUGH_VALUE: u8 = 0x00;
+/// Payout error: the requested notes are not stored.
+pub const PAYOUT_ERROR_EXACT_AMOUNT: u8 = 0x01;
+/// Payout error: another payout is in progress.
+pub const PAYOUT_ERROR_BUSY: u8 = 0x03;
+/// Payout error: the payout is disabled.
+pub const PAYOUT_ERROR_DISABLED: u8 = 0x04;
+/// `Dispensing` event status, with the value dispensed so far.
+pub const EVENT_DISPENSING: u8 = 0xda;
+/// `Dispensed` event status, with the value dispensed by the payout.
+pub const EVENT_DISPENSED: u8 = 0xd2;
+/// `Jammed` event status, with the value dispensed before the jam.
+pub const EVENT_JAMMED: u8 = 0xd5;
+/// `Incomplete payout` event status, with the dispensed, and requested values.
+pub const EVENT_INCOMPLETE_PAYOUT: u8 = 0xdc;
+/// `Emptying` event status.
+pub const EVENT_EMPTYING: u8 = 0xc2;
+/// `Emptied` event status.
+pub const EVENT_EMPTIED: u8 = 0xc3;
+/// `Smart emptying` event status, with the value moved to the cashbox so far.
+pub const EVENT_SMART_EMPTYING: u8 = 0xb3;
+/// `Smart emptied` event status, with the value moved to the cashbox.
+pub const EVENT_SMART_EMPTIED: u8 = 0xb4;
+
+/// Position of a note inserted with [Emulator::insert_note].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoteState {
+    /// No note in the device.
+    #[default]
+    Idle,
+    /// The note on the channel is being read, and moves into escrow once reported.
+    Reading(u8),
+    /// The note on the channel is held in escrow, until the host polls, or rejects it.
+    Escrow(u8),
+    /// The note on the channel, `0` if not yet identified, is jammed inside the device.
+    Jammed(u8),
+}
+
+/// Progress of a payout started with
+/// [PayoutByDenomination](ssp::MessageType::PayoutByDenomination).
+///
+/// Values are in the lowest currency unit, see [PAYOUT_VALUE_MULTIPLIER].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PayoutState {
+    /// No payout in progress.
+    #[default]
+    Idle,
+    /// Notes are dispensed, one per poll.
+    Dispensing {
+        /// Value dispensed so far.
+        dispensed: u32,
+        /// Value requested by the host.
+        requested: u32,
+    },
+    /// A note jammed in the payout, until [clear_payout_jam](Emulator::clear_payout_jam).
+    Jammed {
+        /// Value dispensed before the jam.
+        dispensed: u32,
+        /// Value requested by the host.
+        requested: u32,
+    },
+}
+
+/// Emulated SSP/eSSP banknote validator.
+#[derive(Clone, Debug)]
+pub struct Emulator {
+    address: u8,
+    unit_type: u8,
+    firmware_version: [u8; 4],
+    country_code: [u8; 3],
+    serial_number: u32,
+    dataset_version: String,
+    channels: Vec<u32>,
+    protocol_version: u8,
+    enabled: bool,
+    display_on: bool,
+    inhibits: Vec<u8>,
+    events: VecDeque<Vec<u8>>,
+    note: NoteState,
+    last_reject_code: u8,
+    levels: Vec<u32>,
+    payout_enabled: bool,
+    payout: PayoutState,
+    payout_notes: VecDeque<u8>,
+    decoder: FrameDecoder,
+    last_command: Vec<u8>,
+    last_response: Vec<u8>,
+    generator: Option<ssp::GeneratorKey>,
+    modulus: Option<ssp::ModulusKey>,
+    fixed_key: ssp::FixedKey,
+    key: Option<ssp::AesKey>,
+    count: u32,
+    entropy: Option<SeededEntropy>,
+}
+
+impl Emulator {
+    /// Creates a new [Emulator], as just powered on: disabled, with all channels inhibited.
+    pub fn new() -> Self {
+        let mut emulator = Self {
+            address: 0,
+            unit_type: 0,
+            firmware_version: *b"0400",
+            country_code: *b"EUR",
+            serial_number: DEFAULT_SERIAL_NUMBER,
+            dataset_version: DEFAULT_DATASET_VERSION.into(),
+            channels: DEFAULT_CHANNELS.into(),
+            protocol_version: ssp::ProtocolVersion::Six as u8,
+            enabled: false,
+            display_on: true,
+            inhibits: Vec::new(),
+            events: VecDeque::new(),
+            note: NoteState::Idle,
+            last_reject_code: REJECT_NOTE_ACCEPTED,
+            levels: vec![0; DEFAULT_CHANNELS.len()],
+            payout_enabled: false,
+            payout: PayoutState::Idle,
+            payout_notes: VecDeque::new(),
+            decoder: FrameDecoder::new(),
+            last_command: Vec::new(),
+            last_response: Vec::new(),
+            generator: None,
+            modulus: None,
+            fixed_key: ssp::FixedKey::new(),
+            key: None,
+            count: 0,
+            entropy: None,
+        };
+
+        emulator.power_cycle();
+
+        emulator
+    }
+
+    /// Builder function that sets the device address, `0` for a banknote validator.
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address & 0x7f;
+        self
+    }
+
+    /// Builder function that sets the unit type reported by the setup request.
+    pub fn with_unit_type(mut self, unit_type: u8) -> Self {
+        self.unit_type = unit_type;
+        self
+    }
+
+    /// Builder function that sets the serial number.
+    pub fn with_serial_number(mut self, serial_number: u32) -> Self {
+        self.serial_number = serial_number;
+        self
+    }
+
+    /// Builder function that sets the dataset version.
+    pub fn with_dataset_version(mut self, dataset_version: &str) -> Self {
+        self.dataset_version = dataset_version.into();
+        self
+    }
+
+    /// Builder function that sets the channel values, at most 16 channels are kept.
+    pub fn with_channels(mut self, channels: &[u32]) -> Self {
+        self.channels = channels.iter().take(16).copied().collect();
+        self.levels.resize(self.channels.len(), 0);
+        self
+    }
+
+    /// Builder function that sets the number of notes stored in the payout, per channel.
+    ///
+    /// Levels past the last channel are ignored, missing levels are zero.
+    pub fn with_levels(mut self, levels: &[u32]) -> Self {
+        self.levels = levels.iter().take(self.channels.len()).copied().collect();
+        self.levels.resize(self.channels.len(), 0);
+        self
+    }
+
+    /// Builder function that sets the [FixedKey](ssp::FixedKey) used for key negotiation.
+    pub fn with_fixed_key(mut self, fixed_key: ssp::FixedKey) -> Self {
+        self.fixed_key = fixed_key;
+        self
+    }
+
+    /// Builder function that sets a [SeededEntropy] source for the device-side random key of the
+    /// key exchange.
+    ///
+    /// With a seeded host, see
+    /// [with_entropy_source](crate::DeviceHandle::with_entropy_source), every key exchange
+    /// produces the same intermediate keys, and session key, to assert against fixtures. By
+    /// default, the random key comes from system entropy.
+    pub fn with_entropy(mut self, entropy: SeededEntropy) -> Self {
+        self.entropy = Some(entropy);
+        self
+    }
+
+    /// Gets the device address.
+    pub const fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Gets the channel values.
+    pub fn channels(&self) -> &[u32] {
+        self.channels.as_ref()
+    }
+
+    /// Gets the protocol version set by the host.
+    pub const fn protocol_version(&self) -> u8 {
+        self.protocol_version
+    }
+
+    /// Gets whether the device is enabled.
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Gets whether the bezel display is on.
+    pub const fn display_on(&self) -> bool {
+        self.display_on
+    }
+
+    /// Gets the inhibit bitfields set by the host, a set bit enables the channel.
+    pub fn inhibits(&self) -> &[u8] {
+        self.inhibits.as_ref()
+    }
+
+    /// Gets whether the `channel`, counting from one, accepts notes.
+    pub fn channel_enabled(&self, channel: u8) -> bool {
+        let idx = usize::from(channel).wrapping_sub(1);
+
+        idx < self.channels.len()
+            && self
+                .inhibits
+                .get(idx / 8)
+                .map(|&bits| bits & (1 << (idx % 8)) != 0)
+                .unwrap_or(false)
+    }
+
+    /// Gets whether an eSSP session key is negotiated.
+    pub const fn encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Gets the eSSP sequence count expected in the next encrypted command.
+    pub const fn sequence_count(&self) -> u32 {
+        self.count
+    }
+
+    /// Gets the number of polls with queued events.
+    pub fn pending_events(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Gets the position of the note inserted with [insert_note](Self::insert_note).
+    pub const fn note(&self) -> NoteState {
+        self.note
+    }
+
+    /// Gets the code reported for the last rejected note.
+    pub const fn last_reject_code(&self) -> u8 {
+        self.last_reject_code
+    }
+
+    /// Gets whether the unit type is a payout device.
+    pub const fn payout_unit(&self) -> bool {
+        matches!(
+            self.unit_type,
+            UNIT_SMART_HOPPER | UNIT_SMART_PAYOUT | UNIT_NV11
+        )
+    }
+
+    /// Gets whether the payout is enabled by the host.
+    pub const fn payout_enabled(&self) -> bool {
+        self.payout_enabled
+    }
+
+    /// Gets the number of notes stored in the payout, per channel.
+    pub fn levels(&self) -> &[u32] {
+        self.levels.as_ref()
+    }
+
+    /// Gets the number of notes stored in the payout on the `channel`, counting from one.
+    pub fn level(&self, channel: u8) -> u32 {
+        let idx = usize::from(channel).wrapping_sub(1);
+        self.levels.get(idx).copied().unwrap_or(0)
+    }
+
+    /// Gets the progress of the current payout.
+    pub const fn payout_state(&self) -> PayoutState {
+        self.payout
+    }
+
+    /// Queues an event, reported by the next poll without other events.
+    ///
+    /// `data` holds the event status, followed by any event data, e.g. `[0xef, 1]` for a
+    /// [Read](ssp::ResponseStatus::Read) event on channel one.
+    pub fn push_event(&mut self, data: &[u8]) {
+        self.events.push_back(data.into());
+    }
+
+    /// Accepts a note on the `channel`, counting from one.
+    ///
+    /// The note is read into escrow, stacked, and credited over the following polls.
+    ///
+    /// Returns `Err(_)` if the device is disabled, or the channel does not accept notes.
+    pub fn credit(&mut self, channel: u8) -> Result<()> {
+        use ssp::ResponseStatus::{ChannelDisable, Disabled, NoteCredit, Read, Stacked, Stacking};
+
+        if !self.enabled {
+            return Err(ssp::Error::Status(Disabled));
+        }
+
+        if !self.channel_enabled(channel) {
+            return Err(ssp::Error::Status(ChannelDisable));
+        }
+
+        self.push_event(&[Read.into(), channel]);
+        self.push_event(&[Stacking.into()]);
+        self.push_event(&[NoteCredit.into(), channel, Stacked.into()]);
+        self.store_note(channel);
+
+        log::debug!("Emulator accepted a note on channel {channel}");
+
+        Ok(())
+    }
+
+    /// Inserts a note on the `channel`, counting from one, and drives it through escrow.
+    ///
+    /// The note is read over the following polls, and reported in escrow with a
+    /// [Read](ssp::ResponseStatus::Read) event on its channel. There it stays until the host
+    /// acts: the next poll stacks, and credits it, a [Hold](ssp::MessageType::Hold) keeps it in
+    /// escrow, and a [Reject](ssp::MessageType::Reject) returns it. A note on an inhibited, or
+    /// unknown, channel is rejected once read, like a real device does.
+    ///
+    /// Returns `Err(_)` if the device is disabled, or another note is still inside.
+    pub fn insert_note(&mut self, channel: u8) -> Result<()> {
+        use ssp::ResponseStatus::{CommandCannotBeProcessed, Disabled, Read, Rejected, Rejecting};
+
+        if !self.enabled {
+            return Err(ssp::Error::Status(Disabled));
+        }
+
+        if self.note != NoteState::Idle {
+            return Err(ssp::Error::Status(CommandCannotBeProcessed));
+        }
+
+        self.push_event(&[Read.into(), 0]);
+
+        if self.channel_enabled(channel) {
+            self.push_event(&[Read.into(), channel]);
+            self.note = NoteState::Reading(channel);
+
+            log::debug!("Emulator reading a note on channel {channel}");
+        } else {
+            self.push_event(&[Rejecting.into()]);
+            self.push_event(&[Rejected.into()]);
+            self.last_reject_code = REJECT_CHANNEL_INHIBITED;
+
+            log::debug!("Emulator rejecting a note on inhibited channel {channel}");
+        }
+
+        Ok(())
+    }
+
+    /// Jams the inserted note inside the device.
+    ///
+    /// Queued events are dropped, and every poll reports an
+    /// [UnsafeJam](ssp::ResponseStatus::UnsafeJam) until [clear_jam](Self::clear_jam).
+    pub fn jam(&mut self) {
+        let channel = match self.note {
+            NoteState::Escrow(channel) | NoteState::Jammed(channel) => channel,
+            // the note is stuck before the device identified it
+            NoteState::Idle | NoteState::Reading(_) => 0,
+        };
+
+        self.events.clear();
+        self.note = NoteState::Jammed(channel);
+
+        log::debug!("Emulator jammed a note on channel {channel}");
+    }
+
+    /// Clears a jam, returning the note to the front of the device.
+    ///
+    /// Reports a [NoteClearedFromFront](ssp::ResponseStatus::NoteClearedFromFront) event with the
+    /// channel of the note. Does nothing if no note is jammed.
+    pub fn clear_jam(&mut self) {
+        if let NoteState::Jammed(channel) = self.note {
+            self.push_event(&[ssp::ResponseStatus::NoteClearedFromFront.into(), channel]);
+            self.note = NoteState::Idle;
+        }
+    }
+
+    /// Jams the note being dispensed in the payout.
+    ///
+    /// Every poll reports a `Jammed` event with the value dispensed so far, until
+    /// [clear_payout_jam](Self::clear_payout_jam). Does nothing if no payout is in progress.
+    pub fn jam_payout(&mut self) {
+        if let PayoutState::Dispensing {
+            dispensed,
+            requested,
+        } = self.payout
+        {
+            self.payout = PayoutState::Jammed {
+                dispensed,
+                requested,
+            };
+
+            log::debug!("Emulator jammed the payout after dispensing {dispensed}");
+        }
+    }
+
+    /// Clears a payout jam, abandoning the payout.
+    ///
+    /// Reports an `Incomplete payout` event with the dispensed, and requested values. The notes
+    /// not dispensed stay in the payout. Does nothing if the payout is not jammed.
+    pub fn clear_payout_jam(&mut self) {
+        if let PayoutState::Jammed {
+            dispensed,
+            requested,
+        } = self.payout
+        {
+            self.abandon_payout(dispensed, requested);
+        }
+    }
+
+    /// Power cycles the device: it comes back disabled, with all channels inhibited, without an
+    /// eSSP session, and reports a [DeviceReset](ssp::ResponseStatus::DeviceReset) event.
+    ///
+    /// A payout in progress is abandoned, and reported with an `Incomplete payout` event after
+    /// the reset.
+    pub fn power_cycle(&mut self) {
+        let payout = self.payout;
+
+        self.enabled = false;
+        self.payout_enabled = false;
+        self.payout = PayoutState::Idle;
+        self.payout_notes.clear();
+        self.note = NoteState::Idle;
+        self.inhibits = vec![0; self.channels.len().div_ceil(8).max(1)];
+        self.events.clear();
+        self.decoder.reset();
+        self.last_command.clear();
+        self.last_response.clear();
+        self.generator = None;
+        self.modulus = None;
+        self.key = None;
+        self.count = 0;
+
+        self.push_event(&[ssp::ResponseStatus::DeviceReset.into()]);
+
+        if let PayoutState::Dispensing {
+            dispensed,
+            requested,
+        }
+        | PayoutState::Jammed {
+            dispensed,
+            requested,
+        } = payout
+        {
+            self.abandon_payout(dispensed, requested);
+        }
+    }
+
+    /// Receives `bytes` from the wire, and returns the stuffed responses to any completed
+    /// commands.
+    ///
+    /// Garbage between frames is skipped, frames with a bad CRC, or for another address, are
+    /// ignored.
+    pub fn receive(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        for &byte in bytes {
+            let started = !self.decoder.frame().is_empty();
+
+            match self.decoder.push(byte) {
+                Ok(true) => {
+                    let frame = self.decoder.frame().to_vec();
+                    self.decoder.reset();
+
+                    if let Some(response) = self.handle_frame(&frame) {
+                        out.extend_from_slice(&response);
+                    }
+                }
+                Ok(false) => (),
+                Err(_) => {
+                    self.decoder.reset();
+
+                    if byte == STX {
+                        let _ = self.decoder.push(byte);
+                    } else if started {
+                        // an unpaired STX truncated the current frame, and starts the next one
+                        let _ = self.decoder.push(STX);
+                        let _ = self.decoder.push(byte);
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Handles a single unstuffed command `frame`, and returns the stuffed response, if any.
+    pub fn handle_frame(&mut self, frame: &[u8]) -> Option<Vec<u8>> {
+        use ssp::message::index;
+
+        if frame.len() < len::METADATA || frame[0] != STX {
+            return None;
+        }
+
+        let crc_start = frame.len() - 2;
+        let crc = u16::from_le_bytes([frame[crc_start], frame[crc_start + 1]]);
+        if crc != ssp::crc::crc16(&frame[index::SEQ_ID..crc_start]) {
+            log::warn!("Emulator received a command with an invalid CRC");
+            return None;
+        }
+
+        let seq_id = frame[index::SEQ_ID];
+        if seq_id & 0x7f != self.address {
+            return None;
+        }
+
+        // a retransmission gets the last response again, without executing the command twice
+        if frame == self.last_command.as_slice() {
+            log::debug!("Emulator resending the last response");
+            return Some(self.last_response.clone());
+        }
+
+        let data = &frame[index::DATA..crc_start];
+
+        let response = if data.first() == Some(&STEXN) {
+            self.handle_encrypted(data)?
+        } else {
+            self.execute(data)?
+        };
+
+        let response = Self::frame_response(seq_id, &response).ok()?;
+
+        self.last_command = frame.into();
+        self.last_response = response.clone();
+
+        Some(response)
+    }
+
+    // Builds the stuffed response frame with the `data`, echoing the `seq_id` of the command.
+    fn frame_response(seq_id: u8, data: &[u8]) -> Result<Vec<u8>> {
+        let mut frame = Vec::with_capacity(data.len() + len::METADATA);
+        frame.extend_from_slice(&[STX, seq_id, data.len() as u8]);
+        frame.extend_from_slice(data);
+
+        let crc = ssp::crc::crc16(&frame[1..]);
+        frame.extend_from_slice(crc.to_le_bytes().as_ref());
+
+        framing::stuff(&frame)
+    }
+
+    // Decrypts an encrypted command, and returns the encrypted response data.
+    //
+    // Commands that fail to decrypt, or carry the wrong sequence count, are ignored, like a real
+    // device does.
+    fn handle_encrypted(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        use ssp::ResponseStatus::KeyNotSet;
+
+        let Some(key) = self.key else {
+            log::warn!("Emulator received an encrypted command without a session key");
+            return Some(vec![KeyNotSet.into()]);
+        };
+
+        let packet = match essp::decrypt(&key, data) {
+            Ok(packet) => packet,
+            Err(err) => {
+                log::warn!("Emulator failed to decrypt a command: {err}");
+                return None;
+            }
+        };
+
+        if packet.count() != self.count {
+            log::warn!(
+                "Emulator received eSSP count {}, expected: {}",
+                packet.count(),
+                self.count
+            );
+            return None;
+        }
+        self.count = self.count.wrapping_add(1);
+
+        let response = self.execute(packet.data())?;
+
+        // packing is random on a real device, the host never interprets it
+        match essp::encrypt(&key, &essp::Packet::new(self.count, &response)) {
+            Ok(encrypted) => Some(encrypted),
+            Err(err) => {
+                log::error!("Emulator failed to encrypt a response: {err}");
+                None
+            }
+        }
+    }
+
+    // Executes the command `data`, and returns the response data, starting with the status.
+    fn execute(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        use ssp::MessageType as Msg;
+        use ssp::ResponseStatus::{
+            CommandCannotBeProcessed, CommandNotKnown, Disabled, Fail, Ok, ParameterOutOfRange,
+            WrongNumberParameters,
+        };
+
+        let (&command, params) = data.split_first()?;
+
+        let status = |status: ssp::ResponseStatus| Some(vec![u8::from(status)]);
+
+        log::trace!("Emulator executing command 0x{command:02x}");
+
+        match Msg::from(command) {
+            Msg::Reset => {
+                // the device restarts without answering
+                self.power_cycle();
+                None
+            }
+            Msg::Synchronisation => status(Ok),
+            Msg::Poll => {
+                let mut res = vec![Ok.into()];
+                if let Some(event) = self.poll_event() {
+                    res.extend(event);
+                }
+                if !self.enabled {
+                    res.push(Disabled.into());
+                }
+                Some(res)
+            }
+            Msg::Enable => {
+                self.enabled = true;
+                status(Ok)
+            }
+            Msg::Disable => {
+                self.enabled = false;
+                status(Ok)
+            }
+            Msg::DisplayOn | Msg::DisplayOff => {
+                self.display_on = Msg::from(command) == Msg::DisplayOn;
+                status(Ok)
+            }
+            Msg::Hold => status(Ok),
+            Msg::Reject => {
+                if let NoteState::Escrow(_) = self.note {
+                    self.push_event(&[ssp::ResponseStatus::Rejecting.into()]);
+                    self.push_event(&[ssp::ResponseStatus::Rejected.into()]);
+                    self.note = NoteState::Idle;
+                    self.last_reject_code = REJECT_HOST_REJECTED;
+                }
+                status(Ok)
+            }
+            Msg::SetInhibits if params.is_empty() => status(WrongNumberParameters),
+            Msg::SetInhibits => {
+                self.inhibits = params.into();
+                status(Ok)
+            }
+            Msg::HostProtocolVersion => match params.first() {
+                Some(&version) if (1..=MAX_PROTOCOL_VERSION).contains(&version) => {
+                    self.protocol_version = version;
+                    status(Ok)
+                }
+                Some(_) => status(Fail),
+                None => status(WrongNumberParameters),
+            },
+            Msg::SetupRequest => Some(self.setup_request()),
+            Msg::UnitData => {
+                let mut res = vec![Ok.into(), self.unit_type];
+                res.extend_from_slice(&self.firmware_version);
+                res.extend_from_slice(&self.country_code);
+                res.extend_from_slice(&[0, 0, 1, self.protocol_version]);
+                Some(res)
+            }
+            Msg::SerialNumber => {
+                let mut res = vec![Ok.into()];
+                res.extend_from_slice(self.serial_number.to_be_bytes().as_ref());
+                Some(res)
+            }
+            Msg::DatasetVersion => {
+                let mut res = vec![Ok.into()];
+                res.extend_from_slice(self.dataset_version.as_bytes());
+                Some(res)
+            }
+            Msg::ChannelValueData => {
+                let mut res = vec![Ok.into(), self.channels.len() as u8];
+                res.extend(self.channels.iter().map(|&v| v.min(0xff) as u8));
+                Some(res)
+            }
+            Msg::LastRejectCode => Some(vec![Ok.into(), self.last_reject_code]),
+            Msg::EnablePayout
+            | Msg::DisablePayout
+            | Msg::PayoutByDenomination
+            | Msg::Empty
+            | Msg::SmartEmpty
+                if !self.payout_unit() =>
+            {
+                status(CommandNotKnown)
+            }
+            Msg::EnablePayout => {
+                self.payout_enabled = true;
+                status(Ok)
+            }
+            Msg::DisablePayout => {
+                self.payout_enabled = false;
+                status(Ok)
+            }
+            Msg::PayoutByDenomination => Some(self.payout_by_denomination(params)),
+            Msg::Empty | Msg::SmartEmpty if self.payout != PayoutState::Idle => {
+                Some(vec![CommandCannotBeProcessed.into(), PAYOUT_ERROR_BUSY])
+            }
+            Msg::Empty => {
+                self.levels.iter_mut().for_each(|level| *level = 0);
+                self.push_event(&[EVENT_EMPTYING]);
+                self.push_event(&[EVENT_EMPTIED]);
+                status(Ok)
+            }
+            Msg::SmartEmpty => {
+                let value = self.stored_value();
+                self.levels.iter_mut().for_each(|level| *level = 0);
+
+                let emptying = self.value_event(EVENT_SMART_EMPTYING, value);
+                let emptied = self.value_event(EVENT_SMART_EMPTIED, value);
+                self.push_event(&emptying);
+                self.push_event(&emptied);
+
+                status(Ok)
+            }
+            Msg::SetGenerator | Msg::SetModulus | Msg::SetEncryptionKey if params.len() != 8 => {
+                status(WrongNumberParameters)
+            }
+            Msg::SetGenerator => {
+                self.generator = Some(Self::key_param(params).into());
+                status(Ok)
+            }
+            Msg::SetModulus => {
+                self.modulus = Some(Self::key_param(params).into());
+                status(Ok)
+            }
+            Msg::RequestKeyExchange if params.len() != 8 => status(WrongNumberParameters),
+            Msg::RequestKeyExchange => {
+                let (Some(generator), Some(modulus)) =
+                    (self.generator.as_ref(), self.modulus.as_ref())
+                else {
+                    return status(CommandCannotBeProcessed);
+                };
+
+                if generator.as_inner() == 0 || modulus.as_inner() == 0 {
+                    return status(ParameterOutOfRange);
+                }
+
+                let host_key = ssp::IntermediateKey::from(Self::key_param(params));
+                let random = match self.entropy.as_mut() {
+                    Some(entropy) => entropy.random_key(),
+                    None => ssp::RandomKey::from_entropy(),
+                };
+
+                let inter_key = ssp::IntermediateKey::from_keys(generator, &random, modulus);
+                let enc_key = ssp::EncryptionKey::from_keys(&host_key, &random, modulus);
+
+                let mut key = ssp::AesKey::from(&self.fixed_key);
+                key[8..].copy_from_slice(enc_key.as_inner().to_le_bytes().as_ref());
+
+                self.key = Some(key);
+                self.count = 0;
+
+                log::debug!("Emulator negotiated a new eSSP session key");
+
+                let mut res = vec![Ok.into()];
+                res.extend_from_slice(inter_key.as_inner().to_le_bytes().as_ref());
+                Some(res)
+            }
+            Msg::SetEncryptionKey => {
+                // takes effect with the next key negotiation
+                self.fixed_key = Self::key_param(params).into();
+                status(Ok)
+            }
+            Msg::EncryptionReset => {
+                self.fixed_key = ssp::FixedKey::new();
+                status(Ok)
+            }
+            _ => status(CommandNotKnown),
+        }
+    }
+
+    // Gets the event reported by the next poll, moving an inserted note along.
+    fn poll_event(&mut self) -> Option<Vec<u8>> {
+        use ssp::ResponseStatus::{NoteCredit, Stacked, Stacking, UnsafeJam};
+
+        if self.events.is_empty() {
+            match self.note {
+                // the host polled instead of holding, or rejecting, the note in escrow
+                NoteState::Escrow(channel) => {
+                    self.push_event(&[Stacking.into()]);
+                    self.push_event(&[NoteCredit.into(), channel, Stacked.into()]);
+                    self.store_note(channel);
+                    self.note = NoteState::Idle;
+                    self.last_reject_code = REJECT_NOTE_ACCEPTED;
+                }
+                NoteState::Jammed(_) => return Some(vec![UnsafeJam.into()]),
+                NoteState::Idle | NoteState::Reading(_) => (),
+            }
+        }
+
+        if self.events.is_empty() {
+            match self.payout {
+                PayoutState::Dispensing {
+                    dispensed,
+                    requested,
+                } => match self.payout_notes.pop_front() {
+                    Some(channel) => {
+                        let idx = usize::from(channel) - 1;
+                        self.levels[idx] -= 1;
+
+                        let dispensed = dispensed + self.payout_value(channel);
+                        self.payout = PayoutState::Dispensing {
+                            dispensed,
+                            requested,
+                        };
+
+                        return Some(self.value_event(EVENT_DISPENSING, dispensed));
+                    }
+                    None => {
+                        self.payout = PayoutState::Idle;
+
+                        log::debug!("Emulator dispensed {dispensed}");
+
+                        return Some(self.value_event(EVENT_DISPENSED, dispensed));
+                    }
+                },
+                PayoutState::Jammed { dispensed, .. } => {
+                    return Some(self.value_event(EVENT_JAMMED, dispensed))
+                }
+                PayoutState::Idle => (),
+            }
+        }
+
+        let event = self.events.pop_front()?;
+
+        // the note is in escrow once its Read event with the channel is reported
+        if let NoteState::Reading(channel) = self.note {
+            if event == [ssp::ResponseStatus::Read.into(), channel] {
+                self.note = NoteState::Escrow(channel);
+            }
+        }
+
+        Some(event)
+    }
+
+    // Validates a PayoutByDenomination command, and starts the payout, unless only testing it.
+    //
+    // Every denomination holds the number of notes (2 bytes), the value (4 bytes), and the
+    // country code (3 bytes), the option byte comes last.
+    fn payout_by_denomination(&mut self, params: &[u8]) -> Vec<u8> {
+        use ssp::ResponseStatus::{
+            CommandCannotBeProcessed, Ok, ParameterOutOfRange, WrongNumberParameters,
+        };
+
+        let error = |code: u8| vec![CommandCannotBeProcessed.into(), code];
+
+        let Some((&count, rest)) = params.split_first() else {
+            return vec![WrongNumberParameters.into()];
+        };
+        if rest.len() != usize::from(count) * 9 + 1 {
+            return vec![WrongNumberParameters.into()];
+        }
+        let (denominations, option) = rest.split_at(rest.len() - 1);
+
+        if self.payout != PayoutState::Idle {
+            return error(PAYOUT_ERROR_BUSY);
+        }
+        if !self.payout_enabled {
+            return error(PAYOUT_ERROR_DISABLED);
+        }
+
+        let mut notes = Vec::new();
+        let mut requested = 0u32;
+        let mut exact = true;
+
+        for denomination in denominations.chunks_exact(9) {
+            let count = u16::from_le_bytes([denomination[0], denomination[1]]);
+            let value = u32::from_le_bytes([
+                denomination[2],
+                denomination[3],
+                denomination[4],
+                denomination[5],
+            ]);
+
+            requested = requested.saturating_add(value.saturating_mul(count.into()));
+
+            let channel = (1..=self.channels.len() as u8).find(|&channel| {
+                self.payout_value(channel) == value && denomination[6..] == self.country_code
+            });
+
+            match channel {
+                Some(channel) => notes.extend((0..count).map(|_| channel)),
+                None => exact &= count == 0,
+            }
+        }
+
+        if requested > self.stored_value() {
+            return error(PAYOUT_ERROR_NOT_ENOUGH_VALUE);
+        }
+
+        let available = (1..=self.channels.len() as u8).all(|channel| {
+            notes.iter().filter(|&&c| c == channel).count() as u64 <= u64::from(self.level(channel))
+        });
+        if !exact || !available {
+            return error(PAYOUT_ERROR_EXACT_AMOUNT);
+        }
+
+        match option[0] {
+            PAYOUT_OPTION_TEST => vec![Ok.into()],
+            PAYOUT_OPTION_PAYOUT => {
+                self.payout = PayoutState::Dispensing {
+                    dispensed: 0,
+                    requested,
+                };
+                self.payout_notes = notes.into();
+
+                log::debug!("Emulator dispensing {requested}");
+
+                vec![Ok.into()]
+            }
+            _ => vec![ParameterOutOfRange.into()],
+        }
+    }
+
+    // Abandons the payout, reporting the dispensed, and requested values.
+    fn abandon_payout(&mut self, dispensed: u32, requested: u32) {
+        let mut event = vec![EVENT_INCOMPLETE_PAYOUT];
+        if self.protocol_version >= 6 {
+            event.push(1);
+        }
+        event.extend_from_slice(dispensed.to_le_bytes().as_ref());
+        event.extend_from_slice(requested.to_le_bytes().as_ref());
+        if self.protocol_version >= 6 {
+            event.extend_from_slice(&self.country_code);
+        }
+
+        self.push_event(&event);
+        self.payout = PayoutState::Idle;
+        self.payout_notes.clear();
+
+        log::debug!("Emulator abandoned a payout after dispensing {dispensed} of {requested}");
+    }
+
+    // Stores a credited note in the payout, if enabled, and not full, the cashbox takes it
+    // otherwise.
+    fn store_note(&mut self, channel: u8) {
+        let stored: u32 = self.levels.iter().sum();
+
+        if self.payout_enabled && stored < PAYOUT_CAPACITY {
+            if let Some(level) = self.levels.get_mut(usize::from(channel).wrapping_sub(1)) {
+                *level += 1;
+            }
+        }
+    }
+
+    // Gets the payout value of a note on the `channel`, counting from one.
+    fn payout_value(&self, channel: u8) -> u32 {
+        let idx = usize::from(channel).wrapping_sub(1);
+
+        self.channels
+            .get(idx)
+            .map(|&value| value.saturating_mul(PAYOUT_VALUE_MULTIPLIER))
+            .unwrap_or(0)
+    }
+
+    // Gets the total payout value of the stored notes.
+    fn stored_value(&self) -> u32 {
+        (1..=self.levels.len() as u8)
+            .map(|channel| {
+                self.payout_value(channel)
+                    .saturating_mul(self.level(channel))
+            })
+            .fold(0, u32::saturating_add)
+    }
+
+    // Builds a payout event with the `value`, for a single country since protocol version 6.
+    fn value_event(&self, status: u8, value: u32) -> Vec<u8> {
+        let mut event = vec![status];
+        if self.protocol_version >= 6 {
+            event.push(1);
+        }
+        event.extend_from_slice(value.to_le_bytes().as_ref());
+        if self.protocol_version >= 6 {
+            event.extend_from_slice(&self.country_code);
+        }
+
+        event
+    }
+
+    fn setup_request(&self) -> Vec<u8> {
+        let channels = self.channels.len() as u8;
+
+        let mut res = vec![ssp::ResponseStatus::Ok.into(), self.unit_type];
+        res.extend_from_slice(&self.firmware_version);
+        res.extend_from_slice(&self.country_code);
+        // value multiplier
+        res.extend_from_slice(&[0, 0, 1]);
+        res.push(channels);
+        res.extend(self.channels.iter().map(|&v| v.min(0xff) as u8));
+        // security levels
+        res.extend(self.channels.iter().map(|_| 2));
+        // real value multiplier
+        res.extend_from_slice(&[0, 0, 100]);
+        res.push(self.protocol_version);
+
+        if self.protocol_version >= 6 {
+            for _ in 0..channels {
+                res.extend_from_slice(&self.country_code);
+            }
+            for &value in self.channels.iter() {
+                res.extend_from_slice(value.to_le_bytes().as_ref());
+            }
+        }
+
+        res
+    }
+
+    fn key_param(params: &[u8]) -> u64 {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&params[..8]);
+        u64::from_le_bytes(buf)
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// In-memory [Transport] connected to an [Emulator].
+///
+/// Written commands are answered immediately, reads time out once every response is read.
+pub struct EmulatorTransport {
+    emulator: Arc<Mutex<Emulator>>,
+    wire: VecDeque<u8>,
+}
+
+impl EmulatorTransport {
+    /// Creates a new [EmulatorTransport] connected to the `emulator`.
+    pub fn new(emulator: Arc<Mutex<Emulator>>) -> Self {
+        Self {
+            emulator,
+            wire: VecDeque::new(),
+        }
+    }
+
+    /// Gets a reference to the connected [Emulator].
+    pub fn emulator(&self) -> &Arc<Mutex<Emulator>> {
+        &self.emulator
+    }
+}
+
+impl Read for EmulatorTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for EmulatorTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let response = self.emulator.lock().receive(buf);
+        self.wire.extend(response);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for EmulatorTransport {
+    fn clear(&mut self) -> Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        let discarded = self.wire.len();
+        self.wire.clear();
+
+        Ok(discarded)
+    }
+}
+
+/// Serves the `emulator` over a byte stream, e.g. one end of a PTY, until `stop` is set.
+///
+/// The `port` should time out reads, so the `stop` flag is checked regularly.
+pub fn serve<P: Read + Write>(
+    emulator: Arc<Mutex<Emulator>>,
+    mut port: P,
+    stop: Arc<AtomicBool>,
+) -> Result<()> {
+    let mut buf = [0u8; len::MAX_MESSAGE];
+
+    while !stop.load(Ordering::Relaxed) {
+        let n = match port.read(&mut buf) {
+            Ok(0) => continue,
+            Ok(n) => n,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::TimedOut
+                        | io::ErrorKind::WouldBlock
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                continue
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let response = emulator.lock().receive(&buf[..n]);
+
+        if !response.is_empty() {
+            port.write_all(&response)?;
+            port.flush()?;
+        }
+    }
+
+    Ok(())
+}
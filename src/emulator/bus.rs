@@ -0,0 +1,151 @@
+//! Multi-drop bus of emulated devices sharing one transport.
+//!
+//! SSP devices on the same serial line are told apart by the address in the low seven bits of
+//! the sequence ID, e.g. `0x00` for a banknote validator, and `0x10` for a coin hopper. An
+//! [EmulatorBus] hosts several [Emulator]s with different addresses, and a [BusTransport]
+//! connects a host to all of them at once: every device sees every frame on the wire, only the
+//! addressed device answers.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::transport::Transport;
+
+use super::Emulator;
+
+/// Emulated devices sharing a multi-drop bus.
+#[derive(Clone, Debug, Default)]
+pub struct EmulatorBus {
+    devices: Vec<Arc<Mutex<Emulator>>>,
+}
+
+impl EmulatorBus {
+    /// Creates a new empty [EmulatorBus].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the `emulator` to the bus, and returns the shared device.
+    ///
+    /// Returns `Err(_)` if a device already answers at the same address.
+    pub fn add(&mut self, emulator: Emulator) -> Result<Arc<Mutex<Emulator>>> {
+        let address = emulator.address();
+
+        if self.device(address).is_some() {
+            return Err(ssp::Error::Io(format!(
+                "a device is already on the bus at address {address:#04x}"
+            )));
+        }
+
+        let device = Arc::new(Mutex::new(emulator));
+        self.devices.push(Arc::clone(&device));
+
+        Ok(device)
+    }
+
+    /// Gets the device at the `address`, if any.
+    pub fn device(&self, address: u8) -> Option<&Arc<Mutex<Emulator>>> {
+        self.devices
+            .iter()
+            .find(|device| device.lock().address() == address & 0x7f)
+    }
+
+    /// Gets the addresses of the devices on the bus, in the order they were added.
+    pub fn addresses(&self) -> Vec<u8> {
+        self.devices
+            .iter()
+            .map(|device| device.lock().address())
+            .collect()
+    }
+
+    /// Gets the number of devices on the bus.
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// Gets whether the bus has no devices.
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Feeds `bytes` written by the host to every device on the bus, and returns the stuffed
+    /// responses of the addressed devices, in order.
+    ///
+    /// See [Emulator::receive].
+    pub fn receive(&self, bytes: &[u8]) -> Vec<u8> {
+        self.devices
+            .iter()
+            .flat_map(|device| device.lock().receive(bytes))
+            .collect()
+    }
+}
+
+/// In-memory [Transport] connected to every device on an [EmulatorBus].
+///
+/// Written commands are answered immediately by the addressed device, reads time out once every
+/// response is read, or if no device answers at the address.
+pub struct BusTransport {
+    bus: EmulatorBus,
+    wire: VecDeque<u8>,
+}
+
+impl BusTransport {
+    /// Creates a new [BusTransport] connected to the devices on the `bus`.
+    pub fn new(bus: EmulatorBus) -> Self {
+        Self {
+            bus,
+            wire: VecDeque::new(),
+        }
+    }
+
+    /// Gets a reference to the connected [EmulatorBus].
+    pub fn bus(&self) -> &EmulatorBus {
+        &self.bus
+    }
+}
+
+impl Read for BusTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for BusTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let response = self.bus.receive(buf);
+        self.wire.extend(response);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for BusTransport {
+    fn clear(&mut self) -> Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        let discarded = self.wire.len();
+        self.wire.clear();
+
+        Ok(discarded)
+    }
+}
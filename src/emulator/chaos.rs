@@ -0,0 +1,391 @@
+//! Chaos harness, severing and restoring the link to an [Emulator] mid-command.
+//!
+//! A [ChaosTransport] connects a host to an [Emulator] in memory, like an
+//! [EmulatorTransport](super::EmulatorTransport), but cuts the link at random points of an
+//! exchange: before, or partway through the command, or after the device executed it, before, or
+//! partway through its response. The link stays down for a number of host writes, then comes
+//! back, optionally with the device power cycled, as if it lost power with the cable.
+//!
+//! Cuts follow a seeded [ChaosSchedule], so a failing run can be reproduced from its seed. Tests
+//! read the disconnect counters, and sever, or restore the link by hand, through the shared
+//! [ChaosControl].
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::rng;
+use crate::transport::Transport;
+
+use super::Emulator;
+
+/// Point of an exchange where the link is cut.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cut {
+    /// The command never reaches the device.
+    BeforeCommand,
+    /// The device receives the command up to the byte offset, wrapped to the frame length.
+    MidCommand(usize),
+    /// The device executes the command, the response never reaches the host.
+    BeforeResponse,
+    /// The host receives the response up to the byte offset, wrapped to the frame length.
+    MidResponse(usize),
+}
+
+impl Cut {
+    /// Gets the [Cut] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::BeforeCommand => "before_command",
+            Self::MidCommand(_) => "mid_command",
+            Self::BeforeResponse => "before_response",
+            Self::MidResponse(_) => "mid_response",
+        }
+    }
+}
+
+impl fmt::Display for Cut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MidCommand(offset) | Self::MidResponse(offset) => {
+                write!(f, "{}({offset})", self.as_str())
+            }
+            _ => write!(f, "{}", self.as_str()),
+        }
+    }
+}
+
+/// How the link comes back after an outage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Reconnect {
+    /// The device kept its state, and session.
+    #[default]
+    Resume,
+    /// The device was power cycled, see [Emulator::power_cycle].
+    PowerCycle,
+}
+
+/// Seedable schedule of the link cuts of a [ChaosTransport].
+///
+/// Every host write while the link is up draws a possible cut. The link then stays down for a
+/// number of host writes drawn from the outage range, and every restore draws a possible power
+/// cycle. The same seed, and settings give the same cuts.
+#[derive(Clone, Debug)]
+pub struct ChaosSchedule {
+    seed: u64,
+    state: u64,
+    sever: f64,
+    min_outage: u32,
+    max_outage: u32,
+    power_cycle: f64,
+}
+
+impl ChaosSchedule {
+    /// Creates a new [ChaosSchedule] seeded with `seed`, that never cuts the link.
+    pub const fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: seed,
+            sever: 0.0,
+            min_outage: 1,
+            max_outage: 1,
+            power_cycle: 0.0,
+        }
+    }
+
+    /// Sets the probability of cutting the link on a host write.
+    pub fn with_sever_rate(mut self, rate: f64) -> Self {
+        self.sever = rate;
+        self
+    }
+
+    /// Sets the range of host writes lost while the link is down, at least one.
+    pub fn with_outage(mut self, min_writes: u32, max_writes: u32) -> Self {
+        self.min_outage = min_writes.max(1);
+        self.max_outage = max_writes.max(self.min_outage);
+        self
+    }
+
+    /// Sets the probability of the device being power cycled when the link comes back.
+    pub fn with_power_cycle_rate(mut self, rate: f64) -> Self {
+        self.power_cycle = rate;
+        self
+    }
+
+    /// Gets the seed.
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Gets the cut for the next host write, if any, with the number of host writes lost while
+    /// the link is down.
+    pub fn next_cut(&mut self) -> Option<(Cut, u32)> {
+        // draw for every write, so the sequence does not depend on which draws hit
+        let sever = rng::chance(&mut self.state, self.sever);
+        let value = rng::next_u64(&mut self.state);
+        let outage = rng::next_u64(&mut self.state);
+
+        if !sever {
+            return None;
+        }
+
+        let offset = (value >> 2) as usize;
+        let cut = match value % 4 {
+            0 => Cut::BeforeCommand,
+            1 => Cut::MidCommand(offset),
+            2 => Cut::BeforeResponse,
+            _ => Cut::MidResponse(offset),
+        };
+
+        let span = u64::from(self.max_outage - self.min_outage) + 1;
+
+        Some((cut, self.min_outage + (outage % span) as u32))
+    }
+
+    /// Gets how the link comes back after the next outage.
+    pub fn next_reconnect(&mut self) -> Reconnect {
+        if rng::chance(&mut self.state, self.power_cycle) {
+            Reconnect::PowerCycle
+        } else {
+            Reconnect::Resume
+        }
+    }
+}
+
+impl Default for ChaosSchedule {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ChaosState {
+    // host writes lost until the link comes back, `None` while the link is up
+    outage: Option<u32>,
+    // how the link comes back after an outage ended by hand
+    reconnect: Option<Reconnect>,
+    paused: bool,
+    disconnects: u64,
+    reconnects: u64,
+    power_cycles: u64,
+    cuts: Vec<Cut>,
+}
+
+/// Shared control of the link of a [ChaosTransport].
+///
+/// Stays usable after the transport is moved into a [DeviceHandle](crate::DeviceHandle).
+#[derive(Clone, Debug, Default)]
+pub struct ChaosControl(Arc<Mutex<ChaosState>>);
+
+impl ChaosControl {
+    /// Creates a new [ChaosControl], with the link up.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cuts the link until [restore](Self::restore).
+    pub fn sever(&self) {
+        let mut state = self.0.lock();
+
+        if state.outage.is_none() {
+            state.disconnects += 1;
+        }
+        state.outage = Some(u32::MAX);
+    }
+
+    /// Restores the link, resuming the device, or power cycling it.
+    ///
+    /// The link comes back on the next host write.
+    pub fn restore(&self, reconnect: Reconnect) {
+        let mut state = self.0.lock();
+
+        if state.outage.is_some() {
+            state.outage = Some(0);
+            state.reconnect = Some(reconnect);
+        }
+    }
+
+    /// Stops, or resumes, drawing cuts from the [ChaosSchedule].
+    ///
+    /// Cuts by hand still apply while paused.
+    pub fn set_paused(&self, paused: bool) {
+        self.0.lock().paused = paused;
+    }
+
+    /// Gets whether the link is down.
+    pub fn severed(&self) -> bool {
+        self.0.lock().outage.is_some()
+    }
+
+    /// Gets the number of times the link was cut.
+    pub fn disconnects(&self) -> u64 {
+        self.0.lock().disconnects
+    }
+
+    /// Gets the number of times the link came back.
+    pub fn reconnects(&self) -> u64 {
+        self.0.lock().reconnects
+    }
+
+    /// Gets the number of times the device was power cycled on reconnecting.
+    pub fn power_cycles(&self) -> u64 {
+        self.0.lock().power_cycles
+    }
+
+    /// Gets a copy of the scheduled cuts, in order.
+    pub fn cuts(&self) -> Vec<Cut> {
+        self.0.lock().cuts.clone()
+    }
+}
+
+/// In-memory [Transport] connected to an [Emulator], cutting the link according to a
+/// [ChaosSchedule].
+///
+/// While the link is down, written bytes are lost, and reads time out, like a pulled cable.
+pub struct ChaosTransport {
+    emulator: Arc<Mutex<Emulator>>,
+    schedule: ChaosSchedule,
+    control: ChaosControl,
+    wire: VecDeque<u8>,
+}
+
+impl ChaosTransport {
+    /// Creates a new [ChaosTransport] connected to the `emulator`, cutting the link according to
+    /// the `schedule`.
+    pub fn new(emulator: Arc<Mutex<Emulator>>, schedule: ChaosSchedule) -> Self {
+        Self {
+            emulator,
+            schedule,
+            control: ChaosControl::new(),
+            wire: VecDeque::new(),
+        }
+    }
+
+    /// Gets the [ChaosControl] of the link.
+    pub fn control(&self) -> &ChaosControl {
+        &self.control
+    }
+
+    /// Gets a reference to the connected [Emulator].
+    pub fn emulator(&self) -> &Arc<Mutex<Emulator>> {
+        &self.emulator
+    }
+
+    // Counts a lost host write while the link is down, and brings the link back at the end of the
+    // outage. Returns whether the link is up.
+    fn link_up(&mut self) -> bool {
+        let mut state = self.control.0.lock();
+
+        match state.outage {
+            None => true,
+            Some(0) => {
+                let reconnect = state
+                    .reconnect
+                    .take()
+                    .unwrap_or_else(|| self.schedule.next_reconnect());
+
+                state.outage = None;
+                state.reconnects += 1;
+
+                if reconnect == Reconnect::PowerCycle {
+                    state.power_cycles += 1;
+                    self.emulator.lock().power_cycle();
+                }
+
+                log::debug!("Chaos link restored: {reconnect:?}");
+
+                true
+            }
+            // severed by hand, until restored
+            Some(u32::MAX) => false,
+            Some(writes) => {
+                state.outage = Some(writes - 1);
+                false
+            }
+        }
+    }
+
+    fn next_cut(&mut self) -> Option<Cut> {
+        let mut state = self.control.0.lock();
+        if state.paused {
+            return None;
+        }
+
+        let (cut, outage) = self.schedule.next_cut()?;
+
+        log::debug!("Chaos link cut {cut}, for {outage} writes");
+
+        state.outage = Some(outage);
+        state.disconnects += 1;
+        state.cuts.push(cut);
+
+        Some(cut)
+    }
+}
+
+impl Read for ChaosTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for ChaosTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() || !self.link_up() {
+            return Ok(buf.len());
+        }
+
+        let response = match self.next_cut() {
+            None => self.emulator.lock().receive(buf),
+            Some(Cut::BeforeCommand) => Vec::new(),
+            Some(Cut::MidCommand(offset)) => {
+                self.emulator.lock().receive(&buf[..offset % buf.len()]);
+                Vec::new()
+            }
+            Some(Cut::BeforeResponse) => {
+                self.emulator.lock().receive(buf);
+                Vec::new()
+            }
+            Some(Cut::MidResponse(offset)) => {
+                let mut response = self.emulator.lock().receive(buf);
+                response.truncate(offset % response.len().max(1));
+                response
+            }
+        };
+
+        self.wire.extend(response);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for ChaosTransport {
+    fn clear(&mut self) -> Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        let discarded = self.wire.len();
+        self.wire.clear();
+
+        Ok(discarded)
+    }
+}
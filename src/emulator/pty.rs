@@ -0,0 +1,162 @@
+//! PTY loopback harness, running a [DeviceHandle] against an [Emulator] in integration tests.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use parking_lot::{Mutex, MutexGuard};
+use serialport::TTYPort;
+
+use ssp::{MessageOps, Result};
+
+use crate::DeviceHandle;
+
+use super::{serve, Emulator};
+
+/// Loopback harness connecting a [DeviceHandle] to an [Emulator] over a PTY pair.
+///
+/// The emulator serves the master end from a background thread, the handle talks to the slave
+/// end like it would to a serial device, so every exchange goes through a real TTY.
+///
+/// The emulator thread is stopped when the harness is dropped.
+pub struct PtyLoopback {
+    emulator: Arc<Mutex<Emulator>>,
+    handle: DeviceHandle,
+    device_path: String,
+    stop: Arc<AtomicBool>,
+    device: Option<thread::JoinHandle<Result<()>>>,
+}
+
+impl PtyLoopback {
+    /// Creates a PTY pair, serves the `emulator` on one end, and connects a [DeviceHandle] to
+    /// the other.
+    pub fn new(emulator: Emulator) -> Result<Self> {
+        let (master, slave) = TTYPort::pair()?;
+        let device_path = serialport::SerialPort::name(&slave).unwrap_or_default();
+
+        let emulator = Arc::new(Mutex::new(emulator));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let device = {
+            let emulator = Arc::clone(&emulator);
+            let stop = Arc::clone(&stop);
+            thread::Builder::new()
+                .name("ssp-emulator".into())
+                .spawn(move || serve(emulator, master, stop))?
+        };
+
+        log::debug!("Emulator listening on {device_path}");
+
+        Ok(Self {
+            emulator,
+            handle: DeviceHandle::from_transport(slave)?,
+            device_path,
+            stop,
+            device: Some(device),
+        })
+    }
+
+    /// Gets a lock on the [Emulator].
+    ///
+    /// Release it before sending commands, the emulator thread needs it to answer.
+    pub fn emulator(&self) -> MutexGuard<'_, Emulator> {
+        self.emulator.lock()
+    }
+
+    /// Gets a shared reference to the [Emulator].
+    pub fn shared_emulator(&self) -> &Arc<Mutex<Emulator>> {
+        &self.emulator
+    }
+
+    /// Gets a reference to the [DeviceHandle].
+    pub fn handle(&self) -> &DeviceHandle {
+        &self.handle
+    }
+
+    /// Gets a mutable reference to the [DeviceHandle], e.g. to negotiate a key.
+    pub fn handle_mut(&mut self) -> &mut DeviceHandle {
+        &mut self.handle
+    }
+
+    /// Gets the path of the PTY end used by the [DeviceHandle].
+    pub fn device_path(&self) -> &str {
+        self.device_path.as_str()
+    }
+
+    /// Synchronizes with the emulator, and runs the full initialization sequence.
+    pub fn start(&self, protocol_version: ssp::ProtocolVersion) -> Result<ssp::EnableResponse> {
+        self.handle.sync()?;
+        self.handle.enable_device(protocol_version)
+    }
+
+    /// Polls the emulator `polls` times, and returns the reported events, without the response
+    /// status of each poll.
+    pub fn poll_events(&self, polls: usize) -> Result<Vec<u8>> {
+        let mut events = Vec::new();
+
+        for _ in 0..polls {
+            let res = self.handle.poll()?;
+            events.extend_from_slice(&res.data()[1..]);
+        }
+
+        Ok(events)
+    }
+
+    /// Polls until the emulator has no more queued events, at most `max_polls` times.
+    ///
+    /// Returns the reported events, without the response status of each poll.
+    pub fn drain_events(&self, max_polls: usize) -> Result<Vec<u8>> {
+        let mut events = Vec::new();
+
+        for _ in 0..max_polls {
+            if self.emulator.lock().pending_events() == 0 {
+                break;
+            }
+            events.extend(self.poll_events(1)?);
+        }
+
+        Ok(events)
+    }
+
+    /// Waits up to `timeout` for the `condition` on the [Emulator] to hold, e.g. while background
+    /// polling reports the queued events.
+    pub fn wait_for<F>(&self, timeout: time::Duration, condition: F) -> bool
+    where
+        F: Fn(&Emulator) -> bool,
+    {
+        let start = time::Instant::now();
+
+        while !condition(&self.emulator.lock()) {
+            if start.elapsed() > timeout {
+                return false;
+            }
+            thread::sleep(time::Duration::from_millis(5));
+        }
+
+        true
+    }
+
+    /// Stops the emulator thread, and returns its result.
+    pub fn stop(mut self) -> Result<()> {
+        self.stop_device()
+    }
+
+    fn stop_device(&mut self) -> Result<()> {
+        self.stop.store(true, Ordering::SeqCst);
+
+        match self.device.take() {
+            Some(device) => device
+                .join()
+                .map_err(|err| ssp::Error::Io(format!("emulator thread panicked: {err:?}")))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for PtyLoopback {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop_device() {
+            log::warn!("Emulator stopped with an error: {err}");
+        }
+    }
+}
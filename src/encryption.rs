@@ -0,0 +1,184 @@
+//! Encryption requirements of SSP commands.
+//!
+//! Which commands a device only accepts encrypted depends on the negotiated protocol version, and
+//! the unit type reported by the device. Commands the device requires to be encrypted are always
+//! sent through an eSSP session. Without a negotiated key, they fail with a
+//! [KeyNotSet](ssp::ResponseStatus::KeyNotSet) encryption error, instead of being sent in
+//! plaintext, and rejected by the device.
+//!
+//! An [EncryptionPolicy] overrides the defaults for individual commands.
+
+use std::fmt;
+
+/// Unit type of a SMART Hopper.
+pub const UNIT_SMART_HOPPER: u8 = 0x03;
+/// Unit type of a SMART Payout.
+pub const UNIT_SMART_PAYOUT: u8 = 0x06;
+/// Unit type of an NV11.
+pub const UNIT_NV11: u8 = 0x07;
+
+/// First protocol version requiring payout commands to be encrypted on payout units.
+pub const ENCRYPTED_PAYOUT_PROTOCOL: ssp::ProtocolVersion = ssp::ProtocolVersion::Six;
+
+/// Gets whether the `unit_type` can pay out notes, or coins.
+pub fn is_payout_unit(unit_type: ssp::UnitType) -> bool {
+    matches!(
+        unit_type.as_inner(),
+        UNIT_SMART_HOPPER | UNIT_SMART_PAYOUT | UNIT_NV11
+    )
+}
+
+/// Gets whether the device requires the `command` to be encrypted.
+///
+/// # Parameters
+///
+/// - `command`: type of the command
+/// - `protocol`: negotiated protocol version
+/// - `unit_type`: unit type reported by the device, `None` if not yet known
+///
+/// Key changes, and emptying the payout are always encrypted. Payout commands are encrypted on
+/// payout units from [ENCRYPTED_PAYOUT_PROTOCOL] onwards. Before the unit type is known, payout
+/// commands are treated as sent to a payout unit.
+pub fn requires_encryption(
+    command: ssp::MessageType,
+    protocol: ssp::ProtocolVersion,
+    unit_type: Option<ssp::UnitType>,
+) -> bool {
+    match command {
+        ssp::MessageType::SetEncryptionKey
+        | ssp::MessageType::Empty
+        | ssp::MessageType::SmartEmpty => true,
+        ssp::MessageType::PayoutByDenomination | ssp::MessageType::EnablePayout => {
+            protocol as u8 >= ENCRYPTED_PAYOUT_PROTOCOL as u8
+                && protocol != ssp::ProtocolVersion::Reserved
+                && unit_type.map(is_payout_unit).unwrap_or(true)
+        }
+        _ => false,
+    }
+}
+
+/// Gets whether the `command` moves, or releases value, and is refused in plaintext while
+/// encryption is [required](crate::DeviceHandle::with_require_encryption).
+///
+/// Enabling the device, or payouts, changing channel inhibits, paying out, and emptying are
+/// value-relevant.
+pub fn is_value_relevant(command: ssp::MessageType) -> bool {
+    matches!(
+        command,
+        ssp::MessageType::Enable
+            | ssp::MessageType::EnablePayout
+            | ssp::MessageType::SetInhibits
+            | ssp::MessageType::PayoutByDenomination
+            | ssp::MessageType::Empty
+            | ssp::MessageType::SmartEmpty
+    )
+}
+
+/// How a command is sent to the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum EncryptionMode {
+    /// Always encrypted, refused without a negotiated key.
+    Required,
+    /// Encrypted with a negotiated key, in plaintext otherwise.
+    Preferred,
+    /// Always in plaintext, even with a negotiated key.
+    Plain,
+}
+
+impl EncryptionMode {
+    /// Gets the [EncryptionMode] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Required => "required",
+            Self::Preferred => "preferred",
+            Self::Plain => "plain",
+        }
+    }
+
+    /// Parses an [EncryptionMode] from its name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "required" => Some(Self::Required),
+            "preferred" => Some(Self::Preferred),
+            "plain" => Some(Self::Plain),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EncryptionMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Maps command types to the [EncryptionMode] used to send them.
+///
+/// Commands without an entry use the default mode: [Required](EncryptionMode::Required) if the
+/// device [requires encryption](requires_encryption), [Preferred](EncryptionMode::Preferred)
+/// otherwise. Entries let integrators with unusual firmware tune the table.
+///
+/// Key negotiation commands are always sent in plaintext, regardless of the policy.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncryptionPolicy {
+    modes: Vec<(ssp::MessageType, EncryptionMode)>,
+}
+
+impl EncryptionPolicy {
+    /// Creates a new [EncryptionPolicy] using the default mode for all commands.
+    pub const fn new() -> Self {
+        Self { modes: Vec::new() }
+    }
+
+    /// Builder function that sets the `mode` used to send `command`s.
+    pub fn with_mode(mut self, command: ssp::MessageType, mode: EncryptionMode) -> Self {
+        self.set_mode(command, mode);
+        self
+    }
+
+    /// Sets the `mode` used to send `command`s, replacing any previous entry.
+    pub fn set_mode(&mut self, command: ssp::MessageType, mode: EncryptionMode) {
+        match self.modes.iter_mut().find(|(cmd, _)| *cmd == command) {
+            Some(entry) => entry.1 = mode,
+            None => self.modes.push((command, mode)),
+        }
+    }
+
+    /// Removes the entry for `command`, restoring its default mode.
+    pub fn remove_mode(&mut self, command: ssp::MessageType) {
+        self.modes.retain(|(cmd, _)| *cmd != command);
+    }
+
+    /// Gets the configured entries.
+    pub fn modes(&self) -> &[(ssp::MessageType, EncryptionMode)] {
+        self.modes.as_ref()
+    }
+
+    /// Gets the [EncryptionMode] used to send `command`.
+    ///
+    /// See [requires_encryption] for the parameters used by the default mode.
+    pub fn mode(
+        &self,
+        command: ssp::MessageType,
+        protocol: ssp::ProtocolVersion,
+        unit_type: Option<ssp::UnitType>,
+    ) -> EncryptionMode {
+        if matches!(
+            command,
+            ssp::MessageType::SetGenerator
+                | ssp::MessageType::SetModulus
+                | ssp::MessageType::RequestKeyExchange
+        ) {
+            return EncryptionMode::Plain;
+        }
+
+        match self.modes.iter().find(|(cmd, _)| *cmd == command) {
+            Some((_, mode)) => *mode,
+            None if requires_encryption(command, protocol, unit_type) => EncryptionMode::Required,
+            None => EncryptionMode::Preferred,
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Entropy sources for eSSP key generation.
+//!
+//! The generator, modulus, and random keys used during key negotiation, and new
+//! [FixedKey](ssp::FixedKey)s, are generated from seeds supplied by an [EntropySource]. By default,
+//! seeds come from system entropy ([SystemEntropy]).
+//!
+//! Attach a custom source with [with_entropy_source](crate::DeviceHandle::with_entropy_source),
+//! e.g. a certified hardware RNG, or a [SeededEntropy] for deterministic tests.
+
+use ssp::Seed;
+
+/// Source of seeds for generating eSSP keys.
+pub trait EntropySource: Send {
+    /// Gets a short name for the source, used in log messages.
+    fn name(&self) -> &str;
+
+    /// Gets a new seed.
+    ///
+    /// Every call should return a different seed, keys generated from the same seed are equal.
+    fn seed(&mut self) -> Seed;
+
+    /// Generates a new [GeneratorKey](ssp::GeneratorKey).
+    fn generator_key(&mut self) -> ssp::GeneratorKey {
+        ssp::GeneratorKey::from_seed(self.seed())
+    }
+
+    /// Generates a new [ModulusKey](ssp::ModulusKey).
+    fn modulus_key(&mut self) -> ssp::ModulusKey {
+        ssp::ModulusKey::from_seed(self.seed())
+    }
+
+    /// Generates a new [RandomKey](ssp::RandomKey).
+    fn random_key(&mut self) -> ssp::RandomKey {
+        ssp::RandomKey::from_seed(self.seed())
+    }
+
+    /// Generates a new [FixedKey](ssp::FixedKey).
+    fn fixed_key(&mut self) -> ssp::FixedKey {
+        ssp::FixedKey::from_seed(self.seed())
+    }
+}
+
+/// [EntropySource] using system entropy.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemEntropy;
+
+impl SystemEntropy {
+    /// Creates a new [SystemEntropy].
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl EntropySource for SystemEntropy {
+    fn name(&self) -> &str {
+        "system"
+    }
+
+    fn seed(&mut self) -> Seed {
+        let mut seed = Seed::default();
+
+        for chunk in seed.chunks_exact_mut(8) {
+            chunk.copy_from_slice(
+                ssp::RandomKey::from_entropy()
+                    .as_inner()
+                    .to_le_bytes()
+                    .as_ref(),
+            );
+        }
+
+        seed
+    }
+
+    fn generator_key(&mut self) -> ssp::GeneratorKey {
+        ssp::GeneratorKey::from_entropy()
+    }
+
+    fn modulus_key(&mut self) -> ssp::ModulusKey {
+        ssp::ModulusKey::from_entropy()
+    }
+
+    fn random_key(&mut self) -> ssp::RandomKey {
+        ssp::RandomKey::from_entropy()
+    }
+
+    fn fixed_key(&mut self) -> ssp::FixedKey {
+        ssp::FixedKey::from_entropy()
+    }
+}
+
+/// Deterministic [EntropySource] deriving every seed from an initial seed, and a counter.
+///
+/// Sources created from the same initial seed generate the same sequence of keys. Only use for
+/// testing, or with an initial seed from a high-grade entropy source.
+#[derive(Clone, Debug)]
+pub struct SeededEntropy {
+    seed: Seed,
+    count: u64,
+}
+
+impl SeededEntropy {
+    /// Creates a new [SeededEntropy] from an initial `seed`.
+    pub const fn new(seed: Seed) -> Self {
+        Self { seed, count: 0 }
+    }
+
+    /// Creates a new [SeededEntropy], deriving the initial seed from `data`.
+    ///
+    /// See [ssp::seed] for details on the derivation.
+    pub fn from_data(data: &[u8]) -> Self {
+        Self::new(ssp::seed(data, b"ssp-server entropy"))
+    }
+
+    /// Gets the number of seeds derived so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl EntropySource for SeededEntropy {
+    fn name(&self) -> &str {
+        "seeded"
+    }
+
+    fn seed(&mut self) -> Seed {
+        self.count = self.count.wrapping_add(1);
+        ssp::seed(self.seed.as_ref(), self.count.to_le_bytes().as_ref())
+    }
+}
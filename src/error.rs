@@ -0,0 +1,297 @@
+//! Structured server errors, grouped by failure category.
+//!
+//! Handle functions return [ssp::Error]s, whose variants mix transport, framing, and device
+//! failures. An [Error] sorts an [ssp::Error] into a category callers can match on, and carries
+//! the command it failed for, and the sequence ID of its frame, see
+//! [error](crate::DeviceHandle::error):
+//!
+//! ```no_run
+//! # fn main() -> ssp::Result<()> {
+//! let handle = ssp_server::DeviceHandle::new("/dev/ttyUSB0")?;
+//!
+//! match handle
+//!     .sync()
+//!     .map_err(|err| handle.error(ssp::MessageType::Synchronisation, err))
+//! {
+//!     Ok(_) => (),
+//!     Err(ssp_server::Error::Timeout(ctx)) => log::warn!("Device not responding: {ctx}"),
+//!     Err(ssp_server::Error::Busy(_)) => log::info!("Device busy, retrying later"),
+//!     Err(err) => return Err(err.into()),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Failures raised by the server itself are created in their category, e.g. lease denials from
+//! the [LeaseManager](crate::lease::LeaseManager) are [Busy](Error::Busy) errors, missing tokens
+//! from the [Authenticator](crate::auth::Authenticator) are
+//! [Unauthenticated](Error::Unauthenticated) errors, and malformed client requests are
+//! [InvalidRequest](Error::InvalidRequest) errors.
+
+use std::borrow::Borrow;
+use std::fmt;
+
+use crate::framing;
+
+/// Result of server functions returning a structured [Error].
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Context of an [Error]: the underlying [ssp::Error], and the command it failed for.
+#[derive(Clone, Debug)]
+pub struct ErrorContext {
+    error: ssp::Error,
+    command: Option<ssp::MessageType>,
+    sequence_id: Option<u8>,
+}
+
+impl ErrorContext {
+    /// Creates a new [ErrorContext] for the `error`, without a command.
+    pub const fn new(error: ssp::Error) -> Self {
+        Self {
+            error,
+            command: None,
+            sequence_id: None,
+        }
+    }
+
+    /// Gets the underlying [ssp::Error].
+    pub const fn error(&self) -> &ssp::Error {
+        &self.error
+    }
+
+    /// Gets the type of the failed command, if known.
+    pub const fn command(&self) -> Option<ssp::MessageType> {
+        self.command
+    }
+
+    /// Gets the sequence ID of the failed command frame, if known.
+    pub const fn sequence_id(&self) -> Option<u8> {
+        self.sequence_id
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(command) = self.command {
+            write!(f, "{}", <&str>::from(command))?;
+
+            if let Some(sequence_id) = self.sequence_id {
+                write!(f, " (sequence ID {sequence_id:#04x})")?;
+            }
+
+            write!(f, ": ")?;
+        }
+
+        write!(f, "{}", self.error)
+    }
+}
+
+/// Server error, grouping an [ssp::Error] by failure category.
+#[derive(Clone, Debug)]
+pub enum Error {
+    /// The serial port, or bridge connection failed.
+    Transport(ErrorContext),
+    /// The device did not answer, or a queue, or lock was not available in time.
+    Timeout(ErrorContext),
+    /// A frame, or message was malformed.
+    Protocol(ErrorContext),
+    /// Encryption is missing, failed, or was downgraded.
+    Encryption(ErrorContext),
+    /// The device answered with a failure status.
+    DeviceStatus(ErrorContext),
+    /// The device is in use: leased by another client, or already polling.
+    Busy(ErrorContext),
+    /// The client request was malformed, e.g. an invalid header, or body.
+    InvalidRequest(ErrorContext),
+    /// The requested feature is not configured on the server, e.g. the audit log.
+    NotConfigured(ErrorContext),
+    /// The client sent a missing, or unknown token.
+    Unauthenticated(ErrorContext),
+    /// The client's token does not grant the role required for the command.
+    PermissionDenied(ErrorContext),
+    /// Any other failure, e.g. invalid arguments.
+    Other(ErrorContext),
+}
+
+impl Error {
+    /// Creates a new [Error] in the category of the `error`.
+    ///
+    /// Response, and inter-byte timeouts of the transport surface as [Io](ssp::Error::Io)
+    /// errors, and are sorted as [Timeout](Self::Timeout) errors, see [framing::is_timeout].
+    pub fn from_ssp(error: ssp::Error) -> Self {
+        let ctx = ErrorContext::new(error);
+
+        match &ctx.error {
+            err if framing::is_timeout(err) => Self::Timeout(ctx),
+            ssp::Error::QueueTimeout => Self::Timeout(ctx),
+            ssp::Error::PollingReinit => Self::Busy(ctx),
+            ssp::Error::Io(_) | ssp::Error::SerialPort(_) => Self::Transport(ctx),
+            ssp::Error::Crc(_)
+            | ssp::Error::InvalidSTX(_)
+            | ssp::Error::InvalidLength(_)
+            | ssp::Error::InvalidDataLength(_)
+            | ssp::Error::Event(_) => Self::Protocol(ctx),
+            ssp::Error::Encryption(_) => Self::Encryption(ctx),
+            ssp::Error::Status(_) | ssp::Error::InvalidStatus(_) => Self::DeviceStatus(ctx),
+            _ => Self::Other(ctx),
+        }
+    }
+
+    /// Creates a new [Busy](Self::Busy) error.
+    pub const fn busy(error: ssp::Error) -> Self {
+        Self::Busy(ErrorContext::new(error))
+    }
+
+    /// Creates a new [InvalidRequest](Self::InvalidRequest) error.
+    pub const fn invalid_request(error: ssp::Error) -> Self {
+        Self::InvalidRequest(ErrorContext::new(error))
+    }
+
+    /// Creates a new [NotConfigured](Self::NotConfigured) error.
+    pub const fn not_configured(error: ssp::Error) -> Self {
+        Self::NotConfigured(ErrorContext::new(error))
+    }
+
+    /// Creates a new [Unauthenticated](Self::Unauthenticated) error.
+    pub const fn unauthenticated(error: ssp::Error) -> Self {
+        Self::Unauthenticated(ErrorContext::new(error))
+    }
+
+    /// Creates a new [PermissionDenied](Self::PermissionDenied) error.
+    pub const fn permission_denied(error: ssp::Error) -> Self {
+        Self::PermissionDenied(ErrorContext::new(error))
+    }
+
+    /// Builder function that sets the type of the failed command.
+    pub fn with_command(mut self, command: ssp::MessageType) -> Self {
+        self.context_mut().command = Some(command);
+        self
+    }
+
+    /// Builder function that sets the sequence ID of the failed command frame.
+    pub fn with_sequence_id(mut self, sequence_id: u8) -> Self {
+        self.context_mut().sequence_id = Some(sequence_id);
+        self
+    }
+
+    /// Gets the category name of the [Error].
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Transport(_) => "transport",
+            Self::Timeout(_) => "timeout",
+            Self::Protocol(_) => "protocol",
+            Self::Encryption(_) => "encryption",
+            Self::DeviceStatus(_) => "device_status",
+            Self::Busy(_) => "busy",
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::NotConfigured(_) => "not_configured",
+            Self::Unauthenticated(_) => "unauthenticated",
+            Self::PermissionDenied(_) => "permission_denied",
+            Self::Other(_) => "other",
+        }
+    }
+
+    /// Gets the [ErrorContext].
+    pub const fn context(&self) -> &ErrorContext {
+        match self {
+            Self::Transport(ctx)
+            | Self::Timeout(ctx)
+            | Self::Protocol(ctx)
+            | Self::Encryption(ctx)
+            | Self::DeviceStatus(ctx)
+            | Self::Busy(ctx)
+            | Self::InvalidRequest(ctx)
+            | Self::NotConfigured(ctx)
+            | Self::Unauthenticated(ctx)
+            | Self::PermissionDenied(ctx)
+            | Self::Other(ctx) => ctx,
+        }
+    }
+
+    fn context_mut(&mut self) -> &mut ErrorContext {
+        match self {
+            Self::Transport(ctx)
+            | Self::Timeout(ctx)
+            | Self::Protocol(ctx)
+            | Self::Encryption(ctx)
+            | Self::DeviceStatus(ctx)
+            | Self::Busy(ctx)
+            | Self::InvalidRequest(ctx)
+            | Self::NotConfigured(ctx)
+            | Self::Unauthenticated(ctx)
+            | Self::PermissionDenied(ctx)
+            | Self::Other(ctx) => ctx,
+        }
+    }
+
+    /// Gets the underlying [ssp::Error].
+    pub const fn ssp_error(&self) -> &ssp::Error {
+        self.context().error()
+    }
+
+    /// Gets the type of the failed command, if known.
+    pub const fn command(&self) -> Option<ssp::MessageType> {
+        self.context().command()
+    }
+
+    /// Gets the sequence ID of the failed command frame, if known.
+    pub const fn sequence_id(&self) -> Option<u8> {
+        self.context().sequence_id()
+    }
+
+    /// Gets the device response status of a [DeviceStatus](Self::DeviceStatus) error.
+    pub fn status(&self) -> Option<ssp::ResponseStatus> {
+        match self.ssp_error() {
+            ssp::Error::Status(status) | ssp::Error::InvalidStatus((status, _)) => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Gets whether the failure is likely transient, and the command may succeed when retried.
+    pub const fn is_transient(&self) -> bool {
+        matches!(self, Self::Transport(_) | Self::Timeout(_) | Self::Busy(_))
+    }
+
+    /// Converts the [Error] into the underlying [ssp::Error], dropping the context.
+    pub fn into_inner(self) -> ssp::Error {
+        match self {
+            Self::Transport(ctx)
+            | Self::Timeout(ctx)
+            | Self::Protocol(ctx)
+            | Self::Encryption(ctx)
+            | Self::DeviceStatus(ctx)
+            | Self::Busy(ctx)
+            | Self::InvalidRequest(ctx)
+            | Self::NotConfigured(ctx)
+            | Self::Unauthenticated(ctx)
+            | Self::PermissionDenied(ctx)
+            | Self::Other(ctx) => ctx.error,
+        }
+    }
+}
+
+impl From<ssp::Error> for Error {
+    fn from(err: ssp::Error) -> Self {
+        Self::from_ssp(err)
+    }
+}
+
+impl From<Error> for ssp::Error {
+    fn from(err: Error) -> Self {
+        err.into_inner()
+    }
+}
+
+impl Borrow<ssp::Error> for Error {
+    fn borrow(&self) -> &ssp::Error {
+        self.ssp_error()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} error: {}", self.as_str(), self.context())
+    }
+}
+
+impl std::error::Error for Error {}
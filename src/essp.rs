@@ -0,0 +1,131 @@
+//! eSSP packet encryption, and decryption.
+//!
+//! The data of an encrypted frame is a `STEX` (`0x7e`) byte, followed by an AES-128 encrypted
+//! packet:
+//!
+//! ```text
+//! | LEN | COUNT (4 bytes, LE) | DATA | PACKING | CRC (2 bytes, LE) |
+//! ```
+//!
+//! The packing pads the packet to a multiple of the AES block size, and the CRC covers every
+//! field before it.
+//!
+//! Both functions are pure, and never trust the length field: it is checked against the size of
+//! the decrypted packet before any data is sliced, so arbitrary bytes from the wire fail with an
+//! error, e.g. while fuzzing.
+
+use ssp::{len, Result, STEXN};
+
+/// Length of the packet fields around the data: `LEN`, `COUNT`, and `CRC`.
+pub const PACKET_FIELDS: usize = len::ENCRYPTED_METADATA - 1;
+
+const COUNT: usize = 1;
+const DATA: usize = COUNT + 4;
+
+/// Decrypted eSSP packet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Packet {
+    count: u32,
+    data: Vec<u8>,
+}
+
+impl Packet {
+    /// Creates a new [Packet].
+    pub fn new(count: u32, data: &[u8]) -> Self {
+        Self {
+            count,
+            data: data.into(),
+        }
+    }
+
+    /// Gets the eSSP sequence count.
+    pub const fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Gets the message data, e.g. the response status followed by the response parameters.
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    /// Converts the [Packet] into its message data.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Decrypts the `data` of an encrypted frame, starting with the `STEX` byte.
+///
+/// Returns `Err(_)` for a missing `STEX` byte, a packet that is not a whole number of AES blocks,
+/// a length field past the end of the packet, or an invalid CRC.
+pub fn decrypt(key: &ssp::AesKey, data: &[u8]) -> Result<Packet> {
+    let (&stex, cipher) = data
+        .split_first()
+        .ok_or(ssp::Error::InvalidLength((0, len::ENCRYPTED_METADATA)))?;
+
+    if stex != STEXN {
+        return Err(ssp::Error::InvalidSTX(stex));
+    }
+
+    if cipher.is_empty() || !cipher.len().is_multiple_of(len::AES) {
+        return Err(ssp::Error::InvalidLength((cipher.len(), len::AES)));
+    }
+
+    let mut plain = vec![0u8; cipher.len()];
+    ssp::aes::aes_decrypt_inplace(key.as_ref(), cipher, &mut plain)?;
+
+    let data_len = usize::from(plain[0]);
+    if data_len + PACKET_FIELDS > plain.len() {
+        return Err(ssp::Error::InvalidDataLength((
+            data_len,
+            plain.len() - PACKET_FIELDS,
+        )));
+    }
+
+    let crc_start = plain.len() - len::FOOTER;
+    let have = u16::from_le_bytes([plain[crc_start], plain[crc_start + 1]]);
+    let exp = ssp::crc::crc16(&plain[..crc_start]);
+    if have != exp {
+        return Err(ssp::Error::Crc((have, exp)));
+    }
+
+    let count = u32::from_le_bytes([
+        plain[COUNT],
+        plain[COUNT + 1],
+        plain[COUNT + 2],
+        plain[COUNT + 3],
+    ]);
+
+    Ok(Packet::new(count, &plain[DATA..DATA + data_len]))
+}
+
+/// Encrypts the `packet` into the data of an encrypted frame, starting with the `STEX` byte.
+///
+/// The packing is zeroed, a device fills it with random bytes, the receiver never interprets it.
+pub fn encrypt(key: &ssp::AesKey, packet: &Packet) -> Result<Vec<u8>> {
+    let data = packet.data();
+    if data.len() > len::MAX_ENCRYPTED_DATA {
+        return Err(ssp::Error::InvalidDataLength((
+            data.len(),
+            len::MAX_ENCRYPTED_DATA,
+        )));
+    }
+
+    let packing = len::aes_packing_len(data.len() + PACKET_FIELDS);
+
+    let mut plain = Vec::with_capacity(data.len() + PACKET_FIELDS + packing);
+    plain.push(data.len() as u8);
+    plain.extend_from_slice(packet.count().to_le_bytes().as_ref());
+    plain.extend_from_slice(data);
+    plain.resize(plain.len() + packing, 0);
+
+    let crc = ssp::crc::crc16(&plain);
+    plain.extend_from_slice(crc.to_le_bytes().as_ref());
+
+    let mut out = vec![0u8; plain.len() + 1];
+    out[0] = STEXN;
+    ssp::aes::aes_encrypt_inplace(key.as_ref(), &plain, &mut out[1..])?;
+
+    Ok(out)
+}
@@ -0,0 +1,273 @@
+//! Sequenced, replayable log of device events.
+//!
+//! Every event pushed to the [PushEventReceiver](crate::PushEventReceiver) of a handle with an
+//! [EventLog] is assigned a monotonically increasing sequence number, and kept in a bounded
+//! in-memory history. Consumers remember the sequence number of the last event they processed,
+//! and after reconnecting resume with [subscribe](EventLog::subscribe), or
+//! [events_since](EventLog::events_since). Events are delivered at least once: a consumer that
+//! crashed before storing the last sequence number receives the event again.
+//!
+//! Sequence numbers start at the current time in microseconds, so they keep increasing across
+//! server restarts. The history is not persisted: resuming from a sequence number that is no
+//! longer in the history, e.g. from before a restart, fails with an error, and the consumer has
+//! to resynchronize from the device status.
+//!
+//! The history holds the last [DEFAULT_EVENT_LOG_CAPACITY] events, configurable with the
+//! [EVENT_LOG_ENV_CAPACITY] environment variable.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::{Condvar, Mutex};
+use ssp::Result;
+
+use crate::sink::EventSink;
+
+/// Environment variable with the number of events kept in the event log.
+pub const EVENT_LOG_ENV_CAPACITY: &str = "SSP_EVENT_LOG_CAPACITY";
+
+/// Default number of events kept in the event log.
+pub const DEFAULT_EVENT_LOG_CAPACITY: usize = 1024;
+
+/// Device event with its sequence number.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SequencedEvent {
+    /// Sequence number, increasing by one for every logged event.
+    pub seq: u64,
+    /// Time the event was logged, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Device event.
+    pub event: ssp::Event,
+}
+
+impl fmt::Display for SequencedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{} {} {}", self.seq, self.timestamp_ms, self.event)
+    }
+}
+
+struct EventHistory {
+    events: VecDeque<SequencedEvent>,
+    capacity: usize,
+    next_seq: u64,
+}
+
+impl EventHistory {
+    // Gets the sequence number of the oldest event still in the history, or the next sequence
+    // number if the history is empty.
+    fn first_seq(&self) -> u64 {
+        self.events.front().map(|e| e.seq).unwrap_or(self.next_seq)
+    }
+
+    // Gets the events after `after`, or an error if some of them were dropped.
+    fn events_after(&self, after: u64) -> Result<Vec<SequencedEvent>> {
+        let first = self.first_seq();
+
+        if after >= self.next_seq {
+            return Err(ssp::Error::Io(format!(
+                "event {after} was not logged, next event is {}",
+                self.next_seq
+            )));
+        }
+
+        if after.saturating_add(1) < first {
+            return Err(ssp::Error::Io(format!(
+                "events after {after} are no longer available, oldest event is {first}"
+            )));
+        }
+
+        // events are contiguous, so the index of an event is its offset from the oldest event
+        let skip = after.saturating_add(1).saturating_sub(first) as usize;
+
+        Ok(self.events.iter().skip(skip).cloned().collect())
+    }
+}
+
+/// Bounded history of sequenced device events, see [event_log](crate::event_log).
+///
+/// Cloned logs share the same history.
+#[derive(Clone)]
+pub struct EventLog {
+    history: Arc<(Mutex<EventHistory>, Condvar)>,
+}
+
+impl EventLog {
+    /// Creates a new [EventLog] keeping the last `capacity` events, at least one.
+    pub fn new(capacity: usize) -> Self {
+        let next_seq = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or_default();
+
+        Self {
+            history: Arc::new((
+                Mutex::new(EventHistory {
+                    events: VecDeque::with_capacity(capacity.min(DEFAULT_EVENT_LOG_CAPACITY)),
+                    capacity: capacity.max(1),
+                    next_seq,
+                }),
+                Condvar::new(),
+            )),
+        }
+    }
+
+    /// Creates a new [EventLog] with the capacity set in the [EVENT_LOG_ENV_CAPACITY] environment
+    /// variable.
+    ///
+    /// Returns `Ok(None)` if the capacity is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(capacity) = std::env::var(EVENT_LOG_ENV_CAPACITY) else {
+            return Ok(None);
+        };
+
+        let capacity = capacity
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| ssp::Error::Io(format!("invalid {EVENT_LOG_ENV_CAPACITY}: {capacity}")))?;
+
+        Ok(Some(Self::new(capacity)))
+    }
+
+    /// Gets the number of events kept in the history.
+    pub fn capacity(&self) -> usize {
+        self.history.0.lock().capacity
+    }
+
+    /// Gets the sequence number of the most recent event, `None` if no event was logged.
+    pub fn last_seq(&self) -> Option<u64> {
+        self.history.0.lock().events.back().map(|e| e.seq)
+    }
+
+    /// Gets the sequence number of the oldest event still in the history, `None` if no event
+    /// was logged.
+    pub fn first_seq(&self) -> Option<u64> {
+        self.history.0.lock().events.front().map(|e| e.seq)
+    }
+
+    /// Logs an `event`, and returns its sequence number.
+    ///
+    /// Drops the oldest event if the history is full.
+    pub fn record(&self, event: ssp::Event) -> u64 {
+        let (history, available) = &*self.history;
+        let mut history = history.lock();
+
+        let timestamp_ms = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        let seq = history.next_seq;
+        history.next_seq += 1;
+
+        history.events.push_back(SequencedEvent {
+            seq,
+            timestamp_ms,
+            event,
+        });
+
+        while history.events.len() > history.capacity {
+            history.events.pop_front();
+        }
+
+        available.notify_all();
+
+        seq
+    }
+
+    /// Gets the logged events, oldest first.
+    pub fn events(&self) -> Vec<SequencedEvent> {
+        self.history.0.lock().events.iter().cloned().collect()
+    }
+
+    /// Gets the events logged after the event with sequence number `after`, oldest first.
+    ///
+    /// Returns an error if `after` is past the most recent event, or events after `after` were
+    /// already dropped from the history.
+    pub fn events_since(&self, after: u64) -> Result<Vec<SequencedEvent>> {
+        self.history.0.lock().events_after(after)
+    }
+
+    /// Subscribes to the events logged after the event with sequence number `after`.
+    ///
+    /// With `after` set to `None`, the subscription starts with the next logged event.
+    ///
+    /// Returns an error if `after` is past the most recent event, or events after `after` were
+    /// already dropped from the history.
+    pub fn subscribe(&self, after: Option<u64>) -> Result<EventSubscription> {
+        let history = self.history.0.lock();
+
+        let next_seq = match after {
+            Some(after) => {
+                // check the subscriber did not miss any events
+                history.events_after(after)?;
+                after + 1
+            }
+            None => history.next_seq,
+        };
+
+        Ok(EventSubscription {
+            log: self.clone(),
+            next_seq,
+        })
+    }
+}
+
+impl EventSink for EventLog {
+    fn name(&self) -> &str {
+        "event-log"
+    }
+
+    fn publish_event(&mut self, event: &ssp::Event) -> Result<()> {
+        self.record(event.clone());
+        Ok(())
+    }
+}
+
+/// Subscription to the events of an [EventLog], created with [subscribe](EventLog::subscribe).
+pub struct EventSubscription {
+    log: EventLog,
+    next_seq: u64,
+}
+
+impl EventSubscription {
+    /// Gets the sequence number of the next event returned by the subscription.
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Waits up to `timeout` for the next event.
+    ///
+    /// Returns [QueueTimeout](ssp::Error::QueueTimeout) if no event was logged before the
+    /// timeout, or an error if the subscriber fell behind, and the next event was already dropped
+    /// from the history.
+    pub fn next_event(&mut self, timeout: time::Duration) -> Result<SequencedEvent> {
+        let (history, available) = &*self.log.history;
+        let mut history = history.lock();
+        let deadline = time::Instant::now() + timeout;
+
+        loop {
+            if self.next_seq < history.next_seq {
+                let first = history.first_seq();
+
+                if self.next_seq < first {
+                    let next = self.next_seq;
+                    return Err(ssp::Error::Io(format!(
+                        "subscriber fell behind, event {next} is no longer available, oldest event is {first}"
+                    )));
+                }
+
+                let event = history.events[(self.next_seq - first) as usize].clone();
+                self.next_seq += 1;
+
+                return Ok(event);
+            }
+
+            if available.wait_until(&mut history, deadline).timed_out() {
+                return Err(ssp::Error::QueueTimeout);
+            }
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! CSV export of journaled transactions, for bookkeeping.
+//!
+//! A [CsvExport] selects the [transaction journal](crate::journal) entries in a time range, and
+//! writes them as CSV, one transaction per row:
+//!
+//! ```text
+//! seq,date,serial_number,type,currency,value,count,amount
+//! 0,2024-01-31T09:15:02.318Z,1234,accepted,EUR,20,1,20
+//! 1,2024-01-31T09:20:44.051Z,1234,dispensed,EUR,10,2,20
+//! ```
+//!
+//! The summary adds up the selected transactions per type, currency, and note value. Values are
+//! written as recorded in the journal, without currency formatting. Dates are UTC, in RFC 3339
+//! format.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use ssp::Result;
+
+use crate::journal::{JournalEntry, TransactionKind};
+
+/// Header row of the transactions CSV.
+pub const TRANSACTIONS_HEADER: &str = "seq,date,serial_number,type,currency,value,count,amount";
+
+/// Header row of the summary CSV.
+pub const SUMMARY_HEADER: &str = "type,currency,value,transactions,count,amount";
+
+/// Gets the bookkeeping name of a [TransactionKind].
+pub const fn kind_label(kind: TransactionKind) -> &'static str {
+    match kind {
+        TransactionKind::Credit => "accepted",
+        TransactionKind::Payout => "dispensed",
+        TransactionKind::Empty => "emptied",
+    }
+}
+
+/// Transactions of a single type, currency, and note value.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DenominationSummary {
+    /// Kind of the transactions.
+    pub kind: TransactionKind,
+    /// Currency of the notes, empty if unknown.
+    pub currency: String,
+    /// Value of a single note.
+    pub value: u32,
+    /// Number of transactions.
+    pub transactions: u64,
+    /// Number of notes.
+    pub count: u64,
+    /// Total value of the notes.
+    pub amount: u64,
+}
+
+/// Export of the journaled transactions in a time range.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CsvExport {
+    since_ms: Option<u64>,
+    until_ms: Option<u64>,
+}
+
+impl CsvExport {
+    /// Creates a new [CsvExport] of all transactions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder function that only exports transactions at, or after `since_ms`.
+    pub fn with_since_ms(mut self, since_ms: u64) -> Self {
+        self.since_ms = Some(since_ms);
+        self
+    }
+
+    /// Builder function that only exports transactions before `until_ms`.
+    pub fn with_until_ms(mut self, until_ms: u64) -> Self {
+        self.until_ms = Some(until_ms);
+        self
+    }
+
+    /// Gets whether the `entry` is in the exported time range.
+    pub fn matches(&self, entry: &JournalEntry) -> bool {
+        self.since_ms
+            .is_none_or(|since| entry.timestamp_ms >= since)
+            && self.until_ms.is_none_or(|until| entry.timestamp_ms < until)
+    }
+
+    /// Writes the exported `entries` as CSV, and returns the number of written transactions.
+    pub fn write_transactions<W: Write>(
+        &self,
+        entries: &[JournalEntry],
+        mut writer: W,
+    ) -> Result<usize> {
+        writeln!(writer, "{TRANSACTIONS_HEADER}")?;
+
+        let mut written = 0;
+        for entry in entries.iter().filter(|e| self.matches(e)) {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{}",
+                entry.seq,
+                format_date(entry.timestamp_ms),
+                entry.serial_number,
+                kind_label(entry.kind),
+                csv_field(entry.currency.as_str()),
+                entry.value,
+                entry.count,
+                entry.amount(),
+            )?;
+            written += 1;
+        }
+
+        writer.flush()?;
+
+        Ok(written)
+    }
+
+    /// Adds up the exported `entries` per type, currency, and note value.
+    pub fn summary(&self, entries: &[JournalEntry]) -> Vec<DenominationSummary> {
+        let mut summary: BTreeMap<(&'static str, &str, u32), DenominationSummary> = BTreeMap::new();
+
+        for entry in entries.iter().filter(|e| self.matches(e)) {
+            let row = summary
+                .entry((entry.kind.as_str(), entry.currency.as_str(), entry.value))
+                .or_insert_with(|| DenominationSummary {
+                    kind: entry.kind,
+                    currency: entry.currency.clone(),
+                    value: entry.value,
+                    transactions: 0,
+                    count: 0,
+                    amount: 0,
+                });
+
+            row.transactions += 1;
+            row.count = row.count.saturating_add(entry.count.into());
+            row.amount = row.amount.saturating_add(entry.amount());
+        }
+
+        summary.into_values().collect()
+    }
+
+    /// Writes the [summary](Self::summary) of the exported `entries` as CSV, and returns the
+    /// number of written rows.
+    pub fn write_summary<W: Write>(
+        &self,
+        entries: &[JournalEntry],
+        mut writer: W,
+    ) -> Result<usize> {
+        let summary = self.summary(entries);
+
+        writeln!(writer, "{SUMMARY_HEADER}")?;
+
+        for row in summary.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                kind_label(row.kind),
+                csv_field(row.currency.as_str()),
+                row.value,
+                row.transactions,
+                row.count,
+                row.amount,
+            )?;
+        }
+
+        writer.flush()?;
+
+        Ok(summary.len())
+    }
+}
+
+// Quotes a CSV field containing separators, quotes, or line breaks.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into()
+    }
+}
+
+/// Formats milliseconds since the Unix epoch as an RFC 3339 UTC date, e.g.
+/// `2024-01-31T09:15:02.318Z`.
+pub fn format_date(timestamp_ms: u64) -> String {
+    let days = (timestamp_ms / 86_400_000) as i64;
+    let ms_of_day = timestamp_ms % 86_400_000;
+
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        ms_of_day / 3_600_000,
+        ms_of_day / 60_000 % 60,
+        ms_of_day / 1_000 % 60,
+        ms_of_day % 1_000,
+    )
+}
+
+/// Parses a date as milliseconds since the Unix epoch.
+///
+/// Accepts a `YYYY-MM-DD` UTC date, starting at midnight, or a number of milliseconds.
+pub fn parse_date(date: &str) -> Option<u64> {
+    if let Ok(ms) = date.parse::<u64>() {
+        return Some(ms);
+    }
+
+    let mut parts = date.splitn(3, '-');
+    let year = parts.next()?.parse::<i64>().ok()?;
+    let month = parts.next()?.parse::<u32>().ok()?;
+    let day = parts.next()?.parse::<u32>().ok()?;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year < 1970 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+
+    // reject days past the end of the month, e.g. 2023-02-30
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+
+    Some(days as u64 * 86_400_000)
+}
+
+// Converts days since the Unix epoch to a (year, month, day) proleptic Gregorian date.
+//
+// See <http://howardhinnant.github.io/date_algorithms.html>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+// Converts a (year, month, day) proleptic Gregorian date to days since the Unix epoch.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
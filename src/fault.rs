@@ -0,0 +1,505 @@
+//! Fault injection for testing the retry, and resync logic under realistic serial line errors.
+//!
+//! A [FaultTransport] wraps any [Transport], and applies faults to the frames passing through it,
+//! according to a [FaultSchedule]. Faults are drawn from a seeded generator, so a failing run can
+//! be reproduced from its seed, or placed on specific frames:
+//!
+//! ```no_run
+//! # fn main() -> ssp::Result<()> {
+//! use ssp_server::fault::{Fault, FaultSchedule, FaultTransport};
+//! use ssp_server::DeviceHandle;
+//!
+//! let port = serialport::TTYPort::open(&serialport::new("/dev/ttyUSB0", 9600))?;
+//! let rx = FaultSchedule::new(0x5eed)
+//!     .with_corrupt_crc_rate(0.05)
+//!     .with_drop_byte_rate(0.02)
+//!     .with_fault(3, Fault::DropFrame);
+//!
+//! let handle = DeviceHandle::from_transport(FaultTransport::new(port, rx))?;
+//! handle.sync()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::{thread, time};
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::capture::CaptureDirection;
+use crate::framing::{self, FrameDecoder};
+use crate::rng;
+use crate::transport::Transport;
+
+/// Fault applied to a single frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Fault {
+    /// Drops the whole frame, like a device that never answered.
+    DropFrame,
+    /// Drops the stuffed byte at the offset, wrapped to the frame length.
+    DropByte(usize),
+    /// Flips a bit in the CRC, so the frame fails the CRC check.
+    CorruptCrc,
+    /// Sends the frame twice.
+    Duplicate,
+    /// Delays the frame.
+    Delay(time::Duration),
+}
+
+impl Fault {
+    /// Gets the [Fault] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::DropFrame => "drop_frame",
+            Self::DropByte(_) => "drop_byte",
+            Self::CorruptCrc => "corrupt_crc",
+            Self::Duplicate => "duplicate",
+            Self::Delay(_) => "delay",
+        }
+    }
+
+    /// Applies the fault to a `stuffed` frame, and returns the bytes to send.
+    ///
+    /// Delays are not applied here, the frame is returned unchanged.
+    pub fn apply(&self, stuffed: &[u8]) -> Vec<u8> {
+        match self {
+            Self::DropFrame => Vec::new(),
+            Self::DropByte(offset) => {
+                let mut out = stuffed.to_vec();
+                if !out.is_empty() {
+                    out.remove(offset % stuffed.len());
+                }
+                out
+            }
+            Self::CorruptCrc => match framing::unstuff(stuffed) {
+                Ok(mut frame) => {
+                    if let Some(crc) = frame.last_mut() {
+                        *crc ^= 0x01;
+                    }
+                    framing::stuff(&frame).unwrap_or_else(|_| stuffed.to_vec())
+                }
+                Err(_) => stuffed.to_vec(),
+            },
+            Self::Duplicate => [stuffed, stuffed].concat(),
+            Self::Delay(_) => stuffed.to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DropByte(offset) => write!(f, "{}({offset})", self.as_str()),
+            Self::Delay(delay) => write!(f, "{}({}ms)", self.as_str(), delay.as_millis()),
+            _ => write!(f, "{}", self.as_str()),
+        }
+    }
+}
+
+/// Seedable schedule of the faults applied to the frames in one direction.
+///
+/// Every frame draws at most one random fault, checked in the order: dropped frame, dropped byte,
+/// corrupted CRC, duplicate, and delay. Faults placed on a frame with
+/// [with_fault](Self::with_fault) take precedence over random faults. The same seed, and rates
+/// give the same faults.
+#[derive(Clone, Debug)]
+pub struct FaultSchedule {
+    seed: u64,
+    state: u64,
+    frame: u64,
+    drop_frame: f64,
+    drop_byte: f64,
+    corrupt_crc: f64,
+    duplicate: f64,
+    delay: f64,
+    max_delay: time::Duration,
+    scripted: BTreeMap<u64, Fault>,
+}
+
+impl FaultSchedule {
+    /// Creates a new [FaultSchedule] seeded with `seed`, without any faults.
+    pub const fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: seed,
+            frame: 0,
+            drop_frame: 0.0,
+            drop_byte: 0.0,
+            corrupt_crc: 0.0,
+            duplicate: 0.0,
+            delay: 0.0,
+            max_delay: time::Duration::ZERO,
+            scripted: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a new [FaultSchedule] that never applies a fault.
+    pub const fn none() -> Self {
+        Self::new(0)
+    }
+
+    /// Sets the probability of dropping a whole frame.
+    pub fn with_drop_frame_rate(mut self, rate: f64) -> Self {
+        self.drop_frame = rate;
+        self
+    }
+
+    /// Sets the probability of dropping a single byte of a frame.
+    pub fn with_drop_byte_rate(mut self, rate: f64) -> Self {
+        self.drop_byte = rate;
+        self
+    }
+
+    /// Sets the probability of corrupting the CRC of a frame.
+    pub fn with_corrupt_crc_rate(mut self, rate: f64) -> Self {
+        self.corrupt_crc = rate;
+        self
+    }
+
+    /// Sets the probability of sending a frame twice.
+    pub fn with_duplicate_rate(mut self, rate: f64) -> Self {
+        self.duplicate = rate;
+        self
+    }
+
+    /// Sets the probability of delaying a frame, by up to `max_delay`.
+    pub fn with_delay_rate(mut self, rate: f64, max_delay: time::Duration) -> Self {
+        self.delay = rate;
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Applies the `fault` to the frame at the zero-based `frame` index.
+    pub fn with_fault(mut self, frame: u64, fault: Fault) -> Self {
+        self.scripted.insert(frame, fault);
+        self
+    }
+
+    /// Gets the seed.
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Gets the index of the next frame.
+    pub const fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Gets the fault for the next frame, if any.
+    pub fn next_fault(&mut self) -> Option<Fault> {
+        let frame = self.frame;
+        self.frame += 1;
+
+        // draw for every frame, so scripted faults do not shift the random ones
+        let random = self.random_fault();
+
+        self.scripted.get(&frame).copied().or(random)
+    }
+
+    fn random_fault(&mut self) -> Option<Fault> {
+        let drop_frame = self.chance(self.drop_frame);
+        let drop_byte = self.chance(self.drop_byte);
+        let corrupt_crc = self.chance(self.corrupt_crc);
+        let duplicate = self.chance(self.duplicate);
+        let delay = self.chance(self.delay);
+        let value = self.next_u64();
+
+        if drop_frame {
+            Some(Fault::DropFrame)
+        } else if drop_byte {
+            Some(Fault::DropByte(value as usize))
+        } else if corrupt_crc {
+            Some(Fault::CorruptCrc)
+        } else if duplicate {
+            Some(Fault::Duplicate)
+        } else if delay {
+            let max_us = self.max_delay.as_micros() as u64;
+            Some(Fault::Delay(time::Duration::from_micros(
+                value % max_us.saturating_add(1),
+            )))
+        } else {
+            None
+        }
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        rng::chance(&mut self.state, rate)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rng::next_u64(&mut self.state)
+    }
+}
+
+impl Default for FaultSchedule {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// Fault applied by a [FaultTransport].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InjectedFault {
+    /// Direction of the faulty frame.
+    pub direction: CaptureDirection,
+    /// Index of the frame in its direction.
+    pub frame: u64,
+    /// Applied fault.
+    pub fault: Fault,
+}
+
+impl fmt::Display for InjectedFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} frame {}: {}", self.direction, self.frame, self.fault)
+    }
+}
+
+/// Shared log of the faults applied by a [FaultTransport].
+///
+/// Stays readable after the transport is moved into a [DeviceHandle](crate::DeviceHandle).
+#[derive(Clone, Debug, Default)]
+pub struct FaultLog(Arc<Mutex<Vec<InjectedFault>>>);
+
+impl FaultLog {
+    /// Creates a new, empty [FaultLog].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets a copy of the applied faults, in order.
+    pub fn entries(&self) -> Vec<InjectedFault> {
+        self.0.lock().clone()
+    }
+
+    /// Gets the number of applied faults.
+    pub fn len(&self) -> usize {
+        self.0.lock().len()
+    }
+
+    /// Gets whether no fault was applied.
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().is_empty()
+    }
+
+    /// Gets the number of applied faults matching `condition`.
+    pub fn count<F>(&self, condition: F) -> usize
+    where
+        F: Fn(&InjectedFault) -> bool,
+    {
+        self.0.lock().iter().filter(|f| condition(f)).count()
+    }
+
+    fn push(&self, fault: InjectedFault) {
+        self.0.lock().push(fault);
+    }
+}
+
+/// Transport decorator injecting faults into the frames exchanged with a device.
+///
+/// Received frames are reassembled before the RX schedule is applied, so a fault always hits a
+/// whole frame. Written frames get the TX schedule, the host writes one frame at a time. Bytes
+/// that do not decode as a frame pass through unchanged.
+pub struct FaultTransport<T: Transport> {
+    inner: T,
+    rx: FaultSchedule,
+    tx: FaultSchedule,
+    log: FaultLog,
+    decoder: FrameDecoder,
+    partial: Vec<u8>,
+    pending: VecDeque<u8>,
+}
+
+impl<T: Transport> FaultTransport<T> {
+    /// Creates a new [FaultTransport] applying the `rx` schedule to the frames read from `inner`.
+    pub fn new(inner: T, rx: FaultSchedule) -> Self {
+        Self {
+            inner,
+            rx,
+            tx: FaultSchedule::none(),
+            log: FaultLog::new(),
+            decoder: FrameDecoder::new(),
+            partial: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Sets the schedule applied to the frames written to `inner`.
+    pub fn with_tx_schedule(mut self, tx: FaultSchedule) -> Self {
+        self.tx = tx;
+        self
+    }
+
+    /// Gets the [FaultLog] of the applied faults.
+    pub fn log(&self) -> &FaultLog {
+        &self.log
+    }
+
+    /// Gets a reference to the wrapped transport.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Stops injecting faults, and returns the wrapped transport.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn next_fault(&mut self, direction: CaptureDirection) -> Option<Fault> {
+        let schedule = match direction {
+            CaptureDirection::Tx => &mut self.tx,
+            _ => &mut self.rx,
+        };
+
+        let frame = schedule.frame();
+        let fault = schedule.next_fault()?;
+
+        log::debug!("Injecting fault, {direction} frame {frame}: {fault}");
+
+        self.log.push(InjectedFault {
+            direction,
+            frame,
+            fault,
+        });
+
+        Some(fault)
+    }
+
+    // Reads from the inner transport until a frame is complete, and queues it with the RX fault
+    // applied. Bytes read before an error are passed through.
+    fn fill(&mut self, len: usize) -> io::Result<()> {
+        let mut buf = [0u8; ssp::len::MAX_MESSAGE];
+        let len = len.clamp(1, buf.len());
+
+        loop {
+            let n = match self.inner.read(&mut buf[..len]) {
+                Ok(0) => {
+                    self.flush_partial();
+                    return Ok(());
+                }
+                Ok(n) => n,
+                Err(err) => {
+                    if self.pending.is_empty() && self.partial.is_empty() {
+                        return Err(err);
+                    }
+                    self.flush_partial();
+                    return Ok(());
+                }
+            };
+
+            for &byte in buf[..n].iter() {
+                self.partial.push(byte);
+
+                match self.decoder.push(byte) {
+                    Ok(true) => {
+                        let stuffed = std::mem::take(&mut self.partial);
+                        self.decoder.reset();
+
+                        let out = match self.next_fault(CaptureDirection::Rx) {
+                            Some(Fault::Delay(delay)) => {
+                                thread::sleep(delay);
+                                stuffed
+                            }
+                            Some(fault) => fault.apply(&stuffed),
+                            None => stuffed,
+                        };
+                        self.pending.extend(out);
+                    }
+                    Ok(false) => (),
+                    Err(_) => {
+                        // not a frame, pass it through
+                        self.decoder.reset();
+                        self.flush_partial();
+                    }
+                }
+            }
+
+            if !self.pending.is_empty() && self.partial.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn flush_partial(&mut self) {
+        self.pending.extend(self.partial.drain(..));
+    }
+
+    fn reset_rx(&mut self) -> usize {
+        let discarded = self.pending.len() + self.partial.len();
+
+        self.pending.clear();
+        self.partial.clear();
+        self.decoder.reset();
+
+        discarded
+    }
+}
+
+impl<T: Transport> Read for FaultTransport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.pending.is_empty() {
+            self.fill(buf.len())?;
+        }
+
+        let n = buf.len().min(self.pending.len());
+        for (out, byte) in buf.iter_mut().zip(self.pending.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl<T: Transport> Write for FaultTransport<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if framing::unstuff(buf).is_err() {
+            return self.inner.write(buf);
+        }
+
+        let out = match self.next_fault(CaptureDirection::Tx) {
+            Some(Fault::Delay(delay)) => {
+                thread::sleep(delay);
+                buf.to_vec()
+            }
+            Some(fault) => fault.apply(buf),
+            None => buf.to_vec(),
+        };
+
+        if !out.is_empty() {
+            self.inner.write_all(&out)?;
+        }
+
+        // the whole frame is consumed, even if parts of it were dropped
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Transport> Transport for FaultTransport<T> {
+    fn clear(&mut self) -> Result<()> {
+        self.reset_rx();
+        self.inner.clear()
+    }
+
+    fn set_read_timeout(&mut self, timeout: time::Duration) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        let discarded = self.reset_rx();
+        Ok(discarded + self.inner.discard_input()?)
+    }
+}
@@ -0,0 +1,151 @@
+//! Bounded in-memory log of the most recent frames exchanged with the device.
+//!
+//! Every command frame sent, and every response frame received is kept in a ring buffer, so
+//! support can inspect the traffic leading up to an error without permanently enabling trace
+//! logging. Frames are logged as sent on the wire, before byte stuffing: encrypted commands, and
+//! responses are logged encrypted. Frames of sensitive commands are [redacted](crate::redact).
+//!
+//! The buffer holds the last [DEFAULT_FRAME_LOG_CAPACITY] frames, configurable with
+//! [set_frame_log_capacity], or the [FRAME_LOG_ENV_CAPACITY] environment variable. A capacity of
+//! zero disables the log.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time;
+
+use parking_lot::Mutex;
+
+use crate::capture::CaptureDirection;
+use crate::redact;
+
+/// Environment variable with the number of frames kept in the frame log.
+pub const FRAME_LOG_ENV_CAPACITY: &str = "SSP_FRAME_LOG_CAPACITY";
+
+/// Default number of frames kept in the frame log.
+pub const DEFAULT_FRAME_LOG_CAPACITY: usize = 64;
+
+static FRAME_LOG: Mutex<FrameLog> = Mutex::new(FrameLog::new());
+
+/// Frame exchanged with the device.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LoggedFrame {
+    /// Sequence number, increasing by one for every logged frame.
+    pub seq: u64,
+    /// Time the frame was sent, or received, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Direction of the frame, [Tx](CaptureDirection::Tx) or [Rx](CaptureDirection::Rx).
+    pub direction: CaptureDirection,
+    /// Unstuffed frame bytes, partial for a failed read.
+    pub frame: Vec<u8>,
+    /// Error reading the frame, `None` for a complete frame.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub error: Option<String>,
+}
+
+impl fmt::Display for LoggedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} {} {:02x?}",
+            self.seq, self.timestamp_ms, self.direction, self.frame
+        )?;
+
+        match self.error.as_ref() {
+            Some(err) => write!(f, " error: {err}"),
+            None => Ok(()),
+        }
+    }
+}
+
+struct FrameLog {
+    frames: VecDeque<LoggedFrame>,
+    // `None` until configured, then the default, or environment capacity applies
+    capacity: Option<usize>,
+    next_seq: u64,
+}
+
+impl FrameLog {
+    const fn new() -> Self {
+        Self {
+            frames: VecDeque::new(),
+            capacity: None,
+            next_seq: 0,
+        }
+    }
+
+    fn capacity(&mut self) -> usize {
+        *self.capacity.get_or_insert_with(|| {
+            std::env::var(FRAME_LOG_ENV_CAPACITY)
+                .ok()
+                .and_then(|c| c.parse().ok())
+                .unwrap_or(DEFAULT_FRAME_LOG_CAPACITY)
+        })
+    }
+
+    fn truncate(&mut self) {
+        let capacity = self.capacity();
+        while self.frames.len() > capacity {
+            self.frames.pop_front();
+        }
+    }
+}
+
+/// Gets the number of frames kept in the frame log.
+pub fn frame_log_capacity() -> usize {
+    FRAME_LOG.lock().capacity()
+}
+
+/// Sets the number of frames kept in the frame log, zero disables the log.
+///
+/// Drops the oldest frames beyond the new capacity.
+pub fn set_frame_log_capacity(capacity: usize) {
+    let mut log = FRAME_LOG.lock();
+    log.capacity = Some(capacity);
+    log.truncate();
+}
+
+/// Gets the logged frames, oldest first.
+pub fn recent_frames() -> Vec<LoggedFrame> {
+    FRAME_LOG.lock().frames.iter().cloned().collect()
+}
+
+/// Removes all frames from the frame log.
+pub fn clear_frame_log() {
+    FRAME_LOG.lock().frames.clear();
+}
+
+// Logs a `frame` sent in `direction`, with the `error` reading it, if any.
+pub(crate) fn log_frame(direction: CaptureDirection, frame: &[u8], error: Option<&ssp::Error>) {
+    let mut log = FRAME_LOG.lock();
+
+    if log.capacity() == 0 {
+        return;
+    }
+
+    let timestamp_ms = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+
+    let seq = log.next_seq;
+    log.next_seq += 1;
+
+    // reuse the buffer of the oldest frame once the log is full
+    let mut buf = if log.frames.len() >= log.capacity() {
+        log.frames.pop_front().map(|f| f.frame).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    buf.clear();
+    buf.extend_from_slice(redact::frame(frame).as_ref());
+
+    log.frames.push_back(LoggedFrame {
+        seq,
+        timestamp_ms,
+        direction,
+        frame: buf,
+        error: error.map(|err| format!("{err}")),
+    });
+    log.truncate();
+}
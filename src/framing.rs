@@ -0,0 +1,523 @@
+//! Byte-stuffing codec for SSP frames.
+//!
+//! Every SSP frame starts with a `STX` (`0x7f`) byte. Any `0x7f` byte after the `STX`, i.e. in
+//! the sequence ID, length, data, or CRC fields, is sent twice, so the receiver does not mistake
+//! it for the start of a new frame.
+//!
+//! The codec works on whole frames, and is shared by plain and encrypted messages: frames are
+//! stuffed right before writing, and unstuffed while reading, before the length field is
+//! interpreted.
+//!
+//! Responses from a device are read with [read_frame_timeout], which skips garbage before a
+//! frame, and resynchronizes on corrupted frames, so a single bad frame does not desynchronize
+//! every following read.
+//!
+//! A command whose response is corrupted, or missing, is retransmitted with the same sequence flag,
+//! up to [MAX_RETRANSMISSIONS] times. The device answers a repeated sequence flag with its last
+//! response, so the command is not executed twice.
+
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::time;
+
+use ssp::message::index;
+use ssp::{len, Result, STX};
+
+use crate::transport::Transport;
+
+/// Maximum number of bytes discarded while searching for a valid frame.
+pub const MAX_DISCARDED_BYTES: usize = len::MAX_MESSAGE * 2;
+/// Maximum number of times a command is retransmitted after a corrupted, or missing response.
+pub const MAX_RETRANSMISSIONS: u32 = 3;
+
+/// Adds byte stuffing to an unstuffed `frame`, starting with the `STX` byte.
+pub fn stuff(frame: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(frame.len() * 2);
+    stuff_into(frame, &mut out)?;
+
+    Ok(out)
+}
+
+/// Adds byte stuffing to an unstuffed `frame`, replacing the contents of `out`.
+///
+/// Reuses the capacity of `out`, e.g. to stuff every frame into the same buffer.
+pub fn stuff_into(frame: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    match frame.first() {
+        Some(&STX) => (),
+        Some(&stx) => return Err(ssp::Error::InvalidSTX(stx)),
+        None => return Err(ssp::Error::InvalidLength((0, len::METADATA))),
+    }
+
+    out.clear();
+    out.push(STX);
+
+    for &byte in frame[1..].iter() {
+        out.push(byte);
+        if byte == STX {
+            out.push(STX);
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes byte stuffing from a single, complete `stuffed` frame.
+///
+/// Returns `Err(_)` if the frame is incomplete, followed by extra bytes, or contains an unpaired
+/// `STX` byte.
+pub fn unstuff(stuffed: &[u8]) -> Result<Vec<u8>> {
+    let mut decoder = FrameDecoder::new();
+
+    for (i, &byte) in stuffed.iter().enumerate() {
+        if decoder.push(byte)? {
+            return if i + 1 == stuffed.len() {
+                Ok(decoder.frame().into())
+            } else {
+                Err(ssp::Error::InvalidLength((stuffed.len(), i + 1)))
+            };
+        }
+    }
+
+    Err(ssp::Error::InvalidLength((
+        decoder.frame().len(),
+        decoder.expected_len().unwrap_or(len::METADATA),
+    )))
+}
+
+/// Incremental decoder for stuffed SSP frames.
+///
+/// Bytes are pushed one at a time, as they arrive on the wire, and unstuffed into the frame
+/// buffer. The length field is only interpreted after it is unstuffed, so frames where the
+/// length, or any other field, equals `STX` are decoded correctly.
+#[derive(Clone, Debug)]
+pub struct FrameDecoder {
+    buf: [u8; len::MAX_MESSAGE],
+    len: usize,
+    escaped: bool,
+}
+
+impl FrameDecoder {
+    /// Creates a new [FrameDecoder].
+    pub const fn new() -> Self {
+        Self {
+            buf: [0u8; len::MAX_MESSAGE],
+            len: 0,
+            escaped: false,
+        }
+    }
+
+    /// Discards any partially decoded frame.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.escaped = false;
+    }
+
+    /// Gets the unstuffed frame bytes decoded so far.
+    pub fn frame(&self) -> &[u8] {
+        self.buf[..self.len].as_ref()
+    }
+
+    /// Gets the length of the unstuffed frame, once the length field is decoded.
+    pub fn expected_len(&self) -> Option<usize> {
+        if self.len > index::LEN {
+            Some(self.buf[index::LEN] as usize + len::METADATA)
+        } else {
+            None
+        }
+    }
+
+    /// Gets whether a complete frame is decoded.
+    pub fn is_complete(&self) -> bool {
+        self.expected_len() == Some(self.len)
+    }
+
+    /// Gets the minimum number of stuffed bytes still needed to complete the frame.
+    ///
+    /// Reading at most this many bytes never reads past the end of the frame.
+    pub fn min_remaining(&self) -> usize {
+        let target = self.expected_len().unwrap_or(index::LEN + 1);
+        target.saturating_sub(self.len)
+    }
+
+    /// Pushes the next `byte` read from the wire.
+    ///
+    /// Returns `Ok(true)` when the frame is complete, and `Err(_)` for an invalid `STX` byte, an
+    /// unpaired `STX` byte inside the frame, or bytes pushed after the frame is complete.
+    pub fn push(&mut self, byte: u8) -> Result<bool> {
+        if self.len == 0 {
+            return if byte == STX {
+                self.append(byte).map(|_| false)
+            } else {
+                Err(ssp::Error::InvalidSTX(byte))
+            };
+        }
+
+        if self.is_complete() {
+            return Err(ssp::Error::InvalidLength((self.len + 1, self.len)));
+        }
+
+        if self.escaped {
+            self.escaped = false;
+
+            if byte != STX {
+                // a lone STX starts a new frame, the current one is truncated
+                log::warn!("Unpaired STX byte at frame offset {}", self.len);
+                return Err(ssp::Error::InvalidSTX(byte));
+            }
+        } else if byte == STX {
+            self.escaped = true;
+            return Ok(false);
+        }
+
+        self.append(byte)?;
+
+        Ok(self.is_complete())
+    }
+
+    fn append(&mut self, byte: u8) -> Result<()> {
+        if self.len >= self.buf.len() {
+            return Err(ssp::Error::InvalidLength((self.len + 1, self.buf.len())));
+        }
+
+        self.buf[self.len] = byte;
+        self.len += 1;
+
+        Ok(())
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads a single stuffed frame from `reader`, and unstuffs it into `decoder`.
+///
+/// Never reads past the end of the frame. Returns the length of the unstuffed frame, available
+/// from [FrameDecoder::frame].
+pub fn read_frame<R: Read + ?Sized>(reader: &mut R, decoder: &mut FrameDecoder) -> Result<usize> {
+    decoder.reset();
+
+    let mut chunk = [0u8; len::MAX_MESSAGE];
+
+    loop {
+        let remaining = decoder.min_remaining().max(1);
+        reader.read_exact(chunk[..remaining].as_mut())?;
+
+        for &byte in chunk[..remaining].iter() {
+            if decoder.push(byte)? {
+                return Ok(decoder.frame().len());
+            }
+        }
+    }
+}
+
+/// Timeouts for reading a frame with [read_frame_timeout].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameTimeouts {
+    /// Time to wait for the first byte of a frame.
+    pub response: time::Duration,
+    /// Maximum gap between two bytes, once the first byte arrived.
+    pub inter_byte: time::Duration,
+}
+
+/// Reads a single valid frame from `transport`, and unstuffs it into `decoder`.
+///
+/// Unlike [read_frame], the reader recovers from corrupted input:
+///
+/// - bytes before a `STX` byte are skipped
+/// - an unpaired `STX` byte restarts the frame
+/// - frames with a zero length, or a bad CRC, are dropped, and the bytes after their `STX` are
+///   searched for the next frame
+///
+/// The header is read at once, followed by the rest of the frame, so a frame without stuffed
+/// bytes takes two reads. Every stuffed byte pair in the rest of the frame can take an extra read,
+/// since the reader never reads past the end of the frame.
+///
+/// After the first byte, reads time out after the [inter_byte](FrameTimeouts::inter_byte)
+/// timeout, so a truncated frame fails without waiting for the full response timeout. The
+/// transport is left with the [response](FrameTimeouts::response) timeout.
+///
+/// On failure, [FrameDecoder::frame] keeps the partially decoded frame, and the error of the first
+/// dropped frame is returned, if any.
+pub fn read_frame_timeout<T: Transport + ?Sized>(
+    transport: &mut T,
+    decoder: &mut FrameDecoder,
+    timeouts: FrameTimeouts,
+) -> Result<usize> {
+    read_frame_scratch(transport, decoder, &mut ReadScratch::new(), timeouts)
+}
+
+fn read_frame_scratch<T: Transport + ?Sized>(
+    transport: &mut T,
+    decoder: &mut FrameDecoder,
+    scratch: &mut ReadScratch,
+    timeouts: FrameTimeouts,
+) -> Result<usize> {
+    transport.set_read_timeout(timeouts.response)?;
+
+    let res = resync_frame(transport, decoder, scratch, timeouts.inter_byte);
+    let restored = transport.set_read_timeout(timeouts.response);
+
+    let len = res?;
+    restored?;
+
+    Ok(len)
+}
+
+// Bytes read, but not yet decoded, and the stuffed bytes of the current frame.
+#[derive(Debug)]
+struct ReadScratch {
+    pending: VecDeque<u8>,
+    raw: Vec<u8>,
+}
+
+impl ReadScratch {
+    fn new() -> Self {
+        Self {
+            pending: VecDeque::with_capacity(len::MAX_MESSAGE),
+            raw: Vec::with_capacity(len::MAX_MESSAGE),
+        }
+    }
+}
+
+fn resync_frame<T: Transport + ?Sized>(
+    transport: &mut T,
+    decoder: &mut FrameDecoder,
+    scratch: &mut ReadScratch,
+    inter_byte: time::Duration,
+) -> Result<usize> {
+    decoder.reset();
+
+    let mut chunk = [0u8; len::MAX_MESSAGE];
+    // bytes read, but not yet decoded
+    let pending = &mut scratch.pending;
+    // stuffed bytes of the current frame, searched again if the frame is dropped
+    let raw = &mut scratch.raw;
+
+    pending.clear();
+    raw.clear();
+    let mut in_frame = false;
+    let mut received = false;
+    let mut discarded = 0usize;
+    let mut first_err: Option<ssp::Error> = None;
+
+    loop {
+        if discarded > MAX_DISCARDED_BYTES {
+            return Err(first_err.unwrap_or(ssp::Error::Io(format!(
+                "no valid frame in {discarded} bytes"
+            ))));
+        }
+
+        let byte = match pending.pop_front() {
+            Some(byte) => byte,
+            None => {
+                // read the whole header, then the rest of the frame, never past its end
+                let want = if in_frame {
+                    decoder.min_remaining().max(1)
+                } else {
+                    len::HEADER
+                };
+
+                match transport.read(chunk[..want].as_mut()) {
+                    Ok(0) => {
+                        let err = io::Error::from(io::ErrorKind::UnexpectedEof);
+                        return Err(first_err.unwrap_or(err.into()));
+                    }
+                    Ok(n) => pending.extend(chunk[..n].iter()),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                    Err(err) => return Err(first_err.unwrap_or(err.into())),
+                }
+
+                if !received && !pending.is_empty() {
+                    received = true;
+                    transport.set_read_timeout(inter_byte)?;
+                }
+
+                continue;
+            }
+        };
+
+        if !in_frame {
+            if byte == STX {
+                in_frame = true;
+                decoder.reset();
+                decoder.push(byte)?;
+                raw.clear();
+                raw.push(byte);
+            } else {
+                discarded += 1;
+            }
+
+            continue;
+        }
+
+        raw.push(byte);
+
+        let err = match decoder.push(byte) {
+            Ok(true) => match check_crc(decoder.frame()) {
+                Ok(()) => {
+                    if discarded > 0 {
+                        log::warn!("Discarded {discarded} bytes before a valid frame");
+                    }
+
+                    return Ok(decoder.frame().len());
+                }
+                Err(err) => err,
+            },
+            Ok(false) if decoder.expected_len() == Some(len::METADATA) => {
+                ssp::Error::InvalidDataLength((0, 1))
+            }
+            Ok(false) => continue,
+            Err(err @ ssp::Error::InvalidSTX(_)) => {
+                // the unpaired STX starts a new frame
+                log::warn!("Restarting truncated frame: {err}");
+
+                in_frame = false;
+                discarded += raw.len() - 2;
+                pending.push_front(byte);
+                pending.push_front(STX);
+                first_err.get_or_insert(err);
+
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+
+        log::warn!("Dropping invalid frame: {err}");
+
+        // search the bytes after the dropped STX for the next frame
+        in_frame = false;
+        discarded += 1;
+        for &b in raw[1..].iter().rev() {
+            pending.push_front(b);
+        }
+        first_err.get_or_insert(err);
+    }
+}
+
+/// Reusable buffers for writing, and reading frames.
+///
+/// Frames stuffed with [stuff](Self::stuff), and read with
+/// [read_frame_timeout](Self::read_frame_timeout) reuse the same buffers, so a thread exchanging
+/// frames in a loop, e.g. the polling routine, does not allocate for every frame.
+#[derive(Debug)]
+pub struct FrameBuffers {
+    stuffed: Vec<u8>,
+    scratch: ReadScratch,
+    decoder: FrameDecoder,
+}
+
+impl FrameBuffers {
+    /// Creates a new [FrameBuffers], with room for the largest frame.
+    pub fn new() -> Self {
+        Self {
+            stuffed: Vec::with_capacity(len::MAX_MESSAGE * 2),
+            scratch: ReadScratch::new(),
+            decoder: FrameDecoder::new(),
+        }
+    }
+
+    /// Adds byte stuffing to an unstuffed `frame`, and returns the stuffed frame.
+    pub fn stuff(&mut self, frame: &[u8]) -> Result<&[u8]> {
+        stuff_into(frame, &mut self.stuffed)?;
+        Ok(self.stuffed.as_ref())
+    }
+
+    /// Reads a single valid frame from `transport`, like the
+    /// [read_frame_timeout](fn@read_frame_timeout) function.
+    ///
+    /// The frame, or the partial frame on failure, is available from [frame](Self::frame).
+    pub fn read_frame_timeout<T: Transport + ?Sized>(
+        &mut self,
+        transport: &mut T,
+        timeouts: FrameTimeouts,
+    ) -> Result<usize> {
+        read_frame_scratch(transport, &mut self.decoder, &mut self.scratch, timeouts)
+    }
+
+    /// Gets the unstuffed frame bytes of the last read.
+    pub fn frame(&self) -> &[u8] {
+        self.decoder.frame()
+    }
+}
+
+impl Default for FrameBuffers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Gets whether a command should be retransmitted after reading its response failed with `err`.
+///
+/// Corrupted frames, timeouts, and transport errors are retransmitted. Valid frames rejected for
+/// their contents are not, nor are [Encryption](ssp::Error::Encryption) errors: a plaintext
+/// response to an encrypted command is never retried, see
+/// [is_encryption_downgrade](crate::device_handle::is_encryption_downgrade).
+pub fn is_retransmittable(err: &ssp::Error) -> bool {
+    matches!(
+        err,
+        ssp::Error::Crc(_)
+            | ssp::Error::InvalidSTX(_)
+            | ssp::Error::InvalidDataLength(_)
+            | ssp::Error::Io(_)
+            | ssp::Error::SerialPort(_)
+            | ssp::Error::Timeout(_)
+    )
+}
+
+/// Gets whether reading a response failed with `err`, because no response arrived in time.
+///
+/// Transport timeouts surface as [Io](ssp::Error::Io) errors.
+pub fn is_timeout(err: &ssp::Error) -> bool {
+    match err {
+        ssp::Error::Timeout(_) => true,
+        ssp::Error::Io(msg) => msg.contains("timed out"),
+        _ => false,
+    }
+}
+
+/// Checks an unstuffed `frame`: the `STX` byte, the length field against the frame length, and
+/// the CRC.
+pub fn check_frame(frame: &[u8]) -> Result<()> {
+    if frame.len() < len::METADATA {
+        return Err(ssp::Error::InvalidLength((frame.len(), len::METADATA)));
+    }
+
+    if frame[index::STX] != STX {
+        return Err(ssp::Error::InvalidSTX(frame[index::STX]));
+    }
+
+    let expected = usize::from(frame[index::LEN]) + len::METADATA;
+    if frame.len() != expected {
+        return Err(ssp::Error::InvalidLength((frame.len(), expected)));
+    }
+
+    check_crc(frame)
+}
+
+/// Parses an unstuffed response `frame` to a `command`.
+///
+/// The frame is checked with [check_frame] before it is parsed, so the length field is never
+/// trusted past the end of the frame.
+pub fn parse_response(frame: &[u8], command: ssp::MessageType) -> Result<ssp::MessageVariant> {
+    check_frame(frame)?;
+    ssp::MessageVariant::from_buf(frame, command)
+}
+
+fn check_crc(frame: &[u8]) -> Result<()> {
+    let crc_start = frame.len().saturating_sub(len::FOOTER).max(1);
+    let have = ssp::crc::crc16(frame[1..crc_start].as_ref());
+
+    let exp = match frame[crc_start..] {
+        [lo, hi] => u16::from_le_bytes([lo, hi]),
+        _ => return Err(ssp::Error::InvalidLength((frame.len(), len::METADATA))),
+    };
+
+    if have == exp {
+        Ok(())
+    } else {
+        Err(ssp::Error::Crc((have, exp)))
+    }
+}
@@ -0,0 +1,492 @@
+//! gRPC frontend for controlling a SSP/eSSP device over the network.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use parking_lot::Mutex;
+use ssp::ResponseOps;
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+use crate::audit;
+use crate::auth::{self, Role};
+use crate::cash_levels::{CashEstimate, DenominationLevel};
+use crate::{DeviceHandle, Error, PushEventReceiver, Server};
+
+/// Generated protobuf types and service definitions.
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("ssp_server");
+}
+
+use proto::device_server::{Device, DeviceServer};
+
+/// Default listening address for the gRPC server.
+pub const GRPC_ADDR: &str = "127.0.0.1:50051";
+/// Environment variable for overriding the gRPC listening address.
+pub const GRPC_ENV_ADDR: &str = "SSP_GRPC_ADDR";
+/// Maximum number of events buffered for slow `Events` subscribers.
+pub const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Gets the gRPC listening address from the environment, or the default address.
+pub fn get_grpc_addr() -> ssp::Result<SocketAddr> {
+    std::env::var(GRPC_ENV_ADDR)
+        .unwrap_or(GRPC_ADDR.into())
+        .parse::<SocketAddr>()
+        .map_err(|err| ssp::Error::Io(format!("invalid gRPC address: {err}")))
+}
+
+/// gRPC [Device] service backed by a shared [DeviceHandle].
+pub struct GrpcService {
+    handle: Arc<Mutex<DeviceHandle>>,
+    events: broadcast::Sender<ssp::Event>,
+}
+
+impl GrpcService {
+    /// Creates a new [GrpcService].
+    ///
+    /// # Parameters
+    ///
+    /// - `handle`: shared [DeviceHandle] used to send commands to the device
+    /// - `events`: broadcast channel for device events streamed to `Events` subscribers
+    pub fn new(handle: Arc<Mutex<DeviceHandle>>, events: broadcast::Sender<ssp::Event>) -> Self {
+        Self { handle, events }
+    }
+
+    // Runs a blocking device operation returning a structured [Error], on the blocking thread
+    // pool.
+    async fn run<T, F>(&self, f: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnMut(&DeviceHandle) -> Result<T, Error> + Send + 'static,
+    {
+        let handle = Arc::clone(&self.handle);
+
+        tokio::task::spawn_blocking(move || {
+            // retries once with a new key if the eSSP session was lost
+            Server::lock_handle(&handle)?.with_rekey(f)
+        })
+        .await
+        .map_err(|err| Status::internal(format!("device task failed: {err}")))?
+        .map_err(status_from_error)
+    }
+
+    // Runs a device operation, if the bearer token grants at least `role`.
+    async fn with_role<T, F>(
+        &self,
+        token: Option<String>,
+        role: Role,
+        mut f: F,
+    ) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnMut(&DeviceHandle) -> ssp::Result<T> + Send + 'static,
+    {
+        self.run_with_role(token, role, move |handle| f(handle).map_err(Error::from))
+            .await
+    }
+
+    // Runs a device operation returning a structured [Error], if the bearer token grants at
+    // least `role`.
+    async fn run_with_role<T, F>(
+        &self,
+        token: Option<String>,
+        role: Role,
+        mut f: F,
+    ) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnMut(&DeviceHandle) -> Result<T, Error> + Send + 'static,
+    {
+        self.run(move |handle| {
+            let granted = handle.auth().authorize_role(token.as_deref(), role)?;
+            audit::with_actor(format!("grpc:{granted}").as_str(), || f(handle))
+        })
+        .await
+    }
+
+    // Runs a state-changing device operation, if allowed by the bearer token, and no client of
+    // another frontend holds the lease.
+    //
+    // gRPC clients are anonymous for lease arbitration.
+    async fn with_command<T, F>(
+        &self,
+        token: Option<String>,
+        method: ssp::Method,
+        mut f: F,
+    ) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnMut(&DeviceHandle) -> ssp::Result<T> + Send + 'static,
+    {
+        self.run_with_role(token, auth::required_role(method), move |handle| {
+            handle.leases().authorize(None)?;
+            f(handle).map_err(Error::from)
+        })
+        .await
+    }
+}
+
+#[tonic::async_trait]
+impl Device for GrpcService {
+    async fn enable(
+        &self,
+        request: Request<proto::EnableRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        let token = bearer_token(&request)?;
+        let payout = request.into_inner().payout;
+
+        let status = self
+            .with_command(token, ssp::Method::Accept, move |handle| {
+                let res = handle.enable()?;
+                if payout {
+                    handle.enable_payout()?;
+                }
+                Ok(res.response_status())
+            })
+            .await?;
+
+        Ok(Response::new(command_reply(status)))
+    }
+
+    async fn disable(
+        &self,
+        request: Request<proto::DisableRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        let token = bearer_token(&request)?;
+        let payout = request.into_inner().payout;
+
+        let status = self
+            .with_command(token, ssp::Method::Stop, move |handle| {
+                if payout {
+                    handle.disable_payout()?;
+                }
+                Ok(handle.disable()?.response_status())
+            })
+            .await?;
+
+        Ok(Response::new(command_reply(status)))
+    }
+
+    async fn stack(
+        &self,
+        request: Request<proto::StackRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        self.with_command(bearer_token(&request)?, ssp::Method::Stack, |handle| {
+            handle.stack()
+        })
+        .await?;
+
+        Ok(Response::new(command_reply(ssp::ResponseStatus::Ok)))
+    }
+
+    async fn reject(
+        &self,
+        request: Request<proto::RejectRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        let status = self
+            .with_command(bearer_token(&request)?, ssp::Method::Reject, |handle| {
+                Ok(handle.reject()?.response_status())
+            })
+            .await?;
+
+        Ok(Response::new(command_reply(status)))
+    }
+
+    async fn payout(
+        &self,
+        request: Request<proto::PayoutRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        let token = bearer_token(&request)?;
+        let list = payout_list(&request.into_inner())?;
+
+        self.with_command(token, ssp::Method::Dispense, move |handle| {
+            handle.dispense(&list)
+        })
+        .await?;
+
+        Ok(Response::new(command_reply(ssp::ResponseStatus::Ok)))
+    }
+
+    async fn status(
+        &self,
+        request: Request<proto::StatusRequest>,
+    ) -> Result<Response<proto::StatusReply>, Status> {
+        let status = self
+            .with_role(bearer_token(&request)?, Role::Observer, |handle| {
+                handle.device_status()
+            })
+            .await?;
+
+        Ok(Response::new(proto::StatusReply::from(&status)))
+    }
+
+    /// Gets the host-side [CashLevels](crate::cash_levels::CashLevels) estimate, kept from credited
+    /// notes, payouts, and empties, not levels reported by the device.
+    ///
+    /// Fails with `FAILED_PRECONDITION` if cash levels are not configured on the handle.
+    async fn levels(
+        &self,
+        request: Request<proto::LevelsRequest>,
+    ) -> Result<Response<proto::LevelsReply>, Status> {
+        let estimate = self
+            .run_with_role(bearer_token(&request)?, Role::Observer, |handle| {
+                handle
+                    .cash_levels()
+                    .map(|levels| levels.estimate())
+                    .ok_or(Error::not_configured(ssp::Error::Io(
+                        "cash levels are not configured".into(),
+                    )))
+            })
+            .await?;
+
+        Ok(Response::new(proto::LevelsReply::from(&estimate)))
+    }
+
+    type EventsStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send + 'static>>;
+
+    async fn events(
+        &self,
+        request: Request<proto::EventsRequest>,
+    ) -> Result<Response<Self::EventsStream>, Status> {
+        self.with_role(bearer_token(&request)?, Role::Observer, |_| Ok(()))
+            .await?;
+
+        let stream =
+            BroadcastStream::new(self.events.subscribe()).filter_map(|event| match event {
+                Ok(event) => Some(proto::Event::try_from(&event)),
+                Err(err) => {
+                    log::warn!("gRPC event subscriber missed events: {err}");
+                    None
+                }
+            });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+impl From<&ssp::DeviceStatus> for proto::StatusReply {
+    fn from(val: &ssp::DeviceStatus) -> Self {
+        Self {
+            response_status: val.response_status().to_string(),
+            unit_type: val.unit_type().to_string(),
+            firmware_version: val.firmware_version().to_string(),
+            country_code: val.country_code().to_string(),
+            value_multiplier: val.value_multiplier().to_string(),
+            protocol_version: u8::from(val.protocol_version()).into(),
+            dataset_version: val.dataset_version().into(),
+            cashbox_attached: val.cashbox_attached(),
+        }
+    }
+}
+
+impl From<&DenominationLevel> for proto::NoteLevel {
+    fn from(val: &DenominationLevel) -> Self {
+        Self {
+            value: val.value,
+            count: val.count,
+        }
+    }
+}
+
+impl From<&proto::NoteLevel> for DenominationLevel {
+    fn from(val: &proto::NoteLevel) -> Self {
+        Self::new(val.value, val.count)
+    }
+}
+
+impl From<&CashEstimate> for proto::LevelsReply {
+    fn from(val: &CashEstimate) -> Self {
+        Self {
+            cashbox: val.cashbox.iter().map(proto::NoteLevel::from).collect(),
+            recycler: val.recycler.iter().map(proto::NoteLevel::from).collect(),
+            updated_ms: val.updated_ms,
+        }
+    }
+}
+
+impl From<&proto::LevelsReply> for CashEstimate {
+    fn from(val: &proto::LevelsReply) -> Self {
+        Self {
+            cashbox: val.cashbox.iter().map(DenominationLevel::from).collect(),
+            recycler: val.recycler.iter().map(DenominationLevel::from).collect(),
+            updated_ms: val.updated_ms,
+        }
+    }
+}
+
+impl TryFrom<&ssp::Event> for proto::Event {
+    type Error = Status;
+
+    fn try_from(val: &ssp::Event) -> Result<Self, Self::Error> {
+        Ok(Self {
+            method: val.method().to_str().into(),
+            payload: serde_json::to_string(val.payload())
+                .map_err(|err| Status::internal(format!("invalid event payload: {err}")))?,
+        })
+    }
+}
+
+fn command_reply(status: ssp::ResponseStatus) -> proto::CommandReply {
+    proto::CommandReply {
+        response_status: status.to_string(),
+    }
+}
+
+fn payout_list(request: &proto::PayoutRequest) -> Result<ssp::PayoutDenominationList, Status> {
+    let mut list = ssp::PayoutDenominationList::new();
+
+    for denom in request.denominations.iter() {
+        let number = u16::try_from(denom.number)
+            .map_err(|_| Status::invalid_argument("payout note number out of range"))?;
+
+        list.as_inner_mut()
+            .push(ssp::PayoutDenomination::create(
+                number,
+                denom.value,
+                ssp::CountryCode::from(denom.currency.as_str()),
+            ))
+            .map_err(|_| Status::invalid_argument("too many payout denominations"))?;
+    }
+
+    if list.is_empty() {
+        Err(Status::invalid_argument("empty payout request"))
+    } else {
+        Ok(list)
+    }
+}
+
+// Gets the token from the `authorization: Bearer <token>` metadata, if any.
+fn bearer_token<T>(request: &Request<T>) -> Result<Option<String>, Status> {
+    match request.metadata().get("authorization") {
+        Some(val) => val
+            .to_str()
+            .ok()
+            .and_then(auth::bearer_token)
+            .map(|token| Some(token.into()))
+            .ok_or(Status::unauthenticated("invalid authorization metadata")),
+        None => Ok(None),
+    }
+}
+
+fn status_from_error(err: Error) -> Status {
+    let msg = format!("{}", err.ssp_error());
+
+    match err {
+        Error::Unauthenticated(_) => Status::unauthenticated(msg),
+        Error::PermissionDenied(_) => Status::permission_denied(msg),
+        Error::InvalidRequest(_) => Status::invalid_argument(msg),
+        Error::Timeout(_) => Status::deadline_exceeded(msg),
+        Error::Encryption(_) | Error::Busy(_) | Error::NotConfigured(_) => {
+            Status::failed_precondition(msg)
+        }
+        Error::DeviceStatus(_) => Status::aborted(msg),
+        _ => Status::internal(msg),
+    }
+}
+
+/// Serves the gRPC [Device] service on `addr` until `stop` is set.
+///
+/// Events popped from the `push_queue` are broadcast to all `Events` subscribers.
+///
+/// # Parameters
+///
+/// - `handle`: shared [DeviceHandle] used to send commands to the device
+/// - `push_queue`: device event queue returned from background polling
+/// - `addr`: socket address to listen on
+/// - `stop`: atomic flag for stopping the server
+pub fn serve(
+    handle: Arc<Mutex<DeviceHandle>>,
+    push_queue: PushEventReceiver,
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+) -> ssp::Result<()> {
+    serve_inner(handle, push_queue, addr, stop, Ok)
+}
+
+/// Serves the gRPC [Device] service over TLS on `addr` until `stop` is set.
+///
+/// If `tls` sets a client CA, clients must present a certificate signed by the CA.
+///
+/// # Parameters
+///
+/// - `handle`: shared [DeviceHandle] used to send commands to the device
+/// - `push_queue`: device event queue returned from background polling
+/// - `addr`: socket address to listen on
+/// - `tls`: server certificate and client authentication settings
+/// - `stop`: atomic flag for stopping the server
+#[cfg(feature = "tls")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "tls")))]
+pub fn serve_tls(
+    handle: Arc<Mutex<DeviceHandle>>,
+    push_queue: PushEventReceiver,
+    addr: SocketAddr,
+    tls: &crate::tls::TlsConfig,
+    stop: Arc<AtomicBool>,
+) -> ssp::Result<()> {
+    let tls_config = tls.grpc_config()?;
+
+    serve_inner(handle, push_queue, addr, stop, move |server| {
+        server
+            .tls_config(tls_config)
+            .map_err(|err| ssp::Error::Io(format!("gRPC TLS error: {err}")))
+    })
+}
+
+fn serve_inner<F>(
+    handle: Arc<Mutex<DeviceHandle>>,
+    push_queue: PushEventReceiver,
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    configure: F,
+) -> ssp::Result<()>
+where
+    F: FnOnce(tonic::transport::Server) -> ssp::Result<tonic::transport::Server>,
+{
+    let mut server = configure(tonic::transport::Server::builder())?;
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+    let event_tx = events.clone();
+    let stop_events = Arc::clone(&stop);
+
+    let forwarder = thread::spawn(move || {
+        while !stop_events.load(Ordering::Relaxed) {
+            while let Ok(event) = push_queue.pop_event() {
+                log::trace!("Broadcasting event to gRPC subscribers: {event}");
+                // sending only fails when there are no subscribers
+                let _ = event_tx.send(event);
+            }
+        }
+    });
+
+    log::info!("Serving gRPC on {addr}");
+
+    let service = DeviceServer::new(GrpcService::new(handle, events));
+
+    runtime
+        .block_on(async move {
+            server
+                .add_service(service)
+                .serve_with_shutdown(addr, async move {
+                    while !stop.load(Ordering::Relaxed) {
+                        tokio::time::sleep(time::Duration::from_millis(250)).await;
+                    }
+                })
+                .await
+        })
+        .map_err(|err| ssp::Error::Io(format!("gRPC server error: {err}")))?;
+
+    forwarder
+        .join()
+        .map_err(|err| ssp::Error::Io(format!("error joining gRPC event thread: {err:?}")))?;
+
+    Ok(())
+}
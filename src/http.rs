@@ -0,0 +1,844 @@
+//! REST HTTP frontend for controlling a SSP/eSSP device over the network.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time;
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Query, Request, State},
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+use ssp::ResponseOps;
+
+use crate::audit::{self, AuditQuery, AuditRecord};
+use crate::auth::{self, Role};
+use crate::cash_levels::CashEstimate;
+use crate::codec::WireFormat;
+use crate::event_log::SequencedEvent;
+use crate::frame_log::{self, LoggedFrame};
+use crate::latency::{self, LatencyHistogram};
+use crate::lease::{ClientId, LeaseReply, LeaseToken};
+use crate::payout_intent::PayoutIntent;
+use crate::registry::DeviceRecord;
+use crate::reject_history::RejectStats;
+use crate::{DeviceHandle, Error, Server};
+
+/// Default listening address for the HTTP server.
+pub const HTTP_ADDR: &str = "127.0.0.1:8080";
+/// Header carrying the lease token returned from `POST /lease`.
+pub const LEASE_HEADER: &str = "x-ssp-lease";
+/// Environment variable for overriding the HTTP listening address.
+pub const HTTP_ENV_ADDR: &str = "SSP_HTTP_ADDR";
+
+/// Gets the HTTP listening address from the environment, or the default address.
+pub fn get_http_addr() -> ssp::Result<SocketAddr> {
+    std::env::var(HTTP_ENV_ADDR)
+        .unwrap_or(HTTP_ADDR.into())
+        .parse::<SocketAddr>()
+        .map_err(|err| ssp::Error::Io(format!("invalid HTTP address: {err}")))
+}
+
+/// Request body for the `POST /enable` and `POST /disable` endpoints.
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct EnableRequest {
+    /// Also enable/disable the payout module, if present.
+    #[serde(default)]
+    pub payout: bool,
+}
+
+/// Single denomination entry for the `POST /payout` endpoint.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Denomination {
+    /// Number of notes to dispense.
+    pub number: u16,
+    /// Note value in the smallest unit of the currency.
+    pub value: u32,
+    /// ISO 4217 currency code, e.g. `EUR`.
+    pub currency: String,
+}
+
+/// Request body for the `POST /payout` endpoint.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct PayoutRequest {
+    pub denominations: Vec<Denomination>,
+}
+
+/// Request body for the `POST /lease` endpoint.
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct LeaseRequest {
+    /// Lease timeout (milliseconds), the server default if unset.
+    #[serde(default)]
+    pub ttl_ms: Option<u64>,
+}
+
+/// Query parameters for the `GET /events` endpoint.
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct EventsQuery {
+    /// Sequence number of the last event received by the client, `None` for all logged events.
+    #[serde(default)]
+    pub since: Option<u64>,
+}
+
+/// Query parameters for the `GET /rejects` endpoint.
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct RejectsQuery {
+    /// Start of the window (milliseconds since the Unix epoch), `None` for all records.
+    #[serde(default)]
+    pub since_ms: Option<u64>,
+    /// End of the window (milliseconds since the Unix epoch), `None` for all records.
+    #[serde(default)]
+    pub until_ms: Option<u64>,
+}
+
+/// Response body for command endpoints.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct CommandReply {
+    /// Response status returned by the device.
+    pub response_status: String,
+}
+
+impl From<ssp::ResponseStatus> for CommandReply {
+    fn from(val: ssp::ResponseStatus) -> Self {
+        Self {
+            response_status: val.to_string(),
+        }
+    }
+}
+
+/// Error returned from API endpoints, rendered as a JSON body with a matching HTTP status code.
+#[derive(Clone, Debug)]
+pub struct ApiError(Error);
+
+impl From<ssp::Error> for ApiError {
+    fn from(err: ssp::Error) -> Self {
+        Self(err.into())
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        Self(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let code = match &self.0 {
+            Error::Unauthenticated(_) => StatusCode::UNAUTHORIZED,
+            Error::PermissionDenied(_) => StatusCode::FORBIDDEN,
+            Error::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            Error::NotConfigured(_) => StatusCode::NOT_IMPLEMENTED,
+            Error::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Error::Encryption(_) => StatusCode::PRECONDITION_FAILED,
+            Error::DeviceStatus(_) | Error::Busy(_) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        let body = serde_json::json!({ "error": format!("{}", self.0.ssp_error()) });
+
+        (code, Json(body)).into_response()
+    }
+}
+
+/// Request body extractor, decoded according to the `Content-Type` header.
+///
+/// Bodies without a supported `Content-Type` are decoded as JSON.
+#[derive(Clone, Debug)]
+pub struct Decoded<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for Decoded<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let format = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|val| val.to_str().ok())
+            .and_then(WireFormat::from_mime)
+            .unwrap_or_default();
+
+        let body = Bytes::from_request(req, state).await.map_err(|err| {
+            Error::invalid_request(ssp::Error::Io(format!("invalid request body: {err}")))
+        })?;
+
+        if body.is_empty() {
+            return Err(Error::invalid_request(ssp::Error::InvalidDataLength((0, 1))).into());
+        }
+
+        Ok(Self(
+            format
+                .decode(body.as_ref())
+                .map_err(Error::invalid_request)?,
+        ))
+    }
+}
+
+/// Response [WireFormat] negotiated from the `Accept` header.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Negotiated(pub WireFormat);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Negotiated {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(
+            parts
+                .headers
+                .get(header::ACCEPT)
+                .and_then(|val| val.to_str().ok())
+                .map(WireFormat::from_accept)
+                .unwrap_or_default(),
+        ))
+    }
+}
+
+/// [LeaseToken] sent in the [LEASE_HEADER] header, `None` for anonymous requests.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LeaseHeader(pub Option<LeaseToken>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for LeaseHeader {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.headers.get(LEASE_HEADER) {
+            Some(val) => val
+                .to_str()
+                .ok()
+                .and_then(|val| val.parse::<LeaseToken>().ok())
+                .map(|token| Self(Some(token)))
+                .ok_or(
+                    Error::invalid_request(ssp::Error::Io(format!(
+                        "invalid {LEASE_HEADER} header"
+                    )))
+                    .into(),
+                ),
+            None => Ok(Self(None)),
+        }
+    }
+}
+
+/// Bearer token sent in the `Authorization` header, `None` for anonymous requests.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Credentials(pub Option<String>);
+
+impl Credentials {
+    /// Gets the bearer token, if any.
+    pub fn token(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Credentials {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.headers.get(header::AUTHORIZATION) {
+            Some(val) => val
+                .to_str()
+                .ok()
+                .and_then(auth::bearer_token)
+                .map(|token| Self(Some(token.into())))
+                .ok_or(
+                    Error::unauthenticated(ssp::Error::Io(format!(
+                        "{}, invalid Authorization header",
+                        auth::AUTH_UNAUTHENTICATED
+                    )))
+                    .into(),
+                ),
+            None => Ok(Self(None)),
+        }
+    }
+}
+
+/// Response body encoded in the negotiated [WireFormat].
+#[derive(Clone, Debug)]
+pub struct Encoded<T>(pub WireFormat, pub T);
+
+impl<T: Serialize> IntoResponse for Encoded<T> {
+    fn into_response(self) -> Response {
+        let Self(format, val) = self;
+
+        match format.encode(&val) {
+            Ok(body) => ([(header::CONTENT_TYPE, format.mime())], body).into_response(),
+            Err(err) => ApiError::from(err).into_response(),
+        }
+    }
+}
+
+/// Shared state for the HTTP endpoints.
+#[derive(Clone)]
+pub struct HttpState {
+    handle: Arc<Mutex<DeviceHandle>>,
+}
+
+impl HttpState {
+    /// Creates a new [HttpState].
+    pub fn new(handle: Arc<Mutex<DeviceHandle>>) -> Self {
+        Self { handle }
+    }
+
+    // Runs a blocking device operation returning a structured [Error], on the blocking thread
+    // pool.
+    async fn run<T, F>(&self, f: F) -> Result<T, ApiError>
+    where
+        T: Send + 'static,
+        F: FnMut(&DeviceHandle) -> Result<T, Error> + Send + 'static,
+    {
+        let handle = Arc::clone(&self.handle);
+
+        tokio::task::spawn_blocking(move || {
+            // retries once with a new key if the eSSP session was lost
+            Server::lock_handle(&handle)?.with_rekey(f)
+        })
+        .await
+        .map_err(|err| ssp::Error::Io(format!("device task failed: {err}")))?
+        .map_err(ApiError::from)
+    }
+
+    // Runs a device operation, if the credentials grant at least `role`.
+    async fn with_role<T, F>(&self, creds: Credentials, role: Role, mut f: F) -> Result<T, ApiError>
+    where
+        T: Send + 'static,
+        F: FnMut(&DeviceHandle) -> ssp::Result<T> + Send + 'static,
+    {
+        self.run_with_role(creds, role, move |handle| f(handle).map_err(Error::from))
+            .await
+    }
+
+    // Runs a device operation returning a structured [Error], if the credentials grant at least
+    // `role`.
+    async fn run_with_role<T, F>(
+        &self,
+        creds: Credentials,
+        role: Role,
+        mut f: F,
+    ) -> Result<T, ApiError>
+    where
+        T: Send + 'static,
+        F: FnMut(&DeviceHandle) -> Result<T, Error> + Send + 'static,
+    {
+        self.run(move |handle| {
+            let granted = handle.auth().authorize_role(creds.token(), role)?;
+            audit::with_actor(format!("http:{granted}").as_str(), || f(handle))
+        })
+        .await
+    }
+
+    // Runs a state-changing device operation, if allowed by the credentials, and the current
+    // lease.
+    async fn with_lease<T, F>(
+        &self,
+        creds: Credentials,
+        token: LeaseHeader,
+        method: ssp::Method,
+        mut f: F,
+    ) -> Result<T, ApiError>
+    where
+        T: Send + 'static,
+        F: FnMut(&DeviceHandle) -> ssp::Result<T> + Send + 'static,
+    {
+        self.run_with_role(creds, auth::required_role(method), move |handle| {
+            // unknown tokens are anonymous
+            let leases = handle.leases();
+            leases.authorize(token.0.and_then(|token| leases.holder_of(&token)))?;
+            f(handle).map_err(Error::from)
+        })
+        .await
+    }
+}
+
+/// Creates the [Router] for the REST API.
+///
+/// Request and response bodies are JSON by default. Clients can use CBOR or MessagePack by
+/// setting the `Content-Type` and `Accept` headers, when the `cbor` or `msgpack` features are
+/// enabled. Error responses are always JSON.
+///
+/// Endpoints:
+///
+/// - `POST /enable`: enables the device, optionally with the payout module
+/// - `POST /disable`: disables the device, optionally with the payout module
+/// - `POST /stack`: stacks a note held in escrow
+/// - `POST /reject`: rejects a note held in escrow
+/// - `POST /payout`: dispenses notes by denomination
+/// - `GET /status`: gets the current device status
+/// - `POST /lease`: claims, or renews the exclusive [lease](crate::lease)
+/// - `DELETE /lease`: releases the lease
+/// - `GET /audit`: queries the [audit log](crate::audit), filtered by the `op`, `actor`,
+///   `since_ms`, `until_ms`, and `limit` query parameters
+/// - `GET /levels`, or `GET /cash-levels`: gets the estimated cashbox, and recycler contents,
+///   see [cash_levels](crate::cash_levels)
+/// - `GET /frames`: gets the most recent frames exchanged with the device, see
+///   [frame_log](crate::frame_log)
+/// - `GET /events`: gets the logged device events after the `since` sequence number, see
+///   [event_log](crate::event_log)
+/// - `GET /devices`: gets the devices recorded in the [device registry](crate::registry)
+/// - `GET /latency`: gets the round-trip latency histograms of the commands sent to the device,
+///   see [latency](crate::latency)
+/// - `GET /metrics`: gets the latency histograms in the Prometheus text format
+/// - `GET /rejects`: gets the reject statistics between the `since_ms`, and `until_ms` query
+///   parameters, see [reject_history](crate::reject_history)
+/// - `GET /payout-intents`: gets the payouts with an unknown outcome, see
+///   [payout_intent](crate::payout_intent)
+///
+/// While a lease is held, state-changing endpoints require the lease token in the
+/// `X-SSP-Lease` header, and respond with `409 Conflict` otherwise.
+///
+/// Malformed requests, e.g. an invalid body, or lease header, get `400 Bad Request`, and
+/// endpoints of features the server was not configured with, e.g. the audit log, get
+/// `501 Not Implemented`.
+///
+/// With [authentication](crate::auth) enabled, requests require a bearer token in the
+/// `Authorization` header. Missing, or unknown tokens get `401 Unauthorized`, and tokens with an
+/// insufficient role get `403 Forbidden`. The status, cash levels, events, devices, latency, and
+/// metrics endpoints require an observer, payouts a maintainer, and all other endpoints an
+/// operator. The audit log, frames, reject statistics, and payout intents require a maintainer.
+pub fn router(state: HttpState) -> Router {
+    Router::new()
+        .route("/enable", post(enable))
+        .route("/disable", post(disable))
+        .route("/stack", post(stack))
+        .route("/reject", post(reject))
+        .route("/payout", post(payout))
+        .route("/status", get(status))
+        .route("/lease", post(claim_lease).delete(release_lease))
+        .route("/audit", get(audit_log))
+        .route("/levels", get(cash_levels))
+        .route("/cash-levels", get(cash_levels))
+        .route("/frames", get(frames))
+        .route("/events", get(events))
+        .route("/rejects", get(rejects))
+        .route("/payout-intents", get(payout_intents))
+        .route("/devices", get(devices))
+        .route("/latency", get(latency_histograms))
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+async fn enable(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+    token: LeaseHeader,
+    body: Option<Decoded<EnableRequest>>,
+) -> Result<Encoded<CommandReply>, ApiError> {
+    let payout = body.map(|Decoded(req)| req.payout).unwrap_or_default();
+
+    let status = state
+        .with_lease(creds, token, ssp::Method::Accept, move |handle| {
+            let res = handle.enable()?;
+            if payout {
+                handle.enable_payout()?;
+            }
+            Ok(res.response_status())
+        })
+        .await?;
+
+    Ok(Encoded(format, status.into()))
+}
+
+async fn disable(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+    token: LeaseHeader,
+    body: Option<Decoded<EnableRequest>>,
+) -> Result<Encoded<CommandReply>, ApiError> {
+    let payout = body.map(|Decoded(req)| req.payout).unwrap_or_default();
+
+    let status = state
+        .with_lease(creds, token, ssp::Method::Stop, move |handle| {
+            if payout {
+                handle.disable_payout()?;
+            }
+            Ok(handle.disable()?.response_status())
+        })
+        .await?;
+
+    Ok(Encoded(format, status.into()))
+}
+
+async fn stack(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+    token: LeaseHeader,
+) -> Result<Encoded<CommandReply>, ApiError> {
+    state
+        .with_lease(creds, token, ssp::Method::Stack, |handle| handle.stack())
+        .await?;
+
+    Ok(Encoded(format, ssp::ResponseStatus::Ok.into()))
+}
+
+async fn reject(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+    token: LeaseHeader,
+) -> Result<Encoded<CommandReply>, ApiError> {
+    let status = state
+        .with_lease(creds, token, ssp::Method::Reject, |handle| {
+            Ok(handle.reject()?.response_status())
+        })
+        .await?;
+
+    Ok(Encoded(format, status.into()))
+}
+
+async fn payout(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+    token: LeaseHeader,
+    Decoded(req): Decoded<PayoutRequest>,
+) -> Result<Encoded<CommandReply>, ApiError> {
+    let list = payout_list(&req)?;
+
+    state
+        .with_lease(creds, token, ssp::Method::Dispense, move |handle| {
+            handle.dispense(&list)
+        })
+        .await?;
+
+    Ok(Encoded(format, ssp::ResponseStatus::Ok.into()))
+}
+
+async fn status(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+) -> Result<Encoded<ssp::DeviceStatus>, ApiError> {
+    Ok(Encoded(
+        format,
+        state
+            .with_role(creds, Role::Observer, |handle| handle.device_status())
+            .await?,
+    ))
+}
+
+async fn claim_lease(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+    token: LeaseHeader,
+    body: Option<Decoded<LeaseRequest>>,
+) -> Result<Encoded<LeaseReply>, ApiError> {
+    let ttl = body
+        .and_then(|Decoded(req)| req.ttl_ms)
+        .map(time::Duration::from_millis);
+
+    let lease = state
+        .run_with_role(creds, Role::Operator, move |handle| {
+            // renew the lease of the sent token, or claim for a new client
+            let leases = handle.leases();
+            let client = token
+                .0
+                .and_then(|token| leases.holder_of(&token))
+                .unwrap_or_else(ClientId::next);
+
+            leases.claim(client, ttl)
+        })
+        .await?;
+
+    Ok(Encoded(format, LeaseReply::from(&lease)))
+}
+
+async fn release_lease(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+    token: LeaseHeader,
+) -> Result<Encoded<CommandReply>, ApiError> {
+    let token = token
+        .0
+        .ok_or(Error::invalid_request(ssp::Error::Io(format!(
+            "missing {LEASE_HEADER} header"
+        ))))?;
+
+    state
+        .run_with_role(creds, Role::Operator, move |handle| {
+            let leases = handle.leases();
+
+            match leases.holder_of(&token) {
+                Some(client) => leases.release(client),
+                // without an active lease, there is nothing to release
+                None => leases.authorize(None),
+            }
+        })
+        .await?;
+
+    Ok(Encoded(format, ssp::ResponseStatus::Ok.into()))
+}
+
+async fn audit_log(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+    Query(query): Query<AuditQuery>,
+) -> Result<Encoded<Vec<AuditRecord>>, ApiError> {
+    let records = state
+        .run_with_role(creds, Role::Maintainer, move |handle| {
+            let audit_log = handle.audit_log().ok_or(not_configured("audit log"))?;
+
+            Ok(audit_log.query(&query)?)
+        })
+        .await?;
+
+    Ok(Encoded(format, records))
+}
+
+async fn cash_levels(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+) -> Result<Encoded<CashEstimate>, ApiError> {
+    let estimate = state
+        .run_with_role(creds, Role::Observer, |handle| {
+            handle
+                .cash_levels()
+                .map(|levels| levels.estimate())
+                .ok_or(not_configured("cash levels"))
+        })
+        .await?;
+
+    Ok(Encoded(format, estimate))
+}
+
+async fn frames(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+) -> Result<Encoded<Vec<LoggedFrame>>, ApiError> {
+    let frames = state
+        .with_role(creds, Role::Maintainer, |_handle| {
+            Ok(frame_log::recent_frames())
+        })
+        .await?;
+
+    Ok(Encoded(format, frames))
+}
+
+async fn events(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+    Query(query): Query<EventsQuery>,
+) -> Result<Encoded<Vec<SequencedEvent>>, ApiError> {
+    let events = state
+        .run_with_role(creds, Role::Observer, move |handle| {
+            let event_log = handle.event_log().ok_or(not_configured("event log"))?;
+
+            match query.since {
+                Some(since) => Ok(event_log.events_since(since)?),
+                None => Ok(event_log.events()),
+            }
+        })
+        .await?;
+
+    Ok(Encoded(format, events))
+}
+
+async fn rejects(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+    Query(query): Query<RejectsQuery>,
+) -> Result<Encoded<RejectStats>, ApiError> {
+    let stats = state
+        .run_with_role(creds, Role::Maintainer, move |handle| {
+            let reject_history = handle
+                .reject_history()
+                .ok_or(not_configured("reject history"))?;
+
+            Ok(reject_history.stats(query.since_ms, query.until_ms)?)
+        })
+        .await?;
+
+    Ok(Encoded(format, stats))
+}
+
+async fn payout_intents(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+) -> Result<Encoded<Vec<PayoutIntent>>, ApiError> {
+    let intents = state
+        .run_with_role(creds, Role::Maintainer, |handle| {
+            handle
+                .payout_intents()
+                .map(|payout_intents| payout_intents.unresolved())
+                .ok_or(not_configured("payout intent log"))
+        })
+        .await?;
+
+    Ok(Encoded(format, intents))
+}
+
+async fn devices(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+) -> Result<Encoded<Vec<DeviceRecord>>, ApiError> {
+    let devices = state
+        .run_with_role(creds, Role::Observer, |handle| {
+            handle
+                .device_registry()
+                .map(|registry| registry.devices())
+                .ok_or(not_configured("device registry"))
+        })
+        .await?;
+
+    Ok(Encoded(format, devices))
+}
+
+async fn latency_histograms(
+    State(state): State<HttpState>,
+    Negotiated(format): Negotiated,
+    creds: Credentials,
+) -> Result<Encoded<Vec<LatencyHistogram>>, ApiError> {
+    let histograms = state
+        .with_role(creds, Role::Observer, |_handle| Ok(latency::histograms()))
+        .await?;
+
+    Ok(Encoded(format, histograms))
+}
+
+async fn metrics(State(state): State<HttpState>, creds: Credentials) -> Result<String, ApiError> {
+    let histograms = state
+        .with_role(creds, Role::Observer, |_handle| Ok(latency::histograms()))
+        .await?;
+
+    Ok(latency::to_prometheus(histograms.as_ref()))
+}
+
+// Creates the [NotConfigured](Error::NotConfigured) error for the optional `feature`.
+fn not_configured(feature: &str) -> Error {
+    Error::not_configured(ssp::Error::Io(format!("{feature} is not configured")))
+}
+
+fn payout_list(req: &PayoutRequest) -> Result<ssp::PayoutDenominationList, Error> {
+    let mut list = ssp::PayoutDenominationList::new();
+
+    for denom in req.denominations.iter() {
+        list.as_inner_mut()
+            .push(ssp::PayoutDenomination::create(
+                denom.number,
+                denom.value,
+                ssp::CountryCode::from(denom.currency.as_str()),
+            ))
+            .map_err(|_| {
+                Error::invalid_request(ssp::Error::InvalidLength((
+                    req.denominations.len(),
+                    ssp::MAX_PAYOUTS,
+                )))
+            })?;
+    }
+
+    if list.is_empty() {
+        Err(Error::invalid_request(ssp::Error::InvalidLength((0, 1))))
+    } else {
+        Ok(list)
+    }
+}
+
+/// Serves the REST API on `addr` until `stop` is set.
+///
+/// # Parameters
+///
+/// - `handle`: shared [DeviceHandle] used to send commands to the device
+/// - `addr`: socket address to listen on
+/// - `stop`: atomic flag for stopping the server
+pub fn serve(
+    handle: Arc<Mutex<DeviceHandle>>,
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+) -> ssp::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    let app = router(HttpState::new(handle));
+
+    runtime.block_on(async move {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+
+        log::info!("Serving HTTP on {addr}");
+
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                while !stop.load(Ordering::Relaxed) {
+                    tokio::time::sleep(time::Duration::from_millis(250)).await;
+                }
+            })
+            .await?;
+
+        Ok::<(), ssp::Error>(())
+    })
+}
+
+/// Serves the REST API over TLS on `addr` until `stop` is set.
+///
+/// If `tls` sets a client CA, clients must present a certificate signed by the CA.
+///
+/// # Parameters
+///
+/// - `handle`: shared [DeviceHandle] used to send commands to the device
+/// - `addr`: socket address to listen on
+/// - `tls`: server certificate and client authentication settings
+/// - `stop`: atomic flag for stopping the server
+#[cfg(feature = "tls")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "tls")))]
+pub fn serve_tls(
+    handle: Arc<Mutex<DeviceHandle>>,
+    addr: SocketAddr,
+    tls: &crate::tls::TlsConfig,
+    stop: Arc<AtomicBool>,
+) -> ssp::Result<()> {
+    let mut server_config = tls.server_config()?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    let config = axum_server::tls_rustls::RustlsConfig::from_config(Arc::new(server_config));
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    let app = router(HttpState::new(handle));
+
+    runtime.block_on(async move {
+        let server_handle = axum_server::Handle::new();
+        let shutdown = server_handle.clone();
+
+        tokio::spawn(async move {
+            while !stop.load(Ordering::Relaxed) {
+                tokio::time::sleep(time::Duration::from_millis(250)).await;
+            }
+            shutdown.graceful_shutdown(None);
+        });
+
+        log::info!("Serving HTTPS on {addr}");
+
+        axum_server::bind_rustls(addr, config)
+            .handle(server_handle)
+            .serve(app.into_make_service())
+            .await?;
+
+        Ok::<(), ssp::Error>(())
+    })
+}
@@ -0,0 +1,305 @@
+//! Append-only transaction journal of credits, payouts, and empties.
+//!
+//! Every credited note, every completed payout, and every empty is recorded with the device
+//! serial number, and a timestamp. Empties record the notes moved into the cashbox if the
+//! [cash levels](crate::cash_levels) are known, and a single entry without notes otherwise.
+//!
+//! Records are synced to disk before they are counted, and the journal is replayed when it is
+//! opened, so the running totals survive process crashes.
+//!
+//! A crash while appending leaves at most one incomplete record at the end of the journal. The
+//! incomplete record is discarded on the next [open](TransactionJournal::open), since the
+//! transaction was never acknowledged.
+//!
+//! The journal is kept in a file by default, or in any [Storage] backend with
+//! [from_storage](TransactionJournal::from_storage).
+
+use std::fmt;
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::storage::{FileStorage, Storage, JOURNAL_COLLECTION};
+
+/// Environment variable with the path of the transaction journal file.
+pub const JOURNAL_ENV_PATH: &str = "SSP_JOURNAL";
+
+/// Kind of a journaled transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TransactionKind {
+    /// Note accepted, and moved into storage.
+    Credit,
+    /// Notes dispensed from the payout module.
+    Payout,
+    /// Notes moved from the payout module into the cashbox.
+    Empty,
+}
+
+impl TransactionKind {
+    /// Gets the [TransactionKind] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Credit => "credit",
+            Self::Payout => "payout",
+            Self::Empty => "empty",
+        }
+    }
+
+    /// Parses a [TransactionKind] from its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "credit" => Some(Self::Credit),
+            "payout" => Some(Self::Payout),
+            "empty" => Some(Self::Empty),
+            _ => None,
+        }
+    }
+}
+
+/// Single entry in the transaction journal.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JournalEntry {
+    /// Sequence number, increasing by one for every entry.
+    pub seq: u64,
+    /// Time of the transaction, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Serial number of the device, zero if unknown.
+    pub serial_number: u32,
+    /// Kind of the transaction.
+    pub kind: TransactionKind,
+    /// Value of a single note.
+    pub value: u32,
+    /// Number of notes.
+    pub count: u32,
+    /// Currency of the notes, empty if unknown.
+    pub currency: String,
+}
+
+impl JournalEntry {
+    /// Gets the total value of the transaction.
+    pub fn amount(&self) -> u64 {
+        self.value as u64 * self.count as u64
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.seq,
+            self.timestamp_ms,
+            self.serial_number,
+            self.kind.as_str(),
+            self.value,
+            self.count,
+            self.currency,
+        )
+    }
+
+    fn from_line(line: &str) -> Result<Self> {
+        let invalid = || ssp::Error::Io(format!("invalid journal entry: {line}"));
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 7 {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            seq: fields[0].parse().map_err(|_| invalid())?,
+            timestamp_ms: fields[1].parse().map_err(|_| invalid())?,
+            serial_number: fields[2].parse().map_err(|_| invalid())?,
+            kind: TransactionKind::from_name(fields[3]).ok_or_else(invalid)?,
+            value: fields[4].parse().map_err(|_| invalid())?,
+            count: fields[5].parse().map_err(|_| invalid())?,
+            currency: fields[6].into(),
+        })
+    }
+}
+
+/// Running totals of the journaled transactions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JournalTotals {
+    /// Total value of credited notes.
+    pub credited: u64,
+    /// Number of credited notes.
+    pub credited_notes: u64,
+    /// Total value of dispensed notes.
+    pub paid_out: u64,
+    /// Number of dispensed notes.
+    pub paid_out_notes: u64,
+    /// Total value of notes emptied into the cashbox, if known.
+    pub emptied: u64,
+    /// Number of notes emptied into the cashbox, if known.
+    pub emptied_notes: u64,
+}
+
+impl JournalTotals {
+    /// Creates a new, empty [JournalTotals].
+    pub const fn new() -> Self {
+        Self {
+            credited: 0,
+            credited_notes: 0,
+            paid_out: 0,
+            paid_out_notes: 0,
+            emptied: 0,
+            emptied_notes: 0,
+        }
+    }
+
+    /// Gets the credited value minus the dispensed value.
+    ///
+    /// Empties move notes inside the device, and do not change the net value.
+    pub fn net(&self) -> i128 {
+        self.credited as i128 - self.paid_out as i128
+    }
+
+    fn add(&mut self, entry: &JournalEntry) {
+        match entry.kind {
+            TransactionKind::Credit => {
+                self.credited = self.credited.saturating_add(entry.amount());
+                self.credited_notes = self.credited_notes.saturating_add(entry.count as u64);
+            }
+            TransactionKind::Payout => {
+                self.paid_out = self.paid_out.saturating_add(entry.amount());
+                self.paid_out_notes = self.paid_out_notes.saturating_add(entry.count as u64);
+            }
+            TransactionKind::Empty => {
+                self.emptied = self.emptied.saturating_add(entry.amount());
+                self.emptied_notes = self.emptied_notes.saturating_add(entry.count as u64);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct JournalState {
+    next_seq: u64,
+    totals: JournalTotals,
+}
+
+/// Append-only transaction journal, with one entry per line.
+///
+/// Cloned journals share the same storage, and totals.
+#[derive(Clone)]
+pub struct TransactionJournal {
+    path: String,
+    storage: Arc<dyn Storage>,
+    state: Arc<Mutex<JournalState>>,
+}
+
+impl TransactionJournal {
+    /// Opens the journal at `path`, creating it if it does not exist.
+    ///
+    /// Replays the existing entries to restore the totals, and discards an incomplete entry left
+    /// by a crash. Returns `Err(_)` if any complete entry is invalid.
+    pub fn open(path: &str) -> Result<Self> {
+        Self::from_storage(Arc::new(FileStorage::for_file(JOURNAL_COLLECTION, path)))
+    }
+
+    /// Opens the journal kept in the [JOURNAL_COLLECTION] of the `storage`.
+    ///
+    /// Replays the existing entries to restore the totals. Returns `Err(_)` if any entry is
+    /// invalid.
+    pub fn from_storage(storage: Arc<dyn Storage>) -> Result<Self> {
+        let path = storage.location(JOURNAL_COLLECTION);
+
+        let mut state = JournalState {
+            next_seq: 0,
+            totals: JournalTotals::new(),
+        };
+
+        for line in storage.load(JOURNAL_COLLECTION)?.iter() {
+            let entry = JournalEntry::from_line(line)?;
+
+            state.totals.add(&entry);
+            state.next_seq = entry.seq + 1;
+        }
+
+        log::debug!("Replayed {} journal entries from {path}", state.next_seq);
+
+        Ok(Self {
+            path,
+            storage,
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    /// Opens the journal at the path set in the [JOURNAL_ENV_PATH] environment variable.
+    ///
+    /// Returns `Ok(None)` if the variable is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(JOURNAL_ENV_PATH) {
+            Ok(path) => Self::open(path.as_str()).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Gets the path of the journal file, or the location in the [Storage] backend.
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    /// Gets the running totals of all journaled transactions.
+    pub fn totals(&self) -> JournalTotals {
+        self.state.lock().totals
+    }
+
+    /// Appends an entry for a transaction of `count` notes of `value`.
+    ///
+    /// The entry is durably stored before the totals are updated.
+    pub fn record(
+        &self,
+        kind: TransactionKind,
+        serial_number: u32,
+        value: u32,
+        count: u32,
+        currency: &str,
+    ) -> Result<JournalEntry> {
+        let timestamp_ms = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        let mut state = self.state.lock();
+
+        let entry = JournalEntry {
+            seq: state.next_seq,
+            timestamp_ms,
+            serial_number,
+            kind,
+            value,
+            count,
+            currency: currency.replace(['\t', '\n'], ""),
+        };
+
+        self.storage
+            .append(JOURNAL_COLLECTION, entry.to_line().as_str())?;
+
+        state.next_seq += 1;
+        state.totals.add(&entry);
+
+        Ok(entry)
+    }
+
+    /// Gets all journal entries, oldest first.
+    pub fn entries(&self) -> Result<Vec<JournalEntry>> {
+        self.storage
+            .load(JOURNAL_COLLECTION)?
+            .iter()
+            .map(|line| JournalEntry::from_line(line))
+            .collect()
+    }
+}
+
+impl fmt::Debug for TransactionJournal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TransactionJournal")
+            .field("path", &self.path)
+            .field("storage", &self.storage.name())
+            .finish_non_exhaustive()
+    }
+}
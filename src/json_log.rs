@@ -0,0 +1,242 @@
+//! Structured JSON log of device events, and command outcomes.
+//!
+//! The [JsonLogSink] writes one JSON object per line, suitable for shipping to log pipelines,
+//! e.g. ELK, or a SIEM:
+//!
+//! - device events, status snapshots, and cash level alerts are written by adding the sink as an
+//!   [EventSink] to a [SinkDispatcher](crate::sink::SinkDispatcher)
+//! - command outcomes are written by attaching the sink with
+//!   [with_json_log](crate::DeviceHandle::with_json_log)
+//!
+//! Every [JsonLogRecord] has the same fields, set to `null` if they do not apply to the record
+//! kind, and carries the [DeviceIdentity] of the device:
+//!
+//! ```json
+//! {"timestamp_ms":1700000000000,"kind":"event","device":{"serial_number":1234,"device_id":"lane-3"},"method":"note_credit","ok":null,"error":null,"data":{...}}
+//! ```
+
+use std::fs;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::cash_levels::CashAlert;
+use crate::reject_code::RejectReason;
+use crate::sink::EventSink;
+
+/// Environment variable with the path of the JSON log file, `-` for stdout.
+pub const JSON_LOG_ENV_PATH: &str = "SSP_JSON_LOG";
+
+/// Environment variable with the site-specific device identifier attached to every record.
+pub const JSON_LOG_ENV_DEVICE_ID: &str = "SSP_JSON_LOG_DEVICE_ID";
+
+/// Kind of a [JsonLogRecord].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonLogKind {
+    /// Device event.
+    Event,
+    /// Outcome of a command sent to the device.
+    Command,
+    /// Periodic device status snapshot.
+    Status,
+    /// Cash level alert.
+    Alert,
+    /// Reason of a rejected note.
+    Reject,
+}
+
+/// Identity of the device attached to every [JsonLogRecord].
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct DeviceIdentity {
+    /// Serial number reported by the device, zero if unknown.
+    pub serial_number: u32,
+    /// Site-specific device identifier, if configured.
+    pub device_id: Option<String>,
+}
+
+/// Single line of the JSON log.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct JsonLogRecord {
+    /// Time the record was written, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Record kind.
+    pub kind: JsonLogKind,
+    /// Identity of the device.
+    pub device: DeviceIdentity,
+    /// Event, or command method, `None` for status snapshots, alerts, and reject reasons.
+    pub method: Option<String>,
+    /// Whether the command succeeded, `None` for other records.
+    pub ok: Option<bool>,
+    /// Error returned by the command, `None` if it succeeded, and for other records.
+    pub error: Option<String>,
+    /// Event, status, alert, or reject reason contents, `None` for commands.
+    pub data: Option<serde_json::Value>,
+}
+
+/// [EventSink] writing one [JsonLogRecord] per line, see [json_log](crate::json_log).
+///
+/// Cloned sinks share the same writer, and device serial number.
+#[derive(Clone)]
+pub struct JsonLogSink {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    serial_number: Arc<AtomicU32>,
+    device_id: Option<String>,
+}
+
+impl JsonLogSink {
+    /// Creates a new [JsonLogSink] writing to `writer`.
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+            serial_number: Arc::new(AtomicU32::new(0)),
+            device_id: None,
+        }
+    }
+
+    /// Creates a new [JsonLogSink] writing to stdout.
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    /// Opens the JSON log file at `path` for appending, creating it if it does not exist.
+    pub fn open(path: &str) -> Result<Self> {
+        let file = fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        Ok(Self::new(file))
+    }
+
+    /// Opens the JSON log set in the [JSON_LOG_ENV_PATH] environment variable, with the device
+    /// identifier set in [JSON_LOG_ENV_DEVICE_ID].
+    ///
+    /// Returns `Ok(None)` if the path is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(path) = std::env::var(JSON_LOG_ENV_PATH) else {
+            return Ok(None);
+        };
+
+        let sink = match path.as_str() {
+            "-" => Self::stdout(),
+            path => Self::open(path)?,
+        };
+
+        Ok(Some(match std::env::var(JSON_LOG_ENV_DEVICE_ID) {
+            Ok(device_id) => sink.with_device_id(device_id.as_str()),
+            Err(_) => sink,
+        }))
+    }
+
+    /// Gets the site-specific device identifier, if set.
+    pub fn device_id(&self) -> Option<&str> {
+        self.device_id.as_deref()
+    }
+
+    /// Builder function that sets the site-specific device identifier attached to every record.
+    pub fn with_device_id(mut self, device_id: &str) -> Self {
+        self.device_id = Some(device_id.into());
+        self
+    }
+
+    /// Sets the serial number of the device attached to new records.
+    ///
+    /// Set by the [DeviceHandle](crate::DeviceHandle) the sink is attached to, when the device
+    /// reports its serial number.
+    pub fn set_serial_number(&self, serial_number: u32) {
+        self.serial_number.store(serial_number, Ordering::Relaxed);
+    }
+
+    /// Gets the [DeviceIdentity] attached to new records.
+    pub fn identity(&self) -> DeviceIdentity {
+        DeviceIdentity {
+            serial_number: self.serial_number.load(Ordering::Relaxed),
+            device_id: self.device_id.clone(),
+        }
+    }
+
+    /// Writes the outcome of a command sent with `method`.
+    pub fn record_command<T>(&self, method: ssp::Method, res: &Result<T>) -> Result<()> {
+        let mut record = self.record(JsonLogKind::Command, Some(method.to_str()), None);
+        record.ok = Some(res.is_ok());
+        record.error = res.as_ref().err().map(|err| format!("{err}"));
+
+        self.write(&record)
+    }
+
+    /// Writes a [JsonLogRecord] as a single line.
+    pub fn write(&self, record: &JsonLogRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)
+            .map_err(|err| ssp::Error::Io(format!("JSON log encoding error: {err}")))?;
+        line.push(b'\n');
+
+        let mut writer = self.writer.lock();
+        writer.write_all(line.as_ref())?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn record(
+        &self,
+        kind: JsonLogKind,
+        method: Option<&str>,
+        data: Option<serde_json::Value>,
+    ) -> JsonLogRecord {
+        JsonLogRecord {
+            timestamp_ms: now_ms(),
+            kind,
+            device: self.identity(),
+            method: method.map(String::from),
+            ok: None,
+            error: None,
+            data,
+        }
+    }
+
+    fn write_data<T: serde::Serialize>(
+        &self,
+        kind: JsonLogKind,
+        method: Option<&str>,
+        data: &T,
+    ) -> Result<()> {
+        let data = serde_json::to_value(data)
+            .map_err(|err| ssp::Error::Io(format!("JSON log encoding error: {err}")))?;
+
+        self.write(&self.record(kind, method, Some(data)))
+    }
+}
+
+impl EventSink for JsonLogSink {
+    fn name(&self) -> &str {
+        "JSON log"
+    }
+
+    fn publish_event(&mut self, event: &ssp::Event) -> Result<()> {
+        self.write_data(JsonLogKind::Event, Some(event.method().to_str()), event)
+    }
+
+    fn publish_status(&mut self, status: &ssp::DeviceStatus) -> Result<()> {
+        self.write_data(JsonLogKind::Status, None, status)
+    }
+
+    fn publish_alert(&mut self, alert: &CashAlert) -> Result<()> {
+        self.write_data(JsonLogKind::Alert, None, alert)
+    }
+
+    fn publish_reject(&mut self, reason: &RejectReason) -> Result<()> {
+        self.write_data(JsonLogKind::Reject, None, reason)
+    }
+}
+
+fn now_ms() -> u64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,213 @@
+//! Kafka [EventSink] for feeding device events into data pipelines.
+//!
+//! Each record is keyed by `<device>:<seq>`, the device identifier and a per-sink event sequence
+//! number. Every event gets a distinct key, so consumers can de-duplicate records redelivered by
+//! the producer, e.g. with a compacted topic, or an idempotent downstream write.
+//!
+//! Record values are JSON encoded [Event](ssp::Event)s.
+
+use std::time;
+
+use ::kafka::producer::{Producer, Record, RequiredAcks};
+use ssp::Result;
+
+use crate::sink::EventSink;
+use crate::DeviceHandle;
+
+/// Default Kafka broker address.
+pub const KAFKA_BROKER: &str = "127.0.0.1:9092";
+/// Default topic.
+pub const KAFKA_TOPIC: &str = "ssp.events";
+/// Default timeout for broker acknowledgements (milliseconds).
+pub const KAFKA_ACK_TIMEOUT_MS: u64 = 5_000;
+
+/// Events published by default: credits and payouts.
+pub const KAFKA_DEFAULT_METHODS: [ssp::Method; 2] =
+    [ssp::Method::NoteCredit, ssp::Method::Dispense];
+
+/// Configuration for a [KafkaSink].
+#[derive(Clone, Debug, PartialEq)]
+pub struct KafkaConfig {
+    brokers: Vec<String>,
+    topic: String,
+    device: String,
+    methods: Vec<ssp::Method>,
+    start_sequence: u64,
+    ack_timeout: time::Duration,
+}
+
+impl KafkaConfig {
+    /// Creates a new [KafkaConfig] for the device identified by `device`, e.g. its serial number.
+    pub fn new(device: &str) -> Self {
+        Self {
+            brokers: vec![KAFKA_BROKER.into()],
+            topic: KAFKA_TOPIC.into(),
+            device: device.into(),
+            methods: KAFKA_DEFAULT_METHODS.into(),
+            start_sequence: 0,
+            ack_timeout: time::Duration::from_millis(KAFKA_ACK_TIMEOUT_MS),
+        }
+    }
+
+    /// Creates a new [KafkaConfig], using the serial number reported by the device.
+    pub fn from_device(handle: &DeviceHandle) -> Result<Self> {
+        let serial = handle.serial_number()?.serial_number();
+
+        Ok(Self::new(format!("{serial}").as_str()))
+    }
+
+    /// Gets the list of broker addresses.
+    pub fn brokers(&self) -> &[String] {
+        self.brokers.as_ref()
+    }
+
+    /// Builder function that sets the list of broker addresses.
+    pub fn with_brokers(mut self, brokers: &[&str]) -> Self {
+        self.brokers = brokers.iter().map(|&b| b.into()).collect();
+        self
+    }
+
+    /// Gets the topic.
+    pub fn topic(&self) -> &str {
+        self.topic.as_str()
+    }
+
+    /// Builder function that sets the topic.
+    pub fn with_topic(mut self, topic: &str) -> Self {
+        self.topic = topic.into();
+        self
+    }
+
+    /// Gets the device identifier used in record keys.
+    pub fn device(&self) -> &str {
+        self.device.as_str()
+    }
+
+    /// Gets the list of published event [Method](ssp::Method)s.
+    ///
+    /// An empty list publishes all events.
+    pub fn methods(&self) -> &[ssp::Method] {
+        self.methods.as_ref()
+    }
+
+    /// Builder function that sets the list of published event [Method](ssp::Method)s.
+    pub fn with_methods(mut self, methods: &[ssp::Method]) -> Self {
+        self.methods = methods.into();
+        self
+    }
+
+    /// Gets the sequence number of the first published event.
+    pub const fn start_sequence(&self) -> u64 {
+        self.start_sequence
+    }
+
+    /// Builder function that sets the sequence number of the first published event.
+    ///
+    /// Useful for continuing the key sequence after a restart, e.g. from the last offset
+    /// committed by a consumer.
+    pub fn with_start_sequence(mut self, seq: u64) -> Self {
+        self.start_sequence = seq;
+        self
+    }
+
+    /// Gets the timeout for broker acknowledgements.
+    pub const fn ack_timeout(&self) -> time::Duration {
+        self.ack_timeout
+    }
+
+    /// Builder function that sets the timeout for broker acknowledgements.
+    pub fn with_ack_timeout(mut self, timeout: time::Duration) -> Self {
+        self.ack_timeout = timeout;
+        self
+    }
+
+    /// Gets whether the [Event](ssp::Event) is published to Kafka.
+    pub fn publishes(&self, event: &ssp::Event) -> bool {
+        self.methods.is_empty() || self.methods.contains(&event.method())
+    }
+
+    /// Gets the record key for the event with sequence number `seq`.
+    pub fn record_key(&self, seq: u64) -> String {
+        format!("{}:{seq}", self.device)
+    }
+
+    /// Gets the record value for an [Event](ssp::Event), the JSON encoded event.
+    pub fn record_value(&self, event: &ssp::Event) -> Result<Vec<u8>> {
+        serde_json::to_vec(event)
+            .map_err(|err| ssp::Error::Io(format!("Kafka event encoding error: {err}")))
+    }
+}
+
+/// [EventSink] that produces JSON encoded events to a Kafka topic.
+///
+/// Records are acknowledged by all in-sync replicas. The sequence number also advances for failed
+/// events, so lost events show up as gaps in the key sequence.
+pub struct KafkaSink {
+    config: KafkaConfig,
+    producer: Producer,
+    seq: u64,
+}
+
+impl KafkaSink {
+    /// Creates a new [KafkaSink], and connects to the Kafka brokers.
+    pub fn new(config: KafkaConfig) -> Result<Self> {
+        let producer = Producer::from_hosts(config.brokers().to_vec())
+            .with_ack_timeout(config.ack_timeout())
+            .with_required_acks(RequiredAcks::All)
+            .with_client_id("ssp-server".into())
+            .create()
+            .map_err(kafka_error)?;
+
+        log::info!("Connected to Kafka at {}", config.brokers().join(","));
+
+        let seq = config.start_sequence();
+
+        Ok(Self {
+            config,
+            producer,
+            seq,
+        })
+    }
+
+    /// Gets a reference to the [KafkaConfig].
+    pub fn config(&self) -> &KafkaConfig {
+        &self.config
+    }
+
+    /// Gets the sequence number for the next event.
+    pub const fn sequence(&self) -> u64 {
+        self.seq
+    }
+}
+
+impl EventSink for KafkaSink {
+    fn name(&self) -> &str {
+        "Kafka"
+    }
+
+    fn publish_event(&mut self, event: &ssp::Event) -> Result<()> {
+        if !self.config.publishes(event) {
+            return Ok(());
+        }
+
+        let value = self.config.record_value(event)?;
+        let key = self.config.record_key(self.seq);
+        self.seq = self.seq.wrapping_add(1);
+
+        self.producer
+            .send(&Record::from_key_value(
+                self.config.topic(),
+                key.as_bytes(),
+                value,
+            ))
+            .map_err(kafka_error)?;
+
+        log::trace!("Produced event to Kafka with key: {key}");
+
+        Ok(())
+    }
+}
+
+fn kafka_error(err: ::kafka::Error) -> ssp::Error {
+    ssp::Error::Io(format!("Kafka error: {err}"))
+}
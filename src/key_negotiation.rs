@@ -0,0 +1,169 @@
+//! Diagnostics, and retry backoff for eSSP key negotiation.
+//!
+//! Some units run eSSP firmware that fails key exchange intermittently. Every failed negotiation
+//! attempt is recorded in a [KeyNegotiationDiagnostic], with the key sizes, the failed step, and
+//! the device response. Attempts are spaced out with exponential backoff, starting at
+//! [KEY_NEGOTIATION_BACKOFF_MS], and capped at [KEY_NEGOTIATION_MAX_BACKOFF_MS].
+
+use std::{fmt, time};
+
+/// Delay before the second key negotiation attempt (milliseconds), doubled for every further
+/// attempt.
+pub const KEY_NEGOTIATION_BACKOFF_MS: u64 = 500;
+/// Maximum delay between key negotiation attempts (milliseconds).
+pub const KEY_NEGOTIATION_MAX_BACKOFF_MS: u64 = 8_000;
+
+/// Gets the delay after the failed key negotiation `attempt`, counting from one.
+pub fn backoff(attempt: u32) -> time::Duration {
+    let shift = attempt.saturating_sub(1).min(16);
+    let delay = KEY_NEGOTIATION_BACKOFF_MS.saturating_mul(1 << shift);
+
+    time::Duration::from_millis(delay.min(KEY_NEGOTIATION_MAX_BACKOFF_MS))
+}
+
+/// Step of the eSSP key negotiation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum KeyNegotiationStep {
+    /// Resetting the sequence ID with a [SyncCommand](ssp::SyncCommand).
+    #[default]
+    Sync,
+    /// Sending the generator prime.
+    SetGenerator,
+    /// Sending the modulus prime.
+    SetModulus,
+    /// Exchanging the intermediate keys.
+    RequestKeyExchange,
+    /// Deriving the AES key from the exchanged keys.
+    SetKey,
+}
+
+impl KeyNegotiationStep {
+    /// Gets the [KeyNegotiationStep] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sync => "sync",
+            Self::SetGenerator => "set_generator",
+            Self::SetModulus => "set_modulus",
+            Self::RequestKeyExchange => "request_key_exchange",
+            Self::SetKey => "set_key",
+        }
+    }
+}
+
+impl fmt::Display for KeyNegotiationStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Record of a failed key negotiation attempt.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyNegotiationAttempt {
+    /// Attempt number, counting from one.
+    pub attempt: u32,
+    /// Step that failed.
+    pub step: KeyNegotiationStep,
+    /// Size of the generator prime (bits).
+    pub generator_bits: u32,
+    /// Size of the modulus prime (bits).
+    pub modulus_bits: u32,
+    /// Response status of the failed step, `None` if no response was received.
+    pub status: Option<ssp::ResponseStatus>,
+    /// Raw response of the failed step, empty if no response was received.
+    pub response: Vec<u8>,
+    /// Error returned by the failed step.
+    pub error: String,
+    /// Delay before the next attempt (milliseconds), zero after the last attempt.
+    pub backoff_ms: u64,
+}
+
+impl KeyNegotiationAttempt {
+    /// Creates a new [KeyNegotiationAttempt] for the keys used in the `attempt`.
+    pub fn new(attempt: u32, generator: &ssp::GeneratorKey, modulus: &ssp::ModulusKey) -> Self {
+        Self {
+            attempt,
+            generator_bits: u64::BITS - generator.as_inner().leading_zeros(),
+            modulus_bits: u64::BITS - modulus.as_inner().leading_zeros(),
+            ..Default::default()
+        }
+    }
+
+    /// Records the response to `step`, if one was received.
+    pub fn record_response<R: ssp::ResponseOps>(
+        &mut self,
+        step: KeyNegotiationStep,
+        res: &ssp::Result<R>,
+    ) {
+        self.step = step;
+
+        if let Ok(res) = res {
+            self.status = Some(res.response_status());
+            self.response = res.buf().into();
+        } else {
+            self.status = None;
+            self.response.clear();
+        }
+    }
+}
+
+impl fmt::Display for KeyNegotiationAttempt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "attempt #{}: {} failed, generator: {} bits, modulus: {} bits",
+            self.attempt, self.step, self.generator_bits, self.modulus_bits
+        )?;
+
+        if let Some(status) = self.status {
+            write!(f, ", status: {status}, response: {:x?}", self.response)?;
+        }
+
+        write!(f, ", error: {}", self.error)
+    }
+}
+
+/// Failed attempts of the last key negotiation.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyNegotiationDiagnostic {
+    /// Failed attempts, oldest first.
+    pub attempts: Vec<KeyNegotiationAttempt>,
+    /// Whether the negotiation established a key.
+    pub succeeded: bool,
+}
+
+impl KeyNegotiationDiagnostic {
+    /// Creates a new, empty [KeyNegotiationDiagnostic].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets whether any attempt failed.
+    pub fn has_failures(&self) -> bool {
+        !self.attempts.is_empty()
+    }
+}
+
+impl fmt::Display for KeyNegotiationDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let outcome = if self.succeeded {
+            "succeeded"
+        } else {
+            "failed"
+        };
+        write!(
+            f,
+            "key negotiation {outcome}, {} failed attempt(s)",
+            self.attempts.len()
+        )?;
+
+        for attempt in self.attempts.iter() {
+            write!(f, "; {attempt}")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+//! Scheduled rotation of the eSSP session key.
+//!
+//! Long-lived sessions reuse the same AES key for every encrypted packet. A [KeyRotationPolicy]
+//! negotiates a new key after a number of encrypted commands, or after the session reaches a
+//! maximum age, whichever comes first.
+//!
+//! Background polling pauses while a new key is negotiated, so rotation never interleaves with an
+//! in-flight poll.
+
+use std::time;
+
+/// Configures when the eSSP session key is rotated.
+///
+/// Rotation is disabled by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyRotationPolicy {
+    max_commands: Option<u32>,
+    max_age: Option<time::Duration>,
+}
+
+impl KeyRotationPolicy {
+    /// Creates a new [KeyRotationPolicy] with rotation disabled.
+    pub const fn new() -> Self {
+        Self {
+            max_commands: None,
+            max_age: None,
+        }
+    }
+
+    /// Gets the maximum number of encrypted commands sent with a session key.
+    pub const fn max_commands(&self) -> Option<u32> {
+        self.max_commands
+    }
+
+    /// Builder function that sets the maximum number of encrypted commands sent with a session
+    /// key.
+    ///
+    /// Background poll commands are included in the count.
+    pub fn with_max_commands(mut self, max: u32) -> Self {
+        self.max_commands = Some(max);
+        self
+    }
+
+    /// Gets the maximum age of a session key.
+    pub const fn max_age(&self) -> Option<time::Duration> {
+        self.max_age
+    }
+
+    /// Builder function that sets the maximum age of a session key.
+    pub fn with_max_age(mut self, max: time::Duration) -> Self {
+        self.max_age = Some(max);
+        self
+    }
+
+    /// Gets whether any rotation limit is set.
+    pub const fn is_enabled(&self) -> bool {
+        self.max_commands.is_some() || self.max_age.is_some()
+    }
+
+    /// Gets whether a session key used for `commands` encrypted commands, and negotiated `age`
+    /// ago, is due for rotation.
+    pub fn is_due(&self, commands: u32, age: time::Duration) -> bool {
+        self.max_commands
+            .map(|max| commands >= max)
+            .unwrap_or(false)
+            || self.max_age.map(|max| age >= max).unwrap_or(false)
+    }
+}
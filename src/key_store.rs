@@ -0,0 +1,464 @@
+//! Persistent storage for the eSSP [FixedKey](ssp::FixedKey).
+//!
+//! [set_encryption_key](crate::DeviceHandle::set_encryption_key) replaces the fixed part of the
+//! device's AES key. Without storing the new key, a restarted server negotiates with the default
+//! key, and is permanently locked out of encrypted communication with the device.
+//!
+//! Attach a [KeyStore] with [with_key_store](crate::DeviceHandle::with_key_store) to load the
+//! stored key on startup, and persist new keys before they are sent to the device.
+
+use std::fs;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+use ssp::Result;
+
+/// Environment variable with a site-specific [FixedKey](ssp::FixedKey), as 16 hex digits.
+pub const FIXED_KEY_ENV: &str = "SSP_FIXED_KEY";
+
+/// Length of the secret protecting a [FileKeyStore] (bytes).
+pub const KEY_STORE_SECRET_LEN: usize = ssp::aes::AES_KEY;
+
+// Identifies a key store record, and checks it decrypted with the right secret.
+const RECORD_MAGIC: [u8; 4] = *b"SSPK";
+const RECORD_VERSION: u8 = 1;
+const RECORD_LEN: usize = ssp::aes::AES_BLOCK;
+
+/// Parses a [FixedKey](ssp::FixedKey) from 16 hex digits, with an optional `0x` prefix.
+///
+/// The digits use the same notation as [DEFAULT_FIXED_KEY_U64](ssp::DEFAULT_FIXED_KEY_U64), e.g.
+/// `0123456701234567` for the default ITL key.
+pub fn parse_fixed_key(hex: &str) -> Result<ssp::FixedKey> {
+    let hex = hex.trim();
+    let hex = hex
+        .strip_prefix("0x")
+        .or_else(|| hex.strip_prefix("0X"))
+        .unwrap_or(hex);
+
+    if hex.len() != 16 {
+        return Err(ssp::Error::InvalidLength((hex.len(), 16)));
+    }
+
+    u64::from_str_radix(hex, 16)
+        .map(ssp::FixedKey::from_inner)
+        .map_err(|err| ssp::Error::Io(format!("invalid fixed key: {err}")))
+}
+
+/// Gets the [FixedKey](ssp::FixedKey) configured with the [FIXED_KEY_ENV] environment variable.
+///
+/// Returns `Ok(None)` if the variable is unset, and `Err(_)` if it is not a valid key.
+pub fn fixed_key_from_env() -> Result<Option<ssp::FixedKey>> {
+    match std::env::var(FIXED_KEY_ENV) {
+        Ok(hex) => parse_fixed_key(hex.as_str()).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Storage for the [FixedKey](ssp::FixedKey) negotiated with a device.
+pub trait KeyStore: Send {
+    /// Gets a short name for the store, used in log messages.
+    fn name(&self) -> &str;
+
+    /// Loads the stored [FixedKey](ssp::FixedKey).
+    ///
+    /// Returns `Ok(None)` if no key is stored.
+    fn load(&self) -> Result<Option<ssp::FixedKey>>;
+
+    /// Stores the [FixedKey](ssp::FixedKey), replacing any stored key.
+    fn store(&mut self, key: &ssp::FixedKey) -> Result<()>;
+}
+
+/// [KeyStore] that keeps the [FixedKey](ssp::FixedKey) in an AES encrypted file.
+///
+/// The file is only readable by the owner, and replaced atomically, so a crash while storing a key
+/// leaves the previous key intact.
+pub struct FileKeyStore {
+    path: PathBuf,
+    secret: [u8; KEY_STORE_SECRET_LEN],
+}
+
+impl FileKeyStore {
+    /// Creates a new [FileKeyStore] at `path`, encrypted with `secret`.
+    pub fn new(path: &str, secret: [u8; KEY_STORE_SECRET_LEN]) -> Self {
+        Self {
+            path: path.into(),
+            secret,
+        }
+    }
+
+    /// Creates a new [FileKeyStore] at `path`, encrypted with a hex-encoded `secret`.
+    ///
+    /// Returns `Err(_)` if `secret` is not 32 hex characters.
+    pub fn from_hex_secret(path: &str, secret: &str) -> Result<Self> {
+        let secret = secret.trim();
+
+        if secret.len() != KEY_STORE_SECRET_LEN * 2 {
+            return Err(ssp::Error::InvalidLength((
+                secret.len(),
+                KEY_STORE_SECRET_LEN * 2,
+            )));
+        }
+
+        let mut buf = [0u8; KEY_STORE_SECRET_LEN];
+
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&secret[i * 2..i * 2 + 2], 16)
+                .map_err(|err| ssp::Error::Io(format!("invalid key store secret: {err}")))?;
+        }
+
+        Ok(Self::new(path, buf))
+    }
+
+    /// Gets the path of the key file.
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap_or_default()
+    }
+
+    fn encode(&self, key: &ssp::FixedKey) -> Result<[u8; RECORD_LEN]> {
+        let mut record = [0u8; RECORD_LEN];
+
+        record[..4].copy_from_slice(RECORD_MAGIC.as_ref());
+        record[4..12].copy_from_slice(key.as_inner().to_le_bytes().as_ref());
+        record[12] = RECORD_VERSION;
+
+        let crc = ssp::crc::crc16(record[..14].as_ref());
+        record[14..].copy_from_slice(crc.to_le_bytes().as_ref());
+
+        ssp::aes::aes_encrypt(&self.secret, &record)
+    }
+
+    fn decode(&self, cipher: &[u8]) -> Result<ssp::FixedKey> {
+        let cipher: [u8; RECORD_LEN] = cipher
+            .try_into()
+            .map_err(|_| ssp::Error::InvalidLength((cipher.len(), RECORD_LEN)))?;
+
+        let record = ssp::aes::aes_decrypt(&self.secret, &cipher)?;
+
+        let crc = u16::from_le_bytes([record[14], record[15]]);
+        let exp_crc = ssp::crc::crc16(record[..14].as_ref());
+
+        if record[..4] != RECORD_MAGIC || record[12] != RECORD_VERSION || crc != exp_crc {
+            return Err(ssp::Error::Io(format!(
+                "invalid key store record in {}, wrong secret or corrupt file",
+                self.path.display()
+            )));
+        }
+
+        let mut key = [0u8; 8];
+        key.copy_from_slice(record[4..12].as_ref());
+
+        Ok(ssp::FixedKey::from_inner(u64::from_le_bytes(key)))
+    }
+}
+
+impl KeyStore for FileKeyStore {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn load(&self) -> Result<Option<ssp::FixedKey>> {
+        match fs::read(&self.path) {
+            Ok(buf) => self.decode(buf.as_ref()).map(Some),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn store(&mut self, key: &ssp::FixedKey) -> Result<()> {
+        let record = self.encode(key)?;
+
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        let tmp_path = self.path.with_extension("tmp");
+
+        let mut opts = fs::OpenOptions::new();
+        opts.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        opts.mode(0o600);
+
+        let mut file = opts.open(&tmp_path)?;
+        file.write_all(record.as_ref())?;
+        file.sync_all()?;
+
+        fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+/// [KeyStore] that keeps the [FixedKey](ssp::FixedKey) in the OS keyring, e.g. the Secret Service
+/// on Linux, or the Keychain on macOS.
+#[cfg(feature = "keyring")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "keyring")))]
+pub struct KeyringStore {
+    entry: ::keyring::Entry,
+}
+
+#[cfg(feature = "keyring")]
+impl KeyringStore {
+    /// Creates a new [KeyringStore] for the keyring entry identified by `service` and `user`.
+    ///
+    /// Use a distinct `user` per device, e.g. the device serial number.
+    pub fn new(service: &str, user: &str) -> Result<Self> {
+        let entry = ::keyring::Entry::new(service, user).map_err(keyring_error)?;
+
+        Ok(Self { entry })
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl KeyStore for KeyringStore {
+    fn name(&self) -> &str {
+        "keyring"
+    }
+
+    fn load(&self) -> Result<Option<ssp::FixedKey>> {
+        match self.entry.get_password() {
+            Ok(key) => u64::from_str_radix(key.trim(), 16)
+                .map(|key| Some(ssp::FixedKey::from_inner(key)))
+                .map_err(|err| ssp::Error::Io(format!("invalid keyring entry: {err}"))),
+            Err(::keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(keyring_error(err)),
+        }
+    }
+
+    fn store(&mut self, key: &ssp::FixedKey) -> Result<()> {
+        self.entry
+            .set_password(format!("{:016x}", key.as_inner()).as_str())
+            .map_err(keyring_error)
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_error(err: ::keyring::Error) -> ssp::Error {
+    ssp::Error::Io(format!("keyring error: {err}"))
+}
+
+/// Environment variable with the path of the PKCS#11 module, e.g. `/usr/lib/libtpm2_pkcs11.so`.
+#[cfg(feature = "pkcs11")]
+pub const PKCS11_ENV_MODULE: &str = "SSP_PKCS11_MODULE";
+/// Environment variable with the label of the PKCS#11 token.
+#[cfg(feature = "pkcs11")]
+pub const PKCS11_ENV_TOKEN: &str = "SSP_PKCS11_TOKEN";
+/// Environment variable with the user PIN of the PKCS#11 token.
+#[cfg(feature = "pkcs11")]
+pub const PKCS11_ENV_PIN: &str = "SSP_PKCS11_PIN";
+/// Environment variable with the label of the key object on the token.
+#[cfg(feature = "pkcs11")]
+pub const PKCS11_ENV_LABEL: &str = "SSP_PKCS11_LABEL";
+/// Default label of the key object on the token.
+#[cfg(feature = "pkcs11")]
+pub const PKCS11_LABEL: &str = "ssp-fixed-key";
+
+/// [KeyStore] that keeps the [FixedKey](ssp::FixedKey) on a PKCS#11 token, e.g. a HSM, smart
+/// card, or a TPM through [tpm2-pkcs11](https://github.com/tpm2-software/tpm2-pkcs11).
+///
+/// The key is stored as a private data object, only readable after logging in to the token, so
+/// it never rests on disk.
+///
+/// # Limitations
+///
+/// The token only protects the key at rest. The eSSP AES key combines the fixed key with the key
+/// negotiated for each session, and the `ssp` library encrypts on the host, so the fixed key is
+/// read into process memory on [load](KeyStore::load). It can not be kept as a non-extractable
+/// secret key object, with the AES operations on the token.
+///
+/// Exactly one object may carry the label. If several objects match, e.g. left by another tool,
+/// [load](KeyStore::load) fails instead of guessing which key the device uses, since PKCS#11
+/// does not order the objects it finds.
+#[cfg(feature = "pkcs11")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "pkcs11")))]
+pub struct Pkcs11KeyStore {
+    pkcs11: cryptoki::context::Pkcs11,
+    slot: cryptoki::slot::Slot,
+    pin: cryptoki::types::AuthPin,
+    label: String,
+}
+
+#[cfg(feature = "pkcs11")]
+impl Pkcs11KeyStore {
+    /// Creates a new [Pkcs11KeyStore] on the token labelled `token`.
+    ///
+    /// # Parameters
+    ///
+    /// - `module`: path of the PKCS#11 module
+    /// - `token`: label of the token
+    /// - `pin`: user PIN of the token
+    /// - `label`: label of the key object on the token
+    pub fn new(module: &str, token: &str, pin: &str, label: &str) -> Result<Self> {
+        use cryptoki::context::{CInitializeArgs, Pkcs11};
+
+        let pkcs11 = Pkcs11::new(module).map_err(pkcs11_error)?;
+        pkcs11
+            .initialize(CInitializeArgs::OsThreads)
+            .map_err(pkcs11_error)?;
+
+        let mut slot = None;
+        for s in pkcs11.get_slots_with_token().map_err(pkcs11_error)? {
+            let info = pkcs11.get_token_info(s).map_err(pkcs11_error)?;
+            if info.label().trim() == token {
+                slot = Some(s);
+                break;
+            }
+        }
+
+        let slot = slot.ok_or(ssp::Error::Io(format!("PKCS#11 token not found: {token}")))?;
+
+        let store = Self {
+            pkcs11,
+            slot,
+            pin: cryptoki::types::AuthPin::new(pin.into()),
+            label: label.into(),
+        };
+
+        // fail early on a wrong PIN, instead of on the first key operation
+        store.session()?;
+
+        Ok(store)
+    }
+
+    /// Creates a new [Pkcs11KeyStore] from the [PKCS11_ENV_MODULE], [PKCS11_ENV_TOKEN],
+    /// [PKCS11_ENV_PIN], and [PKCS11_ENV_LABEL] environment variables.
+    ///
+    /// Returns `Ok(None)` if no module is configured.
+    pub fn from_env() -> Result<Option<Self>> {
+        let module = match std::env::var(PKCS11_ENV_MODULE) {
+            Ok(module) => module,
+            Err(_) => return Ok(None),
+        };
+
+        let var = |name: &str| {
+            std::env::var(name)
+                .map_err(|_| ssp::Error::Io(format!("{PKCS11_ENV_MODULE} is set without {name}")))
+        };
+
+        let token = var(PKCS11_ENV_TOKEN)?;
+        let pin = var(PKCS11_ENV_PIN)?;
+        let label = std::env::var(PKCS11_ENV_LABEL).unwrap_or(PKCS11_LABEL.into());
+
+        Self::new(
+            module.as_str(),
+            token.as_str(),
+            pin.as_str(),
+            label.as_str(),
+        )
+        .map(Some)
+    }
+
+    /// Gets the label of the key object on the token.
+    pub fn label(&self) -> &str {
+        self.label.as_str()
+    }
+
+    fn session(&self) -> Result<cryptoki::session::Session> {
+        let session = self
+            .pkcs11
+            .open_rw_session(self.slot)
+            .map_err(pkcs11_error)?;
+
+        session
+            .login(cryptoki::session::UserType::User, Some(&self.pin))
+            .map_err(pkcs11_error)?;
+
+        Ok(session)
+    }
+
+    fn find_objects(
+        &self,
+        session: &cryptoki::session::Session,
+    ) -> Result<Vec<cryptoki::object::ObjectHandle>> {
+        use cryptoki::object::{Attribute, ObjectClass};
+
+        session
+            .find_objects(&[
+                Attribute::Class(ObjectClass::DATA),
+                Attribute::Label(self.label.as_bytes().to_vec()),
+            ])
+            .map_err(pkcs11_error)
+    }
+}
+
+#[cfg(feature = "pkcs11")]
+impl KeyStore for Pkcs11KeyStore {
+    fn name(&self) -> &str {
+        "pkcs11"
+    }
+
+    fn load(&self) -> Result<Option<ssp::FixedKey>> {
+        use cryptoki::object::{Attribute, AttributeType};
+
+        let session = self.session()?;
+
+        let obj = match self.find_objects(&session)?.as_slice() {
+            [] => return Ok(None),
+            [obj] => *obj,
+            objs => {
+                return Err(ssp::Error::Io(format!(
+                    "{} PKCS#11 objects labelled {}, remove all but the key used by the device",
+                    objs.len(),
+                    self.label
+                )))
+            }
+        };
+
+        let value = session
+            .get_attributes(obj, &[AttributeType::Value])
+            .map_err(pkcs11_error)?
+            .into_iter()
+            .find_map(|attr| match attr {
+                Attribute::Value(val) => Some(val),
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        let key: [u8; 8] = value
+            .as_slice()
+            .try_into()
+            .map_err(|_| ssp::Error::InvalidLength((value.len(), 8)))?;
+
+        Ok(Some(ssp::FixedKey::from_inner(u64::from_le_bytes(key))))
+    }
+
+    fn store(&mut self, key: &ssp::FixedKey) -> Result<()> {
+        use cryptoki::object::{Attribute, ObjectClass};
+
+        let session = self.session()?;
+
+        let old = self.find_objects(&session)?;
+
+        // create the new object before destroying the old, so a failure leaves a key in place
+        let new = session
+            .create_object(&[
+                Attribute::Class(ObjectClass::DATA),
+                Attribute::Token(true),
+                Attribute::Private(true),
+                Attribute::Modifiable(false),
+                Attribute::Label(self.label.as_bytes().to_vec()),
+                Attribute::Application(b"ssp-server".to_vec()),
+                Attribute::Value(key.as_inner().to_le_bytes().to_vec()),
+            ])
+            .map_err(pkcs11_error)?;
+
+        for obj in old {
+            if let Err(err) = session.destroy_object(obj) {
+                // keep a single key object, so `load` does not fail on the next start
+                if let Err(err) = session.destroy_object(new) {
+                    log::error!("Failed to remove the new PKCS#11 key object: {err}");
+                }
+
+                return Err(pkcs11_error(err));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "pkcs11")]
+fn pkcs11_error(err: cryptoki::error::Error) -> ssp::Error {
+    ssp::Error::Io(format!("PKCS#11 error: {err}"))
+}
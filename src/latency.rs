@@ -0,0 +1,231 @@
+//! Round-trip latency histograms of the commands sent to the device.
+//!
+//! Every command/response round trip is recorded in a histogram for its command type, including
+//! the `Poll` commands of the background polling routine. A growing share of slow round trips
+//! points to a degrading cable, or USB adapter, before it shows up as timeouts.
+//!
+//! Histograms count round trips in fixed [LATENCY_BUCKETS_US] buckets, and are exported as
+//! [LatencyHistogram]s with [histograms], or in the Prometheus text format with [to_prometheus].
+//! Failed round trips are counted as errors, and are not included in the buckets.
+
+use std::fmt;
+use std::fmt::Write;
+use std::time;
+
+use parking_lot::Mutex;
+
+/// Upper bounds of the histogram buckets, in microseconds.
+///
+/// Round trips slower than the last bound are counted in an overflow bucket.
+pub const LATENCY_BUCKETS_US: [u64; 12] = [
+    1_000, 2_000, 5_000, 10_000, 20_000, 50_000, 100_000, 200_000, 500_000, 1_000_000, 2_000_000,
+    5_000_000,
+];
+
+static LATENCY: Mutex<Vec<(ssp::MessageType, LatencyHistogram)>> = Mutex::new(Vec::new());
+
+/// Round-trip latency histogram of a single command type.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyHistogram {
+    /// Command type.
+    pub command: String,
+    /// Number of round trips per bucket of [LATENCY_BUCKETS_US], followed by the overflow
+    /// bucket.
+    pub buckets: Vec<u64>,
+    /// Number of completed round trips.
+    pub count: u64,
+    /// Total duration of the completed round trips, in microseconds.
+    pub sum_us: u64,
+    /// Shortest completed round trip, in microseconds.
+    pub min_us: u64,
+    /// Longest completed round trip, in microseconds.
+    pub max_us: u64,
+    /// Number of failed round trips.
+    pub errors: u64,
+}
+
+impl LatencyHistogram {
+    /// Creates a new, empty [LatencyHistogram] for the `command` type.
+    pub fn new(command: ssp::MessageType) -> Self {
+        Self {
+            command: <&str>::from(command).into(),
+            buckets: vec![0; LATENCY_BUCKETS_US.len() + 1],
+            ..Default::default()
+        }
+    }
+
+    /// Records a completed round trip of `duration`.
+    pub fn record(&mut self, duration: time::Duration) {
+        let duration_us = duration.as_micros().min(u64::MAX.into()) as u64;
+        let bucket = LATENCY_BUCKETS_US
+            .iter()
+            .position(|&bound| duration_us <= bound)
+            .unwrap_or(LATENCY_BUCKETS_US.len());
+
+        self.buckets[bucket] += 1;
+        self.min_us = if self.count == 0 {
+            duration_us
+        } else {
+            self.min_us.min(duration_us)
+        };
+        self.max_us = self.max_us.max(duration_us);
+        self.count += 1;
+        self.sum_us = self.sum_us.saturating_add(duration_us);
+    }
+
+    /// Records a failed round trip.
+    pub fn record_error(&mut self) {
+        self.errors += 1;
+    }
+
+    /// Gets the mean duration of the completed round trips, in microseconds.
+    pub fn mean_us(&self) -> u64 {
+        self.sum_us.checked_div(self.count).unwrap_or(0)
+    }
+
+    /// Gets the upper bound of the bucket holding the `quantile` of the completed round trips,
+    /// in microseconds, e.g. `0.99` for the 99th percentile.
+    ///
+    /// Returns the longest round trip for the overflow bucket, and `None` without round trips.
+    pub fn quantile_us(&self, quantile: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((self.count as f64) * quantile.clamp(0.0, 1.0))
+            .ceil()
+            .max(1.0) as u64;
+        let mut seen = 0;
+
+        for (i, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(LATENCY_BUCKETS_US.get(i).copied().unwrap_or(self.max_us));
+            }
+        }
+
+        Some(self.max_us)
+    }
+}
+
+impl fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let quantile = |q| self.quantile_us(q).unwrap_or_default();
+
+        write!(
+            f,
+            "{}: count: {}, errors: {}, mean: {}us, p50: <={}us, p99: <={}us, max: {}us",
+            self.command,
+            self.count,
+            self.errors,
+            self.mean_us(),
+            quantile(0.5),
+            quantile(0.99),
+            self.max_us,
+        )
+    }
+}
+
+/// Records the round trip of a `command`, lasting `duration`.
+///
+/// Failed round trips, with `ok` set to `false`, are counted as errors.
+pub fn record(command: ssp::MessageType, duration: time::Duration, ok: bool) {
+    let mut latency = LATENCY.lock();
+
+    let index = match latency.iter().position(|(c, _)| *c == command) {
+        Some(index) => index,
+        None => {
+            latency.push((command, LatencyHistogram::new(command)));
+            latency.len() - 1
+        }
+    };
+
+    let histogram = &mut latency[index].1;
+    if ok {
+        histogram.record(duration);
+    } else {
+        histogram.record_error();
+    }
+}
+
+/// Gets the histogram of the `command` type, `None` if it was never sent.
+pub fn histogram(command: ssp::MessageType) -> Option<LatencyHistogram> {
+    LATENCY
+        .lock()
+        .iter()
+        .find(|(c, _)| *c == command)
+        .map(|(_, h)| h.clone())
+}
+
+/// Gets the histograms of all command types sent to the device, by command name.
+pub fn histograms() -> Vec<LatencyHistogram> {
+    let mut histograms: Vec<LatencyHistogram> =
+        LATENCY.lock().iter().map(|(_, h)| h.clone()).collect();
+    histograms.sort_by(|a, b| a.command.cmp(&b.command));
+
+    histograms
+}
+
+/// Clears all histograms.
+pub fn reset() {
+    LATENCY.lock().clear();
+}
+
+/// Formats the `histograms` in the Prometheus text exposition format.
+///
+/// Exports the `ssp_round_trip_seconds` histogram, and the `ssp_round_trip_errors_total`
+/// counter, labelled by command.
+pub fn to_prometheus(histograms: &[LatencyHistogram]) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(
+        out,
+        "# HELP ssp_round_trip_seconds Round-trip latency of commands sent to the device."
+    );
+    let _ = writeln!(out, "# TYPE ssp_round_trip_seconds histogram");
+
+    for histogram in histograms.iter() {
+        let command = histogram.command.as_str();
+        let mut cumulative = 0;
+
+        for (bound, count) in LATENCY_BUCKETS_US.iter().zip(histogram.buckets.iter()) {
+            cumulative += count;
+            let le = *bound as f64 / 1_000_000.0;
+            let _ = writeln!(
+                out,
+                "ssp_round_trip_seconds_bucket{{command=\"{command}\",le=\"{le}\"}} {cumulative}"
+            );
+        }
+
+        let (count, sum) = (histogram.count, histogram.sum_us as f64 / 1_000_000.0);
+        let _ = writeln!(
+            out,
+            "ssp_round_trip_seconds_bucket{{command=\"{command}\",le=\"+Inf\"}} {count}"
+        );
+        let _ = writeln!(
+            out,
+            "ssp_round_trip_seconds_sum{{command=\"{command}\"}} {sum}"
+        );
+        let _ = writeln!(
+            out,
+            "ssp_round_trip_seconds_count{{command=\"{command}\"}} {count}"
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP ssp_round_trip_errors_total Failed round trips of commands sent to the device."
+    );
+    let _ = writeln!(out, "# TYPE ssp_round_trip_errors_total counter");
+
+    for histogram in histograms.iter() {
+        let _ = writeln!(
+            out,
+            "ssp_round_trip_errors_total{{command=\"{}\"}} {}",
+            histogram.command, histogram.errors
+        );
+    }
+
+    out
+}
@@ -0,0 +1,301 @@
+//! Plaintext-leak checks for enforce-encryption mode.
+//!
+//! A host requiring encryption, see
+//! [with_require_encryption](crate::DeviceHandle::with_require_encryption), must never put a
+//! value-relevant command on the wire outside of an eSSP packet. A [LeakCheckTransport] wraps the
+//! transport of a [DeviceHandle](crate::DeviceHandle), inspects every written frame, and records
+//! each plaintext frame of a guarded command as a [Leak]. Leaked frames are not forwarded, so a
+//! leak never reaches the device, and the command fails on the host.
+//!
+//! The shared [LeakReport] asserts the absence of leaks in this crate's tests, and in downstream
+//! integration tests:
+//!
+//! ```rust
+//! # #[cfg(feature = "emulator")]
+//! # fn main() -> ssp::Result<()> {
+//! use std::sync::Arc;
+//!
+//! use parking_lot::Mutex;
+//! use ssp_server::emulator::{Emulator, EmulatorTransport};
+//! use ssp_server::leak::LeakCheckTransport;
+//!
+//! let emulator = Arc::new(Mutex::new(Emulator::new()));
+//! let transport = LeakCheckTransport::new(EmulatorTransport::new(emulator));
+//! let report = transport.report().clone();
+//!
+//! let mut handle =
+//!     ssp_server::DeviceHandle::from_transport(transport)?.with_require_encryption(true);
+//!
+//! handle.negotiate_keys()?;
+//! handle.enable_device(ssp::ProtocolVersion::Six)?;
+//!
+//! report.assert_no_leaks();
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "emulator"))]
+//! # fn main() {}
+//! ```
+
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::{fmt, time};
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::framing;
+use crate::transport::Transport;
+
+/// Value-relevant commands guarded by default: accepting, and paying out cash, and changing the
+/// accepted channels.
+pub const VALUE_COMMANDS: [ssp::MessageType; 6] = [
+    ssp::MessageType::Enable,
+    ssp::MessageType::EnablePayout,
+    ssp::MessageType::SetInhibits,
+    ssp::MessageType::PayoutByDenomination,
+    ssp::MessageType::Empty,
+    ssp::MessageType::SmartEmpty,
+];
+
+/// Guarded command written to the wire in plaintext.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Leak {
+    /// Type of the leaked command.
+    pub command: ssp::MessageType,
+    /// Sequence ID of the leaked frame.
+    pub sequence_id: u8,
+    /// Index of the leaked frame among the frames written through the transport.
+    pub frame: u64,
+}
+
+impl fmt::Display for Leak {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "plaintext {} command in frame {} (sequence ID {:#04x})",
+            <&str>::from(self.command),
+            self.frame,
+            self.sequence_id
+        )
+    }
+}
+
+#[derive(Debug)]
+struct LeakState {
+    armed: bool,
+    frames: u64,
+    encrypted: u64,
+    leaks: Vec<Leak>,
+}
+
+impl Default for LeakState {
+    fn default() -> Self {
+        Self {
+            armed: true,
+            frames: 0,
+            encrypted: 0,
+            leaks: Vec::new(),
+        }
+    }
+}
+
+/// Shared record of the frames written through a [LeakCheckTransport].
+///
+/// Stays readable after the transport is moved into a [DeviceHandle](crate::DeviceHandle).
+#[derive(Clone, Debug, Default)]
+pub struct LeakReport(Arc<Mutex<LeakState>>);
+
+impl LeakReport {
+    /// Creates a new, empty [LeakReport], with the check armed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets whether plaintext frames of guarded commands are recorded, and blocked.
+    pub fn armed(&self) -> bool {
+        self.0.lock().armed
+    }
+
+    /// Arms, or disarms the check, e.g. to let a setup phase run before encryption is enforced.
+    pub fn set_armed(&self, armed: bool) {
+        self.0.lock().armed = armed;
+    }
+
+    /// Gets the number of frames written through the transport.
+    pub fn frames(&self) -> u64 {
+        self.0.lock().frames
+    }
+
+    /// Gets the number of eSSP frames written through the transport.
+    pub fn encrypted_frames(&self) -> u64 {
+        self.0.lock().encrypted
+    }
+
+    /// Gets a copy of the leaks, in order.
+    pub fn leaks(&self) -> Vec<Leak> {
+        self.0.lock().leaks.clone()
+    }
+
+    /// Gets whether no guarded command was written in plaintext.
+    pub fn is_clean(&self) -> bool {
+        self.0.lock().leaks.is_empty()
+    }
+
+    /// Returns `Err(_)` describing the first leak, if any.
+    pub fn check(&self) -> Result<()> {
+        match self.0.lock().leaks.first() {
+            Some(leak) => Err(ssp::Error::Io(leak.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Panics with the list of leaks, if any.
+    #[track_caller]
+    pub fn assert_no_leaks(&self) {
+        let leaks = self.leaks();
+
+        if !leaks.is_empty() {
+            let leaks = leaks
+                .iter()
+                .map(Leak::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            panic!("guarded commands leaked on the wire: {leaks}");
+        }
+    }
+
+    /// Clears the counters, and leaks, keeping the check armed, or disarmed.
+    pub fn clear(&self) {
+        let mut state = self.0.lock();
+
+        state.frames = 0;
+        state.encrypted = 0;
+        state.leaks.clear();
+    }
+}
+
+/// Transport decorator recording, and blocking plaintext frames of guarded commands.
+///
+/// Written bytes are inspected one frame per write, the way a
+/// [DeviceHandle](crate::DeviceHandle) writes them. A blocked write fails with
+/// [PermissionDenied](io::ErrorKind::PermissionDenied).
+pub struct LeakCheckTransport<T: Transport> {
+    inner: T,
+    commands: Vec<ssp::MessageType>,
+    report: LeakReport,
+}
+
+impl<T: Transport> LeakCheckTransport<T> {
+    /// Creates a new [LeakCheckTransport] guarding the [VALUE_COMMANDS] written to `inner`.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            commands: VALUE_COMMANDS.into(),
+            report: LeakReport::new(),
+        }
+    }
+
+    /// Builder function that adds a guarded `command`.
+    pub fn with_command(mut self, command: ssp::MessageType) -> Self {
+        if !self.commands.contains(&command) {
+            self.commands.push(command);
+        }
+        self
+    }
+
+    /// Builder function that stops guarding a `command`.
+    pub fn without_command(mut self, command: ssp::MessageType) -> Self {
+        self.commands.retain(|&c| c != command);
+        self
+    }
+
+    /// Gets the guarded commands.
+    pub fn commands(&self) -> &[ssp::MessageType] {
+        &self.commands
+    }
+
+    /// Gets the [LeakReport] of the written frames.
+    pub fn report(&self) -> &LeakReport {
+        &self.report
+    }
+
+    /// Gets a reference to the wrapped transport.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Stops checking, and returns the wrapped transport.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    // Records the frame, and returns the leak, if the frame is a plaintext guarded command.
+    fn inspect(&self, stuffed: &[u8]) -> Option<Leak> {
+        use ssp::message::index;
+
+        let frame = framing::unstuff(stuffed).ok()?;
+        let command = ssp::MessageType::from(*frame.get(index::DATA)?);
+
+        let mut state = self.report.0.lock();
+        let n = state.frames;
+        state.frames += 1;
+
+        if command == ssp::MessageType::Encrypted {
+            state.encrypted += 1;
+            return None;
+        }
+
+        if !state.armed || !self.commands.contains(&command) {
+            return None;
+        }
+
+        let leak = Leak {
+            command,
+            sequence_id: frame[index::SEQ_ID],
+            frame: n,
+        };
+
+        log::error!("Blocked a leak: {leak}");
+        state.leaks.push(leak);
+
+        Some(leak)
+    }
+}
+
+impl<T: Transport> Read for LeakCheckTransport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Transport> Write for LeakCheckTransport<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(leak) = self.inspect(buf) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                leak.to_string(),
+            ));
+        }
+
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Transport> Transport for LeakCheckTransport<T> {
+    fn clear(&mut self) -> Result<()> {
+        self.inner.clear()
+    }
+
+    fn set_read_timeout(&mut self, timeout: time::Duration) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        self.inner.discard_input()
+    }
+}
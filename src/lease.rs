@@ -0,0 +1,340 @@
+//! Exclusive session leases for arbitrating between multiple frontend clients.
+//!
+//! A client claims a lease to become the only client allowed to send state-changing commands,
+//! e.g. enabling the device, or dispensing notes. Other clients can still query the device status,
+//! and receive device events.
+//!
+//! Leases expire after a timeout, unless renewed by claiming again, so a crashed client does not
+//! lock out the others. Without an active lease, all clients can send commands.
+//!
+//! Every lease carries a secret [LeaseToken], for connectionless frontends, e.g. HTTP, to prove
+//! they hold the lease. Tokens are unrelated to the internal [ClientId] of the holder.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+
+use crate::entropy::{EntropySource, SystemEntropy};
+use crate::error::{Error, Result};
+
+/// Default lease timeout (milliseconds).
+pub const LEASE_TTL_MS: u64 = 30_000;
+/// Maximum lease timeout (milliseconds).
+pub const LEASE_MAX_TTL_MS: u64 = 600_000;
+/// JSON-RPC method for claiming, or renewing a lease.
+pub const LEASE_CLAIM_METHOD: &str = "lease_claim";
+/// JSON-RPC method for releasing a lease.
+pub const LEASE_RELEASE_METHOD: &str = "lease_release";
+/// Prefix of the message of [Busy](Error::Busy) errors returned when a command is denied by
+/// another client's lease.
+pub const LEASE_DENIED: &str = "device is leased by another client";
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Length of a [LeaseToken] (bytes).
+pub const LEASE_TOKEN_LEN: usize = 16;
+
+/// Identifies a frontend client for lease arbitration.
+///
+/// Connection-based frontends assign one identifier per connection. Connectionless frontends,
+/// e.g. HTTP, get a new identifier for every claimed lease, and send its [LeaseToken] with each
+/// request. Identifiers are never accepted from clients.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClientId(u64);
+
+impl ClientId {
+    /// Creates a new unique [ClientId].
+    pub fn next() -> Self {
+        Self(NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Creates a [ClientId] from a raw value.
+    pub const fn from_u64(val: u64) -> Self {
+        Self(val)
+    }
+
+    /// Gets the raw value of the [ClientId].
+    pub const fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Secret token proving a client holds a [Lease].
+///
+/// Tokens are 128-bit values from system entropy, so clients can not guess the token of another
+/// client's lease. Tokens are formatted as lowercase hex, and never printed in debug output.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LeaseToken([u8; LEASE_TOKEN_LEN]);
+
+impl LeaseToken {
+    /// Generates a new random [LeaseToken].
+    pub fn generate() -> Self {
+        let seed = SystemEntropy::new().seed();
+
+        let mut token = [0u8; LEASE_TOKEN_LEN];
+        token.copy_from_slice(&seed[..LEASE_TOKEN_LEN]);
+
+        Self(token)
+    }
+
+    /// Creates a [LeaseToken] from raw bytes.
+    pub const fn from_bytes(bytes: [u8; LEASE_TOKEN_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    /// Gets the raw bytes of the [LeaseToken].
+    pub const fn as_bytes(&self) -> &[u8; LEASE_TOKEN_LEN] {
+        &self.0
+    }
+
+    // Compares the tokens in constant time.
+    fn matches(&self, other: &Self) -> bool {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+    }
+}
+
+impl fmt::Debug for LeaseToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LeaseToken(..)")
+    }
+}
+
+impl fmt::Display for LeaseToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in self.0.iter() {
+            write!(f, "{b:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for LeaseToken {
+    type Err = ssp::Error;
+
+    fn from_str(val: &str) -> ssp::Result<Self> {
+        let val = val.trim();
+
+        if val.len() != LEASE_TOKEN_LEN * 2 || !val.is_ascii() {
+            return Err(ssp::Error::Io(format!(
+                "invalid lease token, expected {} hex digits",
+                LEASE_TOKEN_LEN * 2
+            )));
+        }
+
+        let mut token = [0u8; LEASE_TOKEN_LEN];
+        for (i, b) in token.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&val[i * 2..i * 2 + 2], 16)
+                .map_err(|err| ssp::Error::Io(format!("invalid lease token: {err}")))?;
+        }
+
+        Ok(Self(token))
+    }
+}
+
+/// An active lease held by a client.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lease {
+    holder: ClientId,
+    token: LeaseToken,
+    expires: time::Instant,
+}
+
+impl Lease {
+    /// Gets the [ClientId] of the lease holder.
+    pub const fn holder(&self) -> ClientId {
+        self.holder
+    }
+
+    /// Gets the secret [LeaseToken] of the lease.
+    pub const fn token(&self) -> LeaseToken {
+        self.token
+    }
+
+    /// Gets the remaining time until the lease expires.
+    pub fn remaining(&self) -> time::Duration {
+        self.expires.saturating_duration_since(time::Instant::now())
+    }
+
+    /// Gets whether the lease has expired.
+    pub fn is_expired(&self) -> bool {
+        time::Instant::now() >= self.expires
+    }
+}
+
+/// Reply to a lease claim, as sent to frontend clients.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LeaseReply {
+    /// Secret lease token, see [LeaseToken].
+    pub token: String,
+    /// Remaining time until the lease expires (milliseconds).
+    pub expires_ms: u64,
+}
+
+impl From<&Lease> for LeaseReply {
+    fn from(val: &Lease) -> Self {
+        Self {
+            token: val.token.to_string(),
+            expires_ms: val.remaining().as_millis() as u64,
+        }
+    }
+}
+
+/// Arbitrates the exclusive lease between frontend clients.
+///
+/// Cloned managers share the same lease state.
+#[derive(Clone, Debug)]
+pub struct LeaseManager {
+    lease: Arc<Mutex<Option<Lease>>>,
+    default_ttl: time::Duration,
+}
+
+impl LeaseManager {
+    /// Creates a new [LeaseManager] with the default lease timeout.
+    pub fn new() -> Self {
+        Self {
+            lease: Arc::new(Mutex::new(None)),
+            default_ttl: time::Duration::from_millis(LEASE_TTL_MS),
+        }
+    }
+
+    /// Gets the default lease timeout.
+    pub const fn default_ttl(&self) -> time::Duration {
+        self.default_ttl
+    }
+
+    /// Builder function that sets the default lease timeout.
+    pub fn with_default_ttl(mut self, ttl: time::Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Claims the lease for `client`, or renews it if `client` already holds the lease.
+    ///
+    /// The lease expires after `ttl`, or the default timeout if `None`. Timeouts are capped at
+    /// [LEASE_MAX_TTL_MS].
+    ///
+    /// Returns `Err(_)` if another client holds an unexpired lease.
+    pub fn claim(&self, client: ClientId, ttl: Option<time::Duration>) -> Result<Lease> {
+        let ttl = ttl
+            .unwrap_or(self.default_ttl)
+            .min(time::Duration::from_millis(LEASE_MAX_TTL_MS));
+
+        let mut lease = self.lease.lock();
+
+        match lease.as_ref() {
+            Some(held) if held.holder != client && !held.is_expired() => Err(denied(held)),
+            held => {
+                // renewals keep the token, new leases get a fresh one
+                let token = match held {
+                    Some(held) if held.holder == client && !held.is_expired() => held.token,
+                    _ => LeaseToken::generate(),
+                };
+
+                let claimed = Lease {
+                    holder: client,
+                    token,
+                    expires: time::Instant::now() + ttl,
+                };
+
+                log::debug!(
+                    "Client {} claimed lease for {}ms",
+                    client.as_u64(),
+                    ttl.as_millis()
+                );
+
+                *lease = Some(claimed);
+
+                Ok(claimed)
+            }
+        }
+    }
+
+    /// Releases the lease held by `client`.
+    ///
+    /// Releasing without an active lease succeeds. Returns `Err(_)` if another client holds an
+    /// unexpired lease.
+    pub fn release(&self, client: ClientId) -> Result<()> {
+        let mut lease = self.lease.lock();
+
+        match lease.as_ref() {
+            Some(held) if held.holder != client && !held.is_expired() => Err(denied(held)),
+            _ => {
+                if lease.take().is_some() {
+                    log::debug!("Client {} released lease", client.as_u64());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Releases the lease if held by `client`, e.g. when the client disconnects.
+    pub fn drop_client(&self, client: ClientId) {
+        let mut lease = self.lease.lock();
+
+        if lease.map(|held| held.holder == client).unwrap_or(false) {
+            log::debug!("Released lease of disconnected client {}", client.as_u64());
+            lease.take();
+        }
+    }
+
+    /// Gets the active [Lease], if any.
+    pub fn current(&self) -> Option<Lease> {
+        self.lease.lock().filter(|held| !held.is_expired())
+    }
+
+    /// Gets the holder of the active lease, if the `token` matches its [LeaseToken].
+    pub fn holder_of(&self, token: &LeaseToken) -> Option<ClientId> {
+        self.current()
+            .filter(|held| held.token.matches(token))
+            .map(|held| held.holder)
+    }
+
+    /// Checks whether `client` may send state-changing commands.
+    ///
+    /// Anonymous clients (`None`) are only allowed without an active lease.
+    pub fn authorize(&self, client: Option<ClientId>) -> Result<()> {
+        match self.current() {
+            Some(held) if Some(held.holder) != client => Err(denied(&held)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks whether `client` may send a command with the given [Method](ssp::Method).
+    ///
+    /// Status queries are allowed for all clients.
+    pub fn authorize_method(&self, client: Option<ClientId>, method: ssp::Method) -> Result<()> {
+        if is_state_changing(method) {
+            self.authorize(client)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for LeaseManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Gets whether a command [Method](ssp::Method) changes the device state.
+pub fn is_state_changing(method: ssp::Method) -> bool {
+    !matches!(method, ssp::Method::Status)
+}
+
+fn denied(held: &Lease) -> Error {
+    Error::busy(ssp::Error::Io(format!(
+        "{LEASE_DENIED}, expires in {}ms",
+        held.remaining().as_millis()
+    )))
+}
@@ -1,12 +1,94 @@
 #![cfg_attr(doc_cfg, feature(doc_cfg))]
 
+pub mod audit;
+pub mod auth;
+pub mod bridge;
+pub mod broadcast;
+pub mod capture;
+pub mod cash_levels;
+pub mod clock;
+#[cfg(any(feature = "jsonrpc", feature = "http"))]
+pub mod codec;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod conformance;
 pub mod device_handle;
+#[cfg(feature = "emulator")]
+pub mod emulator;
+pub mod encryption;
+pub mod entropy;
+pub mod error;
+pub mod essp;
+pub mod event_log;
+pub mod export;
+#[cfg(feature = "mock")]
+pub mod fault;
+pub mod frame_log;
+pub mod framing;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod journal;
+#[cfg(feature = "json-log")]
+pub mod json_log;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod key_negotiation;
+pub mod key_rotation;
+pub mod key_store;
+pub mod latency;
+#[cfg(feature = "mock")]
+pub mod leak;
+pub mod lease;
 #[macro_use]
 mod macros;
 #[cfg(feature = "mock")]
 pub mod mock;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod payout_intent;
+pub mod poll_timer;
+pub mod reconcile;
+pub mod redact;
+#[cfg(feature = "redis-streams")]
+pub mod redis_stream;
+pub mod registry;
+pub mod reject_code;
+pub mod reject_history;
+pub mod retry;
+#[cfg(any(feature = "mock", feature = "emulator"))]
+mod rng;
+#[cfg(feature = "jsonrpc")]
+pub mod schema;
+#[cfg(feature = "mock")]
+pub mod scripted;
 mod server;
+pub mod sink;
+pub mod snapshot;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+#[cfg(feature = "jsonrpc")]
+pub mod stdio;
+pub mod storage;
+pub mod systemd;
+pub mod telemetry;
+pub mod timeouts;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod transport;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+#[cfg(feature = "zeromq")]
+pub mod zeromq;
 
+pub use error::{Error, ErrorContext};
 pub use server::*;
 
-pub use device_handle::{DeviceHandle, PollMode, PushEventReceiver};
+pub use device_handle::{
+    CommandHandle, CommandPriority, DeviceHandle, PollMode, PushEventReceiver, SerialPortGuard,
+    WorkerKind, WorkerStatus,
+};
@@ -1,10 +1,12 @@
-/// Convenience macro to get a Option<&ssp::AesKey> from the [DeviceHandle](crate::DeviceHandle).
+/// Convenience macro to get a copy of the Option<ssp::AesKey> from the
+/// [DeviceHandle](crate::DeviceHandle).
 ///
-/// If the encryption key is unset, returns `None`.
+/// The key is copied, so the lock is not held during serial I/O. If the encryption key is unset,
+/// returns `None`.
 #[macro_export]
 macro_rules! encryption_key {
     ($handle:tt) => {{
-        $handle.encryption_key()?.as_ref()
+        $handle.encryption_key_copy()?
     }};
 }
 
@@ -22,3 +24,18 @@ macro_rules! continue_on_err {
         }
     }};
 }
+
+/// Breaks out of the loop on an `Err(_)` result.
+#[macro_export]
+macro_rules! break_on_err {
+    ($res:expr, $err:expr) => {{
+        match $res {
+            Ok(res) => res,
+            Err(err) => {
+                let err_msg = $err;
+                log::warn!("{err_msg}: {err}");
+                break;
+            }
+        }
+    }};
+}
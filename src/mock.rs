@@ -8,6 +8,7 @@ use serialport::{SerialPort, TTYPort};
 use ssp::{ResponseOps, Result};
 
 use super::device_handle::{BAUD_RATE, SERIAL_TIMEOUT_MS};
+use super::framing;
 
 /// Represents a mock SSP device used for integration tests.
 ///
@@ -178,7 +179,8 @@ impl MockDevice {
     pub fn send_default_response(&mut self) -> Result<()> {
         if let Some(mut res) = Self::default_response(self.msg_type) {
             res.set_response_status(self.response_status);
-            self.serial_port.write_all(res.as_bytes())?;
+            self.serial_port
+                .write_all(framing::stuff(res.as_bytes())?.as_ref())?;
         }
         Ok(())
     }
@@ -222,7 +224,8 @@ impl MockDevice {
                     res.set_data_len(Self::response_data_len(msg_type) as u8);
                     res.set_sequence_id(seq_id);
                     log::debug!("Writing response: {:x?}", res.as_bytes());
-                    self.serial_port.write_all(res.as_bytes())?;
+                    self.serial_port
+                        .write_all(framing::stuff(res.as_bytes())?.as_ref())?;
                     log::trace!("Successfully wrote response");
                     self.serial_port.flush()?;
                 }
@@ -0,0 +1,202 @@
+//! MQTT [EventSink] for publishing device events to a message broker.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use rumqttc::{Client, MqttOptions, QoS};
+use ssp::Result;
+
+use crate::sink::EventSink;
+
+/// Default MQTT broker port.
+pub const MQTT_PORT: u16 = 1883;
+/// Default prefix for published topics.
+pub const MQTT_TOPIC_PREFIX: &str = "ssp";
+/// Default client identifier.
+pub const MQTT_CLIENT_ID: &str = "ssp-server";
+/// Maximum number of messages queued for the broker.
+pub const MQTT_QUEUE_CAPACITY: usize = 64;
+
+/// Configuration for a [MqttSink].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MqttConfig {
+    host: String,
+    port: u16,
+    client_id: String,
+    topic_prefix: String,
+    qos: QoS,
+    keep_alive: time::Duration,
+}
+
+impl MqttConfig {
+    /// Creates a new [MqttConfig] for the broker at `host`, using default settings.
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.into(),
+            port: MQTT_PORT,
+            client_id: MQTT_CLIENT_ID.into(),
+            topic_prefix: MQTT_TOPIC_PREFIX.into(),
+            qos: QoS::AtLeastOnce,
+            keep_alive: time::Duration::from_secs(5),
+        }
+    }
+
+    /// Gets the broker host.
+    pub fn host(&self) -> &str {
+        self.host.as_str()
+    }
+
+    /// Gets the broker port.
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Builder function that sets the broker port.
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Gets the MQTT client identifier.
+    pub fn client_id(&self) -> &str {
+        self.client_id.as_str()
+    }
+
+    /// Builder function that sets the MQTT client identifier.
+    pub fn with_client_id(mut self, client_id: &str) -> Self {
+        self.client_id = client_id.into();
+        self
+    }
+
+    /// Gets the topic prefix.
+    ///
+    /// Events are published to `<prefix>/events/<method>`, status snapshots to `<prefix>/status`.
+    pub fn topic_prefix(&self) -> &str {
+        self.topic_prefix.as_str()
+    }
+
+    /// Builder function that sets the topic prefix.
+    pub fn with_topic_prefix(mut self, prefix: &str) -> Self {
+        self.topic_prefix = prefix.trim_end_matches('/').into();
+        self
+    }
+
+    /// Gets the publish [QoS].
+    pub const fn qos(&self) -> QoS {
+        self.qos
+    }
+
+    /// Builder function that sets the publish [QoS].
+    pub fn with_qos(mut self, qos: QoS) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    /// Builder function that sets the connection keep-alive interval.
+    pub fn with_keep_alive(mut self, keep_alive: time::Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Gets the topic for an [Event](ssp::Event).
+    pub fn event_topic(&self, event: &ssp::Event) -> String {
+        format!("{}/events/{}", self.topic_prefix, event.method().to_str())
+    }
+
+    /// Gets the topic for status snapshots.
+    pub fn status_topic(&self) -> String {
+        format!("{}/status", self.topic_prefix)
+    }
+
+    /// Gets the payload for an [Event](ssp::Event), the JSON encoded event.
+    pub fn event_payload(&self, event: &ssp::Event) -> Result<Vec<u8>> {
+        serde_json::to_vec(event)
+            .map_err(|err| ssp::Error::Io(format!("MQTT event encoding error: {err}")))
+    }
+}
+
+/// [EventSink] that publishes JSON encoded events and status snapshots to an MQTT broker.
+///
+/// The broker connection is driven by a background thread, which reconnects automatically.
+/// Messages are queued while the broker is unreachable, up to [MQTT_QUEUE_CAPACITY], then
+/// publishing fails instead of blocking the caller.
+pub struct MqttSink {
+    config: MqttConfig,
+    client: Client,
+    closed: Arc<AtomicBool>,
+}
+
+impl MqttSink {
+    /// Creates a new [MqttSink], and starts connecting to the broker.
+    pub fn new(config: MqttConfig) -> Self {
+        let mut opts = MqttOptions::new(config.client_id(), config.host(), config.port());
+        opts.set_keep_alive(config.keep_alive);
+
+        let (client, mut connection) = Client::new(opts, MQTT_QUEUE_CAPACITY);
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let conn_closed = Arc::clone(&closed);
+
+        thread::spawn(move || {
+            for notification in connection.iter() {
+                match notification {
+                    Ok(notification) => log::trace!("MQTT notification: {notification:?}"),
+                    Err(err) => {
+                        if conn_closed.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        log::warn!("MQTT connection error: {err}, reconnecting");
+                        thread::sleep(time::Duration::from_secs(1));
+                    }
+                }
+            }
+        });
+
+        Self {
+            config,
+            client,
+            closed,
+        }
+    }
+
+    /// Gets a reference to the [MqttConfig].
+    pub fn config(&self) -> &MqttConfig {
+        &self.config
+    }
+
+    fn publish(&mut self, topic: String, payload: Vec<u8>) -> Result<()> {
+        self.client
+            .try_publish(topic, self.config.qos(), false, payload)
+            .map_err(|err| ssp::Error::Io(format!("MQTT publish error: {err}")))
+    }
+}
+
+impl EventSink for MqttSink {
+    fn name(&self) -> &str {
+        "MQTT"
+    }
+
+    fn publish_event(&mut self, event: &ssp::Event) -> Result<()> {
+        let topic = self.config.event_topic(event);
+        let payload = self.config.event_payload(event)?;
+
+        self.publish(topic, payload)
+    }
+
+    fn publish_status(&mut self, status: &ssp::DeviceStatus) -> Result<()> {
+        let topic = self.config.status_topic();
+        let payload = serde_json::to_vec(status)
+            .map_err(|err| ssp::Error::Io(format!("MQTT status encoding error: {err}")))?;
+
+        self.publish(topic, payload)
+    }
+}
+
+impl Drop for MqttSink {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+        // a full queue must not block the drop
+        let _ = self.client.try_disconnect();
+    }
+}
@@ -0,0 +1,164 @@
+//! NATS [EventSink] for publishing device events to an edge messaging fleet.
+//!
+//! Subjects are organized per device:
+//!
+//! - `<prefix>.<device>.events.<method>`: JSON encoded [Event](ssp::Event)s
+//! - `<prefix>.<device>.status`: JSON encoded [DeviceStatus](ssp::DeviceStatus) snapshots
+//!
+//! Subscribers can use wildcards, e.g. `ssp.*.events.>` for events from all devices.
+
+use ssp::Result;
+
+use crate::sink::EventSink;
+use crate::DeviceHandle;
+
+/// Default NATS server URL.
+pub const NATS_URL: &str = "nats://127.0.0.1:4222";
+/// Default subject prefix.
+pub const NATS_SUBJECT_PREFIX: &str = "ssp";
+
+/// Configuration for a [NatsSink].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NatsConfig {
+    url: String,
+    subject_prefix: String,
+    device: String,
+}
+
+impl NatsConfig {
+    /// Creates a new [NatsConfig] for the device identified by `device`, e.g. its serial number.
+    pub fn new(device: &str) -> Self {
+        Self {
+            url: NATS_URL.into(),
+            subject_prefix: NATS_SUBJECT_PREFIX.into(),
+            device: subject_token(device),
+        }
+    }
+
+    /// Creates a new [NatsConfig], using the serial number reported by the device.
+    pub fn from_device(handle: &DeviceHandle) -> Result<Self> {
+        let serial = handle.serial_number()?.serial_number();
+
+        Ok(Self::new(format!("{serial}").as_str()))
+    }
+
+    /// Gets the NATS server URL.
+    pub fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    /// Builder function that sets the NATS server URL.
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Gets the subject prefix.
+    pub fn subject_prefix(&self) -> &str {
+        self.subject_prefix.as_str()
+    }
+
+    /// Builder function that sets the subject prefix.
+    pub fn with_subject_prefix(mut self, prefix: &str) -> Self {
+        self.subject_prefix = prefix.trim_end_matches('.').into();
+        self
+    }
+
+    /// Gets the device subject token.
+    pub fn device(&self) -> &str {
+        self.device.as_str()
+    }
+
+    /// Gets the subject for an [Event](ssp::Event).
+    pub fn event_subject(&self, event: &ssp::Event) -> String {
+        format!(
+            "{}.{}.events.{}",
+            self.subject_prefix,
+            self.device,
+            event.method().to_str()
+        )
+    }
+
+    /// Gets the subject for status snapshots.
+    pub fn status_subject(&self) -> String {
+        format!("{}.{}.status", self.subject_prefix, self.device)
+    }
+
+    /// Gets the payload for an [Event](ssp::Event), the JSON encoded event.
+    pub fn event_payload(&self, event: &ssp::Event) -> Result<Vec<u8>> {
+        serde_json::to_vec(event)
+            .map_err(|err| ssp::Error::Io(format!("NATS event encoding error: {err}")))
+    }
+}
+
+// Replaces characters with special meaning in NATS subjects.
+fn subject_token(token: &str) -> String {
+    token
+        .chars()
+        .map(|c| match c {
+            '.' | '*' | '>' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// [EventSink] that publishes JSON encoded events and status snapshots to a NATS server.
+///
+/// The client reconnects automatically, buffering messages while disconnected.
+pub struct NatsSink {
+    config: NatsConfig,
+    conn: ::nats::Connection,
+}
+
+impl NatsSink {
+    /// Creates a new [NatsSink], and connects to the NATS server.
+    pub fn new(config: NatsConfig) -> Result<Self> {
+        let conn = ::nats::Options::new()
+            .with_name("ssp-server")
+            .max_reconnects(None)
+            .connect(config.url())?;
+
+        log::info!("Connected to NATS at {}", config.url());
+
+        Ok(Self { config, conn })
+    }
+
+    /// Gets a reference to the [NatsConfig].
+    pub fn config(&self) -> &NatsConfig {
+        &self.config
+    }
+}
+
+impl EventSink for NatsSink {
+    fn name(&self) -> &str {
+        "NATS"
+    }
+
+    fn publish_event(&mut self, event: &ssp::Event) -> Result<()> {
+        let payload = self.config.event_payload(event)?;
+
+        Ok(self
+            .conn
+            .publish(self.config.event_subject(event).as_str(), payload)?)
+    }
+
+    fn publish_status(&mut self, status: &ssp::DeviceStatus) -> Result<()> {
+        let payload = serde_json::to_vec(status)
+            .map_err(|err| ssp::Error::Io(format!("NATS status encoding error: {err}")))?;
+
+        Ok(self
+            .conn
+            .publish(self.config.status_subject().as_str(), payload)?)
+    }
+}
+
+impl Drop for NatsSink {
+    fn drop(&mut self) {
+        // flush any buffered messages before closing
+        if let Err(err) = self.conn.flush() {
+            log::warn!("Failed to flush NATS connection: {err}");
+        }
+    }
+}
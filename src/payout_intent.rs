@@ -0,0 +1,451 @@
+//! Write-ahead log of payout intents.
+//!
+//! Before a payout command is sent, by [dispense](crate::DeviceHandle::dispense),
+//! [payout_by_denomination](crate::DeviceHandle::payout_by_denomination), or
+//! [submit](crate::DeviceHandle::submit), a [PayoutIntent] is synced to disk. The intent is marked
+//! as issued once the device accepts the command, and as completed once the background polling
+//! routine observes the `Dispensed` event. If the device refuses the payout, the intent is marked
+//! as failed. A `Jammed`, or `Incomplete payout` event marks the intent as incomplete.
+//!
+//! After a power loss mid-payout, the [unresolved](PayoutIntentLog::unresolved) intents tell what
+//! happened to every payout:
+//!
+//! - [Pending](IntentState::Pending): the payout command may, or may not have reached the device,
+//!   check the device before paying out again
+//! - [Issued](IntentState::Issued): the device accepted the payout, the notes were dispensed, or
+//!   are stuck in the device, do not pay out again
+//!
+//! Completed, failed, and incomplete intents need no recovery. Operators record the outcome of unresolved
+//! intents with [resolve](PayoutIntentLog::resolve).
+//!
+//! Every state change is appended as a full record, the latest record of an intent wins.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+use ssp::{CommandOps, Result};
+
+use crate::cash_levels::DenominationLevel;
+
+/// Environment variable with the path of the payout intent log.
+pub const PAYOUT_INTENT_ENV_PATH: &str = "SSP_PAYOUT_INTENTS";
+
+// PayoutByDenomination option dispensing the notes, instead of testing the payout.
+const PAYOUT_OPTION_PAYOUT: u8 = 0x58;
+// Length of a denomination in a PayoutByDenomination command: count, value, and country code.
+const PAYOUT_DENOMINATION_LEN: usize = 9;
+
+/// State of a [PayoutIntent].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum IntentState {
+    /// Intent written, the payout command may, or may not have reached the device.
+    #[default]
+    Pending,
+    /// Payout command accepted by the device, the `Dispensed` event was not observed yet.
+    Issued,
+    /// `Dispensed` event observed, or payout confirmed by an operator.
+    Completed,
+    /// Payout refused by the device, or voided by an operator, nothing was dispensed.
+    Failed,
+    /// Payout stopped by a `Jammed`, or `Incomplete payout` event, only some notes were
+    /// dispensed.
+    Incomplete,
+}
+
+impl IntentState {
+    /// Gets the [IntentState] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Issued => "issued",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+            Self::Incomplete => "incomplete",
+        }
+    }
+
+    /// Parses an [IntentState] from its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pending" => Some(Self::Pending),
+            "issued" => Some(Self::Issued),
+            "completed" => Some(Self::Completed),
+            "failed" => Some(Self::Failed),
+            "incomplete" => Some(Self::Incomplete),
+            _ => None,
+        }
+    }
+
+    /// Gets whether the outcome of the payout is known.
+    pub const fn is_resolved(&self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Incomplete)
+    }
+}
+
+impl fmt::Display for IntentState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Single payout in the [PayoutIntentLog].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PayoutIntent {
+    /// Identifier of the intent, increasing from one.
+    pub id: u64,
+    /// Time the intent was written, in milliseconds since the Unix epoch.
+    pub created_ms: u64,
+    /// Time of the last state change, in milliseconds since the Unix epoch.
+    pub updated_ms: u64,
+    /// Serial number of the device, zero if unknown.
+    pub serial_number: u32,
+    /// Currency of the payout, empty if unknown.
+    pub currency: String,
+    /// Notes to pay out, by note value.
+    pub notes: Vec<DenominationLevel>,
+    /// State of the payout.
+    pub state: IntentState,
+}
+
+impl PayoutIntent {
+    /// Gets the total amount of the payout.
+    pub fn amount(&self) -> u64 {
+        self.notes.iter().map(|n| n.amount()).sum()
+    }
+
+    fn to_line(&self) -> String {
+        let notes: Vec<String> = self
+            .notes
+            .iter()
+            .map(|n| format!("{}:{}", n.value, n.count))
+            .collect();
+
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            self.id,
+            self.state.as_str(),
+            self.created_ms,
+            self.updated_ms,
+            self.serial_number,
+            self.currency.replace(['\t', '\n', '\r'], " "),
+            notes.join(","),
+        )
+    }
+
+    fn from_line(line: &str) -> Result<Self> {
+        let invalid = || ssp::Error::Io(format!("invalid payout intent: {line}"));
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [id, state, created_ms, updated_ms, serial_number, currency, notes] = fields.as_slice()
+        else {
+            return Err(invalid());
+        };
+
+        let notes = notes
+            .split(',')
+            .filter(|n| !n.is_empty())
+            .map(|n| {
+                let (value, count) = n.split_once(':').ok_or_else(invalid)?;
+                Ok(DenominationLevel::new(
+                    value.parse().map_err(|_| invalid())?,
+                    count.parse().map_err(|_| invalid())?,
+                ))
+            })
+            .collect::<Result<Vec<DenominationLevel>>>()?;
+
+        Ok(Self {
+            id: id.parse().map_err(|_| invalid())?,
+            created_ms: created_ms.parse().map_err(|_| invalid())?,
+            updated_ms: updated_ms.parse().map_err(|_| invalid())?,
+            serial_number: serial_number.parse().map_err(|_| invalid())?,
+            currency: (*currency).into(),
+            notes,
+            state: IntentState::from_name(state).ok_or_else(invalid)?,
+        })
+    }
+}
+
+impl fmt::Display for PayoutIntent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let notes: Vec<String> = self
+            .notes
+            .iter()
+            .map(|n| format!("{}x{}", n.count, n.value))
+            .collect();
+
+        write!(
+            f,
+            "payout {}: {} {} ({}), {}",
+            self.id,
+            self.amount(),
+            self.currency,
+            notes.join(", "),
+            self.state,
+        )
+    }
+}
+
+struct IntentLogState {
+    file: fs::File,
+    intents: BTreeMap<u64, PayoutIntent>,
+}
+
+/// Append-only payout intent log, with one record per line.
+///
+/// Cloned logs share the same file.
+#[derive(Clone)]
+pub struct PayoutIntentLog {
+    path: PathBuf,
+    state: Arc<Mutex<IntentLogState>>,
+}
+
+impl PayoutIntentLog {
+    /// Opens the payout intent log at `path`, creating it if it does not exist.
+    pub fn open(path: &str) -> Result<Self> {
+        let path = PathBuf::from(path);
+
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        let complete_len = contents.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        if complete_len < contents.len() {
+            log::warn!(
+                "Discarding incomplete payout intent at the end of {}",
+                path.display()
+            );
+
+            file.set_len(complete_len as u64)?;
+        }
+
+        let mut intents = BTreeMap::new();
+        for line in contents[..complete_len].lines().filter(|l| !l.is_empty()) {
+            let intent = PayoutIntent::from_line(line)?;
+            intents.insert(intent.id, intent);
+        }
+
+        Ok(Self {
+            path,
+            state: Arc::new(Mutex::new(IntentLogState { file, intents })),
+        })
+    }
+
+    /// Opens the payout intent log at the path set in the [PAYOUT_INTENT_ENV_PATH] environment
+    /// variable.
+    ///
+    /// Returns `Ok(None)` if the variable is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(PAYOUT_INTENT_ENV_PATH) {
+            Ok(path) => Self::open(path.as_str()).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Gets the path of the payout intent log.
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap_or_default()
+    }
+
+    /// Gets all intents, oldest first.
+    pub fn intents(&self) -> Vec<PayoutIntent> {
+        self.state.lock().intents.values().cloned().collect()
+    }
+
+    /// Gets the intent with `id`, if any.
+    pub fn intent(&self, id: u64) -> Option<PayoutIntent> {
+        self.state.lock().intents.get(&id).cloned()
+    }
+
+    /// Gets the intents with an unknown outcome, oldest first.
+    pub fn unresolved(&self) -> Vec<PayoutIntent> {
+        self.state
+            .lock()
+            .intents
+            .values()
+            .filter(|i| !i.state.is_resolved())
+            .cloned()
+            .collect()
+    }
+
+    /// Writes a [Pending](IntentState::Pending) intent to pay out the `list`.
+    ///
+    /// The intent is synced to disk before returning.
+    pub fn begin(
+        &self,
+        serial_number: u32,
+        list: &ssp::PayoutDenominationList,
+    ) -> Result<PayoutIntent> {
+        let mut notes: Vec<DenominationLevel> = Vec::new();
+        for denom in list.iter().filter(|d| d.number() != 0) {
+            match notes.iter_mut().find(|n| n.value == denom.value()) {
+                Some(note) => note.count += u32::from(denom.number()),
+                None => notes.push(DenominationLevel::new(denom.value(), denom.number().into())),
+            }
+        }
+
+        let currency = list
+            .iter()
+            .find(|d| d.number() != 0)
+            .map(|d| <&str>::from(d.currency()).to_string())
+            .unwrap_or_default();
+
+        let mut state = self.state.lock();
+        let now = now_ms();
+
+        let intent = PayoutIntent {
+            id: state
+                .intents
+                .keys()
+                .next_back()
+                .map(|id| id + 1)
+                .unwrap_or(1),
+            created_ms: now,
+            updated_ms: now,
+            serial_number,
+            currency,
+            notes,
+            state: IntentState::Pending,
+        };
+
+        Self::append(&mut state, intent)
+    }
+
+    /// Moves the intent with `id` to the `state`.
+    ///
+    /// Returns `Ok(None)` if there is no intent with `id`.
+    pub fn mark(&self, id: u64, state: IntentState) -> Result<Option<PayoutIntent>> {
+        let mut log_state = self.state.lock();
+
+        let Some(mut intent) = log_state.intents.get(&id).cloned() else {
+            return Ok(None);
+        };
+
+        intent.state = state;
+        intent.updated_ms = now_ms();
+
+        Self::append(&mut log_state, intent).map(Some)
+    }
+
+    /// Marks the oldest [Issued](IntentState::Issued) intent as
+    /// [Completed](IntentState::Completed), after a `Dispensed` event.
+    ///
+    /// Returns `Ok(None)` if no intent is issued.
+    pub fn complete_dispensed(&self) -> Result<Option<PayoutIntent>> {
+        self.mark_oldest_issued(IntentState::Completed)
+    }
+
+    /// Marks the oldest [Issued](IntentState::Issued) intent as
+    /// [Incomplete](IntentState::Incomplete), after a `Jammed`, or `Incomplete payout` event.
+    ///
+    /// Returns `Ok(None)` if no intent is issued.
+    pub fn mark_incomplete(&self) -> Result<Option<PayoutIntent>> {
+        self.mark_oldest_issued(IntentState::Incomplete)
+    }
+
+    fn mark_oldest_issued(&self, state: IntentState) -> Result<Option<PayoutIntent>> {
+        let id = self
+            .state
+            .lock()
+            .intents
+            .values()
+            .find(|i| i.state == IntentState::Issued)
+            .map(|i| i.id);
+
+        match id {
+            Some(id) => self.mark(id, state),
+            None => Ok(None),
+        }
+    }
+
+    /// Records the outcome of an unresolved intent, after checking the device.
+    ///
+    /// `dispensed` marks the intent as [Completed](IntentState::Completed), otherwise as
+    /// [Failed](IntentState::Failed). Returns an error if the intent does not exist, or is
+    /// already resolved.
+    pub fn resolve(&self, id: u64, dispensed: bool) -> Result<PayoutIntent> {
+        match self.intent(id) {
+            Some(intent) if intent.state.is_resolved() => Err(ssp::Error::Io(format!(
+                "payout intent {id} is already {}",
+                intent.state
+            ))),
+            Some(_) => {
+                let state = if dispensed {
+                    IntentState::Completed
+                } else {
+                    IntentState::Failed
+                };
+
+                self.mark(id, state)?
+                    .ok_or(ssp::Error::Io(format!("no payout intent {id}")))
+            }
+            None => Err(ssp::Error::Io(format!("no payout intent {id}"))),
+        }
+    }
+
+    fn append(state: &mut IntentLogState, intent: PayoutIntent) -> Result<PayoutIntent> {
+        state.file.write_all(intent.to_line().as_bytes())?;
+        state.file.sync_data()?;
+
+        state.intents.insert(intent.id, intent.clone());
+
+        Ok(intent)
+    }
+}
+
+/// Gets the notes paid out by a `command`.
+///
+/// Returns `None` for test payouts, and commands other than
+/// [PayoutByDenomination](ssp::MessageType::PayoutByDenomination).
+pub(crate) fn payout_list(command: &dyn CommandOps) -> Option<ssp::PayoutDenominationList> {
+    if command.command() != ssp::MessageType::PayoutByDenomination {
+        return None;
+    }
+
+    let (&count, params) = command.data().get(1..)?.split_first()?;
+    let (&option, denominations) = params.split_last()?;
+
+    if option != PAYOUT_OPTION_PAYOUT
+        || denominations.len() != usize::from(count) * PAYOUT_DENOMINATION_LEN
+    {
+        return None;
+    }
+
+    let mut list = ssp::PayoutDenominationList::new();
+    for denom in denominations.chunks_exact(PAYOUT_DENOMINATION_LEN) {
+        list.as_inner_mut()
+            .push(ssp::PayoutDenomination::create(
+                u16::from_le_bytes([denom[0], denom[1]]),
+                u32::from_le_bytes([denom[2], denom[3], denom[4], denom[5]]),
+                ssp::CountryCode::from(&[denom[6], denom[7], denom[8]]),
+            ))
+            .ok()?;
+    }
+
+    Some(list)
+}
+
+fn now_ms() -> u64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,216 @@
+//! Drift-corrected timer for the background polling routines.
+//!
+//! Poll ticks are scheduled on a fixed grid, measured from the previous scheduled tick instead of
+//! the time the previous poll completed. A poll delayed by a long running command is followed by
+//! a shorter wait, so the cadence does not drift.
+//!
+//! A poll firing later than the [jitter budget](PollTimer::jitter_budget) after its tick missed
+//! the schedule. Instead of bursting polls to catch up, the grid restarts from the late poll.
+//!
+//! Ticks are measured on a [Clock], the system clock unless set with
+//! [with_clock](PollTimer::with_clock).
+//!
+//! A [PollSchedule] shares the interval, and jitter budget with a running polling routine, which
+//! applies changes from its next tick on.
+
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+
+use crate::clock::{self, Clock};
+
+/// Default maximum delay of a poll after its scheduled tick (milliseconds).
+pub const DEFAULT_JITTER_BUDGET_MS: u64 = 100;
+
+/// Timer scheduling polls every `interval`, correcting for drift.
+#[derive(Clone, Debug)]
+pub struct PollTimer {
+    clock: Arc<dyn Clock>,
+    interval: time::Duration,
+    jitter_budget: time::Duration,
+    next: time::Instant,
+    missed: u64,
+}
+
+impl PollTimer {
+    /// Creates a new [PollTimer], with the first tick one `interval` from now.
+    pub fn new(interval: time::Duration) -> Self {
+        let clock = clock::system_clock();
+        let next = clock.now() + interval;
+
+        Self {
+            clock,
+            interval,
+            jitter_budget: time::Duration::from_millis(DEFAULT_JITTER_BUDGET_MS),
+            next,
+            missed: 0,
+        }
+    }
+
+    /// Builder function that sets the [Clock] measuring the ticks.
+    ///
+    /// Restarts the schedule, with the first tick one interval from the clock's current time.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self.restart();
+        self
+    }
+
+    /// Gets the [Clock] measuring the ticks.
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Builder function that sets the maximum delay of a poll after its scheduled tick.
+    pub fn with_jitter_budget(mut self, jitter_budget: time::Duration) -> Self {
+        self.jitter_budget = jitter_budget;
+        self
+    }
+
+    /// Gets the interval between ticks.
+    pub const fn interval(&self) -> time::Duration {
+        self.interval
+    }
+
+    /// Sets the interval between ticks, from the next scheduled tick on.
+    pub fn set_interval(&mut self, interval: time::Duration) {
+        self.interval = interval;
+    }
+
+    /// Sets the maximum delay of a poll after its scheduled tick.
+    pub fn set_jitter_budget(&mut self, jitter_budget: time::Duration) {
+        self.jitter_budget = jitter_budget;
+    }
+
+    /// Gets the maximum delay of a poll after its scheduled tick.
+    pub const fn jitter_budget(&self) -> time::Duration {
+        self.jitter_budget
+    }
+
+    /// Gets the time of the next scheduled tick.
+    pub const fn next_tick(&self) -> time::Instant {
+        self.next
+    }
+
+    /// Gets whether the next tick is due.
+    pub fn due(&self) -> bool {
+        self.clock.now() >= self.next
+    }
+
+    /// Gets the number of ticks that fired later than the jitter budget.
+    pub const fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    /// Fires the due tick, and schedules the next one.
+    ///
+    /// Returns how late the tick fired.
+    pub fn tick(&mut self) -> time::Duration {
+        let now = self.clock.now();
+        let late = now.saturating_duration_since(self.next);
+
+        if late > self.jitter_budget {
+            self.missed += 1;
+            log::warn!(
+                "Poll fired {late:?} after its scheduled tick, over the {:?} jitter budget",
+                self.jitter_budget
+            );
+            self.next = now + self.interval;
+        } else {
+            self.next += self.interval;
+        }
+
+        late
+    }
+
+    /// Restarts the schedule one interval from now, e.g. after polling was paused on purpose.
+    pub fn restart(&mut self) {
+        self.next = self.clock.now() + self.interval;
+    }
+
+    /// Gets the time of the next scheduled tick on the system clock, to wait for it.
+    ///
+    /// Equal to [next_tick](Self::next_tick) with the system clock.
+    pub fn next_system_tick(&self) -> time::Instant {
+        self.clock.system_deadline(self.next)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ScheduleState {
+    interval: Option<time::Duration>,
+    jitter_budget: time::Duration,
+}
+
+/// Poll interval, and jitter budget shared with a running polling routine.
+///
+/// Clones share the same schedule.
+#[derive(Clone, Debug)]
+pub struct PollSchedule(Arc<Mutex<ScheduleState>>);
+
+impl PollSchedule {
+    /// Creates a new [PollSchedule], with the default interval, and jitter budget.
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(ScheduleState {
+            interval: None,
+            jitter_budget: time::Duration::from_millis(DEFAULT_JITTER_BUDGET_MS),
+        })))
+    }
+
+    /// Gets the interval between polls, if set.
+    ///
+    /// Unless set, the polling routine uses its default interval.
+    pub fn interval(&self) -> Option<time::Duration> {
+        self.0.lock().interval
+    }
+
+    /// Sets the interval between polls, `None` restores the default of the polling routine.
+    ///
+    /// A zero `interval` is ignored.
+    pub fn set_interval(&self, interval: Option<time::Duration>) {
+        if interval.is_some_and(|i| i.is_zero()) {
+            log::warn!("Ignoring zero poll interval");
+        } else {
+            self.0.lock().interval = interval;
+        }
+    }
+
+    /// Gets the maximum delay of a poll after its scheduled tick.
+    pub fn jitter_budget(&self) -> time::Duration {
+        self.0.lock().jitter_budget
+    }
+
+    /// Sets the maximum delay of a poll after its scheduled tick.
+    pub fn set_jitter_budget(&self, jitter_budget: time::Duration) {
+        self.0.lock().jitter_budget = jitter_budget;
+    }
+
+    /// Creates a [PollTimer] on the schedule, using the `default_interval` unless an interval is
+    /// set.
+    pub fn timer(&self, default_interval: time::Duration) -> PollTimer {
+        let state = *self.0.lock();
+
+        PollTimer::new(state.interval.unwrap_or(default_interval))
+            .with_jitter_budget(state.jitter_budget)
+    }
+
+    /// Applies schedule changes to a running `timer`.
+    pub fn update(&self, timer: &mut PollTimer, default_interval: time::Duration) {
+        let state = *self.0.lock();
+        let interval = state.interval.unwrap_or(default_interval);
+
+        if timer.interval() != interval {
+            log::info!("Poll interval changed to {interval:?}");
+            timer.set_interval(interval);
+        }
+
+        timer.set_jitter_budget(state.jitter_budget);
+    }
+}
+
+impl Default for PollSchedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
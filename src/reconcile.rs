@@ -0,0 +1,281 @@
+//! Reconciliation of the host-side transaction journal with the device counters, and levels.
+//!
+//! [reconcile] compares the [journal](crate::journal) with:
+//!
+//! - the note counters of the device: notes accepted (stacked, and stored), and dispensed
+//! - the recycler levels reported by the device, or estimated by the
+//!   [cash levels](crate::cash_levels)
+//!
+//! and returns a [ReconciliationReport] listing every [Discrepancy].
+//!
+//! The `ssp` library does not implement the `GetCounters` command yet, so counters obtained
+//! elsewhere are passed as [DeviceCounters]. Counters are compared with the journal totals, so
+//! they have to be reset when the journal is started, or passed as the difference since then.
+//!
+//! The expected recycler level of every note value is replayed from the journal: recycled
+//! credits add notes, payouts, and empties remove them. An empty with unknown notes clears all
+//! levels.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time;
+
+use ssp::Result;
+
+use crate::cash_levels::{CashLevels, DenominationLevel};
+use crate::journal::{JournalTotals, TransactionJournal, TransactionKind};
+
+/// Note counters reported by the device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceCounters {
+    /// Number of notes stacked in the cashbox.
+    pub stacked: u64,
+    /// Number of notes stored in the recycler.
+    pub stored: u64,
+    /// Number of notes dispensed.
+    pub dispensed: u64,
+}
+
+impl DeviceCounters {
+    /// Creates a new [DeviceCounters].
+    pub const fn new(stacked: u64, stored: u64, dispensed: u64) -> Self {
+        Self {
+            stacked,
+            stored,
+            dispensed,
+        }
+    }
+
+    /// Gets the number of accepted notes, stacked, or stored.
+    pub const fn accepted(&self) -> u64 {
+        self.stacked.saturating_add(self.stored)
+    }
+}
+
+/// Kind of a [Discrepancy].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DiscrepancyKind {
+    /// Credited notes in the journal differ from the notes accepted by the device.
+    AcceptedNotes,
+    /// Paid out notes in the journal differ from the notes dispensed by the device.
+    DispensedNotes,
+    /// Journaled recycler level of a note value differs from the device level.
+    RecyclerLevel,
+}
+
+impl DiscrepancyKind {
+    /// Gets the [DiscrepancyKind] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::AcceptedNotes => "accepted_notes",
+            Self::DispensedNotes => "dispensed_notes",
+            Self::RecyclerLevel => "recycler_level",
+        }
+    }
+}
+
+impl fmt::Display for DiscrepancyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Mismatch between the journal, and the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Discrepancy {
+    /// Discrepancy kind.
+    pub kind: DiscrepancyKind,
+    /// Note value of a recycler level, zero for counters.
+    pub value: u32,
+    /// Number of notes according to the journal.
+    pub journal: i64,
+    /// Number of notes according to the device.
+    pub device: i64,
+}
+
+impl Discrepancy {
+    /// Gets the number of notes the device holds, or counted, in excess of the journal.
+    ///
+    /// Negative if notes are missing on the device.
+    pub const fn difference(&self) -> i64 {
+        self.device - self.journal
+    }
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (kind, journal, device, diff) =
+            (self.kind, self.journal, self.device, self.difference());
+
+        match self.kind {
+            DiscrepancyKind::RecyclerLevel => write!(
+                f,
+                "{kind} of {}: journal {journal}, device {device} ({diff:+})",
+                self.value
+            ),
+            _ => write!(f, "{kind}: journal {journal}, device {device} ({diff:+})"),
+        }
+    }
+}
+
+/// Result of a [reconcile] run.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReconciliationReport {
+    /// Time of the reconciliation, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Serial number of the device, zero if unknown.
+    pub serial_number: u32,
+    /// Journal totals at the time of the reconciliation.
+    pub totals: JournalTotals,
+    /// Device counters compared with the journal, if any.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub counters: Option<DeviceCounters>,
+    /// Recycler levels replayed from the journal, by increasing note value.
+    pub expected_recycler: Vec<DenominationLevel>,
+    /// Recycler levels of the device, by increasing note value, empty if unknown.
+    pub recycler: Vec<DenominationLevel>,
+    /// Mismatches between the journal, and the device.
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl ReconciliationReport {
+    /// Gets whether the journal matches the device.
+    pub fn is_balanced(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+impl fmt::Display for ReconciliationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_balanced() {
+            return write!(f, "balanced");
+        }
+
+        let discrepancies: Vec<String> =
+            self.discrepancies.iter().map(|d| format!("{d}")).collect();
+
+        write!(f, "{}", discrepancies.join(", "))
+    }
+}
+
+/// Compares the `journal` with the device `counters`, and recycler `levels`.
+///
+/// Skips the counter checks if `counters` is `None`, and the recycler checks if `levels` is
+/// `None`. The serial number of the report is left zero, see
+/// [DeviceHandle::reconcile](crate::DeviceHandle::reconcile).
+pub fn reconcile(
+    journal: &TransactionJournal,
+    counters: Option<&DeviceCounters>,
+    levels: Option<&CashLevels>,
+) -> Result<ReconciliationReport> {
+    let totals = journal.totals();
+    let mut discrepancies = Vec::new();
+
+    if let Some(counters) = counters {
+        let checks = [
+            (
+                DiscrepancyKind::AcceptedNotes,
+                totals.credited_notes,
+                counters.accepted(),
+            ),
+            (
+                DiscrepancyKind::DispensedNotes,
+                totals.paid_out_notes,
+                counters.dispensed,
+            ),
+        ];
+
+        for (kind, journal, device) in checks {
+            if journal != device {
+                discrepancies.push(Discrepancy {
+                    kind,
+                    value: 0,
+                    journal: journal as i64,
+                    device: device as i64,
+                });
+            }
+        }
+    }
+
+    let (expected_recycler, recycler) = match levels {
+        Some(levels) => {
+            let expected = expected_recycler(journal, levels.recycled().as_ref())?;
+            let recycler = levels.estimate().recycler;
+
+            let mut values: BTreeMap<u32, (i64, i64)> = BTreeMap::new();
+            for (&value, &count) in expected.iter() {
+                values.entry(value).or_default().0 = count;
+            }
+            for level in recycler.iter() {
+                values.entry(level.value).or_default().1 = level.count.into();
+            }
+
+            discrepancies.extend(
+                values
+                    .into_iter()
+                    .filter(|(_, (journal, device))| journal != device)
+                    .map(|(value, (journal, device))| Discrepancy {
+                        kind: DiscrepancyKind::RecyclerLevel,
+                        value,
+                        journal,
+                        device,
+                    }),
+            );
+
+            let expected = expected
+                .into_iter()
+                .filter(|&(_, count)| count > 0)
+                .map(|(value, count)| {
+                    DenominationLevel::new(value, count.min(u32::MAX.into()) as u32)
+                })
+                .collect();
+
+            (expected, recycler)
+        }
+        None => (Vec::new(), Vec::new()),
+    };
+
+    Ok(ReconciliationReport {
+        timestamp_ms: time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default(),
+        serial_number: 0,
+        totals,
+        counters: counters.copied(),
+        expected_recycler,
+        recycler,
+        discrepancies,
+    })
+}
+
+// Replays the recycler levels of the `recycled` note values from the journal.
+//
+// Levels are signed, so payouts of notes that were never journaled show up as negative levels.
+fn expected_recycler(journal: &TransactionJournal, recycled: &[u32]) -> Result<BTreeMap<u32, i64>> {
+    let mut levels: BTreeMap<u32, i64> = BTreeMap::new();
+
+    for entry in journal.entries()? {
+        let count = i64::from(entry.count);
+
+        match entry.kind {
+            TransactionKind::Credit if recycled.contains(&entry.value) => {
+                *levels.entry(entry.value).or_default() += count;
+            }
+            TransactionKind::Credit => (),
+            TransactionKind::Payout => *levels.entry(entry.value).or_default() -= count,
+            // the emptied notes are unknown, the recycler is empty afterwards
+            TransactionKind::Empty if entry.count == 0 => levels.clear(),
+            TransactionKind::Empty => *levels.entry(entry.value).or_default() -= count,
+        }
+    }
+
+    levels.retain(|_, count| *count != 0);
+
+    Ok(levels)
+}
@@ -0,0 +1,345 @@
+//! Redaction of sensitive material from logs, the frame log, and wire captures.
+//!
+//! A [RedactionPolicy] selects the categories of sensitive commands:
+//!
+//! - `keys`: key exchange values, and fixed keys, sent with the `SetGenerator`, `SetModulus`,
+//!   `RequestKeyExchange`, and `SetEncryptionKey` commands
+//! - `barcodes`: barcode contents returned from `GetBarcodeData`
+//! - `serials`: serial numbers returned from `SerialNumber`
+//!
+//! Frames exchanged for a redacted command keep their structure for debugging: the sequence ID,
+//! length, and command, or response status bytes are kept, the remaining data bytes are replaced
+//! with [REDACTED_BYTE], and the CRC is recalculated over the redacted frame, so redacted
+//! captures still pretty-print as valid frames.
+//!
+//! The policy is set with [set_redaction_policy], or the [REDACT_ENV] environment variable, as a
+//! comma-separated list of categories, `all`, or `none`, e.g. `SSP_REDACT=keys,barcodes`. An
+//! invalid list redacts all categories. Nothing is redacted by default.
+//!
+//! The `ssp` library does not read note serial numbers, so `serials` only covers the serial
+//! number of the device.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use parking_lot::Mutex;
+use ssp::message::index;
+use ssp::{len, Result, STX};
+
+use crate::framing::{self, FrameDecoder};
+
+/// Environment variable with the comma-separated list of redacted categories.
+pub const REDACT_ENV: &str = "SSP_REDACT";
+
+/// Replacement for redacted data bytes.
+pub const REDACTED_BYTE: u8 = 0x00;
+
+// `None` until configured, then the environment policy applies
+static REDACTION_POLICY: Mutex<Option<RedactionPolicy>> = Mutex::new(None);
+// set while a frame of a redacted command is exchanged with the device
+static REDACTING: AtomicBool = AtomicBool::new(false);
+
+/// Categories of sensitive material redacted from logs, and captures.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RedactionPolicy {
+    /// Redacts key exchange values, and fixed keys.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub keys: bool,
+    /// Redacts barcode contents.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub barcodes: bool,
+    /// Redacts serial numbers.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub serials: bool,
+}
+
+impl RedactionPolicy {
+    /// Creates a new [RedactionPolicy] that redacts nothing.
+    pub const fn new() -> Self {
+        Self {
+            keys: false,
+            barcodes: false,
+            serials: false,
+        }
+    }
+
+    /// Creates a new [RedactionPolicy] that redacts all categories.
+    pub const fn all() -> Self {
+        Self {
+            keys: true,
+            barcodes: true,
+            serials: true,
+        }
+    }
+
+    /// Builder function that sets whether key exchange values, and fixed keys are redacted.
+    pub const fn with_keys(mut self, keys: bool) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// Builder function that sets whether barcode contents are redacted.
+    pub const fn with_barcodes(mut self, barcodes: bool) -> Self {
+        self.barcodes = barcodes;
+        self
+    }
+
+    /// Builder function that sets whether serial numbers are redacted.
+    pub const fn with_serials(mut self, serials: bool) -> Self {
+        self.serials = serials;
+        self
+    }
+
+    /// Parses a comma-separated list of categories: `keys`, `barcodes`, `serials`, `all`, or
+    /// `none`.
+    pub fn from_list(list: &str) -> Result<Self> {
+        let mut policy = Self::new();
+
+        for category in list.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            policy = match category.to_lowercase().as_str() {
+                "keys" => policy.with_keys(true),
+                "barcodes" => policy.with_barcodes(true),
+                "serials" => policy.with_serials(true),
+                "all" => Self::all(),
+                "none" => policy,
+                _ => {
+                    return Err(ssp::Error::Io(format!(
+                        "invalid redaction category: {category}"
+                    )))
+                }
+            };
+        }
+
+        Ok(policy)
+    }
+
+    /// Gets the [RedactionPolicy] set in the [REDACT_ENV] environment variable.
+    ///
+    /// Returns `Ok(None)` if the variable is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(REDACT_ENV) {
+            Ok(list) => Self::from_list(list.as_str()).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Gets whether nothing is redacted.
+    pub const fn is_empty(&self) -> bool {
+        !(self.keys || self.barcodes || self.serials)
+    }
+
+    /// Gets whether frames of the `command` are redacted.
+    pub fn redacts(&self, command: ssp::MessageType) -> bool {
+        match command {
+            ssp::MessageType::SetGenerator
+            | ssp::MessageType::SetModulus
+            | ssp::MessageType::RequestKeyExchange
+            | ssp::MessageType::SetEncryptionKey => self.keys,
+            ssp::MessageType::GetBarcodeData => self.barcodes,
+            ssp::MessageType::SerialNumber => self.serials,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for RedactionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let categories: Vec<&str> = [
+            (self.keys, "keys"),
+            (self.barcodes, "barcodes"),
+            (self.serials, "serials"),
+        ]
+        .into_iter()
+        .filter_map(|(redacted, name)| redacted.then_some(name))
+        .collect();
+
+        if categories.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", categories.join(","))
+        }
+    }
+}
+
+/// Gets the current [RedactionPolicy].
+pub fn redaction_policy() -> RedactionPolicy {
+    *REDACTION_POLICY
+        .lock()
+        .get_or_insert_with(|| match RedactionPolicy::from_env() {
+            Ok(policy) => policy.unwrap_or_default(),
+            Err(err) => {
+                log::warn!("Redacting all categories, {REDACT_ENV} is invalid: {err}");
+                RedactionPolicy::all()
+            }
+        })
+}
+
+/// Sets the [RedactionPolicy] for all devices.
+pub fn set_redaction_policy(policy: RedactionPolicy) {
+    *REDACTION_POLICY.lock() = Some(policy);
+}
+
+/// Redacts an unstuffed `frame`, starting with the `STX` byte.
+///
+/// Keeps the sequence ID, length, and first data byte, replaces the other data bytes with
+/// [REDACTED_BYTE], and recalculates the CRC of a complete frame. The CRC of a partial frame is
+/// redacted as well.
+pub fn redact_frame(frame: &[u8]) -> Vec<u8> {
+    let mut redacted: Vec<u8> = frame.into();
+
+    // the first data byte holds the command, or response status
+    let start = index::DATA + 1;
+
+    let complete = redacted.len() > index::LEN
+        && redacted.len() == redacted[index::LEN] as usize + len::METADATA;
+
+    let end = if complete {
+        redacted.len() - 2
+    } else {
+        redacted.len()
+    };
+
+    if let Some(data) = redacted.get_mut(start..end) {
+        data.fill(REDACTED_BYTE);
+    }
+
+    if complete {
+        let crc = ssp::crc::crc16(&redacted[1..end]);
+        redacted[end..].copy_from_slice(crc.to_le_bytes().as_ref());
+    }
+
+    redacted
+}
+
+/// Redacts message `data`, keeping the first byte with the command, or response status.
+pub fn redact_data(data: &[u8]) -> Vec<u8> {
+    let mut redacted: Vec<u8> = data.into();
+
+    if let Some(rest) = redacted.get_mut(1..) {
+        rest.fill(REDACTED_BYTE);
+    }
+
+    redacted
+}
+
+// Gets whether a frame of a redacted command is being exchanged.
+pub(crate) fn redacting() -> bool {
+    REDACTING.load(Ordering::Relaxed)
+}
+
+// Gets the `frame` for logging, redacted during a redacted exchange.
+pub(crate) fn frame(frame: &[u8]) -> Cow<'_, [u8]> {
+    if redacting() {
+        Cow::Owned(redact_frame(frame))
+    } else {
+        Cow::Borrowed(frame)
+    }
+}
+
+// Gets the message `data` for logging, redacted during a redacted exchange.
+pub(crate) fn data(data: &[u8]) -> Cow<'_, [u8]> {
+    if redacting() {
+        Cow::Owned(redact_data(data))
+    } else {
+        Cow::Borrowed(data)
+    }
+}
+
+// Marks the exchange of a `command` as redacted, if required by the policy, until dropped.
+pub(crate) struct RedactedExchange(bool);
+
+impl RedactedExchange {
+    pub(crate) fn enter(command: ssp::MessageType) -> Self {
+        let redacted = redaction_policy().redacts(command);
+
+        if redacted {
+            REDACTING.store(true, Ordering::Relaxed);
+        }
+
+        Self(redacted)
+    }
+}
+
+impl Drop for RedactedExchange {
+    fn drop(&mut self) {
+        if self.0 {
+            REDACTING.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Redacts the frames of redacted exchanges from a stream of stuffed bytes.
+///
+/// Bytes outside of redacted exchanges pass through unchanged. Frames of a redacted exchange are
+/// buffered until complete, and returned [redacted](redact_frame), and stuffed again.
+#[derive(Clone, Debug, Default)]
+pub struct FrameRedactor {
+    decoder: FrameDecoder,
+    active: bool,
+}
+
+impl FrameRedactor {
+    /// Creates a new [FrameRedactor].
+    pub const fn new() -> Self {
+        Self {
+            decoder: FrameDecoder::new(),
+            active: false,
+        }
+    }
+
+    /// Pushes a `chunk` of the stream, and returns the bytes to record.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(chunk.len());
+
+        for &byte in chunk.iter() {
+            if !self.active {
+                if !(redacting() && byte == STX) {
+                    out.push(byte);
+                    continue;
+                }
+
+                self.active = true;
+                self.decoder.reset();
+            }
+
+            match self.decoder.push(byte) {
+                Ok(true) => {
+                    out.extend(self.take());
+                }
+                Ok(false) => (),
+                Err(ssp::Error::InvalidSTX(_)) => {
+                    out.extend(self.take());
+
+                    // an unpaired STX byte started the next frame
+                    out.extend(self.push(&[STX, byte]));
+                }
+                Err(_) => {
+                    out.extend(self.take());
+                    out.push(byte);
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Returns a redacted partial frame, if any, and stops buffering.
+    pub fn flush(&mut self) -> Vec<u8> {
+        if self.active {
+            self.take()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn take(&mut self) -> Vec<u8> {
+        let frame = redact_frame(self.decoder.frame());
+
+        self.active = false;
+        self.decoder.reset();
+
+        framing::stuff(frame.as_ref()).unwrap_or_default()
+    }
+}
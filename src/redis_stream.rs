@@ -0,0 +1,186 @@
+//! Redis Streams [EventSink] for appending device events to a stream.
+//!
+//! Each event is added as a stream entry with the fields:
+//!
+//! - `device_id`: configured device identifier, e.g. the device serial number
+//! - `seq`: per-sink event sequence number, starting at zero
+//! - `method`: event method, e.g. `note_credit`
+//! - `event`: JSON encoded [Event](ssp::Event)
+//!
+//! Consumers can use consumer groups (`XREADGROUP`) to process events independently.
+
+use ssp::Result;
+
+use crate::sink::EventSink;
+
+/// Default Redis server URL.
+pub const REDIS_URL: &str = "redis://127.0.0.1:6379";
+/// Default stream key.
+pub const REDIS_STREAM_KEY: &str = "ssp:events";
+/// Default device identifier.
+pub const REDIS_DEVICE_ID: &str = "ssp";
+
+/// Configuration for a [RedisStreamSink].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RedisConfig {
+    url: String,
+    stream_key: String,
+    device_id: String,
+    max_len: Option<usize>,
+}
+
+impl RedisConfig {
+    /// Creates a new [RedisConfig] with default settings.
+    pub fn new() -> Self {
+        Self {
+            url: REDIS_URL.into(),
+            stream_key: REDIS_STREAM_KEY.into(),
+            device_id: REDIS_DEVICE_ID.into(),
+            max_len: None,
+        }
+    }
+
+    /// Gets the Redis server URL.
+    pub fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    /// Builder function that sets the Redis server URL.
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Gets the stream key.
+    pub fn stream_key(&self) -> &str {
+        self.stream_key.as_str()
+    }
+
+    /// Builder function that sets the stream key.
+    pub fn with_stream_key(mut self, key: &str) -> Self {
+        self.stream_key = key.into();
+        self
+    }
+
+    /// Gets the device identifier added to each entry.
+    pub fn device_id(&self) -> &str {
+        self.device_id.as_str()
+    }
+
+    /// Builder function that sets the device identifier added to each entry.
+    pub fn with_device_id(mut self, device_id: &str) -> Self {
+        self.device_id = device_id.into();
+        self
+    }
+
+    /// Gets the approximate maximum stream length, `None` for an unbounded stream.
+    pub const fn max_len(&self) -> Option<usize> {
+        self.max_len
+    }
+
+    /// Builder function that sets the approximate maximum stream length.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// [EventSink] that appends device events to a Redis stream.
+///
+/// The connection is re-established on the next event after a failure.
+pub struct RedisStreamSink {
+    config: RedisConfig,
+    client: redis::Client,
+    conn: Option<redis::Connection>,
+    seq: u64,
+}
+
+impl RedisStreamSink {
+    /// Creates a new [RedisStreamSink], and connects to the Redis server.
+    pub fn new(config: RedisConfig) -> Result<Self> {
+        let client = redis::Client::open(config.url()).map_err(redis_error)?;
+        let conn = Some(client.get_connection().map_err(redis_error)?);
+
+        Ok(Self {
+            config,
+            client,
+            conn,
+            seq: 0,
+        })
+    }
+
+    /// Gets a reference to the [RedisConfig].
+    pub fn config(&self) -> &RedisConfig {
+        &self.config
+    }
+
+    /// Gets the sequence number for the next event.
+    pub const fn sequence(&self) -> u64 {
+        self.seq
+    }
+
+    fn connection(&mut self) -> Result<&mut redis::Connection> {
+        if self.conn.is_none() {
+            log::debug!("Reconnecting to Redis at {}", self.config.url());
+            self.conn = Some(self.client.get_connection().map_err(redis_error)?);
+        }
+
+        self.conn
+            .as_mut()
+            .ok_or(ssp::Error::Io("Redis connection unavailable".into()))
+    }
+}
+
+impl EventSink for RedisStreamSink {
+    fn name(&self) -> &str {
+        "Redis"
+    }
+
+    fn publish_event(&mut self, event: &ssp::Event) -> Result<()> {
+        let json = serde_json::to_string(event)
+            .map_err(|err| ssp::Error::Io(format!("Redis event encoding error: {err}")))?;
+
+        let mut cmd = redis::cmd("XADD");
+        cmd.arg(self.config.stream_key());
+
+        if let Some(max_len) = self.config.max_len() {
+            cmd.arg("MAXLEN").arg("~").arg(max_len);
+        }
+
+        cmd.arg("*")
+            .arg("device_id")
+            .arg(self.config.device_id())
+            .arg("seq")
+            .arg(self.seq)
+            .arg("method")
+            .arg(event.method().to_str())
+            .arg("event")
+            .arg(json);
+
+        let res = cmd.query::<String>(self.connection()?);
+
+        match res {
+            Ok(id) => {
+                log::trace!("Added event #{} to Redis stream: {id}", self.seq);
+                self.seq = self.seq.wrapping_add(1);
+                Ok(())
+            }
+            Err(err) => {
+                // drop the connection to reconnect on the next event
+                self.conn = None;
+                Err(redis_error(err))
+            }
+        }
+    }
+}
+
+fn redis_error(err: redis::RedisError) -> ssp::Error {
+    ssp::Error::Io(format!("Redis error: {err}"))
+}
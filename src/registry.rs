@@ -0,0 +1,285 @@
+//! Persistent registry of the devices the server has talked to.
+//!
+//! Every device initialized with [enable_device](crate::DeviceHandle::enable_device) is recorded
+//! with its serial number, unit type, firmware version, dataset version, and the port it was
+//! connected to. The background polling routine updates the time the device was last seen, so
+//! fleet tools can find which validator is where, even while the server, or device is offline.
+//!
+//! The registry is saved after every change, and loaded on startup. Last-seen times are saved at
+//! most once every [DEVICE_REGISTRY_TOUCH_MS], so polling does not rewrite the file constantly.
+//!
+//! The registry is kept in a file by default, or in any [Storage] backend with
+//! [from_storage](DeviceRegistry::from_storage).
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::redact;
+use crate::storage::{FileStorage, Storage, REGISTRY_COLLECTION};
+
+/// Environment variable with the path of the device registry file.
+pub const DEVICE_REGISTRY_ENV_PATH: &str = "SSP_DEVICE_REGISTRY";
+
+/// First line of every device registry file.
+pub const DEVICE_REGISTRY_HEADER: &str = "# ssp-device-registry v1";
+
+/// Minimum interval between saving last-seen updates, in milliseconds.
+pub const DEVICE_REGISTRY_TOUCH_MS: u64 = 60_000;
+
+/// Device entry in the [DeviceRegistry].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceRecord {
+    /// Serial number of the device.
+    pub serial_number: u32,
+    /// Unit type reported by the device.
+    pub unit_type: String,
+    /// Firmware version reported by the device.
+    pub firmware: String,
+    /// Dataset version reported by the device, empty if unknown.
+    pub dataset: String,
+    /// Serial device path, or bridge address the device was connected to, empty if unknown.
+    pub port: String,
+    /// Time the device was first registered, in milliseconds since the Unix epoch.
+    pub first_seen_ms: u64,
+    /// Time the device was last seen, in milliseconds since the Unix epoch.
+    pub last_seen_ms: u64,
+}
+
+impl DeviceRecord {
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.serial_number,
+            field(&self.unit_type),
+            field(&self.firmware),
+            field(&self.dataset),
+            field(&self.port),
+            self.first_seen_ms,
+            self.last_seen_ms,
+        )
+    }
+
+    fn from_line(line: &str) -> Result<Self> {
+        let invalid = || ssp::Error::Io(format!("invalid device record: {line}"));
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [serial_number, unit_type, firmware, dataset, port, first_seen_ms, last_seen_ms] =
+            fields.as_slice()
+        else {
+            return Err(invalid());
+        };
+
+        Ok(Self {
+            serial_number: serial_number.parse().map_err(|_| invalid())?,
+            unit_type: (*unit_type).into(),
+            firmware: (*firmware).into(),
+            dataset: (*dataset).into(),
+            port: (*port).into(),
+            first_seen_ms: first_seen_ms.parse().map_err(|_| invalid())?,
+            last_seen_ms: last_seen_ms.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl fmt::Display for DeviceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}, firmware: {}, dataset: {}, port: {}, last seen: {}",
+            self.serial_number,
+            self.unit_type,
+            self.firmware,
+            self.dataset,
+            self.port,
+            self.last_seen_ms,
+        )
+    }
+}
+
+struct RegistryState {
+    devices: BTreeMap<u32, DeviceRecord>,
+    saved_ms: u64,
+}
+
+/// Device registry, with one device per line.
+///
+/// Cloned registries share the same devices.
+#[derive(Clone)]
+pub struct DeviceRegistry {
+    path: String,
+    storage: Arc<dyn Storage>,
+    state: Arc<Mutex<RegistryState>>,
+}
+
+impl DeviceRegistry {
+    /// Opens the device registry at `path`, starting empty if the file does not exist.
+    pub fn open(path: &str) -> Result<Self> {
+        Self::from_storage(Arc::new(FileStorage::for_file(REGISTRY_COLLECTION, path)))
+    }
+
+    /// Opens the device registry kept in the [REGISTRY_COLLECTION] of the `storage`, starting
+    /// empty if the collection does not exist.
+    pub fn from_storage(storage: Arc<dyn Storage>) -> Result<Self> {
+        let path = storage.location(REGISTRY_COLLECTION);
+        let mut devices = BTreeMap::new();
+
+        for line in storage
+            .load(REGISTRY_COLLECTION)?
+            .iter()
+            .filter(|l| !l.starts_with(DEVICE_REGISTRY_HEADER))
+        {
+            let record = DeviceRecord::from_line(line)?;
+            devices.insert(record.serial_number, record);
+        }
+
+        log::debug!("Loaded {} devices from {path}", devices.len());
+
+        Ok(Self {
+            path,
+            storage,
+            state: Arc::new(Mutex::new(RegistryState {
+                devices,
+                saved_ms: now_ms(),
+            })),
+        })
+    }
+
+    /// Opens the device registry at the path set in the [DEVICE_REGISTRY_ENV_PATH] environment
+    /// variable.
+    ///
+    /// Returns `Ok(None)` if the variable is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(DEVICE_REGISTRY_ENV_PATH) {
+            Ok(path) => Self::open(path.as_str()).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Gets the path of the device registry file, or the location in the [Storage] backend.
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    /// Gets the registered devices, by increasing serial number.
+    pub fn devices(&self) -> Vec<DeviceRecord> {
+        self.state.lock().devices.values().cloned().collect()
+    }
+
+    /// Gets the device with `serial_number`, if registered.
+    pub fn device(&self, serial_number: u32) -> Option<DeviceRecord> {
+        self.state.lock().devices.get(&serial_number).cloned()
+    }
+
+    /// Registers a device, or updates its identity, and marks it as seen now.
+    ///
+    /// The `serial_number`, `unit_type`, `firmware`, and `dataset` are reported by the device,
+    /// `port` is the path, or address it is connected to.
+    pub fn register(
+        &self,
+        serial_number: u32,
+        unit_type: &str,
+        firmware: &str,
+        dataset: &str,
+        port: &str,
+    ) -> Result<DeviceRecord> {
+        let mut state = self.state.lock();
+        let now = now_ms();
+
+        let record = state
+            .devices
+            .entry(serial_number)
+            .or_insert_with(|| DeviceRecord {
+                serial_number,
+                first_seen_ms: now,
+                ..Default::default()
+            });
+
+        record.unit_type = unit_type.into();
+        record.firmware = firmware.into();
+        record.dataset = dataset.into();
+        record.port = port.into();
+        record.last_seen_ms = now;
+
+        let record = record.clone();
+
+        if redact::redaction_policy().serials {
+            log::info!("Registered device at {}", record.port);
+        } else {
+            log::info!("Registered device {record}");
+        }
+
+        self.save(&mut state)?;
+
+        Ok(record)
+    }
+
+    /// Marks the device with `serial_number` as seen now.
+    ///
+    /// Unregistered devices are ignored. The update is saved if the registry was not saved for
+    /// [DEVICE_REGISTRY_TOUCH_MS].
+    pub fn touch(&self, serial_number: u32) -> Result<()> {
+        let mut state = self.state.lock();
+        let now = now_ms();
+
+        match state.devices.get_mut(&serial_number) {
+            Some(record) => record.last_seen_ms = now,
+            None => return Ok(()),
+        }
+
+        if now.saturating_sub(state.saved_ms) >= DEVICE_REGISTRY_TOUCH_MS {
+            self.save(&mut state)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the device with `serial_number`, and returns its record, if registered.
+    pub fn remove(&self, serial_number: u32) -> Result<Option<DeviceRecord>> {
+        let mut state = self.state.lock();
+
+        let record = state.devices.remove(&serial_number);
+        if record.is_some() {
+            self.save(&mut state)?;
+        }
+
+        Ok(record)
+    }
+
+    /// Saves pending last-seen updates.
+    pub fn flush(&self) -> Result<()> {
+        self.save(&mut self.state.lock())
+    }
+
+    // Saves the registry.
+    //
+    // The records are replaced atomically, so a crash leaves either the old, or the new registry.
+    fn save(&self, state: &mut RegistryState) -> Result<()> {
+        let mut records = vec![DEVICE_REGISTRY_HEADER.to_string()];
+        records.extend(state.devices.values().map(|record| record.to_line()));
+
+        self.storage
+            .replace(REGISTRY_COLLECTION, records.as_ref())?;
+
+        state.saved_ms = now_ms();
+
+        Ok(())
+    }
+}
+
+// Replaces the separators of the registry file in a text field.
+fn field(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
+
+fn now_ms() -> u64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
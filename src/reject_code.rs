@@ -0,0 +1,314 @@
+//! Typed reject codes, with human-readable reasons, and categories.
+//!
+//! The raw code returned by [last_reject_code](crate::DeviceHandle::last_reject_code) maps to a
+//! [RejectReason], telling why the last note was rejected, and a [RejectCategory] grouping the
+//! reasons by what an operator should look at: the note itself, the note path, or the channel
+//! configuration.
+//!
+//! Query the reason with [reject_reason](crate::DeviceHandle::reject_reason). A
+//! [SinkDispatcher](crate::sink::SinkDispatcher) queries it after every
+//! [Rejected](ssp::Method::Rejected) event, and publishes it to the
+//! [EventSink](crate::sink::EventSink)s.
+
+use std::fmt;
+
+/// Category of a [RejectReason].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RejectCategory {
+    /// The last note was accepted.
+    Accepted,
+    /// The note failed validation: not recognised, or a damaged note.
+    Validation,
+    /// The note path, or a sensor, misbehaved.
+    Mechanical,
+    /// The note channel is inhibited, or the device is disabled.
+    Inhibit,
+    /// The host rejected the note in escrow, or let the escrow time out.
+    Host,
+    /// A fraud attempt was detected.
+    Fraud,
+    /// The code is not known.
+    Unknown,
+}
+
+impl RejectCategory {
+    /// Gets the [RejectCategory] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Accepted => "accepted",
+            Self::Validation => "validation",
+            Self::Mechanical => "mechanical",
+            Self::Inhibit => "inhibit",
+            Self::Host => "host",
+            Self::Fraud => "fraud",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for RejectCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Reason the last note was rejected, as reported by the
+/// [LastRejectCode](ssp::MessageType::LastRejectCode) command.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RejectReason {
+    /// `0x00`: the note was accepted.
+    #[default]
+    NoteAccepted,
+    /// `0x01`: the note length is incorrect.
+    LengthFail,
+    /// `0x02`: the note failed the average validation.
+    AverageFail,
+    /// `0x03`: the note failed the coastline validation.
+    CoastlineFail,
+    /// `0x04`: the note failed the graph validation.
+    GraphFail,
+    /// `0x05`: the note failed the buried validation.
+    BuriedFail,
+    /// `0x06`: the note channel is inhibited.
+    ChannelInhibited,
+    /// `0x07`: a second note was inserted behind the first.
+    SecondNoteDetected,
+    /// `0x08`: the host rejected the note.
+    RejectedByHost,
+    /// `0x09`: the note was recognised in more than one channel.
+    CrossChannelDetected,
+    /// `0x0A`: the rear sensor failed.
+    RearSensorError,
+    /// `0x0B`: the note is too long.
+    NoteTooLong,
+    /// `0x0C`: the host disabled the device.
+    DisabledByHost,
+    /// `0x0D`: the note mechanism is slow.
+    SlowMechanism,
+    /// `0x0E`: the note was pulled back on a string.
+    StrimAttempt,
+    /// `0x0F`: the note was recognised in a fraud channel.
+    FraudChannel,
+    /// `0x10`: no note was detected.
+    NoNotesDetected,
+    /// `0x11`: the peak detection failed.
+    PeakDetectFail,
+    /// `0x12`: the note is twisted.
+    TwistedNote,
+    /// `0x13`: the note was held in escrow too long.
+    EscrowTimeout,
+    /// `0x14`: the barcode scan failed.
+    BarcodeScanFail,
+    /// `0x15`: the cam did not activate.
+    NoCamActivate,
+    /// `0x16`: the first slot sensor failed.
+    SlotFail1,
+    /// `0x17`: the second slot sensor failed.
+    SlotFail2,
+    /// `0x18`: the lens was oversampled.
+    LensOversample,
+    /// `0x19`: the note width detection failed.
+    WidthDetectionFail,
+    /// `0x1A`: the note is too short.
+    ShortNoteDetected,
+    /// `0x1B`: the note is a payout note.
+    PayoutNote,
+    /// `0x1C`: two notes were inserted together.
+    DoubleNoteDetected,
+    /// `0x1D`: the note could not be stacked.
+    UnableToStack,
+    /// Any other code.
+    Unknown(u8),
+}
+
+impl RejectReason {
+    /// Gets the raw reject code.
+    pub const fn code(&self) -> u8 {
+        match self {
+            Self::NoteAccepted => 0x00,
+            Self::LengthFail => 0x01,
+            Self::AverageFail => 0x02,
+            Self::CoastlineFail => 0x03,
+            Self::GraphFail => 0x04,
+            Self::BuriedFail => 0x05,
+            Self::ChannelInhibited => 0x06,
+            Self::SecondNoteDetected => 0x07,
+            Self::RejectedByHost => 0x08,
+            Self::CrossChannelDetected => 0x09,
+            Self::RearSensorError => 0x0a,
+            Self::NoteTooLong => 0x0b,
+            Self::DisabledByHost => 0x0c,
+            Self::SlowMechanism => 0x0d,
+            Self::StrimAttempt => 0x0e,
+            Self::FraudChannel => 0x0f,
+            Self::NoNotesDetected => 0x10,
+            Self::PeakDetectFail => 0x11,
+            Self::TwistedNote => 0x12,
+            Self::EscrowTimeout => 0x13,
+            Self::BarcodeScanFail => 0x14,
+            Self::NoCamActivate => 0x15,
+            Self::SlotFail1 => 0x16,
+            Self::SlotFail2 => 0x17,
+            Self::LensOversample => 0x18,
+            Self::WidthDetectionFail => 0x19,
+            Self::ShortNoteDetected => 0x1a,
+            Self::PayoutNote => 0x1b,
+            Self::DoubleNoteDetected => 0x1c,
+            Self::UnableToStack => 0x1d,
+            Self::Unknown(code) => *code,
+        }
+    }
+
+    /// Creates a [RejectReason] from a raw reject `code`.
+    pub const fn from_code(code: u8) -> Self {
+        match code {
+            0x00 => Self::NoteAccepted,
+            0x01 => Self::LengthFail,
+            0x02 => Self::AverageFail,
+            0x03 => Self::CoastlineFail,
+            0x04 => Self::GraphFail,
+            0x05 => Self::BuriedFail,
+            0x06 => Self::ChannelInhibited,
+            0x07 => Self::SecondNoteDetected,
+            0x08 => Self::RejectedByHost,
+            0x09 => Self::CrossChannelDetected,
+            0x0a => Self::RearSensorError,
+            0x0b => Self::NoteTooLong,
+            0x0c => Self::DisabledByHost,
+            0x0d => Self::SlowMechanism,
+            0x0e => Self::StrimAttempt,
+            0x0f => Self::FraudChannel,
+            0x10 => Self::NoNotesDetected,
+            0x11 => Self::PeakDetectFail,
+            0x12 => Self::TwistedNote,
+            0x13 => Self::EscrowTimeout,
+            0x14 => Self::BarcodeScanFail,
+            0x15 => Self::NoCamActivate,
+            0x16 => Self::SlotFail1,
+            0x17 => Self::SlotFail2,
+            0x18 => Self::LensOversample,
+            0x19 => Self::WidthDetectionFail,
+            0x1a => Self::ShortNoteDetected,
+            0x1b => Self::PayoutNote,
+            0x1c => Self::DoubleNoteDetected,
+            0x1d => Self::UnableToStack,
+            code => Self::Unknown(code),
+        }
+    }
+
+    /// Gets the [RejectCategory] of the reason.
+    pub const fn category(&self) -> RejectCategory {
+        match self {
+            Self::NoteAccepted => RejectCategory::Accepted,
+            Self::LengthFail
+            | Self::AverageFail
+            | Self::CoastlineFail
+            | Self::GraphFail
+            | Self::BuriedFail
+            | Self::CrossChannelDetected
+            | Self::NoteTooLong
+            | Self::NoNotesDetected
+            | Self::PeakDetectFail
+            | Self::TwistedNote
+            | Self::BarcodeScanFail
+            | Self::WidthDetectionFail
+            | Self::ShortNoteDetected
+            | Self::PayoutNote => RejectCategory::Validation,
+            Self::SecondNoteDetected
+            | Self::RearSensorError
+            | Self::SlowMechanism
+            | Self::NoCamActivate
+            | Self::SlotFail1
+            | Self::SlotFail2
+            | Self::LensOversample
+            | Self::DoubleNoteDetected
+            | Self::UnableToStack => RejectCategory::Mechanical,
+            Self::ChannelInhibited | Self::DisabledByHost => RejectCategory::Inhibit,
+            Self::RejectedByHost | Self::EscrowTimeout => RejectCategory::Host,
+            Self::StrimAttempt | Self::FraudChannel => RejectCategory::Fraud,
+            Self::Unknown(_) => RejectCategory::Unknown,
+        }
+    }
+
+    /// Gets a human-readable description of the reason.
+    pub const fn description(&self) -> &'static str {
+        match self {
+            Self::NoteAccepted => "Note accepted",
+            Self::LengthFail => "Note length incorrect",
+            Self::AverageFail => "Invalid note read (average fail)",
+            Self::CoastlineFail => "Invalid note read (coastline fail)",
+            Self::GraphFail => "Invalid note read (graph fail)",
+            Self::BuriedFail => "Invalid note read (buried fail)",
+            Self::ChannelInhibited => "Channel inhibited",
+            Self::SecondNoteDetected => "Second note inserted",
+            Self::RejectedByHost => "Rejected by host",
+            Self::CrossChannelDetected => "Note recognised in more than one channel",
+            Self::RearSensorError => "Rear sensor error",
+            Self::NoteTooLong => "Note too long",
+            Self::DisabledByHost => "Disabled by host",
+            Self::SlowMechanism => "Slow mechanism",
+            Self::StrimAttempt => "Strimming attempt",
+            Self::FraudChannel => "Fraud channel reject",
+            Self::NoNotesDetected => "No notes inserted",
+            Self::PeakDetectFail => "Peak detect fail",
+            Self::TwistedNote => "Twisted note detected",
+            Self::EscrowTimeout => "Escrow time-out",
+            Self::BarcodeScanFail => "Bar code scan fail",
+            Self::NoCamActivate => "No cam activate",
+            Self::SlotFail1 => "Slot fail 1",
+            Self::SlotFail2 => "Slot fail 2",
+            Self::LensOversample => "Lens over-sample",
+            Self::WidthDetectionFail => "Width detect fail",
+            Self::ShortNoteDetected => "Short note detected",
+            Self::PayoutNote => "Note payout",
+            Self::DoubleNoteDetected => "Double note detected",
+            Self::UnableToStack => "Unable to stack note",
+            Self::Unknown(_) => "Unknown reject code",
+        }
+    }
+
+    /// Gets whether the last note was accepted.
+    pub const fn is_accepted(&self) -> bool {
+        matches!(self, Self::NoteAccepted)
+    }
+}
+
+impl From<u8> for RejectReason {
+    fn from(val: u8) -> Self {
+        Self::from_code(val)
+    }
+}
+
+impl From<RejectReason> for u8 {
+    fn from(val: RejectReason) -> Self {
+        val.code()
+    }
+}
+
+impl From<ssp::LastRejectCode> for RejectReason {
+    fn from(val: ssp::LastRejectCode) -> Self {
+        Self::from_code(val.into())
+    }
+}
+
+impl From<&ssp::LastRejectCodeResponse> for RejectReason {
+    fn from(val: &ssp::LastRejectCodeResponse) -> Self {
+        val.reject_code().into()
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({:#04x}, {})",
+            self.description(),
+            self.code(),
+            self.category()
+        )
+    }
+}
@@ -0,0 +1,316 @@
+//! Append-only history of rejected notes, and reject codes.
+//!
+//! Every `Rejected` event from the background polling routine, and every result of
+//! [last_reject_code](crate::DeviceHandle::last_reject_code) is recorded with the device serial
+//! number, and a timestamp. [RejectStats] over a time window help diagnosing a site with a dirty
+//! sensor, e.g. a rising share of `Invalid note read`, or repeated counterfeit attempts, e.g.
+//! `Fraud channel reject`.
+//!
+//! Reject events do not carry a reason. Query [last_reject_code](crate::DeviceHandle::last_reject_code)
+//! after a reject to record the reason as well.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::reject_code::RejectReason;
+
+/// Environment variable with the path of the reject history file.
+pub const REJECT_HISTORY_ENV_PATH: &str = "SSP_REJECT_HISTORY";
+
+/// Source of a [RejectRecord].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RejectSource {
+    /// `Rejected` event reported by a poll, without a reject code.
+    Event,
+    /// Result of a `LastRejectCode` command.
+    Query,
+}
+
+impl RejectSource {
+    /// Gets the [RejectSource] name.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Event => "event",
+            Self::Query => "query",
+        }
+    }
+
+    /// Parses a [RejectSource] from its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "event" => Some(Self::Event),
+            "query" => Some(Self::Query),
+            _ => None,
+        }
+    }
+}
+
+/// Single entry in the reject history.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RejectRecord {
+    /// Time of the reject, or query, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Serial number of the device, zero if unknown.
+    pub serial_number: u32,
+    /// Source of the record.
+    pub source: RejectSource,
+    /// Reject code returned by the device, `None` for reject events.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub code: Option<u8>,
+}
+
+impl RejectRecord {
+    /// Gets the reject reason, if known.
+    pub fn reason(&self) -> Option<ssp::LastRejectCode> {
+        self.code.map(ssp::LastRejectCode::from)
+    }
+
+    /// Gets the typed [RejectReason], with its category, if known.
+    pub fn reject_reason(&self) -> Option<RejectReason> {
+        self.code.map(RejectReason::from_code)
+    }
+
+    fn to_line(self) -> String {
+        let code = self.code.map(|c| c.to_string()).unwrap_or("-".into());
+
+        format!(
+            "{}\t{}\t{}\t{code}\n",
+            self.timestamp_ms,
+            self.serial_number,
+            self.source.as_str(),
+        )
+    }
+
+    fn from_line(line: &str) -> Result<Self> {
+        let invalid = || ssp::Error::Io(format!("invalid reject record: {line}"));
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [timestamp_ms, serial_number, source, code] = fields.as_slice() else {
+            return Err(invalid());
+        };
+
+        Ok(Self {
+            timestamp_ms: timestamp_ms.parse().map_err(|_| invalid())?,
+            serial_number: serial_number.parse().map_err(|_| invalid())?,
+            source: RejectSource::from_name(source).ok_or_else(invalid)?,
+            code: match *code {
+                "-" => None,
+                code => Some(code.parse().map_err(|_| invalid())?),
+            },
+        })
+    }
+}
+
+/// Number of queries returning a single reject code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RejectCodeCount {
+    /// Reject code returned by the device.
+    pub code: u8,
+    /// Description of the reject code.
+    pub reason: String,
+    /// Number of queries returning the code.
+    pub count: u64,
+}
+
+/// Reject statistics over a time window.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RejectStats {
+    /// Start of the window, in milliseconds since the Unix epoch, `None` for all records.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub since_ms: Option<u64>,
+    /// End of the window, in milliseconds since the Unix epoch, `None` for all records.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub until_ms: Option<u64>,
+    /// Number of `Rejected` events.
+    pub rejects: u64,
+    /// Number of reject code queries.
+    pub queries: u64,
+    /// Number of queries per reject code, most frequent first.
+    pub codes: Vec<RejectCodeCount>,
+}
+
+impl RejectStats {
+    /// Gets the number of queries returning the reject `code`.
+    pub fn count(&self, code: ssp::LastRejectCode) -> u64 {
+        let code = u8::from(code);
+
+        self.codes
+            .iter()
+            .filter(|c| c.code == code)
+            .map(|c| c.count)
+            .sum()
+    }
+}
+
+/// Append-only reject history file, with one record per line.
+///
+/// Cloned histories share the same file.
+#[derive(Clone, Debug)]
+pub struct RejectHistory {
+    path: PathBuf,
+    file: Arc<Mutex<fs::File>>,
+}
+
+impl RejectHistory {
+    /// Opens the reject history at `path`, creating it if it does not exist.
+    ///
+    /// Discards an incomplete record left by a crash.
+    pub fn open(path: &str) -> Result<Self> {
+        let path = PathBuf::from(path);
+
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        let complete_len = contents.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        if complete_len < contents.len() {
+            log::warn!(
+                "Discarding incomplete reject record at the end of {}",
+                path.display()
+            );
+
+            file.set_len(complete_len as u64)?;
+        }
+
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Opens the reject history at the path set in the [REJECT_HISTORY_ENV_PATH] environment
+    /// variable.
+    ///
+    /// Returns `Ok(None)` if the variable is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(REJECT_HISTORY_ENV_PATH) {
+            Ok(path) => Self::open(path.as_str()).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Gets the path of the reject history file.
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap_or_default()
+    }
+
+    /// Records a `Rejected` event.
+    pub fn record_event(&self, serial_number: u32) -> Result<RejectRecord> {
+        self.record(serial_number, RejectSource::Event, None)
+    }
+
+    /// Records the reject `code` returned from a `LastRejectCode` query.
+    pub fn record_code(
+        &self,
+        serial_number: u32,
+        code: ssp::LastRejectCode,
+    ) -> Result<RejectRecord> {
+        self.record(serial_number, RejectSource::Query, Some(code.into()))
+    }
+
+    /// Gets all records, oldest first.
+    pub fn records(&self) -> Result<Vec<RejectRecord>> {
+        // hold the lock, so a concurrent append is not read half-written
+        let _file = self.file.lock();
+
+        fs::read_to_string(&self.path)?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(RejectRecord::from_line)
+            .collect()
+    }
+
+    /// Gets the reject statistics of the records at, or after `since_ms`, and before
+    /// `until_ms`.
+    pub fn stats(&self, since_ms: Option<u64>, until_ms: Option<u64>) -> Result<RejectStats> {
+        let mut stats = RejectStats {
+            since_ms,
+            until_ms,
+            ..Default::default()
+        };
+        let mut codes: BTreeMap<u8, u64> = BTreeMap::new();
+
+        for record in self.records()?.iter().filter(|r| {
+            since_ms.is_none_or(|since| r.timestamp_ms >= since)
+                && until_ms.is_none_or(|until| r.timestamp_ms < until)
+        }) {
+            match (record.source, record.code) {
+                (RejectSource::Event, _) => stats.rejects += 1,
+                (RejectSource::Query, code) => {
+                    stats.queries += 1;
+                    if let Some(code) = code {
+                        *codes.entry(code).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        stats.codes = codes
+            .into_iter()
+            .map(|(code, count)| RejectCodeCount {
+                code,
+                reason: <&str>::from(ssp::LastRejectCode::from(code)).into(),
+                count,
+            })
+            .collect();
+        // stable sort keeps equal counts by increasing code
+        stats.codes.sort_by_key(|c| std::cmp::Reverse(c.count));
+
+        Ok(stats)
+    }
+
+    /// Gets the reject statistics of the last `window`.
+    pub fn recent_stats(&self, window: time::Duration) -> Result<RejectStats> {
+        let now_ms = now_ms();
+        self.stats(Some(now_ms.saturating_sub(window.as_millis() as u64)), None)
+    }
+
+    fn record(
+        &self,
+        serial_number: u32,
+        source: RejectSource,
+        code: Option<u8>,
+    ) -> Result<RejectRecord> {
+        let record = RejectRecord {
+            timestamp_ms: now_ms(),
+            serial_number,
+            source,
+            code,
+        };
+
+        let mut file = self.file.lock();
+        file.write_all(record.to_line().as_bytes())?;
+        file.sync_data()?;
+
+        Ok(record)
+    }
+}
+
+fn now_ms() -> u64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,266 @@
+//! Retry policies for sending commands to the device.
+//!
+//! When writing a command to the transport fails, the command is retried according to the
+//! [RetryPolicy] of its [CommandClass]: up to a maximum number of attempts, with an exponential
+//! backoff, and random jitter between attempts. When the attempts are exhausted, the command fails
+//! with a [SerialPort](ssp::Error::SerialPort) error.
+//!
+//! Every class starts with the [default](RetryPolicy::new) policy, configurable with
+//! [set_retry_policy].
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::time;
+
+use parking_lot::Mutex;
+
+/// Default maximum number of attempts to send a command.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+/// Default backoff before the first retry (milliseconds).
+pub const DEFAULT_INITIAL_BACKOFF_MS: u64 = 100;
+/// Default maximum backoff between retries (milliseconds).
+pub const DEFAULT_MAX_BACKOFF_MS: u64 = 1_000;
+
+static RETRY_POLICIES: Mutex<[RetryPolicy; CommandClass::ALL.len()]> =
+    Mutex::new([RetryPolicy::new(); CommandClass::ALL.len()]);
+
+/// Class of commands sharing a [RetryPolicy].
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CommandClass {
+    /// Polls, and event acknowledgements.
+    Poll,
+    /// Commands reading information from the device, e.g. the setup, or serial number.
+    Query,
+    /// Commands changing the state, or configuration of the device.
+    #[default]
+    Control,
+    /// Commands moving cash, e.g. payouts, and emptying the device.
+    Payout,
+    /// Key negotiation, and encryption management commands.
+    Encryption,
+    /// Firmware programming commands.
+    Firmware,
+}
+
+impl CommandClass {
+    /// All command classes.
+    pub const ALL: [Self; 6] = [
+        Self::Poll,
+        Self::Query,
+        Self::Control,
+        Self::Payout,
+        Self::Encryption,
+        Self::Firmware,
+    ];
+
+    /// Gets the class of a command.
+    pub const fn from_message_type(command: ssp::MessageType) -> Self {
+        use ssp::MessageType as M;
+
+        match command {
+            M::Poll | M::PollWithAck | M::EventAck => Self::Poll,
+            M::SetupRequest
+            | M::SerialNumber
+            | M::UnitData
+            | M::ChannelValueData
+            | M::LastRejectCode
+            | M::FirmwareVersion
+            | M::DatasetVersion
+            | M::GetBarcodeReaderConfiguration
+            | M::GetBarcodeInhibit
+            | M::GetBarcodeData => Self::Query,
+            M::PayoutByDenomination
+            | M::EnablePayout
+            | M::DisablePayout
+            | M::Empty
+            | M::SmartEmpty => Self::Payout,
+            M::SetGenerator
+            | M::SetModulus
+            | M::RequestKeyExchange
+            | M::SetEncryptionKey
+            | M::EncryptionReset
+            | M::Encrypted => Self::Encryption,
+            M::ProgramFirmware => Self::Firmware,
+            _ => Self::Control,
+        }
+    }
+
+    /// Gets the name of the class.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Poll => "poll",
+            Self::Query => "query",
+            Self::Control => "control",
+            Self::Payout => "payout",
+            Self::Encryption => "encryption",
+            Self::Firmware => "firmware",
+        }
+    }
+}
+
+impl fmt::Display for CommandClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Policy for retrying a command after a failed write.
+///
+/// The backoff before retry `n` is `initial_backoff * multiplier^(n - 1)`, capped at the
+/// `max_backoff`, plus up to `jitter_percent` percent of random jitter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: time::Duration,
+    max_backoff: time::Duration,
+    multiplier: u32,
+    jitter_percent: u8,
+}
+
+impl RetryPolicy {
+    /// Creates a new [RetryPolicy] with the default settings.
+    pub const fn new() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: time::Duration::from_millis(DEFAULT_INITIAL_BACKOFF_MS),
+            max_backoff: time::Duration::from_millis(DEFAULT_MAX_BACKOFF_MS),
+            multiplier: 2,
+            jitter_percent: 20,
+        }
+    }
+
+    /// Creates a new [RetryPolicy] that never retries.
+    pub const fn no_retry() -> Self {
+        Self::new().with_max_attempts(1)
+    }
+
+    /// Builder function that sets the maximum number of attempts, including the first.
+    ///
+    /// A command is attempted at least once.
+    pub const fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = if max_attempts == 0 { 1 } else { max_attempts };
+        self
+    }
+
+    /// Builder function that sets the backoff before the first retry, and the maximum backoff.
+    pub const fn with_backoff(mut self, initial: time::Duration, max: time::Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Builder function that sets the factor the backoff grows by after every retry.
+    pub const fn with_multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Builder function that sets the maximum random jitter added to the backoff, in percent.
+    pub const fn with_jitter(mut self, jitter_percent: u8) -> Self {
+        self.jitter_percent = jitter_percent;
+        self
+    }
+
+    /// Gets the maximum number of attempts, including the first.
+    pub const fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Gets the backoff before the first retry.
+    pub const fn initial_backoff(&self) -> time::Duration {
+        self.initial_backoff
+    }
+
+    /// Gets the maximum backoff between retries.
+    pub const fn max_backoff(&self) -> time::Duration {
+        self.max_backoff
+    }
+
+    /// Gets the factor the backoff grows by after every retry.
+    pub const fn multiplier(&self) -> u32 {
+        self.multiplier
+    }
+
+    /// Gets the maximum random jitter added to the backoff, in percent.
+    pub const fn jitter_percent(&self) -> u8 {
+        self.jitter_percent
+    }
+
+    /// Gets whether another attempt is allowed after `attempts` failed attempts.
+    pub const fn should_retry(&self, attempts: u32) -> bool {
+        attempts < self.max_attempts
+    }
+
+    /// Gets the backoff before retry number `retry`, starting at one, without jitter.
+    pub fn base_backoff(&self, retry: u32) -> time::Duration {
+        let factor = self
+            .multiplier
+            .checked_pow(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+
+        self.initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+
+    /// Gets the backoff before retry number `retry`, starting at one, including random jitter.
+    pub fn backoff(&self, retry: u32) -> time::Duration {
+        let base = self.base_backoff(retry);
+
+        if self.jitter_percent == 0 {
+            return base;
+        }
+
+        let max_jitter = base.as_millis() as u64 * self.jitter_percent as u64 / 100;
+        let jitter = random_u64() % max_jitter.saturating_add(1);
+
+        base + time::Duration::from_millis(jitter)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} attempts, backoff {:?}..{:?} x{}, jitter {}%",
+            self.max_attempts,
+            self.initial_backoff,
+            self.max_backoff,
+            self.multiplier,
+            self.jitter_percent
+        )
+    }
+}
+
+/// Gets the [RetryPolicy] of a command `class`.
+pub fn retry_policy(class: CommandClass) -> RetryPolicy {
+    RETRY_POLICIES.lock()[class as usize]
+}
+
+/// Sets the [RetryPolicy] of a command `class`.
+pub fn set_retry_policy(class: CommandClass, policy: RetryPolicy) {
+    log::debug!("Retry policy for {class} commands: {policy}");
+    RETRY_POLICIES.lock()[class as usize] = policy;
+}
+
+/// Resets the [RetryPolicy] of every command class to the default.
+pub fn reset_retry_policies() {
+    *RETRY_POLICIES.lock() = [RetryPolicy::new(); CommandClass::ALL.len()];
+}
+
+// Gets a random number for jitter, seeded by the standard library.
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
@@ -0,0 +1,18 @@
+//! Seeded generator shared by the fault injection, and chaos transports.
+
+// Draws a sample from the generator `state`, and returns whether it falls under the `rate`.
+pub(crate) fn chance(state: &mut u64, rate: f64) -> bool {
+    // 53 random bits, uniform in [0, 1)
+    let sample = (next_u64(state) >> 11) as f64 / (1u64 << 53) as f64;
+    sample < rate
+}
+
+// SplitMix64, small, and good enough for spreading faults
+pub(crate) fn next_u64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
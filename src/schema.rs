@@ -0,0 +1,513 @@
+//! JSON Schemas for the messages exchanged with the network frontends.
+//!
+//! The schemas follow the [JSON Schema 2020-12](https://json-schema.org/draft/2020-12/schema)
+//! dialect, so non-Rust clients can generate typed bindings with standard tooling.
+//!
+//! All documents share the same `$defs`, and differ in the root schema:
+//!
+//! - [command_schema]: JSON-RPC command requests sent by clients
+//! - [response_schema]: JSON-RPC responses to commands
+//! - [event_schema]: JSON-RPC notifications for device events pushed to clients
+//! - [schema]: all of the above, plus the [Event](ssp::Event) objects published by event sinks
+
+use serde_json::{json, Map, Value};
+
+use crate::lease;
+
+/// JSON Schema dialect used for all documents.
+pub const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Commands accepted by the frontends: `(method, params definition, result payload)`.
+///
+/// A `None` params definition means the command takes no parameters, a `None` result payload
+/// means the command returns a `null` result.
+const COMMANDS: [(ssp::Method, Option<&str>, Option<&str>); 9] = [
+    (
+        ssp::Method::Accept,
+        Some("EnableParams"),
+        Some("EnableEvent"),
+    ),
+    (ssp::Method::Stop, None, Some("DisableEvent")),
+    (
+        ssp::Method::Enable,
+        Some("EnableParams"),
+        Some("EnableEvent"),
+    ),
+    (ssp::Method::Disable, None, Some("DisableEvent")),
+    (ssp::Method::Reject, None, Some("RejectEvent")),
+    (ssp::Method::Stack, None, Some("StackEvent")),
+    (ssp::Method::Status, None, Some("StatusEvent")),
+    (ssp::Method::Reset, None, Some("ResetEvent")),
+    (ssp::Method::Dispense, Some("PayoutDenominationList"), None),
+];
+
+/// Events pushed by the frontends: `(method, payload name)`.
+const EVENTS: [(ssp::Method, &str); 16] = [
+    (ssp::Method::CashboxRemoved, "CashboxRemovedEvent"),
+    (ssp::Method::CashboxReplaced, "CashboxReplacedEvent"),
+    (ssp::Method::Disabled, "DisabledEvent"),
+    (ssp::Method::FraudAttempt, "FraudAttemptEvent"),
+    (
+        ssp::Method::NoteClearedFromFront,
+        "NoteClearedFromFrontEvent",
+    ),
+    (
+        ssp::Method::NoteClearedIntoCashbox,
+        "NoteClearedIntoCashboxEvent",
+    ),
+    (ssp::Method::NoteCredit, "NoteCreditEvent"),
+    (ssp::Method::Read, "ReadEvent"),
+    (ssp::Method::Rejected, "RejectedEvent"),
+    (ssp::Method::Rejecting, "RejectingEvent"),
+    (ssp::Method::Reset, "ResetEvent"),
+    (ssp::Method::Stacked, "StackedEvent"),
+    (ssp::Method::StackerFull, "StackerFullEvent"),
+    (ssp::Method::Stacking, "StackingEvent"),
+    (ssp::Method::UnsafeJam, "UnsafeJamEvent"),
+    // device errors, and eSSP session re-keys
+    (ssp::Method::Fail, "Error"),
+];
+
+/// Payload variants of [EventPayload](ssp::EventPayload): `(payload name, definition)`.
+const PAYLOADS: [(&str, Option<&str>); 22] = [
+    ("DisableEvent", None),
+    ("DispenseEvent", Some("PayoutDenominationList")),
+    ("EnableEvent", Some("EnableParams")),
+    ("RejectEvent", None),
+    ("StackEvent", Some("ValueParams")),
+    ("StatusEvent", Some("StatusParams")),
+    ("CashboxRemovedEvent", None),
+    ("CashboxReplacedEvent", None),
+    ("DisabledEvent", None),
+    ("FraudAttemptEvent", Some("ValueParams")),
+    ("NoteClearedFromFrontEvent", Some("ValueParams")),
+    ("NoteClearedIntoCashboxEvent", Some("ValueParams")),
+    ("NoteCreditEvent", Some("ValueParams")),
+    ("ReadEvent", Some("ValueParams")),
+    ("RejectedEvent", None),
+    ("RejectingEvent", None),
+    ("ResetEvent", None),
+    ("StackedEvent", None),
+    ("StackerFullEvent", None),
+    ("StackingEvent", None),
+    ("UnsafeJamEvent", None),
+    ("Error", Some("Error")),
+];
+
+/// Named values of [ResponseStatus](ssp::ResponseStatus).
+const RESPONSE_STATUSES: [&str; 23] = [
+    "NoteClearedFromFront",
+    "NoteClearedIntoCashbox",
+    "CashboxRemoved",
+    "CashboxReplaced",
+    "FraudAttempt",
+    "StackerFull",
+    "Disabled",
+    "UnsafeJam",
+    "Stacked",
+    "Stacking",
+    "Rejected",
+    "Rejecting",
+    "NoteCredit",
+    "Read",
+    "Ok",
+    "DeviceReset",
+    "CommandNotKnown",
+    "WrongNumberParameters",
+    "ParameterOutOfRange",
+    "CommandCannotBeProcessed",
+    "Fail",
+    "KeyNotSet",
+    "ChannelDisable",
+];
+
+/// Gets the schema document for all frontend messages, and [Event](ssp::Event) objects.
+pub fn schema() -> Value {
+    document(
+        "SSP server messages",
+        json!({
+            "oneOf": [
+                def_ref("CommandRequest"),
+                def_ref("CommandResponse"),
+                def_ref("EventNotification"),
+                def_ref("Event"),
+            ]
+        }),
+    )
+}
+
+/// Gets the schema document for JSON-RPC command requests.
+pub fn command_schema() -> Value {
+    document("SSP server command request", def_ref("CommandRequest"))
+}
+
+/// Gets the schema document for JSON-RPC command responses.
+pub fn response_schema() -> Value {
+    document("SSP server command response", def_ref("CommandResponse"))
+}
+
+/// Gets the schema document for JSON-RPC event notifications.
+pub fn event_schema() -> Value {
+    document(
+        "SSP server event notification",
+        def_ref("EventNotification"),
+    )
+}
+
+/// Gets the schema document by name: `all`, `commands`, `responses`, or `events`.
+pub fn schema_by_name(name: &str) -> Option<Value> {
+    match name {
+        "all" => Some(schema()),
+        "commands" => Some(command_schema()),
+        "responses" => Some(response_schema()),
+        "events" => Some(event_schema()),
+        _ => None,
+    }
+}
+
+fn document(title: &str, root: Value) -> Value {
+    let mut doc = Map::new();
+
+    doc.insert("$schema".into(), SCHEMA_DIALECT.into());
+    doc.insert("title".into(), title.into());
+
+    if let Value::Object(root) = root {
+        doc.extend(root);
+    }
+
+    doc.insert("$defs".into(), Value::Object(defs()));
+
+    Value::Object(doc)
+}
+
+fn def_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/$defs/{name}") })
+}
+
+fn params_schema(def: Option<&str>) -> Value {
+    def.map(def_ref).unwrap_or(json!({ "type": "null" }))
+}
+
+fn jsonrpc_id() -> Value {
+    json!({ "type": ["integer", "string", "null"] })
+}
+
+fn defs() -> Map<String, Value> {
+    let mut defs = Map::new();
+
+    defs.insert(
+        "ChannelValue".into(),
+        json!({
+            "description": "Note value in the smallest unit of the currency.",
+            "type": "integer",
+            "minimum": 0,
+            "maximum": u32::MAX,
+        }),
+    );
+    defs.insert(
+        "CountryCode".into(),
+        json!({
+            "description": "ISO 4217 currency code, e.g. `EUR`.",
+            "type": "string",
+            "pattern": "^[A-Z]{3}$",
+        }),
+    );
+    defs.insert(
+        "ProtocolVersion".into(),
+        json!({
+            "enum": ["One", "Two", "Three", "Four", "Five", "Six", "Seven", "Eight", "Reserved"],
+        }),
+    );
+    defs.insert(
+        "ResponseStatus".into(),
+        json!({
+            "oneOf": [
+                { "enum": RESPONSE_STATUSES },
+                {
+                    "type": "object",
+                    "properties": { "Reserved": { "type": "integer", "minimum": 0, "maximum": 255 } },
+                    "required": ["Reserved"],
+                    "additionalProperties": false,
+                },
+            ]
+        }),
+    );
+    defs.insert(
+        "PayoutDenomination".into(),
+        json!({
+            "type": "object",
+            "properties": {
+                "number": { "description": "Number of notes.", "type": "integer", "minimum": 0, "maximum": u16::MAX },
+                "value": def_ref("ChannelValue"),
+                "currency": def_ref("CountryCode"),
+            },
+            "required": ["number", "value", "currency"],
+        }),
+    );
+    defs.insert(
+        "PayoutDenominationList".into(),
+        json!({
+            "type": "object",
+            "properties": {
+                "denominations": { "type": "array", "items": def_ref("PayoutDenomination") },
+            },
+            "required": ["denominations"],
+        }),
+    );
+    defs.insert(
+        "DeviceStatus".into(),
+        json!({
+            "type": "object",
+            "properties": {
+                "status": def_ref("ResponseStatus"),
+                "unit_type": { "type": "integer" },
+                "firmware_version": { "type": "integer" },
+                "country_code": def_ref("CountryCode"),
+                "value_multiplier": { "type": "integer" },
+                "protocol_version": def_ref("ProtocolVersion"),
+                "dataset_version": { "type": "string" },
+                "cashbox_attached": { "type": "boolean" },
+            },
+            "required": [
+                "status",
+                "unit_type",
+                "firmware_version",
+                "country_code",
+                "value_multiplier",
+                "protocol_version",
+                "dataset_version",
+                "cashbox_attached",
+            ],
+        }),
+    );
+    defs.insert(
+        "Error".into(),
+        json!({
+            "description": "Library error, encoded as a single-key object, e.g. `{\"Io\": \"...\"}`.",
+            "type": "object",
+            "minProperties": 1,
+            "maxProperties": 1,
+        }),
+    );
+    defs.insert(
+        "ValueParams".into(),
+        json!({
+            "type": "object",
+            "properties": { "value": def_ref("ChannelValue") },
+            "required": ["value"],
+        }),
+    );
+    defs.insert(
+        "EnableParams".into(),
+        json!({
+            "type": "object",
+            "properties": { "protocol": def_ref("ProtocolVersion") },
+        }),
+    );
+    defs.insert(
+        "StatusParams".into(),
+        json!({
+            "type": "object",
+            "properties": { "details": def_ref("DeviceStatus") },
+            "required": ["details"],
+        }),
+    );
+    defs.insert("Method".into(), json!({ "enum": methods() }));
+    defs.insert(
+        "EventPayload".into(),
+        json!({
+            "oneOf": PAYLOADS
+                .iter()
+                .map(|&(name, _)| payload_schema(name))
+                .collect::<Vec<Value>>()
+        }),
+    );
+    defs.insert(
+        "Event".into(),
+        json!({
+            "description": "Device event, as published by the event sinks.",
+            "type": "object",
+            "properties": {
+                "method": def_ref("Method"),
+                "payload": def_ref("EventPayload"),
+            },
+            "required": ["method", "payload"],
+        }),
+    );
+    defs.insert(
+        "CommandRequest".into(),
+        json!({
+            "oneOf": COMMANDS
+                .iter()
+                .map(|&(method, params, _)| {
+                    jsonrpc_request(method.to_str(), params_schema(params))
+                })
+                .chain([
+                    jsonrpc_request(lease::LEASE_CLAIM_METHOD, def_ref("LeaseParams")),
+                    jsonrpc_request(lease::LEASE_RELEASE_METHOD, json!({ "type": "null" })),
+                ])
+                .collect::<Vec<Value>>()
+        }),
+    );
+    defs.insert(
+        "CommandResponse".into(),
+        json!({
+            "type": "object",
+            "properties": {
+                "jsonrpc": { "const": "2.0" },
+                "id": jsonrpc_id(),
+                "result": {
+                    "oneOf": command_results()
+                        .into_iter()
+                        .map(payload_schema)
+                        .chain([def_ref("LeaseReply"), json!({ "type": "null" })])
+                        .collect::<Vec<Value>>()
+                },
+                "error": {
+                    "oneOf": [
+                        { "type": "null" },
+                        {
+                            "type": "object",
+                            "properties": {
+                                "code": { "type": "integer" },
+                                "message": { "type": "string" },
+                                "data": {},
+                            },
+                            "required": ["code", "message"],
+                        },
+                    ]
+                },
+            },
+            "required": ["jsonrpc"],
+        }),
+    );
+    defs.insert(
+        "EventNotification".into(),
+        json!({
+            "description": "Device event pushed to clients, with the full event as parameters.",
+            "oneOf": EVENTS
+                .iter()
+                .map(|&(method, payload)| {
+                    jsonrpc_request(
+                        method.to_str(),
+                        json!({
+                            "type": "object",
+                            "properties": {
+                                "method": { "const": method.to_str() },
+                                "payload": payload_schema(payload),
+                            },
+                            "required": ["method", "payload"],
+                        }),
+                    )
+                })
+                .collect::<Vec<Value>>()
+        }),
+    );
+
+    defs.insert(
+        "LeaseParams".into(),
+        json!({
+            "description": "Parameters for claiming, or renewing the exclusive lease.",
+            "type": ["object", "null"],
+            "properties": { "ttl_ms": { "type": "integer", "minimum": 0 } },
+        }),
+    );
+    defs.insert(
+        "LeaseReply".into(),
+        json!({
+            "description": "Active lease, the secret token proves holding it.",
+            "type": "object",
+            "properties": {
+                "token": { "type": "string", "pattern": "^[0-9a-f]{32}$" },
+                "expires_ms": { "type": "integer", "minimum": 0 },
+            },
+            "required": ["token", "expires_ms"],
+        }),
+    );
+    defs.insert(
+        "HttpEnableRequest".into(),
+        json!({
+            "description": "Request body for the HTTP `POST /enable` and `POST /disable` endpoints.",
+            "type": "object",
+            "properties": { "payout": { "type": "boolean", "default": false } },
+        }),
+    );
+    defs.insert(
+        "HttpPayoutRequest".into(),
+        json!({
+            "description": "Request body for the HTTP `POST /payout` endpoint.",
+            "$ref": "#/$defs/PayoutDenominationList",
+        }),
+    );
+    defs.insert(
+        "HttpCommandReply".into(),
+        json!({
+            "description": "Response body for HTTP command endpoints.",
+            "type": "object",
+            "properties": { "response_status": { "type": "string" } },
+            "required": ["response_status"],
+        }),
+    );
+
+    defs
+}
+
+// Externally tagged payload, e.g. `{"NoteCreditEvent": {"value": 500}}`.
+fn payload_schema(name: &str) -> Value {
+    let def = PAYLOADS
+        .iter()
+        .find(|&&(payload, _)| payload == name)
+        .and_then(|&(_, def)| def);
+
+    json!({
+        "type": "object",
+        "properties": { name: params_schema(def) },
+        "required": [name],
+        "additionalProperties": false,
+    })
+}
+
+// Distinct result payloads of all commands.
+fn command_results() -> Vec<&'static str> {
+    let mut results: Vec<&str> = Vec::new();
+
+    for name in COMMANDS.iter().filter_map(|&(_, _, result)| result) {
+        if !results.contains(&name) {
+            results.push(name);
+        }
+    }
+
+    results
+}
+
+// Distinct method names of all commands and events.
+fn methods() -> Vec<&'static str> {
+    let mut methods: Vec<&str> = Vec::new();
+
+    let all = COMMANDS
+        .iter()
+        .map(|&(method, _, _)| method)
+        .chain(EVENTS.iter().map(|&(method, _)| method))
+        .chain([ssp::Method::Fail]);
+
+    for method in all.map(|m| m.to_str()) {
+        if !methods.contains(&method) {
+            methods.push(method);
+        }
+    }
+
+    methods
+}
+
+fn jsonrpc_request(method: &str, params: Value) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "jsonrpc": { "const": "2.0" },
+            "id": jsonrpc_id(),
+            "method": { "const": method },
+            "params": params,
+        },
+        "required": ["jsonrpc", "method"],
+    })
+}
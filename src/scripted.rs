@@ -0,0 +1,311 @@
+//! Scripted transport for testing the exact wire behavior of a [DeviceHandle](crate::DeviceHandle).
+//!
+//! Tests enqueue the commands they expect the host to write, each with a canned response, on a
+//! [Script]. A [ScriptedTransport] checks every written frame against the next step, and answers
+//! with its response, without the state machine of a device, or an emulator:
+//!
+//! ```no_run
+//! # fn main() -> ssp::Result<()> {
+//! use ssp_server::scripted::{frame, ScriptedTransport};
+//! use ssp_server::DeviceHandle;
+//!
+//! let transport = ScriptedTransport::new();
+//! let script = transport.script().clone();
+//!
+//! // Sync is sent with the sequence flag set, and answered with OK
+//! script.expect_frame(&frame(0x80, &[0x11])?, &frame(0x80, &[0xf0])?);
+//! // match the command data, answer with the same sequence ID
+//! script.expect(&[0x07], &[0xf0]);
+//!
+//! let handle = DeviceHandle::from_transport(transport)?;
+//! handle.sync()?;
+//! handle.poll()?;
+//!
+//! script.finish()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use ssp::{len, Result};
+
+use crate::framing;
+use crate::transport::Transport;
+
+/// Command expected by a [Script] step.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expected {
+    /// Exact stuffed frame bytes.
+    Frame(Vec<u8>),
+    /// Command data of the unstuffed frame, with any sequence ID.
+    Data(Vec<u8>),
+    /// Any command.
+    Any,
+}
+
+impl Expected {
+    fn matches(&self, written: &[u8]) -> bool {
+        match self {
+            Self::Frame(frame) => frame.as_slice() == written,
+            Self::Data(data) => command_data(written)
+                .map(|d| d == data.as_slice())
+                .unwrap_or(false),
+            Self::Any => true,
+        }
+    }
+}
+
+/// Response sent by a [Script] step.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reply {
+    /// Exact stuffed bytes, e.g. a corrupted, or partial frame.
+    Bytes(Vec<u8>),
+    /// Response data, framed with the sequence ID of the command.
+    Data(Vec<u8>),
+    /// No response, like a device that did not receive the command.
+    Silent,
+}
+
+/// Single scripted exchange.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    /// Expected command.
+    pub expected: Expected,
+    /// Response to the command.
+    pub reply: Reply,
+}
+
+#[derive(Debug, Default)]
+struct ScriptState {
+    steps: VecDeque<Step>,
+    written: Vec<Vec<u8>>,
+    consumed: usize,
+    diverged: Option<String>,
+}
+
+/// Shared list of the exchanges expected by a [ScriptedTransport].
+///
+/// Steps can be enqueued, and checked after the transport is moved into a
+/// [DeviceHandle](crate::DeviceHandle).
+#[derive(Clone, Debug, Default)]
+pub struct Script(Arc<Mutex<ScriptState>>);
+
+impl Script {
+    /// Creates a new, empty [Script].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enqueues a [Step].
+    pub fn push(&self, step: Step) -> &Self {
+        self.0.lock().steps.push_back(step);
+        self
+    }
+
+    /// Expects a command with the `data`, and answers with the `reply` data, framed with the
+    /// sequence ID of the command.
+    pub fn expect(&self, data: &[u8], reply: &[u8]) -> &Self {
+        self.push(Step {
+            expected: Expected::Data(data.into()),
+            reply: Reply::Data(reply.into()),
+        })
+    }
+
+    /// Expects the exact stuffed `frame`, and answers with the exact `reply` bytes.
+    pub fn expect_frame(&self, frame: &[u8], reply: &[u8]) -> &Self {
+        self.push(Step {
+            expected: Expected::Frame(frame.into()),
+            reply: Reply::Bytes(reply.into()),
+        })
+    }
+
+    /// Expects a command with the `data`, and does not answer.
+    pub fn expect_silence(&self, data: &[u8]) -> &Self {
+        self.push(Step {
+            expected: Expected::Data(data.into()),
+            reply: Reply::Silent,
+        })
+    }
+
+    /// Gets the number of steps left.
+    pub fn pending(&self) -> usize {
+        self.0.lock().steps.len()
+    }
+
+    /// Gets the number of consumed steps.
+    pub fn consumed(&self) -> usize {
+        self.0.lock().consumed
+    }
+
+    /// Gets a copy of every frame written by the host, in order.
+    pub fn written(&self) -> Vec<Vec<u8>> {
+        self.0.lock().written.clone()
+    }
+
+    /// Gets where the host diverged from the script, if it did.
+    pub fn diverged(&self) -> Option<String> {
+        self.0.lock().diverged.clone()
+    }
+
+    /// Checks that the host wrote every expected command, and nothing else.
+    pub fn finish(&self) -> Result<()> {
+        let state = self.0.lock();
+
+        if let Some(reason) = state.diverged.as_ref() {
+            Err(ssp::Error::Io(format!("script diverged: {reason}")))
+        } else if !state.steps.is_empty() {
+            Err(ssp::Error::Io(format!(
+                "script unfinished: step {} of {} not written, expected {:02x?}",
+                state.consumed,
+                state.consumed + state.steps.len(),
+                state.steps[0].expected
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Consumes the next step for the `written` frame, and returns the bytes to answer with.
+    fn exchange(&self, written: &[u8]) -> Option<Vec<u8>> {
+        let mut state = self.0.lock();
+        state.written.push(written.into());
+
+        if state.diverged.is_some() {
+            return None;
+        }
+
+        let index = state.consumed;
+        let step = match state.steps.pop_front() {
+            Some(step) => step,
+            None => {
+                let reason = format!("step {index}: unexpected command {written:02x?}");
+                log::warn!("Script diverged: {reason}");
+                state.diverged = Some(reason);
+                return None;
+            }
+        };
+        state.consumed += 1;
+
+        if !step.expected.matches(written) {
+            let reason = format!(
+                "step {index}: expected {:02x?}, written {written:02x?}",
+                step.expected
+            );
+            log::warn!("Script diverged: {reason}");
+            state.diverged = Some(reason);
+            return None;
+        }
+
+        match step.reply {
+            Reply::Bytes(bytes) => Some(bytes),
+            Reply::Data(data) => framing::unstuff(written)
+                .ok()
+                .and_then(|frame| self::frame(frame[ssp::message::index::SEQ_ID], &data).ok()),
+            Reply::Silent => None,
+        }
+    }
+}
+
+/// [Transport] checking written commands against a [Script], and answering with its canned
+/// responses.
+///
+/// Once the host diverges from the script, every read fails with the divergence, so the command
+/// under test fails. Reads time out when no response is queued, like a silent device.
+#[derive(Debug, Default)]
+pub struct ScriptedTransport {
+    script: Script,
+    wire: VecDeque<u8>,
+}
+
+impl ScriptedTransport {
+    /// Creates a new [ScriptedTransport] with an empty [Script].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new [ScriptedTransport] following the `script`.
+    pub fn from_script(script: Script) -> Self {
+        Self {
+            script,
+            wire: VecDeque::new(),
+        }
+    }
+
+    /// Gets a reference to the [Script].
+    pub fn script(&self) -> &Script {
+        &self.script
+    }
+}
+
+impl Read for ScriptedTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(reason) = self.script.diverged() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, reason));
+        }
+
+        let n = buf.len().min(self.wire.len());
+        if n == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for ScriptedTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(reply) = self.script.exchange(buf) {
+            self.wire.extend(reply);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for ScriptedTransport {
+    fn clear(&mut self) -> Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        let discarded = self.wire.len();
+        self.wire.clear();
+
+        Ok(discarded)
+    }
+}
+
+/// Builds a stuffed frame with the `seq_id`, and `data`, e.g. the expected bytes of a command.
+pub fn frame(seq_id: u8, data: &[u8]) -> Result<Vec<u8>> {
+    let data_len = u8::try_from(data.len())
+        .map_err(|_| ssp::Error::InvalidDataLength((data.len(), len::MAX_DATA)))?;
+
+    let mut frame = vec![ssp::STX, seq_id, data_len];
+    frame.extend_from_slice(data);
+
+    let crc = ssp::crc::crc16(&frame[1..]);
+    frame.extend_from_slice(crc.to_le_bytes().as_ref());
+
+    framing::stuff(&frame)
+}
+
+// Gets the command data of a stuffed `frame`.
+fn command_data(frame: &[u8]) -> Option<Vec<u8>> {
+    let frame = framing::unstuff(frame).ok()?;
+    frame
+        .get(len::HEADER..frame.len() - len::FOOTER)
+        .map(|data| data.to_vec())
+}
@@ -5,7 +5,7 @@ use std::sync::atomic::Ordering;
 use std::sync::{atomic::AtomicBool, Arc};
 use std::time;
 #[cfg(feature = "jsonrpc")]
-use std::{io::Write, net::Shutdown, path::PathBuf, thread};
+use std::{net::Shutdown, path::PathBuf, thread};
 
 use parking_lot::{Mutex, MutexGuard};
 use ssp::{Error, Result};
@@ -14,7 +14,13 @@ use ssp::{Event, Method};
 
 use crate::DeviceHandle;
 #[cfg(feature = "jsonrpc")]
-use crate::{continue_on_err, PollMode, PushEventReceiver};
+use crate::{
+    broadcast::{Broadcast, OverflowPolicy, DEFAULT_BROADCAST_CAPACITY},
+    codec::WireFormat,
+    continue_on_err,
+    lease::ClientId,
+    PollMode, PushEventReceiver,
+};
 
 const HANDLE_TIMEOUT_MS: u128 = 5_000;
 const MAX_RESETS: u64 = 10;
@@ -44,7 +50,7 @@ pub struct Server {
     #[cfg(feature = "jsonrpc")]
     listener: Option<UnixListener>,
     #[cfg(feature = "jsonrpc")]
-    bus: Option<bus::Bus<Event>>,
+    bus: Option<Broadcast<Event>>,
 }
 
 impl Server {
@@ -54,7 +60,17 @@ impl Server {
         stop_polling: Arc<AtomicBool>,
         protocol_version: ssp::ProtocolVersion,
     ) -> Result<Self> {
-        let handle = DeviceHandle::new(serial_path)?;
+        let handle = DeviceHandle::new(serial_path)?
+            .with_env_fixed_key()?
+            .with_env_audit_log()?
+            .with_env_journal()?
+            .with_env_cash_levels()?
+            .with_env_event_log()?
+            .with_env_reject_history()?
+            .with_env_device_registry()?
+            .with_env_payout_intents()?
+            .with_env_capture()?
+            .with_env_secure_shutdown();
         handle.start_background_polling(stop_polling)?;
         // enable the device to fully configure
         handle.enable_device(protocol_version)?;
@@ -83,7 +99,17 @@ impl Server {
         protocol_version: ssp::ProtocolVersion,
         encrypt: bool,
     ) -> Result<Self> {
-        let mut handle = DeviceHandle::new(serial_path)?;
+        let mut handle = DeviceHandle::new(serial_path)?
+            .with_env_fixed_key()?
+            .with_env_audit_log()?
+            .with_env_journal()?
+            .with_env_cash_levels()?
+            .with_env_event_log()?
+            .with_env_reject_history()?
+            .with_env_device_registry()?
+            .with_env_payout_intents()?
+            .with_env_capture()?
+            .with_env_secure_shutdown();
 
         if encrypt {
             handle.sync()?;
@@ -126,7 +152,10 @@ impl Server {
         std::fs::create_dir_all(sock_path)?;
 
         let listener = Some(UnixListener::bind(socket_path)?);
-        let bus = Some(bus::Bus::new(1024));
+        let bus = Some(Broadcast::new(
+            DEFAULT_BROADCAST_CAPACITY,
+            OverflowPolicy::from_env()?,
+        ));
 
         Ok(Self {
             handle: Arc::new(Mutex::new(handle)),
@@ -157,11 +186,22 @@ impl Server {
         Err(Error::Timeout("waiting for DeviceHandle".into()))
     }
 
+    /// Builder function that sets the event broadcast to clients.
+    ///
+    /// Every client queues up to `capacity` events, and `policy` decides what happens when a
+    /// client falls behind.
+    #[cfg(feature = "jsonrpc")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "jsonrpc")))]
+    pub fn with_event_broadcast(mut self, capacity: usize, policy: OverflowPolicy) -> Self {
+        self.bus = Some(Broadcast::new(capacity, policy));
+        self
+    }
+
     /// Gets a reference to the event queue bus for broadcasting [Event]s.
     ///
-    /// Returns `Err(_)` if the [Bus](bus::Bus) is unset.
+    /// Returns `Err(_)` if the [Broadcast] is unset.
     #[cfg(feature = "jsonrpc")]
-    pub fn bus(&self) -> Result<&bus::Bus<Event>> {
+    pub fn bus(&self) -> Result<&Broadcast<Event>> {
         self.bus
             .as_ref()
             .ok_or(ssp::Error::JsonRpc("unset event queue Bus".into()))
@@ -169,9 +209,9 @@ impl Server {
 
     /// Gets a mutable reference to the event queue bus for broadcasting [Event]s.
     ///
-    /// Returns `Err(_)` if the [Bus](bus::Bus) is unset.
+    /// Returns `Err(_)` if the [Broadcast] is unset.
     #[cfg(feature = "jsonrpc")]
-    pub fn bus_mut(&mut self) -> Result<&mut bus::Bus<Event>> {
+    pub fn bus_mut(&mut self) -> Result<&mut Broadcast<Event>> {
         self.bus
             .as_mut()
             .ok_or(ssp::Error::JsonRpc("unset event queue Bus".into()))
@@ -235,57 +275,90 @@ impl Server {
 
                 let handle = Arc::clone(&self.handle);
                 let stop_stream = Arc::clone(&stop);
-                let mut rx = self.bus_mut()?.add_rx();
+                let rx = self.bus()?.subscribe();
 
                 thread::spawn(move || -> Result<()> {
-                    while !stop_stream.load(Ordering::Relaxed) {
-                        let mut lock = continue_on_err!(
-                            Self::lock_handle(&handle),
-                            "lock handle in accept loop"
-                        );
-                        match Self::receive(&mut lock, &mut stream) {
-                            Ok(method) => match method {
-                                Method::Enable | Method::StackerFull => {
-                                    set_stop_serving_client(false);
-                                }
-                                Method::Disable => {
-                                    if stop_serving_client() {
-                                        let _ = stream.shutdown(Shutdown::Both);
+                    // clients start with JSON, and may negotiate a binary format
+                    let mut format = WireFormat::Json;
+                    let client = ClientId::next();
+                    let mut reported_drops = 0;
+
+                    let res = (|| -> Result<()> {
+                        while !stop_stream.load(Ordering::Relaxed) {
+                            let mut lock = continue_on_err!(
+                                Self::lock_handle(&handle),
+                                "lock handle in accept loop"
+                            );
+                            match Self::receive(&mut lock, &mut stream, &mut format, client) {
+                                Ok(method) => match method {
+                                    Method::Enable | Method::StackerFull => {
                                         set_stop_serving_client(false);
-                                        log::debug!("Shutting down stream");
+                                    }
+                                    Method::Disable => {
+                                        if stop_serving_client() {
+                                            let _ = stream.shutdown(Shutdown::Both);
+                                            set_stop_serving_client(false);
+                                            log::debug!("Shutting down stream");
+                                            return Ok(());
+                                        } else {
+                                            set_stop_serving_client(true);
+                                            continue;
+                                        }
+                                    }
+                                    Method::Shutdown => {
+                                        log::debug!("Shutting down the socket connection");
+                                        stream.shutdown(Shutdown::Both)?;
                                         return Ok(());
-                                    } else {
-                                        set_stop_serving_client(true);
-                                        continue;
                                     }
-                                }
-                                Method::Shutdown => {
-                                    log::debug!("Shutting down the socket connection");
+                                    _ => log::debug!("Handled method: {method}"),
+                                },
+                                Err(err) => {
+                                    log::warn!("Error handling request: {err}");
                                     stream.shutdown(Shutdown::Both)?;
-                                    return Ok(());
+                                    return Err(err);
                                 }
-                                _ => log::debug!("Handled method: {method}"),
-                            },
-                            Err(err) => {
-                                log::warn!("Error handling request: {err}");
-                                stream.shutdown(Shutdown::Both)?;
-                                return Err(err);
                             }
-                        }
 
-                        while let Ok(msg) = rx.try_recv() {
-                            Self::send(&mut stream, &msg)?;
+                            while let Some(msg) = rx.try_recv() {
+                                Self::send(&mut stream, &msg, format)?;
+                            }
+
+                            let dropped = rx.dropped();
+                            if dropped > reported_drops {
+                                log::warn!(
+                                    "Client fell behind, dropped {} events",
+                                    dropped - reported_drops
+                                );
+                                reported_drops = dropped;
+                            }
                         }
+
+                        Ok(())
+                    })();
+
+                    // release any lease held by the disconnected client
+                    if let Ok(lock) = Self::lock_handle(&handle) {
+                        lock.leases().drop_client(client);
                     }
 
-                    Ok(())
+                    res
                 });
             }
 
             while let Ok(msg) = self.push_queue()?.pop_event() {
                 log::trace!("Sending message from push queue: {msg}");
 
-                self.bus_mut()?.broadcast(msg);
+                self.bus()?.send(msg);
+            }
+
+            // session maintenance skips busy handles, and runs on a later iteration instead
+            if let Some(mut handle) = self.handle.try_lock() {
+                if let Err(err) = handle.resync_session() {
+                    log::error!("Failed to resync the eSSP session: {err}");
+                }
+                if let Err(err) = handle.rotate_key_if_due() {
+                    log::error!("Failed to rotate the eSSP session key: {err}");
+                }
             }
 
             // Sleep for a bit to avoid a tight loop
@@ -296,25 +369,24 @@ impl Server {
     }
 
     #[cfg(feature = "jsonrpc")]
-    fn receive(handle: &mut DeviceHandle, stream: &mut UnixStream) -> Result<Method> {
-        handle.on_message(stream)
+    fn receive(
+        handle: &mut DeviceHandle,
+        stream: &mut UnixStream,
+        format: &mut WireFormat,
+        client: ClientId,
+    ) -> Result<Method> {
+        handle.on_client_message(stream, format, Some(client))
     }
 
     #[cfg(feature = "jsonrpc")]
-    fn send(stream: &mut UnixStream, msg: &Event) -> Result<()> {
+    fn send(stream: &mut UnixStream, msg: &Event, format: WireFormat) -> Result<()> {
         log::debug!("Sending push event: {msg}");
 
         let push_req = smol_jsonrpc::Request::new()
             .with_method(msg.method().to_str())
             .with_params(msg);
 
-        let mut json_str = serde_json::to_string(&push_req)?;
-        json_str += "\n";
-
-        stream.write_all(json_str.as_bytes())?;
-        stream.flush()?;
-
-        Ok(())
+        format.write_frame(stream, &push_req)
     }
 }
 
@@ -0,0 +1,172 @@
+//! Event sinks for forwarding device events to external systems.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+use crate::cash_levels::CashAlert;
+use crate::reject_code::RejectReason;
+use crate::{DeviceHandle, PushEventReceiver, Server};
+
+/// Destination for device events and status snapshots, e.g. a message broker.
+pub trait EventSink: Send {
+    /// Gets a short name for the sink, used in log messages.
+    fn name(&self) -> &str;
+
+    /// Publishes a device [Event](ssp::Event).
+    fn publish_event(&mut self, event: &ssp::Event) -> Result<()>;
+
+    /// Publishes a periodic [DeviceStatus](ssp::DeviceStatus) snapshot.
+    ///
+    /// By default, status snapshots are ignored.
+    fn publish_status(&mut self, _status: &ssp::DeviceStatus) -> Result<()> {
+        Ok(())
+    }
+
+    /// Publishes a [CashAlert] raised by the [cash levels](crate::cash_levels) watermarks.
+    ///
+    /// By default, alerts are ignored.
+    fn publish_alert(&mut self, _alert: &CashAlert) -> Result<()> {
+        Ok(())
+    }
+
+    /// Publishes the [RejectReason] of a rejected note, queried after a
+    /// [Rejected](ssp::Method::Rejected) event.
+    ///
+    /// By default, reject reasons are ignored.
+    fn publish_reject(&mut self, _reason: &RejectReason) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Fans out device events and status snapshots to a list of [EventSink]s.
+///
+/// Errors from individual sinks are logged, and do not stop delivery to the other sinks.
+#[derive(Default)]
+pub struct SinkDispatcher {
+    sinks: Vec<Box<dyn EventSink>>,
+}
+
+impl SinkDispatcher {
+    /// Creates a new [SinkDispatcher] with no sinks.
+    pub fn new() -> Self {
+        Self { sinks: Vec::new() }
+    }
+
+    /// Adds an [EventSink] to the dispatcher.
+    pub fn add_sink<S: EventSink + 'static>(&mut self, sink: S) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// Builder function that adds an [EventSink] to the dispatcher.
+    pub fn with_sink<S: EventSink + 'static>(mut self, sink: S) -> Self {
+        self.add_sink(sink);
+        self
+    }
+
+    /// Gets the number of configured sinks.
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    /// Gets whether there are no configured sinks.
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Publishes an [Event](ssp::Event) to all sinks.
+    pub fn publish_event(&mut self, event: &ssp::Event) {
+        for sink in self.sinks.iter_mut() {
+            if let Err(err) = sink.publish_event(event) {
+                log::warn!("Failed to publish event to {} sink: {err}", sink.name());
+            }
+        }
+    }
+
+    /// Publishes a [DeviceStatus](ssp::DeviceStatus) snapshot to all sinks.
+    pub fn publish_status(&mut self, status: &ssp::DeviceStatus) {
+        for sink in self.sinks.iter_mut() {
+            if let Err(err) = sink.publish_status(status) {
+                log::warn!("Failed to publish status to {} sink: {err}", sink.name());
+            }
+        }
+    }
+
+    /// Publishes a [CashAlert] to all sinks.
+    pub fn publish_alert(&mut self, alert: &CashAlert) {
+        for sink in self.sinks.iter_mut() {
+            if let Err(err) = sink.publish_alert(alert) {
+                log::warn!("Failed to publish alert to {} sink: {err}", sink.name());
+            }
+        }
+    }
+
+    /// Publishes a [RejectReason] to all sinks.
+    pub fn publish_reject(&mut self, reason: &RejectReason) {
+        for sink in self.sinks.iter_mut() {
+            if let Err(err) = sink.publish_reject(reason) {
+                log::warn!(
+                    "Failed to publish reject reason to {} sink: {err}",
+                    sink.name()
+                );
+            }
+        }
+    }
+
+    /// Runs the dispatch loop until `stop` is set.
+    ///
+    /// # Parameters
+    ///
+    /// - `handle`: shared [DeviceHandle] used to query status snapshots, cash level alerts, and
+    ///   the reject reason after a [Rejected](ssp::Method::Rejected) event
+    /// - `push_queue`: device event queue returned from background polling
+    /// - `status_interval`: interval between status snapshots, `None` disables snapshots
+    /// - `stop`: atomic flag for stopping the dispatch loop
+    pub fn run(
+        &mut self,
+        handle: &Arc<Mutex<DeviceHandle>>,
+        push_queue: &PushEventReceiver,
+        status_interval: Option<time::Duration>,
+        stop: &AtomicBool,
+    ) -> Result<()> {
+        let mut last_status = time::Instant::now();
+        let cash_levels = Server::lock_handle(handle)?.cash_levels().cloned();
+
+        while !stop.load(Ordering::Relaxed) {
+            while let Ok(event) = push_queue.pop_event() {
+                log::trace!("Dispatching event to sinks: {event}");
+                self.publish_event(&event);
+
+                if matches!(event.method(), ssp::Method::Rejected) {
+                    match Server::lock_handle(handle).and_then(|h| h.reject_reason()) {
+                        Ok(reason) => self.publish_reject(&reason),
+                        Err(err) => log::warn!("Failed to get the reject reason: {err}"),
+                    }
+                }
+            }
+
+            if let Some(cash_levels) = cash_levels.as_ref() {
+                for alert in cash_levels.take_alerts() {
+                    log::trace!("Dispatching cash level alert to sinks: {alert}");
+                    self.publish_alert(&alert);
+                }
+            }
+
+            if let Some(interval) = status_interval {
+                if last_status.elapsed() >= interval {
+                    last_status = time::Instant::now();
+
+                    match Server::lock_handle(handle).and_then(|h| h.device_status()) {
+                        Ok(status) => self.publish_status(&status),
+                        Err(err) => log::warn!("Failed to get status snapshot: {err}"),
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
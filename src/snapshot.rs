@@ -0,0 +1,93 @@
+//! Snapshot of the logical device configuration applied by the host.
+//!
+//! A device reset discards the configuration held in device RAM. The handle tracks the
+//! configuration it applied, so a [DeviceSnapshot] taken with
+//! [snapshot](crate::DeviceHandle::snapshot) can be reapplied in one call with
+//! [restore](crate::DeviceHandle::restore):
+//!
+//! - negotiated host protocol version
+//! - channel inhibits
+//! - bezel color, if stored in RAM
+//!
+//! Bezel colors stored in EEPROM survive a reset, and are not written again on restore. Note
+//! routes are not part of the snapshot, since the `ssp` library does not implement the
+//! `SetDenominationRoute` command.
+
+/// Bezel configuration applied with [configure_bezel](crate::DeviceHandle::configure_bezel).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BezelSnapshot {
+    /// Red, green, and blue color bytes.
+    pub rgb: [u8; 3],
+    /// Whether the color is stored in EEPROM, and survives a reset.
+    pub eeprom: bool,
+}
+
+impl BezelSnapshot {
+    /// Creates a new [BezelSnapshot].
+    pub fn new(rgb: ssp::RGB, storage: ssp::BezelConfigStorage) -> Self {
+        Self {
+            rgb: rgb.into(),
+            eeprom: storage == ssp::BezelConfigStorage::Eeprom,
+        }
+    }
+
+    /// Gets the bezel color.
+    pub fn rgb(&self) -> ssp::RGB {
+        ssp::RGB::from(self.rgb)
+    }
+
+    /// Gets the bezel configuration storage.
+    pub fn storage(&self) -> ssp::BezelConfigStorage {
+        if self.eeprom {
+            ssp::BezelConfigStorage::Eeprom
+        } else {
+            ssp::BezelConfigStorage::Ram
+        }
+    }
+}
+
+/// Logical device configuration, see [snapshot](crate::snapshot).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceSnapshot {
+    /// Negotiated host protocol version, `None` if never negotiated.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub protocol_version: Option<u8>,
+    /// Channel inhibit bitfields, `None` if never set.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub inhibits: Option<Vec<u8>>,
+    /// Bezel configuration, `None` if never configured.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bezel: Option<BezelSnapshot>,
+}
+
+impl DeviceSnapshot {
+    /// Creates a new, empty [DeviceSnapshot].
+    pub const fn new() -> Self {
+        Self {
+            protocol_version: None,
+            inhibits: None,
+            bezel: None,
+        }
+    }
+
+    /// Gets the negotiated host protocol version, if any.
+    pub fn protocol(&self) -> Option<ssp::ProtocolVersion> {
+        self.protocol_version.map(ssp::ProtocolVersion::from)
+    }
+
+    /// Gets the channel inhibits, if any.
+    pub fn inhibit_list(&self) -> Option<ssp::EnableBitfieldList> {
+        self.inhibits.as_ref().map(|inhibits| {
+            let bitfields: Vec<ssp::EnableBitfield> =
+                inhibits.iter().map(ssp::EnableBitfield::from).collect();
+            ssp::EnableBitfieldList::from(bitfields.as_slice())
+        })
+    }
+
+    /// Gets whether the snapshot holds any configuration.
+    pub fn is_empty(&self) -> bool {
+        self.protocol_version.is_none() && self.inhibits.is_none() && self.bezel.is_none()
+    }
+}
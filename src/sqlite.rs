@@ -0,0 +1,441 @@
+//! SQLite-backed store of device events and command outcomes.
+//!
+//! Sites get a local history of the device without building their own pipeline:
+//!
+//! - device events are stored by adding the [SqliteEventStore] as an [EventSink] to a
+//!   [SinkDispatcher](crate::sink::SinkDispatcher)
+//! - command outcomes are stored by attaching the store with
+//!   [with_event_store](crate::DeviceHandle::with_event_store)
+//!
+//! Stored records are queried by time range, event type, and denomination with an [EventQuery].
+//!
+//! The [SqliteStorage] keeps the records of the persistent features, e.g. the
+//! [transaction journal](crate::journal), in the same database, see [storage](crate::storage).
+
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+use rusqlite::{params, params_from_iter, types::Value, Connection};
+use ssp::Result;
+
+use crate::sink::EventSink;
+use crate::storage::Storage;
+
+/// Environment variable with the path of the SQLite database.
+pub const SQLITE_ENV_PATH: &str = "SSP_SQLITE_DB";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp_ms INTEGER NOT NULL,
+    method TEXT NOT NULL,
+    denomination INTEGER,
+    payload TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_timestamp ON events (timestamp_ms);
+CREATE INDEX IF NOT EXISTS events_method ON events (method, timestamp_ms);
+CREATE TABLE IF NOT EXISTS commands (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp_ms INTEGER NOT NULL,
+    method TEXT NOT NULL,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS commands_timestamp ON commands (timestamp_ms);
+";
+
+const STORAGE_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS records (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    collection TEXT NOT NULL,
+    record TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS records_collection ON records (collection, id);
+";
+
+/// Device event stored in a [SqliteEventStore].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StoredEvent {
+    /// Row ID, increasing for every event.
+    pub id: i64,
+    /// Time the event was stored, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Event type.
+    pub method: String,
+    /// Note value carried by the event, if any.
+    pub denomination: Option<u32>,
+    /// JSON encoded [Event](ssp::Event).
+    pub payload: String,
+}
+
+/// Command outcome stored in a [SqliteEventStore].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StoredCommand {
+    /// Row ID, increasing for every command.
+    pub id: i64,
+    /// Time the command completed, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Command method.
+    pub method: String,
+    /// Error returned by the command, `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+impl StoredCommand {
+    /// Gets whether the command succeeded.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Filter for [SqliteEventStore] queries.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventQuery {
+    /// Only records of this event type, or command method.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub method: Option<ssp::Method>,
+    /// Only events carrying this note value, ignored for commands.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub denomination: Option<u32>,
+    /// Only records at, or after this time (milliseconds since the Unix epoch).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub since_ms: Option<u64>,
+    /// Only records before this time (milliseconds since the Unix epoch).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub until_ms: Option<u64>,
+    /// Only the most recent matching records, up to this number.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub limit: Option<usize>,
+}
+
+impl EventQuery {
+    /// Creates a new [EventQuery] matching all records.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder function that only matches records of `method`.
+    pub fn with_method(mut self, method: ssp::Method) -> Self {
+        self.method = Some(method);
+        self
+    }
+
+    /// Builder function that only matches events carrying the note value `denomination`.
+    pub fn with_denomination(mut self, denomination: u32) -> Self {
+        self.denomination = Some(denomination);
+        self
+    }
+
+    /// Builder function that only matches records at, or after `since_ms`.
+    pub fn with_since_ms(mut self, since_ms: u64) -> Self {
+        self.since_ms = Some(since_ms);
+        self
+    }
+
+    /// Builder function that only matches records before `until_ms`.
+    pub fn with_until_ms(mut self, until_ms: u64) -> Self {
+        self.until_ms = Some(until_ms);
+        self
+    }
+
+    /// Builder function that limits the result to the `limit` most recent records.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    // Builds the WHERE, and LIMIT clauses, and their parameters.
+    fn to_sql(&self, with_denomination: bool) -> (String, Vec<Value>) {
+        let mut conds = Vec::new();
+        let mut params = Vec::new();
+
+        if let Some(method) = self.method {
+            conds.push("method = ?");
+            params.push(Value::Text(method.to_str().into()));
+        }
+        if let Some(denomination) = self.denomination.filter(|_| with_denomination) {
+            conds.push("denomination = ?");
+            params.push(Value::Integer(denomination.into()));
+        }
+        if let Some(since) = self.since_ms {
+            conds.push("timestamp_ms >= ?");
+            params.push(Value::Integer(since as i64));
+        }
+        if let Some(until) = self.until_ms {
+            conds.push("timestamp_ms < ?");
+            params.push(Value::Integer(until as i64));
+        }
+
+        let mut sql = String::new();
+        if !conds.is_empty() {
+            sql = format!(" WHERE {}", conds.join(" AND "));
+        }
+
+        // newest first, so the limit keeps the most recent records
+        sql.push_str(" ORDER BY id DESC");
+        if let Some(limit) = self.limit {
+            sql.push_str(" LIMIT ?");
+            params.push(Value::Integer(limit as i64));
+        }
+
+        (sql, params)
+    }
+}
+
+/// Gets the note value carried by the `event`, if any.
+pub fn event_denomination(event: &ssp::Event) -> Option<u32> {
+    let value = match event.payload() {
+        ssp::EventPayload::NoteCreditEvent(e) => e.value(),
+        ssp::EventPayload::ReadEvent(e) => e.value(),
+        ssp::EventPayload::StackEvent(e) => e.value(),
+        ssp::EventPayload::FraudAttemptEvent(e) => *e.value(),
+        ssp::EventPayload::NoteClearedFromFrontEvent(e) => *e.value(),
+        ssp::EventPayload::NoteClearedIntoCashboxEvent(e) => *e.value(),
+        _ => return None,
+    };
+
+    Some(value.as_inner()).filter(|&v| v != 0)
+}
+
+/// SQLite database storing device events, and command outcomes.
+///
+/// Cloned stores share the same database connection.
+#[derive(Clone)]
+pub struct SqliteEventStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteEventStore {
+    /// Opens the database at `path`, creating it if it does not exist.
+    pub fn open(path: &str) -> Result<Self> {
+        Self::from_connection(Connection::open(path).map_err(sqlite_error)?)
+    }
+
+    /// Opens a new in-memory database, e.g. for testing.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory().map_err(sqlite_error)?)
+    }
+
+    /// Opens the database at the path set in the [SQLITE_ENV_PATH] environment variable.
+    ///
+    /// Returns `Ok(None)` if the variable is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(SQLITE_ENV_PATH) {
+            Ok(path) => Self::open(path.as_str()).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA).map_err(sqlite_error)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Stores a device [Event](ssp::Event), and returns its row ID.
+    pub fn record_event(&self, event: &ssp::Event) -> Result<i64> {
+        let payload = serde_json::to_string(event)
+            .map_err(|err| ssp::Error::Io(format!("SQLite event encoding error: {err}")))?;
+
+        let conn = self.conn.lock();
+
+        conn.execute(
+            "INSERT INTO events (timestamp_ms, method, denomination, payload) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                now_ms() as i64,
+                event.method().to_str(),
+                event_denomination(event),
+                payload
+            ],
+        )
+        .map_err(sqlite_error)?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Stores the outcome of a command sent with `method`, and returns its row ID.
+    pub fn record_command<T>(&self, method: ssp::Method, res: &Result<T>) -> Result<i64> {
+        let error = res.as_ref().err().map(|err| format!("{err}"));
+
+        let conn = self.conn.lock();
+
+        conn.execute(
+            "INSERT INTO commands (timestamp_ms, method, error) VALUES (?1, ?2, ?3)",
+            params![now_ms() as i64, method.to_str(), error],
+        )
+        .map_err(sqlite_error)?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Gets the events matching `query`, oldest first.
+    pub fn query_events(&self, query: &EventQuery) -> Result<Vec<StoredEvent>> {
+        let (filter, params) = query.to_sql(true);
+        let sql =
+            format!("SELECT id, timestamp_ms, method, denomination, payload FROM events{filter}");
+
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(sql.as_str()).map_err(sqlite_error)?;
+
+        let mut events = stmt
+            .query_map(params_from_iter(params.iter()), |row| {
+                Ok(StoredEvent {
+                    id: row.get(0)?,
+                    timestamp_ms: row.get::<_, i64>(1)? as u64,
+                    method: row.get(2)?,
+                    denomination: row.get(3)?,
+                    payload: row.get(4)?,
+                })
+            })
+            .map_err(sqlite_error)?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(sqlite_error)?;
+
+        events.reverse();
+
+        Ok(events)
+    }
+
+    /// Gets the command outcomes matching `query`, oldest first.
+    pub fn query_commands(&self, query: &EventQuery) -> Result<Vec<StoredCommand>> {
+        let (filter, params) = query.to_sql(false);
+        let sql = format!("SELECT id, timestamp_ms, method, error FROM commands{filter}");
+
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(sql.as_str()).map_err(sqlite_error)?;
+
+        let mut commands = stmt
+            .query_map(params_from_iter(params.iter()), |row| {
+                Ok(StoredCommand {
+                    id: row.get(0)?,
+                    timestamp_ms: row.get::<_, i64>(1)? as u64,
+                    method: row.get(2)?,
+                    error: row.get(3)?,
+                })
+            })
+            .map_err(sqlite_error)?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(sqlite_error)?;
+
+        commands.reverse();
+
+        Ok(commands)
+    }
+}
+
+impl EventSink for SqliteEventStore {
+    fn name(&self) -> &str {
+        "SQLite"
+    }
+
+    fn publish_event(&mut self, event: &ssp::Event) -> Result<()> {
+        self.record_event(event).map(|_| ())
+    }
+}
+
+/// [Storage] keeping all collections in a single SQLite table.
+///
+/// Cloned storages share the same database connection.
+#[derive(Clone)]
+pub struct SqliteStorage {
+    location: String,
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, creating it if it does not exist.
+    pub fn open(path: &str) -> Result<Self> {
+        Self::from_connection(path, Connection::open(path).map_err(sqlite_error)?)
+    }
+
+    /// Opens a new in-memory database, e.g. for testing.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(
+            ":memory:",
+            Connection::open_in_memory().map_err(sqlite_error)?,
+        )
+    }
+
+    fn from_connection(location: &str, conn: Connection) -> Result<Self> {
+        conn.execute_batch(STORAGE_SCHEMA).map_err(sqlite_error)?;
+
+        Ok(Self {
+            location: location.into(),
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn name(&self) -> &str {
+        "SQLite"
+    }
+
+    fn location(&self, collection: &str) -> String {
+        format!("{}#{collection}", self.location)
+    }
+
+    fn load(&self, collection: &str) -> Result<Vec<String>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn
+            .prepare("SELECT record FROM records WHERE collection = ?1 ORDER BY id")
+            .map_err(sqlite_error)?;
+
+        let records = stmt
+            .query_map(params![collection], |row| row.get(0))
+            .map_err(sqlite_error)?
+            .collect::<std::result::Result<Vec<String>, _>>()
+            .map_err(sqlite_error)?;
+
+        Ok(records)
+    }
+
+    fn append(&self, collection: &str, record: &str) -> Result<()> {
+        self.conn
+            .lock()
+            .execute(
+                "INSERT INTO records (collection, record) VALUES (?1, ?2)",
+                params![collection, record],
+            )
+            .map_err(sqlite_error)?;
+
+        Ok(())
+    }
+
+    fn replace(&self, collection: &str, records: &[String]) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction().map_err(sqlite_error)?;
+
+        tx.execute(
+            "DELETE FROM records WHERE collection = ?1",
+            params![collection],
+        )
+        .map_err(sqlite_error)?;
+
+        for record in records.iter() {
+            tx.execute(
+                "INSERT INTO records (collection, record) VALUES (?1, ?2)",
+                params![collection, record],
+            )
+            .map_err(sqlite_error)?;
+        }
+
+        tx.commit().map_err(sqlite_error)
+    }
+}
+
+fn now_ms() -> u64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn sqlite_error(err: rusqlite::Error) -> ssp::Error {
+    ssp::Error::Io(format!("SQLite error: {err}"))
+}
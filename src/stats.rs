@@ -0,0 +1,194 @@
+//! Rolling statistics of the exchanges with a device.
+//!
+//! Every command/response exchange of a [DeviceHandle](crate::DeviceHandle), including polls, is
+//! recorded on its link. [stats](crate::DeviceHandle::stats) summarizes the exchanges of the
+//! last [STATS_WINDOW_SECS] seconds, up to the last [STATS_MAX_SAMPLES] exchanges, so
+//! applications can expose the health of the link without scraping logs.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time;
+
+use parking_lot::Mutex;
+
+use crate::framing;
+
+/// Time span covered by the statistics (seconds).
+pub const STATS_WINDOW_SECS: u64 = 300;
+/// Maximum number of exchanges covered by the statistics.
+pub const STATS_MAX_SAMPLES: usize = 1024;
+
+/// Rolling statistics of the exchanges with a device, returned by
+/// [stats](crate::DeviceHandle::stats).
+///
+/// Round trips only cover the successful exchanges, and are zero without any.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkStats {
+    /// Time span covered by the statistics, from the oldest exchange, in milliseconds.
+    pub window_ms: u64,
+    /// Number of exchanges, including polls.
+    pub exchanges: u64,
+    /// Number of polls sent.
+    pub polls: u64,
+    /// Number of exchanges that failed, after any retransmissions.
+    pub errors: u64,
+    /// Number of reads that timed out waiting for a response.
+    pub timeouts: u64,
+    /// Number of responses with an invalid CRC.
+    pub crc_errors: u64,
+    /// Number of retransmitted commands.
+    pub retries: u64,
+    /// Average round trip, in microseconds.
+    pub avg_rtt_us: u64,
+    /// Median round trip, in microseconds.
+    pub p50_rtt_us: u64,
+    /// 90th percentile round trip, in microseconds.
+    pub p90_rtt_us: u64,
+    /// 99th percentile round trip, in microseconds.
+    pub p99_rtt_us: u64,
+    /// Longest round trip, in microseconds.
+    pub max_rtt_us: u64,
+}
+
+impl LinkStats {
+    /// Gets the share of failed exchanges, from `0.0` to `1.0`.
+    pub fn error_rate(&self) -> f64 {
+        if self.exchanges == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.exchanges as f64
+        }
+    }
+}
+
+impl fmt::Display for LinkStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            concat!(
+                "exchanges: {}, polls: {}, errors: {}, timeouts: {}, CRC errors: {}, retries: {}, ",
+                "avg: {}us, p50: {}us, p99: {}us, max: {}us"
+            ),
+            self.exchanges,
+            self.polls,
+            self.errors,
+            self.timeouts,
+            self.crc_errors,
+            self.retries,
+            self.avg_rtt_us,
+            self.p50_rtt_us,
+            self.p99_rtt_us,
+            self.max_rtt_us,
+        )
+    }
+}
+
+/// Single command/response exchange, including its retransmissions.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Exchange {
+    at: time::Instant,
+    poll: bool,
+    // round trip of the successful attempt
+    rtt_us: Option<u64>,
+    retries: u32,
+    timeouts: u32,
+    crc_errors: u32,
+}
+
+impl Exchange {
+    /// Starts a new exchange, for a poll, or any other command.
+    pub(crate) fn new(poll: bool) -> Self {
+        Self {
+            at: time::Instant::now(),
+            poll,
+            rtt_us: None,
+            retries: 0,
+            timeouts: 0,
+            crc_errors: 0,
+        }
+    }
+
+    /// Records the result of reading a response, `elapsed` after writing the command.
+    pub(crate) fn record_read<T>(&mut self, res: &ssp::Result<T>, elapsed: time::Duration) {
+        match res {
+            Ok(_) => self.rtt_us = Some(elapsed.as_micros().min(u64::MAX.into()) as u64),
+            Err(ssp::Error::Crc(_)) => self.crc_errors += 1,
+            Err(err) if framing::is_timeout(err) => self.timeouts += 1,
+            Err(_) => (),
+        }
+    }
+
+    /// Records a retransmission of the command.
+    pub(crate) fn record_retry(&mut self) {
+        self.retries += 1;
+    }
+}
+
+/// Recorder of the recent exchanges of a link.
+#[derive(Debug)]
+pub(crate) struct StatsRecorder {
+    exchanges: Mutex<VecDeque<Exchange>>,
+}
+
+impl Default for StatsRecorder {
+    fn default() -> Self {
+        Self {
+            // allocated once, so recording never allocates
+            exchanges: Mutex::new(VecDeque::with_capacity(STATS_MAX_SAMPLES)),
+        }
+    }
+}
+
+impl StatsRecorder {
+    /// Records a finished exchange, dropping the oldest beyond [STATS_MAX_SAMPLES].
+    pub(crate) fn record(&self, exchange: Exchange) {
+        let mut exchanges = self.exchanges.lock();
+
+        if exchanges.len() >= STATS_MAX_SAMPLES {
+            exchanges.pop_front();
+        }
+        exchanges.push_back(exchange);
+    }
+
+    /// Summarizes the exchanges of the last [STATS_WINDOW_SECS] seconds.
+    pub(crate) fn stats(&self) -> LinkStats {
+        let window = time::Duration::from_secs(STATS_WINDOW_SECS);
+        let exchanges = self.exchanges.lock();
+        let recent = exchanges.iter().filter(|e| e.at.elapsed() <= window);
+
+        let mut stats = LinkStats::default();
+        let mut rtts = Vec::with_capacity(exchanges.len());
+
+        for exchange in recent {
+            stats.window_ms = stats
+                .window_ms
+                .max(exchange.at.elapsed().as_millis().min(u64::MAX.into()) as u64);
+            stats.exchanges += 1;
+            stats.polls += u64::from(exchange.poll);
+            stats.timeouts += u64::from(exchange.timeouts);
+            stats.crc_errors += u64::from(exchange.crc_errors);
+            stats.retries += u64::from(exchange.retries);
+
+            match exchange.rtt_us {
+                Some(rtt) => rtts.push(rtt),
+                None => stats.errors += 1,
+            }
+        }
+
+        if !rtts.is_empty() {
+            rtts.sort_unstable();
+
+            // nearest-rank percentile
+            let percentile = |p: usize| rtts[(rtts.len() * p).div_ceil(100).max(1) - 1];
+
+            stats.avg_rtt_us = rtts.iter().sum::<u64>() / rtts.len() as u64;
+            stats.p50_rtt_us = percentile(50);
+            stats.p90_rtt_us = percentile(90);
+            stats.p99_rtt_us = percentile(99);
+            stats.max_rtt_us = rtts[rtts.len() - 1];
+        }
+
+        stats
+    }
+}
@@ -0,0 +1,157 @@
+//! Line-delimited JSON frontend over stdin/stdout.
+//!
+//! Supervisors written in other languages can embed the server as a child process, without any
+//! network or socket setup:
+//!
+//! - commands are read from stdin as JSON-RPC requests, one per line
+//! - responses, and device events as JSON-RPC push requests, are written to stdout, one per line
+//!
+//! Log messages go to stderr, so they never interleave with the protocol messages. The server
+//! exits when stdin is closed, e.g. when the supervisor exits.
+//!
+//! The supervisor is a single [lease](crate::lease) client, and can claim the lease to lock out
+//! clients of other frontends sharing the device.
+
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crossbeam::channel;
+use parking_lot::Mutex;
+use smol_jsonrpc::{Error as RpcError, Request, Response};
+use ssp::Result;
+
+use crate::codec::WireFormat;
+use crate::lease::ClientId;
+use crate::{DeviceHandle, PushEventReceiver, Server};
+
+/// Frontend speaking newline-delimited JSON-RPC over stdin/stdout.
+pub struct StdioServer {
+    handle: Arc<Mutex<DeviceHandle>>,
+    client: ClientId,
+}
+
+impl StdioServer {
+    /// Creates a new [StdioServer].
+    ///
+    /// # Parameters
+    ///
+    /// - `handle`: shared [DeviceHandle] used to send commands to the device
+    pub fn new(handle: Arc<Mutex<DeviceHandle>>) -> Self {
+        Self {
+            handle,
+            client: ClientId::next(),
+        }
+    }
+
+    /// Serves commands from stdin, and writes responses and events from the `push_queue` to
+    /// stdout, until `stop` is set or stdin is closed.
+    pub fn serve(&self, push_queue: &PushEventReceiver, stop: &AtomicBool) -> Result<()> {
+        self.serve_with(
+            io::BufReader::new(io::stdin()),
+            &mut io::stdout(),
+            push_queue,
+            stop,
+        )
+    }
+
+    /// Serves commands from `input`, and writes responses and events to `output`, until `stop`
+    /// is set or `input` is closed.
+    ///
+    /// Useful for serving over other byte streams, e.g. pipes to a child process.
+    pub fn serve_with<R, W>(
+        &self,
+        input: R,
+        output: &mut W,
+        push_queue: &PushEventReceiver,
+        stop: &AtomicBool,
+    ) -> Result<()>
+    where
+        R: BufRead + Send + 'static,
+        W: Write,
+    {
+        let lines = spawn_reader(input);
+
+        log::info!("Serving line-delimited JSON on stdio");
+
+        while !stop.load(Ordering::Relaxed) {
+            loop {
+                match lines.try_recv() {
+                    Ok(line) => {
+                        let res = self.on_request(line.as_str());
+                        WireFormat::Json.write_frame(output, &res)?;
+                    }
+                    Err(channel::TryRecvError::Empty) => break,
+                    Err(channel::TryRecvError::Disconnected) => {
+                        log::info!("Input closed, stopping stdio server");
+                        return Ok(());
+                    }
+                }
+            }
+
+            // blocks for the queue timeout, so the loop does not spin
+            while let Ok(event) = push_queue.pop_event() {
+                log::debug!("Sending push event: {event}");
+
+                let push_req = Request::new()
+                    .with_method(event.method().to_str())
+                    .with_params(&event);
+
+                WireFormat::Json.write_frame(output, &push_req)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_request(&self, msg: &str) -> Response {
+        log::debug!("Received stdio message: {msg}");
+
+        let req = match serde_json::from_str::<Request>(msg) {
+            Ok(req) => req,
+            Err(err) => {
+                log::warn!("Expected valid JSON-RPC request, error: {err}");
+                return Response::new()
+                    .with_error(RpcError::from(&ssp::Error::JsonRpc(format!("{err}"))));
+            }
+        };
+
+        match Server::lock_handle(&self.handle) {
+            Ok(mut handle) => handle.on_client_request(Some(self.client), &req),
+            Err(err) => {
+                let res = Response::new().with_error(RpcError::from(&err));
+                match req.id() {
+                    Some(id) => res.with_id(id),
+                    None => res,
+                }
+            }
+        }
+    }
+}
+
+// Reads non-empty lines on a background thread, so reads do not block event delivery.
+//
+// The channel disconnects when the input reaches EOF, or fails.
+fn spawn_reader<R: BufRead + Send + 'static>(input: R) -> channel::Receiver<String> {
+    let (tx, rx) = channel::unbounded();
+
+    thread::spawn(move || {
+        for line in input.lines() {
+            match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => {
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    log::error!("Error reading input: {err}");
+                    break;
+                }
+            }
+        }
+    });
+
+    rx
+}
@@ -0,0 +1,246 @@
+//! Pluggable storage backends for the persistent features.
+//!
+//! The [transaction journal](crate::journal), [device registry](crate::registry), and
+//! [audit log](crate::audit) keep their records in a [Storage] backend. Records are single lines
+//! of text, grouped in named collections, e.g. [JOURNAL_COLLECTION].
+//!
+//! Built-in backends:
+//!
+//! - [FileStorage]: one file per collection, the default of the `open` constructors
+//! - `SqliteStorage`: one table for all collections, with the `sqlite` feature
+//!
+//! Embedders plug in their own database by implementing [Storage], and passing it to the
+//! `from_storage` constructors, e.g.
+//! [TransactionJournal::from_storage](crate::journal::TransactionJournal::from_storage).
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use parking_lot::Mutex;
+use ssp::Result;
+
+/// Collection of the [transaction journal](crate::journal) entries.
+pub const JOURNAL_COLLECTION: &str = "journal";
+/// Collection of the [device registry](crate::registry) records.
+pub const REGISTRY_COLLECTION: &str = "registry";
+/// Collection of the [audit log](crate::audit) records.
+pub const AUDIT_COLLECTION: &str = "audit";
+
+/// Storage backend keeping collections of records.
+///
+/// Records never contain line breaks. Implementations are shared between threads, and have to
+/// serialize concurrent calls.
+pub trait Storage: Send + Sync {
+    /// Gets a short name for the backend, used in log messages.
+    fn name(&self) -> &str;
+
+    /// Gets where the `collection` is stored, e.g. a file path, used in log messages.
+    fn location(&self, collection: &str) -> String;
+
+    /// Loads the records of the `collection`, oldest first.
+    ///
+    /// Returns an empty list if the collection does not exist.
+    fn load(&self, collection: &str) -> Result<Vec<String>>;
+
+    /// Appends a `record` to the `collection`.
+    ///
+    /// The record has to be durable when the function returns.
+    fn append(&self, collection: &str, record: &str) -> Result<()>;
+
+    /// Replaces all records of the `collection`.
+    ///
+    /// A failure, or crash has to leave either the old, or the new records.
+    fn replace(&self, collection: &str, records: &[String]) -> Result<()>;
+}
+
+/// [Storage] keeping every collection in a file, with one record per line.
+///
+/// Collections are stored in `<collection>.log` files in the storage directory, unless mapped to
+/// a path with [with_path](Self::with_path). Files are only readable by the owner.
+///
+/// A crash while appending leaves at most one incomplete record at the end of a file. The
+/// incomplete record is discarded when the file is first used, since it was never acknowledged.
+/// A complete record that is not valid UTF-8, e.g. after disk corruption, fails loading with an
+/// error naming the file, and line, so a ledger is never replayed without it.
+pub struct FileStorage {
+    dir: PathBuf,
+    paths: HashMap<String, PathBuf>,
+    files: Mutex<HashMap<String, fs::File>>,
+}
+
+impl FileStorage {
+    /// Creates a new [FileStorage] in the `dir` directory.
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            paths: HashMap::new(),
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates a new [FileStorage] keeping the `collection` at `path`.
+    pub fn for_file(collection: &str, path: &str) -> Self {
+        let dir = Path::new(path)
+            .parent()
+            .and_then(|d| d.to_str())
+            .unwrap_or_default();
+
+        Self::new(dir).with_path(collection, path)
+    }
+
+    /// Builder function that keeps the `collection` at `path`, instead of the storage directory.
+    pub fn with_path(mut self, collection: &str, path: &str) -> Self {
+        self.paths.insert(collection.into(), PathBuf::from(path));
+        self
+    }
+
+    /// Gets the path of the `collection` file.
+    pub fn path(&self, collection: &str) -> PathBuf {
+        self.paths
+            .get(collection)
+            .cloned()
+            .unwrap_or_else(|| self.dir.join(format!("{collection}.log")))
+    }
+
+    // Runs `f` with the `collection` file opened for appending, opening it on first use.
+    fn with_file<T>(
+        &self,
+        collection: &str,
+        f: impl FnOnce(&mut fs::File) -> Result<T>,
+    ) -> Result<T> {
+        let mut files = self.files.lock();
+
+        if !files.contains_key(collection) {
+            let file = self.open_file(collection)?;
+            files.insert(collection.into(), file);
+        }
+
+        match files.get_mut(collection) {
+            Some(file) => f(file),
+            None => Err(ssp::Error::Io(format!("{collection} file is not open"))),
+        }
+    }
+
+    // Opens the `collection` file, and discards an incomplete record at the end.
+    fn open_file(&self, collection: &str) -> Result<fs::File> {
+        let path = self.path(collection);
+
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut opts = fs::OpenOptions::new();
+        opts.read(true).append(true).create(true);
+        #[cfg(unix)]
+        opts.mode(0o600);
+
+        let mut file = opts.open(&path)?;
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        let complete_len = complete_len(&contents);
+
+        if complete_len < contents.len() {
+            log::warn!(
+                "Discarding incomplete {collection} record at the end of {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&contents[complete_len..])
+            );
+
+            file.set_len(complete_len as u64)?;
+            file.sync_data()?;
+        }
+
+        Ok(file)
+    }
+}
+
+// Gets the length of the complete records in `contents`, up to, and including the last line break.
+fn complete_len(contents: &[u8]) -> usize {
+    contents
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map(|i| i + 1)
+        .unwrap_or(0)
+}
+
+impl Storage for FileStorage {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn location(&self, collection: &str) -> String {
+        format!("{}", self.path(collection).display())
+    }
+
+    fn load(&self, collection: &str) -> Result<Vec<String>> {
+        // hold the lock, so a concurrent append is not read half-written
+        self.with_file(collection, |_file| {
+            let path = self.path(collection);
+            let contents = fs::read(&path)?;
+
+            contents[..complete_len(&contents)]
+                .split(|&b| b == b'\n')
+                .enumerate()
+                .filter(|(_, line)| !line.is_empty())
+                .map(|(i, line)| {
+                    std::str::from_utf8(line).map(String::from).map_err(|err| {
+                        ssp::Error::Io(format!(
+                            "invalid {collection} record on line {} of {}: {err}",
+                            i + 1,
+                            path.display()
+                        ))
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn append(&self, collection: &str, record: &str) -> Result<()> {
+        self.with_file(collection, |file| {
+            file.write_all(format!("{record}\n").as_bytes())?;
+            file.sync_data()?;
+
+            Ok(())
+        })
+    }
+
+    fn replace(&self, collection: &str, records: &[String]) -> Result<()> {
+        let mut contents = String::new();
+        for record in records.iter() {
+            contents.push_str(record);
+            contents.push('\n');
+        }
+
+        let path = self.path(collection);
+
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut files = self.files.lock();
+
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut opts = fs::OpenOptions::new();
+            opts.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            opts.mode(0o600);
+
+            let mut file = opts.open(&tmp_path)?;
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &path)?;
+
+        // the open file was replaced, reopen it on the next append
+        files.remove(collection);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+//! systemd service integration using the `sd_notify` protocol.
+//!
+//! All functions are no-ops when the process is not started by systemd, i.e. when the
+//! `NOTIFY_SOCKET` environment variable is not set.
+//!
+//! Example unit file section:
+//!
+//! ```ini
+//! [Service]
+//! Type=notify
+//! WatchdogSec=30
+//! Restart=on-failure
+//! ```
+
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use ssp::Result;
+
+use crate::DeviceHandle;
+
+/// Environment variable containing the path to the systemd notification socket.
+pub const NOTIFY_ENV_SOCKET: &str = "NOTIFY_SOCKET";
+/// Environment variable containing the watchdog timeout (microseconds).
+pub const WATCHDOG_ENV_USEC: &str = "WATCHDOG_USEC";
+/// Environment variable containing the PID expected to send watchdog notifications.
+pub const WATCHDOG_ENV_PID: &str = "WATCHDOG_PID";
+
+/// Sends a notification `state` to the systemd service manager, e.g. `READY=1`.
+///
+/// Returns `false` if the process is not running under systemd.
+pub fn notify(state: &str) -> Result<bool> {
+    let path = match std::env::var(NOTIFY_ENV_SOCKET) {
+        Ok(path) if !path.is_empty() => path,
+        _ => return Ok(false),
+    };
+
+    let socket = UnixDatagram::unbound()?;
+
+    if let Some(name) = path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            return Err(ssp::Error::Io(format!(
+                "abstract notification socket not supported: @{name}"
+            )));
+        }
+    } else {
+        socket.send_to(state.as_bytes(), path.as_str())?;
+    }
+
+    log::trace!("Sent systemd notification: {state}");
+
+    Ok(true)
+}
+
+/// Notifies systemd that the service finished initializing.
+pub fn notify_ready() -> Result<bool> {
+    notify("READY=1")
+}
+
+/// Notifies systemd that the service is shutting down.
+pub fn notify_stopping() -> Result<bool> {
+    notify("STOPPING=1")
+}
+
+/// Sends a free-form status message, displayed by `systemctl status`.
+pub fn notify_status(status: &str) -> Result<bool> {
+    notify(format!("STATUS={status}").as_str())
+}
+
+/// Gets the watchdog timeout configured for the service, if any.
+pub fn watchdog_timeout() -> Option<time::Duration> {
+    if let Ok(pid) = std::env::var(WATCHDOG_ENV_PID) {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+
+    std::env::var(WATCHDOG_ENV_USEC)
+        .ok()?
+        .parse::<u64>()
+        .ok()
+        .filter(|&usec| usec != 0)
+        .map(time::Duration::from_micros)
+}
+
+/// Starts a background thread that pets the systemd watchdog until `stop` is set.
+///
+/// The watchdog is only notified while background polling of the `handle` succeeds, so a wedged
+/// serial connection lets the watchdog expire, and systemd restarts the service.
+///
+/// Returns `None` if no watchdog is configured for the service.
+pub fn start_watchdog(
+    handle: &DeviceHandle,
+    stop: Arc<AtomicBool>,
+) -> Option<thread::JoinHandle<()>> {
+    let timeout = watchdog_timeout()?;
+    // notify at half the timeout, as recommended by `sd_watchdog_enabled(3)`
+    let interval = timeout / 2;
+
+    log::info!(
+        "Starting systemd watchdog, timeout: {}ms",
+        timeout.as_millis()
+    );
+
+    let link = handle.link();
+
+    Some(thread::spawn(move || {
+        let mut next_notify = time::Instant::now();
+
+        while !stop.load(Ordering::Relaxed) {
+            if time::Instant::now() >= next_notify {
+                next_notify = time::Instant::now() + interval;
+
+                match link.last_poll_elapsed() {
+                    Some(elapsed) if elapsed < timeout => {
+                        if let Err(err) = notify("WATCHDOG=1") {
+                            log::warn!("Failed to notify systemd watchdog: {err}");
+                        }
+                    }
+                    Some(elapsed) => log::warn!(
+                        "No successful poll for {}ms, skipping watchdog notification",
+                        elapsed.as_millis()
+                    ),
+                    None => log::debug!("No successful poll yet, skipping watchdog notification"),
+                }
+            }
+
+            thread::sleep(time::Duration::from_millis(250));
+        }
+    }))
+}
@@ -0,0 +1,105 @@
+//! Telemetry for command/response round trips.
+//!
+//! With the `tracing` feature, every round trip runs inside a `ssp_command` span, recording the
+//! command type, sequence ID, encrypted flag, duration, and response status.
+//!
+//! The rest of the crate logs through the `log` facade, so its messages are only recorded inside
+//! the span when `log` records are routed into `tracing`, see [init_log_bridge]. Otherwise the span
+//! only carries its own fields.
+//!
+//! Without the feature, the same fields are logged at the `trace` level when the round trip
+//! completes.
+
+use std::{fmt, time};
+
+use ssp::Result;
+
+/// Routes the `log` messages of the crate into `tracing`, so messages emitted during a round trip
+/// are recorded inside its `ssp_command` span.
+///
+/// Call once at startup, instead of installing a `log` logger, e.g. `env_logger`. Fails if a
+/// logger is already installed.
+#[cfg(feature = "tracing")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "tracing")))]
+pub fn init_log_bridge() -> Result<()> {
+    tracing_log::LogTracer::init().map_err(|err| ssp::Error::Io(format!("log bridge: {err}")))
+}
+
+/// Measures a single command/response round trip.
+pub(crate) struct CommandSpan {
+    start: time::Instant,
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+    #[cfg(not(feature = "tracing"))]
+    command: ssp::MessageType,
+    #[cfg(not(feature = "tracing"))]
+    encrypted: bool,
+}
+
+impl CommandSpan {
+    /// Enters the span for sending a `command`, before it is written to the device.
+    pub(crate) fn enter(command: ssp::MessageType, encrypted: bool) -> Self {
+        Self {
+            start: time::Instant::now(),
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "ssp_command",
+                command = %command,
+                encrypted,
+                seq_id = tracing::field::Empty,
+                duration_us = tracing::field::Empty,
+                status = tracing::field::Empty,
+                error = tracing::field::Empty,
+            )
+            .entered(),
+            #[cfg(not(feature = "tracing"))]
+            command,
+            #[cfg(not(feature = "tracing"))]
+            encrypted,
+        }
+    }
+
+    /// Records the result of the round trip, and leaves the span.
+    ///
+    /// `seq_id` is the sequence ID the command was sent with.
+    pub(crate) fn finish(self, seq_id: ssp::SequenceId, res: &Result<ssp::MessageVariant>) {
+        let duration_us = self.start.elapsed().as_micros() as u64;
+        // only formatted when logged
+        let outcome = Outcome(res);
+
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("seq_id", tracing::field::display(seq_id));
+            self.span.record("duration_us", duration_us);
+
+            match res {
+                Ok(res) => self.span.record(
+                    "status",
+                    tracing::field::display(res.as_response().response_status()),
+                ),
+                Err(err) => self.span.record("error", tracing::field::display(err)),
+            };
+
+            tracing::trace!("Round trip completed, {outcome}");
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        log::trace!(
+            "Round trip: {}, SEQID: {seq_id}, encrypted: {}, duration: {duration_us}us, {outcome}",
+            self.command,
+            self.encrypted,
+        );
+    }
+}
+
+// Outcome of a round trip, for log messages.
+struct Outcome<'a>(&'a Result<ssp::MessageVariant>);
+
+impl fmt::Display for Outcome<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Ok(res) => write!(f, "status: {}", res.as_response().response_status()),
+            Err(err) => write!(f, "error: {err}"),
+        }
+    }
+}
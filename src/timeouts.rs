@@ -0,0 +1,83 @@
+//! Response timeouts for commands sent to the device.
+//!
+//! After a command is written, the device has the response timeout of the command's
+//! [CommandClass] to start answering. Polls are answered quickly, so a lost poll is detected in
+//! hundreds of milliseconds, while payouts, and key negotiation may take seconds.
+//!
+//! Once the first byte of a response arrives, the rest of the frame is read with the
+//! [inter-byte timeout](crate::device_handle::INTER_BYTE_TIMEOUT_MS).
+//!
+//! Every class starts with its [default](default_response_timeout) timeout, configurable with
+//! [set_response_timeout].
+
+use std::time;
+
+use parking_lot::Mutex;
+
+use crate::device_handle::SERIAL_TIMEOUT_MS;
+use crate::retry::CommandClass;
+
+/// Default response timeout for poll commands (milliseconds).
+pub const POLL_TIMEOUT_MS: u64 = 500;
+/// Default response timeout for query commands (milliseconds).
+pub const QUERY_TIMEOUT_MS: u64 = 1_000;
+/// Default response timeout for control commands (milliseconds).
+pub const CONTROL_TIMEOUT_MS: u64 = 2_000;
+/// Default response timeout for payout commands (milliseconds).
+pub const PAYOUT_TIMEOUT_MS: u64 = SERIAL_TIMEOUT_MS;
+/// Default response timeout for encryption commands (milliseconds).
+pub const ENCRYPTION_TIMEOUT_MS: u64 = SERIAL_TIMEOUT_MS;
+/// Default response timeout for firmware commands (milliseconds).
+pub const FIRMWARE_TIMEOUT_MS: u64 = 30_000;
+
+static RESPONSE_TIMEOUTS: Mutex<[time::Duration; CommandClass::ALL.len()]> =
+    Mutex::new(default_response_timeouts());
+
+/// Gets the default response timeout of a command `class`.
+pub const fn default_response_timeout(class: CommandClass) -> time::Duration {
+    let ms = match class {
+        CommandClass::Poll => POLL_TIMEOUT_MS,
+        CommandClass::Query => QUERY_TIMEOUT_MS,
+        CommandClass::Control => CONTROL_TIMEOUT_MS,
+        CommandClass::Payout => PAYOUT_TIMEOUT_MS,
+        CommandClass::Encryption => ENCRYPTION_TIMEOUT_MS,
+        CommandClass::Firmware => FIRMWARE_TIMEOUT_MS,
+    };
+
+    time::Duration::from_millis(ms)
+}
+
+/// Gets the response timeout of a command `class`.
+pub fn response_timeout(class: CommandClass) -> time::Duration {
+    RESPONSE_TIMEOUTS.lock()[class as usize]
+}
+
+/// Sets the response timeout of a command `class`.
+///
+/// A zero `timeout` is ignored, since it would fail every read.
+pub fn set_response_timeout(class: CommandClass, timeout: time::Duration) {
+    if timeout.is_zero() {
+        log::warn!("Ignoring zero response timeout for {class} commands");
+        return;
+    }
+
+    log::debug!("Response timeout for {class} commands: {timeout:?}");
+    RESPONSE_TIMEOUTS.lock()[class as usize] = timeout;
+}
+
+/// Resets the response timeout of every command class to the default.
+pub fn reset_response_timeouts() {
+    *RESPONSE_TIMEOUTS.lock() = default_response_timeouts();
+}
+
+const fn default_response_timeouts() -> [time::Duration; CommandClass::ALL.len()] {
+    let mut timeouts = [time::Duration::ZERO; CommandClass::ALL.len()];
+
+    let mut i = 0;
+    while i < timeouts.len() {
+        timeouts[i] = default_response_timeout(CommandClass::ALL[i]);
+        i += 1;
+    }
+
+    timeouts
+}
@@ -0,0 +1,311 @@
+//! TLS termination for the network frontends.
+//!
+//! Cash-device control traffic must not cross the network in cleartext. With the `tls` feature,
+//! the [TcpBridge](crate::bridge::TcpBridge), HTTP, and gRPC frontends can serve over TLS, using
+//! PEM encoded certificates and keys.
+//!
+//! Setting a client CA enables client-certificate authentication: only clients presenting a
+//! certificate signed by the CA can connect.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::{fs, time};
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use ssp::Result;
+
+use crate::device_handle::SERIAL_TIMEOUT_MS;
+use crate::transport::Transport;
+
+/// Environment variable for the server certificate chain (PEM file path).
+pub const TLS_ENV_CERT: &str = "SSP_TLS_CERT";
+/// Environment variable for the server private key (PEM file path).
+pub const TLS_ENV_KEY: &str = "SSP_TLS_KEY";
+/// Environment variable for the CA authenticating client certificates (PEM file path).
+pub const TLS_ENV_CLIENT_CA: &str = "SSP_TLS_CLIENT_CA";
+
+/// Server-side TLS configuration.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TlsConfig {
+    cert_path: String,
+    key_path: String,
+    client_ca_path: Option<String>,
+}
+
+impl TlsConfig {
+    /// Creates a new [TlsConfig] from the paths of the PEM encoded certificate chain and private
+    /// key.
+    pub fn new(cert_path: &str, key_path: &str) -> Self {
+        Self {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+            client_ca_path: None,
+        }
+    }
+
+    /// Creates a new [TlsConfig] from the [TLS_ENV_CERT], [TLS_ENV_KEY], and
+    /// [TLS_ENV_CLIENT_CA] environment variables.
+    ///
+    /// Returns `Ok(None)` if no certificate is configured, and `Err(_)` if a certificate is
+    /// configured without a key.
+    pub fn from_env() -> Result<Option<Self>> {
+        let cert_path = match std::env::var(TLS_ENV_CERT) {
+            Ok(path) => path,
+            Err(_) => return Ok(None),
+        };
+
+        let key_path = std::env::var(TLS_ENV_KEY)
+            .map_err(|_| ssp::Error::Io(format!("{TLS_ENV_CERT} is set without {TLS_ENV_KEY}")))?;
+
+        let config = Self::new(cert_path.as_str(), key_path.as_str());
+
+        Ok(Some(match std::env::var(TLS_ENV_CLIENT_CA) {
+            Ok(ca_path) => config.with_client_ca(ca_path.as_str()),
+            Err(_) => config,
+        }))
+    }
+
+    /// Gets the path of the certificate chain.
+    pub fn cert_path(&self) -> &str {
+        self.cert_path.as_str()
+    }
+
+    /// Gets the path of the private key.
+    pub fn key_path(&self) -> &str {
+        self.key_path.as_str()
+    }
+
+    /// Gets the path of the CA authenticating client certificates, if any.
+    pub fn client_ca_path(&self) -> Option<&str> {
+        self.client_ca_path.as_deref()
+    }
+
+    /// Builder function that sets the CA authenticating client certificates.
+    ///
+    /// Clients without a certificate signed by the CA are rejected.
+    pub fn with_client_ca(mut self, ca_path: &str) -> Self {
+        self.client_ca_path = Some(ca_path.into());
+        self
+    }
+
+    /// Builds a [ServerConfig](rustls::ServerConfig) from the certificates and keys.
+    pub fn server_config(&self) -> Result<rustls::ServerConfig> {
+        let certs = load_certs(self.cert_path())?;
+        let key = load_key(self.key_path())?;
+
+        let builder = rustls::ServerConfig::builder();
+
+        let builder = match self.client_ca_path() {
+            Some(ca_path) => {
+                let verifier =
+                    rustls::server::WebPkiClientVerifier::builder(Arc::new(load_roots(ca_path)?))
+                        .build()
+                        .map_err(tls_error)?;
+
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        builder.with_single_cert(certs, key).map_err(tls_error)
+    }
+
+    /// Builds a TLS configuration for the gRPC server.
+    #[cfg(feature = "grpc")]
+    pub fn grpc_config(&self) -> Result<tonic::transport::ServerTlsConfig> {
+        let identity = tonic::transport::Identity::from_pem(
+            fs::read(self.cert_path())?,
+            fs::read(self.key_path())?,
+        );
+
+        let config = tonic::transport::ServerTlsConfig::new().identity(identity);
+
+        Ok(match self.client_ca_path() {
+            Some(ca_path) => {
+                config.client_ca_root(tonic::transport::Certificate::from_pem(fs::read(ca_path)?))
+            }
+            None => config,
+        })
+    }
+}
+
+/// Client-side TLS configuration, for connecting to a [TcpBridge](crate::bridge::TcpBridge)
+/// serving over TLS.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TlsClientConfig {
+    ca_path: String,
+    server_name: Option<String>,
+    identity: Option<(String, String)>,
+}
+
+impl TlsClientConfig {
+    /// Creates a new [TlsClientConfig] trusting the CA at `ca_path` (PEM file path).
+    pub fn new(ca_path: &str) -> Self {
+        Self {
+            ca_path: ca_path.into(),
+            server_name: None,
+            identity: None,
+        }
+    }
+
+    /// Gets the path of the trusted CA.
+    pub fn ca_path(&self) -> &str {
+        self.ca_path.as_str()
+    }
+
+    /// Gets the server name verified against the server certificate, if set.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Builder function that sets the server name verified against the server certificate.
+    ///
+    /// By default, the host of the connection address is used.
+    pub fn with_server_name(mut self, name: &str) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+
+    /// Builder function that sets the client certificate chain and private key (PEM file
+    /// paths), for servers requiring client-certificate authentication.
+    pub fn with_identity(mut self, cert_path: &str, key_path: &str) -> Self {
+        self.identity = Some((cert_path.into(), key_path.into()));
+        self
+    }
+
+    /// Builds a [ClientConfig](rustls::ClientConfig) from the certificates and keys.
+    pub fn client_config(&self) -> Result<rustls::ClientConfig> {
+        let builder =
+            rustls::ClientConfig::builder().with_root_certificates(load_roots(self.ca_path())?);
+
+        match self.identity.as_ref() {
+            Some((cert_path, key_path)) => builder
+                .with_client_auth_cert(load_certs(cert_path)?, load_key(key_path)?)
+                .map_err(tls_error),
+            None => Ok(builder.with_no_client_auth()),
+        }
+    }
+}
+
+/// [Transport] connected to a [TcpBridge](crate::bridge::TcpBridge) over TLS.
+pub struct TlsTransport {
+    stream: rustls::StreamOwned<rustls::ClientConnection, TcpStream>,
+}
+
+impl TlsTransport {
+    /// Connects to a [TcpBridge](crate::bridge::TcpBridge) serving TLS on `addr`.
+    pub fn connect(addr: &str, config: &TlsClientConfig) -> Result<Self> {
+        let host = addr.rsplit_once(':').map(|(host, _)| host).unwrap_or(addr);
+        let name = config.server_name().unwrap_or(host);
+
+        let server_name = ServerName::try_from(name.to_string()).map_err(tls_error)?;
+        let conn = rustls::ClientConnection::new(Arc::new(config.client_config()?), server_name)
+            .map_err(tls_error)?;
+
+        let sock = TcpStream::connect(addr)?;
+
+        let timeout = Some(time::Duration::from_millis(SERIAL_TIMEOUT_MS));
+
+        // SSP messages are small, send them immediately
+        sock.set_nodelay(true)?;
+        sock.set_read_timeout(timeout)?;
+        sock.set_write_timeout(timeout)?;
+
+        let mut stream = rustls::StreamOwned::new(conn, sock);
+
+        // complete the handshake, so certificate errors surface on connect
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.sock)?;
+        }
+
+        log::debug!("Connected to serial bridge at {addr} over TLS");
+
+        Ok(Self { stream })
+    }
+}
+
+impl Read for TlsTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stream.read(buf)? {
+            // a serial port never reaches EOF, so report the closed connection as an error
+            0 if !buf.is_empty() => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "serial bridge closed the connection",
+            )),
+            n => Ok(n),
+        }
+    }
+}
+
+impl Write for TlsTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for TlsTransport {
+    fn clear(&mut self) -> Result<()> {
+        self.discard_input()?;
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        let mut buf = [0u8; 256];
+        let mut discarded = 0;
+
+        self.stream.sock.set_nonblocking(true)?;
+
+        let res = loop {
+            match self.stream.read(&mut buf) {
+                Ok(n) if n > 0 => discarded += n,
+                Ok(_) => break Ok(discarded),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break Ok(discarded),
+                Err(err) => break Err(err),
+            }
+        };
+
+        self.stream.sock.set_nonblocking(false)?;
+
+        Ok(res?)
+    }
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let mut reader = io::BufReader::new(fs::File::open(path)?);
+
+    let certs = rustls_pemfile::certs(&mut reader).collect::<io::Result<Vec<_>>>()?;
+
+    if certs.is_empty() {
+        Err(ssp::Error::Io(format!("no certificates found in {path}")))
+    } else {
+        Ok(certs)
+    }
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let mut reader = io::BufReader::new(fs::File::open(path)?);
+
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or(ssp::Error::Io(format!("no private key found in {path}")))
+}
+
+fn load_roots(path: &str) -> Result<rustls::RootCertStore> {
+    let mut roots = rustls::RootCertStore::empty();
+
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(tls_error)?;
+    }
+
+    Ok(roots)
+}
+
+pub(crate) fn tls_error<E: std::fmt::Display>(err: E) -> ssp::Error {
+    ssp::Error::Io(format!("TLS error: {err}"))
+}
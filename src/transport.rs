@@ -0,0 +1,286 @@
+//! Byte transports for communicating with a SSP/eSSP device.
+//!
+//! Besides a local serial port, a device can be reached over TCP through a
+//! [TcpBridge](crate::bridge::TcpBridge) running on the host the device is attached to.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::{thread, time};
+
+use serialport::{SerialPort, TTYPort};
+use ssp::Result;
+
+use crate::device_handle::{BAUD_RATE, SERIAL_TIMEOUT_MS};
+
+/// Path prefix for connecting to a [TcpBridge](crate::bridge::TcpBridge) instead of a local
+/// serial device.
+pub const TCP_SCHEME: &str = "tcp://";
+
+static DISCARDED_BYTES: AtomicU64 = AtomicU64::new(0);
+static DISCARD_FLUSHES: AtomicU64 = AtomicU64::new(0);
+
+/// Raw byte stream used to exchange SSP messages with a device.
+pub trait Transport: Read + Write + Send {
+    /// Discards any data buffered in the transport, in both directions.
+    fn clear(&mut self) -> Result<()>;
+
+    /// Sets how long a read waits for data, before failing with a timeout.
+    ///
+    /// Used to apply an inter-byte timeout while reading a frame. The default implementation keeps
+    /// the transport's own timeout, e.g. for transports over a network, where parts of a frame
+    /// can be delayed.
+    fn set_read_timeout(&mut self, _timeout: time::Duration) -> Result<()> {
+        Ok(())
+    }
+
+    /// Discards any unread data received by the transport, e.g. a late response to a command
+    /// that timed out.
+    ///
+    /// Returns the number of discarded bytes. The default implementation discards nothing.
+    fn discard_input(&mut self) -> Result<usize> {
+        Ok(0)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn clear(&mut self) -> Result<()> {
+        (**self).clear()
+    }
+
+    fn set_read_timeout(&mut self, timeout: time::Duration) -> Result<()> {
+        (**self).set_read_timeout(timeout)
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        (**self).discard_input()
+    }
+}
+
+// Placeholder while the transport of a handle is being replaced, e.g. to wrap it.
+pub(crate) struct NullTransport;
+
+impl Read for NullTransport {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+}
+
+impl Write for NullTransport {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for NullTransport {
+    fn clear(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for TTYPort {
+    fn clear(&mut self) -> Result<()> {
+        SerialPort::clear(self, serialport::ClearBuffer::All)?;
+        Ok(())
+    }
+
+    fn set_read_timeout(&mut self, timeout: time::Duration) -> Result<()> {
+        SerialPort::set_timeout(self, timeout)?;
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        let pending = SerialPort::bytes_to_read(self)?;
+
+        if pending > 0 {
+            SerialPort::clear(self, serialport::ClearBuffer::Input)?;
+        }
+
+        Ok(pending as usize)
+    }
+}
+
+/// Opens a serial port with the settings required by the SSP protocol.
+///
+/// For details on the setup, see sections 5.4 & 7 in the SSP implementation guide.
+pub fn open_serial_port(serial_path: &str) -> Result<TTYPort> {
+    open_serial_port_with_baud_rate(serial_path, BAUD_RATE)
+}
+
+/// Opens a serial port with the settings required by the SSP protocol, at the `baud_rate`.
+///
+/// Devices run at [BAUD_RATE] by default, a few are configured for higher rates.
+pub fn open_serial_port_with_baud_rate(serial_path: &str, baud_rate: u32) -> Result<TTYPort> {
+    Ok(serialport::new(serial_path, baud_rate)
+        // disable flow control serial lines
+        .flow_control(serialport::FlowControl::None)
+        // eight-bit data size
+        .data_bits(serialport::DataBits::Eight)
+        // no control bit parity
+        .parity(serialport::Parity::None)
+        // two bit stop
+        .stop_bits(serialport::StopBits::Two)
+        // serial device times out after 10 seconds, so do we
+        .timeout(time::Duration::from_millis(SERIAL_TIMEOUT_MS))
+        // get back a TTY port for POSIX systems, Windows is not supported
+        .open_native()?)
+}
+
+/// [Transport] over a TCP connection to a [TcpBridge](crate::bridge::TcpBridge).
+pub struct TcpTransport {
+    stream: TcpStream,
+}
+
+impl TcpTransport {
+    /// Connects to a [TcpBridge](crate::bridge::TcpBridge) listening on `addr`.
+    pub fn connect(addr: &str) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+
+        log::debug!("Connected to serial bridge at {addr}");
+
+        Self::from_stream(stream)
+    }
+
+    /// Creates a [TcpTransport] from an already connected [TcpStream].
+    pub fn from_stream(stream: TcpStream) -> Result<Self> {
+        let timeout = Some(time::Duration::from_millis(SERIAL_TIMEOUT_MS));
+
+        // SSP messages are small, send them immediately
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+
+        Ok(Self { stream })
+    }
+
+    /// Gets a reference to the underlying [TcpStream].
+    pub fn stream(&self) -> &TcpStream {
+        &self.stream
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stream.read(buf)? {
+            // a serial port never reaches EOF, so report the closed connection as an error
+            0 if !buf.is_empty() => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "serial bridge closed the connection",
+            )),
+            n => Ok(n),
+        }
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn clear(&mut self) -> Result<()> {
+        self.discard_input()?;
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> Result<usize> {
+        let mut buf = [0u8; 256];
+        let mut discarded = 0;
+
+        self.stream.set_nonblocking(true)?;
+
+        let res = loop {
+            match self.stream.read(&mut buf) {
+                Ok(n) if n > 0 => discarded += n,
+                Ok(_) => break Ok(discarded),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break Ok(discarded),
+                Err(err) => break Err(err),
+            }
+        };
+
+        self.stream.set_nonblocking(false)?;
+
+        Ok(res?)
+    }
+}
+
+/// Counters of stale bytes discarded before writing commands.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiscardCounters {
+    /// Number of commands preceded by stale bytes.
+    pub flushes: u64,
+    /// Total number of discarded bytes.
+    pub bytes: u64,
+}
+
+/// Discards any stale bytes received by the `transport` before a new command is written, and
+/// counts them in the [DiscardCounters].
+///
+/// Returns the number of discarded bytes.
+pub fn discard_stale_input<T: Transport + ?Sized>(transport: &mut T) -> Result<usize> {
+    let discarded = transport.discard_input()?;
+
+    if discarded > 0 {
+        log::warn!("Discarded {discarded} stale bytes before writing a command");
+
+        DISCARD_FLUSHES.fetch_add(1, Ordering::Relaxed);
+        DISCARDED_BYTES.fetch_add(discarded as u64, Ordering::Relaxed);
+    }
+
+    Ok(discarded)
+}
+
+/// Discards input until the `transport` stays silent for `quiet`, or `max` elapses, e.g. while a
+/// device finishes sending the response to a command that timed out.
+///
+/// Returns the number of discarded bytes, counted in the [DiscardCounters].
+pub fn discard_until_quiet<T: Transport + ?Sized>(
+    transport: &mut T,
+    quiet: time::Duration,
+    max: time::Duration,
+) -> Result<usize> {
+    let start = time::Instant::now();
+    let mut discarded = transport.discard_input()?;
+
+    while start.elapsed() < max {
+        thread::sleep(quiet);
+
+        match transport.discard_input()? {
+            0 => break,
+            n => discarded += n,
+        }
+    }
+
+    if discarded > 0 {
+        log::warn!("Discarded {discarded} bytes while waiting for the bus to go quiet");
+
+        DISCARD_FLUSHES.fetch_add(1, Ordering::Relaxed);
+        DISCARDED_BYTES.fetch_add(discarded as u64, Ordering::Relaxed);
+    }
+
+    Ok(discarded)
+}
+
+/// Gets the [DiscardCounters] since the start, or the last [reset](reset_discard_counters).
+pub fn discard_counters() -> DiscardCounters {
+    DiscardCounters {
+        flushes: DISCARD_FLUSHES.load(Ordering::Relaxed),
+        bytes: DISCARDED_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+/// Resets the [DiscardCounters] to zero.
+pub fn reset_discard_counters() {
+    DISCARD_FLUSHES.store(0, Ordering::Relaxed);
+    DISCARDED_BYTES.store(0, Ordering::Relaxed);
+}
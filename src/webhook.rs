@@ -0,0 +1,213 @@
+//! Webhook [EventSink] for POSTing device events to HTTP endpoints.
+
+use std::{thread, time};
+
+use crossbeam::channel;
+use ssp::Result;
+
+use crate::sink::EventSink;
+
+/// Default maximum number of retries for a failed delivery.
+pub const WEBHOOK_MAX_RETRIES: u32 = 5;
+/// Default delay before the first retry (milliseconds), doubled for each following retry.
+pub const WEBHOOK_BACKOFF_MS: u64 = 500;
+/// Default timeout for a single delivery attempt (milliseconds).
+pub const WEBHOOK_TIMEOUT_MS: u64 = 5_000;
+/// Maximum number of events queued for delivery.
+pub const WEBHOOK_QUEUE_CAPACITY: usize = 1024;
+
+/// Events delivered by default: credits, cashbox removal, jams, and fraud attempts.
+pub const WEBHOOK_DEFAULT_METHODS: [ssp::Method; 4] = [
+    ssp::Method::NoteCredit,
+    ssp::Method::CashboxRemoved,
+    ssp::Method::UnsafeJam,
+    ssp::Method::FraudAttempt,
+];
+
+/// Configuration for a [WebhookSink].
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebhookConfig {
+    urls: Vec<String>,
+    methods: Vec<ssp::Method>,
+    max_retries: u32,
+    backoff: time::Duration,
+    timeout: time::Duration,
+}
+
+impl WebhookConfig {
+    /// Creates a new [WebhookConfig] delivering the default events to `url`.
+    pub fn new(url: &str) -> Self {
+        Self {
+            urls: vec![url.into()],
+            methods: WEBHOOK_DEFAULT_METHODS.into(),
+            max_retries: WEBHOOK_MAX_RETRIES,
+            backoff: time::Duration::from_millis(WEBHOOK_BACKOFF_MS),
+            timeout: time::Duration::from_millis(WEBHOOK_TIMEOUT_MS),
+        }
+    }
+
+    /// Gets the list of webhook URLs.
+    pub fn urls(&self) -> &[String] {
+        self.urls.as_ref()
+    }
+
+    /// Builder function that adds a webhook URL.
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.urls.push(url.into());
+        self
+    }
+
+    /// Gets the list of delivered event [Method](ssp::Method)s.
+    ///
+    /// An empty list delivers all events.
+    pub fn methods(&self) -> &[ssp::Method] {
+        self.methods.as_ref()
+    }
+
+    /// Builder function that sets the list of delivered event [Method](ssp::Method)s.
+    pub fn with_methods(mut self, methods: &[ssp::Method]) -> Self {
+        self.methods = methods.into();
+        self
+    }
+
+    /// Gets the maximum number of retries for a failed delivery.
+    pub const fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Builder function that sets the maximum number of retries for a failed delivery.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Gets the delay before the first retry.
+    pub const fn backoff(&self) -> time::Duration {
+        self.backoff
+    }
+
+    /// Builder function that sets the delay before the first retry.
+    pub fn with_backoff(mut self, backoff: time::Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Builder function that sets the timeout for a single delivery attempt.
+    pub fn with_timeout(mut self, timeout: time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Gets whether the [Event](ssp::Event) is delivered to the webhooks.
+    pub fn delivers(&self, event: &ssp::Event) -> bool {
+        self.methods.is_empty() || self.methods.contains(&event.method())
+    }
+}
+
+/// [EventSink] that POSTs JSON encoded events to one or more webhook URLs.
+///
+/// Deliveries run on a background thread, so slow endpoints do not block other sinks. Failed
+/// deliveries are retried with exponential backoff.
+pub struct WebhookSink {
+    config: WebhookConfig,
+    tx: Option<channel::Sender<String>>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl WebhookSink {
+    /// Creates a new [WebhookSink], and starts the delivery thread.
+    pub fn new(config: WebhookConfig) -> Self {
+        let (tx, rx) = channel::bounded::<String>(WEBHOOK_QUEUE_CAPACITY);
+
+        let agent = ureq::AgentBuilder::new().timeout(config.timeout).build();
+        let worker_config = config.clone();
+
+        let worker = thread::spawn(move || {
+            // exits once the sink is dropped, and the queue is drained
+            for body in rx.iter() {
+                for url in worker_config.urls() {
+                    if let Err(err) = deliver(&agent, &worker_config, url, body.as_str()) {
+                        log::error!("Failed to deliver webhook to {url}: {err}");
+                    }
+                }
+            }
+        });
+
+        Self {
+            config,
+            tx: Some(tx),
+            worker: Some(worker),
+        }
+    }
+
+    /// Gets a reference to the [WebhookConfig].
+    pub fn config(&self) -> &WebhookConfig {
+        &self.config
+    }
+}
+
+fn deliver(agent: &ureq::Agent, config: &WebhookConfig, url: &str, body: &str) -> Result<()> {
+    let mut backoff = config.backoff();
+    let mut attempt = 0;
+
+    loop {
+        let err = match agent
+            .post(url)
+            .set("Content-Type", "application/json")
+            .send_string(body)
+        {
+            Ok(_) => return Ok(()),
+            // client errors will not succeed on retry, except rate limiting
+            Err(ureq::Error::Status(code, _)) if (400..500).contains(&code) && code != 429 => {
+                return Err(ssp::Error::Io(format!(
+                    "webhook rejected with status {code}"
+                )));
+            }
+            Err(err) => err,
+        };
+
+        if attempt >= config.max_retries() {
+            return Err(ssp::Error::Io(format!(
+                "webhook failed after {attempt} retries: {err}"
+            )));
+        }
+
+        attempt += 1;
+        log::warn!("Webhook delivery to {url} failed: {err}, retry #{attempt} in {backoff:?}");
+
+        thread::sleep(backoff);
+        backoff = backoff.saturating_mul(2);
+    }
+}
+
+impl EventSink for WebhookSink {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn publish_event(&mut self, event: &ssp::Event) -> Result<()> {
+        if !self.config.delivers(event) {
+            return Ok(());
+        }
+
+        let body = serde_json::to_string(event)
+            .map_err(|err| ssp::Error::Io(format!("webhook event encoding error: {err}")))?;
+
+        self.tx
+            .as_ref()
+            .ok_or(ssp::Error::Io("webhook sink is closed".into()))?
+            .try_send(body)
+            .map_err(|err| ssp::Error::Io(format!("webhook queue error: {err}")))
+    }
+}
+
+impl Drop for WebhookSink {
+    fn drop(&mut self) {
+        // close the queue, and let the worker finish pending deliveries
+        self.tx.take();
+
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
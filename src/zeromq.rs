@@ -0,0 +1,230 @@
+//! ZeroMQ frontend for controlling a SSP/eSSP device over the network.
+//!
+//! Commands are received as JSON-RPC requests on a `REP` socket, using the same methods as the
+//! Unix socket server. Device events are published on a `PUB` socket as two-part messages: the
+//! event method as the topic frame, followed by a JSON-RPC push request.
+//!
+//! `REP` sockets do not identify clients, so ZeroMQ clients can not claim a
+//! [lease](crate::lease), and state-changing commands are rejected while a client of another
+//! frontend holds the lease.
+//!
+//! With [authentication](crate::auth) enabled, clients send the bearer token as a leading frame,
+//! followed by the request frame.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use smol_jsonrpc::{Error as RpcError, Request, Response};
+use ssp::Result;
+
+use crate::{audit, DeviceHandle, PushEventReceiver, Server};
+
+/// Default endpoint for the command `REP` socket.
+pub const ZMQ_REP_ENDPOINT: &str = "tcp://127.0.0.1:5555";
+/// Default endpoint for the event `PUB` socket.
+pub const ZMQ_PUB_ENDPOINT: &str = "tcp://127.0.0.1:5556";
+/// Environment variable for overriding the command socket endpoint.
+pub const ZMQ_ENV_REP_ENDPOINT: &str = "SSP_ZMQ_REP_ENDPOINT";
+/// Environment variable for overriding the event socket endpoint.
+pub const ZMQ_ENV_PUB_ENDPOINT: &str = "SSP_ZMQ_PUB_ENDPOINT";
+
+// Timeout (in milliseconds) for polling the command socket.
+const POLL_TIMEOUT_MS: i64 = 50;
+
+/// Configuration for a [ZmqServer].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ZmqConfig {
+    rep_endpoint: String,
+    pub_endpoint: String,
+}
+
+impl ZmqConfig {
+    /// Creates a new [ZmqConfig] with the default endpoints.
+    pub fn new() -> Self {
+        Self {
+            rep_endpoint: ZMQ_REP_ENDPOINT.into(),
+            pub_endpoint: ZMQ_PUB_ENDPOINT.into(),
+        }
+    }
+
+    /// Creates a new [ZmqConfig] from the environment, falling back to the default endpoints.
+    pub fn from_env() -> Self {
+        Self {
+            rep_endpoint: std::env::var(ZMQ_ENV_REP_ENDPOINT).unwrap_or(ZMQ_REP_ENDPOINT.into()),
+            pub_endpoint: std::env::var(ZMQ_ENV_PUB_ENDPOINT).unwrap_or(ZMQ_PUB_ENDPOINT.into()),
+        }
+    }
+
+    /// Gets the command `REP` socket endpoint.
+    pub fn rep_endpoint(&self) -> &str {
+        self.rep_endpoint.as_str()
+    }
+
+    /// Builder function that sets the command `REP` socket endpoint.
+    pub fn with_rep_endpoint(mut self, endpoint: &str) -> Self {
+        self.rep_endpoint = endpoint.into();
+        self
+    }
+
+    /// Gets the event `PUB` socket endpoint.
+    pub fn pub_endpoint(&self) -> &str {
+        self.pub_endpoint.as_str()
+    }
+
+    /// Builder function that sets the event `PUB` socket endpoint.
+    pub fn with_pub_endpoint(mut self, endpoint: &str) -> Self {
+        self.pub_endpoint = endpoint.into();
+        self
+    }
+}
+
+impl Default for ZmqConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// ZeroMQ server with a `REP` socket for commands, and a `PUB` socket for device events.
+pub struct ZmqServer {
+    handle: Arc<Mutex<DeviceHandle>>,
+    // keep the context alive for the lifetime of the sockets
+    _context: zmq::Context,
+    rep: zmq::Socket,
+    publisher: zmq::Socket,
+}
+
+impl ZmqServer {
+    /// Creates a new [ZmqServer], and binds the command and event sockets.
+    ///
+    /// # Parameters
+    ///
+    /// - `handle`: shared [DeviceHandle] used to send commands to the device
+    /// - `config`: socket endpoint configuration
+    pub fn new(handle: Arc<Mutex<DeviceHandle>>, config: &ZmqConfig) -> Result<Self> {
+        let context = zmq::Context::new();
+
+        let rep = context.socket(zmq::REP).map_err(zmq_error)?;
+        rep.bind(config.rep_endpoint()).map_err(zmq_error)?;
+
+        let publisher = context.socket(zmq::PUB).map_err(zmq_error)?;
+        publisher.bind(config.pub_endpoint()).map_err(zmq_error)?;
+
+        log::info!(
+            "Serving ZeroMQ commands on {}, events on {}",
+            config.rep_endpoint(),
+            config.pub_endpoint()
+        );
+
+        Ok(Self {
+            handle,
+            _context: context,
+            rep,
+            publisher,
+        })
+    }
+
+    /// Serves command requests, and publishes events from the `push_queue` until `stop` is set.
+    pub fn serve(&self, push_queue: &PushEventReceiver, stop: &AtomicBool) -> Result<()> {
+        while !stop.load(Ordering::Relaxed) {
+            if self
+                .rep
+                .poll(zmq::POLLIN, POLL_TIMEOUT_MS)
+                .map_err(zmq_error)?
+                > 0
+            {
+                let frames = self.rep.recv_multipart(0).map_err(zmq_error)?;
+
+                // requests are optionally preceded by a token frame
+                let res = match frames.as_slice() {
+                    [msg] => self.on_frames(None, msg),
+                    [token, msg] => self.on_frames(Some(token.as_slice()), msg),
+                    _ => error_response(
+                        None,
+                        &ssp::Error::JsonRpc(
+                            "expected an optional token frame, and a request frame".into(),
+                        ),
+                    ),
+                };
+
+                let res_str = serde_json::to_string(&res)?;
+                self.rep.send(res_str.as_str(), 0).map_err(zmq_error)?;
+            }
+
+            while let Ok(event) = push_queue.pop_event() {
+                self.publish(&event)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Publishes a device [Event](ssp::Event) on the `PUB` socket.
+    ///
+    /// The event method is sent as the topic frame, so subscribers can filter by method.
+    pub fn publish(&self, event: &ssp::Event) -> Result<()> {
+        log::debug!("Publishing event: {event}");
+
+        let method = event.method().to_str();
+        let push_req = Request::new().with_method(method).with_params(event);
+        let push_str = serde_json::to_string(&push_req)?;
+
+        self.publisher
+            .send_multipart([method.as_bytes(), push_str.as_bytes()], 0)
+            .map_err(zmq_error)
+    }
+
+    fn on_frames(&self, token: Option<&[u8]>, msg: &[u8]) -> Response {
+        let token = token.map(std::str::from_utf8).transpose();
+
+        match (token, std::str::from_utf8(msg)) {
+            (Ok(token), Ok(msg)) => self.on_request(token, msg),
+            _ => error_response(
+                None,
+                &ssp::Error::JsonRpc("expected UTF-8 encoded request".into()),
+            ),
+        }
+    }
+
+    fn on_request(&self, token: Option<&str>, msg: &str) -> Response {
+        log::debug!("Received ZeroMQ message: {msg}");
+
+        let req = match serde_json::from_str::<Request>(msg) {
+            Ok(req) => req,
+            Err(err) => {
+                log::warn!("Expected valid JSON-RPC request, error: {err}");
+                return error_response(None, &ssp::Error::JsonRpc(format!("{err}")));
+            }
+        };
+
+        let method = ssp::Event::from(&req).method();
+
+        match Server::lock_handle(&self.handle) {
+            Ok(mut handle) => match handle.auth().authorize(token, method) {
+                // REP sockets do not identify clients, so requests are anonymous
+                Ok(role) => audit::with_actor(format!("zmq:{role}").as_str(), || {
+                    handle.on_client_request(None, &req)
+                }),
+                Err(err) => {
+                    log::warn!("ZeroMQ request denied: {err}");
+                    error_response(req.id(), &err.into())
+                }
+            },
+            Err(err) => error_response(req.id(), &err),
+        }
+    }
+}
+
+fn error_response(id: Option<u64>, err: &ssp::Error) -> Response {
+    let res = Response::new().with_error(RpcError::from(err));
+
+    match id {
+        Some(id) => res.with_id(id),
+        None => res,
+    }
+}
+
+fn zmq_error(err: zmq::Error) -> ssp::Error {
+    ssp::Error::Io(format!("ZeroMQ error: {err}"))
+}
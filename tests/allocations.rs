@@ -0,0 +1,124 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use ssp_server::frame_log;
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+// Counts heap allocations of the whole test binary.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const STX: u8 = 0x7f;
+
+// Transport answering every poll with OK, without allocating.
+struct PollTransport {
+    reply: [u8; 6],
+    pos: usize,
+    polls: Arc<AtomicUsize>,
+}
+
+impl PollTransport {
+    fn new(polls: Arc<AtomicUsize>) -> Self {
+        let mut reply = [STX, 0x80, 0x01, 0xf0, 0, 0];
+        let crc = ssp::crc::crc16(&reply[1..4]);
+        reply[4..].copy_from_slice(crc.to_le_bytes().as_ref());
+
+        Self {
+            reply,
+            pos: reply.len(),
+            polls,
+        }
+    }
+}
+
+impl Read for PollTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.reply.len() - self.pos);
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        buf[..n].copy_from_slice(&self.reply[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+impl Write for PollTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.polls.fetch_add(1, Ordering::SeqCst);
+        self.pos = 0;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for PollTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_polling_does_not_allocate() -> ssp::Result<()> {
+    let polls = Arc::new(AtomicUsize::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    // a small frame log fills up after a few polls
+    frame_log::set_frame_log_capacity(4);
+
+    let handle = DeviceHandle::from_transport(PollTransport::new(Arc::clone(&polls)))?;
+    handle.start_background_polling(Arc::clone(&stop))?;
+
+    // fill the buffers, and the frame log
+    while polls.load(Ordering::SeqCst) < 4 {
+        thread::sleep(time::Duration::from_millis(50));
+    }
+
+    let start_polls = polls.load(Ordering::SeqCst);
+    let start_allocations = ALLOCATIONS.load(Ordering::SeqCst);
+
+    thread::sleep(time::Duration::from_millis(2_000));
+
+    let allocations = ALLOCATIONS.load(Ordering::SeqCst) - start_allocations;
+    let steady_polls = polls.load(Ordering::SeqCst) - start_polls;
+
+    stop.store(true, Ordering::SeqCst);
+
+    assert!(steady_polls >= 2, "{steady_polls} polls");
+    assert_eq!(
+        allocations, 0,
+        "{allocations} allocations in {steady_polls} polls"
+    );
+
+    Ok(())
+}
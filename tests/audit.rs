@@ -0,0 +1,89 @@
+use ssp_server::audit::{self, AuditLog, AuditOp, AuditQuery};
+
+#[test]
+fn test_audit_log() {
+    let path = std::env::temp_dir().join(format!("ssp-audit-{}.log", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+
+    let log = AuditLog::open(path).unwrap();
+
+    let ok: ssp::Result<()> = Ok(());
+    let err: ssp::Result<()> = Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet));
+
+    let first = log.record(AuditOp::Payout, "2x 20 EUR", &ok).unwrap();
+    assert_eq!(first.actor, audit::AUDIT_LOCAL_ACTOR);
+
+    audit::with_actor("http:maintainer", || {
+        log.record(AuditOp::Empty, "tab\there\nnewline", &err)
+            .unwrap();
+    });
+    assert_eq!(audit::actor(), None);
+
+    // records survive reopening, and sequence numbers continue
+    let log = AuditLog::open(path).unwrap();
+    let last = log.record(AuditOp::KeyNegotiation, "", &ok).unwrap();
+    assert_eq!(last.seq, 2);
+
+    let all = log.query(&AuditQuery::new()).unwrap();
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[0], first);
+    assert_eq!(all[1].params, "tab\there\nnewline");
+    assert!(!all[1].is_ok());
+
+    let empties = log
+        .query(&AuditQuery::new().with_actor("http:maintainer"))
+        .unwrap();
+    assert_eq!(empties.len(), 1);
+    assert_eq!(empties[0].op, AuditOp::Empty);
+
+    let recent = log.query(&AuditQuery::new().with_limit(1)).unwrap();
+    assert_eq!(recent, vec![last]);
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn test_note_audit_trail() {
+    use ssp_server::audit::NoteRecord;
+
+    let path = std::env::temp_dir().join(format!("ssp-audit-notes-{}.log", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+
+    let log = AuditLog::open(path).unwrap();
+
+    let first = NoteRecord::new(2, 10, 8, 1234);
+    let second = NoteRecord::new(4, 50, 8, 1234).with_note_serial("AB 1234567");
+    assert_eq!(second.note_serial.as_deref(), Some("AB1234567"));
+
+    let record = log.record_note(&first).unwrap();
+    assert_eq!(record.op, AuditOp::NoteCredit);
+    assert_eq!(record.actor, audit::AUDIT_DEVICE_ACTOR);
+    assert_eq!(NoteRecord::from_record(&record), Some(first.clone()));
+
+    log.record(AuditOp::Payout, "1x 10 EUR", &Ok(())).unwrap();
+    log.record_note(&second).unwrap();
+
+    let notes = log.notes(&AuditQuery::new()).unwrap();
+    assert_eq!(notes.len(), 2);
+    assert_eq!(notes[0].1, first);
+    assert_eq!(notes[1].1, second);
+
+    // the query operation is ignored
+    let notes = log
+        .notes(&AuditQuery::new().with_op(AuditOp::Payout).with_limit(1))
+        .unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].1, second);
+
+    assert_eq!(NoteRecord::from_params("channel=x"), None);
+
+    // records written before the note serial number was added
+    assert_eq!(
+        NoteRecord::from_params("channel=2 value=10 protocol=8 serial=1234"),
+        Some(first)
+    );
+
+    let _ = std::fs::remove_file(path);
+}
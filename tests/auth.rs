@@ -0,0 +1,43 @@
+use ssp_server::auth::{self, Authenticator, Role};
+use ssp_server::Error;
+
+#[test]
+fn test_authorize() {
+    let auth = Authenticator::parse("observer:kiosk, operator:till\nmaintainer:office").unwrap();
+
+    assert!(auth.is_enabled());
+    assert_eq!(auth.authenticate(Some("kiosk")).unwrap(), Role::Observer);
+    assert_eq!(auth.authenticate(Some("office")).unwrap(), Role::Maintainer);
+
+    let err = auth.authenticate(Some("guess")).unwrap_err();
+    assert!(matches!(err, Error::Unauthenticated(_)), "{err}");
+    assert!(matches!(
+        auth.authenticate(None),
+        Err(Error::Unauthenticated(_))
+    ));
+
+    assert!(auth.authorize(Some("kiosk"), ssp::Method::Status).is_ok());
+    assert!(matches!(
+        auth.authorize(Some("kiosk"), ssp::Method::Enable),
+        Err(Error::PermissionDenied(_))
+    ));
+    assert!(auth.authorize(Some("till"), ssp::Method::Stack).is_ok());
+    assert!(matches!(
+        auth.authorize(Some("till"), ssp::Method::Dispense),
+        Err(Error::PermissionDenied(_))
+    ));
+    assert!(auth
+        .authorize(Some("office"), ssp::Method::Dispense)
+        .is_ok());
+
+    // without tokens, all clients are maintainers
+    let open = Authenticator::new();
+    assert!(!open.is_enabled());
+    assert!(open.authorize(None, ssp::Method::Dispense).is_ok());
+
+    assert!(Authenticator::parse("admin:token").is_err());
+    assert!(Authenticator::parse("token").is_err());
+
+    assert_eq!(auth::bearer_token("Bearer kiosk"), Some("kiosk"));
+    assert_eq!(auth::bearer_token("Basic a2lvc2s="), None);
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use std::{thread, time};
+
+use ssp_server::broadcast::{Broadcast, OverflowPolicy};
+
+#[test]
+fn test_drop_oldest() {
+    let broadcast = Broadcast::new(2, OverflowPolicy::DropOldest);
+    let fast = broadcast.subscribe();
+    let slow = broadcast.subscribe();
+
+    for event in 0..2 {
+        assert_eq!(broadcast.send(event), 2);
+        assert_eq!(fast.try_recv(), Some(event));
+    }
+
+    // the slow subscriber never grows beyond its capacity, nor holds back the fast one
+    assert_eq!(broadcast.send(2), 2);
+    assert_eq!(fast.try_recv(), Some(2));
+
+    assert_eq!(slow.len(), 2);
+    assert_eq!(slow.dropped(), 1);
+    assert_eq!(slow.try_recv(), Some(1));
+    assert_eq!(slow.try_recv(), Some(2));
+    assert_eq!(slow.try_recv(), None);
+}
+
+#[test]
+fn test_drop_newest() {
+    let broadcast = Broadcast::new(2, OverflowPolicy::DropNewest);
+    let slow = broadcast.subscribe();
+
+    assert_eq!(broadcast.send(0), 1);
+    assert_eq!(broadcast.send(1), 1);
+    assert_eq!(broadcast.send(2), 0);
+
+    assert_eq!(slow.dropped(), 1);
+    assert_eq!(slow.try_recv(), Some(0));
+    assert_eq!(slow.try_recv(), Some(1));
+    assert!(slow.is_empty());
+}
+
+#[test]
+fn test_block() {
+    let broadcast = Arc::new(Broadcast::new(1, OverflowPolicy::Block));
+    let slow = broadcast.subscribe();
+
+    let sender = {
+        let broadcast = Arc::clone(&broadcast);
+        thread::spawn(move || (0..4).map(|event| broadcast.send(event)).sum::<usize>())
+    };
+
+    let timeout = time::Duration::from_secs(5);
+    let received: Vec<_> = (0..4).filter_map(|_| slow.recv_timeout(timeout)).collect();
+
+    // nothing is dropped, the sender waits for the subscriber instead
+    assert_eq!(received, [0, 1, 2, 3]);
+    assert_eq!(sender.join().unwrap(), 4);
+    assert_eq!(slow.dropped(), 0);
+}
+
+#[test]
+fn test_unsubscribe_releases_sender() {
+    let broadcast = Arc::new(Broadcast::new(1, OverflowPolicy::Block));
+    let slow = broadcast.subscribe();
+    assert_eq!(broadcast.send(0), 1);
+
+    let sender = {
+        let broadcast = Arc::clone(&broadcast);
+        thread::spawn(move || broadcast.send(1))
+    };
+
+    thread::sleep(time::Duration::from_millis(50));
+    drop(slow);
+
+    assert_eq!(sender.join().unwrap(), 0);
+    assert_eq!(broadcast.subscribers(), 0);
+}
+
+#[test]
+fn test_overflow_policy_names() {
+    for policy in [
+        OverflowPolicy::DropOldest,
+        OverflowPolicy::DropNewest,
+        OverflowPolicy::Block,
+    ] {
+        assert_eq!(OverflowPolicy::from_name(policy.as_str()), Some(policy));
+    }
+
+    assert_eq!(
+        OverflowPolicy::from_name("DROP_NEWEST"),
+        Some(OverflowPolicy::DropNewest)
+    );
+    assert_eq!(OverflowPolicy::from_name("unbounded"), None);
+}
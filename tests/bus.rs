@@ -0,0 +1,108 @@
+#![cfg(feature = "emulator")]
+
+use ssp::MessageOps;
+use ssp_server::emulator::{BusTransport, Emulator, EmulatorBus};
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::frame;
+
+const SERIAL_NUMBER: u8 = 0x0c;
+const SYNC: u8 = 0x11;
+const OK: u8 = 0xf0;
+const READ: u8 = 0xef;
+
+const VALIDATOR: u8 = 0x00;
+const HOPPER: u8 = 0x10;
+
+fn bus() -> ssp::Result<EmulatorBus> {
+    let mut bus = EmulatorBus::new();
+
+    bus.add(Emulator::new().with_serial_number(0x0a0b_0c0d))?;
+    bus.add(
+        Emulator::new()
+            .with_address(HOPPER)
+            .with_unit_type(0x03)
+            .with_serial_number(0x1122_3344),
+    )?;
+
+    Ok(bus)
+}
+
+#[test]
+fn test_bus_addressing() -> ssp::Result<()> {
+    let bus = bus()?;
+
+    assert_eq!(bus.addresses(), [VALIDATOR, HOPPER]);
+    assert!(bus.device(HOPPER | 0x80).is_some());
+    assert!(bus.device(0x20).is_none());
+
+    // only the addressed device answers, each tracking its own sequence flag
+    assert_eq!(
+        bus.receive(&frame(0x80 | HOPPER, &[SYNC])),
+        frame(0x80 | HOPPER, &[OK])
+    );
+    assert_eq!(
+        bus.receive(&frame(HOPPER, &[SERIAL_NUMBER])),
+        frame(HOPPER, &[OK, 0x11, 0x22, 0x33, 0x44])
+    );
+
+    assert_eq!(
+        bus.receive(&frame(0x80 | VALIDATOR, &[SYNC])),
+        frame(0x80 | VALIDATOR, &[OK])
+    );
+    assert_eq!(
+        bus.receive(&frame(VALIDATOR, &[SERIAL_NUMBER])),
+        frame(VALIDATOR, &[OK, 0x0a, 0x0b, 0x0c, 0x0d])
+    );
+
+    // nobody answers at an empty address
+    assert!(bus.receive(&frame(0x80 | 0x20, &[SYNC])).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_address() -> ssp::Result<()> {
+    let mut bus = bus()?;
+
+    assert!(bus.add(Emulator::new().with_address(HOPPER)).is_err());
+    assert_eq!(bus.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_handle_on_bus() -> ssp::Result<()> {
+    let bus = bus()?;
+    let validator = bus
+        .device(VALIDATOR)
+        .cloned()
+        .expect("validator on the bus");
+    let hopper = bus.device(HOPPER).cloned().expect("hopper on the bus");
+
+    let handle = DeviceHandle::from_transport(BusTransport::new(bus))?;
+
+    handle.sync()?;
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    assert_eq!(
+        handle.serial_number()?.serial_number().as_inner(),
+        0x0a0b_0c0d
+    );
+
+    // the hopper stays silent, and untouched
+    assert!(validator.lock().enabled());
+    assert!(!hopper.lock().enabled());
+
+    // the hopper keeps its power-on reset event, nobody polled it
+    while validator.lock().pending_events() > 0 {
+        handle.poll()?;
+    }
+    assert_eq!(hopper.lock().pending_events(), 1);
+
+    validator.lock().credit(1)?;
+    assert_eq!(handle.poll()?.data()[1..3], [READ, 1]);
+
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use ssp_server::framing::MAX_RETRANSMISSIONS;
+use ssp_server::transport::{self, Transport};
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::frame;
+
+const SYNC: u8 = 0x11;
+const DISPLAY_ON: u8 = 0x03;
+
+// Transport answering the first writes with a response that only arrives after the read timed
+// out, and every later write with OK.
+struct SlowTransport {
+    slow: usize,
+    wire: VecDeque<u8>,
+    late: VecDeque<u8>,
+    writes: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl Read for SlowTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 {
+            // the response trickles in after the read timed out
+            self.wire.append(&mut self.late);
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for SlowTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes.lock().push(buf.to_vec());
+
+        if self.slow > 0 {
+            self.slow -= 1;
+            self.late.extend(frame(buf[1] & 0x80, &[0xf0]));
+        } else {
+            self.wire.extend(frame(0x80, &[0xf0]));
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for SlowTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> ssp::Result<usize> {
+        Ok(self.wire.drain(..).count())
+    }
+}
+
+fn command(frame: &[u8]) -> u8 {
+    frame[3]
+}
+
+fn seq_flag(frame: &[u8]) -> bool {
+    frame[1] & 0x80 != 0
+}
+
+#[test]
+fn test_bus_recovery() -> ssp::Result<()> {
+    let writes = Arc::new(Mutex::new(Vec::new()));
+    let slow = MAX_RETRANSMISSIONS as usize + 1;
+    let handle = DeviceHandle::from_transport(SlowTransport {
+        slow,
+        wire: VecDeque::new(),
+        late: VecDeque::new(),
+        writes: Arc::clone(&writes),
+    })?;
+
+    let before = transport::discard_counters();
+
+    // every response arrives too late
+    assert!(handle.display_on().is_err());
+    assert_eq!(writes.lock().len(), slow);
+
+    handle.display_on()?;
+    handle.display_on()?;
+
+    // the late bytes are discarded, before resynchronizing the sequence flag with a Sync
+    assert!(transport::discard_counters().bytes > before.bytes);
+
+    let writes = writes.lock();
+    let recovered = &writes[slow..];
+    assert_eq!(recovered.len(), 3);
+
+    assert_eq!(command(&recovered[0]), SYNC);
+    assert!(seq_flag(&recovered[0]));
+
+    // the next command starts from a known sequence flag, and only the first one is recovered
+    assert_eq!(command(&recovered[1]), DISPLAY_ON);
+    assert!(!seq_flag(&recovered[1]));
+    assert_eq!(command(&recovered[2]), DISPLAY_ON);
+    assert!(seq_flag(&recovered[2]));
+
+    Ok(())
+}
+
+#[test]
+fn test_failed_recovery() -> ssp::Result<()> {
+    let writes = Arc::new(Mutex::new(Vec::new()));
+    let slow = 2 * (MAX_RETRANSMISSIONS as usize + 1);
+    let handle = DeviceHandle::from_transport(SlowTransport {
+        slow,
+        wire: VecDeque::new(),
+        late: VecDeque::new(),
+        writes: Arc::clone(&writes),
+    })?;
+
+    assert!(handle.display_on().is_err());
+
+    // the Sync times out, so the command is never sent on a desynced bus
+    assert!(handle.display_on().is_err());
+    assert!(writes.lock()[slow / 2..]
+        .iter()
+        .all(|frame| command(frame) == SYNC));
+
+    // the bus is still dirty, and recovered before the next command
+    handle.display_on()?;
+
+    let writes = writes.lock();
+    assert_eq!(command(&writes[slow]), SYNC);
+    assert_eq!(command(&writes[slow + 1]), DISPLAY_ON);
+
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+use ssp_server::capture::{
+    self, CaptureDirection, CaptureFile, CaptureRecord, CaptureTransport, ReplayTransport,
+};
+use ssp_server::transport::Transport;
+use ssp_server::{framing, DeviceHandle};
+
+mod common;
+
+use common::{frame, STX};
+
+fn capture_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("ssp-capture-{name}-{}.log", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    path
+}
+
+// Transport replying with canned chunks, at most one per read.
+struct ReplyTransport {
+    replies: VecDeque<Vec<u8>>,
+    written: Vec<u8>,
+}
+
+impl Read for ReplyTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut reply = self.replies.pop_front().ok_or(io::ErrorKind::TimedOut)?;
+        let len = reply.len().min(buf.len());
+        buf[..len].copy_from_slice(&reply[..len]);
+
+        // keep the rest of the chunk for the next read
+        if len < reply.len() {
+            self.replies.push_front(reply.split_off(len));
+        }
+
+        Ok(len)
+    }
+}
+
+impl Write for ReplyTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for ReplyTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_capture_transport() -> ssp::Result<()> {
+    let path = capture_path("transport");
+
+    let sync = frame(0x80, &[0x11]);
+    let ok = frame(0x80, &[0xf0]);
+
+    let inner = ReplyTransport {
+        replies: [ok[..2].to_vec(), ok[2..].to_vec()].into(),
+        written: Vec::new(),
+    };
+
+    let mut transport = CaptureTransport::new(inner, CaptureFile::create(&path)?);
+    assert_eq!(transport.capture().path(), path.as_str());
+
+    transport.clear()?;
+    transport.write_all(&sync)?;
+
+    let mut buf = [0u8; 16];
+    let mut read = Vec::new();
+    while read.len() < ok.len() {
+        let n = transport.read(&mut buf)?;
+        read.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(read, ok);
+    assert!(transport.read(&mut buf).is_err());
+
+    assert_eq!(transport.into_inner().written, sync);
+
+    let records = capture::read_capture(&path)?;
+    let directions: Vec<CaptureDirection> = records.iter().map(|r| r.direction).collect();
+    assert_eq!(
+        directions,
+        [
+            CaptureDirection::Clear,
+            CaptureDirection::Tx,
+            CaptureDirection::Rx,
+            CaptureDirection::Rx
+        ]
+    );
+    assert_eq!(records[1].data, sync);
+    assert!(records
+        .windows(2)
+        .all(|w| w[0].elapsed_us <= w[1].elapsed_us));
+
+    // chunks are reassembled into frames
+    let frames = capture::frames(&records);
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].direction, CaptureDirection::Tx);
+    assert_eq!(frames[0].frame, sync);
+    assert_eq!(frames[0].sequence_id(), Some(0x80));
+    assert_eq!(frames[0].data(), [0x11]);
+    assert_eq!(frames[1].direction, CaptureDirection::Rx);
+    assert_eq!(frames[1].frame, ok);
+    assert!(frames
+        .iter()
+        .all(|f| f.error.is_none() && !f.is_encrypted()));
+
+    let printed = capture::pretty_print(&records);
+    assert_eq!(printed.lines().count(), 2);
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
+
+#[test]
+fn test_capture_frames() -> ssp::Result<()> {
+    // STX bytes in the data, and CRC fields are stuffed on the wire
+    let stuffed_frame = frame(0x00, &[0xf0, STX, 0x01]);
+    let stuffed = framing::stuff(&stuffed_frame)?;
+    assert!(stuffed.len() > stuffed_frame.len());
+
+    let (head, _) = stuffed.split_at(4);
+
+    let records = vec![
+        CaptureRecord::new(0, CaptureDirection::Tx, &frame(0x80, &[0x07])),
+        CaptureRecord::new(10, CaptureDirection::Rx, head),
+        // garbage left in the receive buffer is discarded by a clear
+        CaptureRecord::new(20, CaptureDirection::Clear, &[]),
+        CaptureRecord::new(30, CaptureDirection::Rx, &stuffed),
+        CaptureRecord::new(40, CaptureDirection::Rx, &[0x42]),
+    ];
+
+    let frames = capture::frames(&records);
+    assert_eq!(frames.len(), 3);
+
+    assert_eq!(frames[0].direction, CaptureDirection::Tx);
+    assert_eq!(frames[0].data(), [0x07]);
+
+    assert_eq!(frames[1].elapsed_us, 30);
+    assert_eq!(frames[1].frame, stuffed_frame);
+    assert_eq!(frames[1].data(), [0xf0, STX, 0x01]);
+    assert!(frames[1].error.is_none());
+
+    // bytes not starting a frame are reported, not dropped
+    assert_eq!(frames[2].frame, [0x42]);
+    assert!(frames[2].error.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_capture_file_invalid() -> ssp::Result<()> {
+    let path = capture_path("invalid");
+
+    std::fs::write(&path, "# ssp-capture v1 0\n0\ttx\t7f80\n\n10\tup\t7f\n")?;
+    assert!(capture::read_capture(&path).is_err());
+
+    std::fs::write(&path, "# ssp-capture v1 0\n0\ttx\t7f8\n")?;
+    assert!(capture::read_capture(&path).is_err());
+
+    std::fs::write(&path, "# ssp-capture v1 0\n0\ttx\t7f80\n\n10\tclear\t\n")?;
+    let records = capture::read_capture(&path)?;
+    assert_eq!(
+        records,
+        [
+            CaptureRecord::new(0, CaptureDirection::Tx, &[0x7f, 0x80]),
+            CaptureRecord::new(10, CaptureDirection::Clear, &[]),
+        ]
+    );
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
+
+#[test]
+fn test_capture_replay() -> ssp::Result<()> {
+    let path = capture_path("replay");
+
+    // capture a session with a device replying OK to a sync
+    let device = ReplyTransport {
+        replies: [frame(0x80, &[0xf0])].into(),
+        written: Vec::new(),
+    };
+    let handle = DeviceHandle::from_transport(device)?.with_capture(CaptureFile::create(&path)?)?;
+    let captured = handle.sync()?;
+    drop(handle);
+
+    // replay the session without the device
+    let replay = ReplayTransport::open(&path)?;
+    assert!(replay.strict());
+    assert!(!replay.is_finished());
+
+    let handle = DeviceHandle::from_transport(replay)?;
+    assert_eq!(handle.sync()?, captured);
+
+    // nothing is left to replay, the device never answers
+    assert!(handle.sync().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_capture_replay_diverged() -> ssp::Result<()> {
+    let sync = frame(0x80, &[0x11]);
+    let ok = frame(0x80, &[0xf0]);
+    let records = vec![
+        CaptureRecord::new(0, CaptureDirection::Clear, &[]),
+        CaptureRecord::new(10, CaptureDirection::Tx, &sync),
+        CaptureRecord::new(20, CaptureDirection::Rx, &ok),
+    ];
+
+    // the device does not reply before the command is sent
+    let mut replay = ReplayTransport::new(records.clone());
+    let mut buf = [0u8; 16];
+    assert_eq!(
+        replay.read(&mut buf).map_err(|err| err.kind()),
+        Err(io::ErrorKind::TimedOut)
+    );
+
+    // commands are written, and replies read in any chunks
+    replay.write_all(&sync[..2])?;
+    replay.write_all(&sync[2..])?;
+    assert_eq!(replay.read(&mut buf[..3])?, 3);
+    assert_eq!(replay.read(&mut buf[3..])?, ok.len() - 3);
+    assert_eq!(&buf[..ok.len()], ok.as_slice());
+    assert!(replay.is_finished());
+    assert!(replay.diverged().is_none());
+
+    // past the end of the capture, the device is silent
+    replay.write_all(&sync)?;
+    assert!(replay.read(&mut buf).is_err());
+
+    // a different command fails the strict replay on the next read
+    let poll = frame(0x80, &[0x07]);
+    let mut replay = ReplayTransport::new(records.clone());
+    replay.write_all(&poll)?;
+    assert!(replay.diverged().is_some());
+    assert_eq!(
+        replay.read(&mut buf).map_err(|err| err.kind()),
+        Err(io::ErrorKind::InvalidData)
+    );
+
+    // lenient replays count mismatched bytes instead
+    let mut replay = ReplayTransport::new(records).with_strict(false);
+    replay.write_all(&poll)?;
+    assert!(replay.mismatches() > 0);
+    assert!(replay.diverged().is_none());
+    assert_eq!(replay.read(&mut buf)?, ok.len());
+    assert!(replay.is_finished());
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use ssp_server::cash_levels::{CashAlertKind, CashLevels, CashLocation, DenominationLevel};
+
+fn levels_path(name: &str) -> String {
+    let path =
+        std::env::temp_dir().join(format!("ssp-cash-levels-{name}-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    path
+}
+
+#[test]
+fn test_cash_levels() -> ssp::Result<()> {
+    let path = levels_path("model");
+
+    let levels = CashLevels::open(&path)?.with_recycled(500);
+    assert_eq!(levels.recycled(), [500]);
+    assert_eq!(levels.estimate().total(), 0);
+
+    assert_eq!(levels.record_credit(500)?, CashLocation::Recycler);
+    assert_eq!(levels.record_credit(500)?, CashLocation::Recycler);
+    assert_eq!(levels.record_credit(2000)?, CashLocation::Cashbox);
+    levels.record_cleared_into_cashbox(1000)?;
+
+    let estimate = levels.estimate();
+    assert_eq!(
+        estimate.cashbox,
+        [
+            DenominationLevel::new(1000, 1),
+            DenominationLevel::new(2000, 1)
+        ]
+    );
+    assert_eq!(estimate.recycler, [DenominationLevel::new(500, 2)]);
+    assert_eq!(estimate.cashbox_total(), 3000);
+    assert_eq!(estimate.recycler_total(), 1000);
+    assert_eq!(estimate.total(), 4000);
+    assert!(estimate.updated_ms > 0);
+
+    // payouts are taken from the recycler, and never go below zero
+    levels.record_payout(500, 1)?;
+    assert_eq!(levels.estimate().recycler_notes(), 1);
+    levels.record_payout(500, 3)?;
+    assert_eq!(levels.estimate().recycler_notes(), 0);
+
+    // reported levels replace the recycler estimate
+    levels.apply_levels(&[
+        DenominationLevel::new(500, 4),
+        DenominationLevel::new(1000, 2),
+    ])?;
+    assert_eq!(levels.estimate().recycler_total(), 4000);
+
+    // an empty moves the recycler into the cashbox
+    levels.record_empty()?;
+    let estimate = levels.estimate();
+    assert!(estimate.recycler.is_empty());
+    assert_eq!(estimate.cashbox_notes(), 8);
+    assert_eq!(estimate.cashbox_total(), 7000);
+
+    // the estimate survives a restart
+    drop(levels);
+    let levels = CashLevels::open(&path)?;
+    assert_eq!(levels.estimate(), estimate);
+    assert!(levels.recycled().is_empty());
+
+    // collection empties the cashbox
+    let collected = levels.collect_cashbox()?;
+    assert_eq!(collected.iter().map(|l| l.amount()).sum::<u64>(), 7000);
+    assert_eq!(levels.estimate().total(), 0);
+    assert_eq!(CashLevels::open(&path)?.estimate().total(), 0);
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
+
+#[test]
+fn test_cash_levels_invalid() -> ssp::Result<()> {
+    let path = levels_path("invalid");
+
+    std::fs::write(&path, "# ssp-cash-levels v1 0\ncashbox\t500\tmany\n")?;
+    assert!(CashLevels::open(&path).is_err());
+
+    std::fs::write(&path, "# ssp-cash-levels v1 0\nstacker\t500\t1\n")?;
+    assert!(CashLevels::open(&path).is_err());
+
+    std::fs::write(
+        &path,
+        "# ssp-cash-levels v1 1700000000000\nrecycler\t500\t3\n",
+    )?;
+    let estimate = CashLevels::open(&path)?.estimate();
+    assert_eq!(estimate.recycler, [DenominationLevel::new(500, 3)]);
+    assert_eq!(estimate.updated_ms, 1_700_000_000_000);
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
+
+#[test]
+fn test_cash_level_alerts() -> ssp::Result<()> {
+    let path = levels_path("alerts");
+    let alerts_path = std::path::Path::new(&path).with_extension("alerts");
+    let _ = std::fs::remove_file(&alerts_path);
+
+    let levels = CashLevels::open(&path)?
+        .with_recycled(500)
+        .with_low_float(500, 2)
+        .with_cashbox_full(3);
+
+    assert_eq!(levels.low_float(), [DenominationLevel::new(500, 2)]);
+    assert_eq!(levels.cashbox_full(), Some(3));
+    assert_eq!(levels.alerts_path(), alerts_path);
+
+    // the empty recycler is below the low float
+    let raised = levels.check_watermarks()?;
+    assert_eq!(raised.len(), 1);
+    assert_eq!(raised[0].kind, CashAlertKind::FloatLow);
+    assert_eq!(raised[0].value, 500);
+    assert_eq!(raised[0].count, 0);
+    assert_eq!(raised[0].threshold, 2);
+    assert!(raised[0].timestamp_ms > 0);
+
+    // alerts are only raised once while the level stays low
+    assert!(levels.check_watermarks()?.is_empty());
+    levels.record_credit(500)?;
+    assert_eq!(levels.active_alerts(), raised);
+
+    // recovered levels clear the alert, and raise it again on the next crossing
+    levels.record_credit(500)?;
+    assert!(levels.active_alerts().is_empty());
+    levels.record_payout(500, 1)?;
+    assert_eq!(levels.active_alerts().len(), 1);
+    assert_eq!(levels.active_alerts()[0].count, 1);
+
+    for _ in 0..3 {
+        levels.record_credit(2000)?;
+    }
+
+    let active = levels.active_alerts();
+    assert_eq!(active.len(), 2);
+    assert_eq!(active[1].kind, CashAlertKind::CashboxNearFull);
+    assert_eq!(active[1].value, 0);
+    assert_eq!(active[1].count, 3);
+    assert_eq!(active[1].threshold, 3);
+
+    // raised alerts are queued, and kept in the history
+    let taken = levels.take_alerts();
+    assert_eq!(taken.len(), 3);
+    assert!(levels.take_alerts().is_empty());
+    assert_eq!(levels.alerts()?, taken);
+
+    levels.collect_cashbox()?;
+    assert_eq!(levels.active_alerts().len(), 1);
+
+    // the history survives a restart
+    drop(levels);
+    assert_eq!(CashLevels::open(&path)?.alerts()?, taken);
+
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&alerts_path);
+
+    Ok(())
+}
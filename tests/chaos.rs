@@ -0,0 +1,170 @@
+#![cfg(feature = "emulator")]
+
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use ssp::MessageOps;
+use ssp_server::emulator::chaos::{Cut, Reconnect};
+use ssp_server::emulator::{ChaosControl, ChaosSchedule, ChaosTransport, Emulator};
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::drain;
+
+const READ: u8 = 0xef;
+const CREDIT: u8 = 0xee;
+const STACKING: u8 = 0xcc;
+const STACKED: u8 = 0xeb;
+const DEVICE_RESET: u8 = 0xf1;
+const DISABLED: u8 = 0xe8;
+
+fn connect(
+    schedule: ChaosSchedule,
+) -> ssp::Result<(Arc<Mutex<Emulator>>, DeviceHandle, ChaosControl)> {
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+    let transport = ChaosTransport::new(Arc::clone(&emulator), schedule);
+    let control = transport.control().clone();
+
+    Ok((emulator, DeviceHandle::from_transport(transport)?, control))
+}
+
+#[test]
+fn test_schedule_is_seeded() {
+    let schedule = |seed| {
+        ChaosSchedule::new(seed)
+            .with_sever_rate(0.2)
+            .with_outage(2, 5)
+    };
+    let cuts =
+        |mut schedule: ChaosSchedule| (0..256).map(|_| schedule.next_cut()).collect::<Vec<_>>();
+
+    let first = cuts(schedule(7));
+    assert_eq!(first, cuts(schedule(7)));
+    assert_ne!(first, cuts(schedule(8)));
+    assert!(first.iter().any(Option::is_none));
+    assert!(first
+        .iter()
+        .flatten()
+        .all(|&(_, outage)| (2..=5).contains(&outage)));
+    assert!(first
+        .iter()
+        .flatten()
+        .any(|&(cut, _)| matches!(cut, Cut::MidResponse(_))));
+
+    assert!(cuts(ChaosSchedule::new(7)).iter().all(Option::is_none));
+}
+
+#[test]
+fn test_sever_and_restore() -> ssp::Result<()> {
+    let (emulator, handle, control) = connect(ChaosSchedule::new(0))?;
+
+    handle.sync()?;
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    assert_eq!(drain(&emulator, &handle)?, [DEVICE_RESET]);
+
+    // the note is read while the cable is pulled, and reported once it is back
+    control.sever();
+    emulator.lock().credit(3)?;
+    assert!(handle.poll().is_err());
+    assert!(control.severed());
+    assert_eq!(emulator.lock().pending_events(), 3);
+
+    control.restore(Reconnect::Resume);
+    assert_eq!(
+        drain(&emulator, &handle)?,
+        [READ, 3, STACKING, CREDIT, 3, STACKED]
+    );
+    assert!(!control.severed());
+    assert_eq!((control.disconnects(), control.reconnects()), (1, 1));
+
+    Ok(())
+}
+
+#[test]
+fn test_power_cycle_on_reconnect() -> ssp::Result<()> {
+    let (emulator, mut handle, control) = connect(ChaosSchedule::new(0))?;
+
+    handle.negotiate_keys()?;
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    drain(&emulator, &handle)?;
+
+    control.sever();
+    assert!(handle.poll().is_err());
+
+    // the device lost its session with the power, the host negotiates a new key
+    control.restore(Reconnect::PowerCycle);
+    let res = handle.with_rekey(|handle| handle.poll())?;
+    assert_eq!(res.data()[1..], [DEVICE_RESET, DISABLED]);
+
+    assert!(emulator.lock().encrypted());
+    assert_eq!(control.power_cycles(), 1);
+
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    emulator.lock().credit(1)?;
+    assert_eq!(
+        drain(&emulator, &handle)?,
+        [READ, 1, STACKING, CREDIT, 1, STACKED]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_chaos_soak() -> ssp::Result<()> {
+    let schedule = ChaosSchedule::new(0xc4a05)
+        .with_sever_rate(0.1)
+        .with_outage(1, 6)
+        .with_power_cycle_rate(0.2);
+
+    let (emulator, handle, control) = connect(schedule)?;
+
+    handle.sync().ok();
+    handle.enable_device(ssp::ProtocolVersion::Six).ok();
+
+    let mut credited = 0;
+    let mut reported = Vec::new();
+
+    for i in 0..300 {
+        if i % 10 == 0 && emulator.lock().credit(1).is_ok() {
+            credited += 1;
+        }
+
+        let Ok(res) = handle.poll() else {
+            continue;
+        };
+
+        let events = &res.data()[1..];
+        reported.extend_from_slice(events);
+
+        // the device came back from a power cycle
+        if events.contains(&DISABLED) {
+            handle.enable_device(ssp::ProtocolVersion::Six).ok();
+        }
+    }
+
+    assert!(control.disconnects() > 0);
+    assert!(control.reconnects() > 0);
+    assert!(control.power_cycles() > 0);
+
+    // credits may be lost with the link, never reported twice
+    let credits = reported.iter().filter(|&&b| b == CREDIT).count();
+    assert!(credits <= credited);
+
+    // once the link is stable again, the host recovers without help
+    control.set_paused(true);
+    control.restore(Reconnect::Resume);
+
+    let recovered = (0..8).any(|_| handle.enable_device(ssp::ProtocolVersion::Six).is_ok());
+    assert!(recovered);
+
+    drain(&emulator, &handle)?;
+    emulator.lock().credit(2)?;
+    assert_eq!(
+        drain(&emulator, &handle)?,
+        [READ, 2, STACKING, CREDIT, 2, STACKED]
+    );
+
+    Ok(())
+}
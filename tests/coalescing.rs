@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use ssp::ResponseOps;
+
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::frame;
+
+const RESET: u8 = 0x01;
+const SET_INHIBITS: u8 = 0x02;
+const DISABLE: u8 = 0x09;
+const ENABLE: u8 = 0x0a;
+
+// Transport answering every command with OK, recording the written commands.
+#[derive(Default)]
+struct OkTransport {
+    wire: VecDeque<u8>,
+    commands: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Read for OkTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for OkTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.commands.lock().push(buf[3]);
+
+        // no response to a reset
+        if buf[3] != RESET {
+            self.wire.extend(frame(0x80, &[0xf0]));
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for OkTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+}
+
+fn inhibits(first: u8) -> ssp::EnableBitfieldList {
+    ssp::EnableBitfieldList::from([
+        ssp::EnableBitfield::from(first),
+        ssp::EnableBitfield::from(0xff),
+    ])
+}
+
+#[test]
+fn test_command_coalescing() -> ssp::Result<()> {
+    let commands = Arc::new(Mutex::new(Vec::new()));
+    let handle = DeviceHandle::from_transport(OkTransport {
+        commands: Arc::clone(&commands),
+        ..Default::default()
+    })?;
+
+    let sent = |command| commands.lock().iter().filter(|&&c| c == command).count();
+
+    // the state is unknown at first, so commands are sent once
+    for _ in 0..3 {
+        handle.enable()?;
+    }
+    assert_eq!(sent(ENABLE), 1);
+
+    handle.force_enable()?;
+    assert_eq!(sent(ENABLE), 2);
+
+    handle.disable()?;
+    assert_eq!(handle.disable()?.response_status(), ssp::ResponseStatus::Ok);
+    assert_eq!(sent(DISABLE), 1);
+
+    // only changed inhibits are sent
+    handle.set_inhibits(inhibits(0xff))?;
+    handle.set_inhibits(inhibits(0xff))?;
+    assert_eq!(sent(SET_INHIBITS), 1);
+
+    handle.set_inhibits(inhibits(0x0f))?;
+    assert_eq!(sent(SET_INHIBITS), 2);
+
+    handle.force_set_inhibits(inhibits(0x0f))?;
+    assert_eq!(sent(SET_INHIBITS), 3);
+
+    // a reset forgets the known state
+    handle.reset()?;
+    handle.disable()?;
+    handle.set_inhibits(inhibits(0x0f))?;
+    assert_eq!(sent(DISABLE), 2);
+    assert_eq!(sent(SET_INHIBITS), 4);
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "jsonrpc")]
+
+use ssp_server::codec::WireFormat;
+
+#[test]
+fn test_wire_format_negotiation() {
+    assert_eq!(WireFormat::from_name("json"), Some(WireFormat::Json));
+    assert_eq!(WireFormat::from_name("unknown"), None);
+
+    assert_eq!(
+        WireFormat::from_mime("application/json; charset=utf-8"),
+        Some(WireFormat::Json)
+    );
+    assert_eq!(WireFormat::from_accept("text/html, */*"), WireFormat::Json);
+
+    #[cfg(feature = "cbor")]
+    assert_eq!(
+        WireFormat::from_accept("text/html, application/cbor, application/json"),
+        WireFormat::Cbor
+    );
+}
+
+#[test]
+fn test_json_frame() -> ssp::Result<()> {
+    let event = ssp::Event::from(ssp::RejectEvent::new());
+
+    let mut buf = Vec::new();
+    WireFormat::Json.write_frame(&mut buf, &event)?;
+
+    assert_eq!(buf.last(), Some(&b'\n'));
+    assert_eq!(
+        WireFormat::Json.decode::<ssp::Event>(&buf[..buf.len() - 1])?,
+        event
+    );
+
+    Ok(())
+}
+
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+#[test]
+fn test_binary_frames() -> ssp::Result<()> {
+    let formats = [
+        #[cfg(feature = "cbor")]
+        WireFormat::Cbor,
+        #[cfg(feature = "msgpack")]
+        WireFormat::MessagePack,
+    ];
+
+    let event = ssp::Event::from(ssp::RejectEvent::new());
+
+    for format in formats {
+        let mut buf = Vec::new();
+        format.write_frame(&mut buf, &event)?;
+
+        let len = u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize;
+        assert_eq!(len, buf.len() - 4);
+
+        let decoded: ssp::Event = format.read_frame(&mut buf.as_slice())?;
+        assert_eq!(decoded, event);
+    }
+
+    Ok(())
+}
@@ -1,12 +1,17 @@
 #![allow(dead_code)]
 
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::time;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::{thread, time};
 
 use parking_lot::{Mutex, MutexGuard};
 
 use ssp::{Error, Result};
 
+#[cfg(feature = "emulator")]
+use ssp::MessageOps;
+#[cfg(feature = "emulator")]
+use ssp_server::{emulator::Emulator, DeviceHandle};
+
 static INIT: AtomicBool = AtomicBool::new(false);
 static LOCK: Mutex<()> = Mutex::new(());
 
@@ -30,3 +35,46 @@ pub fn init() -> Result<MutexGuard<'static, ()>> {
     LOCK.try_lock_for(time::Duration::from_secs(5))
         .ok_or(Error::Io("lock test mutex".into()))
 }
+
+/// Start byte of SSP frames.
+pub const STX: u8 = 0x7f;
+
+/// Builds an unstuffed SSP frame with the sequence `seq`, and the `data`.
+pub fn frame(seq: u8, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![STX, seq, data.len() as u8];
+    frame.extend_from_slice(data);
+
+    let crc = ssp::crc::crc16(&frame[1..]);
+    frame.extend_from_slice(crc.to_le_bytes().as_ref());
+
+    frame
+}
+
+/// Waits up to `timeout` for `count` to reach at least `min`.
+pub fn wait_for(count: &AtomicUsize, min: usize, timeout: time::Duration) -> bool {
+    let start = time::Instant::now();
+
+    while count.load(Ordering::SeqCst) < min {
+        if start.elapsed() > timeout {
+            return false;
+        }
+        thread::sleep(time::Duration::from_millis(5));
+    }
+
+    true
+}
+
+/// Polls until the emulator has no more queued events, and returns the reported events.
+#[cfg(feature = "emulator")]
+pub fn drain(emulator: &Mutex<Emulator>, handle: &DeviceHandle) -> Result<Vec<u8>> {
+    let mut events = Vec::new();
+
+    for _ in 0..16 {
+        if emulator.lock().pending_events() == 0 {
+            break;
+        }
+        events.extend_from_slice(&handle.poll()?.data()[1..]);
+    }
+
+    Ok(events)
+}
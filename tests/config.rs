@@ -0,0 +1,340 @@
+#![cfg(feature = "config")]
+
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::time;
+
+use ssp_server::config::{
+    ConfigChange, ConfigWatcher, EscrowPolicy, SspServerConfig, DEFAULT_PORT,
+};
+use ssp_server::retry::CommandClass;
+use ssp_server::{DeviceHandle, PollMode};
+
+const SAMPLE: &str = r#"
+port = "/dev/ttyACM0"
+baud_rate = 19200
+
+[timeouts]
+poll_ms = 400
+payout_ms = 12000
+
+[polling]
+interval_ms = 300
+jitter_budget_ms = 50
+
+[escrow]
+policy = "hold"
+
+[inhibits]
+enabled_channels = [1, 2, 3, 9]
+
+[encryption]
+require = true
+fixed_key = "0123456789abcdef"
+rotate_after_commands = 1000
+rotate_after_secs = 3600
+
+[frontends]
+jsonrpc = false
+http = "127.0.0.1:8080"
+"#;
+
+#[test]
+fn test_parse_sample() -> ssp::Result<()> {
+    let config = SspServerConfig::from_toml(SAMPLE)?;
+
+    assert_eq!(config.port, "/dev/ttyACM0");
+    assert_eq!(config.baud_rate, 19200);
+
+    assert_eq!(
+        config.timeouts.response_timeout(CommandClass::Poll),
+        Some(time::Duration::from_millis(400))
+    );
+    assert_eq!(
+        config.timeouts.response_timeout(CommandClass::Payout),
+        Some(time::Duration::from_secs(12))
+    );
+    assert_eq!(config.timeouts.response_timeout(CommandClass::Query), None);
+
+    assert_eq!(
+        config.polling.interval(),
+        Some(time::Duration::from_millis(300))
+    );
+    assert_eq!(
+        config.polling.jitter_budget(),
+        time::Duration::from_millis(50)
+    );
+
+    assert_eq!(config.escrow.policy, EscrowPolicy::Hold);
+    assert_eq!(config.escrow.policy.poll_mode(), PollMode::Interactive);
+
+    assert_eq!(config.inhibits.bitfields()?, [0b0000_0111, 0b0000_0001]);
+
+    assert!(config.encryption.require);
+    assert_eq!(
+        config.encryption.fixed_key()?,
+        Some(ssp::FixedKey::from_inner(0x0123_4567_89ab_cdef))
+    );
+
+    let rotation = config.encryption.key_rotation();
+    assert_eq!(rotation.max_commands(), Some(1000));
+    assert_eq!(rotation.max_age(), Some(time::Duration::from_secs(3600)));
+
+    assert_eq!(config.frontends.enabled(), ["http"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_defaults() -> ssp::Result<()> {
+    let config = SspServerConfig::from_toml("")?;
+
+    assert_eq!(config, SspServerConfig::new());
+    assert_eq!(config.port, DEFAULT_PORT);
+    assert_eq!(config.polling.interval(), None);
+    assert_eq!(config.escrow.policy, EscrowPolicy::Auto);
+    assert_eq!(config.inhibits.bitfields()?, [0xff, 0xff]);
+    assert_eq!(config.encryption.fixed_key()?, None);
+    assert_eq!(config.frontends.enabled(), ["jsonrpc"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid() {
+    for contents in [
+        "prot = \"/dev/ttyUSB0\"",
+        "port = \"\"",
+        "baud_rate = 0",
+        "[timeouts]\npoll_ms = 0",
+        "[polling]\ninterval_ms = 0",
+        "[polling]\ninterval_ms = 60000",
+        "[escrow]\npolicy = \"reject\"",
+        "[inhibits]\nenabled_channels = [0]",
+        "[inhibits]\nenabled_channels = [17]",
+        "[encryption]\nfixed_key = \"0123\"",
+        "[frontends]\nsoap = true",
+    ] {
+        assert!(
+            SspServerConfig::from_toml(contents).is_err(),
+            "accepted: {contents}"
+        );
+    }
+}
+
+#[test]
+fn test_toml_round_trip() -> ssp::Result<()> {
+    let config = SspServerConfig::from_toml(SAMPLE)?;
+
+    assert_eq!(SspServerConfig::from_toml(&config.to_toml()?)?, config);
+
+    Ok(())
+}
+
+// Looks up variables in the `vars`, instead of the process environment.
+fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+
+    move |name| vars.get(name).cloned()
+}
+
+#[test]
+fn test_env_overrides() -> ssp::Result<()> {
+    let config = SspServerConfig::from_toml(SAMPLE)?.with_env_vars(env(&[
+        ("SSP_SERIAL_PATH", "tcp://bridge.local:7000"),
+        ("SSP_POLL_MS", "200"),
+        ("SSP_QUERY_TIMEOUT_MS", "800"),
+        ("SSP_ESCROW_POLICY", "auto"),
+        ("SSP_ENABLED_CHANNELS", "4, 5"),
+        ("SSP_REQUIRE_ENCRYPTION", "false"),
+        ("SSP_STDIO", "on"),
+        ("SSP_GRPC_ADDR", "0.0.0.0:50051"),
+    ]))?;
+
+    assert_eq!(config.port, "tcp://bridge.local:7000");
+    assert_eq!(
+        config.polling.interval(),
+        Some(time::Duration::from_millis(200))
+    );
+    assert_eq!(
+        config.timeouts.response_timeout(CommandClass::Query),
+        Some(time::Duration::from_millis(800))
+    );
+    assert_eq!(config.escrow.policy, EscrowPolicy::Auto);
+    assert_eq!(config.inhibits.bitfields()?, [0b0001_1000, 0]);
+    assert!(!config.encryption.require);
+    assert_eq!(config.frontends.enabled(), ["stdio", "http", "grpc"]);
+
+    // unset variables keep the file settings
+    assert_eq!(config.baud_rate, 19200);
+    assert_eq!(
+        config.timeouts.response_timeout(CommandClass::Payout),
+        Some(time::Duration::from_secs(12))
+    );
+    assert_eq!(config.encryption.rotate_after_secs, Some(3600));
+
+    let config = config.with_env_vars(env(&[("SSP_ENABLED_CHANNELS", "all")]))?;
+    assert_eq!(config.inhibits.bitfields()?, [0xff, 0xff]);
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_env() {
+    for var in [
+        ("SSP_BAUD_RATE", "fast"),
+        ("SSP_POLL_MS", "0"),
+        ("SSP_FIRMWARE_TIMEOUT_MS", "-1"),
+        ("SSP_ESCROW_POLICY", "reject"),
+        ("SSP_ENABLED_CHANNELS", "1,x"),
+        ("SSP_REQUIRE_ENCRYPTION", "maybe"),
+        ("SSP_FIXED_KEY", "0123"),
+    ] {
+        assert!(
+            SspServerConfig::new().with_env_vars(env(&[var])).is_err(),
+            "accepted: {var:?}"
+        );
+    }
+}
+
+#[test]
+fn test_from_config() -> ssp::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+
+    let mut config = SspServerConfig::from_toml(SAMPLE)?;
+    config.port = format!("tcp://{}", listener.local_addr()?);
+
+    let handle = DeviceHandle::from_config(&config)?;
+
+    assert!(handle.requires_encryption());
+    assert_eq!(
+        handle.poll_interval(),
+        Some(time::Duration::from_millis(300))
+    );
+    assert_eq!(handle.default_inhibits(), [0b0000_0111, 0b0000_0001]);
+    assert_eq!(
+        handle.fixed_key(),
+        &ssp::FixedKey::from_inner(0x0123_4567_89ab_cdef)
+    );
+    assert_eq!(handle.key_rotation().max_commands(), Some(1000));
+    assert_eq!(
+        handle.response_timeout(CommandClass::Payout),
+        time::Duration::from_secs(12)
+    );
+    assert_eq!(
+        handle.response_timeout(CommandClass::Query),
+        ssp_server::timeouts::response_timeout(CommandClass::Query)
+    );
+
+    // the timeouts of other handles are unchanged
+    assert_eq!(
+        ssp_server::timeouts::response_timeout(CommandClass::Payout),
+        ssp_server::timeouts::default_response_timeout(CommandClass::Payout)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_changes() -> ssp::Result<()> {
+    let old = SspServerConfig::from_toml(SAMPLE)?;
+
+    let mut new = old.clone();
+    assert!(old.changes(&new).is_empty());
+
+    new.port = "/dev/ttyUSB1".into();
+    new.polling.interval_ms = Some(500);
+    new.inhibits.enabled_channels = None;
+    new.encryption.rotate_after_secs = None;
+
+    let changes = old.changes(&new);
+    assert_eq!(
+        changes,
+        [
+            ConfigChange::Port,
+            ConfigChange::Polling,
+            ConfigChange::Inhibits,
+            ConfigChange::KeyRotation,
+        ]
+    );
+    assert_eq!(
+        changes
+            .iter()
+            .filter(|c| c.requires_restart())
+            .collect::<Vec<_>>(),
+        [&ConfigChange::Port]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_reload() -> ssp::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+
+    let path = std::env::temp_dir().join(format!("ssp-config-{}.toml", std::process::id()));
+
+    let mut config = SspServerConfig::from_toml(SAMPLE)?;
+    config.port = format!("tcp://{}", listener.local_addr()?);
+    std::fs::write(&path, config.to_toml()?)?;
+
+    let mut handle = DeviceHandle::from_config(&config)?;
+    let mut watcher = ConfigWatcher::new(&path, config.clone());
+
+    let mut other_config = SspServerConfig::new();
+    other_config.port = config.port.clone();
+    let other = DeviceHandle::from_config(&other_config)?;
+    assert!(!watcher.due());
+
+    config.polling.interval_ms = None;
+    config.inhibits.enabled_channels = Some(vec![1]);
+    config.encryption.rotate_after_commands = Some(500);
+    config.timeouts.poll_ms = Some(250);
+    std::fs::write(&path, config.to_toml()?)?;
+
+    watcher.request_reload();
+    assert!(watcher.due());
+
+    assert_eq!(
+        watcher.reload(&mut handle)?,
+        [
+            ConfigChange::Timeouts,
+            ConfigChange::Polling,
+            ConfigChange::Inhibits,
+            ConfigChange::KeyRotation
+        ]
+    );
+    assert!(!watcher.due());
+    assert_eq!(watcher.config(), &config);
+
+    // the serial session is kept, with the new runtime settings
+    assert_eq!(handle.poll_interval(), None);
+    assert_eq!(handle.default_inhibits(), [0b0000_0001, 0]);
+    assert_eq!(handle.key_rotation().max_commands(), Some(500));
+    assert_eq!(handle.poll_mode(), PollMode::Interactive);
+    assert_eq!(
+        handle.response_timeout(CommandClass::Poll),
+        time::Duration::from_millis(250)
+    );
+
+    // other handles in the process keep their settings
+    assert_eq!(other.poll_mode(), PollMode::Auto);
+    assert_eq!(
+        other.response_timeout(CommandClass::Poll),
+        ssp_server::timeouts::default_response_timeout(CommandClass::Poll)
+    );
+
+    // an invalid file keeps the current settings
+    std::fs::write(&path, "[polling]\ninterval_ms = 0")?;
+    watcher.request_reload();
+    assert!(watcher.reload(&mut handle).is_err());
+    assert_eq!(watcher.config(), &config);
+    assert_eq!(handle.default_inhibits(), [0b0000_0001, 0]);
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
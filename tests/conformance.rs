@@ -0,0 +1,146 @@
+use std::time;
+
+use ssp_server::conformance::{TimingLimits, TimingMonitor, Violation};
+
+mod common;
+
+use common::{frame, STX};
+
+const POLL: u8 = 0x07;
+const SYNC: u8 = 0x11;
+const OK: u8 = 0xf0;
+
+fn ms(ms: u64) -> time::Duration {
+    time::Duration::from_millis(ms)
+}
+
+#[test]
+fn test_poll_gaps() {
+    let start = time::Instant::now();
+    let mut monitor = TimingMonitor::new(TimingLimits::new());
+
+    // polls every 500ms, then one too early, and one too late
+    for (i, at) in [0, 500, 1_000, 1_100, 2_500].into_iter().enumerate() {
+        let seq = if i % 2 == 0 { 0x80 } else { 0x00 };
+        monitor.on_write(start + ms(at), &frame(seq, &[POLL]));
+        monitor.on_read(start + ms(at + 20), &frame(seq, &[OK]));
+    }
+
+    // other commands do not count as polls
+    monitor.on_write(start + ms(2_600), &frame(0x00, &[SYNC]));
+
+    let summary = monitor.report().summary();
+    assert_eq!(
+        (summary.commands, summary.polls, summary.responses),
+        (6, 5, 5)
+    );
+    assert_eq!(summary.min_poll_gap, Some(ms(100)));
+    assert_eq!(summary.max_poll_gap, Some(ms(1_400)));
+    assert_eq!(summary.max_response_time, Some(ms(20)));
+    assert_eq!(
+        summary.violations,
+        [
+            Violation::PollGapTooShort(ms(100)),
+            Violation::PollGapTooLong(ms(1_400))
+        ]
+    );
+}
+
+#[test]
+fn test_response_windows() {
+    let start = time::Instant::now();
+    let mut monitor = TimingMonitor::new(TimingLimits::new());
+
+    let response = frame(0x80, &[OK]);
+
+    monitor.on_write(start, &frame(0x80, &[SYNC]));
+    monitor.on_read(start + ms(1_200), &response[..3]);
+    monitor.on_read(start + ms(1_350), &response[3..]);
+
+    let violations = monitor.report().violations();
+    assert_eq!(
+        violations,
+        [
+            Violation::SlowResponse {
+                command: ssp::MessageType::Synchronisation,
+                elapsed: ms(1_200)
+            },
+            Violation::InterByteGap {
+                command: ssp::MessageType::Synchronisation,
+                gap: ms(150)
+            },
+        ]
+    );
+    assert_eq!(monitor.report().summary().responses, 1);
+}
+
+#[test]
+fn test_retransmission_timing() {
+    let start = time::Instant::now();
+    let limits = TimingLimits::new().with_retransmissions(ms(500), 2);
+    let mut monitor = TimingMonitor::new(limits);
+
+    let poll = frame(0x80, &[POLL]);
+
+    monitor.on_write(start, &poll);
+    // no response, the host waited long enough
+    monitor.on_write(start + ms(500), &poll);
+    // a corrupted response may be retransmitted right away
+    monitor.on_read(start + ms(510), &[STX, 0x80, 0x01, OK, 0x00, 0x00]);
+    monitor.on_write(start + ms(520), &poll);
+    // no response, the host did not wait
+    monitor.on_write(start + ms(600), &poll);
+
+    let summary = monitor.report().summary();
+    assert_eq!((summary.polls, summary.retransmissions), (1, 3));
+    assert_eq!(
+        summary.violations,
+        [
+            Violation::EarlyRetransmission {
+                command: ssp::MessageType::Poll,
+                gap: ms(80)
+            },
+            Violation::ExcessRetransmissions {
+                command: ssp::MessageType::Poll,
+                count: 3
+            },
+        ]
+    );
+    assert!(!monitor.report().is_conformant());
+
+    monitor.report().clear();
+    assert!(monitor.report().is_conformant());
+}
+
+#[cfg(feature = "emulator")]
+#[test]
+fn test_timing_transport() -> ssp::Result<()> {
+    use std::sync::Arc;
+    use std::thread;
+
+    use parking_lot::Mutex;
+
+    use ssp_server::conformance::TimingTransport;
+    use ssp_server::emulator::{Emulator, EmulatorTransport};
+    use ssp_server::DeviceHandle;
+
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+    let transport = TimingTransport::new(EmulatorTransport::new(emulator), TimingLimits::new());
+    let report = transport.report().clone();
+
+    let handle = DeviceHandle::from_transport(transport)?;
+
+    handle.sync()?;
+    for _ in 0..3 {
+        thread::sleep(ms(250));
+        handle.poll()?;
+    }
+
+    let summary = report.summary();
+    assert_eq!(summary.polls, 3);
+    assert_eq!(summary.responses, summary.commands);
+    assert!(summary.min_poll_gap >= Some(ms(250)));
+    assert!(report.is_conformant(), "{:?}", report.violations());
+
+    Ok(())
+}
@@ -0,0 +1,278 @@
+#![cfg(feature = "emulator")]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+
+use ssp::{MessageOps, ResponseOps};
+use ssp_server::emulator::{
+    Emulator, EmulatorTransport, NoteState, REJECT_CHANNEL_INHIBITED, REJECT_HOST_REJECTED,
+};
+use ssp_server::{DeviceHandle, PollMode};
+
+mod common;
+
+use common::frame;
+
+const POLL: u8 = 0x07;
+
+const READ: u8 = 0xef;
+const CREDIT: u8 = 0xee;
+const STACKING: u8 = 0xcc;
+const STACKED: u8 = 0xeb;
+const DEVICE_RESET: u8 = 0xf1;
+const DISABLED: u8 = 0xe8;
+const REJECTING: u8 = 0xed;
+const REJECTED: u8 = 0xec;
+const UNSAFE_JAM: u8 = 0xe9;
+const CLEARED_FROM_FRONT: u8 = 0xe1;
+
+fn connect(emulator: Emulator) -> ssp::Result<(Arc<Mutex<Emulator>>, DeviceHandle)> {
+    let emulator = Arc::new(Mutex::new(emulator));
+    let handle = DeviceHandle::from_transport(EmulatorTransport::new(Arc::clone(&emulator)))?;
+
+    Ok((emulator, handle))
+}
+
+// Gets the events of a poll response, without the status.
+fn events(res: &ssp::PollResponse) -> Vec<u8> {
+    res.data()[1..].into()
+}
+
+#[test]
+fn test_setup_and_credit() -> ssp::Result<()> {
+    let (emulator, handle) = connect(Emulator::new().with_serial_number(0x0a0b_0c0d))?;
+
+    assert!(handle.sync()?.response_status().is_ok());
+    assert!(handle
+        .enable_device(ssp::ProtocolVersion::Seven)?
+        .response_status()
+        .is_ok());
+
+    {
+        let emulator = emulator.lock();
+        assert!(emulator.enabled());
+        assert!(emulator.channel_enabled(1));
+        assert_eq!(emulator.protocol_version(), 7);
+    }
+
+    assert_eq!(
+        handle.serial_number()?.serial_number().as_inner(),
+        0x0a0b_0c0d
+    );
+    assert_eq!(handle.dataset_version()?.dataset_version()?, "EUR01610");
+
+    // the reset after power on is reported first
+    assert_eq!(events(&handle.poll()?), [DEVICE_RESET]);
+    assert!(events(&handle.poll()?).is_empty());
+
+    emulator.lock().credit(2)?;
+
+    assert_eq!(events(&handle.poll()?), [READ, 2]);
+    assert_eq!(events(&handle.poll()?), [STACKING]);
+    assert_eq!(events(&handle.poll()?), [CREDIT, 2, STACKED]);
+    assert!(events(&handle.poll()?).is_empty());
+
+    // a disabled device rejects notes, and reports being disabled on every poll
+    handle.disable()?;
+    assert!(emulator.lock().credit(2).is_err());
+    assert_eq!(events(&handle.poll()?), [DISABLED]);
+
+    Ok(())
+}
+
+#[test]
+fn test_retransmission() {
+    let mut emulator = Emulator::new();
+
+    let poll = frame(0x80, &[POLL]);
+    let first = emulator.receive(&poll);
+    assert!(!first.is_empty());
+
+    // a repeated sequence flag gets the same response, without consuming another event
+    emulator.push_event(&[STACKING]);
+    assert_eq!(emulator.receive(&poll), first);
+    assert_eq!(emulator.pending_events(), 1);
+
+    // a toggled sequence flag is a new command
+    assert_ne!(emulator.receive(&frame(0x00, &[POLL])), first);
+    assert_eq!(emulator.pending_events(), 0);
+
+    // garbage, and corrupted frames are ignored
+    let mut corrupted = frame(0x80, &[POLL]);
+    corrupted[3] ^= 0x01;
+    assert!(emulator.receive(&[0x00, 0x55]).is_empty());
+    assert!(emulator.receive(&corrupted).is_empty());
+
+    // a truncated frame is dropped, the next frame is still answered
+    let truncated = frame(0x80, &[POLL]);
+    let mut bytes = truncated[..truncated.len() - 1].to_vec();
+    bytes.extend(frame(0x00, &[POLL]));
+    assert!(!emulator.receive(&bytes).is_empty());
+
+    // frames for another address are ignored
+    assert!(emulator.receive(&frame(0x90, &[POLL])).is_empty());
+}
+
+#[test]
+fn test_reset() -> ssp::Result<()> {
+    let (emulator, handle) = connect(Emulator::new())?;
+
+    handle.sync()?;
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    handle.poll()?;
+    assert!(emulator.lock().enabled());
+
+    handle.reset()?;
+
+    assert!(!emulator.lock().enabled());
+    assert!(!emulator.lock().channel_enabled(1));
+
+    handle.sync()?;
+    assert_eq!(events(&handle.poll()?), [DEVICE_RESET, DISABLED]);
+
+    Ok(())
+}
+
+#[test]
+fn test_encrypted_session() -> ssp::Result<()> {
+    let (emulator, mut handle) = connect(Emulator::new())?;
+
+    handle.negotiate_keys()?;
+    assert!(emulator.lock().encrypted());
+    assert_eq!(emulator.lock().sequence_count(), 0);
+
+    // every following command is encrypted
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    assert!(emulator.lock().enabled());
+
+    let count = emulator.lock().sequence_count();
+    assert!(count > 0);
+
+    emulator.lock().credit(1)?;
+
+    let mut polled = Vec::new();
+    for _ in 0..4 {
+        polled.extend(events(&handle.poll()?));
+    }
+    assert_eq!(
+        polled,
+        [DEVICE_RESET, READ, 1, STACKING, CREDIT, 1, STACKED]
+    );
+    assert_eq!(emulator.lock().sequence_count(), count + 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_truncated_event() -> ssp::Result<()> {
+    let (emulator, handle) = connect(Emulator::new())?;
+
+    handle.sync()?;
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let rx = handle.start_background_polling_with_queue(Arc::clone(&stop), PollMode::Auto)?;
+
+    // a Read event without its channel ends the events of the poll, instead of stalling polling
+    emulator.lock().push_event(&[READ]);
+    emulator.lock().push_event(&[CREDIT, 2]);
+
+    let timeout = time::Duration::from_secs(5);
+    let credited = std::iter::from_fn(|| rx.0.recv_timeout(timeout).ok())
+        .any(|event| event.method() == ssp::Method::NoteCredit);
+    assert!(credited);
+
+    stop.store(true, Ordering::SeqCst);
+
+    Ok(())
+}
+
+#[test]
+fn test_insert_note() -> ssp::Result<()> {
+    let (emulator, handle) = connect(Emulator::new())?;
+
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    assert_eq!(events(&handle.poll()?), [DEVICE_RESET]);
+
+    emulator.lock().insert_note(3)?;
+    assert!(emulator.lock().insert_note(1).is_err());
+
+    assert_eq!(events(&handle.poll()?), [READ, 0]);
+    assert_eq!(events(&handle.poll()?), [READ, 3]);
+    assert_eq!(emulator.lock().note(), NoteState::Escrow(3));
+
+    // holding keeps the note in escrow, the next poll stacks it
+    handle.hold()?;
+    assert_eq!(emulator.lock().note(), NoteState::Escrow(3));
+
+    assert_eq!(events(&handle.poll()?), [STACKING]);
+    assert_eq!(events(&handle.poll()?), [CREDIT, 3, STACKED]);
+    assert!(events(&handle.poll()?).is_empty());
+    assert_eq!(emulator.lock().note(), NoteState::Idle);
+
+    Ok(())
+}
+
+#[test]
+fn test_insert_note_rejected() -> ssp::Result<()> {
+    let (emulator, handle) = connect(Emulator::new())?;
+
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    handle.poll()?;
+
+    // the host rejects the note in escrow
+    emulator.lock().insert_note(2)?;
+    handle.poll()?;
+    assert_eq!(events(&handle.poll()?), [READ, 2]);
+
+    handle.reject()?;
+    assert_eq!(events(&handle.poll()?), [REJECTING]);
+    assert_eq!(events(&handle.poll()?), [REJECTED]);
+    assert_eq!(emulator.lock().last_reject_code(), REJECT_HOST_REJECTED);
+
+    // the device rejects a note on an inhibited channel by itself
+    handle.set_inhibits(ssp::EnableBitfieldList::from([
+        ssp::EnableBitfield::from(0x01),
+        ssp::EnableBitfield::from(0x00),
+    ]))?;
+    emulator.lock().insert_note(2)?;
+
+    let mut polled = Vec::new();
+    for _ in 0..3 {
+        polled.extend(events(&handle.poll()?));
+    }
+    assert_eq!(polled, [READ, 0, REJECTING, REJECTED]);
+    assert_eq!(emulator.lock().last_reject_code(), REJECT_CHANNEL_INHIBITED);
+
+    Ok(())
+}
+
+#[test]
+fn test_jam() -> ssp::Result<()> {
+    let (emulator, handle) = connect(Emulator::new())?;
+
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    handle.poll()?;
+
+    emulator.lock().insert_note(1)?;
+    handle.poll()?;
+    handle.hold()?;
+    handle.poll()?;
+    assert_eq!(emulator.lock().note(), NoteState::Escrow(1));
+
+    // a jammed note is reported on every poll, until cleared
+    emulator.lock().jam();
+    assert_eq!(events(&handle.poll()?), [UNSAFE_JAM]);
+    assert_eq!(events(&handle.poll()?), [UNSAFE_JAM]);
+    assert!(emulator.lock().insert_note(2).is_err());
+
+    emulator.lock().clear_jam();
+    assert_eq!(events(&handle.poll()?), [CLEARED_FROM_FRONT, 1]);
+    assert!(events(&handle.poll()?).is_empty());
+    assert_eq!(emulator.lock().note(), NoteState::Idle);
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use ssp_server::encryption::{
+    self, EncryptionMode, EncryptionPolicy, UNIT_NV11, UNIT_SMART_HOPPER, UNIT_SMART_PAYOUT,
+};
+
+#[test]
+fn test_requires_encryption() {
+    use ssp::{MessageType, ProtocolVersion, UnitType};
+
+    let validator = Some(UnitType::from_inner(0x00));
+    let payout = Some(UnitType::from_inner(UNIT_SMART_PAYOUT));
+
+    for unit in [UNIT_SMART_HOPPER, UNIT_SMART_PAYOUT, UNIT_NV11] {
+        assert!(encryption::is_payout_unit(UnitType::from_inner(unit)));
+    }
+    assert!(!encryption::is_payout_unit(UnitType::from_inner(0x00)));
+
+    // always encrypted
+    for command in [
+        MessageType::SetEncryptionKey,
+        MessageType::Empty,
+        MessageType::SmartEmpty,
+    ] {
+        for unit_type in [None, validator, payout] {
+            assert!(encryption::requires_encryption(
+                command,
+                ProtocolVersion::Four,
+                unit_type
+            ));
+        }
+    }
+
+    // payouts depend on the protocol version, and unit type
+    let payout_cmd = MessageType::PayoutByDenomination;
+    assert!(encryption::requires_encryption(
+        payout_cmd,
+        ProtocolVersion::Six,
+        payout
+    ));
+    assert!(encryption::requires_encryption(
+        payout_cmd,
+        ProtocolVersion::Eight,
+        None
+    ));
+    assert!(!encryption::requires_encryption(
+        payout_cmd,
+        ProtocolVersion::Five,
+        payout
+    ));
+    assert!(!encryption::requires_encryption(
+        payout_cmd,
+        ProtocolVersion::Eight,
+        validator
+    ));
+
+    // plain commands
+    for command in [
+        MessageType::Poll,
+        MessageType::Synchronisation,
+        MessageType::SetGenerator,
+        MessageType::RequestKeyExchange,
+    ] {
+        assert!(!encryption::requires_encryption(
+            command,
+            ProtocolVersion::Eight,
+            payout
+        ));
+    }
+}
+
+#[test]
+fn test_encryption_policy() {
+    use ssp::{MessageType, ProtocolVersion, UnitType};
+
+    let payout = Some(UnitType::from_inner(UNIT_SMART_PAYOUT));
+    let mode =
+        |policy: &EncryptionPolicy, command| policy.mode(command, ProtocolVersion::Eight, payout);
+
+    let mut policy = EncryptionPolicy::new();
+
+    // defaults
+    assert_eq!(mode(&policy, MessageType::Empty), EncryptionMode::Required);
+    assert_eq!(mode(&policy, MessageType::Poll), EncryptionMode::Preferred);
+
+    policy = policy
+        .with_mode(MessageType::Empty, EncryptionMode::Preferred)
+        .with_mode(MessageType::Poll, EncryptionMode::Plain)
+        .with_mode(MessageType::Poll, EncryptionMode::Required)
+        .with_mode(MessageType::SetModulus, EncryptionMode::Required);
+
+    assert_eq!(policy.modes().len(), 3);
+    assert_eq!(mode(&policy, MessageType::Empty), EncryptionMode::Preferred);
+    assert_eq!(mode(&policy, MessageType::Poll), EncryptionMode::Required);
+
+    // key negotiation is always in plaintext
+    assert_eq!(
+        mode(&policy, MessageType::SetModulus),
+        EncryptionMode::Plain
+    );
+
+    policy.remove_mode(MessageType::Empty);
+    assert_eq!(mode(&policy, MessageType::Empty), EncryptionMode::Required);
+
+    assert_eq!(
+        EncryptionMode::from_name(" Plain "),
+        Some(EncryptionMode::Plain)
+    );
+    assert_eq!(EncryptionMode::from_name("sometimes"), None);
+}
@@ -0,0 +1,99 @@
+use ssp_server::entropy::{EntropySource, SeededEntropy, SystemEntropy};
+
+// Counts the seeds requested, every seed is different.
+struct CountingEntropy(u8);
+
+impl EntropySource for CountingEntropy {
+    fn name(&self) -> &str {
+        "counting"
+    }
+
+    fn seed(&mut self) -> ssp::Seed {
+        self.0 += 1;
+        [self.0; 32]
+    }
+}
+
+#[test]
+fn test_seeded_entropy_is_deterministic() {
+    let mut first = SeededEntropy::from_data(b"fixture");
+    let mut second = SeededEntropy::from_data(b"fixture");
+
+    assert_eq!(first.generator_key(), second.generator_key());
+    assert_eq!(first.modulus_key(), second.modulus_key());
+    assert_eq!(first.random_key(), second.random_key());
+    assert_eq!(first.fixed_key(), second.fixed_key());
+    assert_eq!(first.count(), 4);
+
+    // every seed is different
+    let seeds: Vec<ssp::Seed> = (0..8).map(|_| first.seed()).collect();
+    for (i, seed) in seeds.iter().enumerate() {
+        assert!(seeds[i + 1..].iter().all(|s| s != seed));
+    }
+
+    // different initial seeds generate different keys
+    let mut other = SeededEntropy::from_data(b"other fixture");
+    assert_ne!(
+        SeededEntropy::from_data(b"fixture").random_key(),
+        other.random_key()
+    );
+}
+
+#[test]
+fn test_custom_entropy_source() {
+    let mut entropy = CountingEntropy(0);
+
+    assert_eq!(
+        entropy.generator_key(),
+        ssp::GeneratorKey::from_seed([1; 32])
+    );
+    assert_eq!(entropy.modulus_key(), ssp::ModulusKey::from_seed([2; 32]));
+    assert_eq!(entropy.random_key(), ssp::RandomKey::from_seed([3; 32]));
+    assert_eq!(entropy.fixed_key(), ssp::FixedKey::from_seed([4; 32]));
+
+    let mut system = SystemEntropy::new();
+    assert_ne!(system.seed(), system.seed());
+}
+
+#[cfg(feature = "emulator")]
+#[test]
+fn test_reproducible_key_exchange() -> ssp::Result<()> {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use ssp_server::emulator::{Emulator, EmulatorTransport};
+    use ssp_server::DeviceHandle;
+
+    // Negotiates a session key with seeded host, and device entropy.
+    let negotiate = |host: &[u8], device: &[u8]| -> ssp::Result<Vec<u8>> {
+        let emulator = Arc::new(Mutex::new(
+            Emulator::new().with_entropy(SeededEntropy::from_data(device)),
+        ));
+        let mut handle = DeviceHandle::from_transport(EmulatorTransport::new(emulator))?
+            .with_entropy_source(SeededEntropy::from_data(host));
+
+        handle.negotiate_keys()?;
+
+        // the session works
+        handle.poll()?;
+
+        Ok(handle
+            .encryption_key()?
+            .as_ref()
+            .map(|key| key.to_vec())
+            .unwrap_or_default())
+    };
+
+    let key = negotiate(b"host fixture", b"device fixture")?;
+    assert_eq!(key.len(), 16);
+
+    // the same seeds produce the same session key
+    assert_eq!(negotiate(b"host fixture", b"device fixture")?, key);
+
+    // either side with another seed produces another key
+    assert_ne!(negotiate(b"other host", b"device fixture")?, key);
+    assert_ne!(negotiate(b"host fixture", b"other device")?, key);
+
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use std::io::{self, Read, Write};
+
+use ssp_server::lease::{ClientId, LeaseManager};
+use ssp_server::transport::Transport;
+use ssp_server::{DeviceHandle, Error, ErrorContext};
+
+// Transport accepting every write, and never answering, like a device that stopped responding.
+struct SilentTransport;
+
+impl Read for SilentTransport {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::TimedOut.into())
+    }
+}
+
+impl Write for SilentTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for SilentTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_categories() {
+    let categories = [
+        (
+            ssp::Error::SerialPort("device unplugged".into()),
+            "transport",
+        ),
+        (ssp::Error::Io("broken pipe".into()), "transport"),
+        (ssp::Error::Timeout("response".into()), "timeout"),
+        (ssp::Error::QueueTimeout, "timeout"),
+        (ssp::Error::Crc((0x1234, 0x4321)), "protocol"),
+        (ssp::Error::InvalidDataLength((0, 1)), "protocol"),
+        (
+            ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet),
+            "encryption",
+        ),
+        (
+            ssp::Error::Encryption(ssp::ResponseStatus::Ok),
+            "encryption",
+        ),
+        (
+            ssp::Error::Status(ssp::ResponseStatus::CommandCannotBeProcessed),
+            "device_status",
+        ),
+        (ssp::Error::Io("read: timed out".into()), "timeout"),
+        (ssp::Error::PollingReinit, "busy"),
+    ];
+
+    for (err, category) in categories {
+        let msg = err.to_string();
+        assert_eq!(Error::from(err).as_str(), category, "{msg}");
+    }
+}
+
+#[test]
+fn test_frontend_categories() {
+    let io = || ssp::Error::Io("frontend failure".into());
+
+    for (err, category) in [
+        (Error::invalid_request(io()), "invalid_request"),
+        (Error::not_configured(io()), "not_configured"),
+        (Error::unauthenticated(io()), "unauthenticated"),
+        (Error::permission_denied(io()), "permission_denied"),
+    ] {
+        assert_eq!(err.as_str(), category);
+        assert!(!err.is_transient(), "{err}");
+    }
+
+    // I/O errors of the device are still transport failures
+    assert!(Error::from(io()).is_transient());
+}
+
+#[test]
+fn test_context() {
+    let err = Error::from_ssp(ssp::Error::Status(
+        ssp::ResponseStatus::CommandCannotBeProcessed,
+    ))
+    .with_command(ssp::MessageType::Enable)
+    .with_sequence_id(0x80);
+
+    assert!(matches!(err, Error::DeviceStatus(_)));
+    assert_eq!(
+        err.status(),
+        Some(ssp::ResponseStatus::CommandCannotBeProcessed)
+    );
+    assert_eq!(err.command(), Some(ssp::MessageType::Enable));
+    assert_eq!(err.sequence_id(), Some(0x80));
+    assert!(!err.is_transient());
+    assert!(err.to_string().starts_with("device_status error: "));
+    assert!(err.to_string().contains("(sequence ID 0x80)"));
+
+    // converts back for functions returning ssp::Result
+    assert!(matches!(
+        ssp::Error::from(err),
+        ssp::Error::Status(ssp::ResponseStatus::CommandCannotBeProcessed)
+    ));
+
+    let ctx = ErrorContext::new(ssp::Error::QueueTimeout);
+    assert_eq!(ctx.command(), None);
+    assert_eq!(ctx.to_string(), ssp::Error::QueueTimeout.to_string());
+    assert!(Error::Timeout(ctx).is_transient());
+}
+
+#[cfg(feature = "emulator")]
+#[test]
+fn test_handle_error() -> ssp::Result<()> {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+    use ssp_server::emulator::{Emulator, EmulatorTransport};
+    use ssp_server::DeviceHandle;
+
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+    let handle = DeviceHandle::from_transport(EmulatorTransport::new(emulator))?;
+
+    assert_eq!(handle.last_command(), None);
+
+    handle.sync()?;
+
+    let err = handle.error(
+        ssp::MessageType::Synchronisation,
+        ssp::Error::Status(ssp::ResponseStatus::Fail),
+    );
+    assert!(matches!(err, Error::DeviceStatus(_)));
+    assert_eq!(err.command(), Some(ssp::MessageType::Synchronisation));
+    assert_eq!(err.sequence_id(), Some(0x80));
+
+    // the sequence ID of another command is not reported
+    let err = handle.error(
+        ssp::MessageType::Enable,
+        ssp::Error::Status(ssp::ResponseStatus::Fail),
+    );
+    assert_eq!(err.command(), Some(ssp::MessageType::Enable));
+    assert_eq!(err.sequence_id(), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_transport_timeout() -> ssp::Result<()> {
+    let handle = DeviceHandle::from_transport(SilentTransport)?;
+
+    let err = handle
+        .sync()
+        .map_err(|err| handle.error(ssp::MessageType::Synchronisation, err))
+        .unwrap_err();
+
+    assert!(matches!(err, Error::Timeout(_)), "{err}");
+    assert!(err.is_transient());
+    assert_eq!(err.command(), Some(ssp::MessageType::Synchronisation));
+
+    Ok(())
+}
+
+#[test]
+fn test_lease_denied() -> ssp::Result<()> {
+    let leases = LeaseManager::new();
+    leases.claim(ClientId::next(), None)?;
+
+    let err = leases.authorize(Some(ClientId::next())).unwrap_err();
+    assert!(matches!(err, Error::Busy(_)), "{err}");
+
+    Ok(())
+}
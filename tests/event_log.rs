@@ -0,0 +1,113 @@
+use std::thread;
+use std::time;
+
+use ssp_server::event_log::EventLog;
+use ssp_server::sink::EventSink;
+
+const TIMEOUT: time::Duration = time::Duration::from_millis(100);
+
+fn credit(value: u32) -> ssp::Event {
+    ssp::Event::from(ssp::NoteCreditEvent::new(ssp::ChannelValue::from(value)))
+}
+
+#[test]
+fn test_event_log_sequence() {
+    let log = EventLog::new(8);
+
+    assert_eq!(log.last_seq(), None);
+    assert!(log.events().is_empty());
+
+    let first = log.record(credit(5));
+    let second = log.record(ssp::Event::from(ssp::DisabledEvent::new()));
+
+    assert_eq!(second, first + 1);
+    assert_eq!(log.first_seq(), Some(first));
+    assert_eq!(log.last_seq(), Some(second));
+
+    let events = log.events();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].seq, first);
+    assert_eq!(events[0].event, credit(5));
+    assert_eq!(events[1].seq, second);
+
+    let since = log.events_since(first).unwrap();
+    assert_eq!(since.len(), 1);
+    assert_eq!(since[0].seq, second);
+
+    assert!(log.events_since(second).unwrap().is_empty());
+    // sequence numbers past the most recent event were never logged
+    assert!(log.events_since(second + 1).is_err());
+}
+
+#[test]
+fn test_event_log_dropped_events() {
+    let log = EventLog::new(2);
+
+    let first = log.record(credit(5));
+    log.record(credit(10));
+    let third = log.record(credit(20));
+
+    assert_eq!(log.events().len(), 2);
+    assert_eq!(log.first_seq(), Some(first + 1));
+
+    // the event after `first - 1` was dropped
+    assert!(log.events_since(first - 1).is_err());
+    assert!(log.subscribe(Some(first - 1)).is_err());
+
+    let since = log.events_since(first).unwrap();
+    assert_eq!(since.len(), 2);
+    assert_eq!(since[1].seq, third);
+}
+
+#[test]
+fn test_event_log_subscribe_resume() {
+    let mut log = EventLog::new(8);
+
+    let first = log.record(credit(5));
+    log.publish_event(&credit(10)).unwrap();
+
+    // resume after the first event, receiving the second again
+    let mut sub = log.subscribe(Some(first)).unwrap();
+    assert_eq!(sub.next_seq(), first + 1);
+
+    let event = sub.next_event(TIMEOUT).unwrap();
+    assert_eq!(event.seq, first + 1);
+    assert_eq!(event.event, credit(10));
+
+    assert!(matches!(
+        sub.next_event(TIMEOUT),
+        Err(ssp::Error::QueueTimeout)
+    ));
+
+    // live events are delivered to waiting subscribers
+    let writer = log.clone();
+    let producer = thread::spawn(move || {
+        thread::sleep(time::Duration::from_millis(20));
+        writer.record(credit(20))
+    });
+
+    let event = sub.next_event(time::Duration::from_secs(5)).unwrap();
+    assert_eq!(event.seq, producer.join().unwrap());
+    assert_eq!(event.event, credit(20));
+
+    // new subscribers only receive the next events
+    let mut live = log.subscribe(None).unwrap();
+    assert!(live.next_event(TIMEOUT).is_err());
+
+    let seq = log.record(credit(50));
+    assert_eq!(live.next_event(TIMEOUT).unwrap().seq, seq);
+    assert_eq!(sub.next_event(TIMEOUT).unwrap().seq, seq);
+}
+
+#[test]
+fn test_event_log_subscriber_fell_behind() {
+    let log = EventLog::new(1);
+
+    let mut sub = log.subscribe(None).unwrap();
+
+    log.record(credit(5));
+    log.record(credit(10));
+
+    let err = sub.next_event(TIMEOUT).unwrap_err();
+    assert!(!matches!(err, ssp::Error::QueueTimeout));
+}
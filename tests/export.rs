@@ -0,0 +1,95 @@
+use ssp_server::export::{self, CsvExport, DenominationSummary};
+use ssp_server::journal::{JournalEntry, TransactionKind};
+
+fn entry(
+    seq: u64,
+    timestamp_ms: u64,
+    kind: TransactionKind,
+    value: u32,
+    count: u32,
+) -> JournalEntry {
+    JournalEntry {
+        seq,
+        timestamp_ms,
+        serial_number: 1234,
+        kind,
+        value,
+        count,
+        currency: "EUR".into(),
+    }
+}
+
+#[test]
+fn test_csv_export() -> ssp::Result<()> {
+    let day = export::parse_date("2024-01-31").unwrap();
+    assert_eq!(day, 1_706_659_200_000);
+
+    let entries = vec![
+        entry(0, day - 1, TransactionKind::Credit, 50, 1),
+        entry(1, day + 33_302_318, TransactionKind::Credit, 20, 1),
+        entry(2, day + 33_644_051, TransactionKind::Payout, 10, 2),
+        entry(3, day + 40_000_000, TransactionKind::Credit, 20, 1),
+        entry(4, day + 50_000_000, TransactionKind::Empty, 10, 3),
+        entry(5, day + 86_400_000, TransactionKind::Credit, 20, 1),
+    ];
+
+    let csv = CsvExport::new()
+        .with_since_ms(day)
+        .with_until_ms(export::parse_date("2024-02-01").unwrap());
+
+    let mut out = Vec::new();
+    assert_eq!(csv.write_transactions(&entries, &mut out)?, 4);
+
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], export::TRANSACTIONS_HEADER);
+    assert_eq!(
+        lines[1],
+        "1,2024-01-31T09:15:02.318Z,1234,accepted,EUR,20,1,20"
+    );
+    assert_eq!(
+        lines[2],
+        "2,2024-01-31T09:20:44.051Z,1234,dispensed,EUR,10,2,20"
+    );
+    assert_eq!(lines.len(), 5);
+
+    let summary = csv.summary(&entries);
+    assert_eq!(
+        summary[0],
+        DenominationSummary {
+            kind: TransactionKind::Credit,
+            currency: "EUR".into(),
+            value: 20,
+            transactions: 2,
+            count: 2,
+            amount: 40,
+        }
+    );
+
+    let mut out = Vec::new();
+    assert_eq!(csv.write_summary(&entries, &mut out)?, 3);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!(
+            "{}\naccepted,EUR,20,2,2,40\nemptied,EUR,10,1,3,30\ndispensed,EUR,10,1,2,20\n",
+            export::SUMMARY_HEADER
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_export_dates() {
+    assert_eq!(export::format_date(0), "1970-01-01T00:00:00.000Z");
+    assert_eq!(
+        export::format_date(951_782_400_000),
+        "2000-02-29T00:00:00.000Z"
+    );
+    assert_eq!(export::parse_date("2000-02-29"), Some(951_782_400_000));
+    assert_eq!(export::parse_date("1700000000000"), Some(1_700_000_000_000));
+
+    assert_eq!(export::parse_date("2023-02-29"), None);
+    assert_eq!(export::parse_date("2023-13-01"), None);
+    assert_eq!(export::parse_date("yesterday"), None);
+}
@@ -0,0 +1,143 @@
+#![cfg(all(feature = "emulator", feature = "mock"))]
+
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+
+use ssp_server::capture::CaptureDirection;
+use ssp_server::emulator::{Emulator, EmulatorTransport};
+use ssp_server::fault::{Fault, FaultLog, FaultSchedule, FaultTransport};
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::drain;
+
+const READ: u8 = 0xef;
+const CREDIT: u8 = 0xee;
+const STACKING: u8 = 0xcc;
+const STACKED: u8 = 0xeb;
+const DEVICE_RESET: u8 = 0xf1;
+
+fn connect(
+    rx: FaultSchedule,
+    tx: FaultSchedule,
+) -> ssp::Result<(Arc<Mutex<Emulator>>, DeviceHandle, FaultLog)> {
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+    let transport =
+        FaultTransport::new(EmulatorTransport::new(Arc::clone(&emulator)), rx).with_tx_schedule(tx);
+    let log = transport.log().clone();
+
+    Ok((emulator, DeviceHandle::from_transport(transport)?, log))
+}
+
+#[test]
+fn test_schedule_is_seeded() {
+    let schedule = |seed| {
+        FaultSchedule::new(seed)
+            .with_drop_frame_rate(0.1)
+            .with_drop_byte_rate(0.1)
+            .with_corrupt_crc_rate(0.1)
+            .with_duplicate_rate(0.1)
+            .with_delay_rate(0.1, time::Duration::from_millis(5))
+    };
+    let faults =
+        |mut schedule: FaultSchedule| (0..256).map(|_| schedule.next_fault()).collect::<Vec<_>>();
+
+    let first = faults(schedule(42));
+    assert_eq!(first, faults(schedule(42)));
+    assert_ne!(first, faults(schedule(43)));
+    assert!(first.iter().any(Option::is_some));
+    assert!(first.iter().any(Option::is_none));
+
+    // scripted faults do not shift the random ones
+    let scripted = faults(schedule(42).with_fault(7, Fault::Duplicate));
+    assert_eq!(scripted[7], Some(Fault::Duplicate));
+    assert_eq!(scripted[8..], first[8..]);
+
+    assert!(faults(FaultSchedule::none()).iter().all(Option::is_none));
+}
+
+#[test]
+fn test_apply() {
+    let frame = [0x7f, 0x80, 0x01, 0xf0, 0x23, 0x80];
+
+    assert!(Fault::DropFrame.apply(&frame).is_empty());
+    assert_eq!(
+        Fault::DropByte(9).apply(&frame),
+        [0x7f, 0x80, 0x01, 0x23, 0x80]
+    );
+    assert_eq!(
+        Fault::CorruptCrc.apply(&frame),
+        [0x7f, 0x80, 0x01, 0xf0, 0x23, 0x81]
+    );
+    assert_eq!(Fault::Duplicate.apply(&frame), [frame, frame].concat());
+    assert_eq!(
+        Fault::Delay(time::Duration::from_millis(1)).apply(&frame),
+        frame
+    );
+}
+
+#[test]
+fn test_scripted_rx_faults() -> ssp::Result<()> {
+    // every fault hits a response, the host retransmits until it gets a clean one
+    let rx = FaultSchedule::none()
+        .with_fault(1, Fault::CorruptCrc)
+        .with_fault(3, Fault::DropFrame)
+        .with_fault(5, Fault::DropByte(4))
+        .with_fault(7, Fault::Duplicate)
+        .with_fault(8, Fault::Delay(time::Duration::from_millis(2)));
+
+    let (emulator, handle, log) = connect(rx, FaultSchedule::none())?;
+
+    handle.sync()?;
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    assert_eq!(drain(&emulator, &handle)?, [DEVICE_RESET]);
+
+    emulator.lock().credit(2)?;
+    assert_eq!(
+        drain(&emulator, &handle)?,
+        [READ, 2, STACKING, CREDIT, 2, STACKED]
+    );
+
+    assert_eq!(log.len(), 5);
+    assert_eq!(
+        log.count(|f| f.direction == CaptureDirection::Rx),
+        log.len()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_random_faults() -> ssp::Result<()> {
+    let schedule = |seed| {
+        FaultSchedule::new(seed)
+            .with_drop_frame_rate(0.03)
+            .with_drop_byte_rate(0.03)
+            .with_corrupt_crc_rate(0.03)
+            .with_duplicate_rate(0.03)
+            .with_delay_rate(0.03, time::Duration::from_millis(2))
+    };
+
+    let (emulator, handle, log) = connect(schedule(0x5eed), schedule(0xfeed))?;
+
+    handle.sync()?;
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    drain(&emulator, &handle)?;
+
+    // every credit is reported exactly once, despite the faults
+    for channel in [1, 2, 3, 4, 5, 6, 7].repeat(3) {
+        emulator.lock().credit(channel)?;
+        assert_eq!(
+            drain(&emulator, &handle)?,
+            [READ, channel, STACKING, CREDIT, channel, STACKED]
+        );
+    }
+
+    assert!(log.count(|f| f.direction == CaptureDirection::Tx) > 0);
+    assert!(log.count(|f| f.direction == CaptureDirection::Rx) > 0);
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+use ssp_server::capture::CaptureDirection;
+use ssp_server::frame_log;
+use ssp_server::framing::MAX_RETRANSMISSIONS;
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::frame;
+
+// Transport replying with canned bytes, one byte per read.
+struct ReplyTransport(VecDeque<u8>);
+
+impl Read for ReplyTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match (self.0.pop_front(), buf.first_mut()) {
+            (Some(byte), Some(out)) => {
+                *out = byte;
+                Ok(1)
+            }
+            _ => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
+
+impl Write for ReplyTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for ReplyTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_frame_log() -> ssp::Result<()> {
+    assert_eq!(
+        frame_log::frame_log_capacity(),
+        frame_log::DEFAULT_FRAME_LOG_CAPACITY
+    );
+
+    let ok = frame(0x80, &[0xf0]);
+
+    // a complete response, followed by a truncated one
+    let mut replies: VecDeque<u8> = ok.iter().copied().collect();
+    replies.extend(ok[..3].iter());
+
+    let handle = DeviceHandle::from_transport(ReplyTransport(replies))?;
+    frame_log::clear_frame_log();
+
+    handle.sync()?;
+    assert!(handle.sync().is_err());
+
+    // the failed command is retransmitted
+    let frames = frame_log::recent_frames();
+    assert_eq!(frames.len(), 4 + 2 * MAX_RETRANSMISSIONS as usize);
+    assert!(frames.windows(2).all(|w| w[0].seq + 1 == w[1].seq));
+
+    assert_eq!(frames[0].direction, CaptureDirection::Tx);
+    assert_eq!(frames[0].frame, frame(0x80, &[0x11]));
+    assert_eq!(frames[1].direction, CaptureDirection::Rx);
+    assert_eq!(frames[1].frame, ok);
+    assert!(frames[1].error.is_none());
+
+    // failed reads keep the partial frame, and the error
+    assert_eq!(frames[3].direction, CaptureDirection::Rx);
+    assert_eq!(frames[3].frame, ok[..3]);
+    assert!(frames[3].error.is_some());
+    assert_eq!(frames[4].frame, frames[2].frame);
+
+    // the oldest frames are dropped beyond the capacity
+    frame_log::set_frame_log_capacity(2);
+    assert_eq!(frame_log::recent_frames(), frames[frames.len() - 2..]);
+
+    // zero disables the log
+    frame_log::set_frame_log_capacity(0);
+    assert!(frame_log::recent_frames().is_empty());
+    assert!(handle.sync().is_err());
+    assert!(frame_log::recent_frames().is_empty());
+
+    Ok(())
+}
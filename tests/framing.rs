@@ -0,0 +1,360 @@
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Read, Write};
+use std::time;
+
+use ssp_server::framing::{self, FrameDecoder, FrameTimeouts};
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::{frame, STX};
+
+const TIMEOUTS: FrameTimeouts = FrameTimeouts {
+    response: time::Duration::from_millis(1_000),
+    inter_byte: time::Duration::from_millis(10),
+};
+
+// Transport replying with canned bytes, and recording reads, and read timeouts.
+struct WireTransport {
+    wire: VecDeque<u8>,
+    max_read: usize,
+    reads: usize,
+    timeouts: Vec<time::Duration>,
+}
+
+impl WireTransport {
+    // Replies one byte per read.
+    fn new(wire: &[u8]) -> Self {
+        Self {
+            wire: wire.iter().copied().collect(),
+            max_read: 1,
+            reads: 0,
+            timeouts: Vec::new(),
+        }
+    }
+
+    // Replies as many bytes as requested per read.
+    fn buffered(wire: &[u8]) -> Self {
+        Self {
+            max_read: usize::MAX,
+            ..Self::new(wire)
+        }
+    }
+}
+
+impl Read for WireTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reads += 1;
+
+        let n = buf.len().min(self.max_read).min(self.wire.len());
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for WireTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for WireTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+
+    fn set_read_timeout(&mut self, timeout: time::Duration) -> ssp::Result<()> {
+        self.timeouts.push(timeout);
+        Ok(())
+    }
+}
+
+fn decode_bytewise(stuffed: &[u8]) -> ssp::Result<Vec<u8>> {
+    let mut decoder = FrameDecoder::new();
+
+    for (i, &byte) in stuffed.iter().enumerate() {
+        if decoder.push(byte)? {
+            assert_eq!(i + 1, stuffed.len(), "frame completed early");
+            return Ok(decoder.frame().into());
+        }
+    }
+
+    Err(ssp::Error::InvalidLength((stuffed.len(), 0)))
+}
+
+fn assert_round_trip(frame: &[u8]) -> ssp::Result<()> {
+    let stuffed = framing::stuff(frame)?;
+
+    let stx_count = frame[1..].iter().filter(|&&b| b == STX).count();
+    assert_eq!(stuffed.len(), frame.len() + stx_count);
+    assert_eq!(stuffed[0], STX);
+
+    assert_eq!(framing::unstuff(&stuffed)?, frame);
+    assert_eq!(decode_bytewise(&stuffed)?, frame);
+
+    let mut decoder = FrameDecoder::new();
+    let len = framing::read_frame(&mut Cursor::new(&stuffed), &mut decoder)?;
+    assert_eq!(decoder.frame(), frame);
+    assert_eq!(len, frame.len());
+
+    Ok(())
+}
+
+#[test]
+fn test_stuff() -> ssp::Result<()> {
+    assert_eq!(
+        framing::stuff(&[STX, 0x80, 0x02, STX, 0x01, STX, STX])?,
+        [STX, 0x80, 0x02, STX, STX, 0x01, STX, STX, STX, STX]
+    );
+    assert_eq!(framing::stuff(&[STX, 0x00, 0x00])?, [STX, 0x00, 0x00]);
+
+    assert_eq!(
+        framing::stuff(&[0x00, 0x80]),
+        Err(ssp::Error::InvalidSTX(0x00))
+    );
+    assert!(framing::stuff(&[]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_round_trip_every_byte_in_every_field() -> ssp::Result<()> {
+    for byte in 0..=u8::MAX {
+        // sequence ID
+        assert_round_trip(&frame(byte, &[0xf0]))?;
+
+        // single data byte, and a data byte between others
+        assert_round_trip(&frame(0x80, &[byte]))?;
+        assert_round_trip(&frame(0x00, &[0x01, byte, 0x02]))?;
+
+        // runs of repeated bytes
+        assert_round_trip(&frame(0x80, &[byte; 4]))?;
+        assert_round_trip(&frame(0x80, &[byte; 5]))?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_round_trip_every_length() -> ssp::Result<()> {
+    for len in 0..=u8::MAX as usize {
+        // includes the length 0x7f, which is stuffed before it is interpreted
+        assert_round_trip(&frame(0x80, &vec![0xf0; len]))?;
+        assert_round_trip(&frame(0x80, &vec![STX; len]))?;
+
+        let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        assert_round_trip(&frame(0x00, &data))?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_round_trip_stx_checksum() -> ssp::Result<()> {
+    let mut found = 0;
+
+    for a in 0..=u8::MAX {
+        for b in 0..=u8::MAX {
+            let frame = frame(0x80, &[a, b]);
+            let crc = &frame[frame.len() - 2..];
+
+            if crc.contains(&STX) {
+                found += 1;
+                assert_round_trip(&frame)?;
+            }
+        }
+    }
+
+    assert!(found > 0, "no checksum containing STX");
+
+    Ok(())
+}
+
+#[test]
+fn test_encrypted_frame() -> ssp::Result<()> {
+    // wrapped encrypted data starts with STEX, and is pseudo-random
+    let mut data = vec![ssp::STEXN];
+    data.extend((0..32).map(|i: u8| i.wrapping_mul(37)));
+    data.extend([STX, 0x12, STX, STX, 0x34, STX, STX, STX]);
+
+    assert_round_trip(&frame(0x80, &data))
+}
+
+#[test]
+fn test_invalid_frames() -> ssp::Result<()> {
+    let stuffed = framing::stuff(&frame(0x80, &[STX, 0x01]))?;
+
+    // does not start with STX
+    assert_eq!(
+        framing::unstuff(&stuffed[1..]),
+        Err(ssp::Error::InvalidSTX(stuffed[1]))
+    );
+
+    // unpaired STX, i.e. a new frame started inside the current one
+    let mut unpaired = stuffed.clone();
+    unpaired.remove(4);
+    assert!(matches!(
+        framing::unstuff(&unpaired),
+        Err(ssp::Error::InvalidSTX(_))
+    ));
+
+    // truncated frames
+    for end in 0..stuffed.len() {
+        assert!(framing::unstuff(&stuffed[..end]).is_err());
+    }
+
+    // trailing bytes
+    let mut trailing = stuffed.clone();
+    trailing.push(0x00);
+    assert!(framing::unstuff(&trailing).is_err());
+
+    // bytes pushed after the frame is complete
+    let mut decoder = FrameDecoder::new();
+    stuffed
+        .iter()
+        .try_for_each(|&b| decoder.push(b).map(|_| ()))?;
+    assert!(decoder.is_complete());
+    assert!(decoder.push(STX).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_read_frame_stops_at_frame_end() -> ssp::Result<()> {
+    let first = frame(0x80, &[0xf0, STX, STX]);
+    let second = frame(0x00, &[STX]);
+
+    let mut wire = framing::stuff(&first)?;
+    wire.extend(framing::stuff(&second)?);
+
+    let mut reader = Cursor::new(wire);
+    let mut decoder = FrameDecoder::new();
+
+    framing::read_frame(&mut reader, &mut decoder)?;
+    assert_eq!(decoder.frame(), first);
+
+    framing::read_frame(&mut reader, &mut decoder)?;
+    assert_eq!(decoder.frame(), second);
+
+    assert!(framing::read_frame(&mut reader, &mut decoder).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_read_frame_resync() -> ssp::Result<()> {
+    let valid = frame(0x80, &[0xf0, STX, 0x01]);
+    let stuffed = framing::stuff(&valid)?;
+
+    // garbage before the frame, including a stuffed STX pair
+    let mut wire = vec![0x00, 0xff, STX, STX, 0x12];
+    wire.extend(&stuffed);
+
+    let mut transport = WireTransport::new(&wire);
+    let mut decoder = FrameDecoder::new();
+    framing::read_frame_timeout(&mut transport, &mut decoder, TIMEOUTS)?;
+    assert_eq!(decoder.frame(), valid);
+    assert!(transport.wire.is_empty());
+
+    // inter-byte timeout after the first byte, response timeout restored at the end
+    assert_eq!(
+        transport.timeouts,
+        [TIMEOUTS.response, TIMEOUTS.inter_byte, TIMEOUTS.response]
+    );
+
+    // corrupted length bytes, shorter, zero, and running into the next frame
+    for len in [0x01, 0x00, 0x06] {
+        let mut corrupted = stuffed.clone();
+        corrupted[2] = len;
+        corrupted.truncate(5);
+        corrupted.extend(&stuffed);
+
+        let mut transport = WireTransport::new(&corrupted);
+        framing::read_frame_timeout(&mut transport, &mut decoder, TIMEOUTS)?;
+        assert_eq!(decoder.frame(), valid, "length: {len}");
+    }
+
+    // a new frame starting inside a truncated one
+    let mut truncated = stuffed[..4].to_vec();
+    truncated.extend(&stuffed);
+
+    let mut transport = WireTransport::new(&truncated);
+    framing::read_frame_timeout(&mut transport, &mut decoder, TIMEOUTS)?;
+    assert_eq!(decoder.frame(), valid);
+
+    // the next read is not affected by a bad frame
+    let mut bad_crc = stuffed.clone();
+    let last = bad_crc.len() - 1;
+    bad_crc[last] ^= 0x01;
+
+    let mut transport = WireTransport::new(&bad_crc);
+    assert!(matches!(
+        framing::read_frame_timeout(&mut transport, &mut decoder, TIMEOUTS),
+        Err(ssp::Error::Crc(_))
+    ));
+
+    transport.wire.extend(&stuffed);
+    framing::read_frame_timeout(&mut transport, &mut decoder, TIMEOUTS)?;
+    assert_eq!(decoder.frame(), valid);
+
+    // endless garbage fails
+    let mut transport = WireTransport::new(&[0x00; framing::MAX_DISCARDED_BYTES + 1]);
+    assert!(framing::read_frame_timeout(&mut transport, &mut decoder, TIMEOUTS).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_device_resync() -> ssp::Result<()> {
+    let ok = frame(0x80, &[0xf0]);
+
+    let mut wire = vec![0x00, 0x12, 0x34];
+    wire.extend(&ok);
+
+    let handle = DeviceHandle::from_transport(WireTransport::new(&wire))?;
+    handle.sync()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_read_frame_reads() -> ssp::Result<()> {
+    let mut decoder = FrameDecoder::new();
+
+    // header, and the rest of the frame
+    let plain = frame(0x80, &[0xf0, 0x01, 0x02, 0x03]);
+    let mut transport = WireTransport::buffered(&framing::stuff(&plain)?);
+
+    framing::read_frame_timeout(&mut transport, &mut decoder, TIMEOUTS)?;
+    assert_eq!(decoder.frame(), plain);
+    assert_eq!(transport.reads, 2);
+
+    // stuffed bytes are read after the unstuffed length
+    let stuffed = frame(0x80, &[0xf0, STX, 0x01]);
+    let mut wire = framing::stuff(&stuffed)?;
+    let next = framing::stuff(&plain)?;
+    wire.extend(&next);
+
+    let mut transport = WireTransport::buffered(&wire);
+
+    framing::read_frame_timeout(&mut transport, &mut decoder, TIMEOUTS)?;
+    assert_eq!(decoder.frame(), stuffed);
+    assert_eq!(transport.reads, 3);
+
+    // never reads into the next frame
+    assert_eq!(transport.wire, next);
+
+    Ok(())
+}
@@ -0,0 +1,252 @@
+//! Golden wire-frame vectors for every plaintext command sent by a [DeviceHandle].
+//!
+//! Frames are written out byte for byte, CRC included, instead of being built with the `ssp`
+//! crate, so a change of encoding in a new `ssp` release fails here, instead of on a device.
+
+#![cfg(feature = "mock")]
+
+use ssp::ResponseOps;
+use ssp_server::scripted::{Script, ScriptedTransport};
+use ssp_server::DeviceHandle;
+
+// Sync, with the sequence flag set, and its response
+const SYNC: &[u8] = &[0x7f, 0x80, 0x01, 0x11, 0x65, 0x82];
+const SYNC_OK: &[u8] = &[0x7f, 0x80, 0x01, 0xf0, 0x23, 0x80];
+
+// Plain OK response, with the sequence flag cleared
+const OK: &[u8] = &[0x7f, 0x00, 0x01, 0xf0, 0x20, 0x0a];
+
+// Setup request response of a protocol 6 validator: firmware "0400", "EUR", 5, 10, and 20
+const SETUP: &[u8] = &[
+    0x7f, 0x00, 0x2c, 0xf0, 0x00, 0x30, 0x34, 0x30, 0x30, 0x45, 0x55, 0x52, 0x00, 0x00, 0x01, 0x03,
+    0x05, 0x0a, 0x14, 0x02, 0x02, 0x02, 0x00, 0x00, 0x64, 0x06, 0x45, 0x55, 0x52, 0x45, 0x55, 0x52,
+    0x45, 0x55, 0x52, 0x05, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0xd7,
+    0x32,
+];
+
+// Unit data response of a protocol 6 validator
+const UNIT_DATA: &[u8] = &[
+    0x7f, 0x00, 0x0d, 0xf0, 0x00, 0x30, 0x34, 0x30, 0x30, 0x45, 0x55, 0x52, 0x00, 0x00, 0x01, 0x06,
+    0x6e, 0xb4,
+];
+
+// Serial number 123456
+const SERIAL_NUMBER: &[u8] = &[0x7f, 0x00, 0x05, 0xf0, 0x00, 0x01, 0xe2, 0x40, 0x1e, 0x27];
+
+// Dataset version "EUR01610"
+const DATASET_VERSION: &[u8] = &[
+    0x7f, 0x00, 0x09, 0xf0, 0x45, 0x55, 0x52, 0x30, 0x31, 0x36, 0x31, 0x30, 0x87, 0x0a,
+];
+
+// Channel values 5, 10, and 20
+const CHANNEL_VALUE_DATA: &[u8] = &[0x7f, 0x00, 0x05, 0xf0, 0x03, 0x05, 0x0a, 0x14, 0xb9, 0xea];
+
+// Last reject code: channel inhibited
+const LAST_REJECT_CODE: &[u8] = &[0x7f, 0x00, 0x02, 0xf0, 0x06, 0x14, 0x20];
+
+struct Vector {
+    name: &'static str,
+    command: &'static [u8],
+    response: &'static [u8],
+    send: fn(&DeviceHandle) -> ssp::Result<()>,
+}
+
+fn vectors() -> Vec<Vector> {
+    vec![
+        Vector {
+            name: "poll",
+            command: &[0x7f, 0x00, 0x01, 0x07, 0x11, 0x88],
+            response: OK,
+            send: |handle| handle.poll().map(drop),
+        },
+        Vector {
+            name: "enable",
+            command: &[0x7f, 0x00, 0x01, 0x0a, 0x3c, 0x08],
+            response: OK,
+            send: |handle| handle.enable().map(drop),
+        },
+        Vector {
+            name: "disable",
+            command: &[0x7f, 0x00, 0x01, 0x09, 0x36, 0x08],
+            response: OK,
+            send: |handle| handle.disable().map(drop),
+        },
+        Vector {
+            name: "display_on",
+            command: &[0x7f, 0x00, 0x01, 0x03, 0x0a, 0x08],
+            response: OK,
+            send: |handle| handle.display_on().map(drop),
+        },
+        Vector {
+            name: "display_off",
+            command: &[0x7f, 0x00, 0x01, 0x04, 0x1b, 0x88],
+            response: OK,
+            send: |handle| handle.display_off().map(drop),
+        },
+        Vector {
+            name: "host_protocol_version",
+            command: &[0x7f, 0x00, 0x02, 0x06, 0x06, 0x1b, 0x94],
+            response: OK,
+            send: |handle| {
+                handle
+                    .host_protocol_version(ssp::ProtocolVersion::Six)
+                    .map(drop)
+            },
+        },
+        Vector {
+            name: "set_inhibits",
+            command: &[0x7f, 0x00, 0x03, 0x02, 0xff, 0xff, 0x26, 0x18],
+            response: OK,
+            send: |handle| {
+                handle
+                    .set_inhibits(ssp::EnableBitfieldList::from([
+                        ssp::EnableBitfield::from(0xff),
+                        ssp::EnableBitfield::from(0xff),
+                    ]))
+                    .map(drop)
+            },
+        },
+        Vector {
+            name: "reject",
+            command: &[0x7f, 0x00, 0x01, 0x08, 0x33, 0x88],
+            response: OK,
+            send: |handle| handle.reject().map(drop),
+        },
+        Vector {
+            name: "setup_request",
+            command: &[0x7f, 0x00, 0x01, 0x05, 0x1e, 0x08],
+            response: SETUP,
+            send: |handle| handle.setup_request().map(drop),
+        },
+        Vector {
+            name: "unit_data",
+            command: &[0x7f, 0x00, 0x01, 0x0d, 0x2d, 0x88],
+            response: UNIT_DATA,
+            send: |handle| handle.unit_data().map(drop),
+        },
+        Vector {
+            name: "serial_number",
+            command: &[0x7f, 0x00, 0x01, 0x0c, 0x28, 0x08],
+            response: SERIAL_NUMBER,
+            send: |handle| handle.serial_number().map(drop),
+        },
+        Vector {
+            name: "dataset_version",
+            command: &[0x7f, 0x00, 0x01, 0x21, 0xc6, 0x08],
+            response: DATASET_VERSION,
+            send: |handle| handle.dataset_version().map(drop),
+        },
+        Vector {
+            name: "channel_value_data",
+            command: &[0x7f, 0x00, 0x01, 0x0e, 0x27, 0x88],
+            response: CHANNEL_VALUE_DATA,
+            send: |handle| handle.channel_value_data().map(drop),
+        },
+        Vector {
+            name: "last_reject_code",
+            command: &[0x7f, 0x00, 0x01, 0x17, 0x72, 0x08],
+            response: LAST_REJECT_CODE,
+            send: |handle| handle.last_reject_code().map(drop),
+        },
+    ]
+}
+
+// Connects a fresh handle, and syncs it, so the command of the vector is sent next, with the
+// sequence flag cleared.
+fn connect(vector: &Vector) -> ssp::Result<(Script, DeviceHandle)> {
+    let transport = ScriptedTransport::new();
+    let script = transport.script().clone();
+
+    script
+        .expect_frame(SYNC, SYNC_OK)
+        .expect_frame(vector.command, vector.response);
+
+    let handle = DeviceHandle::from_transport(transport)?;
+    handle.sync()?;
+
+    Ok((script, handle))
+}
+
+fn vector(name: &str) -> Vector {
+    vectors()
+        .into_iter()
+        .find(|vector| vector.name == name)
+        .expect("unknown vector")
+}
+
+#[test]
+fn test_command_frames() -> ssp::Result<()> {
+    for vector in vectors() {
+        let (script, handle) = connect(&vector)?;
+
+        if let Err(err) = (vector.send)(&handle) {
+            panic!("{}: {err}, diverged: {:?}", vector.name, script.diverged());
+        }
+
+        script.finish()?;
+        assert_eq!(script.written()[1], vector.command, "{}", vector.name);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_setup_request_response() -> ssp::Result<()> {
+    let (script, handle) = connect(&vector("setup_request"))?;
+
+    let res = handle.setup_request()?;
+    assert!(res.response_status().is_ok());
+    assert_eq!(res.unit_type().as_inner(), 0x00);
+    assert_eq!(u8::from(res.protocol_version()), 6);
+
+    script.finish()
+}
+
+#[test]
+fn test_serial_number_response() -> ssp::Result<()> {
+    let (script, handle) = connect(&vector("serial_number"))?;
+
+    assert_eq!(handle.serial_number()?.serial_number().as_inner(), 123_456);
+
+    script.finish()
+}
+
+#[test]
+fn test_dataset_version_response() -> ssp::Result<()> {
+    let (script, handle) = connect(&vector("dataset_version"))?;
+
+    assert_eq!(handle.dataset_version()?.dataset_version()?, "EUR01610");
+
+    script.finish()
+}
+
+#[test]
+fn test_channel_value_data_response() -> ssp::Result<()> {
+    let (script, handle) = connect(&vector("channel_value_data"))?;
+
+    let res = handle.channel_value_data()?;
+    assert!(res.response_status().is_ok());
+    assert_eq!(res.channel_values()?.as_ref().len(), 3);
+
+    script.finish()
+}
+
+#[test]
+fn test_unit_data_response() -> ssp::Result<()> {
+    let (script, handle) = connect(&vector("unit_data"))?;
+
+    assert!(handle.unit_data()?.response_status().is_ok());
+
+    script.finish()
+}
+
+#[test]
+fn test_last_reject_code_response() -> ssp::Result<()> {
+    let (script, handle) = connect(&vector("last_reject_code"))?;
+
+    let res = handle.last_reject_code()?;
+    assert!(res.response_status().is_ok());
+    assert_eq!(res.data()[1], 0x06);
+
+    script.finish()
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "grpc")]
+
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use prost::Message;
+use tokio::sync::broadcast;
+use tonic::Request;
+
+use ssp_server::cash_levels::{CashEstimate, CashLevels};
+use ssp_server::grpc::proto::device_server::Device;
+use ssp_server::grpc::{proto, GrpcService, EVENT_CHANNEL_CAPACITY};
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+// Transport for RPCs that never talk to the device.
+struct IdleTransport;
+
+impl Read for IdleTransport {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::TimedOut.into())
+    }
+}
+
+impl Write for IdleTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for IdleTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_levels() -> ssp::Result<()> {
+    let path = std::env::temp_dir().join(format!("ssp-grpc-levels-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    let cash_levels = CashLevels::open(&path)?.with_recycled(1000);
+    cash_levels.record_credit(1000)?;
+    cash_levels.record_credit(2000)?;
+    cash_levels.record_credit(2000)?;
+
+    let expected = cash_levels.estimate();
+
+    let handle = DeviceHandle::from_transport(IdleTransport)?.with_cash_levels(cash_levels);
+    let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+    let service = GrpcService::new(Arc::new(Mutex::new(handle)), events);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    let reply = runtime
+        .block_on(service.levels(Request::new(proto::LevelsRequest {})))
+        .map_err(|err| ssp::Error::Io(err.message().into()))?
+        .into_inner();
+
+    // the estimate survives the wire encoding
+    let decoded = proto::LevelsReply::decode(reply.encode_to_vec().as_slice())
+        .map_err(|err| ssp::Error::Io(format!("{err}")))?;
+
+    assert_eq!(decoded, reply);
+    assert_eq!(CashEstimate::from(&decoded), expected);
+    assert_eq!(decoded.cashbox.len(), 1);
+    assert_eq!(decoded.recycler.len(), 1);
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
+
+#[test]
+fn test_levels_not_configured() -> ssp::Result<()> {
+    let handle = DeviceHandle::from_transport(IdleTransport)?;
+    let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+    let service = GrpcService::new(Arc::new(Mutex::new(handle)), events);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    let status = runtime
+        .block_on(service.levels(Request::new(proto::LevelsRequest {})))
+        .unwrap_err();
+
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use ssp::MessageOps;
+use ssp_server::capture::{self, CaptureDirection, CapturedFrame, ReplayTransport};
+use ssp_server::entropy::SeededEntropy;
+use ssp_server::{essp, framing, DeviceHandle};
+
+const FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/handshake.capture"
+);
+const ENTROPY: &[u8] = b"handshake fixture";
+const KEY: [u8; 16] = [
+    0x67, 0x45, 0x23, 0x01, 0x67, 0x45, 0x23, 0x01, 0x16, 0x9f, 0x9b, 0xd3, 0xcd, 0x4b, 0x16, 0x02,
+];
+
+const STX: u8 = 0x7f;
+const OK: u8 = 0xf0;
+
+const READ: u8 = 0xef;
+const CREDIT: u8 = 0xee;
+const STACKING: u8 = 0xcc;
+const STACKED: u8 = 0xeb;
+const DEVICE_RESET: u8 = 0xf1;
+
+// Pairs every command of the fixture with its response.
+fn exchanges() -> ssp::Result<Vec<(CapturedFrame, CapturedFrame)>> {
+    let frames = capture::frames(&capture::read_capture(FIXTURE)?);
+    assert!(frames.iter().all(|frame| frame.error.is_none()));
+
+    let (tx, rx): (Vec<_>, Vec<_>) = frames
+        .into_iter()
+        .partition(|frame| frame.direction == CaptureDirection::Tx);
+    assert_eq!(tx.len(), rx.len());
+
+    Ok(tx.into_iter().zip(rx).collect())
+}
+
+// Gets the events of a poll response, without the status.
+fn events(res: &ssp::PollResponse) -> Vec<u8> {
+    res.data()[1..].into()
+}
+
+#[test]
+fn test_fixture_stuffing() -> ssp::Result<()> {
+    // the fixture covers stuffed STX bytes in encrypted frames, in both directions
+    let stuffed = |direction| {
+        capture::read_capture(FIXTURE).map(|records| {
+            records.iter().any(|record| {
+                record.direction == direction && record.data.windows(2).any(|w| w == [STX, STX])
+            })
+        })
+    };
+
+    assert!(stuffed(CaptureDirection::Tx)?);
+    assert!(stuffed(CaptureDirection::Rx)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_fixture_parsing() -> ssp::Result<()> {
+    let key = ssp::AesKey::clone_from_slice(KEY.as_ref());
+    let exchanges = exchanges()?;
+
+    let mut count = None;
+    let mut encrypted = 0;
+
+    for (command, response) in exchanges.iter() {
+        framing::check_frame(&command.frame)?;
+        framing::check_frame(&response.frame)?;
+        assert_eq!(command.sequence_id(), response.sequence_id());
+
+        if command.is_encrypted() {
+            let command = essp::decrypt(&key, command.data())?;
+            let response = essp::decrypt(&key, response.data())?;
+
+            // the count starts at zero after the negotiation, the device increments it before
+            // answering
+            assert_eq!(command.count(), count.map(|c| c + 1).unwrap_or(0));
+            assert_eq!(response.count(), command.count() + 1);
+            assert_eq!(response.data().first(), Some(&OK));
+
+            count = Some(command.count());
+            encrypted += 1;
+        } else {
+            let message_type = ssp::MessageType::from(command.data()[0]);
+            let res = framing::parse_response(&response.frame, message_type)?;
+            assert!(res.as_response().response_status().is_ok());
+        }
+    }
+
+    // Sync, SetGenerator, SetModulus, and RequestKeyExchange are sent in clear-text
+    assert!(exchanges[..4].iter().all(|(cmd, _)| !cmd.is_encrypted()));
+    assert_eq!(encrypted, exchanges.len() - 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_fixture_replay() -> ssp::Result<()> {
+    // the host fills the packing of encrypted commands with random bytes, only their length
+    // matches the fixture
+    let replay = ReplayTransport::open(FIXTURE)?.with_strict(false);
+    let mut handle = DeviceHandle::from_transport(replay)?
+        .with_entropy_source(SeededEntropy::from_data(ENTROPY));
+
+    handle.negotiate_keys()?;
+    assert_eq!(
+        handle.encryption_key()?.as_ref().map(|key| key.to_vec()),
+        Some(KEY.to_vec())
+    );
+
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+
+    let mut polled = Vec::new();
+    for _ in 0..4 {
+        polled.extend(events(&handle.poll()?));
+    }
+    assert_eq!(
+        polled,
+        [DEVICE_RESET, READ, 1, STACKING, CREDIT, 1, STACKED]
+    );
+
+    // past the end of the fixture, the device is silent
+    assert!(handle.poll().is_err());
+
+    Ok(())
+}
@@ -0,0 +1,182 @@
+//! Hardware-in-the-loop checks against a real device.
+//!
+//! Opt-in: the tests only talk to a device when `SSP_HIL_PORT` names its serial port, and pass
+//! without doing anything otherwise. The command matrix only queries the device, toggles its
+//! display, inhibits, and enabled state, and negotiates a key: no note is stacked, rejected, or
+//! paid out.
+//!
+//! ```text
+//! SSP_HIL_PORT=/dev/ttyUSB0 cargo test --test hil -- --nocapture
+//! ```
+
+use std::fmt;
+
+use ssp_server::{encryption, DeviceHandle};
+
+mod common;
+
+/// Environment variable naming the serial port of the device under test.
+const HIL_ENV_PORT: &str = "SSP_HIL_PORT";
+
+const PROTOCOL: ssp::ProtocolVersion = ssp::ProtocolVersion::Six;
+
+// Outcome of one command of the matrix.
+struct Outcome {
+    command: &'static str,
+    result: Result<String, ssp::Error>,
+}
+
+// Per-command pass/fail report of a matrix run.
+#[derive(Default)]
+struct Report {
+    device: String,
+    outcomes: Vec<Outcome>,
+}
+
+impl Report {
+    // Runs one command, and records its outcome. Returns whether the command passed.
+    fn run<T: fmt::Display>(
+        &mut self,
+        command: &'static str,
+        f: impl FnOnce() -> ssp::Result<T>,
+    ) -> bool {
+        let result = f().map(|res| res.to_string());
+        let passed = result.is_ok();
+
+        match &result {
+            Ok(res) => log::debug!("{command} passed: {res}"),
+            Err(err) => log::error!("{command} failed: {err}"),
+        }
+
+        self.outcomes.push(Outcome { command, result });
+
+        passed
+    }
+
+    fn failures(&self) -> Vec<&'static str> {
+        self.outcomes
+            .iter()
+            .filter(|o| o.result.is_err())
+            .map(|o| o.command)
+            .collect()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "HIL report for {}:", self.device)?;
+
+        for Outcome { command, result } in self.outcomes.iter() {
+            match result {
+                Ok(_) => writeln!(f, "  PASS  {command}")?,
+                Err(err) => writeln!(f, "  FAIL  {command}: {err}")?,
+            }
+        }
+
+        let failed = self.failures().len();
+        write!(
+            f,
+            "{} passed, {failed} failed",
+            self.outcomes.len() - failed
+        )
+    }
+}
+
+// Gets the serial port of the device under test, `None` if hardware tests are not requested.
+fn hil_port() -> Option<String> {
+    match std::env::var(HIL_ENV_PORT) {
+        Ok(port) if !port.is_empty() => Some(port),
+        _ => {
+            eprintln!("{HIL_ENV_PORT} not set, skipping hardware-in-the-loop test");
+            None
+        }
+    }
+}
+
+// Runs the plaintext commands of the matrix, and returns the unit type reported by the device.
+fn run_plaintext(handle: &DeviceHandle, report: &mut Report) -> Option<ssp::UnitType> {
+    report.run("sync", || handle.sync());
+    report.run("host_protocol_version", || {
+        handle.host_protocol_version(PROTOCOL)
+    });
+
+    let mut unit_type = None;
+    report.run("setup_request", || {
+        handle.setup_request().map(|res| {
+            unit_type = Some(res.unit_type());
+            res
+        })
+    });
+
+    report.run("serial_number", || handle.serial_number());
+    report.run("unit_data", || handle.unit_data());
+    report.run("dataset_version", || handle.dataset_version());
+    report.run("channel_value_data", || handle.channel_value_data());
+    report.run("last_reject_code", || handle.last_reject_code());
+    report.run("has_barcode_reader", || handle.has_barcode_reader());
+    report.run("display_off", || handle.display_off());
+    report.run("display_on", || handle.display_on());
+    report.run("set_inhibits", || {
+        handle.set_inhibits(ssp::EnableBitfieldList::from([
+            ssp::EnableBitfield::from(0xff),
+            ssp::EnableBitfield::from(0xff),
+        ]))
+    });
+    report.run("enable", || handle.enable());
+    report.run("poll", || handle.poll());
+    report.run("disable", || handle.disable());
+
+    unit_type
+}
+
+// Runs the encrypted commands of the matrix, including the payout toggles on payout units.
+fn run_encrypted(handle: &mut DeviceHandle, unit_type: Option<ssp::UnitType>, report: &mut Report) {
+    if !report.run("negotiate_keys", || handle.negotiate_keys().map(|_| "ok")) {
+        return;
+    }
+
+    report.run("encrypted serial_number", || handle.serial_number());
+    report.run("encrypted poll", || handle.poll());
+
+    if unit_type.map(encryption::is_payout_unit).unwrap_or(false) {
+        report.run("enable_payout", || handle.enable_payout());
+        report.run("disable_payout", || handle.disable_payout());
+    }
+}
+
+#[test]
+fn test_hil_command_matrix() -> ssp::Result<()> {
+    let Some(port) = hil_port() else {
+        return Ok(());
+    };
+
+    let _lock = common::init()?;
+
+    let mut handle = DeviceHandle::new(port.as_str())?;
+    let mut report = Report {
+        device: port,
+        ..Default::default()
+    };
+
+    let unit_type = run_plaintext(&handle, &mut report);
+    if let Some(unit_type) = unit_type {
+        report.device = format!("{} ({unit_type})", report.device);
+    }
+
+    run_encrypted(&mut handle, unit_type, &mut report);
+
+    // leave the device disabled, whatever state the matrix stopped in
+    handle.disable().ok();
+
+    println!("{report}");
+
+    let failures = report.failures();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(ssp::Error::Io(format!(
+            "HIL commands failed: {}",
+            failures.join(", ")
+        )))
+    }
+}
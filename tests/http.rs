@@ -0,0 +1,145 @@
+#![cfg(feature = "http")]
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use parking_lot::Mutex;
+
+use ssp_server::cash_levels::CashLevels;
+use ssp_server::transport::Transport;
+use ssp_server::{http, DeviceHandle};
+
+// Transport for endpoints that never talk to the device.
+struct IdleTransport;
+
+impl Read for IdleTransport {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::TimedOut.into())
+    }
+}
+
+impl Write for IdleTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for IdleTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+// Sends a GET request for `path`, retrying until the server listens, and returns the response.
+fn get(addr: SocketAddr, path: &str) -> ssp::Result<String> {
+    request(addr, "GET", path)
+}
+
+// Sends a `method` request for `path`, retrying until the server listens, and returns the
+// response.
+fn request(addr: SocketAddr, method: &str, path: &str) -> ssp::Result<String> {
+    let deadline = time::Instant::now() + time::Duration::from_secs(5);
+
+    let mut stream = loop {
+        match TcpStream::connect(addr) {
+            Ok(stream) => break stream,
+            Err(_) if time::Instant::now() < deadline => {
+                thread::sleep(time::Duration::from_millis(20))
+            }
+            Err(err) => return Err(err.into()),
+        }
+    };
+
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+    )?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+
+    Ok(response)
+}
+
+// Gets the body of an HTTP `response`.
+fn body(response: &str) -> &str {
+    response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body)
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_levels() -> ssp::Result<()> {
+    let path = std::env::temp_dir().join(format!("ssp-http-levels-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    let cash_levels = CashLevels::open(&path)?.with_recycled(1000);
+    cash_levels.record_credit(1000)?;
+    cash_levels.record_credit(2000)?;
+
+    let handle = DeviceHandle::from_transport(IdleTransport)?.with_cash_levels(cash_levels);
+
+    let addr = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let server = {
+        let handle = Arc::new(Mutex::new(handle));
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || http::serve(handle, addr, stop))
+    };
+
+    let levels = get(addr, "/levels")?;
+    assert!(levels.starts_with("HTTP/1.1 200"), "{levels}");
+    assert!(body(&levels).contains("\"cashbox\""), "{levels}");
+
+    // the levels are also served at the older path
+    let cash_levels = get(addr, "/cash-levels")?;
+    assert!(cash_levels.starts_with("HTTP/1.1 200"), "{cash_levels}");
+    assert_eq!(body(&cash_levels), body(&levels));
+
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap()?;
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
+
+#[test]
+fn test_client_errors() -> ssp::Result<()> {
+    let handle = DeviceHandle::from_transport(IdleTransport)?;
+
+    let addr = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let server = {
+        let handle = Arc::new(Mutex::new(handle));
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || http::serve(handle, addr, stop))
+    };
+
+    // features the server was started without
+    for path in ["/levels", "/audit", "/events", "/devices"] {
+        let res = get(addr, path)?;
+        assert!(res.starts_with("HTTP/1.1 501"), "{path}: {res}");
+        assert!(body(&res).contains("is not configured"), "{path}: {res}");
+    }
+
+    // malformed requests
+    let res = request(addr, "DELETE", "/lease")?;
+    assert!(res.starts_with("HTTP/1.1 400"), "{res}");
+
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap()?;
+
+    Ok(())
+}
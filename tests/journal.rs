@@ -0,0 +1,95 @@
+use std::io::Write;
+
+use ssp_server::journal::{JournalTotals, TransactionJournal, TransactionKind};
+
+fn journal_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("ssp-journal-{name}-{}.log", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    path
+}
+
+#[test]
+fn test_journal_replay() {
+    let path = journal_path("replay");
+
+    let journal = TransactionJournal::open(&path).unwrap();
+    assert_eq!(journal.totals(), JournalTotals::new());
+
+    let first = journal
+        .record(TransactionKind::Credit, 1234, 20, 1, "")
+        .unwrap();
+    assert_eq!(first.seq, 0);
+    journal
+        .record(TransactionKind::Credit, 1234, 50, 1, "EUR")
+        .unwrap();
+    journal
+        .record(TransactionKind::Payout, 1234, 10, 3, "EUR")
+        .unwrap();
+
+    let totals = journal.totals();
+    assert_eq!(totals.credited, 70);
+    assert_eq!(totals.credited_notes, 2);
+    assert_eq!(totals.paid_out, 30);
+    assert_eq!(totals.paid_out_notes, 3);
+    assert_eq!(totals.net(), 40);
+
+    // totals survive reopening, e.g. after a crash, and sequence numbers continue
+    drop(journal);
+    let journal = TransactionJournal::open(&path).unwrap();
+    assert_eq!(journal.totals(), totals);
+
+    let last = journal
+        .record(TransactionKind::Payout, 1234, 20, 1, "EUR")
+        .unwrap();
+    assert_eq!(last.seq, 3);
+    assert_eq!(last.amount(), 20);
+
+    let entries = journal.entries().unwrap();
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries[0], first);
+    assert_eq!(entries[3], last);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_journal_incomplete_entry() {
+    let path = journal_path("incomplete");
+
+    let journal = TransactionJournal::open(&path).unwrap();
+    journal
+        .record(TransactionKind::Credit, 1234, 20, 1, "")
+        .unwrap();
+    drop(journal);
+
+    // crash while appending the second entry
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(b"1\t1700000000000\t1234\tcre").unwrap();
+    drop(file);
+
+    let journal = TransactionJournal::open(&path).unwrap();
+    assert_eq!(journal.totals().credited, 20);
+
+    let next = journal
+        .record(TransactionKind::Credit, 1234, 5, 1, "")
+        .unwrap();
+    assert_eq!(next.seq, 1);
+    assert_eq!(journal.entries().unwrap().len(), 2);
+
+    // corrupted complete entries are not silently dropped
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(b"garbage\n").unwrap();
+    drop(file);
+
+    assert!(TransactionJournal::open(&path).is_err());
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,94 @@
+#![cfg(feature = "json-log")]
+
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use ssp_server::json_log::{JsonLogKind, JsonLogRecord, JsonLogSink};
+use ssp_server::sink::EventSink;
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn records(&self) -> Vec<JsonLogRecord> {
+        String::from_utf8(self.0.lock().clone())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+#[test]
+fn test_json_log() -> ssp::Result<()> {
+    let buffer = SharedBuffer::default();
+    let mut sink = JsonLogSink::new(buffer.clone()).with_device_id("lane-3");
+
+    let credit = ssp::Event::from(ssp::NoteCreditEvent::new(ssp::ChannelValue::from(500)));
+    sink.publish_event(&credit)?;
+    sink.record_command::<()>(ssp::Method::Stack, &Ok(()))?;
+    sink.record_command::<()>(ssp::Method::Reject, &Err(ssp::Error::QueueTimeout))?;
+
+    let records = buffer.records();
+    assert_eq!(records.len(), 3);
+
+    for record in records.iter() {
+        assert_eq!(record.device.device_id.as_deref(), Some("lane-3"));
+        assert!(record.timestamp_ms > 0);
+    }
+
+    assert_eq!(records[0].kind, JsonLogKind::Event);
+    assert_eq!(
+        records[0].method.as_deref(),
+        Some(ssp::Method::NoteCredit.to_str())
+    );
+    assert_eq!(records[0].ok, None);
+    assert!(records[0].data.is_some());
+
+    assert_eq!(records[1].kind, JsonLogKind::Command);
+    assert_eq!(records[1].ok, Some(true));
+    assert_eq!(records[1].error, None);
+
+    assert_eq!(records[2].ok, Some(false));
+    assert!(records[2].error.is_some());
+    assert_eq!(records[2].data, None);
+
+    // every record has the same fields
+    let line = String::from_utf8(buffer.0.lock().clone()).unwrap();
+    for line in line.lines() {
+        let value: serde_json::Value = serde_json::from_str(line).unwrap();
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "data",
+                "device",
+                "error",
+                "kind",
+                "method",
+                "ok",
+                "timestamp_ms"
+            ]
+        );
+    }
+
+    Ok(())
+}
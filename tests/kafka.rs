@@ -0,0 +1,67 @@
+#![cfg(feature = "kafka")]
+
+use std::net::TcpListener;
+use std::time;
+
+use ssp_server::kafka::{KafkaConfig, KafkaSink, KAFKA_TOPIC};
+
+fn credit(value: u32) -> ssp::Event {
+    ssp::Event::from(ssp::NoteCreditEvent::new(ssp::ChannelValue::from(value)))
+}
+
+#[test]
+fn test_record_value() -> ssp::Result<()> {
+    let config = KafkaConfig::new("lane-3");
+    let event = credit(500);
+
+    let value: serde_json::Value = serde_json::from_slice(&config.record_value(&event)?).unwrap();
+
+    assert_eq!(value, serde_json::to_value(&event).unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn test_topic_and_key() {
+    let config = KafkaConfig::new("lane-3");
+
+    assert_eq!(config.topic(), KAFKA_TOPIC);
+    assert_eq!(
+        config.clone().with_topic("site.events").topic(),
+        "site.events"
+    );
+
+    assert_eq!(config.record_key(0), "lane-3:0");
+    assert_eq!(config.record_key(42), "lane-3:42");
+}
+
+#[test]
+fn test_publishes() {
+    let config = KafkaConfig::new("lane-3");
+    let reset = ssp::Event::from(ssp::ResetEvent::new());
+
+    assert!(config.publishes(&credit(500)));
+    assert!(!config.publishes(&reset));
+
+    // an empty list publishes all events
+    let config = config.with_methods(&[]);
+
+    assert!(config.publishes(&credit(500)));
+    assert!(config.publishes(&reset));
+}
+
+#[test]
+fn test_unreachable_broker() -> ssp::Result<()> {
+    // bind, and release a port, so nothing listens on it
+    let addr = TcpListener::bind("127.0.0.1:0")?.local_addr()?.to_string();
+
+    let config = KafkaConfig::new("lane-3")
+        .with_brokers(&[addr.as_str()])
+        .with_ack_timeout(time::Duration::from_millis(500));
+
+    let err = KafkaSink::new(config).err().unwrap();
+
+    assert!(format!("{err}").contains("Kafka error"));
+
+    Ok(())
+}
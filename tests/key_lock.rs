@@ -0,0 +1,60 @@
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+const READ_DELAY: time::Duration = time::Duration::from_millis(2_000);
+
+// Transport blocking on every read, like a device that stopped responding.
+struct SlowTransport;
+
+impl Read for SlowTransport {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        thread::sleep(READ_DELAY);
+        Err(io::ErrorKind::TimedOut.into())
+    }
+}
+
+impl Write for SlowTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for SlowTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_key_lock_released_during_poll() -> ssp::Result<()> {
+    let handle = DeviceHandle::from_transport(SlowTransport)?;
+    let stop = Arc::new(AtomicBool::new(false));
+
+    handle.start_background_polling(Arc::clone(&stop))?;
+
+    // let the first poll, after MED_POLLING_MS, block in a read
+    thread::sleep(time::Duration::from_millis(1_000));
+
+    let start = time::Instant::now();
+    let key = handle.encryption_key()?;
+    let waited = start.elapsed();
+    drop(key);
+
+    stop.store(true, Ordering::SeqCst);
+
+    assert!(
+        waited < READ_DELAY / 2,
+        "waited {waited:?} for the encryption key"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use std::time;
+
+use ssp_server::key_negotiation::{
+    self, KeyNegotiationAttempt, KeyNegotiationDiagnostic, KeyNegotiationStep,
+    KEY_NEGOTIATION_BACKOFF_MS, KEY_NEGOTIATION_MAX_BACKOFF_MS,
+};
+
+#[test]
+fn test_backoff() {
+    let ms = time::Duration::from_millis;
+
+    assert_eq!(key_negotiation::backoff(1), ms(KEY_NEGOTIATION_BACKOFF_MS));
+    assert_eq!(
+        key_negotiation::backoff(2),
+        ms(KEY_NEGOTIATION_BACKOFF_MS * 2)
+    );
+    assert_eq!(
+        key_negotiation::backoff(3),
+        ms(KEY_NEGOTIATION_BACKOFF_MS * 4)
+    );
+
+    for attempt in [8, 32, u32::MAX] {
+        assert_eq!(
+            key_negotiation::backoff(attempt),
+            ms(KEY_NEGOTIATION_MAX_BACKOFF_MS)
+        );
+    }
+}
+
+#[test]
+fn test_diagnostic() {
+    let generator = ssp::GeneratorKey::from(0x1_0000_0001u64);
+    let modulus = ssp::ModulusKey::from(0xffu64);
+
+    let mut attempt = KeyNegotiationAttempt::new(1, &generator, &modulus);
+    assert_eq!(attempt.generator_bits, 33);
+    assert_eq!(attempt.modulus_bits, 8);
+
+    let res: ssp::Result<ssp::SetModulusResponse> = Err(ssp::Error::Timeout("no response".into()));
+    attempt.record_response(KeyNegotiationStep::SetModulus, &res);
+    attempt.error = "timed out".into();
+
+    assert_eq!(attempt.step, KeyNegotiationStep::SetModulus);
+    assert_eq!(attempt.status, None);
+    assert!(attempt.response.is_empty());
+
+    let mut diagnostic = KeyNegotiationDiagnostic::new();
+    assert!(!diagnostic.has_failures());
+
+    diagnostic.attempts.push(attempt);
+    diagnostic.succeeded = true;
+
+    assert!(diagnostic.has_failures());
+    assert_eq!(
+        format!("{diagnostic}"),
+        "key negotiation succeeded, 1 failed attempt(s); attempt #1: set_modulus failed, generator: 33 bits, modulus: 8 bits, error: timed out"
+    );
+}
@@ -0,0 +1,58 @@
+use std::time;
+
+use ssp_server::key_rotation::KeyRotationPolicy;
+
+#[test]
+fn test_key_rotation_policy() {
+    let disabled = KeyRotationPolicy::new();
+
+    assert!(!disabled.is_enabled());
+    assert!(!disabled.is_due(u32::MAX, time::Duration::from_secs(u64::MAX)));
+
+    let policy = KeyRotationPolicy::new()
+        .with_max_commands(1_000)
+        .with_max_age(time::Duration::from_secs(3_600));
+
+    assert!(policy.is_enabled());
+    assert!(!policy.is_due(999, time::Duration::from_secs(3_599)));
+
+    // whichever limit is reached first triggers rotation
+    assert!(policy.is_due(1_000, time::Duration::ZERO));
+    assert!(policy.is_due(0, time::Duration::from_secs(3_600)));
+}
+
+#[cfg(feature = "emulator")]
+#[test]
+fn test_key_rotation_age() -> ssp::Result<()> {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use ssp_server::clock::MockClock;
+    use ssp_server::emulator::{Emulator, EmulatorTransport};
+    use ssp_server::DeviceHandle;
+
+    let clock = MockClock::new();
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+
+    let mut handle = DeviceHandle::from_transport(EmulatorTransport::new(emulator))?
+        .with_key_rotation(KeyRotationPolicy::new().with_max_age(time::Duration::from_secs(3_600)))
+        .with_clock(clock.clone());
+
+    handle.negotiate_keys()?;
+    assert!(!handle.key_rotation_due());
+
+    // an hour passes without sleeping
+    clock.advance(time::Duration::from_secs(3_599));
+    assert!(!handle.rotate_key_if_due()?);
+
+    clock.advance(time::Duration::from_secs(1));
+    assert!(handle.key_rotation_due());
+    assert!(handle.rotate_key_if_due()?);
+
+    // the new key starts a new session
+    assert!(!handle.key_rotation_due());
+    handle.poll()?;
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use ssp_server::key_store::{parse_fixed_key, FileKeyStore, KeyStore};
+
+#[test]
+fn test_file_key_store() {
+    let path = std::env::temp_dir().join(format!("ssp-key-store-{}.key", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+
+    let secret = "000102030405060708090a0b0c0d0e0f";
+    let mut store = FileKeyStore::from_hex_secret(path, secret).unwrap();
+
+    assert_eq!(store.load().unwrap(), None);
+
+    let key = ssp::FixedKey::from_inner(0x0123_4567_89ab_cdef);
+    store.store(&key).unwrap();
+
+    // the key is not stored in plaintext
+    let raw = std::fs::read(path).unwrap();
+    assert!(!raw
+        .windows(8)
+        .any(|w| w == key.as_inner().to_le_bytes().as_slice()));
+
+    let reopened = FileKeyStore::from_hex_secret(path, secret).unwrap();
+    assert_eq!(reopened.load().unwrap(), Some(key));
+
+    let wrong = FileKeyStore::new(path, [0xff; 16]);
+    assert!(wrong.load().is_err());
+
+    assert!(FileKeyStore::from_hex_secret(path, "0011").is_err());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_parse_fixed_key() {
+    let default = ssp::FixedKey::from_inner(ssp::DEFAULT_FIXED_KEY_U64);
+
+    assert_eq!(parse_fixed_key("0123456701234567").unwrap(), default);
+    assert_eq!(parse_fixed_key(" 0x0123456701234567\n").unwrap(), default);
+
+    assert!(parse_fixed_key("01234567").is_err());
+    assert!(parse_fixed_key("0123456701234567ff").is_err());
+    assert!(parse_fixed_key("012345670123456g").is_err());
+}
@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use ssp_server::latency::{self, LatencyHistogram, LATENCY_BUCKETS_US};
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::frame;
+
+// Transport replying with canned bytes, one byte per read.
+struct ReplyTransport(VecDeque<u8>);
+
+impl Read for ReplyTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match (self.0.pop_front(), buf.first_mut()) {
+            (Some(byte), Some(out)) => {
+                *out = byte;
+                Ok(1)
+            }
+            _ => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
+
+impl Write for ReplyTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for ReplyTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_latency_histogram() {
+    let mut histogram = LatencyHistogram::new(ssp::MessageType::Poll);
+    assert_eq!(histogram.command, "Poll");
+    assert_eq!(histogram.buckets.len(), LATENCY_BUCKETS_US.len() + 1);
+    assert_eq!(histogram.quantile_us(0.5), None);
+
+    for ms in [1, 1, 3, 8, 40] {
+        histogram.record(Duration::from_millis(ms));
+    }
+    histogram.record(Duration::from_secs(10));
+    histogram.record_error();
+
+    assert_eq!(histogram.count, 6);
+    assert_eq!(histogram.errors, 1);
+    assert_eq!(histogram.min_us, 1_000);
+    assert_eq!(histogram.max_us, 10_000_000);
+    assert_eq!(histogram.buckets[0], 2);
+    assert_eq!(histogram.buckets[LATENCY_BUCKETS_US.len()], 1);
+    assert_eq!(histogram.quantile_us(0.5), Some(5_000));
+    assert_eq!(histogram.quantile_us(0.8), Some(50_000));
+    assert_eq!(histogram.quantile_us(1.0), Some(10_000_000));
+}
+
+#[test]
+fn test_latency_round_trips() -> ssp::Result<()> {
+    let ok = frame(0x80, &[0xf0]);
+
+    // a complete response, followed by a truncated one
+    let mut replies: VecDeque<u8> = ok.iter().copied().collect();
+    replies.extend(ok[..3].iter());
+
+    let handle = DeviceHandle::from_transport(ReplyTransport(replies))?;
+    latency::reset();
+
+    handle.sync()?;
+    assert!(handle.sync().is_err());
+
+    let histogram = latency::histogram(ssp::MessageType::Synchronisation).unwrap();
+    assert_eq!(histogram.count, 1);
+    assert_eq!(histogram.errors, 1);
+    assert_eq!(histogram.buckets.iter().sum::<u64>(), 1);
+    assert!(latency::histogram(ssp::MessageType::Poll).is_none());
+    assert_eq!(latency::histograms(), [histogram]);
+
+    let metrics = latency::to_prometheus(&latency::histograms());
+    assert!(metrics.contains("# TYPE ssp_round_trip_seconds histogram"));
+    assert!(metrics
+        .contains("ssp_round_trip_seconds_bucket{command=\"Synchronisation\",le=\"+Inf\"} 1\n"));
+    assert!(metrics.contains("ssp_round_trip_seconds_count{command=\"Synchronisation\"} 1\n"));
+    assert!(metrics.contains("ssp_round_trip_errors_total{command=\"Synchronisation\"} 1\n"));
+
+    latency::reset();
+    assert!(latency::histograms().is_empty());
+
+    Ok(())
+}
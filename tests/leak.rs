@@ -0,0 +1,87 @@
+#![cfg(all(feature = "emulator", feature = "mock"))]
+
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use ssp_server::emulator::{Emulator, EmulatorTransport};
+use ssp_server::leak::{LeakCheckTransport, LeakReport};
+use ssp_server::DeviceHandle;
+
+fn connect() -> ssp::Result<(Arc<Mutex<Emulator>>, DeviceHandle, LeakReport)> {
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+    let transport = LeakCheckTransport::new(EmulatorTransport::new(Arc::clone(&emulator)));
+    let report = transport.report().clone();
+
+    Ok((emulator, DeviceHandle::from_transport(transport)?, report))
+}
+
+#[test]
+fn test_encrypted_session_is_clean() -> ssp::Result<()> {
+    let (emulator, handle, report) = connect()?;
+    let mut handle = handle.with_require_encryption(true);
+
+    handle.negotiate_keys()?;
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    handle.set_inhibits(ssp::EnableBitfieldList::from([ssp::EnableBitfield::from(
+        0x0f,
+    )]))?;
+    handle.poll()?;
+
+    assert!(emulator.lock().enabled());
+    assert!(report.encrypted_frames() > 0);
+    assert!(report.frames() > report.encrypted_frames());
+    assert!(report.check().is_ok());
+    report.assert_no_leaks();
+
+    Ok(())
+}
+
+#[test]
+fn test_plaintext_leak_is_blocked() -> ssp::Result<()> {
+    let (emulator, handle, report) = connect()?;
+
+    // encryption is not required, so the handle sends the command in plaintext
+    handle.sync()?;
+    assert!(handle.enable().is_err());
+
+    let leaks = report.leaks();
+    assert!(!leaks.is_empty());
+    assert!(leaks
+        .iter()
+        .all(|leak| leak.command == ssp::MessageType::Enable && leak.frame > 0));
+    assert!(report.check().is_err());
+
+    // the device never saw the command
+    assert!(!emulator.lock().enabled());
+
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "guarded commands leaked on the wire")]
+fn test_assert_no_leaks() {
+    let (_emulator, handle, report) = connect().unwrap();
+
+    handle.sync().unwrap();
+    handle.enable().ok();
+
+    report.assert_no_leaks();
+}
+
+#[test]
+fn test_disarmed() -> ssp::Result<()> {
+    let (emulator, handle, report) = connect()?;
+
+    // a setup phase before encryption is enforced
+    report.set_armed(false);
+    handle.sync()?;
+    handle.enable()?;
+    assert!(emulator.lock().enabled());
+
+    report.set_armed(true);
+    assert!(report.is_clean());
+    assert!(handle.disable().is_ok());
+
+    Ok(())
+}
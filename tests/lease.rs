@@ -0,0 +1,79 @@
+use std::time;
+
+use ssp_server::lease::{ClientId, LeaseManager, LeaseReply, LeaseToken};
+
+#[test]
+fn test_lease_arbitration() {
+    let leases = LeaseManager::new();
+    let (owner, other) = (ClientId::next(), ClientId::next());
+
+    // without a lease, all clients can send commands
+    assert!(leases.authorize(None).is_ok());
+    assert!(leases.authorize(Some(other)).is_ok());
+
+    let lease = leases.claim(owner, None).unwrap();
+    assert_eq!(lease.holder(), owner);
+
+    assert!(leases.authorize(Some(owner)).is_ok());
+    assert!(leases.authorize(Some(other)).is_err());
+    assert!(leases.authorize(None).is_err());
+    assert!(leases
+        .authorize_method(Some(other), ssp::Method::Status)
+        .is_ok());
+
+    assert!(leases.claim(other, None).is_err());
+    assert!(leases.release(other).is_err());
+
+    // renewing by the holder succeeds
+    assert!(leases.claim(owner, None).is_ok());
+
+    leases.drop_client(owner);
+    assert!(leases.current().is_none());
+}
+
+#[test]
+fn test_lease_expiry() {
+    let leases = LeaseManager::new();
+    let (owner, other) = (ClientId::next(), ClientId::next());
+
+    leases
+        .claim(owner, Some(time::Duration::from_millis(10)))
+        .unwrap();
+    std::thread::sleep(time::Duration::from_millis(20));
+
+    assert!(leases.current().is_none());
+    assert!(leases.authorize(Some(other)).is_ok());
+    assert_eq!(leases.claim(other, None).unwrap().holder(), other);
+}
+
+#[test]
+fn test_lease_tokens() {
+    let leases = LeaseManager::new();
+    let (owner, other) = (ClientId::next(), ClientId::next());
+
+    let lease = leases.claim(owner, None).unwrap();
+    let token = lease.token();
+
+    assert_eq!(leases.holder_of(&token), Some(owner));
+
+    // tokens are unrelated to the client ID
+    let reply = LeaseReply::from(&lease);
+    assert_eq!(reply.token.len(), 32);
+    assert_ne!(reply.token, format!("{:032x}", owner.as_u64()));
+    assert_eq!(reply.token.parse::<LeaseToken>().unwrap(), token);
+    assert!(!format!("{token:?}").contains(&reply.token));
+
+    // guessed tokens do not match
+    assert_eq!(leases.holder_of(&LeaseToken::from_bytes([0; 16])), None);
+    assert_eq!(leases.holder_of(&LeaseToken::generate()), None);
+
+    // renewing keeps the token, a new lease gets a new one
+    assert_eq!(leases.claim(owner, None).unwrap().token(), token);
+    leases.release(owner).unwrap();
+    assert_eq!(leases.holder_of(&token), None);
+    assert_ne!(leases.claim(other, None).unwrap().token(), token);
+
+    for invalid in ["", "1", "0123456789abcdef", &"zz".repeat(16)] {
+        assert!(invalid.parse::<LeaseToken>().is_err(), "{invalid}");
+    }
+}
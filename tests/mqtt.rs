@@ -0,0 +1,71 @@
+#![cfg(feature = "mqtt")]
+
+use std::net::TcpListener;
+use std::time;
+
+use ssp_server::mqtt::{MqttConfig, MqttSink, MQTT_QUEUE_CAPACITY, MQTT_TOPIC_PREFIX};
+use ssp_server::sink::EventSink;
+
+fn credit(value: u32) -> ssp::Event {
+    ssp::Event::from(ssp::NoteCreditEvent::new(ssp::ChannelValue::from(value)))
+}
+
+#[test]
+fn test_event_payload() -> ssp::Result<()> {
+    let config = MqttConfig::new("127.0.0.1");
+    let event = credit(500);
+
+    let payload: serde_json::Value =
+        serde_json::from_slice(&config.event_payload(&event)?).unwrap();
+
+    assert_eq!(payload, serde_json::to_value(&event).unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn test_topics() {
+    let config = MqttConfig::new("127.0.0.1");
+
+    assert_eq!(config.topic_prefix(), MQTT_TOPIC_PREFIX);
+    assert_eq!(
+        config.event_topic(&credit(500)),
+        format!(
+            "{MQTT_TOPIC_PREFIX}/events/{}",
+            ssp::Method::NoteCredit.to_str()
+        )
+    );
+    assert_eq!(config.status_topic(), format!("{MQTT_TOPIC_PREFIX}/status"));
+
+    // trailing separators are trimmed from the prefix
+    let config = config.with_topic_prefix("site/lane-3/");
+    let reset = ssp::Event::from(ssp::ResetEvent::new());
+
+    assert_eq!(
+        config.event_topic(&reset),
+        format!("site/lane-3/events/{}", reset.method().to_str())
+    );
+    assert_eq!(config.status_topic(), "site/lane-3/status");
+}
+
+#[test]
+fn test_unreachable_broker() -> ssp::Result<()> {
+    // bind, and release a port, so nothing listens on it
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+
+    let config = MqttConfig::new("127.0.0.1")
+        .with_port(port)
+        .with_keep_alive(time::Duration::from_secs(5));
+    let mut sink = MqttSink::new(config);
+
+    // events are queued until the queue is full, then publishing fails instead of blocking
+    let credit = credit(500);
+    let queued = (0..MQTT_QUEUE_CAPACITY * 2)
+        .take_while(|_| sink.publish_event(&credit).is_ok())
+        .count();
+
+    assert!(queued <= MQTT_QUEUE_CAPACITY);
+    assert!(sink.publish_event(&credit).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use parking_lot::Mutex;
+
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::{frame, wait_for};
+
+const POLL: u8 = 0x07;
+
+// Transport answering every command with OK, and recording the written frames.
+#[derive(Default)]
+struct DeviceTransport {
+    wire: VecDeque<u8>,
+    writes: Arc<Mutex<Vec<Vec<u8>>>>,
+    polls: Arc<AtomicUsize>,
+}
+
+impl Read for DeviceTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for DeviceTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.get(3) == Some(&POLL) {
+            self.polls.fetch_add(1, Ordering::SeqCst);
+        }
+
+        self.writes.lock().push(buf.to_vec());
+        self.wire.extend(frame(0x80, &[0xf0]));
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for DeviceTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+}
+
+#[test]
+fn test_independent_polling() -> ssp::Result<()> {
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let a_polls = Arc::new(AtomicUsize::new(0));
+    let a = DeviceHandle::from_transport(DeviceTransport {
+        polls: Arc::clone(&a_polls),
+        ..Default::default()
+    })?;
+
+    let b_polls = Arc::new(AtomicUsize::new(0));
+    let b = DeviceHandle::from_transport(DeviceTransport {
+        polls: Arc::clone(&b_polls),
+        ..Default::default()
+    })?;
+
+    a.start_background_polling(Arc::clone(&stop))?;
+    b.start_background_polling(Arc::clone(&stop))?;
+
+    let timeout = time::Duration::from_secs(5);
+    assert!(wait_for(&a_polls, 1, timeout));
+    assert!(wait_for(&b_polls, 1, timeout));
+
+    // device A stops answering polls while it resets
+    a.reset()?;
+    thread::sleep(time::Duration::from_millis(100));
+    let a_paused = a_polls.load(Ordering::SeqCst);
+
+    // device B keeps polling
+    let b_start = b_polls.load(Ordering::SeqCst);
+    assert!(wait_for(&b_polls, b_start + 3, timeout));
+    assert_eq!(a_polls.load(Ordering::SeqCst), a_paused);
+
+    stop.store(true, Ordering::SeqCst);
+
+    Ok(())
+}
+
+#[test]
+fn test_independent_sequence_flags() -> ssp::Result<()> {
+    let a_writes = Arc::new(Mutex::new(Vec::new()));
+    let a = DeviceHandle::from_transport(DeviceTransport {
+        writes: Arc::clone(&a_writes),
+        ..Default::default()
+    })?;
+
+    let b_writes = Arc::new(Mutex::new(Vec::new()));
+    let b = DeviceHandle::from_transport(DeviceTransport {
+        writes: Arc::clone(&b_writes),
+        ..Default::default()
+    })?;
+
+    // commands to device A do not toggle the flag of device B
+    b.display_on()?;
+    a.display_on()?;
+    a.display_on()?;
+    a.display_on()?;
+    b.display_on()?;
+
+    let flag = |frame: &Vec<u8>| frame[1] & 0x80;
+
+    let a_writes = a_writes.lock();
+    assert_ne!(flag(&a_writes[0]), flag(&a_writes[1]));
+    assert_ne!(flag(&a_writes[1]), flag(&a_writes[2]));
+
+    let b_writes = b_writes.lock();
+    assert_ne!(flag(&b_writes[0]), flag(&b_writes[1]));
+
+    Ok(())
+}
+
+#[cfg(feature = "emulator")]
+#[test]
+fn test_independent_device_state() -> ssp::Result<()> {
+    use ssp_server::emulator::{Emulator, EmulatorTransport};
+
+    let a_emulator = Arc::new(Mutex::new(Emulator::new().with_serial_number(1111)));
+    let a = DeviceHandle::from_transport(EmulatorTransport::new(a_emulator))?;
+
+    let b_emulator = Arc::new(Mutex::new(Emulator::new().with_serial_number(2222)));
+    let b = DeviceHandle::from_transport(EmulatorTransport::new(b_emulator))?;
+
+    a.host_protocol_version(ssp::ProtocolVersion::Eight)?;
+    b.host_protocol_version(ssp::ProtocolVersion::Six)?;
+
+    a.serial_number()?;
+    b.serial_number()?;
+
+    // the device queried last does not overwrite the state of the other handle
+    assert_eq!(a.device_serial_number(), 1111);
+    assert_eq!(b.device_serial_number(), 2222);
+
+    assert_eq!(a.protocol_version(), ssp::ProtocolVersion::Eight);
+    assert_eq!(b.protocol_version(), ssp::ProtocolVersion::Six);
+
+    assert_eq!(
+        a.snapshot().protocol_version,
+        Some(ssp::ProtocolVersion::Eight.into())
+    );
+    assert_eq!(
+        b.snapshot().protocol_version,
+        Some(ssp::ProtocolVersion::Six.into())
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "emulator")]
+#[test]
+fn test_independent_sequence_counts() -> ssp::Result<()> {
+    use ssp_server::emulator::{Emulator, EmulatorTransport};
+
+    let a_emulator = Arc::new(Mutex::new(Emulator::new()));
+    let mut a = DeviceHandle::from_transport(EmulatorTransport::new(Arc::clone(&a_emulator)))?;
+
+    let b_emulator = Arc::new(Mutex::new(Emulator::new()));
+    let mut b = DeviceHandle::from_transport(EmulatorTransport::new(Arc::clone(&b_emulator)))?;
+
+    a.negotiate_keys()?;
+    for _ in 0..3 {
+        a.poll()?;
+    }
+
+    // a new session on one handle does not reset the count of the other
+    b.negotiate_keys()?;
+    a.poll()?;
+    b.poll()?;
+
+    assert!(!a.session_desynced());
+    assert!(!b.session_desynced());
+    assert_eq!(a.replayed_responses(), 0);
+    assert_eq!(a.sequence_resyncs(), 0);
+
+    assert_eq!(a_emulator.lock().sequence_count(), 4);
+    assert_eq!(b_emulator.lock().sequence_count(), 1);
+
+    Ok(())
+}
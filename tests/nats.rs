@@ -0,0 +1,67 @@
+#![cfg(feature = "nats")]
+
+use std::net::TcpListener;
+
+use ssp_server::nats::{NatsConfig, NatsSink, NATS_SUBJECT_PREFIX};
+
+fn credit(value: u32) -> ssp::Event {
+    ssp::Event::from(ssp::NoteCreditEvent::new(ssp::ChannelValue::from(value)))
+}
+
+#[test]
+fn test_event_payload() -> ssp::Result<()> {
+    let config = NatsConfig::new("lane-3");
+    let event = credit(500);
+
+    let payload: serde_json::Value =
+        serde_json::from_slice(&config.event_payload(&event)?).unwrap();
+
+    assert_eq!(payload, serde_json::to_value(&event).unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn test_subjects() {
+    let config = NatsConfig::new("lane-3");
+    let credit = credit(500);
+    let method = credit.method().to_str();
+
+    assert_eq!(config.subject_prefix(), NATS_SUBJECT_PREFIX);
+    assert_eq!(
+        config.event_subject(&credit),
+        format!("{NATS_SUBJECT_PREFIX}.lane-3.events.{method}")
+    );
+    assert_eq!(
+        config.status_subject(),
+        format!("{NATS_SUBJECT_PREFIX}.lane-3.status")
+    );
+
+    // trailing separators are trimmed from the prefix
+    let config = config.with_subject_prefix("site.");
+
+    assert_eq!(
+        config.event_subject(&credit),
+        format!("site.lane-3.events.{method}")
+    );
+}
+
+#[test]
+fn test_device_token() {
+    // separators, wildcards, and whitespace would split, or widen subjects
+    let config = NatsConfig::new("lane 3.a*>");
+
+    assert_eq!(config.device(), "lane_3_a__");
+    assert_eq!(config.status_subject(), "ssp.lane_3_a__.status");
+}
+
+#[test]
+fn test_unreachable_server() -> ssp::Result<()> {
+    // bind, and release a port, so nothing listens on it
+    let addr = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let config = NatsConfig::new("lane-3").with_url(format!("nats://{addr}").as_str());
+
+    assert!(NatsSink::new(config).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use std::io::{self, Read, Write};
+use std::time;
+
+use ssp_server::essp::{self, Packet};
+use ssp_server::framing::{self, FrameDecoder, FrameTimeouts};
+use ssp_server::transport::Transport;
+
+mod common;
+
+use common::{frame, STX};
+
+const STEXN: u8 = 0x7e;
+
+// SplitMix64, to run the fuzz target bodies on a fixed corpus in CI, without a fuzzer.
+struct Corpus(u64);
+
+impl Corpus {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Random bytes, biased towards STX, STEX, and short lengths, to reach the frame checks.
+    fn input(&mut self, max_len: usize) -> Vec<u8> {
+        let len = self.next_u64() as usize % max_len;
+
+        (0..len)
+            .map(|_| match self.next_u64() % 8 {
+                0 => STX,
+                1 => STEXN,
+                2 => (self.next_u64() % 8) as u8,
+                _ => self.next_u64() as u8,
+            })
+            .collect()
+    }
+}
+
+struct WireTransport<'a> {
+    wire: &'a [u8],
+}
+
+impl Read for WireTransport<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.wire.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        let n = buf.len().min(self.wire.len());
+        buf[..n].copy_from_slice(&self.wire[..n]);
+        self.wire = &self.wire[n..];
+
+        Ok(n)
+    }
+}
+
+impl Write for WireTransport<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for WireTransport<'_> {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_check_frame() {
+    let valid = frame(0x80, &[0xf0, 0x01]);
+    assert!(framing::check_frame(&valid).is_ok());
+
+    // a length field past the end of the frame
+    let mut long = valid.clone();
+    long[2] = 0xff;
+    assert!(framing::check_frame(&long).is_err());
+
+    // a length field short of the end of the frame
+    let mut short = valid.clone();
+    short[2] = 0x00;
+    assert!(framing::check_frame(&short).is_err());
+
+    assert!(framing::check_frame(&valid[..4]).is_err());
+    assert!(framing::check_frame(&[]).is_err());
+    assert!(framing::check_frame(&frame(0x80, &[]).as_slice()[1..]).is_err());
+
+    assert!(framing::parse_response(&long, ssp::MessageType::Poll).is_err());
+    assert!(framing::parse_response(&[STX], ssp::MessageType::SetupRequest).is_err());
+    assert!(framing::parse_response(&valid, ssp::MessageType::Poll).is_ok());
+}
+
+#[test]
+fn test_decrypt() -> ssp::Result<()> {
+    let key = ssp::AesKey::from(ssp::FixedKey::new());
+
+    let packet = Packet::new(42, &[0xf0, 0xef, 0x01]);
+    let encrypted = essp::encrypt(&key, &packet)?;
+    assert_eq!(encrypted[0], STEXN);
+    assert_eq!((encrypted.len() - 1) % 16, 0);
+    assert_eq!(essp::decrypt(&key, &encrypted)?, packet);
+
+    // truncated, and corrupted packets fail, instead of trusting the length field
+    assert!(essp::decrypt(&key, &encrypted[..encrypted.len() - 1]).is_err());
+    assert!(essp::decrypt(&key, &encrypted[..1]).is_err());
+    assert!(essp::decrypt(&key, &[]).is_err());
+
+    let mut corrupted = encrypted.clone();
+    corrupted[1] ^= 0x01;
+    assert!(essp::decrypt(&key, &corrupted).is_err());
+
+    let mut plaintext = encrypted;
+    plaintext[0] = STX;
+    assert!(essp::decrypt(&key, &plaintext).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_fuzz_corpus() {
+    let mut corpus = Corpus(0x5eed);
+    let key = ssp::AesKey::from(ssp::FixedKey::new());
+    let timeouts = FrameTimeouts {
+        response: time::Duration::ZERO,
+        inter_byte: time::Duration::ZERO,
+    };
+
+    for _ in 0..2_000 {
+        let input = corpus.input(300);
+
+        // frame_decoder
+        let mut decoder = FrameDecoder::new();
+        for &byte in input.iter() {
+            match decoder.push(byte) {
+                Ok(true) => {
+                    let frame = decoder.frame().to_vec();
+                    let stuffed = framing::stuff(&frame).unwrap();
+                    assert_eq!(framing::unstuff(&stuffed).ok(), Some(frame));
+                    decoder.reset();
+                }
+                Ok(false) => (),
+                Err(_) => decoder.reset(),
+            }
+        }
+
+        // read_frame
+        let mut transport = WireTransport { wire: &input };
+        while framing::read_frame_timeout(&mut transport, &mut decoder, timeouts).is_ok() {
+            assert!(framing::check_frame(decoder.frame()).is_ok());
+        }
+
+        // parse_response, with valid CRCs, so parsing gets past the frame checks
+        let data = &input[..input.len().min(255)];
+        for command in [
+            ssp::MessageType::Poll,
+            ssp::MessageType::SetupRequest,
+            ssp::MessageType::ChannelValueData,
+            ssp::MessageType::Encrypted,
+        ] {
+            let _ = framing::parse_response(&input, command);
+            let _ = framing::parse_response(&frame(0x80, data), command);
+        }
+
+        // decrypt
+        let mut packet = vec![STEXN];
+        packet.extend_from_slice(&input[..input.len() / 16 * 16]);
+        if let Ok(decrypted) = essp::decrypt(&key, &packet) {
+            let encrypted = essp::encrypt(&key, &decrypted).unwrap();
+            assert_eq!(essp::decrypt(&key, &encrypted).ok(), Some(decrypted));
+        }
+    }
+}
@@ -0,0 +1,546 @@
+#![cfg(feature = "emulator")]
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use parking_lot::Mutex;
+
+use ssp::MessageOps;
+use ssp_server::emulator::{
+    Emulator, EmulatorTransport, PayoutState, EVENT_DISPENSED, EVENT_DISPENSING, EVENT_EMPTIED,
+    EVENT_EMPTYING, EVENT_INCOMPLETE_PAYOUT, EVENT_JAMMED, EVENT_SMART_EMPTIED,
+    EVENT_SMART_EMPTYING, PAYOUT_ERROR_BUSY, PAYOUT_ERROR_DISABLED, PAYOUT_ERROR_EXACT_AMOUNT,
+    PAYOUT_ERROR_NOT_ENOUGH_VALUE, PAYOUT_OPTION_PAYOUT, PAYOUT_OPTION_TEST,
+};
+use ssp_server::encryption::{EncryptionMode, EncryptionPolicy, UNIT_SMART_PAYOUT};
+use ssp_server::journal::{TransactionJournal, TransactionKind};
+use ssp_server::payout_intent::{IntentState, PayoutIntentLog};
+use ssp_server::transport::Transport;
+use ssp_server::{framing, DeviceHandle, PollMode};
+
+const STX: u8 = 0x7f;
+
+const POLL: u8 = 0x07;
+const ENABLE: u8 = 0x0a;
+const SET_INHIBITS: u8 = 0x02;
+const ENABLE_PAYOUT: u8 = 0x5c;
+const DISABLE_PAYOUT: u8 = 0x5b;
+const PAYOUT_BY_DENOMINATION: u8 = 0x46;
+const EMPTY: u8 = 0x3f;
+const SMART_EMPTY: u8 = 0x52;
+
+const OK: u8 = 0xf0;
+const COMMAND_NOT_KNOWN: u8 = 0xf2;
+const CANNOT_BE_PROCESSED: u8 = 0xf5;
+const DEVICE_RESET: u8 = 0xf1;
+const DISABLED: u8 = 0xe8;
+
+// Stored notes of the default channels: 5, 10, 20, 50, 100, 200, and 500.
+const LEVELS: [u32; 7] = [0, 2, 1, 0, 0, 0, 0];
+
+// Sends the command `data` to the emulator, and returns the response data.
+fn command(emulator: &mut Emulator, seq: u8, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![STX, seq, data.len() as u8];
+    frame.extend_from_slice(data);
+
+    let crc = ssp::crc::crc16(&frame[1..]);
+    frame.extend_from_slice(crc.to_le_bytes().as_ref());
+
+    let response = framing::unstuff(&emulator.receive(&frame)).unwrap();
+
+    response[3..response.len() - 2].into()
+}
+
+// Sends the commands in order, toggling the sequence flag, and returns the last response data.
+fn commands(emulator: &mut Emulator, commands: &[&[u8]]) -> Vec<u8> {
+    let mut res = Vec::new();
+    for (i, data) in commands.iter().enumerate() {
+        res = command(emulator, if i % 2 == 0 { 0x80 } else { 0x00 }, data);
+    }
+
+    res
+}
+
+// Builds a PayoutByDenomination command for (count, value) pairs in EUR.
+fn payout(notes: &[(u16, u32)], option: u8) -> Vec<u8> {
+    let mut data = vec![PAYOUT_BY_DENOMINATION, notes.len() as u8];
+    for (count, value) in notes {
+        data.extend_from_slice(count.to_le_bytes().as_ref());
+        data.extend_from_slice(value.to_le_bytes().as_ref());
+        data.extend_from_slice(b"EUR");
+    }
+    data.push(option);
+
+    data
+}
+
+// Builds a single country payout event with the `value`.
+fn value_event(status: u8, value: u32) -> Vec<u8> {
+    let mut event = vec![status, 1];
+    event.extend_from_slice(value.to_le_bytes().as_ref());
+    event.extend_from_slice(b"EUR");
+
+    event
+}
+
+// Polls the emulator, and returns the events, without the status, and the Disabled event.
+fn poll(emulator: &mut Emulator, seq: u8) -> Vec<u8> {
+    let mut res = command(emulator, seq, &[POLL]);
+    if res.last() == Some(&DISABLED) {
+        res.pop();
+    }
+
+    res[1..].into()
+}
+
+fn payout_emulator() -> Emulator {
+    let mut emulator = Emulator::new()
+        .with_unit_type(UNIT_SMART_PAYOUT)
+        .with_levels(&LEVELS);
+
+    // the reset after power on
+    assert_eq!(poll(&mut emulator, 0x80), [DEVICE_RESET]);
+
+    emulator
+}
+
+fn payout_handle(intents: &PayoutIntentLog) -> ssp::Result<(Arc<Mutex<Emulator>>, DeviceHandle)> {
+    let emulator = Arc::new(Mutex::new(payout_emulator()));
+    let mut handle = DeviceHandle::from_transport(EmulatorTransport::new(Arc::clone(&emulator)))?
+        .with_payout_intents(intents.clone());
+
+    handle.sync()?;
+    handle.negotiate_keys()?;
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+
+    Ok((emulator, handle))
+}
+
+fn intent_log(name: &str) -> ssp::Result<(String, PayoutIntentLog)> {
+    let path = std::env::temp_dir().join(format!(
+        "ssp-payout-intents-{name}-{}.txt",
+        std::process::id()
+    ));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    let intents = PayoutIntentLog::open(&path)?;
+
+    Ok((path, intents))
+}
+
+fn two_tens() -> ssp::PayoutDenominationList {
+    [ssp::PayoutDenomination::create(
+        2,
+        1000,
+        ssp::CountryCode::from(b"EUR"),
+    )]
+    .as_ref()
+    .into()
+}
+
+// Polls in the background until the intent with `id` is resolved, or the timeout expires.
+fn poll_until_resolved(
+    handle: &DeviceHandle,
+    intents: &PayoutIntentLog,
+    id: u64,
+) -> ssp::Result<IntentState> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let _rx = handle.start_background_polling_with_queue(Arc::clone(&stop), PollMode::Auto)?;
+
+    let deadline = time::Instant::now() + time::Duration::from_secs(5);
+    let mut state = IntentState::Issued;
+
+    while time::Instant::now() < deadline {
+        state = intents.intent(id).map(|i| i.state).unwrap_or_default();
+        if state.is_resolved() {
+            break;
+        }
+        thread::sleep(time::Duration::from_millis(10));
+    }
+
+    stop.store(true, Ordering::SeqCst);
+
+    Ok(state)
+}
+
+#[test]
+fn test_dispense() -> ssp::Result<()> {
+    let emulator = Arc::new(Mutex::new(payout_emulator()));
+    let mut handle = DeviceHandle::from_transport(EmulatorTransport::new(Arc::clone(&emulator)))?;
+
+    handle.sync()?;
+    handle.negotiate_keys()?;
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    assert!(emulator.lock().payout_enabled());
+
+    let eur = ssp::CountryCode::from(b"EUR");
+    let list = [
+        ssp::PayoutDenomination::create(2, 1000, eur),
+        ssp::PayoutDenomination::create(1, 2000, eur),
+    ]
+    .as_ref()
+    .into();
+
+    handle.dispense(&list)?;
+
+    assert_eq!(
+        emulator.lock().payout_state(),
+        PayoutState::Dispensing {
+            dispensed: 0,
+            requested: 4000
+        }
+    );
+
+    // one note per poll, then the total
+    let mut polled = Vec::new();
+    for _ in 0..4 {
+        let res = handle.poll()?;
+        let events = &res.data()[1..];
+        polled.push(events[..events.len() - 1].to_vec());
+    }
+    assert_eq!(
+        polled,
+        [
+            value_event(EVENT_DISPENSING, 1000),
+            value_event(EVENT_DISPENSING, 2000),
+            value_event(EVENT_DISPENSING, 4000),
+            value_event(EVENT_DISPENSED, 4000),
+        ]
+    );
+
+    let emulator = emulator.lock();
+    assert_eq!(emulator.payout_state(), PayoutState::Idle);
+    assert!(emulator.levels().iter().all(|&level| level == 0));
+
+    Ok(())
+}
+
+#[test]
+fn test_payout_errors() {
+    let mut emulator = payout_emulator();
+
+    let two_tens = payout(&[(2, 1000)], PAYOUT_OPTION_PAYOUT);
+
+    assert_eq!(
+        command(&mut emulator, 0x00, &two_tens),
+        [CANNOT_BE_PROCESSED, PAYOUT_ERROR_DISABLED]
+    );
+
+    assert_eq!(command(&mut emulator, 0x80, &[ENABLE_PAYOUT, 0x03]), [OK]);
+    assert_eq!(
+        command(
+            &mut emulator,
+            0x00,
+            &payout(&[(1, 50000)], PAYOUT_OPTION_TEST)
+        ),
+        [CANNOT_BE_PROCESSED, PAYOUT_ERROR_NOT_ENOUGH_VALUE]
+    );
+
+    // enough value, but no 5 EUR notes stored
+    assert_eq!(
+        command(
+            &mut emulator,
+            0x80,
+            &payout(&[(2, 500)], PAYOUT_OPTION_TEST)
+        ),
+        [CANNOT_BE_PROCESSED, PAYOUT_ERROR_EXACT_AMOUNT]
+    );
+
+    // the test leaves the levels untouched
+    assert_eq!(
+        command(
+            &mut emulator,
+            0x00,
+            &payout(&[(2, 1000)], PAYOUT_OPTION_TEST)
+        ),
+        [OK]
+    );
+    assert_eq!(emulator.levels(), LEVELS);
+    assert_eq!(emulator.payout_state(), PayoutState::Idle);
+
+    assert_eq!(command(&mut emulator, 0x80, &two_tens), [OK]);
+    assert_eq!(
+        command(
+            &mut emulator,
+            0x00,
+            &payout(&[(1, 2000)], PAYOUT_OPTION_PAYOUT)
+        ),
+        [CANNOT_BE_PROCESSED, PAYOUT_ERROR_BUSY]
+    );
+    assert_eq!(
+        command(&mut emulator, 0x80, &[EMPTY]),
+        [CANNOT_BE_PROCESSED, PAYOUT_ERROR_BUSY]
+    );
+
+    // validators do not know payout commands
+    let mut validator = Emulator::new();
+    assert_eq!(
+        command(&mut validator, 0x80, &[ENABLE_PAYOUT, 0x03]),
+        [COMMAND_NOT_KNOWN]
+    );
+}
+
+#[test]
+fn test_payout_jam() {
+    let mut emulator = payout_emulator();
+
+    commands(
+        &mut emulator,
+        &[
+            &[ENABLE_PAYOUT, 0x03],
+            &payout(&[(2, 1000)], PAYOUT_OPTION_PAYOUT),
+        ],
+    );
+
+    assert_eq!(
+        poll(&mut emulator, 0x80),
+        value_event(EVENT_DISPENSING, 1000)
+    );
+
+    // a jammed payout is reported on every poll, until cleared
+    emulator.jam_payout();
+    assert_eq!(poll(&mut emulator, 0x00), value_event(EVENT_JAMMED, 1000));
+    assert_eq!(poll(&mut emulator, 0x80), value_event(EVENT_JAMMED, 1000));
+
+    emulator.clear_payout_jam();
+
+    let mut incomplete = vec![EVENT_INCOMPLETE_PAYOUT, 1];
+    incomplete.extend_from_slice(1000u32.to_le_bytes().as_ref());
+    incomplete.extend_from_slice(2000u32.to_le_bytes().as_ref());
+    incomplete.extend_from_slice(b"EUR");
+
+    assert_eq!(poll(&mut emulator, 0x00), incomplete);
+    assert!(poll(&mut emulator, 0x80).is_empty());
+
+    // the note not dispensed stays in the payout
+    assert_eq!(emulator.level(2), 1);
+    assert_eq!(emulator.payout_state(), PayoutState::Idle);
+}
+
+#[test]
+fn test_payout_power_cycle() {
+    let mut emulator = payout_emulator();
+
+    commands(
+        &mut emulator,
+        &[
+            &[ENABLE_PAYOUT, 0x03],
+            &payout(&[(1, 1000), (1, 2000)], PAYOUT_OPTION_PAYOUT),
+        ],
+    );
+    assert_eq!(
+        poll(&mut emulator, 0x80),
+        value_event(EVENT_DISPENSING, 1000)
+    );
+
+    emulator.power_cycle();
+    assert!(!emulator.payout_enabled());
+
+    let mut incomplete = vec![EVENT_INCOMPLETE_PAYOUT, 1];
+    incomplete.extend_from_slice(1000u32.to_le_bytes().as_ref());
+    incomplete.extend_from_slice(3000u32.to_le_bytes().as_ref());
+    incomplete.extend_from_slice(b"EUR");
+
+    // the abandoned payout is reported after the reset
+    assert_eq!(poll(&mut emulator, 0x80), [DEVICE_RESET]);
+    assert_eq!(poll(&mut emulator, 0x00), incomplete);
+    assert_eq!(emulator.levels(), [0, 1, 1, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_store_and_empty() -> ssp::Result<()> {
+    let mut emulator = payout_emulator();
+
+    commands(
+        &mut emulator,
+        &[&[SET_INHIBITS, 0xff], &[ENABLE], &[ENABLE_PAYOUT, 0x03]],
+    );
+
+    // credited notes are stored in the enabled payout
+    emulator.credit(4)?;
+    assert_eq!(emulator.level(4), 1);
+
+    commands(&mut emulator, &[&[DISABLE_PAYOUT]]);
+    emulator.credit(4)?;
+    assert_eq!(emulator.level(4), 1);
+
+    let mut seq = 0x80;
+    while emulator.pending_events() > 0 {
+        poll(&mut emulator, seq);
+        seq ^= 0x80;
+    }
+
+    // 2x 10, 20, and 50 EUR
+    assert_eq!(command(&mut emulator, seq, &[SMART_EMPTY]), [OK]);
+    assert!(emulator.levels().iter().all(|&level| level == 0));
+
+    assert_eq!(
+        poll(&mut emulator, seq ^ 0x80),
+        value_event(EVENT_SMART_EMPTYING, 9000)
+    );
+    assert_eq!(
+        poll(&mut emulator, seq),
+        value_event(EVENT_SMART_EMPTIED, 9000)
+    );
+
+    assert_eq!(command(&mut emulator, seq ^ 0x80, &[EMPTY]), [OK]);
+    assert_eq!(poll(&mut emulator, seq), [EVENT_EMPTYING]);
+    assert_eq!(poll(&mut emulator, seq ^ 0x80), [EVENT_EMPTIED]);
+
+    Ok(())
+}
+
+#[test]
+fn test_payout_intent_jammed() -> ssp::Result<()> {
+    let (path, intents) = intent_log("jammed")?;
+    let (emulator, handle) = payout_handle(&intents)?;
+
+    handle.dispense(&two_tens())?;
+
+    let intent = intents.intents().pop().unwrap();
+    assert_eq!(intent.state, IntentState::Issued);
+    assert_eq!(intent.amount(), 2000);
+
+    emulator.lock().jam_payout();
+
+    assert_eq!(
+        poll_until_resolved(&handle, &intents, intent.id)?,
+        IntentState::Incomplete
+    );
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
+
+#[test]
+fn test_payout_intent_incomplete() -> ssp::Result<()> {
+    let (path, intents) = intent_log("incomplete")?;
+    let (emulator, handle) = payout_handle(&intents)?;
+
+    // denomination payouts are recorded, like dispensing
+    handle.payout_by_denomination(&two_tens())?;
+
+    let intent = intents.intents().pop().unwrap();
+    assert_eq!(intent.state, IntentState::Issued);
+
+    {
+        let mut emulator = emulator.lock();
+        emulator.jam_payout();
+        emulator.clear_payout_jam();
+    }
+
+    assert_eq!(
+        poll_until_resolved(&handle, &intents, intent.id)?,
+        IntentState::Incomplete
+    );
+    assert!(intents.unresolved().is_empty());
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
+
+#[test]
+fn test_payout_intent_submit() -> ssp::Result<()> {
+    let (path, intents) = intent_log("submit")?;
+    let (_emulator, handle) = payout_handle(&intents)?;
+
+    let payout = ssp::PayoutByDenominationCommand::new().with_payout_denominations(&two_tens());
+
+    // test payouts dispense nothing, and are not recorded
+    handle
+        .submit(payout.with_payout_option(ssp::PayoutOption::TestPayoutAmount))
+        .wait()?;
+    assert!(intents.intents().is_empty());
+
+    handle
+        .submit(payout.with_payout_option(ssp::PayoutOption::PayoutAmount))
+        .wait()?;
+
+    let recorded = intents.intents();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].state, IntentState::Issued);
+    assert_eq!(recorded[0].amount(), 2000);
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
+
+// Emulator transport failing to send the plaintext DisablePayout command.
+struct FailingDisable(EmulatorTransport);
+
+impl Read for FailingDisable {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for FailingDisable {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.get(3) == Some(&DISABLE_PAYOUT) {
+            Err(io::ErrorKind::BrokenPipe.into())
+        } else {
+            self.0.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Transport for FailingDisable {
+    fn clear(&mut self) -> ssp::Result<()> {
+        self.0.clear()
+    }
+}
+
+#[test]
+fn test_dispense_disable_failure() -> ssp::Result<()> {
+    let path = std::env::temp_dir().join(format!("ssp-payout-journal-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    let journal = TransactionJournal::open(&path)?;
+
+    let emulator = Arc::new(Mutex::new(payout_emulator()));
+    let transport = FailingDisable(EmulatorTransport::new(Arc::clone(&emulator)));
+
+    // send the payout in plaintext, so the transport can tell the commands apart
+    let policy = EncryptionPolicy::new()
+        .with_mode(ssp::MessageType::EnablePayout, EncryptionMode::Plain)
+        .with_mode(
+            ssp::MessageType::PayoutByDenomination,
+            EncryptionMode::Plain,
+        );
+
+    let handle = DeviceHandle::from_transport(transport)?
+        .with_encryption_policy(policy)
+        .with_journal(journal.clone());
+
+    handle.sync()?;
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+
+    // the disable error is reported, after the payout was recorded
+    assert!(handle.dispense(&two_tens()).is_err());
+    assert!(!handle.dispensing());
+
+    let entries = journal.entries()?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].kind, TransactionKind::Payout);
+    assert_eq!(entries[0].amount(), 2000);
+
+    assert!(matches!(
+        emulator.lock().payout_state(),
+        PayoutState::Dispensing { .. }
+    ));
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use std::io::Write;
+
+use ssp_server::cash_levels::DenominationLevel;
+use ssp_server::payout_intent::{IntentState, PayoutIntentLog};
+
+fn log_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!(
+        "ssp-payout-intents-{name}-{}.txt",
+        std::process::id()
+    ));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    path
+}
+
+fn payout_list() -> ssp::PayoutDenominationList {
+    let eur = ssp::CountryCode::from(b"EUR");
+
+    [
+        ssp::PayoutDenomination::create(2, 1000, eur),
+        ssp::PayoutDenomination::create(1, 2000, eur),
+        ssp::PayoutDenomination::create(0, 5000, eur),
+    ]
+    .as_ref()
+    .into()
+}
+
+#[test]
+fn test_payout_intent_lifecycle() -> ssp::Result<()> {
+    let path = log_path("lifecycle");
+
+    let intents = PayoutIntentLog::open(&path)?;
+    assert!(intents.intents().is_empty());
+
+    let first = intents.begin(0x1234, &payout_list())?;
+    assert_eq!(first.id, 1);
+    assert_eq!(first.state, IntentState::Pending);
+    assert_eq!(first.currency, "EUR");
+    assert_eq!(
+        first.notes,
+        [
+            DenominationLevel::new(1000, 2),
+            DenominationLevel::new(2000, 1)
+        ]
+    );
+    assert_eq!(first.amount(), 4000);
+
+    let second = intents.begin(0x1234, &payout_list())?;
+    assert_eq!(second.id, 2);
+
+    // nothing issued yet
+    assert_eq!(intents.complete_dispensed()?, None);
+
+    intents.mark(first.id, IntentState::Issued)?;
+    intents.mark(second.id, IntentState::Issued)?;
+
+    let completed = intents.complete_dispensed()?.unwrap();
+    assert_eq!(completed.id, first.id);
+    assert_eq!(completed.state, IntentState::Completed);
+
+    let unresolved = intents.unresolved();
+    assert_eq!(unresolved.len(), 1);
+    assert_eq!(unresolved[0].id, second.id);
+    assert_eq!(unresolved[0].state, IntentState::Issued);
+
+    assert_eq!(intents.mark(42, IntentState::Failed)?, None);
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
+
+#[test]
+fn test_payout_intent_recovery() -> ssp::Result<()> {
+    let path = log_path("recovery");
+
+    {
+        let intents = PayoutIntentLog::open(&path)?;
+
+        let refused = intents.begin(0x1234, &payout_list())?;
+        intents.mark(refused.id, IntentState::Failed)?;
+
+        let issued = intents.begin(0x1234, &payout_list())?;
+        intents.mark(issued.id, IntentState::Issued)?;
+
+        // power loss before the payout command was acknowledged
+        intents.begin(0x1234, &payout_list())?;
+    }
+
+    // simulate a record cut short by the power loss
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)?
+        .write_all(b"4\tpend")?;
+
+    let intents = PayoutIntentLog::open(&path)?;
+    assert_eq!(intents.intents().len(), 3);
+
+    let unresolved = intents.unresolved();
+    let states: Vec<IntentState> = unresolved.iter().map(|i| i.state).collect();
+    assert_eq!(states, [IntentState::Issued, IntentState::Pending]);
+
+    let resolved = intents.resolve(unresolved[1].id, false)?;
+    assert_eq!(resolved.state, IntentState::Failed);
+    assert!(intents.resolve(unresolved[1].id, true).is_err());
+    assert!(intents.resolve(42, true).is_err());
+
+    intents.resolve(unresolved[0].id, true)?;
+    assert!(intents.unresolved().is_empty());
+
+    // new intents continue after the recovered ones
+    assert_eq!(intents.begin(0x1234, &payout_list())?.id, 4);
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use std::{thread, time};
+
+use ssp_server::poll_timer::PollTimer;
+
+const INTERVAL_MS: u64 = 50;
+
+// Waits for the next tick, and fires it.
+fn wait_tick(timer: &mut PollTimer) -> time::Duration {
+    thread::sleep(
+        timer
+            .next_tick()
+            .saturating_duration_since(time::Instant::now()),
+    );
+    assert!(timer.due());
+    timer.tick()
+}
+
+#[test]
+fn test_drift_correction() {
+    let interval = time::Duration::from_millis(INTERVAL_MS);
+    let start = time::Instant::now();
+    let mut timer = PollTimer::new(interval).with_jitter_budget(time::Duration::from_millis(40));
+
+    assert!(!timer.due());
+
+    // every poll takes a while, then a command runs long
+    for i in 0..10 {
+        wait_tick(&mut timer);
+        let work = if i == 5 { 30 } else { 10 };
+        thread::sleep(time::Duration::from_millis(work));
+    }
+
+    // ticks stay on the grid, instead of adding the poll durations
+    assert_eq!(timer.missed(), 0);
+    let scheduled = timer.next_tick() - start;
+    assert!(scheduled >= interval * 11);
+    assert!(scheduled < interval * 11 + time::Duration::from_millis(5));
+    assert!(start.elapsed() < interval * 11);
+}
+
+#[test]
+fn test_missed_tick() {
+    let interval = time::Duration::from_millis(INTERVAL_MS);
+    let mut timer = PollTimer::new(interval).with_jitter_budget(time::Duration::from_millis(10));
+
+    wait_tick(&mut timer);
+
+    // a command runs past the next tick, and the jitter budget
+    thread::sleep(interval * 3);
+
+    let late = timer.tick();
+    let fired = time::Instant::now();
+    assert!(late >= interval * 2);
+    assert_eq!(timer.missed(), 1);
+
+    // the schedule restarts from the late poll, instead of bursting polls to catch up
+    assert!(!timer.due());
+    assert!(timer.next_tick() >= fired + interval - time::Duration::from_millis(5));
+    assert!(timer.next_tick() <= fired + interval);
+}
+
+#[test]
+fn test_mock_clock() {
+    use std::sync::Arc;
+
+    use ssp_server::clock::{Clock, MockClock};
+
+    let interval = time::Duration::from_millis(INTERVAL_MS);
+    let clock = MockClock::new();
+    let start = clock.now();
+
+    let mut timer = PollTimer::new(interval)
+        .with_jitter_budget(time::Duration::from_millis(10))
+        .with_clock(Arc::new(clock.clone()));
+
+    assert_eq!(timer.next_tick(), start + interval);
+    assert!(!timer.due());
+
+    // simulated time only moves when advanced
+    clock.advance(interval);
+    assert!(timer.due());
+    assert_eq!(timer.tick(), time::Duration::ZERO);
+    assert_eq!(timer.next_tick(), start + interval * 2);
+
+    // late within the jitter budget, the grid is kept
+    clock.advance(interval + time::Duration::from_millis(5));
+    assert_eq!(timer.tick(), time::Duration::from_millis(5));
+    assert_eq!(timer.next_tick(), start + interval * 3);
+
+    // a sleep on the clock returns right away, past the jitter budget the grid restarts
+    clock.sleep(interval * 3);
+    assert_eq!(timer.tick(), interval * 2 + time::Duration::from_millis(5));
+    assert_eq!(timer.missed(), 1);
+    assert_eq!(timer.next_tick(), clock.now() + interval);
+}
+
+#[test]
+fn test_schedule_update() {
+    use std::sync::Arc;
+
+    use ssp_server::clock::MockClock;
+    use ssp_server::poll_timer::PollSchedule;
+
+    let default_interval = time::Duration::from_millis(INTERVAL_MS);
+    let clock = MockClock::new();
+
+    let schedule = PollSchedule::new();
+    let mut timer = schedule
+        .timer(default_interval)
+        .with_clock(Arc::new(clock.clone()));
+    assert_eq!(timer.interval(), default_interval);
+
+    // changes apply to the running timer from the next tick on
+    let shared = schedule.clone();
+    shared.set_interval(Some(default_interval * 2));
+    shared.set_jitter_budget(time::Duration::from_millis(10));
+
+    clock.advance(default_interval);
+    timer.tick();
+    schedule.update(&mut timer, default_interval);
+    assert_eq!(timer.interval(), default_interval * 2);
+    assert_eq!(timer.jitter_budget(), time::Duration::from_millis(10));
+
+    // a zero interval is ignored, and unset restores the default
+    shared.set_interval(Some(time::Duration::ZERO));
+    assert_eq!(schedule.interval(), Some(default_interval * 2));
+
+    shared.set_interval(None);
+    schedule.update(&mut timer, default_interval);
+    assert_eq!(timer.interval(), default_interval);
+}
@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use parking_lot::Mutex;
+
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::{frame, wait_for};
+
+const POLL: u8 = 0x07;
+const DISPLAY_ON: u8 = 0x03;
+const DISPLAY_OFF: u8 = 0x04;
+
+// Transport answering every command with OK, taking `poll_delay` to answer polls, and recording
+// the written commands.
+#[derive(Default)]
+struct DeviceTransport {
+    wire: VecDeque<u8>,
+    poll_delay: time::Duration,
+    commands: Arc<Mutex<Vec<u8>>>,
+    polls: Arc<AtomicUsize>,
+}
+
+impl Read for DeviceTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for DeviceTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.commands.lock().push(buf[3]);
+
+        if buf[3] == POLL {
+            self.polls.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(self.poll_delay);
+        }
+
+        self.wire.extend(frame(0x80, &[0xf0]));
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for DeviceTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+}
+
+#[test]
+fn test_turns_in_queue_order() -> ssp::Result<()> {
+    let commands = Arc::new(Mutex::new(Vec::new()));
+    let polls = Arc::new(AtomicUsize::new(0));
+    let handle = Arc::new(Mutex::new(DeviceHandle::from_transport(DeviceTransport {
+        poll_delay: time::Duration::from_millis(300),
+        commands: Arc::clone(&commands),
+        polls: Arc::clone(&polls),
+        ..Default::default()
+    })?));
+
+    let stop = Arc::new(AtomicBool::new(false));
+    handle.lock().start_background_polling(Arc::clone(&stop))?;
+
+    // while the device answers a poll, a command is submitted, then a blocking method is called
+    assert!(wait_for(&polls, 1, time::Duration::from_secs(5)));
+
+    let submitted = handle.lock().submit(ssp::DisplayOnCommand::new());
+    thread::sleep(time::Duration::from_millis(50));
+
+    let blocking = {
+        let handle = Arc::clone(&handle);
+        thread::spawn(move || handle.lock().display_off())
+    };
+
+    submitted.wait()?;
+    blocking.join().unwrap()?;
+    stop.store(true, Ordering::SeqCst);
+
+    // both are sent after the poll, in the order they were queued
+    let commands = commands.lock();
+    let position = |command| commands.iter().position(|&c| c == command).unwrap();
+    assert!(position(POLL) < position(DISPLAY_ON));
+    assert!(position(DISPLAY_ON) < position(DISPLAY_OFF));
+
+    Ok(())
+}
+
+#[test]
+fn test_polls_between_turns() -> ssp::Result<()> {
+    let polls = Arc::new(AtomicUsize::new(0));
+    let handle = Arc::new(Mutex::new(DeviceHandle::from_transport(DeviceTransport {
+        polls: Arc::clone(&polls),
+        ..Default::default()
+    })?));
+
+    let stop = Arc::new(AtomicBool::new(false));
+    handle.lock().start_background_polling(Arc::clone(&stop))?;
+    assert!(wait_for(&polls, 1, time::Duration::from_secs(5)));
+
+    // an application thread calling blocking methods back-to-back
+    let busy = {
+        let handle = Arc::clone(&handle);
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || -> ssp::Result<usize> {
+            let mut sent = 0;
+            while !stop.load(Ordering::SeqCst) {
+                handle.lock().display_on()?;
+                sent += 1;
+            }
+            Ok(sent)
+        })
+    };
+
+    // polls are still sent every polling interval
+    let start = polls.load(Ordering::SeqCst);
+    let polled = wait_for(&polls, start + 3, time::Duration::from_secs(5));
+    stop.store(true, Ordering::SeqCst);
+
+    assert!(polled);
+    assert!(busy.join().unwrap()? > 0);
+
+    Ok(())
+}
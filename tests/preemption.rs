@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use parking_lot::Mutex;
+
+use ssp_server::transport::Transport;
+use ssp_server::{CommandPriority, DeviceHandle};
+
+mod common;
+
+use common::{frame, wait_for};
+
+const DISPLAY_ON: u8 = 0x03;
+const DISPLAY_OFF: u8 = 0x04;
+const POLL: u8 = 0x07;
+const REJECT: u8 = 0x08;
+const SYNC: u8 = 0x11;
+const HOLD: u8 = 0x18;
+
+// Transport answering every command with OK, taking `display_on_delay` to answer DisplayOn, and
+// recording the written commands.
+#[derive(Default)]
+struct DeviceTransport {
+    wire: VecDeque<u8>,
+    display_on_delay: time::Duration,
+    commands: Arc<Mutex<Vec<u8>>>,
+    polls: Arc<AtomicUsize>,
+}
+
+impl Read for DeviceTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for DeviceTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.commands.lock().push(buf[3]);
+
+        match buf[3] {
+            POLL => {
+                self.polls.fetch_add(1, Ordering::SeqCst);
+            }
+            DISPLAY_ON => thread::sleep(self.display_on_delay),
+            _ => (),
+        }
+
+        self.wire.extend(frame(0x80, &[0xf0]));
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for DeviceTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+}
+
+// Gets the commands written after the first `command`.
+fn sent_after(commands: &Mutex<Vec<u8>>, command: u8) -> Vec<u8> {
+    let commands = commands.lock();
+    let start = commands.iter().position(|&c| c == command).unwrap();
+
+    commands[start + 1..].to_vec()
+}
+
+#[test]
+fn test_reject_jumps_the_queue() -> ssp::Result<()> {
+    let commands = Arc::new(Mutex::new(Vec::new()));
+    let polls = Arc::new(AtomicUsize::new(0));
+    let handle = DeviceHandle::from_transport(DeviceTransport {
+        display_on_delay: time::Duration::from_millis(200),
+        commands: Arc::clone(&commands),
+        polls: Arc::clone(&polls),
+        ..Default::default()
+    })?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    handle.start_background_polling(Arc::clone(&stop))?;
+    assert!(wait_for(&polls, 1, time::Duration::from_secs(5)));
+
+    // commands queue up behind a slow one
+    let slow = handle.submit(ssp::DisplayOnCommand::new());
+    thread::sleep(time::Duration::from_millis(50));
+
+    let off = handle.submit_with_priority(ssp::DisplayOffCommand::new(), CommandPriority::High);
+    let sync = handle.submit_with_priority(ssp::SyncCommand::new(), CommandPriority::High);
+    let reject = handle.submit_with_priority(ssp::RejectCommand::new(), CommandPriority::Low);
+
+    slow.wait()?;
+    off.wait()?;
+    sync.wait()?;
+    reject.wait()?;
+    stop.store(true, Ordering::SeqCst);
+
+    // the reject is sent first, the others keep their submission order
+    let sent = sent_after(&commands, DISPLAY_ON);
+    let order: Vec<u8> = sent.into_iter().filter(|&c| c != POLL).collect();
+    assert_eq!(order, [REJECT, DISPLAY_OFF, SYNC]);
+
+    Ok(())
+}
+
+#[test]
+fn test_hold_before_poll() -> ssp::Result<()> {
+    let commands = Arc::new(Mutex::new(Vec::new()));
+    let polls = Arc::new(AtomicUsize::new(0));
+    let handle = Arc::new(Mutex::new(DeviceHandle::from_transport(DeviceTransport {
+        display_on_delay: time::Duration::from_millis(900),
+        commands: Arc::clone(&commands),
+        polls: Arc::clone(&polls),
+        ..Default::default()
+    })?));
+
+    let stop = Arc::new(AtomicBool::new(false));
+    handle.lock().start_background_polling(Arc::clone(&stop))?;
+    assert!(wait_for(&polls, 1, time::Duration::from_secs(5)));
+
+    // a command runs past the next scheduled poll
+    let slow = handle.lock().submit(ssp::DisplayOnCommand::new());
+    thread::sleep(time::Duration::from_millis(50));
+
+    let hold = {
+        let handle = Arc::clone(&handle);
+        thread::spawn(move || handle.lock().hold())
+    };
+
+    slow.wait()?;
+    hold.join().unwrap()?;
+    stop.store(true, Ordering::SeqCst);
+
+    // the hold is sent before the overdue poll
+    assert_eq!(sent_after(&commands, DISPLAY_ON).first(), Some(&HOLD));
+
+    Ok(())
+}
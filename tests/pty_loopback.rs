@@ -0,0 +1,97 @@
+#![cfg(feature = "emulator")]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time;
+
+use ssp_server::emulator::{Emulator, PtyLoopback};
+use ssp_server::{PollMode, PushEventReceiver};
+
+const READ: u8 = 0xef;
+const CREDIT: u8 = 0xee;
+const STACKING: u8 = 0xcc;
+const STACKED: u8 = 0xeb;
+const DEVICE_RESET: u8 = 0xf1;
+
+// Gets the method of the next event from the push event queue, skipping any reset.
+fn next_event(rx: &PushEventReceiver, timeout: time::Duration) -> Option<ssp::Method> {
+    loop {
+        match rx.0.recv_timeout(timeout).ok()?.method() {
+            ssp::Method::Reset => continue,
+            method => return Some(method),
+        }
+    }
+}
+
+#[test]
+fn test_pty_credit() -> ssp::Result<()> {
+    let loopback = PtyLoopback::new(Emulator::new().with_channels(&[5, 10, 20]))?;
+    assert!(loopback.device_path().starts_with("/dev/"));
+
+    loopback.start(ssp::ProtocolVersion::Six)?;
+    assert!(loopback.emulator().enabled());
+    assert_eq!(
+        loopback
+            .handle()
+            .channel_value_data()?
+            .channel_values()?
+            .as_ref()
+            .len(),
+        3
+    );
+
+    assert_eq!(loopback.drain_events(4)?, [DEVICE_RESET]);
+
+    loopback.emulator().credit(3)?;
+    assert_eq!(
+        loopback.drain_events(4)?,
+        [READ, 3, STACKING, CREDIT, 3, STACKED]
+    );
+
+    loopback.stop()
+}
+
+#[test]
+fn test_pty_background_polling() -> ssp::Result<()> {
+    let loopback = PtyLoopback::new(Emulator::new())?;
+    loopback.start(ssp::ProtocolVersion::Six)?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let rx = loopback
+        .handle()
+        .start_background_polling_with_queue(Arc::clone(&stop), PollMode::Auto)?;
+
+    let timeout = time::Duration::from_secs(5);
+    assert!(loopback.wait_for(timeout, |e| e.pending_events() == 0));
+
+    loopback.emulator().credit(2)?;
+
+    // the note is held in escrow, until the application stacks it
+    assert_eq!(next_event(&rx, timeout), Some(ssp::Method::Read));
+    loopback.handle().stack()?;
+    assert_eq!(next_event(&rx, timeout), Some(ssp::Method::NoteCredit));
+
+    stop.store(true, Ordering::SeqCst);
+
+    Ok(())
+}
+
+#[test]
+fn test_pty_encrypted_session() -> ssp::Result<()> {
+    let mut loopback = PtyLoopback::new(Emulator::new())?;
+
+    loopback.handle_mut().negotiate_keys()?;
+    assert!(loopback.emulator().encrypted());
+
+    loopback.handle().enable_device(ssp::ProtocolVersion::Six)?;
+    loopback.drain_events(4)?;
+
+    loopback.emulator().credit(1)?;
+    assert_eq!(
+        loopback.drain_events(4)?,
+        [READ, 1, STACKING, CREDIT, 1, STACKED]
+    );
+    assert!(loopback.emulator().sequence_count() > 0);
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use ssp_server::cash_levels::{CashLevels, DenominationLevel};
+use ssp_server::journal::{TransactionJournal, TransactionKind};
+use ssp_server::reconcile::{reconcile, DeviceCounters, Discrepancy, DiscrepancyKind};
+
+fn temp_path(name: &str, ext: &str) -> String {
+    let path =
+        std::env::temp_dir().join(format!("ssp-reconcile-{name}-{}.{ext}", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    path
+}
+
+#[test]
+fn test_reconcile() -> ssp::Result<()> {
+    let journal_path = temp_path("journal", "log");
+    let levels_path = temp_path("levels", "txt");
+
+    let journal = TransactionJournal::open(&journal_path)?;
+    let levels = CashLevels::open(&levels_path)?.with_recycled(500);
+
+    for value in [500, 500, 500, 2000] {
+        journal.record(TransactionKind::Credit, 1234, value, 1, "EUR")?;
+        levels.record_credit(value)?;
+    }
+    journal.record(TransactionKind::Payout, 1234, 500, 1, "EUR")?;
+    levels.record_payout(500, 1)?;
+
+    // the journal matches the counters, and levels
+    let counters = DeviceCounters::new(1, 3, 1);
+    let report = reconcile(&journal, Some(&counters), Some(&levels))?;
+    assert!(report.is_balanced(), "{report}");
+    assert_eq!(report.totals.credited_notes, 4);
+    assert_eq!(report.expected_recycler, [DenominationLevel::new(500, 2)]);
+    assert_eq!(report.recycler, [DenominationLevel::new(500, 2)]);
+    assert_eq!(format!("{report}"), "balanced");
+
+    // checks are skipped without counters, and levels
+    let report = reconcile(&journal, None, None)?;
+    assert!(report.is_balanced());
+    assert!(report.recycler.is_empty());
+
+    // a note dispensed without a journal entry, and a recycler count off by one
+    let counters = DeviceCounters::new(1, 3, 2);
+    levels.apply_levels(&[
+        DenominationLevel::new(500, 1),
+        DenominationLevel::new(1000, 1),
+    ])?;
+
+    let report = reconcile(&journal, Some(&counters), Some(&levels))?;
+    assert!(!report.is_balanced());
+    assert_eq!(
+        report.discrepancies,
+        [
+            Discrepancy {
+                kind: DiscrepancyKind::DispensedNotes,
+                value: 0,
+                journal: 1,
+                device: 2,
+            },
+            Discrepancy {
+                kind: DiscrepancyKind::RecyclerLevel,
+                value: 500,
+                journal: 2,
+                device: 1,
+            },
+            Discrepancy {
+                kind: DiscrepancyKind::RecyclerLevel,
+                value: 1000,
+                journal: 0,
+                device: 1,
+            },
+        ]
+    );
+    assert_eq!(report.discrepancies[1].difference(), -1);
+
+    // an empty with unknown notes clears the journaled recycler
+    journal.record(TransactionKind::Empty, 1234, 0, 0, "")?;
+    levels.record_empty()?;
+    let report = reconcile(&journal, None, Some(&levels))?;
+    assert!(report.is_balanced(), "{report}");
+    assert!(report.expected_recycler.is_empty());
+
+    let _ = std::fs::remove_file(&journal_path);
+    let _ = std::fs::remove_file(&levels_path);
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+use ssp_server::capture::{self, CaptureDirection, CaptureFile, CaptureTransport};
+use ssp_server::redact::{self, RedactionPolicy, REDACTED_BYTE};
+use ssp_server::transport::Transport;
+use ssp_server::{frame_log, framing, DeviceHandle};
+
+mod common;
+
+use common::{frame, STX};
+
+// Transport replying with canned bytes, one byte per read.
+struct ReplyTransport(VecDeque<u8>);
+
+impl Read for ReplyTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match (self.0.pop_front(), buf.first_mut()) {
+            (Some(byte), Some(out)) => {
+                *out = byte;
+                Ok(1)
+            }
+            _ => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
+
+impl Write for ReplyTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for ReplyTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_redaction_policy() -> ssp::Result<()> {
+    assert!(RedactionPolicy::new().is_empty());
+    assert_eq!(RedactionPolicy::from_list("")?, RedactionPolicy::new());
+    assert_eq!(RedactionPolicy::from_list("none")?, RedactionPolicy::new());
+    assert_eq!(RedactionPolicy::from_list("all")?, RedactionPolicy::all());
+
+    let policy = RedactionPolicy::from_list("keys, Barcodes")?;
+    assert_eq!(
+        policy,
+        RedactionPolicy::new().with_keys(true).with_barcodes(true)
+    );
+    assert_eq!(policy.to_string(), "keys,barcodes");
+    assert_eq!(RedactionPolicy::new().to_string(), "none");
+
+    assert!(policy.redacts(ssp::MessageType::SetGenerator));
+    assert!(policy.redacts(ssp::MessageType::SetModulus));
+    assert!(policy.redacts(ssp::MessageType::RequestKeyExchange));
+    assert!(policy.redacts(ssp::MessageType::SetEncryptionKey));
+    assert!(policy.redacts(ssp::MessageType::GetBarcodeData));
+    assert!(!policy.redacts(ssp::MessageType::SerialNumber));
+    assert!(!policy.redacts(ssp::MessageType::Poll));
+
+    assert!(RedactionPolicy::from_list("keys,passwords").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_redact_frame() {
+    let serial = frame(0x80, &[0xf0, 0x00, 0x12, 0xd6, 0x87]);
+    let redacted = redact::redact_frame(&serial);
+
+    // sequence ID, length, and response status are kept, and the CRC stays valid
+    assert_eq!(
+        redacted,
+        frame(
+            0x80,
+            &[
+                0xf0,
+                REDACTED_BYTE,
+                REDACTED_BYTE,
+                REDACTED_BYTE,
+                REDACTED_BYTE
+            ]
+        )
+    );
+
+    // partial frames are redacted up to the end
+    assert_eq!(
+        redact::redact_frame(&serial[..6]),
+        [STX, 0x80, 0x05, 0xf0, REDACTED_BYTE, REDACTED_BYTE]
+    );
+    assert_eq!(redact::redact_frame(&serial[..3]), serial[..3]);
+
+    assert_eq!(
+        redact::redact_data(&[0xf0, 0x01, 0x02]),
+        [0xf0, REDACTED_BYTE, REDACTED_BYTE]
+    );
+    assert!(redact::redact_data(&[]).is_empty());
+}
+
+#[test]
+fn test_redacted_capture() -> ssp::Result<()> {
+    let path = std::env::temp_dir().join(format!("ssp-redact-{}.log", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    redact::set_redaction_policy(RedactionPolicy::new().with_serials(true));
+    assert!(redact::redaction_policy().serials);
+
+    // the serial number contains an STX byte, stuffed on the wire
+    let ok = frame(0x80, &[0xf0]);
+    let serial = frame(0x00, &[0xf0, 0x00, 0x12, STX, 0x87]);
+
+    let mut replies: VecDeque<u8> = ok.iter().copied().collect();
+    replies.extend(framing::stuff(&serial)?);
+
+    let transport = CaptureTransport::new(ReplyTransport(replies), CaptureFile::create(&path)?);
+    let handle = DeviceHandle::from_transport(transport)?;
+    frame_log::clear_frame_log();
+
+    handle.sync()?;
+
+    // the serial number is only redacted from logs, and captures
+    let res = handle.serial_number()?;
+    assert_eq!(res.serial_number().as_inner(), 0x0012_7f87);
+
+    let redacted_serial = frame(0x00, &[0xf0, 0, 0, 0, 0]);
+
+    let records = capture::read_capture(&path)?;
+    let frames = capture::frames(&records);
+    assert_eq!(frames.len(), 4);
+    assert!(frames.iter().all(|f| f.error.is_none()));
+
+    assert_eq!(frames[1].direction, CaptureDirection::Rx);
+    assert_eq!(frames[1].frame, ok);
+    assert_eq!(frames[3].direction, CaptureDirection::Rx);
+    assert_eq!(frames[3].frame, redacted_serial);
+
+    let logged = frame_log::recent_frames();
+    assert_eq!(logged.len(), 4);
+    assert_eq!(logged[1].frame, ok);
+    assert_eq!(logged[3].frame, redacted_serial);
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
@@ -0,0 +1,209 @@
+#![cfg(feature = "redis-streams")]
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use parking_lot::Mutex;
+
+use ssp_server::redis_stream::{RedisConfig, RedisStreamSink};
+use ssp_server::sink::EventSink;
+
+// Reads a RESP command, or `None` once the client disconnects.
+fn read_command(reader: &mut BufReader<&TcpStream>) -> io::Result<Option<Vec<String>>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let count: usize = line.trim_start_matches('*').trim_end().parse().unwrap_or(0);
+    let mut args = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        line.clear();
+        reader.read_line(&mut line)?;
+
+        let len: usize = line.trim_start_matches('$').trim_end().parse().unwrap_or(0);
+        let mut arg = vec![0; len + 2];
+        reader.read_exact(&mut arg)?;
+        arg.truncate(len);
+
+        args.push(String::from_utf8_lossy(&arg).into_owned());
+    }
+
+    Ok(Some(args))
+}
+
+// Minimal Redis server, recording the received commands, and answering XADD with an entry ID.
+struct RedisServer {
+    url: String,
+    commands: Arc<Mutex<Vec<Vec<String>>>>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl RedisServer {
+    // Starts the server, failing the first XADD if `fail_first` is set.
+    fn start(fail_first: bool) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("redis://{}", listener.local_addr()?);
+
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let fail = Arc::new(AtomicBool::new(fail_first));
+
+        let (server_commands, server_connections) =
+            (Arc::clone(&commands), Arc::clone(&connections));
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+                server_connections.fetch_add(1, Ordering::SeqCst);
+
+                let commands = Arc::clone(&server_commands);
+                let fail = Arc::clone(&fail);
+
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(&stream);
+                    let mut writer = &stream;
+
+                    while let Ok(Some(args)) = read_command(&mut reader) {
+                        let xadd = args
+                            .first()
+                            .map(|cmd| cmd.eq_ignore_ascii_case("XADD"))
+                            .unwrap_or(false);
+                        commands.lock().push(args);
+
+                        let reply: &[u8] = if !xadd {
+                            b"+OK\r\n"
+                        } else if fail.swap(false, Ordering::SeqCst) {
+                            b"-ERR stream unavailable\r\n"
+                        } else {
+                            b"$3\r\n1-0\r\n"
+                        };
+
+                        if writer.write_all(reply).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            url,
+            commands,
+            connections,
+        })
+    }
+
+    // Gets the received XADD commands.
+    fn xadds(&self) -> Vec<Vec<String>> {
+        self.commands
+            .lock()
+            .iter()
+            .filter(|args| args[0].eq_ignore_ascii_case("XADD"))
+            .cloned()
+            .collect()
+    }
+}
+
+fn credit(value: u32) -> ssp::Event {
+    ssp::Event::from(ssp::NoteCreditEvent::new(ssp::ChannelValue::from(value)))
+}
+
+#[test]
+fn test_entries() -> ssp::Result<()> {
+    let server = RedisServer::start(false)?;
+
+    let config = RedisConfig::new()
+        .with_url(server.url.as_str())
+        .with_stream_key("lane-3:events")
+        .with_device_id("lane-3")
+        .with_max_len(100);
+    let mut sink = RedisStreamSink::new(config)?;
+
+    let credit = credit(500);
+    let reset = ssp::Event::from(ssp::ResetEvent::new());
+
+    sink.publish_event(&credit)?;
+    sink.publish_event(&reset)?;
+
+    assert_eq!(sink.sequence(), 2);
+
+    let xadds = server.xadds();
+    assert_eq!(xadds.len(), 2);
+
+    for (seq, (args, event)) in xadds.iter().zip([&credit, &reset]).enumerate() {
+        let seq = seq.to_string();
+        let method = event.method().to_str();
+
+        assert_eq!(
+            args[..13],
+            [
+                "XADD",
+                "lane-3:events",
+                "MAXLEN",
+                "~",
+                "100",
+                "*",
+                "device_id",
+                "lane-3",
+                "seq",
+                seq.as_str(),
+                "method",
+                method,
+                "event",
+            ]
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&args[13]).unwrap();
+        assert_eq!(json, serde_json::to_value(event).unwrap());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reconnect_after_error() -> ssp::Result<()> {
+    let server = RedisServer::start(true)?;
+
+    let config = RedisConfig::new().with_url(server.url.as_str());
+    let mut sink = RedisStreamSink::new(config)?;
+
+    // a failed entry does not advance the sequence, and drops the connection
+    assert!(sink.publish_event(&credit(500)).is_err());
+    assert_eq!(sink.sequence(), 0);
+
+    sink.publish_event(&credit(500))?;
+
+    assert_eq!(sink.sequence(), 1);
+    assert_eq!(server.connections.load(Ordering::SeqCst), 2);
+
+    let xadds = server.xadds();
+    assert_eq!(xadds.len(), 2);
+    // entries without a maximum length are unbounded, and the retried entry keeps its number
+    assert_eq!(
+        xadds[1][1..8],
+        ["ssp:events", "*", "device_id", "ssp", "seq", "0", "method"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_unreachable_server() -> ssp::Result<()> {
+    // bind, and release a port, so nothing listens on it
+    let addr = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let config = RedisConfig::new().with_url(format!("redis://{addr}").as_str());
+
+    let err = RedisStreamSink::new(config).err().unwrap();
+
+    assert!(format!("{err}").contains("Redis error"));
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use ssp_server::registry::{DeviceRegistry, DEVICE_REGISTRY_HEADER};
+
+fn registry_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("ssp-registry-{name}-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    path
+}
+
+#[test]
+fn test_device_registry() -> ssp::Result<()> {
+    let path = registry_path("devices");
+
+    let registry = DeviceRegistry::open(&path)?;
+    assert!(registry.devices().is_empty());
+
+    let first = registry.register(1234, "Note Validator", "0100", "EUR01610", "/dev/ttyUSB0")?;
+    assert_eq!(first.first_seen_ms, first.last_seen_ms);
+    registry.register(
+        99,
+        "SMART Payout",
+        "0420",
+        "EUR01610",
+        "tcp://10.0.0.2:7000",
+    )?;
+
+    // re-registering updates the identity, and keeps the first-seen time
+    let updated = registry.register(1234, "Note Validator", "0101", "EUR01610", "/dev/ttyUSB1")?;
+    assert_eq!(updated.first_seen_ms, first.first_seen_ms);
+    assert_eq!(updated.firmware, "0101");
+    assert_eq!(registry.device(1234), Some(updated.clone()));
+
+    // touching unregistered devices is a no-op
+    registry.touch(5678)?;
+    assert!(registry.device(5678).is_none());
+
+    registry.touch(1234)?;
+    assert!(registry.device(1234).unwrap().last_seen_ms >= updated.last_seen_ms);
+    registry.flush()?;
+
+    // the registry survives a restart
+    let devices = registry.devices();
+    assert_eq!(devices.len(), 2);
+    assert_eq!(devices[0].serial_number, 99);
+    assert_eq!(devices[1].port, "/dev/ttyUSB1");
+
+    drop(registry);
+    let contents = std::fs::read_to_string(&path)?;
+    assert!(contents.starts_with(DEVICE_REGISTRY_HEADER));
+
+    let registry = DeviceRegistry::open(&path)?;
+    assert_eq!(registry.devices(), devices);
+
+    assert_eq!(registry.remove(99)?.map(|d| d.serial_number), Some(99));
+    assert_eq!(registry.remove(99)?, None);
+    assert_eq!(DeviceRegistry::open(&path)?.devices().len(), 1);
+
+    std::fs::write(
+        &path,
+        format!("{DEVICE_REGISTRY_HEADER}\n1234\tvalidator\n"),
+    )?;
+    assert!(DeviceRegistry::open(&path).is_err());
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use ssp::LastRejectCode;
+use ssp_server::reject_code::{RejectCategory, RejectReason};
+
+#[test]
+fn test_reject_codes() {
+    // every raw code maps back to itself
+    for code in 0..=u8::MAX {
+        assert_eq!(RejectReason::from(code).code(), code);
+    }
+
+    let cases = [
+        (0x00, RejectReason::NoteAccepted, RejectCategory::Accepted),
+        (0x02, RejectReason::AverageFail, RejectCategory::Validation),
+        (
+            0x06,
+            RejectReason::ChannelInhibited,
+            RejectCategory::Inhibit,
+        ),
+        (0x08, RejectReason::RejectedByHost, RejectCategory::Host),
+        (
+            0x0d,
+            RejectReason::SlowMechanism,
+            RejectCategory::Mechanical,
+        ),
+        (0x0f, RejectReason::FraudChannel, RejectCategory::Fraud),
+        (
+            0x1d,
+            RejectReason::UnableToStack,
+            RejectCategory::Mechanical,
+        ),
+        (0x42, RejectReason::Unknown(0x42), RejectCategory::Unknown),
+    ];
+
+    for (code, reason, category) in cases {
+        assert_eq!(RejectReason::from(code), reason);
+        assert_eq!(reason.category(), category);
+    }
+
+    assert!(RejectReason::NoteAccepted.is_accepted());
+    assert_eq!(
+        RejectReason::ChannelInhibited.to_string(),
+        "Channel inhibited (0x06, inhibit)"
+    );
+}
+
+#[test]
+fn test_from_last_reject_code() {
+    for code in [
+        LastRejectCode::InvalidNoteRead,
+        LastRejectCode::FraudChannelReject,
+        LastRejectCode::NoteTooLong,
+    ] {
+        assert_eq!(RejectReason::from(code).code(), u8::from(code));
+    }
+}
+
+#[cfg(feature = "emulator")]
+#[test]
+fn test_reject_reason() -> ssp::Result<()> {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use ssp_server::emulator::{Emulator, EmulatorTransport};
+    use ssp_server::DeviceHandle;
+
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+    let handle = DeviceHandle::from_transport(EmulatorTransport::new(Arc::clone(&emulator)))?;
+
+    handle.enable_device(ssp::ProtocolVersion::Six)?;
+    assert_eq!(handle.reject_reason()?, RejectReason::NoteAccepted);
+
+    // the device rejects a note on an inhibited channel by itself
+    handle.set_inhibits(ssp::EnableBitfieldList::from([
+        ssp::EnableBitfield::from(0x01),
+        ssp::EnableBitfield::from(0x00),
+    ]))?;
+    emulator.lock().insert_note(2)?;
+    for _ in 0..3 {
+        handle.poll()?;
+    }
+
+    let reason = handle.reject_reason()?;
+    assert_eq!(reason, RejectReason::ChannelInhibited);
+    assert_eq!(reason.category(), RejectCategory::Inhibit);
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use ssp::LastRejectCode;
+use ssp_server::reject_history::{RejectHistory, RejectSource};
+
+fn history_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!(
+        "ssp-reject-history-{name}-{}.txt",
+        std::process::id()
+    ));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+
+    path
+}
+
+#[test]
+fn test_reject_history() -> ssp::Result<()> {
+    let path = history_path("stats");
+
+    let history = RejectHistory::open(&path)?;
+    assert!(history.records()?.is_empty());
+
+    let first = history.record_event(0x1234)?;
+    assert_eq!(first.source, RejectSource::Event);
+    assert_eq!(first.reason(), None);
+
+    history.record_code(0x1234, LastRejectCode::InvalidNoteRead)?;
+    history.record_event(0x1234)?;
+    history.record_code(0x1234, LastRejectCode::FraudChannelReject)?;
+    history.record_event(0x1234)?;
+    let last = history.record_code(0x1234, LastRejectCode::InvalidNoteRead)?;
+    assert_eq!(last.reason(), Some(LastRejectCode::InvalidNoteRead));
+
+    let stats = history.stats(None, None)?;
+    assert_eq!(stats.rejects, 3);
+    assert_eq!(stats.queries, 3);
+    assert_eq!(stats.count(LastRejectCode::InvalidNoteRead), 2);
+    assert_eq!(stats.count(LastRejectCode::FraudChannelReject), 1);
+    assert_eq!(stats.count(LastRejectCode::NoteTooLong), 0);
+
+    // most frequent codes first
+    assert_eq!(stats.codes[0].reason, "Invalid note read");
+    assert_eq!(stats.codes[0].count, 2);
+
+    // records outside of the window are skipped
+    let stats = history.stats(Some(last.timestamp_ms + 1), None)?;
+    assert_eq!((stats.rejects, stats.queries), (0, 0));
+    let stats = history.stats(None, Some(first.timestamp_ms))?;
+    assert_eq!((stats.rejects, stats.queries), (0, 0));
+    assert_eq!(history.recent_stats(Duration::from_secs(3600))?.rejects, 3);
+
+    // the history survives a restart, discarding an incomplete record
+    drop(history);
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .and_then(|mut f| std::io::Write::write_all(&mut f, b"123\t4"))?;
+
+    let history = RejectHistory::open(&path)?;
+    let records = history.records()?;
+    assert_eq!(records.len(), 6);
+    assert_eq!(records[0], first);
+    assert_eq!(records[5], last);
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+// Transport that counts written bytes, and never replies.
+struct CountingTransport(Arc<AtomicUsize>);
+
+impl Read for CountingTransport {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::TimedOut.into())
+    }
+}
+
+impl Write for CountingTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.fetch_add(buf.len(), Ordering::SeqCst);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for CountingTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_require_encryption() -> ssp::Result<()> {
+    let written = Arc::new(AtomicUsize::new(0));
+
+    let handle = DeviceHandle::from_transport(CountingTransport(Arc::clone(&written)))?
+        .with_require_encryption(true);
+
+    assert!(handle.requires_encryption());
+
+    let key_not_set = ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet);
+
+    assert_eq!(handle.enable(), Err(key_not_set.clone()));
+    assert_eq!(handle.enable_payout(), Err(key_not_set.clone()));
+    assert_eq!(
+        handle.payout_by_denomination(&ssp::PayoutDenominationList::new()),
+        Err(key_not_set.clone())
+    );
+    assert_eq!(handle.empty(), Err(key_not_set.clone()));
+    assert_eq!(handle.smart_empty(), Err(key_not_set));
+
+    // nothing was sent to the device in plaintext
+    assert_eq!(written.load(Ordering::SeqCst), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_require_encryption_submit() -> ssp::Result<()> {
+    let written = Arc::new(AtomicUsize::new(0));
+
+    let handle = DeviceHandle::from_transport(CountingTransport(Arc::clone(&written)))?
+        .with_require_encryption(true);
+
+    let key_not_set = ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet);
+
+    // the policy is applied by command type, also to submitted commands
+    assert_eq!(
+        handle.submit(ssp::EnableCommand::new()).wait().map(|_| ()),
+        Err(key_not_set.clone())
+    );
+    assert_eq!(
+        handle
+            .submit(ssp::SmartEmptyCommand::new())
+            .wait()
+            .map(|_| ()),
+        Err(key_not_set)
+    );
+
+    assert_eq!(written.load(Ordering::SeqCst), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_no_rekey_without_session() -> ssp::Result<()> {
+    let written = Arc::new(AtomicUsize::new(0));
+
+    let mut handle = DeviceHandle::from_transport(CountingTransport(Arc::clone(&written)))?
+        .with_require_encryption(true);
+
+    let mut attempts = 0;
+    let res = handle.with_rekey(|handle| {
+        attempts += 1;
+        handle.enable()
+    });
+
+    // no key was ever negotiated, so there is no session to recover
+    assert_eq!(
+        res,
+        Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet))
+    );
+    assert_eq!(attempts, 1);
+    assert_eq!(written.load(Ordering::SeqCst), 0);
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use ssp_server::framing::{self, MAX_RETRANSMISSIONS};
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::frame;
+
+// Reply to a single write.
+enum Reply {
+    Ok,
+    BadCrc,
+    Silent,
+}
+
+// Transport answering writes with scripted replies, and recording the written frames.
+struct ScriptTransport {
+    replies: VecDeque<Reply>,
+    wire: VecDeque<u8>,
+    writes: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl ScriptTransport {
+    fn new(replies: impl IntoIterator<Item = Reply>, writes: &Arc<Mutex<Vec<Vec<u8>>>>) -> Self {
+        Self {
+            replies: replies.into_iter().collect(),
+            wire: VecDeque::new(),
+            writes: Arc::clone(writes),
+        }
+    }
+}
+
+impl Read for ScriptTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for ScriptTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes.lock().push(buf.to_vec());
+
+        match self.replies.pop_front().unwrap_or(Reply::Ok) {
+            Reply::Ok => self.wire.extend(frame(0x80, &[0xf0])),
+            Reply::BadCrc => {
+                let mut reply = frame(0x80, &[0xf0]);
+                let last = reply.len() - 1;
+                reply[last] ^= 0xff;
+                self.wire.extend(reply);
+            }
+            Reply::Silent => (),
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for ScriptTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+// Gets the sequence flag of a written frame.
+fn seq_flag(frame: &[u8]) -> bool {
+    frame[1] & 0x80 != 0
+}
+
+// Both cases share the global sequence flag, so they run in one test.
+#[test]
+fn test_retransmission() -> ssp::Result<()> {
+    let writes = Arc::new(Mutex::new(Vec::new()));
+    let silent = (0..=MAX_RETRANSMISSIONS).map(|_| Reply::Silent);
+    let replies = [Reply::BadCrc, Reply::Silent, Reply::Ok, Reply::Ok]
+        .into_iter()
+        .chain(silent)
+        .chain([Reply::Ok]);
+    let handle = DeviceHandle::from_transport(ScriptTransport::new(replies, &writes))?;
+
+    handle.display_on()?;
+    handle.display_on()?;
+
+    {
+        let writes = writes.lock();
+        assert_eq!(writes.len(), 4);
+
+        // the same frame is retransmitted until the response is valid
+        assert_eq!(writes[0], writes[1]);
+        assert_eq!(writes[1], writes[2]);
+
+        // only the next command toggles the flag
+        assert_ne!(seq_flag(&writes[2]), seq_flag(&writes[3]));
+    }
+
+    assert!(handle.display_on().is_err());
+    assert_eq!(writes.lock().len(), 4 + MAX_RETRANSMISSIONS as usize + 1);
+
+    // the device may have executed the command, so the bus is resynchronized before the next one
+    handle.display_on()?;
+
+    let writes = writes.lock();
+    let (last, given_up) = writes[4..].split_last().unwrap();
+    let (sync, given_up) = given_up.split_last().unwrap();
+    assert!(given_up.iter().all(|frame| frame == &given_up[0]));
+    assert_ne!(seq_flag(&writes[3]), seq_flag(&given_up[0]));
+    assert_eq!(sync[3], 0x11);
+    assert!(!seq_flag(last));
+
+    Ok(())
+}
+
+#[test]
+fn test_is_retransmittable() {
+    assert!(framing::is_retransmittable(&ssp::Error::Crc((0, 1))));
+    assert!(framing::is_retransmittable(&ssp::Error::Io(
+        "timed out".into()
+    )));
+    assert!(!framing::is_retransmittable(&ssp::Error::Status(
+        ssp::ResponseStatus::CommandCannotBeProcessed
+    )));
+
+    // plaintext responses to encrypted commands are never retried
+    let downgrade = ssp::Error::Encryption(ssp::ResponseStatus::Ok);
+    assert!(ssp_server::device_handle::is_encryption_downgrade(
+        &downgrade
+    ));
+    assert!(!framing::is_retransmittable(&downgrade));
+    assert!(!ssp_server::device_handle::is_encryption_downgrade(
+        &ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet)
+    ));
+}
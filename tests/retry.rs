@@ -0,0 +1,164 @@
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time;
+
+use ssp_server::retry::{self, CommandClass, RetryPolicy};
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+// Transport failing every write, like a disconnected USB adapter.
+struct BrokenTransport(Arc<AtomicUsize>);
+
+impl Read for BrokenTransport {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::TimedOut.into())
+    }
+}
+
+impl Write for BrokenTransport {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for BrokenTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_retry_policy_backoff() {
+    let ms = time::Duration::from_millis;
+
+    let policy = RetryPolicy::new()
+        .with_max_attempts(4)
+        .with_backoff(ms(100), ms(350))
+        .with_jitter(0);
+
+    assert!(policy.should_retry(3));
+    assert!(!policy.should_retry(4));
+
+    assert_eq!(policy.backoff(1), ms(100));
+    assert_eq!(policy.backoff(2), ms(200));
+    assert_eq!(policy.backoff(3), ms(350));
+    assert_eq!(policy.backoff(64), ms(350));
+
+    let jittered = policy.with_jitter(50);
+    for _ in 0..32 {
+        let backoff = jittered.backoff(2);
+        assert!(backoff >= ms(200) && backoff <= ms(300), "{backoff:?}");
+    }
+
+    // always attempted at least once
+    assert_eq!(RetryPolicy::new().with_max_attempts(0).max_attempts(), 1);
+    assert!(!RetryPolicy::no_retry().should_retry(1));
+}
+
+#[test]
+fn test_command_class() {
+    assert_eq!(
+        CommandClass::from_message_type(ssp::MessageType::Poll),
+        CommandClass::Poll
+    );
+    assert_eq!(
+        CommandClass::from_message_type(ssp::MessageType::SerialNumber),
+        CommandClass::Query
+    );
+    assert_eq!(
+        CommandClass::from_message_type(ssp::MessageType::Synchronisation),
+        CommandClass::Control
+    );
+    assert_eq!(
+        CommandClass::from_message_type(ssp::MessageType::PayoutByDenomination),
+        CommandClass::Payout
+    );
+    assert_eq!(
+        CommandClass::from_message_type(ssp::MessageType::SetGenerator),
+        CommandClass::Encryption
+    );
+    assert_eq!(
+        CommandClass::from_message_type(ssp::MessageType::ProgramFirmware),
+        CommandClass::Firmware
+    );
+}
+
+#[test]
+fn test_retries_exhausted() -> ssp::Result<()> {
+    let writes = Arc::new(AtomicUsize::new(0));
+    let handle = DeviceHandle::from_transport(BrokenTransport(Arc::clone(&writes)))?;
+
+    let policy = RetryPolicy::new()
+        .with_max_attempts(3)
+        .with_backoff(time::Duration::ZERO, time::Duration::ZERO);
+    retry::set_retry_policy(CommandClass::Control, policy);
+    assert_eq!(retry::retry_policy(CommandClass::Control), policy);
+
+    assert!(matches!(handle.sync(), Err(ssp::Error::SerialPort(_))));
+    assert_eq!(writes.load(Ordering::SeqCst), 3);
+
+    retry::reset_retry_policies();
+    assert_eq!(
+        retry::retry_policy(CommandClass::Control),
+        RetryPolicy::default()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_connect_retries_exhausted() {
+    let opens = AtomicUsize::new(0);
+
+    let policy = RetryPolicy::new()
+        .with_max_attempts(3)
+        .with_backoff(time::Duration::ZERO, time::Duration::ZERO);
+
+    let res = DeviceHandle::connect_transport_with_retry(
+        || -> ssp::Result<BrokenTransport> {
+            opens.fetch_add(1, Ordering::SeqCst);
+            Err(ssp::Error::Io("no such device".into()))
+        },
+        policy,
+    );
+
+    assert!(matches!(res, Err(ssp::Error::Io(_))));
+    assert_eq!(opens.load(Ordering::SeqCst), 3);
+}
+
+#[cfg(feature = "emulator")]
+#[test]
+fn test_connect_with_retry() -> ssp::Result<()> {
+    use parking_lot::Mutex;
+    use ssp_server::emulator::{Emulator, EmulatorTransport};
+
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+    let opens = AtomicUsize::new(0);
+
+    let policy = RetryPolicy::new()
+        .with_max_attempts(5)
+        .with_backoff(time::Duration::ZERO, time::Duration::ZERO);
+
+    // the adapter shows up on the third attempt
+    let handle = DeviceHandle::connect_transport_with_retry(
+        || {
+            if opens.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(ssp::Error::Io("no such device".into()))
+            } else {
+                Ok(EmulatorTransport::new(Arc::clone(&emulator)))
+            }
+        },
+        policy,
+    )?;
+
+    assert_eq!(opens.load(Ordering::SeqCst), 3);
+    handle.sync()?;
+
+    Ok(())
+}
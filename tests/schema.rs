@@ -0,0 +1,66 @@
+#![cfg(feature = "jsonrpc")]
+
+use serde_json::Value;
+use ssp_server::schema;
+
+// Collects all `$ref` targets in a schema.
+fn refs<'a>(val: &'a Value, out: &mut Vec<&'a str>) {
+    match val {
+        Value::Object(map) => {
+            for (key, val) in map.iter() {
+                match (key.as_str(), val) {
+                    ("$ref", Value::String(target)) => out.push(target.as_str()),
+                    _ => refs(val, out),
+                }
+            }
+        }
+        Value::Array(list) => list.iter().for_each(|val| refs(val, out)),
+        _ => (),
+    }
+}
+
+#[test]
+fn test_schema_refs_resolve() {
+    for kind in ["all", "commands", "responses", "events"] {
+        let doc = schema::schema_by_name(kind).unwrap();
+
+        assert_eq!(doc["$schema"], schema::SCHEMA_DIALECT);
+
+        let mut targets = Vec::new();
+        refs(&doc, &mut targets);
+
+        for target in targets {
+            let name = target.strip_prefix("#/$defs/").unwrap();
+            assert!(doc["$defs"].get(name).is_some(), "unresolved {target}");
+        }
+    }
+
+    assert!(schema::schema_by_name("unknown").is_none());
+}
+
+#[test]
+fn test_schema_methods() {
+    let doc = schema::schema();
+
+    let methods = |def: &str| -> Vec<String> {
+        doc["$defs"][def]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|req| {
+                req["properties"]["method"]["const"]
+                    .as_str()
+                    .unwrap()
+                    .into()
+            })
+            .collect()
+    };
+
+    let commands = methods("CommandRequest");
+    assert!(commands.contains(&ssp::Method::Dispense.to_str().into()));
+    assert!(commands.contains(&ssp::Method::Status.to_str().into()));
+
+    let events = methods("EventNotification");
+    assert!(events.contains(&ssp::Method::NoteCredit.to_str().into()));
+    assert!(events.contains(&ssp::Method::CashboxRemoved.to_str().into()));
+}
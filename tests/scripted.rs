@@ -0,0 +1,162 @@
+#![cfg(feature = "mock")]
+
+use ssp::ResponseOps;
+use ssp_server::scripted::{self, Expected, Reply, Script, ScriptedTransport, Step};
+use ssp_server::DeviceHandle;
+
+const OK: u8 = 0xf0;
+const FAIL: u8 = 0xf8;
+
+const SET_INHIBITS: u8 = 0x02;
+const POLL: u8 = 0x07;
+const DISABLE: u8 = 0x09;
+const ENABLE: u8 = 0x0a;
+const SERIAL_NUMBER: u8 = 0x0c;
+const SYNC: u8 = 0x11;
+
+fn connect() -> ssp::Result<(Script, DeviceHandle)> {
+    let transport = ScriptedTransport::new();
+    let script = transport.script().clone();
+
+    Ok((script, DeviceHandle::from_transport(transport)?))
+}
+
+#[test]
+fn test_exact_frames() -> ssp::Result<()> {
+    let (script, handle) = connect()?;
+
+    // Sync sets the sequence flag, the next command clears it
+    script
+        .expect_frame(
+            &scripted::frame(0x80, &[SYNC])?,
+            &scripted::frame(0x80, &[OK])?,
+        )
+        .expect_frame(
+            &scripted::frame(0x00, &[POLL])?,
+            &scripted::frame(0x00, &[OK])?,
+        )
+        .expect_frame(
+            &scripted::frame(0x80, &[SERIAL_NUMBER])?,
+            &scripted::frame(0x80, &[OK, 0x01, 0x02, 0x03, 0x04])?,
+        );
+
+    handle.sync()?;
+    handle.poll()?;
+    assert_eq!(
+        handle.serial_number()?.serial_number().as_inner(),
+        0x0102_0304
+    );
+
+    script.finish()?;
+    assert_eq!(script.consumed(), 3);
+    assert_eq!(script.written()[1], [0x7f, 0x00, 0x01, POLL, 0x11, 0x88]);
+
+    Ok(())
+}
+
+#[test]
+fn test_stuffed_frames() -> ssp::Result<()> {
+    // a CRC byte equal to STX is stuffed
+    let frame = scripted::frame(0x80, &[0x7f])?;
+    assert_eq!(&frame[..5], [0x7f, 0x80, 0x01, 0x7f, 0x7f]);
+    assert_eq!(ssp_server::framing::unstuff(&frame)?.len(), 6);
+
+    Ok(())
+}
+
+#[test]
+fn test_coalesced_inhibits() -> ssp::Result<()> {
+    let (script, handle) = connect()?;
+
+    script.expect(&[SET_INHIBITS, 0xff, 0xff], &[OK]);
+
+    let inhibits = || {
+        ssp::EnableBitfieldList::from([
+            ssp::EnableBitfield::from(0xff),
+            ssp::EnableBitfield::from(0xff),
+        ])
+    };
+
+    handle.set_inhibits(inhibits())?;
+    // already set, nothing is written
+    handle.set_inhibits(inhibits())?;
+
+    script.finish()?;
+    assert_eq!(script.written().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_retransmission() -> ssp::Result<()> {
+    let (script, handle) = connect()?;
+
+    let mut corrupted = scripted::frame(0x80, &[OK])?;
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0xff;
+
+    script
+        .expect_silence(&[DISABLE])
+        .push(Step {
+            expected: Expected::Data(vec![DISABLE]),
+            reply: Reply::Bytes(corrupted),
+        })
+        .expect(&[DISABLE], &[OK]);
+
+    assert!(handle.disable()?.response_status().is_ok());
+    script.finish()?;
+
+    // every retransmission repeats the same frame, and sequence flag
+    let written = script.written();
+    assert_eq!(written.len(), 3);
+    assert!(written.iter().all(|frame| frame == &written[0]));
+
+    Ok(())
+}
+
+#[test]
+fn test_error_status() -> ssp::Result<()> {
+    let (script, handle) = connect()?;
+
+    script.expect(&[ENABLE], &[FAIL]);
+
+    // a failure status is returned, not retransmitted
+    assert_eq!(
+        handle.enable()?.response_status(),
+        ssp::ResponseStatus::Fail
+    );
+    script.finish()
+}
+
+#[test]
+fn test_divergence() -> ssp::Result<()> {
+    let (script, handle) = connect()?;
+
+    script.expect(&[DISABLE], &[OK]).expect(&[POLL], &[OK]);
+
+    // the host sent another command than the scripted one
+    assert!(handle.enable().is_err());
+    assert!(script.diverged().is_some());
+    assert!(script.finish().is_err());
+
+    // unscripted commands diverge too
+    let (script, handle) = connect()?;
+    assert!(handle.poll().is_err());
+    assert!(script.diverged().is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_unfinished() -> ssp::Result<()> {
+    let (script, handle) = connect()?;
+
+    script.expect(&[SYNC], &[OK]).expect(&[POLL], &[OK]);
+
+    handle.sync()?;
+
+    assert_eq!(script.pending(), 1);
+    assert!(script.finish().is_err());
+
+    Ok(())
+}
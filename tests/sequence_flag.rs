@@ -0,0 +1,214 @@
+#![cfg(feature = "mock")]
+
+use proptest::prelude::*;
+
+use ssp_server::framing::{self, MAX_RETRANSMISSIONS};
+use ssp_server::scripted::{self, Expected, Reply, ScriptedTransport, Step};
+use ssp_server::DeviceHandle;
+
+const OK: u8 = 0xf0;
+const SYNC: u8 = 0x11;
+const FLAG: u8 = 0x80;
+
+// Command sent by the host.
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Sync,
+    Poll,
+    Enable,
+    Disable,
+    DisplayOn,
+    Hold,
+}
+
+impl Op {
+    fn run(self, handle: &DeviceHandle) {
+        // failed exchanges are part of the model, only the written frames are checked
+        let _ = match self {
+            Self::Sync => handle.sync().map(|_| ()),
+            Self::Poll => handle.poll().map(|_| ()),
+            Self::Enable => handle.enable().map(|_| ()),
+            Self::Disable => handle.disable().map(|_| ()),
+            Self::DisplayOn => handle.display_on().map(|_| ()),
+            Self::Hold => handle.hold().map(|_| ()),
+        };
+    }
+}
+
+// Reply of the device to a single written frame.
+#[derive(Clone, Copy, Debug)]
+enum Wire {
+    Ok,
+    Silent,
+    BadCrc,
+}
+
+impl Wire {
+    fn step(self) -> Step {
+        let reply = match self {
+            Self::Ok => Reply::Data(vec![OK]),
+            Self::Silent => Reply::Silent,
+            Self::BadCrc => {
+                let mut frame = scripted::frame(FLAG, &[OK]).unwrap();
+                let last = frame.len() - 1;
+                frame[last] ^= 0xff;
+                Reply::Bytes(frame)
+            }
+        };
+
+        Step {
+            expected: Expected::Any,
+            reply,
+        }
+    }
+
+    fn is_valid(self) -> bool {
+        matches!(self, Self::Ok)
+    }
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        2 => Just(Op::Sync),
+        6 => Just(Op::Poll),
+        1 => Just(Op::Enable),
+        1 => Just(Op::Disable),
+        1 => Just(Op::DisplayOn),
+        1 => Just(Op::Hold),
+    ]
+}
+
+fn wire() -> impl Strategy<Value = Wire> {
+    prop_oneof![
+        6 => Just(Wire::Ok),
+        2 => Just(Wire::Silent),
+        2 => Just(Wire::BadCrc),
+    ]
+}
+
+fn is_sync(frame: &[u8]) -> bool {
+    framing::unstuff(frame)
+        .map(|frame| frame.get(3) == Some(&SYNC))
+        .unwrap_or(false)
+}
+
+fn flag(frame: &[u8]) -> u8 {
+    frame[1] & FLAG
+}
+
+// Runs the `ops` against a device answering with the `replies`, and returns each written frame,
+// with the reply it got.
+fn exchange(ops: &[Op], replies: &[Wire]) -> Vec<(Vec<u8>, Wire)> {
+    let transport = ScriptedTransport::new();
+    let script = transport.script().clone();
+    for &reply in replies {
+        script.push(reply.step());
+    }
+
+    let handle = DeviceHandle::from_transport(transport).unwrap();
+    for &op in ops {
+        op.run(&handle);
+    }
+
+    let written = script.written();
+    assert!(
+        written.len() <= replies.len(),
+        "ran out of scripted replies"
+    );
+
+    written.into_iter().zip(replies.iter().copied()).collect()
+}
+
+// Checks the sequence flag rules over the frames written by the host:
+//
+// - a command is retransmitted, unchanged, only after a missing, or corrupted response, and at
+//   most MAX_RETRANSMISSIONS times in a row, except for Sync
+// - Sync is always sent with the flag set, the device accepts it with any flag
+// - the first command after a Sync clears the flag
+// - any other new command toggles the flag of the previous new command
+fn check_flags(exchanges: &[(Vec<u8>, Wire)]) -> Result<(), TestCaseError> {
+    let mut last: Option<&[u8]> = None;
+    let mut retransmissions = 0;
+
+    for (i, (frame, _)) in exchanges.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|p| &exchanges[p]);
+
+        if let Some((prev_frame, prev_reply)) = prev {
+            if frame == prev_frame && !prev_reply.is_valid() {
+                // every Sync frame is identical, a new Sync can not be told from a retransmission
+                if !is_sync(frame) {
+                    retransmissions += 1;
+                }
+                prop_assert!(
+                    retransmissions <= MAX_RETRANSMISSIONS,
+                    "frame {i}: too many retransmissions"
+                );
+                continue;
+            }
+        }
+        retransmissions = 0;
+
+        if is_sync(frame) {
+            prop_assert_eq!(flag(frame), FLAG, "frame {}: Sync without the flag", i);
+        } else if let Some(last) = last {
+            let expected = if is_sync(last) { 0 } else { flag(last) ^ FLAG };
+            prop_assert_eq!(
+                flag(frame),
+                expected,
+                "frame {}: {:02x?} after {:02x?}",
+                i,
+                frame,
+                last
+            );
+        }
+
+        last = Some(frame);
+    }
+
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_sequence_flags(
+        ops in prop::collection::vec(op(), 1..24),
+        replies in prop::collection::vec(wire(), 256),
+    ) {
+        check_flags(&exchange(&ops, &replies))?;
+    }
+
+    #[test]
+    fn test_sequence_flags_clean_wire(ops in prop::collection::vec(op(), 1..32)) {
+        let exchanges = exchange(&ops, &[Wire::Ok; 64]);
+
+        // without faults, every operation writes at most one frame, repeated state changes are
+        // coalesced
+        prop_assert!(exchanges.len() <= ops.len());
+        check_flags(&exchanges)?;
+    }
+}
+
+#[test]
+fn test_sync_after_recovery() {
+    // the Poll gives up, and leaves the bus dirty, the recovery Sync precedes the requested one
+    let attempts = MAX_RETRANSMISSIONS as usize + 1;
+    let mut replies = vec![Wire::Silent; attempts];
+    replies.extend([Wire::Ok, Wire::Ok, Wire::Ok]);
+
+    let exchanges = exchange(&[Op::Poll, Op::Sync, Op::Poll], &replies);
+    assert_eq!(exchanges.len(), replies.len());
+
+    let frames: Vec<&[u8]> = exchanges
+        .iter()
+        .map(|(frame, _)| frame.as_slice())
+        .collect();
+    let syncs = &frames[attempts..attempts + 2];
+    assert!(syncs
+        .iter()
+        .all(|frame| is_sync(frame) && flag(frame) == FLAG));
+    assert_eq!(flag(frames[frames.len() - 1]), 0);
+
+    check_flags(&exchanges).unwrap();
+}
@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::frame;
+
+const RESET: u8 = 0x01;
+const CHANNEL_VALUE_DATA: u8 = 0x0e;
+const DATASET_VERSION: u8 = 0x21;
+
+// Transport answering with the channel values, and the current dataset version, recording the
+// written commands.
+#[derive(Default)]
+struct SetupTransport {
+    wire: VecDeque<u8>,
+    dataset: Arc<Mutex<String>>,
+    commands: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Read for SetupTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for SetupTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.commands.lock().push(buf[3]);
+
+        match buf[3] {
+            // no response to a reset
+            RESET => (),
+            CHANNEL_VALUE_DATA => self.wire.extend(frame(0x80, &[0xf0, 2, 5, 10])),
+            DATASET_VERSION => {
+                let mut data = vec![0xf0];
+                data.extend_from_slice(self.dataset.lock().as_bytes());
+                self.wire.extend(frame(0x80, &data));
+            }
+            _ => self.wire.extend(frame(0x80, &[0xf0])),
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for SetupTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+}
+
+#[test]
+fn test_setup_cache() -> ssp::Result<()> {
+    let dataset = Arc::new(Mutex::new(String::from("EUR01610")));
+    let commands = Arc::new(Mutex::new(Vec::new()));
+    let handle = DeviceHandle::from_transport(SetupTransport {
+        dataset: Arc::clone(&dataset),
+        commands: Arc::clone(&commands),
+        ..Default::default()
+    })?;
+
+    let queried = || {
+        commands
+            .lock()
+            .iter()
+            .filter(|&&c| c == CHANNEL_VALUE_DATA)
+            .count()
+    };
+
+    // queried once, then served from the cache
+    let res = handle.channel_value_data()?;
+    assert_eq!(handle.channel_value_data()?, res);
+    assert_eq!(res.channel_values()?.as_ref().len(), 2);
+    assert_eq!(queried(), 1);
+
+    // the same dataset keeps the cache
+    handle.dataset_version()?;
+    handle.channel_value_data()?;
+    assert_eq!(queried(), 1);
+
+    // a new dataset clears it
+    *dataset.lock() = "EUR01620".into();
+    assert_eq!(handle.dataset_version()?.dataset_version()?, "EUR01620");
+    handle.channel_value_data()?;
+    assert_eq!(queried(), 2);
+
+    handle.clear_setup_cache();
+    handle.channel_value_data()?;
+    assert_eq!(queried(), 3);
+
+    // a reset clears it
+    handle.reset()?;
+    handle.channel_value_data()?;
+    handle.channel_value_data()?;
+    assert_eq!(queried(), 4);
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use ssp_server::framing;
+use ssp_server::snapshot::{BezelSnapshot, DeviceSnapshot};
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+const STX: u8 = 0x7f;
+
+// Transport replying OK to every command, and recording the command data.
+#[derive(Clone, Default)]
+struct OkTransport {
+    commands: Arc<Mutex<Vec<Vec<u8>>>>,
+    reply: Vec<u8>,
+}
+
+impl Read for OkTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.reply.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        let len = self.reply.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.reply[..len]);
+        self.reply.drain(..len);
+
+        Ok(len)
+    }
+}
+
+impl Write for OkTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let command = framing::unstuff(buf).map_err(|_| io::ErrorKind::InvalidData)?;
+        let seq = command[1];
+        self.commands
+            .lock()
+            .push(command[3..command.len() - 2].to_vec());
+
+        let mut reply = vec![STX, seq, 1, 0xf0];
+        let crc = ssp::crc::crc16(&reply[1..]);
+        reply.extend_from_slice(crc.to_le_bytes().as_ref());
+        self.reply = framing::stuff(&reply).map_err(|_| io::ErrorKind::InvalidData)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for OkTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_snapshot_restore() -> ssp::Result<()> {
+    let transport = OkTransport::default();
+    let commands = Arc::clone(&transport.commands);
+
+    let handle = DeviceHandle::from_transport(transport)?;
+    assert_eq!(handle.snapshot(), DeviceSnapshot::new());
+    assert!(handle.snapshot().is_empty());
+
+    handle.host_protocol_version(ssp::ProtocolVersion::Seven)?;
+    handle.set_inhibits(ssp::EnableBitfieldList::from([
+        ssp::EnableBitfield::from(0x0f),
+        ssp::EnableBitfield::from(0x00),
+    ]))?;
+    handle.configure_bezel(
+        ssp::RGB::from([0x00, 0x80, 0xff]),
+        ssp::BezelConfigStorage::Ram,
+    )?;
+
+    let snapshot = handle.snapshot();
+    assert_eq!(
+        snapshot,
+        DeviceSnapshot {
+            protocol_version: Some(7),
+            inhibits: Some(vec![0x0f, 0x00]),
+            bezel: Some(BezelSnapshot {
+                rgb: [0x00, 0x80, 0xff],
+                eeprom: false,
+            }),
+        }
+    );
+
+    // the full configuration is reapplied in order
+    commands.lock().clear();
+    handle.restore(&snapshot)?;
+    {
+        let commands = commands.lock();
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[0], [0x06, 0x07]);
+        assert_eq!(commands[1], [0x02, 0x0f, 0x00]);
+        assert_eq!(commands[2], [0x54, 0x00, 0x80, 0xff, 0x00]);
+    }
+
+    // EEPROM bezel colors survive a reset, and are not written again
+    commands.lock().clear();
+    handle.configure_bezel(
+        ssp::RGB::from([0xff, 0x00, 0x00]),
+        ssp::BezelConfigStorage::Eeprom,
+    )?;
+    assert!(handle.snapshot().bezel.unwrap().eeprom);
+
+    commands.lock().clear();
+    handle.restore(&handle.snapshot())?;
+    assert_eq!(commands.lock().len(), 2);
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+#![cfg(feature = "sqlite")]
+
+use ssp_server::sink::EventSink;
+use ssp_server::sqlite::{EventQuery, SqliteEventStore};
+
+#[test]
+fn test_event_store() -> ssp::Result<()> {
+    let mut store = SqliteEventStore::open_in_memory()?;
+
+    let credit_20 = ssp::Event::from(ssp::NoteCreditEvent::new(ssp::ChannelValue::from(20)));
+    let credit_50 = ssp::Event::from(ssp::NoteCreditEvent::new(ssp::ChannelValue::from(50)));
+    let reset = ssp::Event::from(ssp::ResetEvent::new());
+
+    store.publish_event(&credit_20)?;
+    store.publish_event(&reset)?;
+    let last = store.record_event(&credit_50)?;
+
+    let all = store.query_events(&EventQuery::new())?;
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[2].id, last);
+    assert_eq!(all[0].denomination, Some(20));
+    assert_eq!(all[1].denomination, None);
+
+    let credits = store.query_events(&EventQuery::new().with_method(ssp::Method::NoteCredit))?;
+    assert_eq!(credits.len(), 2);
+
+    let fifties = store.query_events(&EventQuery::new().with_denomination(50))?;
+    assert_eq!(fifties.len(), 1);
+    assert_eq!(
+        serde_json::from_str::<ssp::Event>(fifties[0].payload.as_str()).unwrap(),
+        credit_50
+    );
+
+    // limit keeps the most recent records, oldest first
+    let recent = store.query_events(&EventQuery::new().with_limit(2))?;
+    assert_eq!(recent, all[1..]);
+
+    let future = all[2].timestamp_ms + 60_000;
+    assert!(store
+        .query_events(&EventQuery::new().with_since_ms(future))?
+        .is_empty());
+    assert_eq!(
+        store
+            .query_events(&EventQuery::new().with_until_ms(future))?
+            .len(),
+        3
+    );
+
+    let ok: ssp::Result<()> = Ok(());
+    let err: ssp::Result<()> = Err(ssp::Error::Encryption(ssp::ResponseStatus::KeyNotSet));
+
+    store.record_command(ssp::Method::Enable, &ok)?;
+    store.record_command(ssp::Method::Dispense, &err)?;
+
+    let commands = store.query_commands(&EventQuery::new())?;
+    assert_eq!(commands.len(), 2);
+    assert!(commands[0].is_ok());
+    assert!(!commands[1].is_ok());
+
+    let dispenses = store.query_commands(&EventQuery::new().with_method(ssp::Method::Dispense))?;
+    assert_eq!(dispenses, commands[1..]);
+
+    Ok(())
+}
+
+#[test]
+fn test_sqlite_storage() -> ssp::Result<()> {
+    use std::sync::Arc;
+
+    use ssp_server::journal::{TransactionJournal, TransactionKind};
+    use ssp_server::registry::DeviceRegistry;
+    use ssp_server::sqlite::SqliteStorage;
+
+    let storage = SqliteStorage::open_in_memory()?;
+
+    {
+        let journal = TransactionJournal::from_storage(Arc::new(storage.clone()))?;
+        journal.record(TransactionKind::Credit, 0x1234, 500, 1, "EUR")?;
+        journal.record(TransactionKind::Payout, 0x1234, 500, 1, "EUR")?;
+
+        let registry = DeviceRegistry::from_storage(Arc::new(storage.clone()))?;
+        registry.register(0x1234, "Note Validator", "0123", "EUR01610", "/dev/ttyUSB0")?;
+        registry.register(0x1234, "Note Validator", "0124", "EUR01610", "/dev/ttyUSB0")?;
+    }
+
+    let journal = TransactionJournal::from_storage(Arc::new(storage.clone()))?;
+    assert_eq!(journal.entries()?.len(), 2);
+    assert_eq!(journal.totals().net(), 0);
+
+    let registry = DeviceRegistry::from_storage(Arc::new(storage))?;
+    assert_eq!(registry.devices().len(), 1);
+    assert_eq!(registry.device(0x1234).unwrap().firmware, "0124");
+
+    Ok(())
+}
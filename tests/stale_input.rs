@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::{thread, time};
+
+use ssp_server::transport::{self, TcpTransport, Transport};
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::frame;
+
+// Transport with a late response waiting in its input, answering every command with OK.
+struct LateTransport {
+    wire: VecDeque<u8>,
+}
+
+impl Read for LateTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for LateTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.wire.extend(frame(0x80, &[0xf0]));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for LateTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> ssp::Result<usize> {
+        Ok(self.wire.drain(..).count())
+    }
+}
+
+#[test]
+fn test_discard_stale_input() -> ssp::Result<()> {
+    // a late "command cannot be processed" response to an earlier command
+    let late = frame(0x00, &[0xf5]);
+
+    let handle = DeviceHandle::from_transport(LateTransport {
+        wire: late.iter().copied().collect(),
+    })?;
+
+    let before = transport::discard_counters();
+
+    handle.display_on()?;
+    handle.display_on()?;
+
+    let counters = transport::discard_counters();
+    assert_eq!(counters.flushes - before.flushes, 1);
+    assert_eq!(counters.bytes - before.bytes, late.len() as u64);
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_discard_input() -> ssp::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    let bridge = thread::spawn(move || -> io::Result<TcpStream> {
+        let (mut stream, _) = listener.accept()?;
+        stream.write_all(&[0xaa; 300])?;
+        Ok(stream)
+    });
+
+    let mut transport = TcpTransport::connect(&addr.to_string())?;
+    let _bridge = bridge.join().unwrap()?;
+
+    // wait for the bytes to arrive
+    let mut discarded = 0;
+    let start = time::Instant::now();
+    while discarded < 300 && start.elapsed() < time::Duration::from_secs(5) {
+        discarded += transport.discard_input()?;
+        thread::sleep(time::Duration::from_millis(10));
+    }
+
+    assert_eq!(discarded, 300);
+    assert_eq!(transport.discard_input()?, 0);
+
+    Ok(())
+}
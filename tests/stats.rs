@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::{thread, time};
+
+use ssp_server::framing::MAX_RETRANSMISSIONS;
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::frame;
+
+// Time the device takes to answer.
+const RESPONSE_DELAY_MS: u64 = 2;
+
+// Reply to a single write.
+enum Reply {
+    Ok,
+    BadCrc,
+    Silent,
+}
+
+// Transport answering writes with scripted replies, then with OK.
+struct ScriptTransport {
+    replies: VecDeque<Reply>,
+    wire: VecDeque<u8>,
+}
+
+impl ScriptTransport {
+    fn new(replies: impl IntoIterator<Item = Reply>) -> Self {
+        Self {
+            replies: replies.into_iter().collect(),
+            wire: VecDeque::new(),
+        }
+    }
+}
+
+impl Read for ScriptTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for ScriptTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        thread::sleep(time::Duration::from_millis(RESPONSE_DELAY_MS));
+
+        match self.replies.pop_front().unwrap_or(Reply::Ok) {
+            Reply::Ok => self.wire.extend(frame(0x80, &[0xf0])),
+            Reply::BadCrc => {
+                // corrupt the data, so the CRC no longer matches
+                let mut reply = frame(0x80, &[0xf0]);
+                reply[3] ^= 0x01;
+                self.wire.extend(reply);
+            }
+            Reply::Silent => (),
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for ScriptTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_link_stats() -> ssp::Result<()> {
+    let given_up = (0..=MAX_RETRANSMISSIONS).map(|_| Reply::Silent);
+    let replies = [Reply::BadCrc, Reply::Ok, Reply::Silent, Reply::Ok]
+        .into_iter()
+        .chain(given_up);
+    let handle = DeviceHandle::from_transport(ScriptTransport::new(replies))?;
+
+    assert_eq!(handle.stats(), Default::default());
+
+    // retransmitted after a corrupted response, then after a timeout
+    handle.display_on()?;
+    handle.display_on()?;
+
+    let stats = handle.stats();
+    assert_eq!(stats.exchanges, 2);
+    assert_eq!(stats.crc_errors, 1);
+    assert_eq!(stats.timeouts, 1);
+    assert_eq!(stats.retries, 2);
+    assert_eq!(stats.errors, 0);
+
+    // round trips only count the answered attempts
+    assert!(stats.p50_rtt_us >= RESPONSE_DELAY_MS * 1_000);
+    assert!(stats.avg_rtt_us >= RESPONSE_DELAY_MS * 1_000);
+    assert!(stats.p99_rtt_us <= stats.max_rtt_us);
+
+    assert!(handle.display_on().is_err());
+
+    let stats = handle.stats();
+    assert_eq!(stats.exchanges, 3);
+    assert_eq!(stats.errors, 1);
+    assert_eq!(stats.timeouts, 2 + u64::from(MAX_RETRANSMISSIONS));
+    assert_eq!(stats.retries, 2 + u64::from(MAX_RETRANSMISSIONS));
+    assert!((stats.error_rate() - 1.0 / 3.0).abs() < f64::EPSILON);
+
+    // polls are counted, the bus is resynchronized before the first one
+    handle.submit(ssp::PollCommand::new()).wait()?;
+
+    let stats = handle.stats();
+    assert_eq!(stats.exchanges, 5);
+    assert_eq!(stats.polls, 1);
+
+    Ok(())
+}
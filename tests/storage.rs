@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use ssp_server::audit::{AuditLog, AuditOp, AuditQuery};
+use ssp_server::journal::{TransactionJournal, TransactionKind};
+use ssp_server::registry::DeviceRegistry;
+use ssp_server::storage::{FileStorage, Storage, JOURNAL_COLLECTION};
+
+// Storage backend of an embedder, keeping the records in memory.
+#[derive(Default)]
+struct MemoryStorage {
+    collections: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn location(&self, collection: &str) -> String {
+        format!("memory:{collection}")
+    }
+
+    fn load(&self, collection: &str) -> ssp::Result<Vec<String>> {
+        let collections = self.collections.lock().unwrap();
+        Ok(collections.get(collection).cloned().unwrap_or_default())
+    }
+
+    fn append(&self, collection: &str, record: &str) -> ssp::Result<()> {
+        let mut collections = self.collections.lock().unwrap();
+        collections
+            .entry(collection.into())
+            .or_default()
+            .push(record.into());
+
+        Ok(())
+    }
+
+    fn replace(&self, collection: &str, records: &[String]) -> ssp::Result<()> {
+        let mut collections = self.collections.lock().unwrap();
+        collections.insert(collection.into(), records.to_vec());
+
+        Ok(())
+    }
+}
+
+fn storage_dir(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("ssp-storage-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+
+    path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn test_custom_storage() -> ssp::Result<()> {
+    let storage = Arc::new(MemoryStorage::default());
+
+    let journal = TransactionJournal::from_storage(storage.clone())?;
+    assert_eq!(journal.path(), "memory:journal");
+    journal.record(TransactionKind::Credit, 0x1234, 1000, 1, "EUR")?;
+    journal.record(TransactionKind::Credit, 0x1234, 2000, 1, "EUR")?;
+
+    let registry = DeviceRegistry::from_storage(storage.clone())?;
+    registry.register(0x1234, "Note Validator", "0123", "EUR01610", "/dev/ttyUSB0")?;
+
+    let audit = AuditLog::from_storage(storage.clone())?;
+    audit.record(AuditOp::Payout, "1x1000 EUR", &Ok(()))?;
+
+    // reopening replays the records kept by the embedder
+    let journal = TransactionJournal::from_storage(storage.clone())?;
+    assert_eq!(journal.totals().credited, 3000);
+    assert_eq!(
+        journal
+            .record(TransactionKind::Payout, 0x1234, 1000, 1, "EUR")?
+            .seq,
+        2
+    );
+
+    let registry = DeviceRegistry::from_storage(storage.clone())?;
+    assert_eq!(registry.device(0x1234).unwrap().unit_type, "Note Validator");
+
+    let audit = AuditLog::from_storage(storage.clone())?;
+    let record = audit.record(AuditOp::Empty, "", &Ok(()))?;
+    assert_eq!(record.seq, 1);
+    assert_eq!(audit.query(&AuditQuery::new())?.len(), 2);
+
+    assert_eq!(storage.load(JOURNAL_COLLECTION)?.len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_file_storage() -> ssp::Result<()> {
+    let dir = storage_dir("file");
+    let storage = FileStorage::new(&dir).with_path("audit", &format!("{dir}/audit/audit.txt"));
+
+    assert_eq!(storage.location("journal"), format!("{dir}/journal.log"));
+    assert_eq!(storage.location("audit"), format!("{dir}/audit/audit.txt"));
+    assert!(storage.load("journal")?.is_empty());
+
+    storage.append("journal", "first")?;
+    storage.append("journal", "second")?;
+    storage.append("audit", "record")?;
+    assert_eq!(storage.load("journal")?, ["first", "second"]);
+    assert_eq!(storage.load("audit")?, ["record"]);
+
+    storage.replace("journal", &["replaced".to_string()])?;
+    storage.append("journal", "appended")?;
+    assert_eq!(storage.load("journal")?, ["replaced", "appended"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    Ok(())
+}
+
+#[test]
+fn test_file_storage_invalid_utf8() -> ssp::Result<()> {
+    let dir = storage_dir("utf8");
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(
+        format!("{dir}/journal.log"),
+        b"first\n\xff\xfe corrupted\nthird\n",
+    )?;
+
+    let storage = FileStorage::new(&dir);
+
+    // the ledger is never replayed without the invalid record
+    let err = storage.load("journal").unwrap_err().to_string();
+    assert!(err.contains("line 2"), "{err}");
+    assert!(err.contains("journal.log"), "{err}");
+
+    assert!(TransactionJournal::open(&format!("{dir}/journal.log")).is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    Ok(())
+}
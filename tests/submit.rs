@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::{thread, time};
+
+use ssp_server::transport::Transport;
+use ssp_server::{CommandPriority, DeviceHandle};
+
+mod common;
+
+use common::frame;
+
+// Transport holding back its canned replies until the gate opens.
+struct GatedTransport {
+    replies: VecDeque<u8>,
+    gate: Arc<AtomicBool>,
+}
+
+impl Read for GatedTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.gate.load(Ordering::SeqCst) {
+            thread::sleep(time::Duration::from_millis(10));
+        }
+
+        match (self.replies.pop_front(), buf.first_mut()) {
+            (Some(byte), Some(out)) => {
+                *out = byte;
+                Ok(1)
+            }
+            _ => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
+
+impl Write for GatedTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for GatedTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+type WriteLog = Arc<Mutex<Vec<(u8, time::Instant)>>>;
+
+// Transport answering every command with OK after a delay, and logging the sent commands.
+struct EchoTransport {
+    writes: WriteLog,
+    replies: VecDeque<u8>,
+    delay: time::Duration,
+    fresh: bool,
+    gate: Arc<AtomicBool>,
+}
+
+impl EchoTransport {
+    fn new(writes: WriteLog, delay: time::Duration, gate: Arc<AtomicBool>) -> Self {
+        Self {
+            writes,
+            replies: VecDeque::new(),
+            delay,
+            fresh: false,
+            gate,
+        }
+    }
+}
+
+impl Read for EchoTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.gate.load(Ordering::SeqCst) {
+            thread::sleep(time::Duration::from_millis(10));
+        }
+
+        if self.fresh {
+            self.fresh = false;
+            thread::sleep(self.delay);
+        }
+
+        match (self.replies.pop_front(), buf.first_mut()) {
+            (Some(byte), Some(out)) => {
+                *out = byte;
+                Ok(1)
+            }
+            _ => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
+
+impl Write for EchoTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // STX | SEQID | LEN | command
+        self.writes
+            .lock()
+            .unwrap()
+            .push((buf[3], time::Instant::now()));
+
+        self.replies = frame(0x80, &[0xf0]).into();
+        self.fresh = true;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for EchoTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+// Wakes the test thread blocked on a future.
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn test_submit() -> ssp::Result<()> {
+    let ok = frame(0x80, &[0xf0]);
+    let gate = Arc::new(AtomicBool::new(false));
+
+    let handle = DeviceHandle::from_transport(GatedTransport {
+        replies: ok.iter().chain(ok.iter()).copied().collect(),
+        gate: Arc::clone(&gate),
+    })?;
+
+    // returns while the worker waits for the reply
+    let mut command = handle.submit(ssp::SyncCommand::new());
+    assert!(!command.is_done());
+    assert!(command.try_result().is_none());
+    assert!(command
+        .wait_timeout(time::Duration::from_millis(100))
+        .is_none());
+
+    gate.store(true, Ordering::SeqCst);
+
+    let response = command.wait()?;
+    assert_eq!(
+        response.as_response().response_status(),
+        ssp::ResponseStatus::Ok
+    );
+
+    // commands complete in submission order
+    let second = handle.submit(ssp::SyncCommand::new());
+    let third = handle.submit(ssp::SyncCommand::new());
+
+    assert!(block_on(second).is_ok());
+    assert!(third.wait().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_priority() -> ssp::Result<()> {
+    let writes = WriteLog::default();
+    let gate = Arc::new(AtomicBool::new(false));
+
+    let handle = DeviceHandle::from_transport(EchoTransport::new(
+        Arc::clone(&writes),
+        time::Duration::ZERO,
+        Arc::clone(&gate),
+    ))?;
+
+    // blocks the I/O worker until the gate opens
+    let first = handle.submit(ssp::SyncCommand::new());
+    while handle.queued_commands() > 0 {
+        thread::sleep(time::Duration::from_millis(10));
+    }
+
+    let low = handle.submit_with_priority(ssp::DisplayOffCommand::new(), CommandPriority::Low);
+    let normal = handle.submit(ssp::DisplayOnCommand::new());
+    let high = handle.submit_with_priority(ssp::EnableCommand::new(), CommandPriority::High);
+    assert_eq!(handle.queued_commands(), 3);
+
+    gate.store(true, Ordering::SeqCst);
+
+    for command in [first, low, normal, high] {
+        command.wait()?;
+    }
+
+    let sent: Vec<u8> = writes.lock().unwrap().iter().map(|w| w.0).collect();
+    assert_eq!(sent, [0x11, 0x0a, 0x03, 0x04]);
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_poll_cadence() -> ssp::Result<()> {
+    const POLL: u8 = 0x07;
+    const DISPLAY_ON: u8 = 0x03;
+
+    let writes = WriteLog::default();
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let handle = DeviceHandle::from_transport(EchoTransport::new(
+        Arc::clone(&writes),
+        time::Duration::from_millis(50),
+        Arc::new(AtomicBool::new(true)),
+    ))?;
+
+    handle.start_background_polling(Arc::clone(&stop))?;
+    thread::sleep(time::Duration::from_millis(100));
+
+    // a burst taking longer than two polling intervals
+    let burst: Vec<_> = (0..40)
+        .map(|_| handle.submit(ssp::DisplayOnCommand::new()))
+        .collect();
+
+    for command in burst {
+        command.wait()?;
+    }
+
+    stop.store(true, Ordering::SeqCst);
+
+    let writes = writes.lock().unwrap().clone();
+    let first = writes.iter().position(|w| w.0 == DISPLAY_ON).unwrap();
+    let last = writes.iter().rposition(|w| w.0 == DISPLAY_ON).unwrap();
+
+    // polls keep their cadence during the burst
+    let polls: Vec<time::Instant> = writes[first..=last]
+        .iter()
+        .filter(|w| w.0 == POLL)
+        .map(|w| w.1)
+        .collect();
+    assert!(polls.len() >= 2, "{} polls during the burst", polls.len());
+
+    for pair in polls.windows(2) {
+        let gap = pair[1] - pair[0];
+        assert!(
+            gap < time::Duration::from_millis(1_000),
+            "{gap:?} between polls"
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,12 @@
+#![cfg(feature = "tracing")]
+
+use ssp_server::telemetry;
+
+#[test]
+fn test_log_bridge() {
+    assert!(telemetry::init_log_bridge().is_ok());
+
+    // only one logger can be installed
+    assert!(telemetry::init_log_bridge().is_err());
+    assert!(env_logger::try_init().is_err());
+}
@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::time;
+
+use parking_lot::Mutex;
+
+use ssp_server::retry::CommandClass;
+use ssp_server::timeouts;
+use ssp_server::transport::Transport;
+use ssp_server::DeviceHandle;
+
+mod common;
+
+use common::frame;
+
+// Transport answering every command with OK, and recording the read timeouts.
+struct OkTransport {
+    wire: VecDeque<u8>,
+    timeouts: Arc<Mutex<Vec<time::Duration>>>,
+}
+
+impl Read for OkTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for OkTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.wire.extend(frame(0x80, &[0xf0]));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for OkTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+
+    fn set_read_timeout(&mut self, timeout: time::Duration) -> ssp::Result<()> {
+        self.timeouts.lock().push(timeout);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_response_timeouts() -> ssp::Result<()> {
+    let ms = time::Duration::from_millis;
+
+    // polls fail fast, payouts wait for the device
+    assert!(timeouts::default_response_timeout(CommandClass::Poll) < ms(1_000));
+    assert!(
+        timeouts::default_response_timeout(CommandClass::Payout)
+            > timeouts::default_response_timeout(CommandClass::Poll)
+    );
+
+    for class in CommandClass::ALL {
+        assert_eq!(
+            timeouts::response_timeout(class),
+            timeouts::default_response_timeout(class)
+        );
+    }
+
+    let recorded = Arc::new(Mutex::new(Vec::new()));
+    let handle = DeviceHandle::from_transport(OkTransport {
+        wire: VecDeque::new(),
+        timeouts: Arc::clone(&recorded),
+    })?;
+
+    handle.sync()?;
+    assert_eq!(
+        recorded.lock().first().copied(),
+        Some(timeouts::default_response_timeout(CommandClass::Control))
+    );
+
+    timeouts::set_response_timeout(CommandClass::Control, ms(750));
+    assert_eq!(timeouts::response_timeout(CommandClass::Control), ms(750));
+
+    // a zero timeout would fail every read
+    timeouts::set_response_timeout(CommandClass::Control, time::Duration::ZERO);
+    assert_eq!(timeouts::response_timeout(CommandClass::Control), ms(750));
+
+    recorded.lock().clear();
+    handle.sync()?;
+
+    // waits for the response, then restores the timeout after reading the frame
+    let recorded = recorded.lock().clone();
+    assert_eq!(recorded.first(), Some(&ms(750)));
+    assert_eq!(recorded.last(), Some(&ms(750)));
+
+    timeouts::reset_response_timeouts();
+    assert_eq!(
+        timeouts::response_timeout(CommandClass::Control),
+        timeouts::default_response_timeout(CommandClass::Control)
+    );
+
+    Ok(())
+}
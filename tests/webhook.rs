@@ -0,0 +1,158 @@
+#![cfg(feature = "webhook")]
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::{thread, time};
+
+use parking_lot::Mutex;
+
+use ssp_server::sink::EventSink;
+use ssp_server::webhook::{WebhookConfig, WebhookSink};
+
+fn credit(value: u32) -> ssp::Event {
+    ssp::Event::from(ssp::NoteCreditEvent::new(ssp::ChannelValue::from(value)))
+}
+
+// Reads a HTTP request, and returns its body.
+fn read_body(stream: &TcpStream) -> io::Result<String> {
+    let mut reader = BufReader::new(stream);
+    let mut len = 0;
+
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                len = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+
+    Ok(String::from_utf8_lossy(&body).into())
+}
+
+// Starts a webhook endpoint answering the requests with `statuses`, then with 200, and returns
+// its URL, and the received request bodies.
+fn endpoint(statuses: &[u16]) -> io::Result<(String, Arc<Mutex<Vec<String>>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/hook", listener.local_addr()?);
+
+    let bodies = Arc::new(Mutex::new(Vec::new()));
+    let received = Arc::clone(&bodies);
+    let statuses = statuses.to_vec();
+
+    thread::spawn(move || {
+        for (i, stream) in listener.incoming().enumerate() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => break,
+            };
+
+            if let Ok(body) = read_body(&stream) {
+                received.lock().push(body);
+            }
+
+            let status = statuses.get(i).copied().unwrap_or(200);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            );
+        }
+    });
+
+    Ok((url, bodies))
+}
+
+fn config(url: &str) -> WebhookConfig {
+    WebhookConfig::new(url)
+        .with_backoff(time::Duration::from_millis(10))
+        .with_timeout(time::Duration::from_secs(5))
+}
+
+#[test]
+fn test_delivery() -> ssp::Result<()> {
+    let (url, bodies) = endpoint(&[])?;
+    let credit = credit(500);
+
+    let mut sink = WebhookSink::new(config(&url));
+    sink.publish_event(&credit)?;
+    // resets are not delivered by default
+    sink.publish_event(&ssp::Event::from(ssp::ResetEvent::new()))?;
+
+    // waits for pending deliveries
+    drop(sink);
+
+    let bodies = bodies.lock();
+    assert_eq!(bodies.len(), 1);
+
+    let body: serde_json::Value = serde_json::from_str(&bodies[0]).unwrap();
+    assert_eq!(body, serde_json::to_value(&credit).unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn test_delivers() {
+    let config = WebhookConfig::new("http://127.0.0.1/hook");
+    let reset = ssp::Event::from(ssp::ResetEvent::new());
+
+    assert!(config.delivers(&credit(500)));
+    assert!(!config.delivers(&reset));
+
+    // an empty list delivers all events
+    let config = config.with_methods(&[]);
+
+    assert!(config.delivers(&reset));
+}
+
+#[test]
+fn test_retry() -> ssp::Result<()> {
+    // server errors, and rate limiting are retried
+    let (url, bodies) = endpoint(&[503, 429])?;
+
+    let mut sink = WebhookSink::new(config(&url).with_max_retries(3));
+    sink.publish_event(&credit(500))?;
+    drop(sink);
+
+    assert_eq!(bodies.lock().len(), 3);
+
+    // client errors are not
+    let (url, bodies) = endpoint(&[400])?;
+
+    let mut sink = WebhookSink::new(config(&url).with_max_retries(3));
+    sink.publish_event(&credit(500))?;
+    drop(sink);
+
+    assert_eq!(bodies.lock().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_unreachable_endpoint() -> ssp::Result<()> {
+    // bind, and release a port, so nothing listens on it
+    let addr = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let url = format!("http://{addr}/hook");
+
+    let start = time::Instant::now();
+
+    // deliveries run in the background, the event is queued, and the failure logged
+    let mut sink = WebhookSink::new(config(&url).with_max_retries(2));
+    sink.publish_event(&credit(500))?;
+    drop(sink);
+
+    // gives up after the retries
+    assert!(start.elapsed() < time::Duration::from_secs(5));
+
+    Ok(())
+}
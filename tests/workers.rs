@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use ssp_server::transport::Transport;
+use ssp_server::{DeviceHandle, PollMode, WorkerKind};
+
+mod common;
+
+use common::frame;
+
+const POLL: u8 = 0x07;
+
+// Transport answering every command with OK, optionally panicking on polls.
+#[derive(Default)]
+struct OkTransport {
+    wire: VecDeque<u8>,
+    panic_on_poll: bool,
+}
+
+impl Read for OkTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.wire.len());
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        for (out, byte) in buf.iter_mut().zip(self.wire.drain(..n)) {
+            *out = byte;
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for OkTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.panic_on_poll && buf.get(3) == Some(&POLL) {
+            panic!("transport bug");
+        }
+
+        self.wire.extend(frame(0x80, &[0xf0]));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for OkTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        self.wire.clear();
+        Ok(())
+    }
+}
+
+fn is_running(handle: &DeviceHandle, kind: WorkerKind) -> bool {
+    handle
+        .workers()
+        .iter()
+        .any(|worker| worker.kind == kind && worker.running)
+}
+
+#[test]
+fn test_join_workers() -> ssp::Result<()> {
+    let handle = DeviceHandle::from_transport(OkTransport::default())?;
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let _rx = handle.start_background_polling_with_queue(Arc::clone(&stop), PollMode::Auto)?;
+    assert!(is_running(&handle, WorkerKind::Poll));
+
+    handle.submit(ssp::DisplayOnCommand::new()).wait()?;
+    assert!(is_running(&handle, WorkerKind::Io));
+
+    // polling runs until stopped
+    let timeout = time::Duration::from_secs(5);
+    assert!(handle
+        .join_workers(time::Duration::from_millis(100))
+        .is_err());
+
+    stop.store(true, Ordering::SeqCst);
+    handle.join_workers(timeout)?;
+
+    // the I/O worker runs until the handle is dropped
+    let workers = handle.workers();
+    assert_eq!(workers.len(), 1);
+    assert_eq!(workers[0].kind, WorkerKind::Io);
+    assert!(workers[0].running);
+
+    Ok(())
+}
+
+#[test]
+fn test_worker_panic() -> ssp::Result<()> {
+    let handle = DeviceHandle::from_transport(OkTransport {
+        panic_on_poll: true,
+        ..Default::default()
+    })?;
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let rx = handle.start_background_polling_with_queue(Arc::clone(&stop), PollMode::Auto)?;
+
+    // the panic is surfaced as an event, instead of silently stopping the polling routine
+    let event =
+        rx.0.recv_timeout(time::Duration::from_secs(5))
+            .expect("fail event");
+    assert_eq!(event.method(), ssp::Method::Fail);
+    assert!(matches!(
+        event.payload(),
+        ssp::EventPayload::Error(ssp::Error::Event(msg)) if msg.contains("transport bug")
+    ));
+
+    // the event is sent right before the worker finishes
+    let start = time::Instant::now();
+    while is_running(&handle, WorkerKind::Poll) && start.elapsed() < time::Duration::from_secs(5) {
+        thread::sleep(time::Duration::from_millis(10));
+    }
+
+    let poll = handle
+        .workers()
+        .into_iter()
+        .find(|worker| worker.kind == WorkerKind::Poll)
+        .unwrap();
+    assert!(!poll.running);
+    assert!(poll.panicked);
+
+    handle.join_workers(time::Duration::from_secs(1))?;
+    assert!(handle.workers().is_empty());
+
+    Ok(())
+}
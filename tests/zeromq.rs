@@ -0,0 +1,153 @@
+#![cfg(feature = "zeromq")]
+
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crossbeam::channel;
+use parking_lot::Mutex;
+
+use ssp_server::transport::Transport;
+use ssp_server::zeromq::{ZmqConfig, ZmqServer};
+use ssp_server::{DeviceHandle, PushEventReceiver};
+
+// Transport for requests that never reach the device.
+struct IdleTransport;
+
+impl Read for IdleTransport {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::TimedOut.into())
+    }
+}
+
+impl Write for IdleTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for IdleTransport {
+    fn clear(&mut self) -> ssp::Result<()> {
+        Ok(())
+    }
+}
+
+// Gets a TCP endpoint on a free local port.
+fn endpoint() -> ssp::Result<String> {
+    let addr = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+
+    Ok(format!("tcp://{addr}"))
+}
+
+fn config() -> ssp::Result<ZmqConfig> {
+    Ok(ZmqConfig::new()
+        .with_rep_endpoint(endpoint()?.as_str())
+        .with_pub_endpoint(endpoint()?.as_str()))
+}
+
+fn server(config: &ZmqConfig) -> ssp::Result<ZmqServer> {
+    let handle = DeviceHandle::from_transport(IdleTransport)?;
+
+    ZmqServer::new(Arc::new(Mutex::new(handle)), config)
+}
+
+#[test]
+fn test_publish() -> ssp::Result<()> {
+    let config = config()?;
+    let server = server(&config)?;
+
+    let credit = ssp::Event::from(ssp::NoteCreditEvent::new(ssp::ChannelValue::from(500)));
+    let reset = ssp::Event::from(ssp::ResetEvent::new());
+    let topic = credit.method().to_str();
+
+    // subscribe to credits only
+    let context = zmq::Context::new();
+    let sub = context.socket(zmq::SUB).unwrap();
+    sub.set_rcvtimeo(100).unwrap();
+    sub.set_subscribe(topic.as_bytes()).unwrap();
+    sub.connect(config.pub_endpoint()).unwrap();
+
+    // subscriptions take a moment to reach the publisher, publish until one arrives
+    let mut frames = None;
+    for _ in 0..50 {
+        server.publish(&reset)?;
+        server.publish(&credit)?;
+
+        if let Ok(msg) = sub.recv_multipart(0) {
+            frames = Some(msg);
+            break;
+        }
+    }
+    let frames = frames.expect("no event received");
+
+    // the topic frame is the event method, followed by a JSON-RPC push request
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0], topic.as_bytes());
+
+    let push: serde_json::Value = serde_json::from_slice(&frames[1]).unwrap();
+
+    assert_eq!(push["method"], topic);
+    assert_eq!(push["params"], serde_json::to_value(&credit).unwrap());
+
+    // reset events are filtered out by the subscription
+    while let Ok(msg) = sub.recv_multipart(0) {
+        assert_eq!(msg[0], topic.as_bytes());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_request() -> ssp::Result<()> {
+    let config = config()?;
+    let server = server(&config)?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let serve_stop = Arc::clone(&stop);
+    let (_tx, rx) = channel::unbounded();
+
+    let serve = thread::spawn(move || server.serve(&PushEventReceiver::new(rx), &serve_stop));
+
+    let context = zmq::Context::new();
+    let req = context.socket(zmq::REQ).unwrap();
+    req.set_rcvtimeo(5_000).unwrap();
+    req.connect(config.rep_endpoint()).unwrap();
+
+    req.send("not a request", 0).unwrap();
+    let res: serde_json::Value = serde_json::from_slice(&req.recv_bytes(0).unwrap()).unwrap();
+
+    // the server answers with an error, and keeps serving
+    assert!(res["error"].is_object());
+
+    req.send_multipart(["token", "request", "extra"], 0)
+        .unwrap();
+    let res: serde_json::Value = serde_json::from_slice(&req.recv_bytes(0).unwrap()).unwrap();
+
+    assert!(res["error"].is_object());
+
+    stop.store(true, Ordering::SeqCst);
+    serve.join().unwrap()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_bind_error() -> ssp::Result<()> {
+    // both sockets on the same endpoint, the second bind fails
+    let endpoint = endpoint()?;
+    let config = ZmqConfig::new()
+        .with_rep_endpoint(endpoint.as_str())
+        .with_pub_endpoint(endpoint.as_str());
+
+    let err = server(&config).err().unwrap();
+
+    assert!(format!("{err}").contains("ZeroMQ error"));
+
+    Ok(())
+}